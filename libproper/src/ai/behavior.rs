@@ -0,0 +1,433 @@
+use std::{collections::HashMap, path::Path};
+
+use serde::Deserialize;
+
+use crate::{
+    error::{Error, ResourceKind},
+    event::GameEvent,
+    world::nav::NavAgent,
+};
+
+use super::{AiContext, Blackboard};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Status {
+    Success,
+    Failure,
+    Running,
+}
+
+pub type ActionFn = Box<dyn FnMut(&mut AiContext) -> Status + Send + Sync>;
+pub type ConditionFn = Box<dyn Fn(&AiContext) -> bool + Send + Sync>;
+
+pub enum Node {
+    // Composites
+    Sequence {
+        children: Vec<Node>,
+        current: usize,
+    },
+    Selector {
+        children: Vec<Node>,
+        current: usize,
+    },
+    Parallel {
+        children: Vec<Node>,
+        required_successes: usize,
+    },
+    // Decorators
+    Inverter(Box<Node>),
+    Succeeder(Box<Node>),
+    Repeat {
+        child: Box<Node>,
+        count: Option<u32>,
+        done: u32,
+    },
+    // Leaves
+    Action(ActionFn),
+    Condition(ConditionFn),
+    Wait {
+        duration: f32,
+        elapsed: f32,
+    },
+    MoveTo {
+        target_key: String,
+        started: bool,
+    },
+    Signal(String),
+}
+
+pub struct BehaviorTree {
+    root: Node,
+}
+
+// Component driving an entity with a behavior tree, ticked by AiSystem
+pub struct AiController {
+    pub tree: BehaviorTree,
+    pub blackboard: Blackboard,
+}
+
+// Data description of a tree, leaves refer to actions/conditions registered by name
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum NodeDescription {
+    Sequence {
+        children: Vec<NodeDescription>,
+    },
+    Selector {
+        children: Vec<NodeDescription>,
+    },
+    Parallel {
+        children: Vec<NodeDescription>,
+        required_successes: Option<usize>,
+    },
+    Inverter {
+        child: Box<NodeDescription>,
+    },
+    Succeeder {
+        child: Box<NodeDescription>,
+    },
+    Repeat {
+        child: Box<NodeDescription>,
+        count: Option<u32>,
+    },
+    Action {
+        name: String,
+    },
+    Condition {
+        name: String,
+    },
+    Wait {
+        seconds: f32,
+    },
+    MoveTo {
+        target: String,
+    },
+    Signal {
+        name: String,
+    },
+    // Succeeds when the blackboard flag is set
+    Flag {
+        key: String,
+    },
+}
+
+#[derive(Deserialize)]
+struct TreeFile {
+    root: NodeDescription,
+}
+
+#[derive(Default)]
+pub struct LeafRegistry {
+    actions: HashMap<String, Box<dyn Fn() -> ActionFn + Send + Sync>>,
+    conditions: HashMap<String, Box<dyn Fn() -> ConditionFn + Send + Sync>>,
+}
+
+impl Node {
+    pub fn sequence(children: Vec<Node>) -> Self {
+        Self::Sequence {
+            children,
+            current: 0,
+        }
+    }
+
+    pub fn selector(children: Vec<Node>) -> Self {
+        Self::Selector {
+            children,
+            current: 0,
+        }
+    }
+
+    pub fn parallel(children: Vec<Node>, required_successes: usize) -> Self {
+        Self::Parallel {
+            children,
+            required_successes,
+        }
+    }
+
+    pub fn inverter(child: Node) -> Self {
+        Self::Inverter(Box::new(child))
+    }
+
+    pub fn succeeder(child: Node) -> Self {
+        Self::Succeeder(Box::new(child))
+    }
+
+    pub fn repeat(child: Node, count: Option<u32>) -> Self {
+        Self::Repeat {
+            child: Box::new(child),
+            count,
+            done: 0,
+        }
+    }
+
+    pub fn action<F: FnMut(&mut AiContext) -> Status + Send + Sync + 'static>(f: F) -> Self {
+        Self::Action(Box::new(f))
+    }
+
+    pub fn condition<F: Fn(&AiContext) -> bool + Send + Sync + 'static>(f: F) -> Self {
+        Self::Condition(Box::new(f))
+    }
+
+    pub fn wait(duration: f32) -> Self {
+        Self::Wait {
+            duration,
+            elapsed: 0.0,
+        }
+    }
+
+    pub fn move_to(target_key: &str) -> Self {
+        Self::MoveTo {
+            target_key: target_key.to_owned(),
+            started: false,
+        }
+    }
+
+    pub fn signal(name: &str) -> Self {
+        Self::Signal(name.to_owned())
+    }
+
+    pub fn tick(&mut self, ctx: &mut AiContext) -> Status {
+        let status = match self {
+            Self::Sequence { children, current } => {
+                Self::tick_composite(children, current, ctx, Status::Success)
+            }
+            Self::Selector { children, current } => {
+                Self::tick_composite(children, current, ctx, Status::Failure)
+            }
+            Self::Parallel {
+                children,
+                required_successes,
+            } => {
+                let statuses = children
+                    .iter_mut()
+                    .map(|child| child.tick(ctx))
+                    .collect::<Vec<_>>();
+                let successes = statuses.iter().filter(|&&s| s == Status::Success).count();
+                let failures = statuses.iter().filter(|&&s| s == Status::Failure).count();
+
+                if successes >= *required_successes {
+                    Status::Success
+                } else if children.len() - failures < *required_successes {
+                    Status::Failure
+                } else {
+                    Status::Running
+                }
+            }
+            Self::Inverter(child) => match child.tick(ctx) {
+                Status::Success => Status::Failure,
+                Status::Failure => Status::Success,
+                Status::Running => Status::Running,
+            },
+            Self::Succeeder(child) => match child.tick(ctx) {
+                Status::Running => Status::Running,
+                _ => Status::Success,
+            },
+            Self::Repeat { child, count, done } => match child.tick(ctx) {
+                Status::Running => Status::Running,
+                Status::Failure => Status::Failure,
+                Status::Success => {
+                    *done += 1;
+                    if count.map_or(false, |count| *done >= count) {
+                        Status::Success
+                    } else {
+                        Status::Running
+                    }
+                }
+            },
+            Self::Action(action) => action(ctx),
+            Self::Condition(condition) => {
+                if condition(ctx) {
+                    Status::Success
+                } else {
+                    Status::Failure
+                }
+            }
+            Self::Wait { duration, elapsed } => {
+                *elapsed += ctx.delta;
+                if *elapsed >= *duration {
+                    Status::Success
+                } else {
+                    Status::Running
+                }
+            }
+            Self::MoveTo {
+                target_key,
+                started,
+            } => Self::tick_move_to(target_key, started, ctx),
+            Self::Signal(name) => {
+                ctx.events.push(GameEvent::Signal {
+                    name: name.clone(),
+                    entity: Some(ctx.entity.id()),
+                });
+                Status::Success
+            }
+        };
+
+        if status != Status::Running {
+            self.reset();
+        }
+
+        status
+    }
+
+    pub fn reset(&mut self) {
+        match self {
+            Self::Sequence { children, current } | Self::Selector { children, current } => {
+                *current = 0;
+                children.iter_mut().for_each(Node::reset);
+            }
+            Self::Parallel { children, .. } => children.iter_mut().for_each(Node::reset),
+            Self::Inverter(child) | Self::Succeeder(child) => child.reset(),
+            Self::Repeat { child, done, .. } => {
+                *done = 0;
+                child.reset();
+            }
+            Self::Wait { elapsed, .. } => *elapsed = 0.0,
+            Self::MoveTo { started, .. } => *started = false,
+            Self::Action(_) | Self::Condition(_) | Self::Signal(_) => (),
+        }
+    }
+
+    // Runs children in order for as long as they return `continue_on`
+    fn tick_composite(
+        children: &mut [Node],
+        current: &mut usize,
+        ctx: &mut AiContext,
+        continue_on: Status,
+    ) -> Status {
+        while let Some(child) = children.get_mut(*current) {
+            match child.tick(ctx) {
+                Status::Running => return Status::Running,
+                status if status == continue_on => *current += 1,
+                status => return status,
+            }
+        }
+        continue_on
+    }
+
+    fn tick_move_to(target_key: &str, started: &mut bool, ctx: &mut AiContext) -> Status {
+        let target = match ctx.blackboard.point(target_key) {
+            Some(target) => target,
+            None => return Status::Failure,
+        };
+        let position = *ctx.entity.position();
+        let navmesh = ctx.navmesh;
+        let agent = match ctx.entity.components_mut().get_mut::<NavAgent>() {
+            Some(agent) => agent,
+            None => return Status::Failure,
+        };
+
+        if !*started {
+            *started = true;
+            let navmesh = match navmesh {
+                Some(navmesh) => navmesh,
+                None => return Status::Failure,
+            };
+            if !agent.set_destination(navmesh, &position, &target) {
+                return Status::Failure;
+            }
+        }
+
+        if agent.is_moving() {
+            Status::Running
+        } else {
+            Status::Success
+        }
+    }
+}
+
+impl BehaviorTree {
+    pub fn new(root: Node) -> Self {
+        Self { root }
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P, leaves: &LeafRegistry) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path).map_err(|err| Error::file(path, err))?;
+        let file: TreeFile = toml::from_str(&text).map_err(|err| Error::asset_parse(path, err))?;
+        let root = leaves
+            .build(&file.root)
+            .map_err(|err| err.context(ResourceKind::BehaviorTree, &path.to_string_lossy()))?;
+        Ok(Self::new(root))
+    }
+
+    pub fn tick(&mut self, ctx: &mut AiContext) -> Status {
+        self.root.tick(ctx)
+    }
+
+    pub fn reset(&mut self) {
+        self.root.reset();
+    }
+}
+
+impl AiController {
+    pub fn new(tree: BehaviorTree) -> Self {
+        Self {
+            tree,
+            blackboard: Blackboard::default(),
+        }
+    }
+}
+
+impl LeafRegistry {
+    pub fn register_action<F>(&mut self, name: &str, factory: F)
+    where
+        F: Fn() -> ActionFn + Send + Sync + 'static,
+    {
+        self.actions.insert(name.to_owned(), Box::new(factory));
+    }
+
+    pub fn register_condition<F>(&mut self, name: &str, factory: F)
+    where
+        F: Fn() -> ConditionFn + Send + Sync + 'static,
+    {
+        self.conditions.insert(name.to_owned(), Box::new(factory));
+    }
+
+    pub fn action(&self, name: &str) -> Result<ActionFn, Error> {
+        self.actions
+            .get(name)
+            .map(|factory| factory())
+            .ok_or_else(|| Error::UnknownAiLeaf(name.to_owned()))
+    }
+
+    pub fn condition(&self, name: &str) -> Result<ConditionFn, Error> {
+        self.conditions
+            .get(name)
+            .map(|factory| factory())
+            .ok_or_else(|| Error::UnknownAiLeaf(name.to_owned()))
+    }
+
+    pub fn build(&self, description: &NodeDescription) -> Result<Node, Error> {
+        let build_all = |children: &[NodeDescription]| {
+            children
+                .iter()
+                .map(|child| self.build(child))
+                .collect::<Result<Vec<_>, _>>()
+        };
+
+        Ok(match description {
+            NodeDescription::Sequence { children } => Node::sequence(build_all(children)?),
+            NodeDescription::Selector { children } => Node::selector(build_all(children)?),
+            NodeDescription::Parallel {
+                children,
+                required_successes,
+            } => Node::parallel(
+                build_all(children)?,
+                required_successes.unwrap_or(children.len()),
+            ),
+            NodeDescription::Inverter { child } => Node::inverter(self.build(child)?),
+            NodeDescription::Succeeder { child } => Node::succeeder(self.build(child)?),
+            NodeDescription::Repeat { child, count } => Node::repeat(self.build(child)?, *count),
+            NodeDescription::Action { name } => Node::Action(self.action(name)?),
+            NodeDescription::Condition { name } => Node::Condition(self.condition(name)?),
+            NodeDescription::Wait { seconds } => Node::wait(*seconds),
+            NodeDescription::MoveTo { target } => Node::move_to(target),
+            NodeDescription::Signal { name } => Node::signal(name),
+            NodeDescription::Flag { key } => {
+                let key = key.clone();
+                Node::condition(move |ctx| ctx.blackboard.flag(&key))
+            }
+        })
+    }
+}
@@ -0,0 +1,127 @@
+use std::collections::HashMap;
+
+use nalgebra::Point3;
+
+use crate::{
+    error::Error,
+    event::GameEvent,
+    world::{
+        entity::EntityId,
+        nav::{NavAgent, NavMesh},
+        schedule::{EntityView, System, SystemAccess, SystemContext},
+    },
+};
+
+use self::behavior::AiController;
+
+pub mod behavior;
+pub mod state;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum BlackboardValue {
+    Bool(bool),
+    Float(f32),
+    Point(Point3<f32>),
+    Entity(EntityId),
+    Text(String),
+}
+
+#[derive(Clone, Default, Debug)]
+pub struct Blackboard {
+    data: HashMap<String, BlackboardValue>,
+}
+
+pub struct AiContext<'a, 'e> {
+    pub entity: &'a mut EntityView<'e>,
+    pub navmesh: Option<&'a NavMesh>,
+    pub blackboard: &'a mut Blackboard,
+    pub events: &'a mut Vec<GameEvent>,
+    pub delta: f32,
+}
+
+impl Blackboard {
+    pub fn set(&mut self, key: &str, value: BlackboardValue) {
+        self.data.insert(key.to_owned(), value);
+    }
+
+    pub fn get(&self, key: &str) -> Option<&BlackboardValue> {
+        self.data.get(key)
+    }
+
+    pub fn remove(&mut self, key: &str) -> Option<BlackboardValue> {
+        self.data.remove(key)
+    }
+
+    pub fn flag(&self, key: &str) -> bool {
+        matches!(self.data.get(key), Some(BlackboardValue::Bool(true)))
+    }
+
+    pub fn float(&self, key: &str) -> Option<f32> {
+        match self.data.get(key) {
+            Some(BlackboardValue::Float(value)) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn point(&self, key: &str) -> Option<Point3<f32>> {
+        match self.data.get(key) {
+            Some(BlackboardValue::Point(value)) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn entity(&self, key: &str) -> Option<EntityId> {
+        match self.data.get(key) {
+            Some(BlackboardValue::Entity(value)) => Some(*value),
+            _ => None,
+        }
+    }
+
+    // For entities moved to another scene, IDs missing from the map are kept
+    pub fn remap_entities(&mut self, ids: &HashMap<EntityId, EntityId>) {
+        for value in self.data.values_mut() {
+            if let BlackboardValue::Entity(id) = value {
+                if let Some(new_id) = ids.get(id) {
+                    *id = *new_id;
+                }
+            }
+        }
+    }
+}
+
+// Ticks the behavior trees of entities with an AiController
+#[derive(Default)]
+pub struct AiSystem;
+
+impl System for AiSystem {
+    fn access(&self) -> SystemAccess {
+        SystemAccess::default()
+            .write::<AiController>()
+            .write::<NavAgent>()
+    }
+
+    fn run(&mut self, ctx: &mut SystemContext) -> Result<(), Error> {
+        let navmesh = ctx.scene.navmesh.as_ref();
+
+        for entity in ctx.entities.iter_mut() {
+            // Taken out for the duration of the tick so leaves can borrow the entity
+            let mut controller = match entity.components_mut().remove::<AiController>() {
+                Some(controller) => controller,
+                None => continue,
+            };
+
+            let mut ai = AiContext {
+                entity,
+                navmesh,
+                blackboard: &mut controller.blackboard,
+                events: &mut ctx.events,
+                delta: ctx.delta,
+            };
+            controller.tree.tick(&mut ai);
+
+            entity.components_mut().insert(controller);
+        }
+
+        Ok(())
+    }
+}
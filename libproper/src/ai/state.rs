@@ -0,0 +1,70 @@
+use super::Blackboard;
+
+type TransitionCondition = Box<dyn Fn(&Blackboard, f32) -> bool + Send + Sync>;
+
+struct Transition<S> {
+    // None means "from any state"
+    from: Option<S>,
+    to: S,
+    condition: TransitionCondition,
+}
+
+pub struct StateMachine<S> {
+    state: S,
+    time_in_state: f32,
+    transitions: Vec<Transition<S>>,
+}
+
+impl<S: Copy + PartialEq> StateMachine<S> {
+    pub fn new(initial: S) -> Self {
+        Self {
+            state: initial,
+            time_in_state: 0.0,
+            transitions: vec![],
+        }
+    }
+
+    // Condition receives the blackboard and the time spent in the current state
+    pub fn with_transition<F>(mut self, from: Option<S>, to: S, condition: F) -> Self
+    where
+        F: Fn(&Blackboard, f32) -> bool + Send + Sync + 'static,
+    {
+        self.transitions.push(Transition {
+            from,
+            to,
+            condition: Box::new(condition),
+        });
+        self
+    }
+
+    #[inline]
+    pub fn state(&self) -> S {
+        self.state
+    }
+
+    #[inline]
+    pub fn time_in_state(&self) -> f32 {
+        self.time_in_state
+    }
+
+    pub fn set_state(&mut self, state: S) {
+        self.state = state;
+        self.time_in_state = 0.0;
+    }
+
+    // Returns the (previous, new) state pair when a transition fires
+    pub fn update(&mut self, blackboard: &Blackboard, delta: f32) -> Option<(S, S)> {
+        self.time_in_state += delta;
+
+        let to = self
+            .transitions
+            .iter()
+            .filter(|t| t.from.map_or(true, |from| from == self.state) && t.to != self.state)
+            .find(|t| (t.condition)(blackboard, self.time_in_state))
+            .map(|t| t.to)?;
+
+        let from = self.state;
+        self.set_state(to);
+        Some((from, to))
+    }
+}
@@ -0,0 +1,49 @@
+// System clipboard for game code. Falls back to a process-local buffer if there's
+// no clipboard available (e.g. no display server)
+pub struct Clipboard {
+    system: Option<arboard::Clipboard>,
+    local: String,
+}
+
+impl Default for Clipboard {
+    fn default() -> Self {
+        let system = match arboard::Clipboard::new() {
+            Ok(clipboard) => Some(clipboard),
+            Err(err) => {
+                log::warn!("System clipboard is not available: {}", err);
+                None
+            }
+        };
+
+        Self {
+            system,
+            local: String::new(),
+        }
+    }
+}
+
+impl Clipboard {
+    pub fn get(&mut self) -> Option<String> {
+        if let Some(system) = self.system.as_mut() {
+            match system.get_text() {
+                Ok(text) => return Some(text),
+                Err(err) => log::warn!("Failed to read the clipboard: {}", err),
+            }
+        }
+
+        if self.local.is_empty() {
+            None
+        } else {
+            Some(self.local.clone())
+        }
+    }
+
+    pub fn set(&mut self, text: &str) {
+        if let Some(system) = self.system.as_mut() {
+            if let Err(err) = system.set_text(text.to_owned()) {
+                log::warn!("Failed to write the clipboard: {}", err);
+            }
+        }
+        self.local = text.to_owned();
+    }
+}
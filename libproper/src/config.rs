@@ -0,0 +1,76 @@
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{error::Error, i18n::DEFAULT_LOCALE};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Theme {
+    Dark,
+    Light,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GuiConfig {
+    pub theme: Theme,
+    // Multiplied by the window's DPI scale factor
+    pub scale: f32,
+    pub font_size: f32,
+    pub accent_color: [u8; 3],
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub locale: String,
+    pub gui: GuiConfig,
+    #[serde(skip)]
+    path: PathBuf,
+}
+
+impl Default for GuiConfig {
+    fn default() -> Self {
+        Self {
+            theme: Theme::Dark,
+            scale: 1.0,
+            font_size: 14.0,
+            accent_color: [0, 92, 128],
+        }
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            locale: DEFAULT_LOCALE.to_owned(),
+            gui: GuiConfig::default(),
+            path: PathBuf::from(Self::DEFAULT_PATH),
+        }
+    }
+}
+
+impl Config {
+    pub const DEFAULT_PATH: &'static str = "config.toml";
+
+    // Missing file is not an error, defaults are used and saved there later
+    pub fn load_or_default<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let mut config = if path.exists() {
+            let text = std::fs::read_to_string(path).map_err(|err| Error::file(path, err))?;
+            toml::from_str(&text).map_err(|err| Error::asset_parse(path, err))?
+        } else {
+            Self::default()
+        };
+        config.path = path.to_owned();
+        Ok(config)
+    }
+
+    pub fn save(&self) -> Result<(), Error> {
+        let text =
+            toml::to_string_pretty(self).map_err(|err| Error::data_serialize(&self.path, err))?;
+        std::fs::write(&self.path, text).map_err(|err| Error::file(&self.path, err))?;
+        Ok(())
+    }
+}
@@ -0,0 +1,113 @@
+use std::{
+    fmt::Write as _,
+    fs,
+    panic::{self, PanicInfo},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, RwLock},
+    thread,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use backtrace::Backtrace;
+
+use crate::{layer::panic_message, logging::LogHistory, render::stats::Stats, world::scene::Scene};
+
+pub const DEFAULT_LOG_LINES: usize = 64;
+
+pub struct CrashReportConfig {
+    dir: PathBuf,
+    log_lines: usize,
+}
+
+// Engine state captured along with the backtrace
+pub struct CrashContext {
+    pub stats: Arc<Mutex<Stats>>,
+    pub scene: Arc<RwLock<Scene>>,
+    pub log_history: Arc<Mutex<LogHistory>>,
+    pub gpu: String,
+}
+
+impl CrashReportConfig {
+    pub fn new<P: Into<PathBuf>>(dir: P) -> Self {
+        Self {
+            dir: dir.into(),
+            log_lines: DEFAULT_LOG_LINES,
+        }
+    }
+
+    pub fn log_lines(mut self, log_lines: usize) -> Self {
+        self.log_lines = log_lines;
+        self
+    }
+
+    // Writes a report for every panic, including the ones isolated by the layer manager,
+    // and then runs the previously installed hook
+    pub fn install(self, context: CrashContext) {
+        let previous = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            let report = context.report(info, self.log_lines);
+            match write_report(&self.dir, &report) {
+                Ok(path) => eprintln!("Crash report written to {}", path.display()),
+                Err(err) => eprintln!("Failed to write crash report: {}", err),
+            }
+            previous(info);
+        }));
+    }
+}
+
+impl CrashContext {
+    // Locks are only tried: the panic may have happened while holding them
+    fn report(&self, info: &PanicInfo, log_lines: usize) -> String {
+        let mut report = String::new();
+        let thread = thread::current();
+
+        writeln!(report, "proper {} crash report", env!("CARGO_PKG_VERSION")).ok();
+        writeln!(report, "Time: {}", unix_time()).ok();
+        writeln!(report, "Thread: {}", thread.name().unwrap_or("<unnamed>")).ok();
+        write!(report, "Panic: {}", panic_message(info.payload())).ok();
+        if let Some(location) = info.location() {
+            write!(report, " at {}", location).ok();
+        }
+        writeln!(report, "\n").ok();
+
+        match self.stats.try_lock() {
+            Ok(stats) => writeln!(report, "Frame: {}", stats.frame_number).ok(),
+            Err(_) => writeln!(report, "Frame: unavailable (stats are locked)").ok(),
+        };
+        match self.scene.try_read() {
+            Ok(scene) => writeln!(report, "Entities: {}", scene.entities().count()).ok(),
+            Err(_) => writeln!(report, "Entities: unavailable (scene is locked)").ok(),
+        };
+        writeln!(report, "GPU: {}\n", self.gpu).ok();
+
+        writeln!(report, "Backtrace:\n{:?}", Backtrace::new()).ok();
+
+        writeln!(report, "Last log records:").ok();
+        match self.log_history.try_lock() {
+            Ok(history) => {
+                let skip = history.records().len().saturating_sub(log_lines);
+                for record in history.records().skip(skip) {
+                    writeln!(report, "{}", record).ok();
+                }
+            }
+            Err(_) => {
+                writeln!(report, "unavailable (log history is locked)").ok();
+            }
+        }
+
+        report
+    }
+}
+
+fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+fn write_report(dir: &Path, report: &str) -> std::io::Result<PathBuf> {
+    fs::create_dir_all(dir)?;
+    let path = dir.join(format!("crash-{}.txt", unix_time()));
+    fs::write(&path, report)?;
+    Ok(path)
+}
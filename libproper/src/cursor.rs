@@ -0,0 +1,71 @@
+use winit::window::{CursorIcon, Window};
+
+use crate::error::Error;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CursorMode {
+    // Free to leave the window
+    Normal,
+    // Visible, but kept within the window
+    Confined,
+    // Hidden and grabbed, only relative motion is reported
+    Grabbed,
+}
+
+// Window cursor state, owned by the Application and changed through GameEvents.
+// The software cursor is drawn by the GUI in place of the (then hidden) hardware one
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Cursor {
+    mode: CursorMode,
+    icon: CursorIcon,
+    software: bool,
+}
+
+impl Default for Cursor {
+    fn default() -> Self {
+        Self {
+            mode: CursorMode::Normal,
+            icon: CursorIcon::Default,
+            software: false,
+        }
+    }
+}
+
+impl Cursor {
+    #[inline]
+    pub const fn mode(&self) -> CursorMode {
+        self.mode
+    }
+
+    #[inline]
+    pub const fn icon(&self) -> CursorIcon {
+        self.icon
+    }
+
+    #[inline]
+    pub const fn is_software(&self) -> bool {
+        self.software
+    }
+
+    #[inline]
+    pub fn is_grabbed(&self) -> bool {
+        self.mode == CursorMode::Grabbed
+    }
+
+    pub fn set_mode(&mut self, window: &Window, mode: CursorMode) -> Result<(), Error> {
+        window.set_cursor_grab(mode != CursorMode::Normal)?;
+        window.set_cursor_visible(mode != CursorMode::Grabbed);
+        self.mode = mode;
+        Ok(())
+    }
+
+    pub fn set_icon(&mut self, window: &Window, icon: CursorIcon) {
+        self.icon = icon;
+        window.set_cursor_icon(icon);
+    }
+
+    #[inline]
+    pub fn set_software(&mut self, software: bool) {
+        self.software = software;
+    }
+}
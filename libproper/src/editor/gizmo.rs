@@ -0,0 +1,286 @@
+use nalgebra::{Point3, Unit, UnitQuaternion, Vector3};
+
+use crate::{
+    error::Error,
+    world::{entity::EntityId, ray::Ray, scene::Scene},
+};
+
+use super::{selection::SelectionSet, snap::SnapSettings};
+
+// Handle length as a fraction of the distance from the camera, keeps the size on screen
+pub const HANDLE_SCREEN_SIZE: f32 = 0.15;
+// How close to a handle the ray has to pass, relative to the handle length
+const PICK_TOLERANCE: f32 = 0.08;
+const MIN_SCALE: f32 = 0.001;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GizmoMode {
+    Translate,
+    Rotate,
+    Scale,
+}
+
+// Handles are aligned with the world axes
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GizmoAxis {
+    X,
+    Y,
+    Z,
+}
+
+// Transforms at the start of a drag, changes are applied relative to them
+struct Drag {
+    axis: GizmoAxis,
+    // Distance along the axis or angle around it where the drag started
+    start: f32,
+    // Centroid of the dragged entities, rotation and scale are around it
+    origin: Point3<f32>,
+    entities: Vec<DragStart>,
+}
+
+struct DragStart {
+    id: EntityId,
+    position: Point3<f32>,
+    rotation: UnitQuaternion<f32>,
+    scale: Vector3<f32>,
+}
+
+pub struct Gizmo {
+    mode: GizmoMode,
+    snap: SnapSettings,
+    drag: Option<Drag>,
+}
+
+impl GizmoMode {
+    pub const ALL: [Self; 3] = [Self::Translate, Self::Rotate, Self::Scale];
+
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Translate => "Translate",
+            Self::Rotate => "Rotate",
+            Self::Scale => "Scale",
+        }
+    }
+}
+
+impl GizmoAxis {
+    pub const ALL: [Self; 3] = [Self::X, Self::Y, Self::Z];
+
+    pub fn direction(self) -> Vector3<f32> {
+        match self {
+            Self::X => Vector3::x(),
+            Self::Y => Vector3::y(),
+            Self::Z => Vector3::z(),
+        }
+    }
+
+    // Two axes spanning the plane of the rotation ring, u x v is the axis itself
+    pub fn plane_basis(self) -> (Vector3<f32>, Vector3<f32>) {
+        match self {
+            Self::X => (Vector3::y(), Vector3::z()),
+            Self::Y => (Vector3::z(), Vector3::x()),
+            Self::Z => (Vector3::x(), Vector3::y()),
+        }
+    }
+
+    const fn index(self) -> usize {
+        self as usize
+    }
+}
+
+impl Default for Gizmo {
+    fn default() -> Self {
+        Self {
+            mode: GizmoMode::Translate,
+            snap: SnapSettings::default(),
+            drag: None,
+        }
+    }
+}
+
+impl Gizmo {
+    #[inline]
+    pub const fn mode(&self) -> GizmoMode {
+        self.mode
+    }
+
+    // Switching modes ends the current drag, keeping the changes made so far
+    pub fn set_mode(&mut self, mode: GizmoMode) {
+        if mode != self.mode {
+            self.drag = None;
+            self.mode = mode;
+        }
+    }
+
+    // Takes effect on the next drag update
+    pub fn set_snap(&mut self, snap: SnapSettings) {
+        self.snap = snap;
+    }
+
+    pub fn handle_length(camera_position: &Point3<f32>, origin: &Point3<f32>) -> f32 {
+        (origin - camera_position).norm() * HANDLE_SCREEN_SIZE
+    }
+
+    #[inline]
+    pub fn is_dragging(&self) -> bool {
+        self.drag.is_some()
+    }
+
+    pub fn dragged_axis(&self) -> Option<GizmoAxis> {
+        self.drag.as_ref().map(|drag| drag.axis)
+    }
+
+    // Closest handle the ray passes by. Translation and scale handles are segments along the
+    // axes, rotation handles are rings around them
+    pub fn pick(&self, ray: &Ray, origin: &Point3<f32>, length: f32) -> Option<GizmoAxis> {
+        let tolerance = length * PICK_TOLERANCE;
+        GizmoAxis::ALL
+            .into_iter()
+            .filter_map(|axis| {
+                let distance = match self.mode {
+                    GizmoMode::Translate | GizmoMode::Scale => {
+                        ray.distance_to_segment(origin, &(origin + axis.direction() * length))
+                    }
+                    GizmoMode::Rotate => {
+                        let t = ray.intersect_plane(origin, &axis.direction())?;
+                        ((ray.at(t) - origin).norm() - length).abs()
+                    }
+                };
+                (distance <= tolerance).then(|| (axis, distance))
+            })
+            .min_by(|(_, a), (_, b)| a.total_cmp(b))
+            .map(|(axis, _)| axis)
+    }
+
+    // Grabs a handle at the centroid of the selection, returns whether one was hit
+    pub fn begin_drag(
+        &mut self,
+        ray: &Ray,
+        scene: &Scene,
+        selection: &SelectionSet,
+        length: f32,
+    ) -> bool {
+        let origin = match selection.centroid(scene) {
+            Some(origin) => origin,
+            None => return false,
+        };
+        let start = self
+            .pick(ray, &origin, length)
+            .and_then(|axis| Some((axis, self.measure(ray, &origin, axis)?)));
+        let (axis, start) = match start {
+            Some(start) => start,
+            None => return false,
+        };
+
+        let entities = selection
+            .ids()
+            .iter()
+            .filter_map(|&id| scene.get(id))
+            .map(|entity| DragStart {
+                id: entity.id(),
+                position: *entity.position(),
+                rotation: *entity.rotation(),
+                scale: *entity.scale(),
+            })
+            .collect();
+        self.drag = Some(Drag {
+            axis,
+            start,
+            origin,
+            entities,
+        });
+        true
+    }
+
+    pub fn drag(&mut self, ray: &Ray, scene: &mut Scene) -> Result<(), Error> {
+        let drag = match &self.drag {
+            Some(drag) => drag,
+            None => return Ok(()),
+        };
+        // Keeps the last transform while the ray can't be measured, e.g. parallel to the axis
+        let value = match self.measure(ray, &drag.origin, drag.axis) {
+            Some(value) => value,
+            None => return Ok(()),
+        };
+        let direction = drag.axis.direction();
+        let index = drag.axis.index();
+
+        match self.mode {
+            // Only the dragged coordinate of the centroid is snapped, so the entities keep
+            // their offsets from it
+            GizmoMode::Translate => {
+                let target = self
+                    .snap
+                    .translation(drag.origin[index] + value - drag.start);
+                let offset = direction * (target - drag.origin[index]);
+                for start in &drag.entities {
+                    if let Some(entity) = scene.get_mut(start.id) {
+                        entity.set_position(start.position + offset)?;
+                    }
+                }
+            }
+            GizmoMode::Rotate => {
+                let angle = self.snap.angle(value - drag.start);
+                let delta = UnitQuaternion::from_axis_angle(&Unit::new_unchecked(direction), angle);
+                for start in &drag.entities {
+                    if let Some(entity) = scene.get_mut(start.id) {
+                        let position = drag.origin + delta * (start.position - drag.origin);
+                        entity.set_transform(position, delta * start.rotation)?;
+                    }
+                }
+            }
+            GizmoMode::Scale => {
+                // Grabbing the handle right at the origin gives no reference length
+                if drag.start.abs() <= f32::EPSILON {
+                    return Ok(());
+                }
+                let factor = value / drag.start;
+                for start in &drag.entities {
+                    if let Some(entity) = scene.get_mut(start.id) {
+                        let mut scale = start.scale;
+                        scale[index] = self.snap.scale(scale[index] * factor).max(MIN_SCALE);
+                        let mut position = start.position;
+                        position[index] =
+                            drag.origin[index] + (position[index] - drag.origin[index]) * factor;
+                        entity.set_position(position)?;
+                        entity.set_scale(scale)?;
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn end_drag(&mut self) {
+        self.drag = None;
+    }
+
+    // Puts the entities back where the drag started
+    pub fn cancel_drag(&mut self, scene: &mut Scene) -> Result<(), Error> {
+        if let Some(drag) = self.drag.take() {
+            for start in drag.entities {
+                if let Some(entity) = scene.get_mut(start.id) {
+                    entity.set_transform(start.position, start.rotation)?;
+                    entity.set_scale(start.scale)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Distance along the axis for translation and scale, angle around it for rotation
+    fn measure(&self, ray: &Ray, origin: &Point3<f32>, axis: GizmoAxis) -> Option<f32> {
+        match self.mode {
+            GizmoMode::Translate | GizmoMode::Scale => {
+                ray.closest_on_line(origin, &axis.direction())
+            }
+            GizmoMode::Rotate => {
+                let t = ray.intersect_plane(origin, &axis.direction())?;
+                let offset = ray.at(t) - origin;
+                let (u, v) = axis.plane_basis();
+                Some(offset.dot(&v).atan2(offset.dot(&u)))
+            }
+        }
+    }
+}
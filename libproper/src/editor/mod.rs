@@ -0,0 +1,3 @@
+pub mod gizmo;
+pub mod selection;
+pub mod snap;
@@ -0,0 +1,143 @@
+use nalgebra::{Matrix4, Point2, Point3, UnitQuaternion, Vector3};
+
+use crate::{
+    error::Error,
+    world::{entity::EntityId, ray, scene::Scene},
+};
+
+// Entities picked in the editor, in the order they were selected. The last one is the
+// primary, its properties are shown when editing the whole group
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SelectionSet {
+    ids: Vec<EntityId>,
+}
+
+impl SelectionSet {
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    #[inline]
+    pub fn ids(&self) -> &[EntityId] {
+        &self.ids
+    }
+
+    pub fn primary(&self) -> Option<EntityId> {
+        self.ids.last().copied()
+    }
+
+    pub fn contains(&self, id: EntityId) -> bool {
+        self.ids.contains(&id)
+    }
+
+    pub fn clear(&mut self) {
+        self.ids.clear();
+    }
+
+    // Replaces the selection
+    pub fn select(&mut self, id: EntityId) {
+        self.ids.clear();
+        self.ids.push(id);
+    }
+
+    // Makes the entity the primary one if it's already selected
+    pub fn add(&mut self, id: EntityId) {
+        self.ids.retain(|&other| other != id);
+        self.ids.push(id);
+    }
+
+    pub fn toggle(&mut self, id: EntityId) {
+        if self.contains(id) {
+            self.ids.retain(|&other| other != id);
+        } else {
+            self.ids.push(id);
+        }
+    }
+
+    pub fn extend<I: IntoIterator<Item = EntityId>>(&mut self, ids: I) {
+        for id in ids {
+            self.add(id);
+        }
+    }
+
+    // Drops entities which are no longer in the scene
+    pub fn retain_existing(&mut self, scene: &Scene) {
+        self.ids.retain(|&id| scene.get(id).is_some());
+    }
+
+    // Mean position of the selected entities
+    pub fn centroid(&self, scene: &Scene) -> Option<Point3<f32>> {
+        let positions = self
+            .ids
+            .iter()
+            .filter_map(|&id| scene.get(id))
+            .map(|entity| entity.position().coords)
+            .collect::<Vec<_>>();
+        if positions.is_empty() {
+            return None;
+        }
+        let sum: Vector3<f32> = positions.iter().sum();
+        Some(Point3::from(sum / positions.len() as f32))
+    }
+
+    pub fn translate(&self, scene: &mut Scene, delta: &Vector3<f32>) -> Result<(), Error> {
+        for &id in &self.ids {
+            if let Some(entity) = scene.get_mut(id) {
+                entity.set_position(entity.position() + delta)?;
+            }
+        }
+        Ok(())
+    }
+
+    // Turns every entity around the centroid, keeping their relative placement
+    pub fn rotate(&self, scene: &mut Scene, rotation: &UnitQuaternion<f32>) -> Result<(), Error> {
+        let center = match self.centroid(scene) {
+            Some(center) => center,
+            None => return Ok(()),
+        };
+        for &id in &self.ids {
+            if let Some(entity) = scene.get_mut(id) {
+                let position = center + rotation * (entity.position() - center);
+                entity.set_transform(position, rotation * entity.rotation())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// Entities whose projected bounds overlap the rectangle, given in normalized device
+// coordinates. Entities entirely behind the camera are skipped
+pub fn select_in_rect(
+    scene: &Scene,
+    view_projection: &Matrix4<f32>,
+    min: Point2<f32>,
+    max: Point2<f32>,
+) -> Vec<EntityId> {
+    scene
+        .entities()
+        .filter(|entity| {
+            let corners = entity
+                .bounds()
+                .corners()
+                .iter()
+                .filter_map(|corner| ray::project(view_projection, corner))
+                .collect::<Vec<_>>();
+            let first = match corners.first() {
+                Some(first) => *first,
+                None => return false,
+            };
+            let (low, high) = corners.iter().fold((first, first), |(low, high), corner| {
+                (low.inf(corner), high.sup(corner))
+            });
+
+            low.x <= max.x && high.x >= min.x && low.y <= max.y && high.y >= min.y
+        })
+        .map(|entity| entity.id())
+        .collect()
+}
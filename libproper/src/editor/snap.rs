@@ -0,0 +1,105 @@
+use nalgebra::{Point3, UnitQuaternion, Vector3};
+
+use crate::{
+    preferences::{Preferences, SNAP, SNAP_ROTATION, SNAP_SCALE, SNAP_TRANSLATION},
+    world::{entity::EntityId, ray::Ray, scene::Scene},
+};
+
+// Increments edits are rounded to, 0 leaves that kind of edit unsnapped
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SnapSettings {
+    pub enabled: bool,
+    pub translation: f32,
+    // Degrees
+    pub rotation: f32,
+    pub scale: f32,
+}
+
+// Transform that puts an entity onto a surface
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Placement {
+    pub position: Point3<f32>,
+    pub rotation: UnitQuaternion<f32>,
+}
+
+impl Default for SnapSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            translation: 0.5,
+            rotation: 15.0,
+            scale: 0.1,
+        }
+    }
+}
+
+impl SnapSettings {
+    pub fn from_preferences(preferences: &Preferences) -> Self {
+        Self {
+            enabled: preferences.bool(SNAP, false),
+            translation: preferences.float(SNAP_TRANSLATION, 0.5).max(0.0) as f32,
+            rotation: preferences.float(SNAP_ROTATION, 15.0).max(0.0) as f32,
+            scale: preferences.float(SNAP_SCALE, 0.1).max(0.0) as f32,
+        }
+    }
+
+    pub fn translation(&self, value: f32) -> f32 {
+        self.snap(value, self.translation)
+    }
+
+    pub fn position(&self, position: &Point3<f32>) -> Point3<f32> {
+        position.map(|value| self.translation(value))
+    }
+
+    pub fn angle(&self, radians: f32) -> f32 {
+        self.snap(radians.to_degrees(), self.rotation).to_radians()
+    }
+
+    // Values that would round to 0 are kept as they are
+    pub fn scale(&self, value: f32) -> f32 {
+        let snapped = self.snap(value, self.scale);
+        if snapped > 0.0 {
+            snapped
+        } else {
+            value
+        }
+    }
+
+    fn snap(&self, value: f32, increment: f32) -> f32 {
+        if self.enabled && increment > 0.0 {
+            (value / increment).round() * increment
+        } else {
+            value
+        }
+    }
+}
+
+// Casts the ray against everything except the entity and rests the bottom of its model bounds
+// on whatever gets hit. With `align` the entity's up axis is turned to the surface normal
+pub fn place_on_surface(scene: &Scene, id: EntityId, ray: &Ray, align: bool) -> Option<Placement> {
+    let entity = scene.get(id)?;
+    let hit = scene.raycast(ray, Some(id))?;
+
+    let up = if align { hit.normal } else { Vector3::y() };
+    let rotation = if align {
+        UnitQuaternion::rotation_between(&Vector3::y(), &hit.normal)
+            .unwrap_or_else(UnitQuaternion::identity)
+            * entity.rotation()
+    } else {
+        *entity.rotation()
+    };
+    let bottom = entity.mesh().model().bounds().min.y * entity.scale().y;
+
+    Some(Placement {
+        position: hit.point - up * bottom,
+        rotation,
+    })
+}
+
+// Straight down from the top of the entity's bounds
+pub fn drop_to_surface(scene: &Scene, id: EntityId, align: bool) -> Option<Placement> {
+    let entity = scene.get(id)?;
+    let position = entity.position();
+    let origin = Point3::new(position.x, entity.bounds().max.y, position.z);
+    place_on_surface(scene, id, &Ray::new(origin, -Vector3::y()), align)
+}
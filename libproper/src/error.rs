@@ -1,20 +1,41 @@
+use std::{fmt, io, path::PathBuf};
+
 use thiserror::Error as TError;
 use vulkano::{
-    buffer::{cpu_access::WriteLockError, immutable::ImmutableBufferCreationError},
+    buffer::{
+        cpu_access::{ReadLockError, WriteLockError},
+        immutable::ImmutableBufferCreationError,
+    },
     command_buffer::{
-        BuildError, CommandBufferBeginError, CommandBufferExecError, DrawError, RenderPassError,
+        BuildError, CommandBufferBeginError, CommandBufferExecError, CopyError, DispatchError,
+        DrawError, DrawIndexedIndirectError, ExecuteCommandsError, RenderPassError,
     },
     descriptor_set::{layout::DescriptorSetLayoutCreationError, DescriptorSetCreationError},
     device::{physical::SurfacePropertiesError, DeviceCreationError},
+    format::Format,
     image::{view::ImageViewCreationError, ImageCreationError},
     instance::InstanceCreationError,
     memory::DeviceMemoryAllocationError,
-    pipeline::{graphics::GraphicsPipelineCreationError, layout::PipelineLayoutCreationError},
+    pipeline::{
+        compute::ComputePipelineCreationError, graphics::GraphicsPipelineCreationError,
+        layout::PipelineLayoutCreationError,
+    },
     render_pass::{FramebufferCreationError, RenderPassCreationError},
+    sampler::SamplerCreationError,
     shader::ShaderCreationError,
     swapchain::{AcquireError, SwapchainCreationError},
     sync::FlushError,
 };
+use winit::error::ExternalError;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ResourceKind {
+    Material,
+    Model,
+    Texture,
+    BehaviorTree,
+    Locale,
+}
 
 #[derive(TError, Debug)]
 pub enum Error {
@@ -50,10 +71,18 @@ pub enum Error {
     RenderPassOperatoin(#[from] RenderPassError),
     #[error("Draw command error")]
     DrawOperation(#[from] DrawError),
+    #[error("Indirect draw command error")]
+    DrawIndirectOperation(#[from] DrawIndexedIndirectError),
+    #[error("Dispatch command error")]
+    DispatchOperation(#[from] DispatchError),
     #[error("Failed to allocate device memory")]
     DeviceMemoryAllocation(#[from] DeviceMemoryAllocationError),
     #[error("Failed to begin command buffer")]
     CommandBufferBegin(#[from] CommandBufferBeginError),
+    #[error("Failed to execute secondary command buffers")]
+    ExecuteCommands(#[from] ExecuteCommandsError),
+    #[error("Buffer/image copy failed")]
+    Copy(#[from] CopyError),
 
     #[error("Failed to create descriptor set layout")]
     DescriptorSetLayoutCreation(#[from] DescriptorSetLayoutCreationError),
@@ -62,14 +91,26 @@ pub enum Error {
     MissingShaderEntryPoint,
     #[error("Missing render subpass")]
     MissingSubpass,
+    #[error("Material has parameters but its shader declares no material set (set = 1)")]
+    MissingMaterialSet,
     #[error("Failed to load shader")]
     ShaderLoad(#[from] ShaderCreationError),
+    #[error("Failed to compile shader")]
+    ShaderCompilation(#[from] shaderc::Error),
+    #[error("Shader compiler is not available")]
+    ShaderCompilerUnavailable,
+    #[error("The material has no {0} shader variant")]
+    UnsupportedShaderVariant(&'static str),
     #[error("Failed to create graphics pipeline")]
     GraphicsPipelineCreation(#[from] GraphicsPipelineCreationError),
+    #[error("Failed to create compute pipeline")]
+    ComputePipelineCreation(#[from] ComputePipelineCreationError),
     #[error("Failed to create pipeline layout")]
     PipelineLayoutCreation(#[from] PipelineLayoutCreationError),
     #[error("Failed to create image")]
     ImageCreation(#[from] ImageCreationError),
+    #[error("Failed to create sampler")]
+    SamplerCreation(#[from] SamplerCreationError),
     #[error("Failed to create framebuffer")]
     FramebufferCreation(#[from] FramebufferCreationError),
     #[error("Failed to create device-local buffer")]
@@ -77,7 +118,182 @@ pub enum Error {
 
     #[error("Failed to acquire buffer write lock")]
     BufferWriteLock(#[from] WriteLockError),
+    #[error("Failed to acquire buffer read lock")]
+    BufferReadLock(#[from] ReadLockError),
+
+    #[error("I/O error")]
+    Io(#[from] io::Error),
+    #[error("Failed to access {path:?}")]
+    File {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+    #[error("Failed to parse asset {path:?}: {reason}")]
+    AssetParse { path: PathBuf, reason: String },
+    #[error("Failed to parse data file")]
+    DataParse(#[from] toml::de::Error),
+    #[error("Failed to serialize {path:?}")]
+    DataSerialize {
+        path: PathBuf,
+        #[source]
+        source: toml::ser::Error,
+    },
+
+    #[error("Failed to load {kind} {name:?}")]
+    Resource {
+        kind: ResourceKind,
+        name: String,
+        #[source]
+        source: Box<Error>,
+    },
+
+    #[error("Layer {layer} panicked: {message}")]
+    LayerPanic { layer: String, message: String },
+
+    #[error("Failed to install the logger")]
+    LoggerInit(#[from] log::SetLoggerError),
+    #[error("Failed to install the trace exporter: {0}")]
+    TraceInit(String),
+
+    #[error("Window operation failed")]
+    WindowOp(#[from] ExternalError),
 
     #[error("Resource is already loaded")]
     AlreadyLoaded,
+    #[error("Unknown material template: {0:?}")]
+    UnknownMaterial(String),
+    #[error("Unknown texture: {0:?}")]
+    UnknownTexture(String),
+    #[error("Unknown AI action/condition: {0:?}")]
+    UnknownAiLeaf(String),
+    #[error("Unknown scene: {0:?}")]
+    UnknownScene(String),
+    #[error("Preloading scene {0:?} was interrupted")]
+    ScenePreload(String),
+    #[error("Images don't fit into a {0}x{0} atlas")]
+    AtlasOverflow(u32),
+    #[error("Texture data is {actual} bytes, expected {expected}")]
+    TextureDataSize { expected: u64, actual: usize },
+    #[error("Background asset loading failed: {0}")]
+    AssetLoad(String),
+    #[error("Can't capture frames in {0:?} format")]
+    UnsupportedCaptureFormat(Format),
+    #[error("Extrusion produced no geometry")]
+    EmptyExtrusion,
+}
+
+impl fmt::Display for ResourceKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Material => "material",
+            Self::Model => "model",
+            Self::Texture => "texture",
+            Self::BehaviorTree => "behavior tree",
+            Self::Locale => "locale",
+        })
+    }
+}
+
+impl Error {
+    pub fn file<P: Into<PathBuf>>(path: P, source: io::Error) -> Self {
+        Self::File {
+            path: path.into(),
+            source,
+        }
+    }
+
+    // Wraps the error with the resource being loaded when it occurred
+    pub fn context(self, kind: ResourceKind, name: &str) -> Self {
+        Self::Resource {
+            kind,
+            name: name.to_owned(),
+            source: Box::new(self),
+        }
+    }
+
+    // Innermost error of a context chain
+    pub fn root(&self) -> &Self {
+        match self {
+            Self::Resource { source, .. } => source.root(),
+            _ => self,
+        }
+    }
+
+    // Suggestion on how to fix the error, shown to the user along with the message
+    pub fn hint(&self) -> Option<&'static str> {
+        match self.root() {
+            Self::File { source, .. } if source.kind() == io::ErrorKind::NotFound => {
+                Some("Check that the file exists and the game is run from the project directory")
+            }
+            Self::File { .. } => Some("Check the file permissions"),
+            Self::AssetParse { .. } => {
+                Some("The file is either corrupted or in an unsupported format")
+            }
+            Self::DataParse(_) => Some("Fix the syntax error in the data file"),
+            Self::UnknownMaterial(_) => {
+                Some("Register the material template before loading the resources using it")
+            }
+            Self::UnknownAiLeaf(_) => Some("Register the action/condition in the LeafRegistry"),
+            Self::UnknownScene(_) => Some("Preload or insert the scene before switching to it"),
+            Self::AtlasOverflow(_) => {
+                Some("Increase the atlas size or split the images between several atlases")
+            }
+            Self::ShaderCompilation(_) => Some("Check the shader source for compilation errors"),
+            Self::ShaderCompilerUnavailable => Some("Install the shaderc library"),
+            Self::UnsupportedShaderVariant(_) => {
+                Some("Remove the key from the variant the material is loaded with")
+            }
+            Self::LayerPanic { .. } => {
+                Some("The layer was disabled, restart the game to re-enable it")
+            }
+            Self::EmptyExtrusion => {
+                Some("The spline needs a non-zero length and the profile at least two points")
+            }
+            _ => None,
+        }
+    }
+
+    // Missing or broken files, which can be replaced with a placeholder
+    pub fn is_missing_asset(&self) -> bool {
+        matches!(self.root(), Self::File { .. } | Self::AssetParse { .. })
+    }
+
+    pub fn data_serialize<P: Into<PathBuf>>(path: P, source: toml::ser::Error) -> Self {
+        Self::DataSerialize {
+            path: path.into(),
+            source,
+        }
+    }
+
+    pub fn asset_parse<P: Into<PathBuf>, E: std::fmt::Display>(path: P, reason: E) -> Self {
+        Self::AssetParse {
+            path: path.into(),
+            reason: reason.to_string(),
+        }
+    }
+
+    // Message including the whole chain of source errors
+    pub fn full_message(&self) -> String {
+        let mut message = self.to_string();
+        let mut source = std::error::Error::source(self);
+        while let Some(cause) = source {
+            message += &format!(": {}", cause);
+            source = cause.source();
+        }
+        message
+    }
+
+    // Errors after which rendering can't continue, everything else is reported and skipped
+    pub fn is_fatal(&self) -> bool {
+        matches!(
+            self,
+            Self::InstanceCreation(_)
+                | Self::SurfaceCreation(_)
+                | Self::DeviceCreation(_)
+                | Self::NoPhysicalDevice
+                | Self::Flush(FlushError::DeviceLost)
+                | Self::SwapchainAcquire(AcquireError::DeviceLost)
+        )
+    }
 }
@@ -1,10 +1,24 @@
-use std::sync::Arc;
+use std::{path::Path, sync::Arc};
 
+use nalgebra::{Point3, Vector3};
 use vulkano::{
     image::{view::ImageView, SwapchainImage},
     pipeline::graphics::viewport::Viewport,
 };
-use winit::{dpi::PhysicalSize, event::WindowEvent, window::Window};
+use winit::{
+    dpi::PhysicalSize,
+    event::WindowEvent,
+    window::{CursorIcon, Window},
+};
+
+use crate::{
+    cursor::{Cursor, CursorMode},
+    layer::input::Action,
+    preferences::WindowMode,
+    render::upload::UploadId,
+    state::GameState,
+    world::{combat::HitscanShot, entity::EntityId, ray::Ray, streaming::CellCoord},
+};
 
 pub enum Event<'a> {
     SwapchainInvalidated {
@@ -17,13 +31,89 @@ pub enum Event<'a> {
     MouseMotion((f64, f64)),
     // Required for egui-winit compat
     WindowEventWrapped(&'a WindowEvent<'a>),
+    FileHovered(&'a Path),
+    FileHoverCancelled,
+    FileDropped(&'a Path),
     GameEvent(GameEvent),
 }
 
 #[derive(Debug)]
 pub enum GameEvent {
     TestEvent,
-    SetMouseGrab(bool)
+    // Shorthand for SetCursorMode(Grabbed/Normal)
+    SetMouseGrab(bool),
+    SetCursorMode(CursorMode),
+    SetCursorIcon(CursorIcon),
+    SetSoftwareCursor(bool),
+    CursorChanged(Cursor),
+    // A texture was loaded from outside of the resource directory
+    TextureOpened(String),
+    // Resource uploads which were submitted with the last frame have finished
+    UploadsCompleted(Vec<UploadId>),
+    // Shown to the user by the GUI
+    ErrorReported { message: String, hint: Option<String> },
+    CollisionEnter(EntityId, EntityId),
+    CollisionExit(EntityId, EntityId),
+    TriggerEnter { trigger: EntityId, entity: EntityId },
+    TriggerExit { trigger: EntityId, entity: EntityId },
+    Signal { name: String, entity: Option<EntityId> },
+    CellLoaded(CellCoord),
+    CellUnloaded(CellCoord),
+    AnimationEvent { entity: EntityId, name: String },
+    // Taken from the target's health stat, see Stats::apply_damage
+    Damage { target: EntityId, amount: f32, source: Option<EntityId> },
+    // Health reached its minimum, the entity is left in the scene
+    Died { entity: EntityId, source: Option<EntityId> },
+    FireHitscan { ray: Ray, shot: HitscanShot },
+    // A projectile or a hitscan shot hit the entity, for effects and sounds
+    Impact {
+        entity: EntityId,
+        point: Point3<f32>,
+        normal: Vector3<f32>,
+        effect: Option<String>,
+    },
+    // The last entity of the wave was spawned, waves are counted from 0
+    WaveSpawned { spawner: String, wave: u32 },
+    // Text of the interactable under the reticle, None once there's none
+    InteractionPrompt(Option<String>),
+    // The interact key was pressed with the entity focused
+    Interact(EntityId),
+    // Taken from a pickup into the entity's inventory
+    ItemPickedUp { entity: EntityId, item: String, count: u32 },
+    // Adds trauma to the camera shake, in [0, 1]
+    CameraShake(f32),
+    // Red vignette and a bit of shake, in [0, 1]
+    ScreenDamage(f32),
+    ScreenFlash { color: [f32; 3], intensity: f32 },
+    // Blends to a LUT from res/luts over the seconds, None goes back to no grading
+    SetColorGrading { lut: Option<String>, duration: f32 },
+    // In [0, 1], scaled by the preferences
+    SetColorGradingIntensity(f32),
+    // Name of a file in res/cutscenes
+    PlayCutscene(String),
+    StopCutscene,
+    CutsceneFinished(String),
+    // Simulation speed, 1 is normal
+    SetTimeScale(f64),
+    // The previous scene is kept loaded if requested, dropped otherwise
+    SwitchScene { name: String, keep_previous: bool },
+    SceneSwitched(String),
+    // Finished preloading in the background
+    SceneLoaded(String),
+    PushGameState(GameState),
+    PopGameState,
+    SetGameState(GameState),
+    GameStateChanged(GameState),
+    SetLocale(String),
+    SetWindowMode(WindowMode),
+    FocusChanged(bool),
+    // Sent by the GUI when a widget gains or loses keyboard focus
+    SetTextInput(bool),
+    CaptureBinding(Action),
+    // Layers are identified by their names, see LayerManager::set_enabled/move_to
+    SetLayerEnabled { layer: String, enabled: bool },
+    MoveLayer { layer: String, index: usize },
+    Quit,
 }
 
 impl<'a> TryFrom<&'a WindowEvent<'a>> for Event<'a> {
@@ -33,6 +123,9 @@ impl<'a> TryFrom<&'a WindowEvent<'a>> for Event<'a> {
         match value {
             WindowEvent::Resized(new_size) => Ok(Self::WindowResized(*new_size)),
             WindowEvent::CloseRequested => Ok(Self::WindowCloseRequested),
+            WindowEvent::HoveredFile(path) => Ok(Self::FileHovered(path)),
+            WindowEvent::HoveredFileCancelled => Ok(Self::FileHoverCancelled),
+            WindowEvent::DroppedFile(path) => Ok(Self::FileDropped(path)),
             WindowEvent::MouseInput { .. }
             | WindowEvent::MouseWheel { .. }
             | WindowEvent::KeyboardInput { .. }
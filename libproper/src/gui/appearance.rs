@@ -0,0 +1,138 @@
+use std::sync::{Arc, Mutex};
+
+use egui_winit_vulkano::egui;
+use winit::event_loop::EventLoopProxy;
+
+use crate::{
+    config::{Config, GuiConfig, Theme},
+    event::GameEvent,
+    i18n::Localization,
+    lock::Recover,
+};
+
+use super::dock::GuiPanel;
+
+pub struct AppearancePanel {
+    event_proxy: EventLoopProxy<GameEvent>,
+    config: Arc<Mutex<Config>>,
+    localization: Arc<Mutex<Localization>>,
+    locales: Vec<String>,
+    // Set when values were edited, saved once the pointer is released
+    dirty: bool,
+}
+
+pub fn apply_style(ctx: &egui::Context, config: &GuiConfig) {
+    let [r, g, b] = config.accent_color;
+    let accent = egui::Color32::from_rgb(r, g, b);
+
+    let mut visuals = match config.theme {
+        Theme::Dark => egui::Visuals::dark(),
+        Theme::Light => egui::Visuals::light(),
+    };
+    visuals.selection.bg_fill = accent;
+    visuals.hyperlink_color = accent;
+
+    // Font sizes are relative to the default body size
+    let defaults = egui::Style::default();
+    let mut style = (*ctx.style()).clone();
+    let factor = config.font_size / 14.0;
+    for (text_style, font) in style.text_styles.iter_mut() {
+        if let Some(default) = defaults.text_styles.get(text_style) {
+            font.size = default.size * factor;
+        }
+    }
+    style.visuals = visuals;
+
+    ctx.set_style(style);
+}
+
+impl AppearancePanel {
+    pub fn new(
+        event_proxy: EventLoopProxy<GameEvent>,
+        config: Arc<Mutex<Config>>,
+        localization: Arc<Mutex<Localization>>,
+    ) -> Self {
+        Self {
+            event_proxy,
+            config,
+            localization,
+            locales: Localization::available(),
+            dirty: false,
+        }
+    }
+}
+
+impl GuiPanel for AppearancePanel {
+    fn title(&self) -> &str {
+        "Appearance"
+    }
+
+    fn ui(&mut self, ui: &mut egui::Ui) {
+        let i18n = self.localization.lock().recover();
+        let mut config = self.config.lock().recover();
+        let gui = &mut config.gui;
+        let mut changed = false;
+        let mut locale = None;
+
+        egui::Grid::new("appearance").num_columns(2).show(ui, |ui| {
+            ui.label(i18n.get("appearance.language"));
+            egui::ComboBox::from_id_source("locale")
+                .selected_text(i18n.get("language.name"))
+                .show_ui(ui, |ui| {
+                    for name in &self.locales {
+                        if ui.selectable_label(name == i18n.locale(), name).clicked() {
+                            locale = Some(name.clone());
+                        }
+                    }
+                });
+            ui.end_row();
+
+            ui.label(i18n.get("appearance.theme"));
+            ui.horizontal(|ui| {
+                changed |= ui
+                    .selectable_value(&mut gui.theme, Theme::Dark, i18n.get("appearance.dark"))
+                    .changed();
+                changed |= ui
+                    .selectable_value(&mut gui.theme, Theme::Light, i18n.get("appearance.light"))
+                    .changed();
+            });
+            ui.end_row();
+
+            ui.label(i18n.get("appearance.scale"));
+            changed |= ui
+                .add(egui::Slider::new(&mut gui.scale, 0.5..=3.0))
+                .changed();
+            ui.end_row();
+
+            ui.label(i18n.get("appearance.font_size"));
+            changed |= ui
+                .add(egui::Slider::new(&mut gui.font_size, 8.0..=32.0))
+                .changed();
+            ui.end_row();
+
+            ui.label(i18n.get("appearance.accent_color"));
+            changed |= ui.color_edit_button_srgb(&mut gui.accent_color).changed();
+            ui.end_row();
+        });
+
+        if ui.button(i18n.get("appearance.reset")).clicked() {
+            *gui = GuiConfig::default();
+            changed = true;
+        }
+
+        self.dirty |= changed;
+        if self.dirty && !ui.input().pointer.any_down() {
+            self.dirty = false;
+            if let Err(err) = config.save() {
+                log::error!("Failed to save config: {}", err);
+            }
+        }
+
+        // Locale and config are both switched by the event handler
+        if let Some(locale) = locale.filter(|locale| locale != i18n.locale()) {
+            self.event_proxy
+                .send_event(GameEvent::SetLocale(locale))
+                .ok();
+        }
+    }
+}
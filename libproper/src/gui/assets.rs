@@ -0,0 +1,75 @@
+use std::sync::{Arc, Mutex, RwLock};
+
+use egui_winit_vulkano::egui;
+
+use crate::{lock::Recover, resource::texture::TextureRegistry};
+
+use super::dock::GuiPanel;
+
+pub struct AssetsPanel {
+    textures: Arc<RwLock<TextureRegistry>>,
+    selected: Arc<Mutex<Option<String>>>,
+}
+
+impl AssetsPanel {
+    pub fn new(textures: Arc<RwLock<TextureRegistry>>) -> Self {
+        Self {
+            textures,
+            selected: Default::default(),
+        }
+    }
+
+    // Lets the owner open an asset in the panel from outside of the UI
+    #[inline]
+    pub const fn selected(&self) -> &Arc<Mutex<Option<String>>> {
+        &self.selected
+    }
+}
+
+impl GuiPanel for AssetsPanel {
+    fn title(&self) -> &str {
+        "Assets"
+    }
+
+    fn ui(&mut self, ui: &mut egui::Ui) {
+        let textures = self.textures.read().recover();
+        let mut selected = self.selected.lock().recover();
+
+        let mut refresh = false;
+        ui.horizontal(|ui| {
+            ui.label("Textures");
+            refresh = ui.button("Refresh").clicked();
+        });
+        egui::ScrollArea::vertical()
+            .max_height(200.0)
+            .show(ui, |ui| {
+                for name in textures.available() {
+                    let is_selected = selected.as_deref() == Some(name.as_str());
+                    if ui.selectable_label(is_selected, &name).clicked() {
+                        *selected = Some(name);
+                    }
+                }
+            });
+
+        ui.separator();
+        if let Some(name) = selected.as_deref() {
+            ui.label(format!("Name: {}", name));
+            match textures.get(name) {
+                Some(texture) => {
+                    let [width, height] = texture.dimensions();
+                    ui.label(format!("Size: {}x{}", width, height));
+                }
+                None => {
+                    ui.label("Not loaded");
+                }
+            }
+        } else {
+            ui.label("Drop an image into the window to open it");
+        }
+
+        drop(textures);
+        if refresh {
+            self.textures.write().recover().refresh_available();
+        }
+    }
+}
@@ -0,0 +1,199 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    sync::{Arc, Mutex},
+};
+
+use crate::lock::Recover;
+
+#[cfg(feature = "gui")]
+use egui_winit_vulkano::egui;
+
+#[cfg(feature = "gui")]
+use super::dock::GuiPanel;
+
+const MAX_LINES: usize = 1000;
+
+pub type CommandHandler = Box<dyn FnMut(&[&str]) -> Result<String, String>>;
+
+struct ConsoleCommand {
+    help: String,
+    handler: CommandHandler,
+}
+
+#[derive(Default)]
+pub struct Console {
+    lines: VecDeque<String>,
+    history: Vec<String>,
+    commands: BTreeMap<String, ConsoleCommand>,
+}
+
+#[cfg(feature = "gui")]
+pub struct ConsolePanel {
+    console: Arc<Mutex<Console>>,
+    input: String,
+    // Position when browsing history with up/down, None when editing a new line
+    history_cursor: Option<usize>,
+}
+
+impl Console {
+    pub fn register_command<F>(&mut self, name: &str, help: &str, handler: F)
+    where
+        F: FnMut(&[&str]) -> Result<String, String> + 'static,
+    {
+        if self
+            .commands
+            .insert(
+                name.to_owned(),
+                ConsoleCommand {
+                    help: help.to_owned(),
+                    handler: Box::new(handler),
+                },
+            )
+            .is_some()
+        {
+            log::warn!("Replacing console command {:?}", name);
+        }
+    }
+
+    pub fn print<S: Into<String>>(&mut self, line: S) {
+        if self.lines.len() == MAX_LINES {
+            self.lines.pop_front();
+        }
+        self.lines.push_back(line.into());
+    }
+
+    pub fn clear(&mut self) {
+        self.lines.clear();
+    }
+
+    #[inline]
+    pub fn lines(&self) -> impl Iterator<Item = &String> {
+        self.lines.iter()
+    }
+
+    #[inline]
+    pub fn history(&self) -> &[String] {
+        &self.history
+    }
+
+    pub fn execute(&mut self, line: &str) {
+        let line = line.trim();
+        if line.is_empty() {
+            return;
+        }
+
+        self.print(format!("> {}", line));
+        if self.history.last().map_or(true, |last| last != line) {
+            self.history.push(line.to_owned());
+        }
+
+        let words = line.split_whitespace().collect::<Vec<_>>();
+        match words[0] {
+            "help" => {
+                let help = self
+                    .commands
+                    .iter()
+                    .map(|(name, command)| format!("{} - {}", name, command.help))
+                    .collect::<Vec<_>>();
+                self.print("help - list commands");
+                self.print("clear - clear console output");
+                help.into_iter().for_each(|line| self.print(line));
+            }
+            "clear" => self.clear(),
+            name => {
+                let result = match self.commands.get_mut(name) {
+                    Some(command) => (command.handler)(&words[1..]),
+                    None => Err(format!("Unknown command: {:?}", name)),
+                };
+                match result {
+                    Ok(output) if output.is_empty() => (),
+                    Ok(output) => self.print(output),
+                    Err(err) => self.print(format!("Error: {}", err)),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(feature = "gui")]
+impl ConsolePanel {
+    pub fn new(console: Arc<Mutex<Console>>) -> Self {
+        Self {
+            console,
+            input: String::new(),
+            history_cursor: None,
+        }
+    }
+
+    fn browse_history(&mut self, console: &Console, up: bool) {
+        let history = console.history();
+        if history.is_empty() {
+            return;
+        }
+
+        self.history_cursor = match (self.history_cursor, up) {
+            (None, true) => Some(history.len() - 1),
+            (Some(i), true) => Some(i.saturating_sub(1)),
+            (Some(i), false) if i + 1 < history.len() => Some(i + 1),
+            (_, false) => None,
+        };
+        self.input = self
+            .history_cursor
+            .map_or_else(String::new, |i| history[i].clone());
+    }
+}
+
+#[cfg(feature = "gui")]
+impl GuiPanel for ConsolePanel {
+    fn title(&self) -> &str {
+        "Console"
+    }
+
+    fn ui(&mut self, ui: &mut egui::Ui) {
+        let console = self.console.clone();
+        let mut console = console.lock().recover();
+
+        let response = ui.add(
+            egui::TextEdit::singleline(&mut self.input)
+                .desired_width(f32::INFINITY)
+                .hint_text("Type \"help\" for the list of commands"),
+        );
+
+        if response.lost_focus() && ui.input().key_pressed(egui::Key::Enter) {
+            let line = std::mem::take(&mut self.input);
+            console.execute(&line);
+            self.history_cursor = None;
+            response.request_focus();
+        } else if response.has_focus() && ui.input().key_pressed(egui::Key::ArrowUp) {
+            self.browse_history(&console, true);
+        } else if response.has_focus() && ui.input().key_pressed(egui::Key::ArrowDown) {
+            self.browse_history(&console, false);
+        }
+
+        ui.separator();
+        egui::ScrollArea::vertical()
+            .id_source("console_output")
+            .stick_to_bottom()
+            .auto_shrink([false; 2])
+            .show(ui, |ui| {
+                for line in console.lines() {
+                    // Copied text goes to the system clipboard through egui-winit
+                    ui.add(
+                        egui::Label::new(egui::RichText::new(line).monospace())
+                            .sense(egui::Sense::click()),
+                    )
+                    .context_menu(|ui| {
+                        if ui.button("Copy").clicked() {
+                            ui.output().copied_text = line.clone();
+                            ui.close_menu();
+                        }
+                        if ui.button("Copy all").clicked() {
+                            ui.output().copied_text =
+                                console.lines().cloned().collect::<Vec<_>>().join("\n");
+                            ui.close_menu();
+                        }
+                    });
+                }
+            });
+    }
+}
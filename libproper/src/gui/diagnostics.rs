@@ -0,0 +1,101 @@
+use std::sync::{Arc, RwLock};
+
+use egui_winit_vulkano::egui;
+
+use crate::{
+    lock::Recover,
+    world::{
+        scene::Scene,
+        validate::{SceneIssue, SceneStats},
+    },
+};
+
+use super::{dock::GuiPanel, Selection};
+
+const WARNING_COLOR: egui::Color32 = egui::Color32::from_rgb(255, 200, 64);
+
+// Results are kept until the scene is checked again, clicking an issue selects its entity
+pub struct DiagnosticsPanel {
+    scene: Arc<RwLock<Scene>>,
+    selection: Selection,
+    report: Option<(Vec<SceneIssue>, SceneStats)>,
+}
+
+impl DiagnosticsPanel {
+    pub fn new(scene: Arc<RwLock<Scene>>, selection: Selection) -> Self {
+        Self {
+            scene,
+            selection,
+            report: None,
+        }
+    }
+
+    fn check(&mut self) {
+        let scene = self.scene.read().recover();
+        let issues = scene.validate();
+        if !issues.is_empty() {
+            log::warn!("Scene has {} issue(s)", issues.len());
+        }
+        self.report = Some((issues, scene.stats()));
+    }
+}
+
+impl GuiPanel for DiagnosticsPanel {
+    fn title(&self) -> &str {
+        "Diagnostics"
+    }
+
+    fn ui(&mut self, ui: &mut egui::Ui) {
+        if ui.button("Check scene").clicked() || self.report.is_none() {
+            self.check();
+        }
+        let (issues, stats) = match &self.report {
+            Some(report) => report,
+            None => return,
+        };
+
+        ui.separator();
+        ui.label(format!(
+            "Entities: {} ({} loading)",
+            stats.entities, stats.loading
+        ));
+        ui.label(format!("Triangles: {}", stats.triangles));
+        egui::Grid::new("diagnostics_materials")
+            .num_columns(3)
+            .striped(true)
+            .show(ui, |ui| {
+                ui.strong("Material");
+                ui.strong("Entities");
+                ui.strong("Triangles");
+                ui.end_row();
+
+                for material in &stats.materials {
+                    ui.label(format!("#{}", material.template_id));
+                    ui.label(material.entities.to_string());
+                    ui.label(material.triangles.to_string());
+                    ui.end_row();
+                }
+            });
+
+        ui.separator();
+        if issues.is_empty() {
+            ui.label("No issues found");
+            return;
+        }
+        ui.colored_label(WARNING_COLOR, format!("{} issue(s)", issues.len()));
+        egui::ScrollArea::vertical()
+            .id_source("diagnostics_issues")
+            .show(ui, |ui| {
+                let mut selection = self.selection.lock().recover();
+                for issue in issues {
+                    let entity = issue.entity();
+                    if ui
+                        .selectable_label(selection.contains(entity), issue.to_string())
+                        .clicked()
+                    {
+                        selection.select(entity);
+                    }
+                }
+            });
+    }
+}
@@ -0,0 +1,173 @@
+use std::collections::HashMap;
+
+use egui_winit_vulkano::egui;
+
+pub trait GuiPanel {
+    fn title(&self) -> &str;
+    fn ui(&mut self, ui: &mut egui::Ui);
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum DockArea {
+    Left,
+    Right,
+    Bottom,
+    Floating,
+}
+
+struct DockedPanel {
+    panel: Box<dyn GuiPanel>,
+    area: DockArea,
+    open: bool,
+}
+
+// Panels grouped into tabbed areas around the viewport; tabs can be moved
+// between areas (or floated) through their context menu
+#[derive(Default)]
+pub struct Workspace {
+    panels: Vec<DockedPanel>,
+    active: HashMap<DockArea, usize>,
+}
+
+enum DockAction {
+    Move(usize, DockArea),
+    Close(usize),
+}
+
+impl DockArea {
+    const ALL: [Self; 4] = [Self::Left, Self::Right, Self::Bottom, Self::Floating];
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Left => "Left",
+            Self::Right => "Right",
+            Self::Bottom => "Bottom",
+            Self::Floating => "Floating",
+        }
+    }
+}
+
+impl Workspace {
+    pub fn register<P: GuiPanel + 'static>(&mut self, panel: P, area: DockArea) {
+        self.panels.push(DockedPanel {
+            panel: Box::new(panel),
+            area,
+            open: true,
+        });
+    }
+
+    pub fn set_open(&mut self, title: &str, open: bool) {
+        if let Some(docked) = self.panels.iter_mut().find(|p| p.panel.title() == title) {
+            docked.open = open;
+        }
+    }
+
+    // Opens the panel and brings its tab to the front
+    pub fn focus(&mut self, title: &str) {
+        if let Some(index) = self.panels.iter().position(|p| p.panel.title() == title) {
+            let docked = &mut self.panels[index];
+            docked.open = true;
+            self.active.insert(docked.area, index);
+        }
+    }
+
+    pub fn show(&mut self, ctx: &egui::Context) {
+        let mut action = None;
+
+        egui::TopBottomPanel::top("workspace_menu").show(ctx, |ui| {
+            egui::menu::bar(ui, |ui| {
+                ui.menu_button("Panels", |ui| {
+                    for docked in self.panels.iter_mut() {
+                        ui.checkbox(&mut docked.open, docked.panel.title());
+                    }
+                });
+            });
+        });
+
+        if self.has_panels(DockArea::Left) {
+            egui::SidePanel::left("dock_left")
+                .resizable(true)
+                .default_width(250.0)
+                .show(ctx, |ui| self.area_ui(ui, DockArea::Left, &mut action));
+        }
+        if self.has_panels(DockArea::Right) {
+            egui::SidePanel::right("dock_right")
+                .resizable(true)
+                .default_width(250.0)
+                .show(ctx, |ui| self.area_ui(ui, DockArea::Right, &mut action));
+        }
+        if self.has_panels(DockArea::Bottom) {
+            egui::TopBottomPanel::bottom("dock_bottom")
+                .resizable(true)
+                .default_height(160.0)
+                .show(ctx, |ui| self.area_ui(ui, DockArea::Bottom, &mut action));
+        }
+
+        for (index, docked) in self.panels.iter_mut().enumerate() {
+            if !docked.open || docked.area != DockArea::Floating {
+                continue;
+            }
+            egui::Window::new(docked.panel.title().to_owned())
+                .open(&mut docked.open)
+                .show(ctx, |ui| {
+                    ui.horizontal(|ui| {
+                        ui.menu_button("Dock", |ui| dock_menu(ui, index, &mut action));
+                    });
+                    docked.panel.ui(ui);
+                });
+        }
+
+        match action {
+            Some(DockAction::Move(index, area)) => self.panels[index].area = area,
+            Some(DockAction::Close(index)) => self.panels[index].open = false,
+            None => (),
+        }
+    }
+
+    fn has_panels(&self, area: DockArea) -> bool {
+        self.panels.iter().any(|p| p.open && p.area == area)
+    }
+
+    fn area_ui(&mut self, ui: &mut egui::Ui, area: DockArea, action: &mut Option<DockAction>) {
+        let indices = self
+            .panels
+            .iter()
+            .enumerate()
+            .filter(|(_, p)| p.open && p.area == area)
+            .map(|(i, _)| i)
+            .collect::<Vec<_>>();
+
+        let active = self.active.entry(area).or_insert(indices[0]);
+        if !indices.contains(active) {
+            *active = indices[0];
+        }
+
+        ui.horizontal(|ui| {
+            for &index in indices.iter() {
+                ui.selectable_value(active, index, self.panels[index].panel.title())
+                    .context_menu(|ui| dock_menu(ui, index, action));
+            }
+        });
+        ui.separator();
+
+        let index = *active;
+        egui::ScrollArea::vertical()
+            .id_source(area.name())
+            .auto_shrink([false; 2])
+            .show(ui, |ui| self.panels[index].panel.ui(ui));
+    }
+}
+
+fn dock_menu(ui: &mut egui::Ui, index: usize, action: &mut Option<DockAction>) {
+    for area in DockArea::ALL {
+        if ui.button(area.name()).clicked() {
+            *action = Some(DockAction::Move(index, area));
+            ui.close_menu();
+        }
+    }
+    ui.separator();
+    if ui.button("Close").clicked() {
+        *action = Some(DockAction::Close(index));
+        ui.close_menu();
+    }
+}
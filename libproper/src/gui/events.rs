@@ -0,0 +1,125 @@
+use std::sync::{Arc, Mutex};
+
+use egui_winit_vulkano::egui;
+
+use crate::{
+    layer::event_log::{EventLog, EventRecord},
+    lock::Recover,
+};
+
+use super::dock::GuiPanel;
+
+// Kinds which arrive every frame and bury everything else
+const NOISY_KINDS: [&str; 2] = ["MouseMotion", "WindowEvent"];
+
+pub struct EventLogPanel {
+    log: Arc<Mutex<EventLog>>,
+    filter: String,
+    consumer: String,
+    hide_noisy: bool,
+    unhandled_only: bool,
+}
+
+impl EventLogPanel {
+    pub fn new(log: Arc<Mutex<EventLog>>) -> Self {
+        Self {
+            log,
+            filter: String::new(),
+            consumer: String::new(),
+            hide_noisy: true,
+            unhandled_only: false,
+        }
+    }
+
+    fn accepts(&self, record: &EventRecord) -> bool {
+        if self.hide_noisy && NOISY_KINDS.contains(&record.kind.as_str()) {
+            return false;
+        }
+        if self.unhandled_only && record.consumer.is_some() {
+            return false;
+        }
+        let consumer = record.consumer.as_deref().unwrap_or_default();
+        (record.kind.contains(self.filter.as_str())
+            || record.summary.contains(self.filter.as_str()))
+            && consumer.contains(self.consumer.as_str())
+    }
+}
+
+// Layer type names are full paths, the last segment is enough to tell them apart
+fn short_name(name: &str) -> &str {
+    name.rsplit("::").next().unwrap_or(name)
+}
+
+impl GuiPanel for EventLogPanel {
+    fn title(&self) -> &str {
+        "Events"
+    }
+
+    fn ui(&mut self, ui: &mut egui::Ui) {
+        let log = self.log.clone();
+        let mut log = log.lock().recover();
+
+        ui.horizontal(|ui| {
+            let mut recording = log.is_recording();
+            if ui.checkbox(&mut recording, "Record").changed() {
+                log.set_recording(recording);
+            }
+            let mut frames = log.frames();
+            if ui
+                .add(
+                    egui::DragValue::new(&mut frames)
+                        .clamp_range(1..=3600)
+                        .suffix(" frames"),
+                )
+                .changed()
+            {
+                log.set_frames(frames);
+            }
+            if ui.button("Clear").clicked() {
+                log.clear();
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.add(
+                egui::TextEdit::singleline(&mut self.filter)
+                    .desired_width(160.0)
+                    .hint_text("Event"),
+            );
+            ui.add(
+                egui::TextEdit::singleline(&mut self.consumer)
+                    .desired_width(120.0)
+                    .hint_text("Layer"),
+            );
+            ui.checkbox(&mut self.hide_noisy, "Hide input");
+            ui.checkbox(&mut self.unhandled_only, "Unhandled only");
+        });
+
+        ui.separator();
+        let frame = log.frame();
+        egui::ScrollArea::vertical()
+            .id_source("event_log")
+            .stick_to_bottom()
+            .auto_shrink([false; 2])
+            .show(ui, |ui| {
+                egui::Grid::new("event_log_grid")
+                    .num_columns(4)
+                    .striped(true)
+                    .show(ui, |ui| {
+                        for record in log.records().filter(|r| self.accepts(r)) {
+                            ui.monospace(format!("-{}", frame - record.frame));
+                            ui.monospace(&record.kind);
+                            match &record.consumer {
+                                Some(consumer) => ui.label(short_name(consumer)),
+                                None => ui.weak("unhandled"),
+                            };
+                            ui.add(
+                                egui::Label::new(egui::RichText::new(&record.summary).monospace())
+                                    .wrap(false),
+                            )
+                            .on_hover_text(&record.summary);
+                            ui.end_row();
+                        }
+                    });
+            });
+    }
+}
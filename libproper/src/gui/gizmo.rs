@@ -0,0 +1,306 @@
+use std::sync::{Arc, Mutex, RwLock};
+
+use egui_winit_vulkano::egui;
+use nalgebra::{Matrix4, Point2, Point3};
+
+use crate::{
+    editor::{
+        gizmo::{Gizmo, GizmoAxis, GizmoMode},
+        selection,
+        snap::{self, Placement, SnapSettings},
+    },
+    lock::Recover,
+    preferences::{Preferences, SNAP},
+    render::aspect::AspectLock,
+    world::{
+        ray::{self, Ray},
+        scene::Scene,
+    },
+};
+
+use super::Selection;
+
+const RING_SEGMENTS: usize = 48;
+// Box selections smaller than this, in points, pick the entity under the cursor instead
+const CLICK_DISTANCE: f32 = 4.0;
+const HIGHLIGHT_COLOR: egui::Color32 = egui::Color32::from_rgb(255, 220, 64);
+
+// Draws the gizmo of the selection over the scene and drags it with the mouse. Shift-dragging
+// selects the entities in a box, with Ctrl held they're added to the selection
+pub struct GizmoOverlay {
+    scene: Arc<RwLock<Scene>>,
+    selection: Selection,
+    preferences: Arc<Mutex<Preferences>>,
+    gizmo: Gizmo,
+    hovered: Option<GizmoAxis>,
+    box_start: Option<egui::Pos2>,
+    shift: bool,
+}
+
+fn axis_color(axis: GizmoAxis) -> egui::Color32 {
+    match axis {
+        GizmoAxis::X => egui::Color32::from_rgb(230, 60, 60),
+        GizmoAxis::Y => egui::Color32::from_rgb(60, 200, 60),
+        GizmoAxis::Z => egui::Color32::from_rgb(60, 100, 240),
+    }
+}
+
+// Vulkan NDC has Y pointing down, same as egui
+fn to_ndc(pos: egui::Pos2, screen: egui::Rect) -> Point2<f32> {
+    Point2::new(
+        (pos.x - screen.min.x) / screen.width() * 2.0 - 1.0,
+        (pos.y - screen.min.y) / screen.height() * 2.0 - 1.0,
+    )
+}
+
+pub(super) fn to_screen(
+    view_projection: &Matrix4<f32>,
+    point: &Point3<f32>,
+    screen: egui::Rect,
+) -> Option<egui::Pos2> {
+    let ndc = ray::project(view_projection, point)?;
+    Some(egui::pos2(
+        screen.min.x + (ndc.x + 1.0) * 0.5 * screen.width(),
+        screen.min.y + (ndc.y + 1.0) * 0.5 * screen.height(),
+    ))
+}
+
+impl GizmoOverlay {
+    pub fn new(
+        scene: Arc<RwLock<Scene>>,
+        selection: Selection,
+        preferences: Arc<Mutex<Preferences>>,
+    ) -> Self {
+        Self {
+            scene,
+            selection,
+            preferences,
+            gizmo: Gizmo::default(),
+            hovered: None,
+            box_start: None,
+            shift: false,
+        }
+    }
+
+    // Mouse presses over a handle or starting a box selection shouldn't reach the game
+    pub fn wants_pointer(&self) -> bool {
+        self.hovered.is_some() || self.gizmo.is_dragging() || self.box_start.is_some() || self.shift
+    }
+
+    // Escape cancels the drag instead of pausing
+    #[inline]
+    pub fn is_dragging(&self) -> bool {
+        self.gizmo.is_dragging()
+    }
+
+    pub fn show(&mut self, ctx: &egui::Context) {
+        self.hovered = None;
+        let mut selection = self.selection.lock().recover();
+        let mut scene = self.scene.write().recover();
+        selection.retain_existing(&scene);
+
+        let drop_to_surface = if selection.is_empty() {
+            self.gizmo.end_drag();
+            None
+        } else {
+            self.toolbar(ctx)
+        };
+        if let Some(align) = drop_to_surface {
+            for &id in selection.ids() {
+                let placement = snap::drop_to_surface(&scene, id, align);
+                if let Some((Placement { position, rotation }, entity)) =
+                    placement.zip(scene.get_mut(id))
+                {
+                    if let Err(err) = entity.set_transform(position, rotation) {
+                        log::error!("Failed to move entity: {}", err);
+                    }
+                }
+            }
+        }
+
+        let screen = ctx.input().screen_rect();
+        let aspect = AspectLock::from_preferences(&self.preferences.lock().recover());
+        let view_projection = aspect.projection(&scene.camera, screen.width(), screen.height())
+            * scene.camera.view_matrix();
+        let camera_position = *scene.camera.position();
+
+        let over_gui = ctx.is_pointer_over_area();
+        // Copied out so the input lock isn't held across other context calls
+        let (hover_pos, pressed, down, escape, modifiers) = {
+            let input = ctx.input();
+            (
+                input.pointer.hover_pos(),
+                input.pointer.primary_pressed(),
+                input.pointer.primary_down(),
+                input.key_pressed(egui::Key::Escape),
+                input.modifiers,
+            )
+        };
+        self.shift = modifiers.shift && !over_gui;
+        let ray = hover_pos.and_then(|pos| Ray::from_ndc(&view_projection, to_ndc(pos, screen)));
+        let origin = selection.centroid(&scene);
+        let length = origin.map_or(0.0, |origin| {
+            Gizmo::handle_length(&camera_position, &origin)
+        });
+
+        let mut result = Ok(());
+        if self.gizmo.is_dragging() {
+            if escape {
+                result = self.gizmo.cancel_drag(&mut scene);
+            } else if !down {
+                self.gizmo.end_drag();
+            } else if let Some(ray) = ray {
+                result = self.gizmo.drag(&ray, &mut scene);
+            }
+        } else if let Some(start) = self.box_start {
+            let end = hover_pos.unwrap_or(start);
+            if !down {
+                self.box_start = None;
+                if !modifiers.command {
+                    selection.clear();
+                }
+                if start.distance(end) < CLICK_DISTANCE {
+                    if let Some(hit) = ray.and_then(|ray| scene.raycast(&ray, None)) {
+                        selection.toggle(hit.entity);
+                    }
+                } else {
+                    let (a, b) = (to_ndc(start, screen), to_ndc(end, screen));
+                    selection.extend(selection::select_in_rect(
+                        &scene,
+                        &view_projection,
+                        a.inf(&b),
+                        a.sup(&b),
+                    ));
+                }
+            } else {
+                let painter = ctx.layer_painter(egui::LayerId::background());
+                let rect = egui::Rect::from_two_pos(start, end);
+                painter.rect_filled(rect, 0.0, HIGHLIGHT_COLOR.linear_multiply(0.1));
+                painter.rect_stroke(rect, 0.0, egui::Stroke::new(1.0, HIGHLIGHT_COLOR));
+            }
+        } else if pressed && !over_gui {
+            let grabbed = ray.map_or(false, |ray| {
+                self.gizmo.begin_drag(&ray, &scene, &selection, length)
+            });
+            if !grabbed && modifiers.shift {
+                self.box_start = hover_pos;
+            }
+        }
+        if let Err(err) = result {
+            log::error!("Failed to transform entity: {}", err);
+        }
+
+        // The entities may have moved while dragging
+        let origin = match selection.centroid(&scene) {
+            Some(origin) => origin,
+            None => return,
+        };
+        self.hovered = self.gizmo.dragged_axis().or_else(|| {
+            ray.filter(|_| !over_gui && self.box_start.is_none())
+                .and_then(|ray| self.gizmo.pick(&ray, &origin, length))
+        });
+
+        self.paint(ctx, &view_projection, &origin, length, screen);
+    }
+
+    // Returns Some(align) if the selection should be dropped onto the surface below
+    fn toolbar(&mut self, ctx: &egui::Context) -> Option<bool> {
+        let mut mode = self.gizmo.mode();
+        let mut snap = SnapSettings::from_preferences(&self.preferences.lock().recover());
+        let mut snap_changed = false;
+        let mut drop_to_surface = None;
+        egui::Area::new("gizmo_mode")
+            .anchor(egui::Align2::CENTER_TOP, [0.0, 8.0])
+            .show(ctx, |ui| {
+                egui::Frame::popup(ui.style()).show(ui, |ui| {
+                    ui.horizontal(|ui| {
+                        for value in GizmoMode::ALL {
+                            ui.selectable_value(&mut mode, value, value.name());
+                        }
+                        ui.separator();
+                        snap_changed = ui.checkbox(&mut snap.enabled, "Snap").changed();
+                        ui.separator();
+                        if ui.button("Drop").clicked() {
+                            drop_to_surface = Some(false);
+                        }
+                        if ui
+                            .button("Align")
+                            .on_hover_text("Drop and turn to the surface normal")
+                            .clicked()
+                        {
+                            drop_to_surface = Some(true);
+                        }
+                    });
+                });
+            });
+        self.gizmo.set_mode(mode);
+        if snap_changed {
+            self.preferences.lock().recover().set(SNAP, snap.enabled);
+        }
+        self.gizmo.set_snap(snap);
+
+        drop_to_surface
+    }
+
+    fn paint(
+        &self,
+        ctx: &egui::Context,
+        view_projection: &Matrix4<f32>,
+        origin: &Point3<f32>,
+        length: f32,
+        screen: egui::Rect,
+    ) {
+        let painter = ctx.layer_painter(egui::LayerId::background());
+        let center = match to_screen(view_projection, origin, screen) {
+            Some(center) => center,
+            None => return,
+        };
+
+        for axis in GizmoAxis::ALL {
+            let color = if self.hovered == Some(axis) {
+                HIGHLIGHT_COLOR
+            } else {
+                axis_color(axis)
+            };
+            let stroke = egui::Stroke::new(2.0, color);
+
+            match self.gizmo.mode() {
+                GizmoMode::Translate | GizmoMode::Scale => {
+                    let tip = match to_screen(
+                        view_projection,
+                        &(origin + axis.direction() * length),
+                        screen,
+                    ) {
+                        Some(tip) => tip,
+                        None => continue,
+                    };
+                    painter.line_segment([center, tip], stroke);
+                    if self.gizmo.mode() == GizmoMode::Translate {
+                        painter.circle_filled(tip, 5.0, color);
+                    } else {
+                        painter.rect_filled(
+                            egui::Rect::from_center_size(tip, egui::vec2(9.0, 9.0)),
+                            0.0,
+                            color,
+                        );
+                    }
+                }
+                GizmoMode::Rotate => {
+                    let (u, v) = axis.plane_basis();
+                    let points = (0..=RING_SEGMENTS)
+                        .map(|i| {
+                            let angle = i as f32 / RING_SEGMENTS as f32 * std::f32::consts::TAU;
+                            let point = origin + (u * angle.cos() + v * angle.sin()) * length;
+                            to_screen(view_projection, &point, screen)
+                        })
+                        .collect::<Vec<_>>();
+                    for pair in points.windows(2) {
+                        if let [Some(a), Some(b)] = pair {
+                            painter.line_segment([*a, *b], stroke);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,73 @@
+use std::sync::{atomic::Ordering, Arc, RwLock};
+
+use egui_winit_vulkano::egui;
+use winit::event_loop::EventLoopProxy;
+
+use crate::{event::GameEvent, lock::Recover, world::scene::Scene};
+
+use super::{dock::GuiPanel, Selection};
+
+pub struct HierarchyPanel {
+    event_proxy: EventLoopProxy<GameEvent>,
+    scene: Arc<RwLock<Scene>>,
+    selection: Selection,
+}
+
+impl HierarchyPanel {
+    pub fn new(
+        event_proxy: EventLoopProxy<GameEvent>,
+        scene: Arc<RwLock<Scene>>,
+        selection: Selection,
+    ) -> Self {
+        Self {
+            event_proxy,
+            scene,
+            selection,
+        }
+    }
+}
+
+impl GuiPanel for HierarchyPanel {
+    fn title(&self) -> &str {
+        "Scene"
+    }
+
+    fn ui(&mut self, ui: &mut egui::Ui) {
+        if ui.button("Spawn test entity").clicked() {
+            self.event_proxy.send_event(GameEvent::TestEvent).ok();
+        }
+        ui.separator();
+
+        let scene = self.scene.read().recover();
+        let mut selection = self.selection.lock().recover();
+
+        for group in scene.iter() {
+            let template_id = group.material_template.id().load(Ordering::Acquire);
+            egui::CollapsingHeader::new(format!("Material #{}", template_id))
+                .default_open(true)
+                .show(ui, |ui| {
+                    for entity in group.iter() {
+                        let position = entity.position();
+                        let response = ui.selectable_label(
+                            selection.contains(entity.id()),
+                            format!(
+                                "Entity #{} ({:.1}, {:.1}, {:.1})",
+                                entity.id().0,
+                                position.x,
+                                position.y,
+                                position.z
+                            ),
+                        );
+                        // Ctrl-click adds to or removes from the selection
+                        if response.clicked() {
+                            if ui.input().modifiers.command {
+                                selection.toggle(entity.id());
+                            } else {
+                                selection.select(entity.id());
+                            }
+                        }
+                    }
+                });
+        }
+    }
+}
@@ -0,0 +1,149 @@
+use std::sync::{atomic::Ordering, Arc, RwLock};
+
+use egui_winit_vulkano::egui;
+
+use crate::{lock::Recover, resource::texture::TextureRegistry, world::scene::Scene};
+
+use super::{dock::GuiPanel, material::material_editor, Selection};
+
+pub struct InspectorPanel {
+    scene: Arc<RwLock<Scene>>,
+    texture_registry: Arc<RwLock<TextureRegistry>>,
+    selection: Selection,
+}
+
+impl InspectorPanel {
+    pub fn new(
+        scene: Arc<RwLock<Scene>>,
+        texture_registry: Arc<RwLock<TextureRegistry>>,
+        selection: Selection,
+    ) -> Self {
+        Self {
+            scene,
+            texture_registry,
+            selection,
+        }
+    }
+}
+
+impl GuiPanel for InspectorPanel {
+    fn title(&self) -> &str {
+        "Inspector"
+    }
+
+    // With several entities selected the position is their centroid and moves all of them,
+    // scale and material edits are applied to each one. The primary entity provides the values.
+    // The scene is only locked for writing when something was edited
+    fn ui(&mut self, ui: &mut egui::Ui) {
+        let textures = self.texture_registry.read().recover();
+        let scene = self.scene.read().recover();
+        let mut selection = self.selection.lock().recover();
+        selection.retain_existing(&scene);
+
+        let (primary, centroid) = match selection.primary().zip(selection.centroid(&scene)) {
+            Some(selected) => selected,
+            None => {
+                ui.label("No entity selected");
+                return;
+            }
+        };
+        let entity = scene.get(primary).unwrap();
+
+        if selection.len() == 1 {
+            ui.heading(format!("Entity #{}", primary.0));
+        } else {
+            ui.heading(format!("{} entities", selection.len()));
+            ui.label(format!("Primary: entity #{}", primary.0));
+        }
+
+        let mut position = centroid;
+        let mut scale = *entity.scale();
+        let mut moved = false;
+        let mut scaled = [false; 3];
+        egui::Grid::new("transform").num_columns(2).show(ui, |ui| {
+            ui.label("Position");
+            ui.horizontal(|ui| {
+                for i in 0..3 {
+                    moved |= ui
+                        .add(egui::DragValue::new(&mut position[i]).speed(0.05))
+                        .changed();
+                }
+            });
+            ui.end_row();
+
+            ui.label("Scale");
+            ui.horizontal(|ui| {
+                for (i, scaled) in scaled.iter_mut().enumerate() {
+                    *scaled = ui
+                        .add(
+                            egui::DragValue::new(&mut scale[i])
+                                .speed(0.01)
+                                .clamp_range(0.001..=1000.0),
+                        )
+                        .changed();
+                }
+            });
+            ui.end_row();
+        });
+
+        ui.separator();
+        let template_id = entity
+            .mesh()
+            .material_template()
+            .id()
+            .load(Ordering::Acquire);
+        let material_edit = egui::CollapsingHeader::new("Material")
+            .default_open(true)
+            .show(ui, |ui| {
+                let edit = material_editor(ui, entity, &textures);
+                if selection.len() > 1 {
+                    ui.label("Applied to the selected entities with the same material");
+                }
+                edit
+            })
+            .body_returned
+            .flatten();
+
+        if !moved && !scaled.contains(&true) && material_edit.is_none() {
+            return;
+        }
+        drop(scene);
+        drop(textures);
+        let create_info = material_edit.map(|edit| {
+            let mut textures = self.texture_registry.write().recover();
+            edit.apply(&mut textures)
+        });
+        let mut scene = self.scene.write().recover();
+
+        if moved {
+            if let Err(err) = selection.translate(&mut scene, &(position - centroid)) {
+                log::error!("Failed to move entities: {}", err);
+            }
+        }
+        if scaled.contains(&true) {
+            for &id in selection.ids() {
+                let entity = scene.get_mut(id).unwrap();
+                let mut entity_scale = *entity.scale();
+                for i in (0..3).filter(|&i| scaled[i]) {
+                    entity_scale[i] = scale[i];
+                }
+                if let Err(err) = entity.set_scale(entity_scale) {
+                    log::error!("Failed to scale entity: {}", err);
+                }
+            }
+        }
+
+        if let Some(create_info) = create_info {
+            for &id in selection.ids() {
+                let entity = scene.get_mut(id).unwrap();
+                let mesh = entity.mesh_mut();
+                if mesh.material_template().id().load(Ordering::Acquire) != template_id {
+                    continue;
+                }
+                if let Err(err) = mesh.update_material(create_info.clone()) {
+                    log::error!("Failed to update material: {}", err);
+                }
+            }
+        }
+    }
+}
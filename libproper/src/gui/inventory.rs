@@ -0,0 +1,78 @@
+use std::sync::{Arc, RwLock};
+
+use egui_winit_vulkano::egui;
+
+use crate::{
+    lock::Recover,
+    world::{inventory::Inventory, scene::Scene},
+};
+
+use super::dock::GuiPanel;
+
+const COLUMNS: usize = 6;
+const SLOT_SIZE: f32 = 56.0;
+
+// Slots of the player's inventory, i.e. of the first entity holding one. Items are shown by
+// name, the GUI can't draw textures yet, so icons are only listed in the tooltip
+pub struct InventoryPanel {
+    scene: Arc<RwLock<Scene>>,
+}
+
+impl InventoryPanel {
+    pub fn new(scene: Arc<RwLock<Scene>>) -> Self {
+        Self { scene }
+    }
+}
+
+impl GuiPanel for InventoryPanel {
+    fn title(&self) -> &str {
+        "Inventory"
+    }
+
+    fn ui(&mut self, ui: &mut egui::Ui) {
+        let scene = self.scene.read().recover();
+        let inventory = scene
+            .entities()
+            .find_map(|entity| entity.components().get::<Inventory>());
+        let inventory = match inventory {
+            Some(inventory) => inventory,
+            None => {
+                ui.label("No entity has an inventory");
+                return;
+            }
+        };
+
+        egui::Grid::new("inventory_slots")
+            .num_columns(COLUMNS)
+            .show(ui, |ui| {
+                for (index, slot) in inventory.slots().iter().enumerate() {
+                    let text = match slot {
+                        Some(stack) if stack.count > 1 => {
+                            format!("{}\n×{}", stack.definition.name, stack.count)
+                        }
+                        Some(stack) => stack.definition.name.clone(),
+                        None => String::new(),
+                    };
+                    let response = ui.add_sized(
+                        [SLOT_SIZE, SLOT_SIZE],
+                        egui::Button::new(egui::RichText::new(text).small()),
+                    );
+                    if let Some(stack) = slot {
+                        response.on_hover_ui(|ui| {
+                            ui.strong(&stack.definition.name);
+                            ui.label(format!(
+                                "{} / {} ({})",
+                                stack.count, stack.definition.stack_size, stack.id
+                            ));
+                            if let Some(icon) = &stack.definition.icon {
+                                ui.label(format!("Icon: {}", icon));
+                            }
+                        });
+                    }
+                    if (index + 1) % COLUMNS == 0 {
+                        ui.end_row();
+                    }
+                }
+            });
+    }
+}
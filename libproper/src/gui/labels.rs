@@ -0,0 +1,173 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, RwLock},
+};
+
+use egui_winit_vulkano::egui;
+use nalgebra::{Point3, Vector3};
+
+use crate::{
+    lock::Recover,
+    preferences::Preferences,
+    render::aspect::AspectLock,
+    world::{
+        entity::{Entity, EntityId},
+        ray::Ray,
+        scene::Scene,
+    },
+};
+
+use super::gizmo::to_screen;
+
+// Space between the top of the entity and its label, in world units
+const LABEL_MARGIN: f32 = 0.25;
+const HEALTH_BAR_SIZE: egui::Vec2 = egui::vec2(48.0, 5.0);
+// Opacity of labels hidden behind other geometry
+const OCCLUDED_ALPHA: f32 = 0.2;
+// Seconds to fade between visible and occluded
+const FADE_TIME: f32 = 0.2;
+
+// Text floating above an entity
+#[derive(Clone, Debug)]
+pub struct WorldLabel {
+    pub text: String,
+    pub color: egui::Color32,
+    // Added to the point above the entity bounds
+    pub offset: Vector3<f32>,
+    // Labels further away from the camera are not drawn
+    pub max_distance: f32,
+}
+
+// Drawn above the entity, below its label if it has one
+#[derive(Clone, Copy, Debug)]
+pub struct HealthBar {
+    pub value: f32,
+    pub max: f32,
+}
+
+// Draws the labels and health bars of the entities over the scene. Ones blocked by other
+// entities fade out
+pub struct LabelOverlay {
+    scene: Arc<RwLock<Scene>>,
+    preferences: Arc<Mutex<Preferences>>,
+    alpha: HashMap<EntityId, f32>,
+}
+
+impl WorldLabel {
+    pub fn new(text: &str) -> Self {
+        Self {
+            text: text.to_owned(),
+            color: egui::Color32::WHITE,
+            offset: Vector3::zeros(),
+            max_distance: 50.0,
+        }
+    }
+}
+
+impl HealthBar {
+    pub fn new(max: f32) -> Self {
+        Self { value: max, max }
+    }
+
+    pub fn fraction(&self) -> f32 {
+        if self.max > 0.0 {
+            (self.value / self.max).clamp(0.0, 1.0)
+        } else {
+            0.0
+        }
+    }
+}
+
+impl LabelOverlay {
+    pub fn new(scene: Arc<RwLock<Scene>>, preferences: Arc<Mutex<Preferences>>) -> Self {
+        Self {
+            scene,
+            preferences,
+            alpha: HashMap::new(),
+        }
+    }
+
+    pub fn show(&mut self, ctx: &egui::Context) {
+        let scene = self.scene.read().recover();
+        let screen = ctx.input().screen_rect();
+        let dt = ctx.input().stable_dt;
+        let aspect = AspectLock::from_preferences(&self.preferences.lock().recover());
+        let view_projection = aspect.projection(&scene.camera, screen.width(), screen.height())
+            * scene.camera.view_matrix();
+        let camera_position = *scene.camera.position();
+        let painter = ctx.layer_painter(egui::LayerId::background());
+
+        let mut alpha = HashMap::new();
+        for entity in scene.entities() {
+            let label = entity.components().get::<WorldLabel>();
+            let health = entity.components().get::<HealthBar>();
+            if label.is_none() && health.is_none() {
+                continue;
+            }
+
+            let anchor = anchor_point(entity, label);
+            let distance = (anchor - camera_position).norm();
+            let max_distance = label.map_or(f32::INFINITY, |label| label.max_distance);
+            if distance > max_distance {
+                continue;
+            }
+            let position = match to_screen(&view_projection, &anchor, screen) {
+                Some(position) if screen.contains(position) => position,
+                _ => continue,
+            };
+
+            // Anything hit before the anchor blocks the label
+            let ray = Ray::new(camera_position, anchor - camera_position);
+            let occluded = scene
+                .raycast(&ray, Some(entity.id()))
+                .map_or(false, |hit| hit.distance < distance);
+            let target = if occluded { OCCLUDED_ALPHA } else { 1.0 };
+            let current = self.alpha.get(&entity.id()).copied().unwrap_or(target);
+            let step = dt / FADE_TIME * (1.0 - OCCLUDED_ALPHA);
+            let value = if current < target {
+                (current + step).min(target)
+            } else {
+                (current - step).max(target)
+            };
+            alpha.insert(entity.id(), value);
+
+            let mut bottom = position.y;
+            if let Some(health) = health {
+                let rect = egui::Rect::from_center_size(
+                    egui::pos2(position.x, bottom - HEALTH_BAR_SIZE.y * 0.5),
+                    HEALTH_BAR_SIZE,
+                );
+                let fraction = health.fraction();
+                let mut filled = rect;
+                filled.set_width(rect.width() * fraction);
+                let color = egui::Color32::from_rgb(
+                    (255.0 * (1.0 - fraction)) as u8,
+                    (200.0 * fraction) as u8,
+                    40,
+                );
+
+                painter.rect_filled(rect, 1.0, egui::Color32::BLACK.linear_multiply(value));
+                painter.rect_filled(filled, 1.0, color.linear_multiply(value));
+                bottom = rect.min.y - 2.0;
+            }
+            if let Some(label) = label {
+                painter.text(
+                    egui::pos2(position.x, bottom),
+                    egui::Align2::CENTER_BOTTOM,
+                    &label.text,
+                    egui::FontId::proportional(14.0),
+                    label.color.linear_multiply(value),
+                );
+            }
+        }
+
+        // Entities which stopped being drawn start over without a fade
+        self.alpha = alpha;
+    }
+}
+
+fn anchor_point(entity: &Entity, label: Option<&WorldLabel>) -> Point3<f32> {
+    let position = entity.position();
+    let top = Point3::new(position.x, entity.bounds().max.y + LABEL_MARGIN, position.z);
+    top + label.map_or_else(Vector3::zeros, |label| label.offset)
+}
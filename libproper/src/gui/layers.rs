@@ -0,0 +1,115 @@
+use std::sync::{Arc, Mutex};
+
+use egui_winit_vulkano::egui;
+use winit::event_loop::EventLoopProxy;
+
+use crate::{
+    event::GameEvent,
+    layer::{gui::GuiLayer, LayerStatus},
+    lock::Recover,
+};
+
+use super::dock::GuiPanel;
+
+pub struct LayersPanel {
+    event_proxy: EventLoopProxy<GameEvent>,
+    status: Arc<Mutex<Vec<LayerStatus>>>,
+}
+
+impl LayersPanel {
+    pub fn new(
+        event_proxy: EventLoopProxy<GameEvent>,
+        status: Arc<Mutex<Vec<LayerStatus>>>,
+    ) -> Self {
+        Self {
+            event_proxy,
+            status,
+        }
+    }
+
+    fn send(&self, event: GameEvent) {
+        self.event_proxy.send_event(event).ok();
+    }
+}
+
+// Layer type names are full paths, the last segment is enough to tell them apart
+fn short_name(name: &str) -> &str {
+    name.rsplit("::").next().unwrap_or(name)
+}
+
+impl GuiPanel for LayersPanel {
+    fn title(&self) -> &str {
+        "Layers"
+    }
+
+    fn ui(&mut self, ui: &mut egui::Ui) {
+        // Changes show up with the next snapshot, a frame later
+        let status = self.status.lock().recover().clone();
+        let count = status.len();
+        let gui_layer = std::any::type_name::<GuiLayer>();
+
+        ui.weak("Drawn in ascending priority, ties top to bottom");
+        ui.separator();
+        egui::Grid::new("layers_grid")
+            .num_columns(6)
+            .striped(true)
+            .show(ui, |ui| {
+                for (index, layer) in status.iter().enumerate() {
+                    let mut enabled = layer.enabled;
+                    // Disabling the GUI from itself would leave no way to turn it back on
+                    let toggle = ui
+                        .add_enabled(
+                            layer.name != gui_layer,
+                            egui::Checkbox::new(&mut enabled, short_name(&layer.name)),
+                        )
+                        .on_hover_text(&layer.name);
+                    if toggle.changed() {
+                        self.send(GameEvent::SetLayerEnabled {
+                            layer: layer.name.clone(),
+                            enabled,
+                        });
+                    }
+
+                    ui.horizontal(|ui| {
+                        if ui
+                            .add_enabled(index > 0, egui::Button::new("⏶").small())
+                            .clicked()
+                        {
+                            self.send(GameEvent::MoveLayer {
+                                layer: layer.name.clone(),
+                                index: index - 1,
+                            });
+                        }
+                        if ui
+                            .add_enabled(index + 1 < count, egui::Button::new("⏷").small())
+                            .clicked()
+                        {
+                            self.send(GameEvent::MoveLayer {
+                                layer: layer.name.clone(),
+                                index: index + 1,
+                            });
+                        }
+                    });
+
+                    ui.label(format!(
+                        "{} / {}",
+                        layer.draw_priority, layer.event_priority
+                    ))
+                    .on_hover_text("Draw / event priority");
+                    if layer.panicked {
+                        ui.colored_label(egui::Color32::from_rgb(255, 96, 96), "panicked");
+                    } else if !layer.enabled {
+                        ui.weak("disabled");
+                    } else {
+                        ui.label("active");
+                    }
+                    ui.label(format!("{} consumed", layer.consumed));
+                    match &layer.last_consumed {
+                        Some(kind) => ui.monospace(kind),
+                        None => ui.weak("-"),
+                    };
+                    ui.end_row();
+                }
+            });
+    }
+}
@@ -0,0 +1,102 @@
+use std::sync::{Arc, Mutex};
+
+use egui_winit_vulkano::egui;
+use log::{Level, LevelFilter};
+
+use crate::{
+    lock::Recover,
+    logging::{LogHistory, LogRecord},
+};
+
+use super::dock::GuiPanel;
+
+const LEVELS: [LevelFilter; 5] = [
+    LevelFilter::Error,
+    LevelFilter::Warn,
+    LevelFilter::Info,
+    LevelFilter::Debug,
+    LevelFilter::Trace,
+];
+
+pub struct LogPanel {
+    history: Arc<Mutex<LogHistory>>,
+    level: LevelFilter,
+    target: String,
+}
+
+impl LogPanel {
+    pub fn new(history: Arc<Mutex<LogHistory>>) -> Self {
+        Self {
+            history,
+            level: LevelFilter::Info,
+            target: String::new(),
+        }
+    }
+
+    fn accepts(&self, record: &LogRecord) -> bool {
+        record.level <= self.level && record.target.contains(self.target.as_str())
+    }
+}
+
+fn level_color(level: Level) -> egui::Color32 {
+    match level {
+        Level::Error => egui::Color32::from_rgb(255, 96, 96),
+        Level::Warn => egui::Color32::from_rgb(255, 200, 64),
+        Level::Info => egui::Color32::LIGHT_GRAY,
+        Level::Debug | Level::Trace => egui::Color32::GRAY,
+    }
+}
+
+impl GuiPanel for LogPanel {
+    fn title(&self) -> &str {
+        "Log"
+    }
+
+    fn ui(&mut self, ui: &mut egui::Ui) {
+        let history = self.history.clone();
+        let mut history = history.lock().recover();
+
+        ui.horizontal(|ui| {
+            egui::ComboBox::from_id_source("log_level")
+                .selected_text(self.level.to_string())
+                .show_ui(ui, |ui| {
+                    for level in LEVELS {
+                        ui.selectable_value(&mut self.level, level, level.to_string());
+                    }
+                });
+            ui.add(
+                egui::TextEdit::singleline(&mut self.target)
+                    .desired_width(160.0)
+                    .hint_text("Module"),
+            );
+            if ui.button("Clear").clicked() {
+                history.clear();
+            }
+        });
+
+        ui.separator();
+        egui::ScrollArea::vertical()
+            .id_source("log_output")
+            .stick_to_bottom()
+            .auto_shrink([false; 2])
+            .show(ui, |ui| {
+                for record in history.records().filter(|r| self.accepts(r)) {
+                    let line = record.to_string();
+                    ui.add(
+                        egui::Label::new(
+                            egui::RichText::new(&line)
+                                .monospace()
+                                .color(level_color(record.level)),
+                        )
+                        .sense(egui::Sense::click()),
+                    )
+                    .context_menu(|ui| {
+                        if ui.button("Copy").clicked() {
+                            ui.output().copied_text = line.clone();
+                            ui.close_menu();
+                        }
+                    });
+                }
+            });
+    }
+}
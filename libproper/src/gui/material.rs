@@ -0,0 +1,96 @@
+use egui_winit_vulkano::egui;
+
+use crate::{
+    resource::{material::MaterialInstanceCreateInfo, texture::TextureRegistry},
+    world::entity::Entity,
+};
+
+// Parameters changed in the editor. Picked textures are only loaded when the edit is applied,
+// so the editor itself just reads the registry
+pub struct MaterialEdit {
+    create_info: MaterialInstanceCreateInfo,
+    // (parameter, texture) pairs, None clears the parameter
+    textures: Vec<(String, Option<String>)>,
+}
+
+impl MaterialEdit {
+    pub fn apply(mut self, textures: &mut TextureRegistry) -> MaterialInstanceCreateInfo {
+        for (name, selection) in self.textures {
+            match selection.map(|t| textures.get_or_load(&t)).transpose() {
+                Ok(texture) => self.create_info.set_texture(&name, texture),
+                Err(err) => log::error!("Failed to load texture: {}", err),
+            }
+        }
+        self.create_info
+    }
+}
+
+// Live editor for the material parameters of an entity, returns the edit when the parameters
+// are changed
+pub fn material_editor(
+    ui: &mut egui::Ui,
+    entity: &Entity,
+    textures: &TextureRegistry,
+) -> Option<MaterialEdit> {
+    let layout = entity.mesh().material_template().layout().clone();
+    let mut create_info = entity.mesh().material_create_info().clone();
+    let mut changed = false;
+    let mut picked = vec![];
+
+    egui::Grid::new("material_parameters")
+        .num_columns(2)
+        .show(ui, |ui| {
+            for (name, default) in layout.colors() {
+                let mut color = create_info.color(name).unwrap_or(*default);
+                ui.label(name);
+                if ui.color_edit_button_rgba_unmultiplied(&mut color).changed() {
+                    create_info.set_color(name, color);
+                    changed = true;
+                }
+                ui.end_row();
+            }
+
+            for (name, default) in layout.scalars() {
+                let mut value = create_info.scalar(name).unwrap_or(*default);
+                ui.label(name);
+                if ui
+                    .add(egui::DragValue::new(&mut value).speed(0.01))
+                    .changed()
+                {
+                    create_info.set_scalar(name, value);
+                    changed = true;
+                }
+                ui.end_row();
+            }
+
+            let available = textures.available();
+            for name in layout.textures() {
+                let current = create_info
+                    .texture(name)
+                    .and_then(|texture| textures.name_of(texture))
+                    .map(str::to_owned);
+                let mut selection = current.clone();
+
+                ui.label(name);
+                egui::ComboBox::from_id_source(name)
+                    .selected_text(selection.as_deref().unwrap_or("None"))
+                    .show_ui(ui, |ui| {
+                        ui.selectable_value(&mut selection, None, "None");
+                        for texture in available.iter() {
+                            ui.selectable_value(&mut selection, Some(texture.clone()), texture);
+                        }
+                    });
+                ui.end_row();
+
+                if selection != current {
+                    picked.push((name.clone(), selection));
+                    changed = true;
+                }
+            }
+        });
+
+    changed.then(|| MaterialEdit {
+        create_info,
+        textures: picked,
+    })
+}
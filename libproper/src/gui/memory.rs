@@ -0,0 +1,55 @@
+use egui_winit_vulkano::egui;
+
+use crate::render::memory::{self, AllocationCategory, BUDGET_WARNING_THRESHOLD};
+
+use super::dock::GuiPanel;
+
+#[derive(Default)]
+pub struct MemoryPanel;
+
+fn mib(bytes: u64) -> f64 {
+    bytes as f64 / (1024.0 * 1024.0)
+}
+
+impl GuiPanel for MemoryPanel {
+    fn title(&self) -> &str {
+        "GPU memory"
+    }
+
+    fn ui(&mut self, ui: &mut egui::Ui) {
+        let total = memory::total_bytes();
+        let budget = memory::budget();
+
+        if budget != 0 {
+            let fraction = total as f64 / budget as f64;
+            let text = format!("{:.1} / {:.1} MiB", mib(total), mib(budget));
+            ui.add(egui::ProgressBar::new(fraction.min(1.0) as f32).text(text));
+            if fraction >= BUDGET_WARNING_THRESHOLD {
+                ui.colored_label(egui::Color32::from_rgb(255, 200, 64), "Nearing the budget");
+            }
+        } else {
+            ui.label(format!("{:.1} MiB", mib(total)));
+        }
+
+        ui.separator();
+        egui::Grid::new("memory_breakdown")
+            .num_columns(4)
+            .striped(true)
+            .show(ui, |ui| {
+                ui.strong("Category");
+                ui.strong("Count");
+                ui.strong("Size, MiB");
+                ui.strong("Peak, MiB");
+                ui.end_row();
+
+                for category in AllocationCategory::ALL {
+                    let usage = memory::usage(category);
+                    ui.label(category.name());
+                    ui.label(usage.count.to_string());
+                    ui.label(format!("{:.2}", mib(usage.bytes)));
+                    ui.label(format!("{:.2}", mib(usage.peak_bytes)));
+                    ui.end_row();
+                }
+            });
+    }
+}
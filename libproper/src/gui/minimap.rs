@@ -0,0 +1,136 @@
+use std::sync::{Arc, Mutex, RwLock};
+
+use egui_winit_vulkano::egui;
+use nalgebra::Point3;
+
+use crate::{
+    lock::Recover,
+    preferences::{Preferences, MINIMAP, MINIMAP_ZOOM},
+    world::scene::Scene,
+};
+
+const MAP_SIZE: f32 = 180.0;
+const MIN_ZOOM: f32 = 10.0;
+const MAX_ZOOM: f32 = 1000.0;
+const FOOTPRINT_COLOR: egui::Color32 = egui::Color32::from_rgba_premultiplied(90, 90, 90, 160);
+const VIEWER_COLOR: egui::Color32 = egui::Color32::from_rgb(255, 220, 64);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MinimapSettings {
+    pub enabled: bool,
+    // World units across the map
+    pub zoom: f32,
+}
+
+// Drawn as a dot on the minimap, other entities only show their footprint
+#[derive(Clone, Copy, Debug)]
+pub struct MinimapIcon {
+    pub color: egui::Color32,
+    pub radius: f32,
+}
+
+// The minimap is centered on this entity instead of the camera
+#[derive(Clone, Copy, Debug, Default)]
+pub struct MinimapFollow;
+
+// Top-down view of the scene in the top right corner, X points right and Z down. Scrolling
+// over it zooms
+pub struct MinimapOverlay {
+    scene: Arc<RwLock<Scene>>,
+    preferences: Arc<Mutex<Preferences>>,
+}
+
+impl MinimapSettings {
+    pub fn from_preferences(preferences: &Preferences) -> Self {
+        Self {
+            enabled: preferences.bool(MINIMAP, false),
+            zoom: preferences
+                .float(MINIMAP_ZOOM, 50.0)
+                .clamp(MIN_ZOOM as f64, MAX_ZOOM as f64) as f32,
+        }
+    }
+}
+
+impl MinimapOverlay {
+    pub fn new(scene: Arc<RwLock<Scene>>, preferences: Arc<Mutex<Preferences>>) -> Self {
+        Self { scene, preferences }
+    }
+
+    pub fn show(&mut self, ctx: &egui::Context) {
+        let settings = MinimapSettings::from_preferences(&self.preferences.lock().recover());
+        if !settings.enabled {
+            return;
+        }
+
+        let scene = self.scene.read().recover();
+        let viewer = scene
+            .entities()
+            .find(|entity| entity.components().contains::<MinimapFollow>())
+            .map_or(*scene.camera.position(), |entity| *entity.position());
+        let forward = scene.camera.forward();
+
+        let mut zoom = settings.zoom;
+        egui::Area::new("minimap")
+            .anchor(egui::Align2::RIGHT_TOP, [-8.0, 8.0])
+            .show(ctx, |ui| {
+                egui::Frame::popup(ui.style()).show(ui, |ui| {
+                    let (rect, response) = ui
+                        .allocate_exact_size(egui::vec2(MAP_SIZE, MAP_SIZE), egui::Sense::hover());
+                    if response.hovered() {
+                        let scroll = ctx.input().scroll_delta.y;
+                        zoom = (zoom * (1.0 - scroll * 0.002)).clamp(MIN_ZOOM, MAX_ZOOM);
+                    }
+
+                    let painter = ui.painter().with_clip_rect(rect);
+                    let scale = rect.width() / zoom;
+                    let to_map = |point: &Point3<f32>| {
+                        rect.center()
+                            + egui::vec2((point.x - viewer.x) * scale, (point.z - viewer.z) * scale)
+                    };
+
+                    painter.rect_filled(rect, 2.0, egui::Color32::from_black_alpha(200));
+                    for entity in scene.entities() {
+                        let bounds = entity.bounds();
+                        let footprint =
+                            egui::Rect::from_two_pos(to_map(&bounds.min), to_map(&bounds.max));
+                        if !footprint.intersects(rect) {
+                            continue;
+                        }
+                        painter.rect_filled(footprint, 0.0, FOOTPRINT_COLOR);
+                    }
+                    for entity in scene.entities() {
+                        if let Some(icon) = entity.components().get::<MinimapIcon>() {
+                            painter.circle_filled(
+                                to_map(entity.position()),
+                                icon.radius,
+                                icon.color,
+                            );
+                        }
+                    }
+
+                    // Viewer as an arrow along the camera direction
+                    let direction = egui::vec2(forward.x, forward.z);
+                    if direction.length() > f32::EPSILON {
+                        let direction = direction.normalized() * 8.0;
+                        let side = egui::vec2(-direction.y, direction.x) * 0.5;
+                        let center = rect.center();
+                        painter.add(egui::Shape::convex_polygon(
+                            vec![
+                                center + direction,
+                                center - direction * 0.5 + side,
+                                center - direction * 0.5 - side,
+                            ],
+                            VIEWER_COLOR,
+                            egui::Stroke::none(),
+                        ));
+                    } else {
+                        painter.circle_filled(rect.center(), 4.0, VIEWER_COLOR);
+                    }
+                });
+            });
+
+        if zoom != settings.zoom {
+            self.preferences.lock().recover().set(MINIMAP_ZOOM, zoom);
+        }
+    }
+}
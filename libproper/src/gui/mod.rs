@@ -0,0 +1,43 @@
+use std::sync::{Arc, Mutex};
+
+use crate::editor::selection::SelectionSet;
+
+#[cfg(feature = "gui")]
+pub mod appearance;
+#[cfg(feature = "gui")]
+pub mod assets;
+// Only the console works without the GUI, for commands from the game itself
+pub mod console;
+#[cfg(feature = "gui")]
+pub mod diagnostics;
+#[cfg(feature = "gui")]
+pub mod dock;
+#[cfg(feature = "gui")]
+pub mod events;
+#[cfg(feature = "gui")]
+pub mod gizmo;
+#[cfg(feature = "gui")]
+pub mod hierarchy;
+#[cfg(feature = "gui")]
+pub mod inspector;
+#[cfg(feature = "gui")]
+pub mod inventory;
+#[cfg(feature = "gui")]
+pub mod labels;
+#[cfg(feature = "gui")]
+pub mod layers;
+#[cfg(feature = "gui")]
+pub mod log;
+#[cfg(feature = "gui")]
+pub mod memory;
+#[cfg(feature = "gui")]
+pub mod material;
+#[cfg(feature = "gui")]
+pub mod minimap;
+#[cfg(feature = "gui")]
+pub mod preferences;
+#[cfg(feature = "gui")]
+pub mod stats;
+
+// Entities currently picked in the editor panels
+pub type Selection = Arc<Mutex<SelectionSet>>;
@@ -0,0 +1,346 @@
+use std::sync::{Arc, Mutex};
+
+use egui_winit_vulkano::egui;
+use winit::event_loop::EventLoopProxy;
+
+use crate::{
+    editor::snap::SnapSettings,
+    event::GameEvent,
+    layer::input::{Action, Bindings, LookSettings},
+    lock::Recover,
+    preferences::{
+        Preferences, WindowMode, ASPECT_RATIO, COLOR_GRADING, COLOR_GRADING_INTENSITY, DEBUG_AXES,
+        DEBUG_GRID, DEBUG_GRID_FADE, DEBUG_GRID_SPACING, LIGHT_SHAFTS, LIGHT_SHAFT_INTENSITY,
+        LIGHT_SHAFT_LENGTH, MASTER_VOLUME, MINIMAP, MINIMAP_ZOOM, MOUSE_ACCELERATION,
+        MOUSE_INVERT_Y, MOUSE_SENSITIVITY, MOUSE_SMOOTHING, PAUSE_ON_FOCUS_LOSS, SHADOWS,
+        SHADOW_BUDGET, SNAP_ROTATION, SNAP_SCALE, SNAP_TRANSLATION, WINDOW_MODE,
+    },
+    render::{
+        aspect::{AspectLock, ASPECT_PRESETS},
+        debug::DebugViewSettings,
+        grading::ColorGradingSettings,
+        shadow::{ShadowSettings, MAX_SHADOW_MAPS},
+        shafts::LightShaftSettings,
+    },
+};
+
+use super::{dock::GuiPanel, minimap::MinimapSettings};
+
+pub struct PreferencesPanel {
+    event_proxy: EventLoopProxy<GameEvent>,
+    preferences: Arc<Mutex<Preferences>>,
+}
+
+impl PreferencesPanel {
+    pub fn new(
+        event_proxy: EventLoopProxy<GameEvent>,
+        preferences: Arc<Mutex<Preferences>>,
+    ) -> Self {
+        Self {
+            event_proxy,
+            preferences,
+        }
+    }
+}
+
+impl GuiPanel for PreferencesPanel {
+    fn title(&self) -> &str {
+        "Preferences"
+    }
+
+    fn ui(&mut self, ui: &mut egui::Ui) {
+        let mut preferences = self.preferences.lock().recover();
+        let mut look = LookSettings::from_preferences(&preferences);
+        let mut volume = preferences.master_volume();
+        let mut window_mode = preferences.window_mode();
+        let mut pause_on_focus_loss = preferences.pause_on_focus_loss();
+
+        egui::Grid::new("preferences")
+            .num_columns(2)
+            .show(ui, |ui| {
+                ui.label("Mouse sensitivity");
+                if ui
+                    .add(egui::Slider::new(&mut look.sensitivity, 0.001..=0.1).logarithmic(true))
+                    .changed()
+                {
+                    preferences.set(MOUSE_SENSITIVITY, look.sensitivity);
+                }
+                ui.end_row();
+
+                ui.label("Invert Y axis");
+                if ui.checkbox(&mut look.invert_y, "").changed() {
+                    preferences.set(MOUSE_INVERT_Y, look.invert_y);
+                }
+                ui.end_row();
+
+                ui.label("Mouse smoothing");
+                if ui
+                    .add(egui::Slider::new(&mut look.smoothing, 0.0..=0.95))
+                    .changed()
+                {
+                    preferences.set(MOUSE_SMOOTHING, look.smoothing);
+                }
+                ui.end_row();
+
+                ui.label("Mouse acceleration");
+                if ui
+                    .add(egui::Slider::new(&mut look.acceleration, 0.0..=2.0))
+                    .changed()
+                {
+                    preferences.set(MOUSE_ACCELERATION, look.acceleration);
+                }
+                ui.end_row();
+
+                ui.label("Volume");
+                if ui.add(egui::Slider::new(&mut volume, 0.0..=1.0)).changed() {
+                    preferences.set(MASTER_VOLUME, volume);
+                }
+                ui.end_row();
+
+                ui.label("Window mode");
+                ui.horizontal(|ui| {
+                    for mode in [WindowMode::Windowed, WindowMode::Fullscreen] {
+                        if ui
+                            .selectable_value(&mut window_mode, mode, mode.name())
+                            .changed()
+                        {
+                            preferences.set(WINDOW_MODE, mode.name());
+                            self.event_proxy
+                                .send_event(GameEvent::SetWindowMode(mode))
+                                .ok();
+                        }
+                    }
+                });
+                ui.end_row();
+
+                ui.label("Aspect ratio");
+                let aspect = AspectLock::from_preferences(&preferences)
+                    .target
+                    .unwrap_or(0.0);
+                let selected = ASPECT_PRESETS
+                    .iter()
+                    .find(|(_, ratio)| (ratio - aspect).abs() < 1e-3)
+                    .map_or_else(|| format!("{:.2}", aspect), |(name, _)| (*name).to_owned());
+                egui::ComboBox::from_id_source("aspect_ratio")
+                    .selected_text(selected)
+                    .show_ui(ui, |ui| {
+                        for (name, ratio) in ASPECT_PRESETS {
+                            if ui.selectable_label(aspect == ratio, name).clicked() {
+                                preferences.set(ASPECT_RATIO, f64::from(ratio));
+                            }
+                        }
+                    });
+                ui.end_row();
+
+                ui.label("Pause when unfocused");
+                if ui.checkbox(&mut pause_on_focus_loss, "").changed() {
+                    preferences.set(PAUSE_ON_FOCUS_LOSS, pause_on_focus_loss);
+                }
+                ui.end_row();
+            });
+
+        ui.separator();
+        ui.label("Debug view");
+        let mut debug = DebugViewSettings::from_preferences(&preferences);
+        egui::Grid::new("debug_view").num_columns(2).show(ui, |ui| {
+            ui.label("Grid");
+            if ui.checkbox(&mut debug.grid, "").changed() {
+                preferences.set(DEBUG_GRID, debug.grid);
+            }
+            ui.end_row();
+
+            ui.label("Origin axes");
+            if ui.checkbox(&mut debug.axes, "").changed() {
+                preferences.set(DEBUG_AXES, debug.axes);
+            }
+            ui.end_row();
+
+            ui.label("Grid spacing");
+            if ui
+                .add(egui::Slider::new(&mut debug.grid_spacing, 0.1..=10.0).logarithmic(true))
+                .changed()
+            {
+                preferences.set(DEBUG_GRID_SPACING, debug.grid_spacing);
+            }
+            ui.end_row();
+
+            ui.label("Grid fade distance");
+            if ui
+                .add(egui::Slider::new(
+                    &mut debug.grid_fade_distance,
+                    10.0..=1000.0,
+                ))
+                .changed()
+            {
+                preferences.set(DEBUG_GRID_FADE, debug.grid_fade_distance);
+            }
+            ui.end_row();
+        });
+
+        ui.separator();
+        ui.label("Minimap");
+        let mut minimap = MinimapSettings::from_preferences(&preferences);
+        egui::Grid::new("minimap").num_columns(2).show(ui, |ui| {
+            ui.label("Show");
+            if ui.checkbox(&mut minimap.enabled, "").changed() {
+                preferences.set(MINIMAP, minimap.enabled);
+            }
+            ui.end_row();
+
+            ui.label("Zoom");
+            if ui
+                .add(egui::Slider::new(&mut minimap.zoom, 10.0..=1000.0).logarithmic(true))
+                .changed()
+            {
+                preferences.set(MINIMAP_ZOOM, minimap.zoom);
+            }
+            ui.end_row();
+        });
+
+        ui.separator();
+        ui.label("Shadows");
+        let mut shadows = ShadowSettings::from_preferences(&preferences);
+        egui::Grid::new("shadows").num_columns(2).show(ui, |ui| {
+            ui.label("Show");
+            if ui.checkbox(&mut shadows.enabled, "").changed() {
+                preferences.set(SHADOWS, shadows.enabled);
+            }
+            ui.end_row();
+
+            ui.label("Shadow maps per frame");
+            if ui
+                .add(egui::Slider::new(&mut shadows.budget, 0..=MAX_SHADOW_MAPS))
+                .changed()
+            {
+                preferences.set(SHADOW_BUDGET, shadows.budget as i64);
+            }
+            ui.end_row();
+        });
+
+        ui.separator();
+        ui.label("Light shafts");
+        let mut shafts = LightShaftSettings::from_preferences(&preferences);
+        egui::Grid::new("light_shafts")
+            .num_columns(2)
+            .show(ui, |ui| {
+                ui.label("Show");
+                if ui.checkbox(&mut shafts.enabled, "").changed() {
+                    preferences.set(LIGHT_SHAFTS, shafts.enabled);
+                }
+                ui.end_row();
+
+                ui.label("Intensity");
+                if ui
+                    .add(egui::Slider::new(&mut shafts.intensity, 0.0..=4.0))
+                    .changed()
+                {
+                    preferences.set(LIGHT_SHAFT_INTENSITY, shafts.intensity);
+                }
+                ui.end_row();
+
+                ui.label("Length");
+                if ui
+                    .add(egui::Slider::new(&mut shafts.length, 0.05..=1.0))
+                    .changed()
+                {
+                    preferences.set(LIGHT_SHAFT_LENGTH, shafts.length);
+                }
+                ui.end_row();
+            });
+
+        ui.separator();
+        ui.label("Color grading");
+        let mut grading = ColorGradingSettings::from_preferences(&preferences);
+        egui::Grid::new("color_grading")
+            .num_columns(2)
+            .show(ui, |ui| {
+                ui.label("Show");
+                if ui.checkbox(&mut grading.enabled, "").changed() {
+                    preferences.set(COLOR_GRADING, grading.enabled);
+                }
+                ui.end_row();
+
+                ui.label("Intensity");
+                if ui
+                    .add(egui::Slider::new(&mut grading.intensity, 0.0..=1.0))
+                    .changed()
+                {
+                    preferences.set(COLOR_GRADING_INTENSITY, grading.intensity);
+                }
+                ui.end_row();
+            });
+
+        ui.separator();
+        ui.label("Snapping");
+        let mut snap = SnapSettings::from_preferences(&preferences);
+        egui::Grid::new("snapping").num_columns(2).show(ui, |ui| {
+            ui.label("Translation");
+            if ui
+                .add(
+                    egui::DragValue::new(&mut snap.translation)
+                        .speed(0.05)
+                        .clamp_range(0.0..=100.0),
+                )
+                .changed()
+            {
+                preferences.set(SNAP_TRANSLATION, snap.translation);
+            }
+            ui.end_row();
+
+            ui.label("Rotation, degrees");
+            if ui
+                .add(
+                    egui::DragValue::new(&mut snap.rotation)
+                        .speed(1.0)
+                        .clamp_range(0.0..=180.0),
+                )
+                .changed()
+            {
+                preferences.set(SNAP_ROTATION, snap.rotation);
+            }
+            ui.end_row();
+
+            ui.label("Scale");
+            if ui
+                .add(
+                    egui::DragValue::new(&mut snap.scale)
+                        .speed(0.01)
+                        .clamp_range(0.0..=10.0),
+                )
+                .changed()
+            {
+                preferences.set(SNAP_SCALE, snap.scale);
+            }
+            ui.end_row();
+        });
+
+        ui.separator();
+        ui.label("Key bindings");
+        let bindings = Bindings::from_preferences(&preferences);
+        egui::Grid::new("bindings").num_columns(2).show(ui, |ui| {
+            for action in Action::ALL {
+                ui.label(action.name());
+                let text = match bindings.scancode(action) {
+                    Some(scancode) => format!("Scancode {}", scancode),
+                    None => "Unbound".to_owned(),
+                };
+                if ui
+                    .button(text)
+                    .on_hover_text("Click, then press a key")
+                    .clicked()
+                {
+                    self.event_proxy
+                        .send_event(GameEvent::CaptureBinding(action))
+                        .ok();
+                }
+                ui.end_row();
+            }
+        });
+
+        if preferences.is_dirty() && !ui.input().pointer.any_down() {
+            if let Err(err) = preferences.save() {
+                log::error!("Failed to save preferences: {}", err);
+            }
+        }
+    }
+}
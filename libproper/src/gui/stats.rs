@@ -0,0 +1,79 @@
+use std::sync::{Arc, Mutex, RwLock};
+
+use egui_winit_vulkano::egui;
+
+use crate::{lock::Recover, render::stats::Stats, world::scene::Scene};
+
+use super::dock::GuiPanel;
+
+pub struct StatsPanel {
+    scene: Arc<RwLock<Scene>>,
+    stats: Arc<Mutex<Stats>>,
+    // Exponentially smoothed frame time, seconds
+    frame_time: f32,
+}
+
+impl StatsPanel {
+    pub fn new(scene: Arc<RwLock<Scene>>, stats: Arc<Mutex<Stats>>) -> Self {
+        Self {
+            scene,
+            stats,
+            frame_time: 0.0,
+        }
+    }
+}
+
+impl GuiPanel for StatsPanel {
+    fn title(&self) -> &str {
+        "Stats"
+    }
+
+    fn ui(&mut self, ui: &mut egui::Ui) {
+        let stats = *self.stats.lock().recover();
+        self.frame_time = self.frame_time * 0.95 + stats.frame_time as f32 * 0.05;
+
+        ui.label(format!(
+            "Frame time: {:.2} ms ({:.0} FPS)",
+            self.frame_time * 1000.0,
+            1.0 / self.frame_time.max(f32::EPSILON)
+        ));
+
+        ui.label(format!(
+            "Tick: {:.2} ms, draw: {:.2} ms",
+            stats.tick_time * 1000.0,
+            stats.draw_time * 1000.0
+        ));
+        ui.separator();
+        ui.label(format!(
+            "Entities: {} ({} culled)",
+            stats.entities_rendered, stats.entities_culled
+        ));
+        ui.label(format!(
+            "Draw calls: {} ({} binds)",
+            stats.draw_calls, stats.state_binds
+        ));
+        ui.label(format!("Triangles: {}", stats.triangles));
+        ui.label(format!(
+            "Uploads: {} ({:.1} KiB)",
+            stats.buffer_uploads,
+            stats.upload_bytes as f64 / 1024.0
+        ));
+        ui.label(format!(
+            "VRAM: {:.1} MiB",
+            stats.vram_estimate as f64 / (1024.0 * 1024.0)
+        ));
+        ui.separator();
+
+        let scene = self.scene.read().recover();
+        let camera_position = scene.camera.position();
+        ui.label(format!(
+            "Position: {:.3}, {:.3}, {:.3}",
+            camera_position.x, camera_position.y, camera_position.z
+        ));
+        ui.label(format!(
+            "Pitch: {:.3}°, Yaw: {:.3}°",
+            scene.camera.pitch().to_degrees(),
+            scene.camera.yaw().to_degrees()
+        ));
+    }
+}
@@ -0,0 +1,107 @@
+use std::{collections::BTreeMap, fmt::Display, path::Path};
+
+use crate::error::{Error, ResourceKind};
+
+pub const DEFAULT_LOCALE: &str = "en";
+const LOCALE_DIRECTORY: &str = "res/locale";
+
+// Key/value string tables loaded from res/locale/<locale>.toml, nested tables are
+// flattened into dotted keys ("menu.play"). Missing keys fall back to the default
+// locale and then to the key itself
+pub struct Localization {
+    locale: String,
+    strings: BTreeMap<String, String>,
+    fallback: BTreeMap<String, String>,
+}
+
+fn flatten(prefix: &str, table: toml::value::Table, out: &mut BTreeMap<String, String>) {
+    for (key, value) in table {
+        let key = if prefix.is_empty() {
+            key
+        } else {
+            format!("{}.{}", prefix, key)
+        };
+
+        match value {
+            toml::Value::Table(table) => flatten(&key, table, out),
+            toml::Value::String(text) => {
+                out.insert(key, text);
+            }
+            other => {
+                out.insert(key, other.to_string());
+            }
+        }
+    }
+}
+
+fn load_table(locale: &str) -> Result<BTreeMap<String, String>, Error> {
+    let path = Path::new(LOCALE_DIRECTORY).join(format!("{}.toml", locale));
+    let text = std::fs::read_to_string(&path)
+        .map_err(|err| Error::file(&path, err).context(ResourceKind::Locale, locale))?;
+    let table: toml::value::Table = toml::from_str(&text)
+        .map_err(|err| Error::asset_parse(&path, err).context(ResourceKind::Locale, locale))?;
+    let mut strings = BTreeMap::new();
+    flatten("", table, &mut strings);
+    Ok(strings)
+}
+
+impl Localization {
+    pub fn load(locale: &str) -> Result<Self, Error> {
+        let fallback = load_table(DEFAULT_LOCALE)?;
+        let mut localization = Self {
+            locale: DEFAULT_LOCALE.to_owned(),
+            strings: BTreeMap::new(),
+            fallback,
+        };
+        localization.set_locale(locale)?;
+        Ok(localization)
+    }
+
+    pub fn set_locale(&mut self, locale: &str) -> Result<(), Error> {
+        self.strings = if locale == DEFAULT_LOCALE {
+            BTreeMap::new()
+        } else {
+            load_table(locale)?
+        };
+        self.locale = locale.to_owned();
+        Ok(())
+    }
+
+    #[inline]
+    pub fn locale(&self) -> &str {
+        &self.locale
+    }
+
+    pub fn get<'a>(&'a self, key: &'a str) -> &'a str {
+        self.strings
+            .get(key)
+            .or_else(|| self.fallback.get(key))
+            .map(String::as_str)
+            .unwrap_or(key)
+    }
+
+    // Substitutes "{name}" placeholders
+    pub fn format(&self, key: &str, args: &[(&str, &dyn Display)]) -> String {
+        let mut text = self.get(key).to_owned();
+        for (name, value) in args {
+            text = text.replace(&format!("{{{}}}", name), &value.to_string());
+        }
+        text
+    }
+
+    pub fn available() -> Vec<String> {
+        let mut locales = vec![];
+        if let Ok(entries) = std::fs::read_dir(LOCALE_DIRECTORY) {
+            for entry in entries.flatten() {
+                let path = entry.path();
+                if path.extension().map_or(false, |ext| ext == "toml") {
+                    if let Some(stem) = path.file_stem().and_then(|s| s.to_str()) {
+                        locales.push(stem.to_owned());
+                    }
+                }
+            }
+        }
+        locales.sort();
+        locales
+    }
+}
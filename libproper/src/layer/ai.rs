@@ -0,0 +1,87 @@
+use std::sync::{Arc, RwLock};
+
+use vulkano::sync::GpuFuture;
+use winit::event_loop::ControlFlow;
+
+use crate::{
+    ai::{behavior::AiController, BlackboardValue},
+    error::Error,
+    event::{Event, GameEvent},
+    lock::Recover,
+    render::frame::Frame,
+    time::Time,
+    world::{entity::EntityId, scene::Scene},
+};
+
+use super::Layer;
+
+// Behavior trees are ticked by AiSystem in LogicLayer's scheduler, this layer only feeds
+// events into the blackboards
+pub struct AiLayer {
+    scene: Arc<RwLock<Scene>>,
+}
+
+impl AiLayer {
+    pub fn new(scene: Arc<RwLock<Scene>>) -> Self {
+        Self { scene }
+    }
+
+    fn set_blackboard(&self, id: EntityId, key: &str, value: BlackboardValue) {
+        let mut scene = self.scene.write().recover();
+        if let Some(controller) = scene
+            .get_mut(id)
+            .and_then(|e| e.components_mut().get_mut::<AiController>())
+        {
+            controller.blackboard.set(key, value);
+        }
+    }
+}
+
+impl Layer for AiLayer {
+    fn on_attach(&mut self) {}
+
+    fn on_detach(&mut self) {}
+
+    fn on_draw(
+        &mut self,
+        in_future: Box<dyn GpuFuture>,
+        _frame: &Frame,
+    ) -> Result<Box<dyn GpuFuture>, Error> {
+        Ok(in_future)
+    }
+
+    fn on_tick(&mut self, _time: &Time) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn on_event(&mut self, event: &Event, _flow: &mut ControlFlow) -> Result<bool, Error> {
+        // Forward world events into the blackboards of the entities involved
+        match event {
+            Event::GameEvent(GameEvent::Signal {
+                name,
+                entity: Some(entity),
+            }) => self.set_blackboard(*entity, name, BlackboardValue::Bool(true)),
+            Event::GameEvent(GameEvent::TriggerEnter { trigger, entity }) => self.set_blackboard(
+                *trigger,
+                "trigger_entered",
+                BlackboardValue::Entity(*entity),
+            ),
+            Event::GameEvent(GameEvent::Damage {
+                target,
+                source: Some(source),
+                ..
+            }) => self.set_blackboard(*target, "damaged_by", BlackboardValue::Entity(*source)),
+            Event::GameEvent(GameEvent::CollisionEnter(a, b)) => {
+                self.set_blackboard(*a, "collided_with", BlackboardValue::Entity(*b));
+                self.set_blackboard(*b, "collided_with", BlackboardValue::Entity(*a));
+            }
+            _ => (),
+        }
+
+        Ok(false)
+    }
+
+    fn freezes_on_pause(&self) -> bool {
+        true
+    }
+}
@@ -0,0 +1,161 @@
+use std::collections::VecDeque;
+
+use crate::event::Event;
+
+pub const DEFAULT_EVENT_FRAMES: u64 = 300;
+pub const DEFAULT_EVENT_CAPACITY: usize = 4096;
+
+// Longer payloads are cut off, e.g. upload id lists
+const MAX_SUMMARY_LENGTH: usize = 160;
+
+#[derive(Clone, Debug)]
+pub struct EventRecord {
+    pub frame: u64,
+    // Variant name, GameEvents are prefixed with "GameEvent::"
+    pub kind: String,
+    pub summary: String,
+    // Name of the layer which returned true from on_event, None if all of them passed it on
+    pub consumer: Option<String>,
+}
+
+// Events dispatched through the LayerManager within the last frames, shown by the event log
+// panel
+pub struct EventLog {
+    records: VecDeque<EventRecord>,
+    frame: u64,
+    frames: u64,
+    capacity: usize,
+    recording: bool,
+}
+
+// Variant name only, cheaper than describe for everything but GameEvents
+pub fn kind(event: &Event) -> String {
+    match event {
+        Event::GameEvent(_) => describe(event).0,
+        Event::SwapchainInvalidated { .. } => "SwapchainInvalidated".to_owned(),
+        Event::WindowResized(_) => "WindowResized".to_owned(),
+        Event::WindowCloseRequested => "WindowCloseRequested".to_owned(),
+        Event::MouseMotion(_) => "MouseMotion".to_owned(),
+        Event::WindowEventWrapped(_) => "WindowEvent".to_owned(),
+        Event::FileHovered(_) => "FileHovered".to_owned(),
+        Event::FileHoverCancelled => "FileHoverCancelled".to_owned(),
+        Event::FileDropped(_) => "FileDropped".to_owned(),
+    }
+}
+
+fn describe(event: &Event) -> (String, String) {
+    match event {
+        Event::SwapchainInvalidated { dimensions, .. } => (
+            "SwapchainInvalidated".to_owned(),
+            format!("{}x{}", dimensions.width, dimensions.height),
+        ),
+        Event::WindowResized(size) => (
+            "WindowResized".to_owned(),
+            format!("{}x{}", size.width, size.height),
+        ),
+        Event::WindowCloseRequested => ("WindowCloseRequested".to_owned(), String::new()),
+        Event::MouseMotion((dx, dy)) => ("MouseMotion".to_owned(), format!("{:.1}, {:.1}", dx, dy)),
+        Event::WindowEventWrapped(event) => ("WindowEvent".to_owned(), format!("{:?}", event)),
+        Event::FileHovered(path) => ("FileHovered".to_owned(), path.display().to_string()),
+        Event::FileHoverCancelled => ("FileHoverCancelled".to_owned(), String::new()),
+        Event::FileDropped(path) => ("FileDropped".to_owned(), path.display().to_string()),
+        Event::GameEvent(event) => {
+            let debug = format!("{:?}", event);
+            // The variant name is whatever comes before the payload
+            let split = debug
+                .find(|c| matches!(c, '(' | ' ' | '{'))
+                .unwrap_or(debug.len());
+            let (name, payload) = debug.split_at(split);
+            let payload = payload.trim();
+            let payload = payload
+                .strip_prefix('(')
+                .and_then(|payload| payload.strip_suffix(')'))
+                .unwrap_or(payload);
+            (format!("GameEvent::{}", name), payload.to_owned())
+        }
+    }
+}
+
+fn truncate(mut text: String) -> String {
+    if let Some((index, _)) = text.char_indices().nth(MAX_SUMMARY_LENGTH) {
+        text.truncate(index);
+        text.push('…');
+    }
+    text
+}
+
+impl EventLog {
+    pub fn new(frames: u64, capacity: usize) -> Self {
+        Self {
+            records: VecDeque::new(),
+            frame: 0,
+            frames,
+            capacity,
+            recording: true,
+        }
+    }
+
+    pub fn record(&mut self, event: &Event, consumer: Option<&str>) {
+        if !self.recording || self.capacity == 0 {
+            return;
+        }
+        let (kind, summary) = describe(event);
+        if self.records.len() == self.capacity {
+            self.records.pop_front();
+        }
+        self.records.push_back(EventRecord {
+            frame: self.frame,
+            kind,
+            summary: truncate(summary),
+            consumer: consumer.map(str::to_owned),
+        });
+    }
+
+    // Drops the records which are too old
+    pub fn next_frame(&mut self) {
+        self.frame += 1;
+        while let Some(record) = self.records.front() {
+            if record.frame + self.frames >= self.frame {
+                break;
+            }
+            self.records.pop_front();
+        }
+    }
+
+    #[inline]
+    pub const fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    #[inline]
+    pub const fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    pub fn set_frames(&mut self, frames: u64) {
+        self.frames = frames;
+    }
+
+    #[inline]
+    pub const fn is_recording(&self) -> bool {
+        self.recording
+    }
+
+    pub fn set_recording(&mut self, recording: bool) {
+        self.recording = recording;
+    }
+
+    pub fn records(&self) -> impl DoubleEndedIterator<Item = &EventRecord> + ExactSizeIterator {
+        self.records.iter()
+    }
+
+    pub fn clear(&mut self) {
+        self.records.clear();
+    }
+}
+
+impl Default for EventLog {
+    fn default() -> Self {
+        Self::new(DEFAULT_EVENT_FRAMES, DEFAULT_EVENT_CAPACITY)
+    }
+}
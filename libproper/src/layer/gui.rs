@@ -1,24 +1,102 @@
-use std::sync::{Arc, Mutex};
+use std::{
+    path::PathBuf,
+    sync::{Arc, Mutex, RwLock},
+};
 
 use egui_winit_vulkano::{egui, Gui};
 use vulkano::{device::Queue, swapchain::Surface, sync::GpuFuture};
 use winit::{
+    event::WindowEvent,
     event_loop::{ControlFlow, EventLoopProxy},
     window::Window,
 };
 
 use crate::{
+    config::{Config, GuiConfig},
+    cursor::Cursor,
     error::Error,
     event::{Event, GameEvent},
-    layer::Layer,
-    render::frame::Frame,
+    gui::{
+        appearance::{apply_style, AppearancePanel},
+        assets::AssetsPanel,
+        console::{Console, ConsolePanel},
+        diagnostics::DiagnosticsPanel,
+        dock::{DockArea, Workspace},
+        gizmo::GizmoOverlay,
+        hierarchy::HierarchyPanel,
+        inspector::InspectorPanel,
+        inventory::InventoryPanel,
+        labels::LabelOverlay,
+        memory::MemoryPanel,
+        minimap::MinimapOverlay,
+        stats::StatsPanel,
+        Selection,
+    },
+    i18n::Localization,
+    layer::{priority, Layer},
+    lock::Recover,
+    preferences::Preferences,
+    render::{frame::Frame, stats::Stats},
+    resource::texture::TextureRegistry,
+    time::Time,
     world::scene::Scene,
 };
 
 pub struct GuiLayer {
     inner: Gui,
-    scene: Arc<Mutex<Scene>>,
     event_proxy: EventLoopProxy<GameEvent>,
+    surface: Arc<Surface<Window>>,
+    workspace: Arc<Mutex<Workspace>>,
+    config: Arc<Mutex<Config>>,
+    applied_config: Option<GuiConfig>,
+    text_input: bool,
+    cursor: Cursor,
+    // Whether the cursor icon has to be restored once the pointer leaves the GUI
+    cursor_icon_dirty: bool,
+    opened_asset: Arc<Mutex<Option<String>>>,
+    gizmo: GizmoOverlay,
+    labels: LabelOverlay,
+    minimap: MinimapOverlay,
+    hovered_file: Option<PathBuf>,
+    // (message, hint) pairs not yet dismissed by the user
+    errors: Vec<(String, Option<String>)>,
+    interaction_prompt: Option<String>,
+}
+
+// Only the most recent ones are kept if errors keep coming
+const MAX_REPORTED_ERRORS: usize = 8;
+
+fn paint_software_cursor(ctx: &egui::Context) {
+    let pos = match ctx.input().pointer.hover_pos() {
+        Some(pos) => pos,
+        None => return,
+    };
+
+    // Hides the hardware cursor
+    ctx.output().cursor_icon = egui::CursorIcon::None;
+
+    let painter = ctx.layer_painter(egui::LayerId::new(
+        egui::Order::Tooltip,
+        egui::Id::new("software_cursor"),
+    ));
+    painter.add(egui::Shape::convex_polygon(
+        vec![pos, pos + egui::vec2(0.0, 16.0), pos + egui::vec2(11.0, 11.0)],
+        egui::Color32::WHITE,
+        egui::Stroke::new(1.0, egui::Color32::BLACK),
+    ));
+}
+
+fn paint_reticle(ctx: &egui::Context, active: bool) {
+    let painter = ctx.layer_painter(egui::LayerId::new(
+        egui::Order::Foreground,
+        egui::Id::new("reticle"),
+    ));
+    let (radius, color) = if active {
+        (4.0, egui::Color32::WHITE)
+    } else {
+        (2.0, egui::Color32::from_white_alpha(160))
+    };
+    painter.circle_filled(ctx.available_rect().center(), radius, color);
 }
 
 impl GuiLayer {
@@ -26,15 +104,82 @@ impl GuiLayer {
         event_proxy: EventLoopProxy<GameEvent>,
         surface: Arc<Surface<Window>>,
         gfx_queue: Arc<Queue>,
-        scene: Arc<Mutex<Scene>>,
+        scene: Arc<RwLock<Scene>>,
+        texture_registry: Arc<RwLock<TextureRegistry>>,
+        workspace: Arc<Mutex<Workspace>>,
+        console: Arc<Mutex<Console>>,
+        config: Arc<Mutex<Config>>,
+        localization: Arc<Mutex<Localization>>,
+        stats: Arc<Mutex<Stats>>,
+        preferences: Arc<Mutex<Preferences>>,
     ) -> Self {
-        let inner = Gui::new(surface, None, gfx_queue, true);
+        let inner = Gui::new(surface.clone(), None, gfx_queue, true);
+        let selection = Selection::default();
+        let assets = AssetsPanel::new(texture_registry.clone());
+        let opened_asset = assets.selected().clone();
+        let gizmo = GizmoOverlay::new(scene.clone(), selection.clone(), preferences.clone());
+        let labels = LabelOverlay::new(scene.clone(), preferences.clone());
+        let minimap = MinimapOverlay::new(scene.clone(), preferences);
+
+        {
+            let mut workspace = workspace.lock().recover();
+            workspace.register(
+                HierarchyPanel::new(event_proxy.clone(), scene.clone(), selection.clone()),
+                DockArea::Left,
+            );
+            workspace.register(StatsPanel::new(scene.clone(), stats), DockArea::Left);
+            workspace.register(MemoryPanel::default(), DockArea::Left);
+            workspace.register(
+                DiagnosticsPanel::new(scene.clone(), selection.clone()),
+                DockArea::Left,
+            );
+            workspace.register(InventoryPanel::new(scene.clone()), DockArea::Right);
+            workspace.register(
+                InspectorPanel::new(scene, texture_registry.clone(), selection),
+                DockArea::Right,
+            );
+            workspace.register(
+                AppearancePanel::new(event_proxy.clone(), config.clone(), localization),
+                DockArea::Right,
+            );
+            workspace.register(ConsolePanel::new(console), DockArea::Bottom);
+            workspace.register(assets, DockArea::Bottom);
+        }
+
         Self {
             inner,
             event_proxy,
-            scene,
+            surface,
+            workspace,
+            config,
+            applied_config: None,
+            text_input: false,
+            cursor: Cursor::default(),
+            cursor_icon_dirty: false,
+            opened_asset,
+            gizmo,
+            labels,
+            minimap,
+            hovered_file: None,
+            errors: vec![],
+            interaction_prompt: None,
         }
     }
+
+    fn apply_config(&mut self) {
+        let config = self.config.lock().recover().gui.clone();
+        if self.applied_config.as_ref() == Some(&config) {
+            return;
+        }
+
+        let scale_factor = self.surface.window().scale_factor() as f32;
+        self.inner
+            .egui_winit
+            .set_pixels_per_point(scale_factor * config.scale);
+        apply_style(&self.inner.context(), &config);
+
+        self.applied_config = Some(config);
+    }
 }
 
 impl Layer for GuiLayer {
@@ -42,14 +187,54 @@ impl Layer for GuiLayer {
 
     fn on_detach(&mut self) {}
 
-    fn on_tick(&mut self, _delta: f64) -> Result<(), Error> {
+    fn draw_priority(&self) -> i32 {
+        priority::GUI
+    }
+
+    fn on_tick(&mut self, _time: &Time) -> Result<(), Error> {
         Ok(())
     }
 
     fn on_event(&mut self, event: &Event, _: &mut ControlFlow) -> Result<bool, Error> {
         if let Event::WindowEventWrapped(event) = event {
-            Ok(self.inner.update(event))
+            if let WindowEvent::ScaleFactorChanged { .. } = event {
+                // egui-winit resets pixels-per-point to the new native value
+                self.applied_config = None;
+            }
+            let consumed = self.inner.update(event);
+            // Keyboard is exclusive to the focused widget in text input mode
+            let keyboard = matches!(
+                event,
+                WindowEvent::KeyboardInput { .. } | WindowEvent::ReceivedCharacter(_)
+            );
+            let gizmo = match event {
+                WindowEvent::MouseInput { .. } => self.gizmo.wants_pointer(),
+                _ => keyboard && self.gizmo.is_dragging(),
+            };
+            Ok(consumed || (self.text_input && keyboard) || gizmo)
         } else {
+            match event {
+                Event::GameEvent(GameEvent::CursorChanged(cursor)) => {
+                    self.cursor = *cursor;
+                    self.cursor_icon_dirty = true;
+                }
+                Event::GameEvent(GameEvent::TextureOpened(name)) => {
+                    *self.opened_asset.lock().recover() = Some(name.clone());
+                    self.workspace.lock().recover().focus("Assets");
+                }
+                Event::GameEvent(GameEvent::ErrorReported { message, hint }) => {
+                    if self.errors.len() == MAX_REPORTED_ERRORS {
+                        self.errors.remove(0);
+                    }
+                    self.errors.push((message.clone(), hint.clone()));
+                }
+                Event::GameEvent(GameEvent::InteractionPrompt(prompt)) => {
+                    self.interaction_prompt = prompt.clone();
+                }
+                Event::FileHovered(path) => self.hovered_file = Some(path.to_path_buf()),
+                Event::FileHoverCancelled | Event::FileDropped(_) => self.hovered_file = None,
+                _ => (),
+            }
             Ok(false)
         }
     }
@@ -59,33 +244,90 @@ impl Layer for GuiLayer {
         in_future: Box<dyn GpuFuture>,
         frame: &Frame,
     ) -> Result<Box<dyn GpuFuture>, Error> {
+        self.apply_config();
+        let cursor = self.cursor;
+        let hovered_file = self.hovered_file.as_ref();
+        let interaction_prompt = self.interaction_prompt.as_ref();
+        let errors = &mut self.errors;
+        let gizmo = &mut self.gizmo;
+        let labels = &mut self.labels;
+        let minimap = &mut self.minimap;
         self.inner.immediate_ui(|gui| {
             let ctx = gui.context();
+            self.workspace.lock().recover().show(&ctx);
+            labels.show(&ctx);
+            minimap.show(&ctx);
+            gizmo.show(&ctx);
 
-            egui::SidePanel::new(egui::panel::Side::Left, 0)
-                .min_width(200.0)
-                .max_width(200.0)
-                .resizable(true)
-                .show(&ctx, |ui| {
-                    if ui.add(egui::Button::new("TEXT")).clicked() {
-                        self.event_proxy.send_event(GameEvent::TestEvent).ok();
-                    }
-                    let scene = self.scene.lock().unwrap();
-                    let camera_position = scene.camera.position();
-                    let camera_pitch = scene.camera.pitch();
-                    let camera_yaw = scene.camera.yaw();
-                    ui.add(egui::Label::new(format!(
-                        "Position: {:.3}, {:.3}, {:.3}",
-                        camera_position.x, camera_position.y, camera_position.z
-                    )));
-
-                    ui.add(egui::Label::new(format!(
-                        "Pitch: {:.3}°, Yaw: {:.3}°",
-                        camera_pitch.to_degrees(), camera_yaw.to_degrees()
-                    )))
-                });
+            if let Some(name) = hovered_file.and_then(|path| path.file_name()) {
+                egui::Area::new("drop_hint")
+                    .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+                    .order(egui::Order::Foreground)
+                    .show(&ctx, |ui| {
+                        egui::Frame::popup(ui.style()).show(ui, |ui| {
+                            ui.label(format!("Drop to open {}", name.to_string_lossy()));
+                        });
+                    });
+            }
+
+            // Reticle and the prompt below it, only in mouse look
+            if cursor.is_grabbed() {
+                paint_reticle(&ctx, interaction_prompt.is_some());
+                if let Some(prompt) = interaction_prompt {
+                    egui::Area::new("interaction_prompt")
+                        .anchor(egui::Align2::CENTER_CENTER, [0.0, 32.0])
+                        .order(egui::Order::Foreground)
+                        .interactable(false)
+                        .show(&ctx, |ui| {
+                            egui::Frame::popup(ui.style()).show(ui, |ui| {
+                                ui.label(format!("[Interact] {}", prompt));
+                            });
+                        });
+                }
+            }
+
+            if !errors.is_empty() {
+                let mut dismissed = false;
+                egui::Window::new("Error")
+                    .collapsible(false)
+                    .resizable(false)
+                    .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+                    .show(&ctx, |ui| {
+                        for (message, hint) in errors.iter() {
+                            ui.colored_label(egui::Color32::RED, message);
+                            if let Some(hint) = hint {
+                                ui.label(egui::RichText::new(hint).italics());
+                            }
+                            ui.separator();
+                        }
+                        dismissed = ui.button("Dismiss").clicked();
+                    });
+                if dismissed {
+                    errors.clear();
+                }
+            }
+
+            if cursor.is_software() && !cursor.is_grabbed() {
+                paint_software_cursor(&ctx);
+            }
         });
 
+        // egui sets its own icons over widgets, the requested one is used elsewhere
+        if self.inner.context().is_pointer_over_area() {
+            self.cursor_icon_dirty = true;
+        } else if self.cursor_icon_dirty && !cursor.is_software() {
+            self.surface.window().set_cursor_icon(cursor.icon());
+            self.cursor_icon_dirty = false;
+        }
+
+        let text_input = self.inner.context().wants_keyboard_input();
+        if text_input != self.text_input {
+            self.text_input = text_input;
+            self.event_proxy
+                .send_event(GameEvent::SetTextInput(text_input))
+                .ok();
+        }
+
         Ok(self
             .inner
             .draw_on_image(in_future, frame.destination.clone()))
@@ -1,22 +1,49 @@
-use std::sync::{
-    atomic::{AtomicBool, Ordering},
-    Arc,
+use std::{
+    collections::BTreeMap,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
 };
 
+use nalgebra::Vector2;
 use vulkano::sync::GpuFuture;
 use winit::{
-    event::{ElementState, KeyboardInput, MouseButton, VirtualKeyCode, WindowEvent},
+    event::{ElementState, KeyboardInput, MouseButton, ScanCode, VirtualKeyCode, WindowEvent},
     event_loop::{ControlFlow, EventLoopProxy},
 };
 
 use crate::{
     error::Error,
     event::{Event, GameEvent},
+    lock::Recover,
+    preferences::{
+        Preferences, MOUSE_ACCELERATION, MOUSE_INVERT_Y, MOUSE_SENSITIVITY, MOUSE_SMOOTHING,
+    },
     render::frame::Frame,
+    state::GameState,
+    time::Time,
 };
 
 use super::Layer;
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Action {
+    Forward,
+    Back,
+    Left,
+    Right,
+    Up,
+    Down,
+    Interact,
+}
+
+// Actions are bound to physical key positions, so the movement keys stay in place
+// on non-QWERTY layouts
+pub struct Bindings {
+    keys: BTreeMap<ScanCode, Action>,
+}
+
 #[derive(Default)]
 pub struct InputState {
     pub forward: AtomicBool,
@@ -25,18 +52,220 @@ pub struct InputState {
     pub right: AtomicBool,
     pub up: AtomicBool,
     pub down: AtomicBool,
+    pub interact: AtomicBool,
+    // Raw mouse motion accumulated since the last tick
+    look: Mutex<Vector2<f32>>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LookSettings {
+    // Radians per pixel
+    pub sensitivity: f32,
+    pub invert_y: bool,
+    // 0 disables smoothing, values close to 1 make the camera lag behind the mouse
+    pub smoothing: f32,
+    pub acceleration: f32,
+}
+
+// Turns raw mouse motion into camera (pitch, yaw) angle deltas
+#[derive(Default)]
+pub struct MouseLook {
+    smoothed: Vector2<f32>,
+}
+
+impl Action {
+    pub const ALL: [Self; 7] = [
+        Self::Forward,
+        Self::Back,
+        Self::Left,
+        Self::Right,
+        Self::Up,
+        Self::Down,
+        Self::Interact,
+    ];
+
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Forward => "forward",
+            Self::Back => "back",
+            Self::Left => "left",
+            Self::Right => "right",
+            Self::Up => "up",
+            Self::Down => "down",
+            Self::Interact => "interact",
+        }
+    }
+
+    pub fn preference_key(self) -> String {
+        format!("bindings.{}", self.name())
+    }
+
+    // W, S, A, D, Space, Left Control and E
+    #[cfg(not(target_os = "macos"))]
+    const fn default_scancode(self) -> ScanCode {
+        match self {
+            Self::Forward => 17,
+            Self::Back => 31,
+            Self::Left => 30,
+            Self::Right => 32,
+            Self::Up => 57,
+            Self::Down => 29,
+            Self::Interact => 18,
+        }
+    }
+
+    #[cfg(target_os = "macos")]
+    const fn default_scancode(self) -> ScanCode {
+        match self {
+            Self::Forward => 13,
+            Self::Back => 1,
+            Self::Left => 0,
+            Self::Right => 2,
+            Self::Up => 49,
+            Self::Down => 59,
+            Self::Interact => 14,
+        }
+    }
+}
+
+impl Bindings {
+    pub fn from_preferences(preferences: &Preferences) -> Self {
+        let mut bindings = Self {
+            keys: BTreeMap::new(),
+        };
+        for action in Action::ALL {
+            let scancode =
+                preferences.integer(&action.preference_key(), action.default_scancode() as i64);
+            // Negative values are stored for unbound actions
+            if scancode >= 0 {
+                bindings.bind(action, scancode as ScanCode);
+            }
+        }
+        bindings
+    }
+
+    pub fn store(&self, preferences: &mut Preferences) {
+        for action in Action::ALL {
+            let scancode = self.scancode(action).map_or(-1, i64::from);
+            preferences.set(&action.preference_key(), scancode);
+        }
+    }
+
+    // Replaces both the action's previous key and the key's previous action
+    pub fn bind(&mut self, action: Action, scancode: ScanCode) {
+        self.keys.retain(|_, bound| *bound != action);
+        self.keys.insert(scancode, action);
+    }
+
+    #[inline]
+    pub fn action(&self, scancode: ScanCode) -> Option<Action> {
+        self.keys.get(&scancode).copied()
+    }
+
+    pub fn scancode(&self, action: Action) -> Option<ScanCode> {
+        self.keys
+            .iter()
+            .find_map(|(&scancode, &bound)| (bound == action).then(|| scancode))
+    }
+}
+
+impl InputState {
+    pub const fn key(&self, action: Action) -> &AtomicBool {
+        match action {
+            Action::Forward => &self.forward,
+            Action::Back => &self.back,
+            Action::Left => &self.left,
+            Action::Right => &self.right,
+            Action::Up => &self.up,
+            Action::Down => &self.down,
+            Action::Interact => &self.interact,
+        }
+    }
+
+    pub fn clear(&self) {
+        for action in Action::ALL {
+            self.key(action).store(false, Ordering::Release);
+        }
+        *self.look.lock().recover() = Vector2::zeros();
+    }
+
+    pub fn add_look(&self, delta: (f64, f64)) {
+        *self.look.lock().recover() += Vector2::new(delta.0 as f32, delta.1 as f32);
+    }
+
+    pub fn take_look(&self) -> Vector2<f32> {
+        std::mem::take(&mut *self.look.lock().recover())
+    }
+}
+
+impl LookSettings {
+    pub fn from_preferences(preferences: &Preferences) -> Self {
+        Self {
+            sensitivity: preferences.float(MOUSE_SENSITIVITY, 0.02) as f32,
+            invert_y: preferences.bool(MOUSE_INVERT_Y, false),
+            smoothing: preferences.float(MOUSE_SMOOTHING, 0.0).clamp(0.0, 0.99) as f32,
+            acceleration: preferences.float(MOUSE_ACCELERATION, 0.0).max(0.0) as f32,
+        }
+    }
+}
+
+impl MouseLook {
+    pub fn apply(
+        &mut self,
+        motion: Vector2<f32>,
+        settings: &LookSettings,
+        delta: f32,
+    ) -> Vector2<f32> {
+        let mut motion = motion;
+
+        if settings.acceleration > 0.0 && delta > 0.0 {
+            // Speed in pixels per second, fast flicks turn further
+            let speed = motion.norm() / delta;
+            motion *= 1.0 + settings.acceleration * speed / 1000.0;
+        }
+
+        if settings.smoothing > 0.0 {
+            // Framerate-independent exponential smoothing, tuned at 60 FPS
+            let alpha = 1.0 - settings.smoothing.powf(delta * 60.0);
+            self.smoothed += (motion - self.smoothed) * alpha;
+            motion = self.smoothed;
+        } else {
+            self.smoothed = motion;
+        }
+
+        let pitch = if settings.invert_y {
+            motion.y
+        } else {
+            -motion.y
+        };
+        Vector2::new(pitch, motion.x) * settings.sensitivity
+    }
 }
 
 pub struct InputLayer {
     event_proxy: EventLoopProxy<GameEvent>,
     pub state: Arc<InputState>,
+    preferences: Arc<Mutex<Preferences>>,
+    bindings: Bindings,
+    // Next key press gets bound to this action
+    capture: Option<Action>,
+    // A focused UI widget receives all keyboard input
+    text_input: bool,
     mouse_grab_state: bool,
 }
 
 impl InputLayer {
-    pub fn new(event_proxy: EventLoopProxy<GameEvent>) -> Self {
+    pub fn new(
+        event_proxy: EventLoopProxy<GameEvent>,
+        preferences: Arc<Mutex<Preferences>>,
+    ) -> Self {
+        let bindings = Bindings::from_preferences(&preferences.lock().recover());
         Self {
             event_proxy,
+            preferences,
+            bindings,
+            capture: None,
+            text_input: false,
             mouse_grab_state: false,
             state: Default::default(),
         }
@@ -44,24 +273,34 @@ impl InputLayer {
 
     pub fn handle_key_input(&mut self, input: &KeyboardInput) -> Result<bool, Error> {
         let state = input.state == ElementState::Pressed;
-        match input.virtual_keycode {
-            Some(VirtualKeyCode::W) => self.state.forward.store(state, Ordering::Release),
-            Some(VirtualKeyCode::S) => self.state.back.store(state, Ordering::Release),
-            Some(VirtualKeyCode::A) => self.state.left.store(state, Ordering::Release),
-            Some(VirtualKeyCode::D) => self.state.right.store(state, Ordering::Release),
-            Some(VirtualKeyCode::Space) => self.state.up.store(state, Ordering::Release),
-            Some(VirtualKeyCode::LControl) => self.state.down.store(state, Ordering::Release),
-            Some(VirtualKeyCode::Escape) => {
-                if self.mouse_grab_state {
-                    self.mouse_grab_state = false;
-                    self.event_proxy
-                        .send_event(GameEvent::SetMouseGrab(false))
-                        .unwrap();
-                }
+
+        if let Some(action) = self.capture {
+            if state && input.virtual_keycode != Some(VirtualKeyCode::Escape) {
+                self.bindings.bind(action, input.scancode);
+                self.bindings.store(&mut self.preferences.lock().recover());
+            }
+            if state {
+                self.capture = None;
+                return Ok(true);
+            }
+        }
+
+        if let Some(action) = self.bindings.action(input.scancode) {
+            self.state.key(action).store(state, Ordering::Release);
+            return Ok(true);
+        }
+
+        if input.virtual_keycode == Some(VirtualKeyCode::Escape) {
+            // Pausing releases the mouse grab
+            if state {
+                self.event_proxy
+                    .send_event(GameEvent::PushGameState(GameState::Paused))
+                    .unwrap();
             }
-            _ => return Ok(false),
+            return Ok(true);
         }
-        Ok(true)
+
+        Ok(false)
     }
 
     pub fn handle_mouse_input(
@@ -94,18 +333,51 @@ impl Layer for InputLayer {
         Ok(in_future)
     }
 
-    fn on_tick(&mut self, _delta: f64) -> Result<(), Error> {
+    fn on_tick(&mut self, _time: &Time) -> Result<(), Error> {
         Ok(())
     }
 
     fn on_event(&mut self, event: &Event, _flow: &mut ControlFlow) -> Result<bool, Error> {
         match event {
             Event::WindowEventWrapped(WindowEvent::KeyboardInput { input, .. }) => {
-                self.handle_key_input(input)
+                if self.text_input {
+                    Ok(false)
+                } else {
+                    self.handle_key_input(input)
+                }
             }
             Event::WindowEventWrapped(&WindowEvent::MouseInput { state, button, .. }) => {
                 self.handle_mouse_input(button, state)
             }
+            Event::MouseMotion(delta) => {
+                self.state.add_look(*delta);
+                Ok(true)
+            }
+            // Key releases are lost while the window is unfocused
+            Event::GameEvent(GameEvent::FocusChanged(false)) => {
+                self.state.clear();
+                Ok(false)
+            }
+            Event::GameEvent(GameEvent::SetTextInput(enabled)) => {
+                self.text_input = *enabled;
+                if *enabled {
+                    self.state.clear();
+                }
+                Ok(false)
+            }
+            Event::GameEvent(GameEvent::CaptureBinding(action)) => {
+                self.capture = Some(*action);
+                Ok(false)
+            }
+            Event::GameEvent(GameEvent::CursorChanged(cursor)) => {
+                self.mouse_grab_state = cursor.is_grabbed();
+                Ok(false)
+            }
+            // Key releases are not seen while a menu is on top
+            Event::GameEvent(GameEvent::GameStateChanged(state)) if state.is_frozen() => {
+                self.state.clear();
+                Ok(false)
+            }
             _ => Ok(false),
         }
     }
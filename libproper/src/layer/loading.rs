@@ -0,0 +1,121 @@
+use std::sync::{Arc, Mutex};
+
+use egui_winit_vulkano::{egui, Gui};
+use vulkano::{device::Queue, swapchain::Surface, sync::GpuFuture};
+use winit::{
+    event_loop::{ControlFlow, EventLoopProxy},
+    window::Window,
+};
+
+use crate::{
+    error::Error,
+    event::{Event, GameEvent},
+    i18n::Localization,
+    lock::Recover,
+    render::frame::Frame,
+    resource::loader::LoadingHandle,
+    state::GameState,
+    time::Time,
+};
+
+use super::{priority, Layer};
+
+// Covers the screen while required assets are being loaded in the background
+pub struct LoadingLayer {
+    inner: Gui,
+    event_proxy: EventLoopProxy<GameEvent>,
+    handle: LoadingHandle,
+    done: bool,
+    localization: Arc<Mutex<Localization>>,
+}
+
+impl LoadingLayer {
+    pub fn new(
+        event_proxy: EventLoopProxy<GameEvent>,
+        surface: Arc<Surface<Window>>,
+        gfx_queue: Arc<Queue>,
+        handle: LoadingHandle,
+        localization: Arc<Mutex<Localization>>,
+    ) -> Self {
+        let inner = Gui::new(surface, None, gfx_queue, true);
+        Self {
+            inner,
+            event_proxy,
+            handle,
+            done: false,
+            localization,
+        }
+    }
+}
+
+impl Layer for LoadingLayer {
+    fn on_attach(&mut self) {}
+
+    fn on_detach(&mut self) {}
+
+    fn draw_priority(&self) -> i32 {
+        priority::OVERLAY
+    }
+
+    fn on_tick(&mut self, _time: &Time) -> Result<(), Error> {
+        if !self.done && self.handle.poll() && self.handle.error().is_none() {
+            self.done = true;
+            self.event_proxy
+                .send_event(GameEvent::SetGameState(GameState::Playing))
+                .ok();
+        }
+        Ok(())
+    }
+
+    fn on_event(&mut self, event: &Event, _flow: &mut ControlFlow) -> Result<bool, Error> {
+        match event {
+            Event::WindowEventWrapped(event) => {
+                self.inner.update(event);
+                Ok(true)
+            }
+            Event::MouseMotion(_) => Ok(true),
+            _ => Ok(false),
+        }
+    }
+
+    fn on_draw(
+        &mut self,
+        in_future: Box<dyn GpuFuture>,
+        frame: &Frame,
+    ) -> Result<Box<dyn GpuFuture>, Error> {
+        let progress = self.handle.progress().clone();
+        let fraction = self.handle.fraction();
+        let error = self.handle.error().map(str::to_owned);
+        let i18n = self.localization.lock().recover();
+
+        self.inner.immediate_ui(|gui| {
+            let ctx = gui.context();
+
+            egui::CentralPanel::default().show(&ctx, |ui| {
+                ui.vertical_centered(|ui| {
+                    ui.add_space((ui.available_height() / 2.0 - 40.0).max(0.0));
+                    ui.heading(i18n.get("loading.title"));
+
+                    if let Some(error) = error {
+                        ui.colored_label(
+                            egui::Color32::RED,
+                            i18n.format("loading.failed", &[("error", &error)]),
+                        );
+                        return;
+                    }
+
+                    ui.add(
+                        egui::ProgressBar::new(fraction)
+                            .desired_width(ui.available_width() / 2.0)
+                            .text(format!("{} / {}", progress.loaded, progress.total)),
+                    );
+                    ui.label(progress.current.as_str());
+                });
+            });
+        });
+
+        Ok(self
+            .inner
+            .draw_on_image(in_future, frame.destination.clone()))
+    }
+}
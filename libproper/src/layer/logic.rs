@@ -1,42 +1,120 @@
-use std::sync::{atomic::Ordering, Arc, Mutex};
+use std::{
+    path::Path,
+    sync::{atomic::Ordering, Arc, Mutex, RwLock},
+};
 
-use nalgebra::{Point3, Vector3};
+use nalgebra::{Point3, Vector2, Vector3};
+use rand::{rngs::StdRng, Rng};
 use vulkano::sync::GpuFuture;
 use winit::event_loop::{ControlFlow, EventLoopProxy};
 
+#[cfg(feature = "physics")]
+use crate::world::{collision::CollisionSystem, trigger::TriggerSystem};
 use crate::{
+    ai::AiSystem,
     error::Error,
     event::{Event, GameEvent},
-    render::frame::Frame,
+    lock::Recover,
+    preferences::Preferences,
+    random::Random,
+    render::{frame::Frame, grading, shader::ShaderVariant},
     resource::{
         material::{MaterialInstanceCreateInfo, MaterialRegistry},
         model::ModelRegistry,
         texture::TextureRegistry,
     },
-    world::{entity::Entity, scene::Scene},
+    task::{TaskExecutor, Tasks},
+    time::Time,
+    timer::TimerManager,
+    world::{
+        animation::AnimationSystem,
+        combat::ProjectileSystem,
+        component::MaterialPresetRef,
+        cutscene::{Cutscene, CutscenePlayer},
+        entity::{Entity, EntityId},
+        interaction::InteractionSystem,
+        inventory::PickupSystem,
+        morph::MorphSystem,
+        motion::MotionSystem,
+        nav::NavigationSystem,
+        scene::Scene,
+        schedule::SystemScheduler,
+        spawner::SpawnerSystem,
+        sprite::SpriteSortSystem,
+        stats::{Stats, StatsSystem},
+        streaming::{StreamingSettings, StreamingSystem},
+        voxel::VoxelSystem,
+    },
+};
+
+use super::{
+    input::{InputState, LookSettings, MouseLook},
+    Layer,
 };
 
-use super::{input::InputState, Layer};
+// Distance in front of the camera at which dropped models are placed
+const DROPPED_MODEL_DISTANCE: f32 = 5.0;
+// Seconds between checks of the material preset files
+const PRESET_POLL_INTERVAL: f64 = 1.0;
 
 pub struct LogicLayer {
-    #[allow(dead_code)]
     event_proxy: EventLoopProxy<GameEvent>,
-    scene: Arc<Mutex<Scene>>,
-    material_registry: Arc<Mutex<MaterialRegistry>>,
-    model_registry: Arc<Mutex<ModelRegistry>>,
-    texture_registry: Arc<Mutex<TextureRegistry>>,
+    scene: Arc<RwLock<Scene>>,
+    material_registry: Arc<RwLock<MaterialRegistry>>,
+    model_registry: Arc<RwLock<ModelRegistry>>,
+    texture_registry: Arc<RwLock<TextureRegistry>>,
     input_state: Arc<InputState>,
+    preferences: Arc<Mutex<Preferences>>,
+    timers: Arc<Mutex<TimerManager>>,
+    task_executor: TaskExecutor,
+    rng: StdRng,
+    mouse_look: MouseLook,
+    navigation_system: NavigationSystem,
+    motion_system: MotionSystem,
+    #[cfg(feature = "physics")]
+    collision_system: CollisionSystem,
+    #[cfg(feature = "physics")]
+    trigger_system: TriggerSystem,
+    pickup_system: PickupSystem,
+    interaction_system: InteractionSystem,
+    projectile_system: ProjectileSystem,
+    spawner_system: SpawnerSystem,
+    scheduler: SystemScheduler,
+    streaming_system: StreamingSystem,
+    voxel_system: VoxelSystem,
+    preset_poll_accumulator: f64,
+    // Drives the camera instead of the player while playing
+    cutscene: Option<CutscenePlayer>,
 }
 
 impl LogicLayer {
     pub fn new(
         event_proxy: EventLoopProxy<GameEvent>,
-        scene: Arc<Mutex<Scene>>,
-        material_registry: Arc<Mutex<MaterialRegistry>>,
-        model_registry: Arc<Mutex<ModelRegistry>>,
-        texture_registry: Arc<Mutex<TextureRegistry>>,
+        scene: Arc<RwLock<Scene>>,
+        material_registry: Arc<RwLock<MaterialRegistry>>,
+        model_registry: Arc<RwLock<ModelRegistry>>,
+        texture_registry: Arc<RwLock<TextureRegistry>>,
         input_state: Arc<InputState>,
+        preferences: Arc<Mutex<Preferences>>,
+        timers: Arc<Mutex<TimerManager>>,
+        tasks: Tasks,
+        random: Random,
     ) -> Self {
+        let streaming_system = StreamingSystem::new(
+            StreamingSettings::default(),
+            material_registry.clone(),
+            model_registry.clone(),
+            texture_registry.clone(),
+        );
+
+        // Per-frame systems, run in parallel where their component access allows
+        let mut scheduler = SystemScheduler::new(random);
+        scheduler.add(AnimationSystem::default());
+        scheduler.add(MorphSystem::default());
+        scheduler.add(AiSystem::default());
+        scheduler.add(StatsSystem::default());
+        scheduler.add(SpriteSortSystem::default());
+
         Self {
             event_proxy,
             scene,
@@ -44,20 +122,233 @@ impl LogicLayer {
             model_registry,
             texture_registry,
             input_state,
+            preferences,
+            timers,
+            task_executor: TaskExecutor::new(tasks),
+            rng: random.stream("logic"),
+            mouse_look: MouseLook::default(),
+            navigation_system: NavigationSystem::default(),
+            motion_system: MotionSystem::default(),
+            #[cfg(feature = "physics")]
+            collision_system: CollisionSystem::default(),
+            #[cfg(feature = "physics")]
+            trigger_system: TriggerSystem::default(),
+            pickup_system: PickupSystem::default(),
+            interaction_system: InteractionSystem::default(),
+            projectile_system: ProjectileSystem::default(),
+            spawner_system: SpawnerSystem::new(random),
+            scheduler,
+            streaming_system,
+            voxel_system: VoxelSystem::default(),
+            preset_poll_accumulator: 0.0,
+            cutscene: None,
+        }
+    }
+
+    fn play_cutscene(&mut self, name: &str) -> Result<(), Error> {
+        let cutscene = Cutscene::load_by_name(name)?;
+        self.scene
+            .write()
+            .recover()
+            .camera_effects
+            .set_letterbox(cutscene.letterbox);
+        self.cutscene = Some(CutscenePlayer::new(name, cutscene));
+        Ok(())
+    }
+
+    fn set_color_grading(&self, lut: Option<&str>, duration: f32) -> Result<(), Error> {
+        let lut = match lut {
+            Some(name) => Some(grading::load_lut(
+                &mut self.texture_registry.write().recover(),
+                name,
+            )?),
+            None => None,
+        };
+        self.scene
+            .write()
+            .recover()
+            .color_grading
+            .set_lut(lut, duration);
+        Ok(())
+    }
+
+    fn stop_cutscene(&mut self) {
+        if let Some(player) = self.cutscene.take() {
+            self.scene
+                .write()
+                .recover()
+                .camera_effects
+                .set_letterbox(0.0);
+            self.event_proxy
+                .send_event(GameEvent::CutsceneFinished(player.name().to_owned()))
+                .ok();
+        }
+    }
+
+    // Returns false when no cutscene is playing
+    fn update_cutscene(&mut self, delta: f32) -> bool {
+        let player = match self.cutscene.as_mut() {
+            Some(player) => player,
+            None => return false,
+        };
+
+        for name in player.advance(delta) {
+            self.event_proxy
+                .send_event(GameEvent::Signal { name, entity: None })
+                .ok();
+        }
+        if let Some((position, direction)) = player.camera() {
+            let mut scene = self.scene.write().recover();
+            scene.camera.set_position(position);
+            scene.camera.set_direction(&direction);
+        }
+
+        if player.is_finished() {
+            self.stop_cutscene();
+        }
+        true
+    }
+
+    fn apply_damage(&mut self, target: EntityId, amount: f32, source: Option<EntityId>) {
+        let mut scene = self.scene.write().recover();
+        let stats = match scene
+            .get_mut(target)
+            .and_then(|entity| entity.components_mut().get_mut::<Stats>())
+        {
+            Some(stats) => stats,
+            None => return,
+        };
+
+        if stats.apply_damage(amount).is_some() && stats.is_dead() {
+            self.event_proxy
+                .send_event(GameEvent::Died {
+                    entity: target,
+                    source,
+                })
+                .ok();
+        }
+    }
+
+    fn update_spawners(&mut self, delta: f32) -> Result<(), Error> {
+        let (requests, events) = self
+            .spawner_system
+            .update(&mut self.scene.write().recover(), delta);
+        for event in events {
+            self.event_proxy.send_event(event).ok();
+        }
+        if requests.is_empty() {
+            return Ok(());
+        }
+
+        let mut materials = self.material_registry.write().recover();
+        let mut models = self.model_registry.write().recover();
+        let mut textures = self.texture_registry.write().recover();
+        let mut scene = self.scene.write().recover();
+        for request in requests {
+            let entity = request.instantiate(&mut materials, &mut models, &mut textures)?;
+            scene.add(entity);
+        }
+        Ok(())
+    }
+
+    fn fixed_tick(&mut self, delta: f64) -> Result<(), Error> {
+        TimerManager::update(&self.timers, delta);
+
+        let mut scene = self.scene.write().recover();
+        self.navigation_system
+            .update(scene.entities_mut(), delta as f32);
+        self.motion_system.update(&mut scene, delta as f32)?;
+        for event in self.projectile_system.update(&mut scene, delta as f32)? {
+            self.event_proxy.send_event(event).ok();
+        }
+
+        let mut events = vec![];
+        #[cfg(feature = "physics")]
+        {
+            events.extend(self.collision_system.update(&scene));
+            events.extend(self.trigger_system.update(&scene));
+        }
+        let interacting = self.input_state.interact.load(Ordering::Acquire);
+        events.extend(self.pickup_system.update(&mut scene, interacting));
+        for event in events {
+            self.event_proxy.send_event(event).ok();
+        }
+
+        Ok(())
+    }
+
+    // Mouse look and free movement
+    fn update_camera(&mut self, delta: f64) {
+        let want_forward = i32::from(self.input_state.forward.load(Ordering::Acquire))
+            - i32::from(self.input_state.back.load(Ordering::Acquire));
+        let want_side = i32::from(self.input_state.right.load(Ordering::Acquire))
+            - i32::from(self.input_state.left.load(Ordering::Acquire));
+        let want_vertical = i32::from(self.input_state.up.load(Ordering::Acquire))
+            - i32::from(self.input_state.down.load(Ordering::Acquire));
+
+        let look_settings = LookSettings::from_preferences(&self.preferences.lock().recover());
+        let look =
+            self.mouse_look
+                .apply(self.input_state.take_look(), &look_settings, delta as f32);
+        if look != Vector2::zeros() {
+            let mut scene = self.scene.write().recover();
+            scene.camera.rotate_angles(look.x, look.y);
         }
+
+        if want_forward != 0 || want_side != 0 || want_vertical != 0 {
+            let mut scene = self.scene.write().recover();
+            let real_forward = scene.camera.forward();
+            let real_sideward = scene.camera.sideward();
+            let forward = Vector3::new(real_forward.x, 0.0, real_forward.z) * (want_forward as f32);
+            let sideward = Vector3::new(real_sideward.x, 0.0, real_sideward.z) * (want_side as f32);
+            let vertical = Vector3::new(0.0, want_vertical as f32, 0.0);
+            let delta = (forward + sideward + vertical).normalize() * (delta as f32) * 2.0;
+
+            scene.camera.translate(delta);
+        }
+    }
+
+    // Entities using the changed presets get their materials rebuilt
+    fn reload_material_presets(&self) -> Result<(), Error> {
+        let mut materials = self.material_registry.write().recover();
+        let changed = materials.presets_mut().reload_changed();
+        if changed.is_empty() {
+            return Ok(());
+        }
+
+        let mut textures = self.texture_registry.write().recover();
+        let mut scene = self.scene.write().recover();
+        for entity in scene.entities_mut() {
+            let preset_ref = match entity.components().get::<MaterialPresetRef>() {
+                Some(preset_ref) if changed.contains(&preset_ref.preset) => preset_ref,
+                _ => continue,
+            };
+            let preset = match materials.presets().get(&preset_ref.preset) {
+                Some(preset) => preset,
+                None => continue,
+            };
+
+            let mut create_info = preset.create_info(&mut textures)?;
+            create_info.merge(&preset_ref.overrides);
+            if let Err(err) = entity.mesh_mut().update_material(create_info) {
+                log::error!("Failed to update material: {}", err);
+            }
+        }
+
+        Ok(())
     }
 
-    pub fn test_event(&self) -> Result<(), Error> {
-        let mut materials = self.material_registry.lock().unwrap();
-        let mut models = self.model_registry.lock().unwrap();
-        let mut textures = self.texture_registry.lock().unwrap();
-        let mut scene = self.scene.lock().unwrap();
+    pub fn test_event(&mut self) -> Result<(), Error> {
+        let mut materials = self.material_registry.write().recover();
+        let mut models = self.model_registry.write().recover();
+        let mut textures = self.texture_registry.write().recover();
+        let mut scene = self.scene.write().recover();
 
-        let position = random_point() * 4.0;
-        let model_type = rand::random();
-        let texture_type = rand::random();
+        let position = random_point(&mut self.rng) * 4.0;
+        let model_type = self.rng.gen();
+        let texture_type = self.rng.gen();
 
-        let material = materials.get_or_load("simple").unwrap();
+        let material = materials.get_or_load("simple")?;
         let texture = if texture_type {
             textures.get_or_load("texture0")?
         } else {
@@ -78,6 +369,58 @@ impl LogicLayer {
 
         Ok(())
     }
+
+    // Models are placed in front of the camera, images are opened as textures
+    fn open_dropped_file(&self, path: &Path) -> Result<(), Error> {
+        let name = match path.file_stem().and_then(|stem| stem.to_str()) {
+            Some(name) => name,
+            None => return Ok(()),
+        };
+        let extension = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(str::to_ascii_lowercase);
+
+        match extension.as_deref() {
+            Some("obj") => {
+                let mut materials = self.material_registry.write().recover();
+                let mut models = self.model_registry.write().recover();
+                let mut scene = self.scene.write().recover();
+
+                let material = materials.get_or_load_variant(
+                    "simple",
+                    &ShaderVariant::default().with_value(ShaderVariant::HAS_DIFFUSE_MAP, 0),
+                )?;
+                models.load_from_path(name, path, material.clone())?;
+                let mesh = models.create_mesh_object(
+                    name,
+                    material,
+                    MaterialInstanceCreateInfo::default().with_color("diffuse_color", [1.0; 4]),
+                )?;
+
+                let position =
+                    *scene.camera.position() + scene.camera.forward() * DROPPED_MODEL_DISTANCE;
+                scene.add(Entity::new_with_mesh(position, mesh)?);
+            }
+            Some("gltf" | "glb") => {
+                log::warn!("glTF models are not supported yet: {:?}", path);
+            }
+            Some("png" | "jpg" | "jpeg" | "bmp" | "tga") => {
+                self.texture_registry
+                    .write()
+                    .recover()
+                    .load_from_path(name, path)?;
+                self.event_proxy
+                    .send_event(GameEvent::TextureOpened(name.to_owned()))
+                    .ok();
+            }
+            _ => {
+                log::warn!("Don't know how to open {:?}", path);
+            }
+        }
+
+        Ok(())
+    }
 }
 
 impl Layer for LogicLayer {
@@ -93,37 +436,119 @@ impl Layer for LogicLayer {
         Ok(in_future)
     }
 
-    fn on_tick(&mut self, delta: f64) -> Result<(), Error> {
-        let want_forward = i32::from(self.input_state.forward.load(Ordering::Acquire))
-            - i32::from(self.input_state.back.load(Ordering::Acquire));
-        let want_side = i32::from(self.input_state.right.load(Ordering::Acquire))
-            - i32::from(self.input_state.left.load(Ordering::Acquire));
-        let want_vertical = i32::from(self.input_state.up.load(Ordering::Acquire))
-            - i32::from(self.input_state.down.load(Ordering::Acquire));
+    fn on_tick(&mut self, time: &Time) -> Result<(), Error> {
+        let delta = time.delta();
+        if self.update_cutscene(delta as f32) {
+            // Input still has to be drained so it doesn't pile up until the cutscene ends
+            self.input_state.take_look();
+        } else {
+            // The free camera isn't part of the simulation, it keeps its speed in slow motion
+            self.update_camera(time.real_delta());
+        }
 
-        if want_forward != 0 || want_side != 0 || want_vertical != 0 {
-            let mut scene = self.scene.lock().unwrap();
-            let real_forward = scene.camera.forward();
-            let real_sideward = scene.camera.sideward();
-            let forward = Vector3::new(real_forward.x, 0.0, real_forward.z) * (want_forward as f32);
-            let sideward = Vector3::new(real_sideward.x, 0.0, real_sideward.z) * (want_side as f32);
-            let vertical = Vector3::new(0.0, want_vertical as f32, 0.0);
-            let delta = (forward + sideward + vertical).normalize() * (delta as f32) * 2.0;
+        {
+            let mut scene = self.scene.write().recover();
+            let focus = *scene.camera.position();
+            for event in self.streaming_system.update(&mut scene, &focus) {
+                self.event_proxy.send_event(event).ok();
+            }
+            for event in self.scheduler.run(&mut scene, delta as f32)? {
+                self.event_proxy.send_event(event).ok();
+            }
+            // Not while a cutscene has the camera
+            let pressed =
+                self.cutscene.is_none() && self.input_state.interact.load(Ordering::Acquire);
+            for event in self.interaction_system.update(&scene, pressed) {
+                self.event_proxy.send_event(event).ok();
+            }
+        }
 
-            scene.camera.translate(delta);
+        self.update_spawners(delta as f32)?;
+
+        // Tasks lock whatever they need themselves
+        self.task_executor.update(time.total())?;
+
+        {
+            let mut materials = self.material_registry.write().recover();
+            let models = self.model_registry.read().recover();
+            let mut scene = self.scene.write().recover();
+            self.voxel_system
+                .update(&mut scene, &mut materials, &models)?;
+        }
+
+        self.preset_poll_accumulator += time.real_delta();
+        if self.preset_poll_accumulator >= PRESET_POLL_INTERVAL {
+            self.preset_poll_accumulator = 0.0;
+            self.reload_material_presets()?;
+        }
+
+        for _ in 0..time.fixed_steps() {
+            self.fixed_tick(time.fixed_delta())?;
         }
 
         Ok(())
     }
 
     fn on_event(&mut self, event: &Event, _flow: &mut ControlFlow) -> Result<bool, Error> {
-        if let Event::MouseMotion(delta) = event {
-            let mut scene = self.scene.lock().unwrap();
-            scene
-                .camera
-                .rotate_angles(-delta.1 as f32 * 0.02, delta.0 as f32 * 0.02);
+        if let Event::GameEvent(event) = event {
+            self.task_executor.dispatch(event);
+        }
+        if let Event::FileDropped(path) = event {
+            self.open_dropped_file(path)?;
             return Ok(true);
         }
+        match event {
+            Event::GameEvent(GameEvent::PlayCutscene(name)) => {
+                self.play_cutscene(name)?;
+                return Ok(true);
+            }
+            Event::GameEvent(GameEvent::StopCutscene) => {
+                self.stop_cutscene();
+                return Ok(true);
+            }
+            Event::GameEvent(GameEvent::SetColorGrading { lut, duration }) => {
+                self.set_color_grading(lut.as_deref(), *duration)?;
+                return Ok(true);
+            }
+            Event::GameEvent(GameEvent::SetColorGradingIntensity(intensity)) => {
+                self.scene
+                    .write()
+                    .recover()
+                    .color_grading
+                    .set_intensity(*intensity);
+                return Ok(true);
+            }
+            Event::GameEvent(GameEvent::FireHitscan { ray, shot }) => {
+                let events = shot.fire(&self.scene.read().recover(), ray);
+                for event in events {
+                    self.event_proxy.send_event(event).ok();
+                }
+                return Ok(true);
+            }
+            Event::GameEvent(GameEvent::Signal { name, .. }) => {
+                SpawnerSystem::signal(&mut self.scene.write().recover(), name);
+            }
+            // Not consumed, the AI layer reacts to it as well
+            Event::GameEvent(GameEvent::Damage {
+                target,
+                amount,
+                source,
+            }) => self.apply_damage(*target, *amount, *source),
+            // Entity IDs of the previous scene mean nothing in the new one
+            Event::GameEvent(GameEvent::SceneSwitched(_)) => {
+                self.stop_cutscene();
+                #[cfg(feature = "physics")]
+                {
+                    self.collision_system.clear();
+                    self.trigger_system.clear();
+                }
+                if let Some(event) = self.interaction_system.clear() {
+                    self.event_proxy.send_event(event).ok();
+                }
+                self.streaming_system.clear();
+            }
+            _ => (),
+        }
         if let Event::GameEvent(GameEvent::TestEvent) = event {
             self.test_event()?;
             Ok(true)
@@ -131,11 +556,15 @@ impl Layer for LogicLayer {
             Ok(false)
         }
     }
+
+    fn freezes_on_pause(&self) -> bool {
+        true
+    }
 }
 
-fn random_point() -> Point3<f32> {
-    let x = (rand::random::<f32>() - 0.5) * 2.0;
-    let y = (rand::random::<f32>() - 0.5) * 2.0;
-    let z = (rand::random::<f32>() - 0.5) * 2.0;
+fn random_point<R: Rng>(rng: &mut R) -> Point3<f32> {
+    let x = rng.gen_range(-1.0..1.0);
+    let y = rng.gen_range(-1.0..1.0);
+    let z = rng.gen_range(-1.0..1.0);
     Point3::new(x, y, z)
 }
@@ -0,0 +1,143 @@
+use std::sync::{Arc, Mutex};
+
+use egui_winit_vulkano::{egui, Gui};
+use vulkano::{device::Queue, swapchain::Surface, sync::GpuFuture};
+use winit::{
+    event::{ElementState, VirtualKeyCode, WindowEvent},
+    event_loop::{ControlFlow, EventLoopProxy},
+    window::Window,
+};
+
+use crate::{
+    error::Error,
+    event::{Event, GameEvent},
+    i18n::Localization,
+    lock::Recover,
+    render::frame::Frame,
+    state::GameState,
+    time::Time,
+};
+
+use super::{priority, Layer};
+
+// Modal menu shown on top of everything in MainMenu/Paused states, swallows window input
+pub struct MenuLayer {
+    inner: Gui,
+    event_proxy: EventLoopProxy<GameEvent>,
+    state: GameState,
+    localization: Arc<Mutex<Localization>>,
+}
+
+impl MenuLayer {
+    pub fn new(
+        event_proxy: EventLoopProxy<GameEvent>,
+        surface: Arc<Surface<Window>>,
+        gfx_queue: Arc<Queue>,
+        state: GameState,
+        localization: Arc<Mutex<Localization>>,
+    ) -> Self {
+        let inner = Gui::new(surface, None, gfx_queue, true);
+        Self {
+            inner,
+            event_proxy,
+            state,
+            localization,
+        }
+    }
+
+    fn send(&self, event: GameEvent) {
+        self.event_proxy.send_event(event).ok();
+    }
+}
+
+impl Layer for MenuLayer {
+    fn on_attach(&mut self) {}
+
+    fn on_detach(&mut self) {}
+
+    fn draw_priority(&self) -> i32 {
+        priority::OVERLAY
+    }
+
+    fn on_tick(&mut self, _time: &Time) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn on_event(&mut self, event: &Event, _flow: &mut ControlFlow) -> Result<bool, Error> {
+        match event {
+            Event::GameEvent(GameEvent::GameStateChanged(state)) => {
+                self.state = *state;
+                Ok(false)
+            }
+            Event::WindowEventWrapped(event) => {
+                if let WindowEvent::KeyboardInput { input, .. } = event {
+                    if input.state == ElementState::Pressed
+                        && input.virtual_keycode == Some(VirtualKeyCode::Escape)
+                        && self.state == GameState::Paused
+                    {
+                        self.send(GameEvent::PopGameState);
+                        return Ok(true);
+                    }
+                }
+                self.inner.update(event);
+                Ok(true)
+            }
+            Event::MouseMotion(_) => Ok(true),
+            _ => Ok(false),
+        }
+    }
+
+    fn on_draw(
+        &mut self,
+        in_future: Box<dyn GpuFuture>,
+        frame: &Frame,
+    ) -> Result<Box<dyn GpuFuture>, Error> {
+        let mut clicked = None;
+        let state = self.state;
+        let i18n = self.localization.lock().recover();
+
+        self.inner.immediate_ui(|gui| {
+            let ctx = gui.context();
+            let title = match state {
+                GameState::MainMenu => i18n.get("menu.main_menu"),
+                _ => i18n.get("menu.paused"),
+            };
+
+            egui::Window::new(title)
+                .collapsible(false)
+                .resizable(false)
+                .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+                .show(&ctx, |ui| {
+                    ui.vertical_centered_justified(|ui| match state {
+                        GameState::MainMenu => {
+                            if ui.button(i18n.get("menu.play")).clicked() {
+                                clicked = Some(GameEvent::SetGameState(GameState::Playing));
+                            }
+                            if ui.button(i18n.get("menu.quit")).clicked() {
+                                clicked = Some(GameEvent::Quit);
+                            }
+                        }
+                        _ => {
+                            if ui.button(i18n.get("menu.resume")).clicked() {
+                                clicked = Some(GameEvent::PopGameState);
+                            }
+                            if ui.button(i18n.get("menu.main_menu")).clicked() {
+                                clicked = Some(GameEvent::SetGameState(GameState::MainMenu));
+                            }
+                            if ui.button(i18n.get("menu.quit")).clicked() {
+                                clicked = Some(GameEvent::Quit);
+                            }
+                        }
+                    });
+                });
+        });
+
+        if let Some(event) = clicked {
+            self.send(event);
+        }
+
+        Ok(self
+            .inner
+            .draw_on_image(in_future, frame.destination.clone()))
+    }
+}
@@ -1,55 +1,475 @@
-use vulkano::sync::GpuFuture;
+use std::{
+    any::Any,
+    cmp::Reverse,
+    panic::{self, AssertUnwindSafe},
+    sync::{Arc, Mutex},
+};
+
+use vulkano::sync::{self, GpuFuture};
 use winit::event_loop::ControlFlow;
 
-use crate::{error::Error, event::Event, render::frame::Frame};
+use crate::{lock::Recover, error::Error, event::Event, render::frame::Frame, time::Time};
+
+use self::event_log::{self as events, EventLog};
 
+pub mod ai;
+pub mod event_log;
+#[cfg(feature = "gui")]
 pub mod gui;
 pub mod input;
+#[cfg(feature = "gui")]
+pub mod loading;
 pub mod logic;
+#[cfg(feature = "gui")]
+pub mod menu;
 pub mod world;
 
+// Layers are drawn in ascending and get events in descending priority. Equal ones are drawn
+// in push order and get events in reverse push order
+pub mod priority {
+    pub const DEFAULT: i32 = 0;
+    pub const GUI: i32 = 100;
+    // Menus and the loading screen, drawn over the GUI
+    pub const OVERLAY: i32 = 200;
+    // Reads the finished frame back, e.g. for screenshots
+    pub const CAPTURE: i32 = 1000;
+}
+
+// What happens when a layer panics in on_tick/on_event/on_draw
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PanicPolicy {
+    // The layer is skipped from then on
+    Disable,
+    // The layer is disabled and the panic is returned as an error
+    Report,
+    // The panic unwinds through the main loop
+    Propagate,
+}
+
+// Returned by LayerManager::push, stays with the layer when the order changes
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct LayerId(u64);
+
+struct LayerSlot {
+    id: LayerId,
+    layer: Box<dyn Layer>,
+    // Turned off by the user, see LayerManager::set_enabled
+    enabled: bool,
+    panicked: bool,
+    consumed: u64,
+    last_consumed: Option<String>,
+}
+
+// Snapshot of a layer for the layers panel, refreshed every frame
+#[derive(Clone, Debug)]
+pub struct LayerStatus {
+    pub name: String,
+    pub enabled: bool,
+    pub panicked: bool,
+    pub draw_priority: i32,
+    pub event_priority: i32,
+    // Number of events the layer returned true for and the kind of the last one
+    pub consumed: u64,
+    pub last_consumed: Option<String>,
+}
+
 #[derive(Default)]
 pub struct LayerManager {
-    layers: Vec<Box<dyn Layer>>,
+    layers: Vec<LayerSlot>,
+    last_id: u64,
+    frozen: bool,
+    panic_policy: PanicPolicy,
+    event_log: Arc<Mutex<EventLog>>,
+    status: Arc<Mutex<Vec<LayerStatus>>>,
+    // Indices into layers, kept sorted by priority
+    draw_order: Vec<usize>,
+    event_order: Vec<usize>,
 }
 
 pub trait Layer {
     fn on_attach(&mut self);
     fn on_detach(&mut self);
     fn on_event(&mut self, event: &Event, flow: &mut ControlFlow) -> Result<bool, Error>;
-    fn on_tick(&mut self, delta: f64) -> Result<(), Error>;
+    fn on_tick(&mut self, time: &Time) -> Result<(), Error>;
     fn on_draw(
         &mut self,
         in_future: Box<dyn GpuFuture>,
         frame: &Frame,
     ) -> Result<Box<dyn GpuFuture>, Error>;
+
+    // Simulation layers are not ticked while the game is paused
+    fn freezes_on_pause(&self) -> bool {
+        false
+    }
+
+    // See the priority module, only read when the layer order changes
+    fn draw_priority(&self) -> i32 {
+        priority::DEFAULT
+    }
+
+    // The layer drawn on top gets the events first unless overridden
+    fn event_priority(&self) -> i32 {
+        self.draw_priority()
+    }
+
+    fn name(&self) -> &str {
+        std::any::type_name::<Self>()
+    }
+}
+
+impl Default for PanicPolicy {
+    fn default() -> Self {
+        Self::Report
+    }
+}
+
+pub(crate) fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        (*message).to_owned()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown panic".to_owned()
+    }
+}
+
+impl LayerSlot {
+    #[inline]
+    fn is_active(&self) -> bool {
+        self.enabled && !self.panicked
+    }
+
+    // Returns Ok(None) if the layer panicked and got disabled. Locks it held stay poisoned,
+    // the engine takes them with lock::Recover so the other layers keep running
+    fn call<R, F>(&mut self, policy: PanicPolicy, f: F) -> Result<Option<R>, Error>
+    where
+        F: FnOnce(&mut dyn Layer) -> Result<R, Error>,
+    {
+        let layer = &mut *self.layer;
+        match panic::catch_unwind(AssertUnwindSafe(|| f(layer))) {
+            Ok(result) => result.map(Some),
+            Err(payload) if policy == PanicPolicy::Propagate => panic::resume_unwind(payload),
+            Err(payload) => {
+                let layer = self.layer.name().to_owned();
+                let message = panic_message(payload.as_ref());
+                log::error!("Layer {} panicked and is disabled: {}", layer, message);
+                self.panicked = true;
+
+                if policy == PanicPolicy::Report {
+                    Err(Error::LayerPanic { layer, message })
+                } else {
+                    Ok(None)
+                }
+            }
+        }
+    }
 }
 
 impl LayerManager {
     pub fn iter(&self) -> impl Iterator<Item = &Box<dyn Layer>> {
-        self.layers.iter()
+        self.layers.iter().map(|slot| &slot.layer)
     }
     pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut Box<dyn Layer>> {
-        self.layers.iter_mut()
+        self.layers.iter_mut().map(|slot| &mut slot.layer)
     }
 
-    pub fn tick(&mut self, delta: f64) -> Result<(), Error> {
-        for layer in self.layers.iter_mut() {
-            layer.on_tick(delta).unwrap();
+    pub fn tick(&mut self, time: &Time) -> Result<(), Error> {
+        for slot in self.layers.iter_mut() {
+            if !slot.is_active() || (self.frozen && slot.layer.freezes_on_pause()) {
+                continue;
+            }
+            let _span = tracing::info_span!("tick", layer = slot.layer.name()).entered();
+            slot.call(self.panic_policy, |layer| layer.on_tick(time))?;
         }
         Ok(())
     }
 
     pub fn notify_all(&mut self, event: &Event, flow: &mut ControlFlow) -> Result<(), Error> {
-        for layer in self.layers.iter_mut().rev() {
-            if layer.on_event(event, flow)? {
+        let mut consumer = None;
+        for &index in &self.event_order {
+            let slot = &mut self.layers[index];
+            if !slot.is_active() {
+                continue;
+            }
+            if slot.call(self.panic_policy, |layer| layer.on_event(event, flow))? == Some(true) {
+                slot.consumed += 1;
+                slot.last_consumed = Some(events::kind(event));
+                consumer = Some(index);
                 break;
             }
         }
+        let consumer = consumer.map(|index| self.layers[index].layer.name());
+        self.event_log.lock().recover().record(event, consumer);
         Ok(())
     }
 
-    pub fn push(&mut self, layer: Box<dyn Layer>) {
-        self.layers.push(layer);
+    pub fn draw(
+        &mut self,
+        mut in_future: Box<dyn GpuFuture>,
+        frame: &Frame,
+    ) -> Result<Box<dyn GpuFuture>, Error> {
+        self.event_log.lock().recover().next_frame();
+        *self.status.lock().recover() = self.snapshot();
+        for &index in &self.draw_order {
+            let slot = &mut self.layers[index];
+            if !slot.is_active() {
+                continue;
+            }
+            let _span = tracing::info_span!("draw", layer = slot.layer.name()).entered();
+            // The future is lost along with the panicking layer, the rest of the frame
+            // is drawn without waiting for it
+            in_future =
+                match slot.call(self.panic_policy, |layer| layer.on_draw(in_future, frame))? {
+                    Some(future) => future,
+                    None => Box::new(sync::now(frame.gfx_queue.device().clone())),
+                };
+        }
+        Ok(in_future)
+    }
+
+    pub fn push(&mut self, mut layer: Box<dyn Layer>) -> LayerId {
+        self.last_id += 1;
+        let id = LayerId(self.last_id);
+        layer.on_attach();
+        self.layers.push(LayerSlot {
+            id,
+            layer,
+            enabled: true,
+            panicked: false,
+            consumed: 0,
+            last_consumed: None,
+        });
+        self.sort();
+        id
+    }
+
+    // Removes the last layer in push order, regardless of its priority. That's not the last
+    // pushed one once move_to was used, layers which are removed later should be by id
+    pub fn pop(&mut self) -> Option<Box<dyn Layer>> {
+        let mut slot = self.layers.pop()?;
+        self.sort();
+        slot.layer.on_detach();
+        Some(slot.layer)
+    }
+
+    pub fn remove(&mut self, id: LayerId) -> Option<Box<dyn Layer>> {
+        let index = self.layers.iter().position(|slot| slot.id == id)?;
+        let mut slot = self.layers.remove(index);
+        self.sort();
+        slot.layer.on_detach();
+        Some(slot.layer)
+    }
+
+    // Sorts are stable, so equal priorities keep the push order
+    fn sort(&mut self) {
+        let mut draw_order: Vec<_> = (0..self.layers.len()).collect();
+        draw_order.sort_by_key(|&index| self.layers[index].layer.draw_priority());
+        let mut event_order: Vec<_> = (0..self.layers.len()).rev().collect();
+        event_order.sort_by_key(|&index| Reverse(self.layers[index].layer.event_priority()));
+        self.draw_order = draw_order;
+        self.event_order = event_order;
+    }
+
+    pub fn set_frozen(&mut self, frozen: bool) {
+        self.frozen = frozen;
+    }
+
+    pub fn set_panic_policy(&mut self, policy: PanicPolicy) {
+        self.panic_policy = policy;
+    }
+
+    #[inline]
+    pub const fn event_log(&self) -> &Arc<Mutex<EventLog>> {
+        &self.event_log
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.layers.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.layers.is_empty()
+    }
+
+    fn position(&self, name: &str) -> Option<usize> {
+        self.layers
+            .iter()
+            .position(|slot| slot.layer.name() == name)
+    }
+
+    // Disabled layers are skipped by tick, draw and event dispatch but stay attached. Returns
+    // false if there's no such layer
+    pub fn set_enabled(&mut self, name: &str, enabled: bool) -> bool {
+        match self.position(name) {
+            Some(index) => {
+                log::info!(
+                    "Layer {} {}",
+                    name,
+                    if enabled { "enabled" } else { "disabled" }
+                );
+                self.layers[index].enabled = enabled;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn is_enabled(&self, name: &str) -> Option<bool> {
+        self.position(name).map(|index| self.layers[index].enabled)
+    }
+
+    // Changes the push order, which only matters between layers of equal priority
+    pub fn move_to(&mut self, name: &str, index: usize) -> bool {
+        match self.position(name) {
+            Some(from) => {
+                let slot = self.layers.remove(from);
+                let index = index.min(self.layers.len());
+                self.layers.insert(index, slot);
+                self.sort();
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn snapshot(&self) -> Vec<LayerStatus> {
+        self.layers
+            .iter()
+            .map(|slot| LayerStatus {
+                name: slot.layer.name().to_owned(),
+                enabled: slot.enabled,
+                panicked: slot.panicked,
+                draw_priority: slot.layer.draw_priority(),
+                event_priority: slot.layer.event_priority(),
+                consumed: slot.consumed,
+                last_consumed: slot.last_consumed.clone(),
+            })
+            .collect()
+    }
+
+    // Updated every frame, for the GUI
+    #[inline]
+    pub const fn status(&self) -> &Arc<Mutex<Vec<LayerStatus>>> {
+        &self.status
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use super::*;
+
+    struct Dummy {
+        name: &'static str,
+        draw_priority: i32,
+        event_priority: Option<i32>,
+        detached: Rc<RefCell<Vec<&'static str>>>,
+    }
+
+    impl Layer for Dummy {
+        fn on_attach(&mut self) {}
+
+        fn on_detach(&mut self) {
+            self.detached.borrow_mut().push(self.name);
+        }
+
+        fn on_event(&mut self, _event: &Event, _flow: &mut ControlFlow) -> Result<bool, Error> {
+            Ok(false)
+        }
+
+        fn on_tick(&mut self, _time: &Time) -> Result<(), Error> {
+            Ok(())
+        }
+
+        fn on_draw(
+            &mut self,
+            in_future: Box<dyn GpuFuture>,
+            _frame: &Frame,
+        ) -> Result<Box<dyn GpuFuture>, Error> {
+            Ok(in_future)
+        }
+
+        fn draw_priority(&self) -> i32 {
+            self.draw_priority
+        }
+
+        fn event_priority(&self) -> i32 {
+            self.event_priority.unwrap_or(self.draw_priority)
+        }
+
+        fn name(&self) -> &str {
+            self.name
+        }
+    }
+
+    fn push(
+        manager: &mut LayerManager,
+        detached: &Rc<RefCell<Vec<&'static str>>>,
+        name: &'static str,
+        draw_priority: i32,
+        event_priority: Option<i32>,
+    ) -> LayerId {
+        manager.push(Box::new(Dummy {
+            name,
+            draw_priority,
+            event_priority,
+            detached: detached.clone(),
+        }))
+    }
+
+    fn names(manager: &LayerManager, order: &[usize]) -> Vec<String> {
+        order
+            .iter()
+            .map(|&index| manager.layers[index].layer.name().to_owned())
+            .collect()
+    }
+
+    #[test]
+    fn layers_are_ordered_by_priority() {
+        let detached = Rc::default();
+        let mut manager = LayerManager::default();
+        push(&mut manager, &detached, "gui", priority::GUI, None);
+        push(&mut manager, &detached, "world", priority::DEFAULT, None);
+        push(&mut manager, &detached, "logic", priority::DEFAULT, None);
+        push(&mut manager, &detached, "input", priority::DEFAULT, Some(priority::OVERLAY));
+
+        assert_eq!(
+            names(&manager, &manager.draw_order),
+            ["world", "logic", "input", "gui"]
+        );
+        assert_eq!(
+            names(&manager, &manager.event_order),
+            ["input", "gui", "logic", "world"]
+        );
+
+        // Only changes the order between equal priorities
+        assert!(manager.move_to("logic", 0));
+        assert_eq!(
+            names(&manager, &manager.draw_order),
+            ["logic", "world", "input", "gui"]
+        );
+    }
+
+    #[test]
+    fn remove_by_id() {
+        let detached = Rc::default();
+        let mut manager = LayerManager::default();
+        let world = push(&mut manager, &detached, "world", priority::DEFAULT, None);
+        let gui = push(&mut manager, &detached, "gui", priority::GUI, None);
+        push(&mut manager, &detached, "logic", priority::DEFAULT, None);
+        manager.move_to("gui", 2);
+
+        let removed = manager.remove(gui).unwrap();
+        assert_eq!(removed.name(), "gui");
+        assert_eq!(*detached.borrow(), ["gui"]);
+        assert!(manager.remove(gui).is_none());
+        assert_eq!(names(&manager, &manager.draw_order), ["world", "logic"]);
+        assert_eq!(names(&manager, &manager.event_order), ["logic", "world"]);
+
+        assert_eq!(manager.remove(world).unwrap().name(), "world");
+        assert_eq!(manager.len(), 1);
     }
 }
@@ -1,71 +1,102 @@
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, Mutex, RwLock};
 
-use nalgebra::{Matrix4, Vector3};
+use bytemuck::Zeroable;
+use nalgebra::Matrix4;
 use vulkano::{
     buffer::{BufferUsage, CpuAccessibleBuffer},
-    command_buffer::{
-        AutoCommandBufferBuilder, CommandBufferUsage, RenderPassBeginInfo, SubpassContents,
-    },
+    command_buffer::{AutoCommandBufferBuilder, CommandBufferUsage, SubpassContents},
     descriptor_set::{
         layout::{DescriptorSetLayout, DescriptorSetLayoutCreateInfo},
         PersistentDescriptorSet, WriteDescriptorSet,
     },
-    device::{Device, Queue},
-    format::{ClearValue, Format},
-    image::{view::ImageView, AttachmentImage, ImageViewAbstract, SampleCount, SwapchainImage},
+    device::Queue,
     pipeline::{graphics::viewport::Viewport, layout::PipelineLayoutCreateInfo, PipelineLayout},
-    render_pass::{Framebuffer, FramebufferCreateInfo, RenderPass, Subpass},
     sync::GpuFuture,
 };
-use winit::{dpi::PhysicalSize, event_loop::ControlFlow, window::Window};
+use winit::{dpi::PhysicalSize, event_loop::ControlFlow};
 
 use crate::{
     error::Error,
-    event::Event,
+    event::{Event, GameEvent},
     layer::Layer,
+    lock::Recover,
+    preferences::Preferences,
     render::{
+        arena::UniformArena,
+        aspect::AspectLock,
+        cluster::{LightClusters, CLUSTER_COUNT, MAX_CLUSTER_INDICES},
+        color::OutputSettings,
+        debug::DebugViewSettings,
+        extract::RenderScene,
         frame::Frame,
+        grading::ColorGradingSettings,
+        graph::{Pass, RenderGraph, COLOR_ATTACHMENT},
+        memory::{AllocationCategory, GpuAllocation},
         shader,
-        system::{forward::ForwardSystem, screen::ScreenSystem},
+        shadow::{LightSelection, ShadowSettings, MAX_LOCAL_LIGHTS},
+        shafts::LightShaftSettings,
+        stats::{self, Stats},
+        system::{
+            forward::ForwardSystem, grid::GridSystem, indirect::IndirectDrawSettings,
+            screen::ScreenSystem, shadow::ShadowSystem,
+        },
+        uniforms::{CameraUniform, LightUniform, LocalLightUniform, ModelUniform},
     },
     resource::material::MaterialRegistry,
+    time::Time,
     world::scene::Scene,
 };
 
-type FramebufferCreateOutput = (
-    Vec<Arc<Framebuffer>>,
-    Arc<ImageView<AttachmentImage>>,
-    Arc<ImageView<AttachmentImage>>,
-);
+// One per swapchain image, so a frame never writes uniforms another one is still reading
+struct FrameUniforms {
+    camera: Arc<CpuAccessibleBuffer<CameraUniform>>,
+    light: Arc<CpuAccessibleBuffer<LightUniform>>,
+    // Sized for the most there can be, only the start is written each frame
+    local_lights: Arc<CpuAccessibleBuffer<[LocalLightUniform]>>,
+    clusters: Arc<CpuAccessibleBuffer<[[u32; 2]]>>,
+    cluster_indices: Arc<CpuAccessibleBuffer<[u32]>>,
+    set: Arc<PersistentDescriptorSet>,
+}
 
 pub struct WorldLayer {
     gfx_queue: Arc<Queue>,
-    scene: Arc<Mutex<Scene>>,
-    scene_buffer: Arc<CpuAccessibleBuffer<shader::simple_vs::ty::Scene_Data>>,
-    scene_set: Arc<PersistentDescriptorSet>,
-
-    material_registry: Arc<Mutex<MaterialRegistry>>,
-    render_pass: Arc<RenderPass>,
-
-    framebuffers: Vec<Arc<Framebuffer>>,
-    color_view: Arc<ImageView<AttachmentImage>>,
-    depth_view: Arc<ImageView<AttachmentImage>>,
-
+    scene: Arc<RwLock<Scene>>,
+    // Extracted at the start of each frame, the scene isn't locked while recording
+    render_scene: RenderScene,
+    clusters: LightClusters,
+    stats: Arc<Mutex<Stats>>,
+    preferences: Arc<Mutex<Preferences>>,
+    hdr10: bool,
+    scene_layout: Arc<DescriptorSetLayout>,
+    frame_uniforms: Vec<FrameUniforms>,
+    model_arena: UniformArena<ModelUniform>,
+
+    material_registry: Arc<RwLock<MaterialRegistry>>,
+    graph: RenderGraph,
+    _uniform_memory: GpuAllocation,
+
+    shadow_system: ShadowSystem,
     forward_system: ForwardSystem,
+    grid_system: GridSystem,
     screen_system: ScreenSystem,
 
     dimensions: (f32, f32),
+    time: f64,
 }
 
 impl WorldLayer {
     pub fn new(
         gfx_queue: Arc<Queue>,
-        render_pass: Arc<RenderPass>,
-        material_registry: Arc<Mutex<MaterialRegistry>>,
-        swapchain_images: &Vec<Arc<ImageView<SwapchainImage<Window>>>>,
+        graph: RenderGraph,
+        material_registry: Arc<RwLock<MaterialRegistry>>,
+        model_arena: UniformArena<ModelUniform>,
+        image_count: usize,
         viewport: Viewport,
         dimensions: PhysicalSize<u32>,
-        scene: Arc<Mutex<Scene>>,
+        scene: Arc<RwLock<Scene>>,
+        stats: Arc<Mutex<Stats>>,
+        preferences: Arc<Mutex<Preferences>>,
+        hdr10: bool,
     ) -> Result<Self, Error> {
         // Have to load these in order to access DescriptorRequirements
         let dummy_vs = shader::simple_vs::load(gfx_queue.device().clone())?;
@@ -102,100 +133,130 @@ impl WorldLayer {
             },
         )?;
 
-        let (framebuffers, color_view, depth_view) =
-            Self::create_framebuffers(gfx_queue.device().clone(), &render_pass, swapchain_images)?;
+        let shadow_resolution =
+            ShadowSettings::from_preferences(&preferences.lock().recover()).resolution;
+        let shadow_system = ShadowSystem::new(gfx_queue.clone(), shadow_resolution)?;
+        let forward_system = ForwardSystem::new(gfx_queue.clone(), graph.subpass(Pass::Forward))?;
 
-        let forward_system = ForwardSystem::new(
-            gfx_queue.clone(),
-            Subpass::from(render_pass.clone(), 0).unwrap(),
-            common_pipeline_layout.clone(),
-        )?;
+        let grid_system =
+            GridSystem::new(gfx_queue.clone(), graph.subpass(Pass::Forward), &viewport)?;
 
         let screen_system = ScreenSystem::new(
             gfx_queue.clone(),
-            Subpass::from(render_pass.clone(), 1).unwrap(),
-            color_view.clone(),
+            graph.subpass(Pass::Screen),
+            graph.attachment(COLOR_ATTACHMENT).unwrap().clone(),
             &viewport,
         )?;
 
-        let scene_buffer = unsafe {
-            CpuAccessibleBuffer::uninitialized(
-                gfx_queue.device().clone(),
-                BufferUsage::uniform_buffer(),
-                false,
-            )?
-        };
-
-        let scene_layout = common_pipeline_layout.set_layouts().get(0).unwrap();
-        let scene_set = PersistentDescriptorSet::new(
-            scene_layout.clone(),
-            vec![WriteDescriptorSet::buffer(0, scene_buffer.clone())],
-        )?;
+        let scene_layout = common_pipeline_layout.set_layouts().get(0).unwrap().clone();
+        let (frame_uniforms, uniform_memory) =
+            Self::create_frame_uniforms(&gfx_queue, &scene_layout, &shadow_system, image_count)?;
 
         let dimensions = dimensions.into();
 
         Ok(Self {
             gfx_queue,
             dimensions,
-            scene_buffer,
-            scene_set,
-
-            framebuffers,
-            color_view,
-            depth_view,
+            time: 0.0,
+            scene_layout,
+            frame_uniforms,
+            model_arena,
+            _uniform_memory: uniform_memory,
 
             material_registry,
-            render_pass,
+            graph,
 
+            shadow_system,
             forward_system,
+            grid_system,
             screen_system,
 
             scene,
+            render_scene: RenderScene::default(),
+            clusters: LightClusters::default(),
+            stats,
+            preferences,
+            hdr10,
         })
     }
 
-    fn create_framebuffers(
-        device: Arc<Device>,
-        render_pass: &Arc<RenderPass>,
-        swapchain_images: &Vec<Arc<ImageView<SwapchainImage<Window>>>>,
-    ) -> Result<FramebufferCreateOutput, Error> {
-        let color_view = ImageView::new_default(
-            AttachmentImage::transient_multisampled_input_attachment(
-                device.clone(),
-                swapchain_images[0].dimensions().width_height(),
-                SampleCount::Sample4,
-                swapchain_images[0].format().unwrap(),
-            )
-            .unwrap(),
-        )?;
-        let depth_view = ImageView::new_default(AttachmentImage::transient_multisampled(
-            device,
-            swapchain_images[0].dimensions().width_height(),
-            SampleCount::Sample4,
-            Format::D16_UNORM,
-        )?)?;
-
-        Ok((
-            swapchain_images
-                .into_iter()
-                .map(|image| {
-                    Framebuffer::new(
-                        render_pass.clone(),
-                        FramebufferCreateInfo {
-                            attachments: vec![
-                                color_view.clone(),
-                                depth_view.clone(),
-                                image.clone(),
-                            ],
-                            ..Default::default()
-                        },
-                    )
+    fn create_frame_uniforms(
+        gfx_queue: &Arc<Queue>,
+        scene_layout: &Arc<DescriptorSetLayout>,
+        shadow_system: &ShadowSystem,
+        count: usize,
+    ) -> Result<(Vec<FrameUniforms>, GpuAllocation), Error> {
+        let frames = (0..count)
+            .map(|_| {
+                let camera = unsafe {
+                    CpuAccessibleBuffer::uninitialized(
+                        gfx_queue.device().clone(),
+                        BufferUsage::uniform_buffer(),
+                        false,
+                    )?
+                };
+                let light = unsafe {
+                    CpuAccessibleBuffer::uninitialized(
+                        gfx_queue.device().clone(),
+                        BufferUsage::uniform_buffer(),
+                        false,
+                    )?
+                };
+                let local_lights = CpuAccessibleBuffer::from_iter(
+                    gfx_queue.device().clone(),
+                    BufferUsage::storage_buffer(),
+                    false,
+                    (0..MAX_LOCAL_LIGHTS).map(|_| LocalLightUniform::zeroed()),
+                )?;
+                let clusters = CpuAccessibleBuffer::from_iter(
+                    gfx_queue.device().clone(),
+                    BufferUsage::storage_buffer(),
+                    false,
+                    (0..CLUSTER_COUNT).map(|_| [0, 0]),
+                )?;
+                let cluster_indices = CpuAccessibleBuffer::from_iter(
+                    gfx_queue.device().clone(),
+                    BufferUsage::storage_buffer(),
+                    false,
+                    (0..MAX_CLUSTER_INDICES).map(|_| 0),
+                )?;
+                let set = PersistentDescriptorSet::new(
+                    scene_layout.clone(),
+                    vec![
+                        WriteDescriptorSet::buffer(0, camera.clone()),
+                        WriteDescriptorSet::buffer(1, light.clone()),
+                        WriteDescriptorSet::image_view_sampler(
+                            2,
+                            shadow_system.atlas().clone(),
+                            shadow_system.sampler().clone(),
+                        ),
+                        WriteDescriptorSet::buffer(3, local_lights.clone()),
+                        WriteDescriptorSet::buffer(4, clusters.clone()),
+                        WriteDescriptorSet::buffer(5, cluster_indices.clone()),
+                    ],
+                )?;
+
+                Ok(FrameUniforms {
+                    camera,
+                    light,
+                    local_lights,
+                    clusters,
+                    cluster_indices,
+                    set,
                 })
-                .collect::<Result<_, _>>()
-                .map_err(Error::from)?,
-            color_view,
-            depth_view,
-        ))
+            })
+            .collect::<Result<_, Error>>()?;
+        let bytes_per_frame = std::mem::size_of::<CameraUniform>()
+            + std::mem::size_of::<LightUniform>()
+            + MAX_LOCAL_LIGHTS * std::mem::size_of::<LocalLightUniform>()
+            + CLUSTER_COUNT * std::mem::size_of::<[u32; 2]>()
+            + MAX_CLUSTER_INDICES * std::mem::size_of::<u32>();
+        let memory = GpuAllocation::new(
+            AllocationCategory::Uniform,
+            (count * bytes_per_frame) as u64,
+        );
+
+        Ok((frames, memory))
     }
 }
 
@@ -204,7 +265,11 @@ impl Layer for WorldLayer {
 
     fn on_detach(&mut self) {}
 
-    fn on_tick(&mut self, _delta: f64) -> Result<(), Error> {
+    fn on_tick(&mut self, time: &Time) -> Result<(), Error> {
+        self.time = time.total();
+        let mut scene = self.scene.write().recover();
+        scene.camera_effects.update(time.delta() as f32);
+        scene.color_grading.update(time.delta() as f32);
         Ok(())
     }
 
@@ -216,21 +281,38 @@ impl Layer for WorldLayer {
         } = event
         {
             self.dimensions = (*dimensions).into();
-            (self.framebuffers, self.color_view, self.depth_view) = Self::create_framebuffers(
-                self.gfx_queue.device().clone(),
-                &self.render_pass,
-                swapchain_images,
-            )?;
+            self.graph.recreate(swapchain_images)?;
+            if swapchain_images.len() != self.frame_uniforms.len() {
+                (self.frame_uniforms, self._uniform_memory) = Self::create_frame_uniforms(
+                    &self.gfx_queue,
+                    &self.scene_layout,
+                    &self.shadow_system,
+                    swapchain_images.len(),
+                )?;
+            }
 
             self.material_registry
-                .lock()
-                .unwrap()
+                .write()
+                .recover()
                 .recreate_pipelines(viewport)?;
-            self.screen_system
-                .swapchain_invalidated(viewport, self.color_view.clone())?;
+            self.grid_system.swapchain_invalidated(viewport)?;
+            self.screen_system.swapchain_invalidated(
+                viewport,
+                self.graph.attachment(COLOR_ATTACHMENT).unwrap().clone(),
+            )?;
             return Ok(false);
         }
 
+        if let Event::GameEvent(event) = event {
+            let effects = &mut self.scene.write().recover().camera_effects;
+            match event {
+                GameEvent::CameraShake(trauma) => effects.add_trauma(*trauma),
+                GameEvent::ScreenDamage(amount) => effects.damage(*amount),
+                GameEvent::ScreenFlash { color, intensity } => effects.flash(*color, *intensity),
+                _ => (),
+            }
+        }
+
         Ok(false)
     }
 
@@ -239,27 +321,66 @@ impl Layer for WorldLayer {
         in_future: Box<dyn GpuFuture>,
         frame: &Frame,
     ) -> Result<Box<dyn GpuFuture>, Error> {
-        let scene_lock = self.scene.lock().unwrap();
-
-        {
-            let mut data = self.scene_buffer.write()?;
-
-            let view = Matrix4::look_at_rh(
-                scene_lock.camera.position(),
-                &(scene_lock.camera.position() + scene_lock.camera.forward()),
-                &Vector3::new(0.0, 1.0, 0.0),
-            );
-            let projection =
-                Matrix4::new_perspective(self.dimensions.0 / self.dimensions.1, 45.0, 0.01, 100.0);
-
-            // TODO use some common data type for this
-            *data = shader::simple_vs::ty::Scene_Data {
-                projection: projection.into(),
-                view: view.into(),
-            };
+        self.model_arena.flush(frame.image_index)?;
+        self.render_scene
+            .extract(&self.scene.read().recover(), frame.image_index);
+        let scene = &self.render_scene;
+        let uniforms = &self.frame_uniforms[frame.image_index];
+
+        let (width, height) = self.dimensions;
+        let aspect = AspectLock::from_preferences(&self.preferences.lock().recover());
+        let projection = aspect.projection(&scene.camera, width, height);
+        let shadow_settings = ShadowSettings::from_preferences(&self.preferences.lock().recover());
+        let lights =
+            LightSelection::select(&scene.lights, scene.camera.position(), &shadow_settings);
+
+        let upload_span = tracing::info_span!("upload_uniforms").entered();
+        let view = {
+            let mut data = uniforms.camera.write()?;
+            *data = CameraUniform::new(&scene.camera, projection, self.time as f32);
+            data.apply_shake(&scene.shake);
+            Matrix4::from(data.view)
         };
-
-        let framebuffer = &self.framebuffers[frame.image_index];
+        let view_projection = projection * view;
+
+        self.clusters.build(
+            lights.lights.iter().map(|&(index, _)| {
+                let light = &scene.lights[index];
+                (light.position, light.range)
+            }),
+            &view,
+            &projection,
+            scene.camera.near(),
+            scene.camera.far(),
+        );
+        {
+            let mut data = uniforms.light.write()?;
+            *data = LightUniform::from(&scene.light);
+            data.set_shadow_maps(&lights);
+            data.set_clusters(&view, self.clusters.params(width, height));
+        }
+        {
+            let mut data = uniforms.local_lights.write()?;
+            for (slot, &(index, first_map)) in lights.lights.iter().enumerate() {
+                data[slot] = LocalLightUniform::new(&scene.lights[index], first_map);
+            }
+        }
+        {
+            let mut data = uniforms.clusters.write()?;
+            data.copy_from_slice(self.clusters.clusters());
+        }
+        {
+            let indices = self.clusters.indices();
+            uniforms.cluster_indices.write()?[..indices.len()].copy_from_slice(indices);
+        }
+        stats::record_upload(
+            (std::mem::size_of::<CameraUniform>()
+                + std::mem::size_of::<LightUniform>()
+                + lights.lights.len() * std::mem::size_of::<LocalLightUniform>()
+                + CLUSTER_COUNT * std::mem::size_of::<[u32; 2]>()
+                + self.clusters.indices().len() * std::mem::size_of::<u32>()) as u64,
+        );
+        upload_span.exit();
 
         let mut builder = AutoCommandBufferBuilder::primary(
             self.gfx_queue.device().clone(),
@@ -267,29 +388,60 @@ impl Layer for WorldLayer {
             CommandBufferUsage::OneTimeSubmit,
         )?;
 
-        let mut render_pass_begin_info = RenderPassBeginInfo::framebuffer(framebuffer.clone());
+        let indirect_settings =
+            IndirectDrawSettings::from_preferences(&self.preferences.lock().recover());
+        let indirect = self.forward_system.prepare(
+            &mut builder,
+            scene,
+            &indirect_settings,
+            &view_projection,
+        )?;
 
-        render_pass_begin_info
-            .clear_values
-            .push(Some(ClearValue::Float([0.0, 0.0, 0.0, 1.0])));
-        render_pass_begin_info
-            .clear_values
-            .push(Some(ClearValue::Depth(1.0)));
-        render_pass_begin_info
-            .clear_values
-            .push(Some(ClearValue::Float([0.0, 0.0, 0.0, 1.0])));
+        let shadow_draws = self
+            .shadow_system
+            .do_frame(&mut builder, scene, &lights.shadow_maps)?;
 
         builder.begin_render_pass(
-            render_pass_begin_info,
+            self.graph.begin_info(Pass::Forward, frame.image_index),
             SubpassContents::SecondaryCommandBuffers,
         )?;
 
-        self.forward_system
-            .do_frame(&mut builder, &self.scene_set, scene_lock)?;
+        let debug = DebugViewSettings::from_preferences(&self.preferences.lock().recover());
+        let helpers = self
+            .grid_system
+            .do_frame(&scene.camera, &projection, &debug)?;
+
+        let mut counts =
+            self.forward_system
+                .do_frame(&mut builder, &uniforms.set, scene, indirect.as_ref())?;
+        counts.draw_calls += shadow_draws;
+        self.stats.lock().recover().set_draw_counts(counts);
+
+        if let Some(helpers) = helpers {
+            builder.execute_commands(helpers)?;
+        }
 
-        builder.next_subpass(SubpassContents::Inline)?;
+        builder.end_render_pass()?;
+        builder.begin_render_pass(
+            self.graph.begin_info(Pass::Screen, frame.image_index),
+            SubpassContents::Inline,
+        )?;
 
-        self.screen_system.do_frame(&mut builder)?;
+        let output = OutputSettings::from_preferences(&self.preferences.lock().recover());
+        let shafts = LightShaftSettings::from_preferences(&self.preferences.lock().recover())
+            .sun(&scene.light, &view_projection)
+            .unwrap_or_default();
+        let grading = ColorGradingSettings::from_preferences(&self.preferences.lock().recover())
+            .apply(&scene.grading);
+        self.screen_system.do_frame(
+            &mut builder,
+            &output,
+            &scene.overlay,
+            &shafts,
+            &grading,
+            aspect.bars(width, height),
+            self.hdr10,
+        )?;
 
         builder.end_render_pass()?;
 
@@ -1,120 +1,397 @@
 #![allow(clippy::into_iter_on_ref)]
 
 use std::{
-    sync::{Arc, Mutex},
+    sync::{Arc, Mutex, RwLock},
     time::Instant,
 };
 
+use clipboard::Clipboard;
+use config::Config;
+use crash::{CrashContext, CrashReportConfig};
+use cursor::{Cursor, CursorMode};
 use error::Error;
 use event::{Event, GameEvent};
-use layer::{gui::GuiLayer, logic::LogicLayer, world::WorldLayer, LayerManager, input::InputLayer};
-use render::context::VulkanContext;
-use resource::{material::MaterialRegistry, model::ModelRegistry, texture::TextureRegistry};
-use vulkano::format::Format;
+use gui::console::Console;
+#[cfg(feature = "gui")]
+use gui::{dock::{DockArea, Workspace}, events::EventLogPanel, layers::LayersPanel, log::LogPanel, preferences::PreferencesPanel};
+use i18n::{Localization, DEFAULT_LOCALE};
+use layer::{ai::AiLayer, logic::LogicLayer, world::WorldLayer, LayerId, LayerManager, PanicPolicy, input::InputLayer};
+#[cfg(feature = "gui")]
+use layer::{gui::GuiLayer, loading::LoadingLayer, menu::MenuLayer};
+use lock::Recover;
+use logging::{LogConfig, LogHistory};
+use preferences::{Preferences, WindowMode};
+use random::Random;
+#[cfg(feature = "golden")]
+use render::golden::{GoldenLayer, GoldenSuite};
+use render::{bindless::BindlessTextures, context::VulkanContext, graph::RenderGraph, memory, probe::{ReflectionBake, ReflectionBakeLayer}, stats::Stats, upload::UploadQueue};
+use resource::{
+    loader::{AssetLoader, AssetManifest},
+    material::MaterialRegistry,
+    model::ModelRegistry,
+    texture::TextureRegistry,
+};
+use state::{GameState, GameStateStack};
+use task::Tasks;
+use time::Time;
+use timer::TimerManager;
+use trace::{TraceConfig, TraceGuard};
+use tween::TweenManager;
 use winit::{
     event::{DeviceEvent, WindowEvent},
-    event_loop::{ControlFlow, EventLoop},
-    window::WindowBuilder,
+    event_loop::{ControlFlow, EventLoop, EventLoopProxy},
+    window::{Fullscreen, WindowBuilder},
 };
-use world::scene::Scene;
+use world::{lightmap::{self, LightmapBakeSettings}, scene::Scene, scenes::SceneManager};
 
+pub mod ai;
+pub mod clipboard;
+pub mod config;
+pub mod crash;
+pub mod cursor;
+pub mod editor;
 pub mod error;
 pub mod event;
+pub mod gui;
+pub mod i18n;
 pub mod layer;
+pub mod lock;
+pub mod logging;
+pub mod math;
+pub mod preferences;
+pub mod random;
 pub mod render;
 pub mod resource;
+pub mod state;
+pub mod task;
+pub mod time;
+pub mod timer;
+pub mod trace;
+pub mod tween;
 pub mod world;
 
+// Called for errors which reach the main loop, fatal ones stop it afterwards
+pub type ErrorHook = Box<dyn FnMut(&Error)>;
+
+#[derive(Default)]
+pub struct ApplicationBuilder {
+    logging: Option<LogConfig>,
+    crash_reports: Option<CrashReportConfig>,
+    tracing: Option<TraceConfig>,
+    seed: Option<u64>,
+    #[cfg(feature = "golden")]
+    golden: Option<GoldenSuite>,
+    reflection_bake: Option<ReflectionBake>,
+}
+
 pub struct Application {
     event_loop: EventLoop<GameEvent>,
+    event_proxy: EventLoopProxy<GameEvent>,
     render_context: VulkanContext,
-    layer_manager: LayerManager
+    game_states: GameStateStack,
+    // State for which an overlay (menu, loading screen) layer is shown, and the layer
+    overlay: Option<(GameState, LayerId)>,
+    layer_manager: LayerManager,
+    time: Time,
+    tweens: Arc<Mutex<TweenManager>>,
+    timers: Arc<Mutex<TimerManager>>,
+    tasks: Tasks,
+    random: Random,
+    #[cfg(feature = "gui")]
+    workspace: Arc<Mutex<Workspace>>,
+    console: Arc<Mutex<Console>>,
+    config: Arc<Mutex<Config>>,
+    localization: Arc<Mutex<Localization>>,
+    preferences: Arc<Mutex<Preferences>>,
+    cursor: Cursor,
+    clipboard: Arc<Mutex<Clipboard>>,
+    log_history: Arc<Mutex<LogHistory>>,
+    stats: Arc<Mutex<Stats>>,
+    uploads: Arc<Mutex<UploadQueue>>,
+    scenes: Arc<Mutex<SceneManager>>,
+    trace_guard: Option<TraceGuard>,
+    error_hook: ErrorHook,
+    // Golden image run or offline bake: fixed-size hidden window, no GUI and no window mode or
+    // focus handling
+    headless: bool,
+}
+
+impl ApplicationBuilder {
+    // Installs the engine logger, leave unset if the game sets up its own
+    pub fn logging(mut self, config: LogConfig) -> Self {
+        self.logging = Some(config);
+        self
+    }
+
+    // Writes a report file on panic, off by default
+    pub fn crash_reports(mut self, config: CrashReportConfig) -> Self {
+        self.crash_reports = Some(config);
+        self
+    }
+
+    pub fn tracing(mut self, config: TraceConfig) -> Self {
+        self.tracing = Some(config);
+        self
+    }
+
+    // Fixed seed for reproducible runs, a random one is picked otherwise
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    // Renders the suite's scenes instead of running the game, then exits with the result
+    #[cfg(feature = "golden")]
+    pub fn golden(mut self, suite: GoldenSuite) -> Self {
+        self.golden = Some(suite);
+        self
+    }
+
+    // Bakes the reflection probes instead of running the game, then exits
+    pub fn reflection_bake(mut self, bake: ReflectionBake) -> Self {
+        self.reflection_bake = Some(bake);
+        self
+    }
+
+    pub fn build(self) -> Result<Application, Error> {
+        Application::from_builder(self)
+    }
 }
 
 impl Application {
     pub fn new() -> Result<Self, Error> {
+        Self::builder().build()
+    }
+
+    pub fn builder() -> ApplicationBuilder {
+        ApplicationBuilder::default()
+    }
+
+    fn from_builder(builder: ApplicationBuilder) -> Result<Self, Error> {
+        // Records only reach the log panel when the engine logger is used
+        let log_history = match builder.logging {
+            Some(config) => config.init()?,
+            None => Arc::new(Mutex::new(LogHistory::default())),
+        };
+        let trace_guard = builder.tracing.map(TraceConfig::init).transpose()?;
         rayon::ThreadPoolBuilder::new()
             .num_threads(24)
             .build_global()
             .unwrap();
+        let random = builder.seed.map_or_else(Random::from_entropy, Random::new);
+        log::info!("Random seed: {}", random.seed());
+        let config = Config::load_or_default(Config::DEFAULT_PATH)?;
+        let localization = match Localization::load(&config.locale) {
+            Ok(localization) => localization,
+            Err(err) => {
+                log::warn!("Failed to load locale {:?}: {}", config.locale, err);
+                Localization::load(DEFAULT_LOCALE)?
+            }
+        };
+        let config = Arc::new(Mutex::new(config));
+        let localization = Arc::new(Mutex::new(localization));
         let event_loop = EventLoop::with_user_event();
         let proxy = event_loop.create_proxy();
+        #[cfg(feature = "golden")]
+        let golden = builder.golden;
+        let reflection_bake = builder.reflection_bake;
+        #[cfg(feature = "golden")]
+        let headless = golden.is_some() || reflection_bake.is_some();
+        #[cfg(not(feature = "golden"))]
+        let headless = reflection_bake.is_some();
+        // Headless runs render with the defaults, not whatever the player has configured
+        let preferences = if headless {
+            Preferences::default()
+        } else {
+            Preferences::load_or_default(Preferences::default_path())?
+        };
+        let preferences = Arc::new(Mutex::new(preferences));
+        let window_builder = WindowBuilder::new()
+            .with_title("proper")
+            .with_resizable(false);
+        #[cfg(feature = "golden")]
+        let window_builder = match &golden {
+            Some(suite) => window_builder
+                .with_inner_size(winit::dpi::PhysicalSize::new(suite.width, suite.height))
+                .with_visible(false),
+            None => window_builder,
+        };
+        let window_builder = match &reflection_bake {
+            Some(bake) => window_builder
+                .with_inner_size(winit::dpi::PhysicalSize::new(bake.resolution, bake.resolution))
+                .with_visible(false),
+            None => window_builder,
+        };
         let render_context = VulkanContext::new_windowed(
             &event_loop,
-            WindowBuilder::new()
-                .with_title("proper")
-                .with_resizable(false),
+            window_builder,
+            preferences.lock().recover().swapchain_images(),
+            // References are stored as 8-bit sRGB
+            preferences.lock().recover().hdr_output() && !headless,
         )?;
 
-        // TODO I still don't know where to place this lol
-        let render_pass = vulkano::ordered_passes_renderpass!(
+        memory::set_budget(render_context.device_local_memory());
+
+        let graph = RenderGraph::new(
             render_context.gfx_queue().device().clone(),
-            attachments: {
-                ms_color: {
-                    load: Clear,
-                    store: DontCare,
-                    format: render_context.output_format(),
-                    samples: 4,
-                },
-                depth: {
-                    load: Clear,
-                    store: DontCare,
-                    format: Format::D16_UNORM,
-                    samples: 4,
-                },
-                final_color: {
-                    load: Clear,
-                    store: Store,
-                    format: render_context.output_format(),
-                    samples: 1,
-                }
-            },
-            passes: [
-                {
-                    color: [ms_color],
-                    depth_stencil: {depth},
-                    input: []
-                },
-                {
-                    color: [final_color],
-                    depth_stencil: {},
-                    input: [ms_color]
-                }
-            ]
-        )
-        .unwrap();
+            render_context.output_format(),
+            render_context.capabilities().clamp_msaa_samples(preferences.lock().recover().msaa_samples()),
+            render_context.swapchain_images(),
+        )?;
 
-        let material_registry = Arc::new(Mutex::new(MaterialRegistry::new(
+        // Scene and registries are behind RwLocks: rendering and the GUI panels only read them,
+        // so they don't contend with each other, only with logic and loading
+        let uploads = Arc::new(Mutex::new(UploadQueue::new(render_context.gfx_queue().clone())));
+        let model_registry = Arc::new(RwLock::new(ModelRegistry::new(uploads.clone())?));
+        let texture_registry = Arc::new(RwLock::new(TextureRegistry::new(uploads.clone())?));
+        let bindless_textures = render_context.capabilities().bindless_textures().then(|| {
+            let placeholder = texture_registry.read().recover().placeholder().clone();
+            Arc::new(Mutex::new(BindlessTextures::new(placeholder)))
+        });
+        let material_registry = Arc::new(RwLock::new(MaterialRegistry::new(
             render_context.gfx_queue().clone(),
-            render_pass.clone(),
+            graph.render_pass().clone(),
             render_context.viewport().clone(),
+            bindless_textures,
+            texture_registry.read().recover().placeholder().clone(),
         )));
-        let model_registry = Arc::new(Mutex::new(ModelRegistry::new(
-            render_context.gfx_queue().clone(),
-        )));
-        let texture_registry = Arc::new(Mutex::new(TextureRegistry::new(
-            render_context.gfx_queue().clone(),
-        )?));
-        let scene = Arc::new(Mutex::new(Scene::default()));
+        let scene = Arc::new(RwLock::new(Scene::default()));
+        let scenes = Arc::new(Mutex::new(SceneManager::new(scene.clone())));
+        let stats = Arc::new(Mutex::new(Stats::default()));
+        if let Some(config) = builder.crash_reports {
+            config.install(CrashContext {
+                stats: stats.clone(),
+                scene: scene.clone(),
+                log_history: log_history.clone(),
+                gpu: render_context.device_summary(),
+            });
+        }
+        let preload = AssetManifest::load_or_default("res/preload.toml")?;
+        #[cfg(feature = "gui")]
+        let workspace = Arc::new(Mutex::new(Workspace::default()));
+        let console = Arc::new(Mutex::new(Console::default()));
+        let clipboard = Arc::new(Mutex::new(Clipboard::default()));
+        let error_console = console.clone();
+        let error_proxy = proxy.clone();
+        {
+            let mut console = console.lock().recover();
+            let copy_clipboard = clipboard.clone();
+            console.register_command("copy", "copy the arguments to the clipboard", move |args| {
+                copy_clipboard.lock().recover().set(&args.join(" "));
+                Ok(String::new())
+            });
+            let paste_clipboard = clipboard.clone();
+            console.register_command("paste", "print the clipboard contents", move |_| {
+                paste_clipboard.lock().recover().get().ok_or_else(|| "Clipboard is empty".to_owned())
+            });
+            let time_scale_proxy = proxy.clone();
+            console.register_command("time_scale", "set the simulation speed, 1 is normal", move |args| {
+                let scale = args.first().and_then(|arg| arg.parse::<f64>().ok()).ok_or_else(|| "Usage: time_scale <scale>".to_owned())?;
+                time_scale_proxy.send_event(GameEvent::SetTimeScale(scale)).map_err(|err| err.to_string())?;
+                Ok(String::new())
+            });
+            let grading_proxy = proxy.clone();
+            console.register_command("color_grading", "blend to a LUT from res/luts, none to turn it off: <lut> [seconds]", move |args| {
+                let lut = args.first().ok_or_else(|| "Usage: color_grading <lut> [seconds]".to_owned())?;
+                let lut = (*lut != "none").then(|| lut.to_string());
+                let duration = args.get(1).and_then(|arg| arg.parse::<f32>().ok()).unwrap_or(0.0);
+                grading_proxy.send_event(GameEvent::SetColorGrading { lut, duration }).map_err(|err| err.to_string())?;
+                Ok(String::new())
+            });
+            let (bake_scene, bake_textures, bake_materials) = (scene.clone(), texture_registry.clone(), material_registry.clone());
+            console.register_command("bake_lightmaps", "bake and apply lightmaps, blocks until done: [resolution] [samples]", move |args| {
+                let mut settings = LightmapBakeSettings::default();
+                if let Some(resolution) = args.first().and_then(|arg| arg.parse().ok()) {
+                    settings.resolution = resolution;
+                }
+                if let Some(samples) = args.get(1).and_then(|arg| arg.parse().ok()) {
+                    settings.samples = samples;
+                }
+                let baked = {
+                    let scene = bake_scene.read().recover();
+                    lightmap::bake_lightmaps(&scene, &settings)
+                        .into_iter()
+                        .filter_map(|(id, lightmap)| {
+                            let entity = scene.get(id)?;
+                            let name = entity.components().get::<lightmap::LightmapRef>()?.name.clone();
+                            Some((name, lightmap))
+                        })
+                        .collect::<Vec<_>>()
+                };
+                for (name, lightmap) in &baked {
+                    lightmap.save(lightmap::lightmap_path(name)).map_err(|err| err.full_message())?;
+                }
+                let applied = lightmap::apply_lightmaps(
+                    &mut bake_scene.write().recover(),
+                    &mut bake_textures.write().recover(),
+                    &mut bake_materials.write().recover(),
+                )
+                .map_err(|err| err.full_message())?;
+                Ok(format!("Baked {} lightmaps, applied {}", baked.len(), applied))
+            });
+        }
 
         let world_layer = Box::new(WorldLayer::new(
             render_context.gfx_queue().clone(),
-            render_pass,
+            graph,
             material_registry.clone(),
-            render_context.swapchain_images(),
+            model_registry.read().recover().model_arena().clone(),
+            render_context.image_count(),
             render_context.viewport().clone(),
             render_context.dimensions(),
             scene.clone(),
+            stats.clone(),
+            preferences.clone(),
+            // TODO egui draws straight onto the swapchain image, so the GUI isn't PQ-encoded
+            render_context.is_hdr10(),
         )?);
 
+        #[cfg(feature = "gui")]
         let gui = Box::new(GuiLayer::new(
             proxy.clone(),
             render_context.surface().clone(),
             render_context.gfx_queue().clone(),
             scene.clone(),
+            texture_registry.clone(),
+            workspace.clone(),
+            console.clone(),
+            config.clone(),
+            localization.clone(),
+            stats.clone(),
+            preferences.clone(),
         ));
 
-        let input_layer = Box::new(InputLayer::new(proxy.clone()));
+        let event_proxy = proxy.clone();
+        let loader = AssetLoader::new(
+            material_registry.clone(),
+            model_registry.clone(),
+            texture_registry.clone(),
+        );
+        let input_layer = Box::new(InputLayer::new(proxy.clone(), preferences.clone()));
+        let ai_layer = Box::new(AiLayer::new(scene.clone()));
+        let timers = Arc::new(Mutex::new(TimerManager::default()));
+        let tasks = Tasks::default();
+        #[cfg(feature = "golden")]
+        let golden_layer = golden.map(|suite| {
+            Box::new(GoldenLayer::new(
+                proxy.clone(),
+                suite,
+                scenes.clone(),
+                material_registry.clone(),
+                model_registry.clone(),
+                texture_registry.clone(),
+            ))
+        });
+        let reflection_bake_layer = reflection_bake.map(|bake| {
+            Box::new(ReflectionBakeLayer::new(
+                proxy.clone(),
+                bake,
+                scenes.clone(),
+                material_registry.clone(),
+                model_registry.clone(),
+                texture_registry.clone(),
+            ))
+        });
         let logic_layer = Box::new(LogicLayer::new(
             proxy,
             scene,
@@ -122,54 +399,378 @@ impl Application {
             model_registry,
             texture_registry,
             input_layer.state.clone(),
+            preferences.clone(),
+            timers.clone(),
+            tasks.clone(),
+            random,
         ));
 
         let mut layer_manager = LayerManager::default();
         layer_manager.push(world_layer);
         layer_manager.push(logic_layer);
+        layer_manager.push(ai_layer);
         layer_manager.push(input_layer);
-        layer_manager.push(gui);
+        #[cfg(feature = "gui")]
+        if !headless {
+            layer_manager.push(gui);
+        }
+        #[cfg(feature = "golden")]
+        if let Some(golden_layer) = golden_layer {
+            layer_manager.push(golden_layer);
+        }
+        if let Some(reflection_bake_layer) = reflection_bake_layer {
+            layer_manager.push(reflection_bake_layer);
+        }
+
+        #[cfg(feature = "gui")]
+        {
+            let mut workspace = workspace.lock().recover();
+            workspace.register(
+                PreferencesPanel::new(event_proxy.clone(), preferences.clone()),
+                DockArea::Right,
+            );
+            workspace.register(LogPanel::new(log_history.clone()), DockArea::Bottom);
+            workspace.register(EventLogPanel::new(layer_manager.event_log().clone()), DockArea::Bottom);
+            workspace.register(LayersPanel::new(event_proxy.clone(), layer_manager.status().clone()), DockArea::Right);
+        }
+
+        #[cfg(feature = "gui")]
+        let (game_states, overlay) = if preload.is_empty() {
+            (GameStateStack::default(), None)
+        } else if headless {
+            loader.with_manifest(&preload).start().wait()?;
+            (GameStateStack::default(), None)
+        } else {
+            let loading = layer_manager.push(Box::new(LoadingLayer::new(
+                event_proxy.clone(),
+                render_context.surface().clone(),
+                render_context.gfx_queue().clone(),
+                loader.with_manifest(&preload).start(),
+                localization.clone(),
+            )));
+            layer_manager.set_frozen(true);
+            (GameStateStack::new(GameState::Loading), Some((GameState::Loading, loading)))
+        };
+        // Nothing to show the progress on, the window stays blank until everything is loaded
+        #[cfg(not(feature = "gui"))]
+        let (game_states, overlay) = {
+            if !preload.is_empty() {
+                loader.with_manifest(&preload).start().wait()?;
+            }
+            (GameStateStack::default(), None)
+        };
+        let mut time = Time::default();
+        time.set_paused(game_states.current().is_frozen());
 
         Ok(Self {
             event_loop,
+            event_proxy,
             render_context,
-            layer_manager
+            game_states,
+            overlay,
+            layer_manager,
+            time,
+            tweens: Arc::new(Mutex::new(TweenManager::default())),
+            timers,
+            tasks,
+            random,
+            #[cfg(feature = "gui")]
+            workspace,
+            console,
+            config,
+            localization,
+            preferences,
+            cursor: Cursor::default(),
+            clipboard,
+            log_history,
+            stats,
+            uploads,
+            scenes,
+            trace_guard,
+            headless,
+            error_hook: Box::new(move |err| {
+                let message = err.full_message();
+                let hint = err.hint();
+                match hint {
+                    Some(hint) => log::error!("{} ({})", message, hint),
+                    None => log::error!("{}", message),
+                }
+                error_console.lock().recover().print(format!("Error: {}", message));
+                error_proxy
+                    .send_event(GameEvent::ErrorReported {
+                        message,
+                        hint: hint.map(str::to_owned),
+                    })
+                    .ok();
+            }),
         })
     }
 
+    #[inline]
+    pub const fn tweens(&self) -> &Arc<Mutex<TweenManager>> {
+        &self.tweens
+    }
+
+    #[inline]
+    pub const fn timers(&self) -> &Arc<Mutex<TimerManager>> {
+        &self.timers
+    }
+
+    #[inline]
+    pub const fn tasks(&self) -> &Tasks {
+        &self.tasks
+    }
+
+    #[inline]
+    pub const fn random(&self) -> Random {
+        self.random
+    }
+
+    #[cfg(feature = "gui")]
+    #[inline]
+    pub const fn workspace(&self) -> &Arc<Mutex<Workspace>> {
+        &self.workspace
+    }
+
+    #[inline]
+    pub const fn console(&self) -> &Arc<Mutex<Console>> {
+        &self.console
+    }
+
+    #[inline]
+    pub const fn config(&self) -> &Arc<Mutex<Config>> {
+        &self.config
+    }
+
+    #[inline]
+    pub const fn localization(&self) -> &Arc<Mutex<Localization>> {
+        &self.localization
+    }
+
+    #[inline]
+    pub const fn preferences(&self) -> &Arc<Mutex<Preferences>> {
+        &self.preferences
+    }
+
+    #[inline]
+    pub const fn clipboard(&self) -> &Arc<Mutex<Clipboard>> {
+        &self.clipboard
+    }
+
+    #[inline]
+    pub const fn log_history(&self) -> &Arc<Mutex<LogHistory>> {
+        &self.log_history
+    }
+
+    #[inline]
+    pub const fn stats(&self) -> &Arc<Mutex<Stats>> {
+        &self.stats
+    }
+
+    #[inline]
+    pub const fn uploads(&self) -> &Arc<Mutex<UploadQueue>> {
+        &self.uploads
+    }
+
+    #[inline]
+    pub const fn scenes(&self) -> &Arc<Mutex<SceneManager>> {
+        &self.scenes
+    }
+
+    pub fn set_panic_policy(&mut self, policy: PanicPolicy) {
+        self.layer_manager.set_panic_policy(policy);
+    }
+
+    pub fn set_error_hook<F: FnMut(&Error) + 'static>(&mut self, hook: F) {
+        self.error_hook = Box::new(hook);
+    }
+
+    fn report_error(&mut self, err: Error, flow: &mut ControlFlow) {
+        (self.error_hook)(&err);
+        if err.is_fatal() {
+            *flow = ControlFlow::Exit;
+        }
+    }
+
+    fn set_window_mode(&self, mode: WindowMode) {
+        let window = self.render_context.window();
+        match mode {
+            WindowMode::Windowed => window.set_fullscreen(None),
+            WindowMode::Fullscreen => window.set_fullscreen(Some(Fullscreen::Borderless(None))),
+        }
+    }
+
     pub fn run(mut self) {
         let mut t0 = Instant::now();
+        let mut last_frame = Instant::now();
+        // Layers are ticked once per event loop iteration, summed up for the frame stats
+        let mut tick_time = 0.0;
         let mut mouse_grabbed = false;
+        // Set when the game was paused because the window lost focus
+        let mut focus_paused = false;
+
+        if !self.headless {
+            let window_mode = self.preferences.lock().recover().window_mode();
+            self.set_window_mode(window_mode);
+        }
 
         self.event_loop.run(move |event, _, flow| {
             let t = Instant::now();
             let delta = (t - t0).as_secs_f64();
             t0 = t;
 
-            self.layer_manager.tick(delta).unwrap();
+            self.time.advance(delta);
+            self.tweens.lock().recover().update(delta as f32);
+            if let Err(err) = self.layer_manager.tick(&self.time) {
+                self.report_error(err, flow);
+            }
+            let preloaded = self.scenes.lock().recover().poll();
+            for result in preloaded {
+                match result {
+                    Ok(name) => {
+                        self.event_proxy.send_event(GameEvent::SceneLoaded(name)).ok();
+                    }
+                    Err(err) => self.report_error(err, flow),
+                }
+            }
+            tick_time += t.elapsed().as_secs_f64();
 
             match event {
                 winit::event::Event::DeviceEvent { event, .. } => {
                     if mouse_grabbed {
                         if let DeviceEvent::MouseMotion { delta } = event {
-                            self.layer_manager.notify_all(&Event::MouseMotion(delta), flow).unwrap();
+                            if let Err(err) = self.layer_manager.notify_all(&Event::MouseMotion(delta), flow) {
+                                self.report_error(err, flow);
+                            }
                         }
                     }
                 }
                 winit::event::Event::UserEvent(event) => {
-                    // TODO WindowLayer
-                    if let GameEvent::SetMouseGrab(grab) = event {
-                        if grab {
-                            self.render_context.window().set_cursor_grab(true).unwrap();
-                            self.render_context.window().set_cursor_visible(false);
+                    if let GameEvent::Quit = event {
+                        *flow = ControlFlow::Exit;
+                        return;
+                    }
+
+                    if let GameEvent::FocusChanged(focused) = event {
+                        if !focused {
+                            if mouse_grabbed {
+                                self.event_proxy.send_event(GameEvent::SetMouseGrab(false)).ok();
+                            }
+                            if self.preferences.lock().recover().pause_on_focus_loss() && self.game_states.current() == GameState::Playing {
+                                self.event_proxy.send_event(GameEvent::PushGameState(GameState::Paused)).ok();
+                                focus_paused = true;
+                            }
+                        } else if focus_paused {
+                            // Only resume if the player didn't navigate the menu in the meantime
+                            if self.game_states.current() == GameState::Paused {
+                                self.event_proxy.send_event(GameEvent::PopGameState).ok();
+                            }
+                            focus_paused = false;
+                        }
+                    }
+
+                    if let Some(state) = self.game_states.handle(&event) {
+                        self.layer_manager.set_frozen(state.is_frozen());
+                        self.time.set_paused(state.is_frozen());
+                        if state.is_frozen() && mouse_grabbed {
+                            self.event_proxy.send_event(GameEvent::SetMouseGrab(false)).ok();
+                        }
+
+                        // Menu layer is kept when switching between menu states
+                        // Removed by id, the layers panel may have moved it from the end of the stack
+                        if let Some((overlay, layer)) = self.overlay {
+                            if !(overlay.has_menu() && state.has_menu()) {
+                                self.layer_manager.remove(layer);
+                                self.overlay = None;
+                            }
+                        }
+                        #[cfg(feature = "gui")]
+                        if state.has_menu() && self.overlay.is_none() {
+                            let menu = self.layer_manager.push(Box::new(MenuLayer::new(
+                                self.event_proxy.clone(),
+                                self.render_context.surface().clone(),
+                                self.render_context.gfx_queue().clone(),
+                                state,
+                                self.localization.clone(),
+                            )));
+                            self.overlay = Some((state, menu));
+                        }
+
+                        if let Err(err) = self.layer_manager.notify_all(&Event::GameEvent(GameEvent::GameStateChanged(state)), flow) {
+                            self.report_error(err, flow);
+                        }
+                    }
+
+                    if let GameEvent::SetLocale(locale) = &event {
+                        if let Err(err) = self.localization.lock().recover().set_locale(locale) {
+                            log::error!("Failed to switch locale to {:?}: {}", locale, err);
                         } else {
-                            self.render_context.window().set_cursor_grab(false).unwrap();
-                            self.render_context.window().set_cursor_visible(true);
+                            let mut config = self.config.lock().recover();
+                            config.locale = locale.clone();
+                            if let Err(err) = config.save() {
+                                log::error!("Failed to save config: {}", err);
+                            }
+                        }
+                    }
+
+                    if let GameEvent::SetTimeScale(scale) = event {
+                        self.time.set_time_scale(scale);
+                    }
+
+                    if let GameEvent::SwitchScene { name, keep_previous } = &event {
+                        let result = self.scenes.lock().recover().switch_to(name, *keep_previous);
+                        match result {
+                            Ok(()) => {
+                                self.event_proxy.send_event(GameEvent::SceneSwitched(name.clone())).ok();
+                            }
+                            Err(err) => self.report_error(err, flow),
+                        }
+                    }
+
+                    if let GameEvent::SetWindowMode(mode) = event {
+                        self.set_window_mode(mode);
+                    }
+
+                    if let GameEvent::SetLayerEnabled { layer, enabled } = &event && !self.layer_manager.set_enabled(layer, *enabled) {
+                        log::warn!("No layer named {:?}", layer);
+                    }
+
+                    if let GameEvent::MoveLayer { layer, index } = &event && !self.layer_manager.move_to(layer, *index) {
+                        log::warn!("No layer named {:?}", layer);
+                    }
+
+                    // TODO WindowLayer
+                    let cursor = self.cursor;
+                    let window = self.render_context.window();
+                    let result = match event {
+                        GameEvent::SetMouseGrab(grab) => {
+                            let mode = if grab { CursorMode::Grabbed } else { CursorMode::Normal };
+                            self.cursor.set_mode(window, mode)
+                        }
+                        GameEvent::SetCursorMode(mode) => self.cursor.set_mode(window, mode),
+                        GameEvent::SetCursorIcon(icon) => {
+                            self.cursor.set_icon(window, icon);
+                            Ok(())
+                        }
+                        GameEvent::SetSoftwareCursor(software) => {
+                            self.cursor.set_software(software);
+                            Ok(())
+                        }
+                        _ => Ok(()),
+                    };
+                    if let Err(err) = result {
+                        self.report_error(err, flow);
+                    }
+                    if self.cursor != cursor {
+                        mouse_grabbed = self.cursor.is_grabbed();
+                        if let Err(err) = self.layer_manager.notify_all(&Event::GameEvent(GameEvent::CursorChanged(self.cursor)), flow) {
+                            self.report_error(err, flow);
                         }
-                        mouse_grabbed = grab;
                     }
 
-                    self.layer_manager.notify_all(&Event::GameEvent(event), flow).unwrap();
+                    if let Err(err) = self.layer_manager.notify_all(&Event::GameEvent(event), flow) {
+                        self.report_error(err, flow);
+                    }
                 }
                 winit::event::Event::WindowEvent { event, .. } => {
                     if let WindowEvent::Resized(_) = event {
@@ -182,20 +783,43 @@ impl Application {
                         return;
                     }
 
+                    if let WindowEvent::Focused(focused) = event && !self.headless {
+                        self.event_proxy.send_event(GameEvent::FocusChanged(focused)).ok();
+                    }
+
                     if let WindowEvent::CursorMoved { .. } = event && mouse_grabbed {
                         return;
                     }
 
                     if let Ok(event) = Event::try_from(&event) {
-                        self.layer_manager.notify_all(&event, flow).unwrap();
+                        if let Err(err) = self.layer_manager.notify_all(&event, flow) {
+                            self.report_error(err, flow);
+                        }
                     } else {
                         log::info!("Ignoring unhandled event: {:?}", event);
                     }
                 }
+                winit::event::Event::LoopDestroyed => {
+                    // The event loop never returns, so the trace has to be flushed here
+                    self.trace_guard.take();
+                    if let Err(err) = self.preferences.lock().recover().save_if_dirty() {
+                        log::error!("Failed to save preferences: {}", err);
+                    }
+                }
                 winit::event::Event::RedrawEventsCleared => {
-                    self.render_context
-                        .do_frame(flow, &mut self.layer_manager)
-                        .unwrap();
+                    let t = Instant::now();
+                    match self.render_context.do_frame(flow, &mut self.layer_manager, &self.uploads) {
+                        Ok(completed) if !completed.is_empty() => {
+                            self.event_proxy.send_event(GameEvent::UploadsCompleted(completed)).ok();
+                        }
+                        Ok(_) => (),
+                        Err(err) => self.report_error(err, flow),
+                    }
+                    let draw_time = t.elapsed().as_secs_f64();
+                    let frame_time = (t - last_frame).as_secs_f64();
+                    last_frame = t;
+
+                    self.stats.lock().recover().end_frame(frame_time, std::mem::take(&mut tick_time), draw_time);
                 }
                 _ => (),
             }
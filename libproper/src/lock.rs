@@ -0,0 +1,15 @@
+use std::sync::{LockResult, PoisonError};
+
+// Used in place of unwrap() on the engine's shared locks. A layer which panics while holding
+// one poisons it, LayerManager reports the panic and disables the layer, and the other layers
+// go on with the data as the panicking one left it instead of panicking in turn
+pub trait Recover<G> {
+    fn recover(self) -> G;
+}
+
+impl<G> Recover<G> for LockResult<G> {
+    #[inline]
+    fn recover(self) -> G {
+        self.unwrap_or_else(PoisonError::into_inner)
+    }
+}
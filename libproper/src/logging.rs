@@ -0,0 +1,275 @@
+use std::{
+    collections::VecDeque,
+    ffi::OsString,
+    fmt,
+    fs::{self, File, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+use log::{Level, LevelFilter, Log, Metadata, Record};
+
+use crate::{error::Error, lock::Recover};
+
+pub const DEFAULT_HISTORY_SIZE: usize = 1024;
+
+struct FileOutput {
+    path: PathBuf,
+    // Bytes after which the file gets rotated, 0 means never
+    max_size: u64,
+    // Number of rotated files kept besides the current one
+    max_files: usize,
+}
+
+pub struct LogConfig {
+    level: LevelFilter,
+    modules: Vec<(String, LevelFilter)>,
+    console: bool,
+    file: Option<FileOutput>,
+    history: usize,
+}
+
+#[derive(Clone, Debug)]
+pub struct LogRecord {
+    pub level: Level,
+    pub target: String,
+    pub message: String,
+    // Seconds since the logger was initialized
+    pub time: f64,
+}
+
+// Most recent records, tailed by the log panel
+pub struct LogHistory {
+    records: VecDeque<LogRecord>,
+    capacity: usize,
+}
+
+struct RotatingFile {
+    output: FileOutput,
+    file: File,
+    size: u64,
+}
+
+struct Logger {
+    level: LevelFilter,
+    // Sorted by descending module path length, so the most specific filter matches first
+    modules: Vec<(String, LevelFilter)>,
+    console: bool,
+    file: Option<Mutex<RotatingFile>>,
+    history: Arc<Mutex<LogHistory>>,
+    start: Instant,
+}
+
+impl LogConfig {
+    pub fn new(level: LevelFilter) -> Self {
+        Self {
+            level,
+            modules: vec![],
+            console: true,
+            file: None,
+            history: DEFAULT_HISTORY_SIZE,
+        }
+    }
+
+    // Overrides the level for a module path and all of its submodules
+    pub fn module<S: Into<String>>(mut self, module: S, level: LevelFilter) -> Self {
+        self.modules.push((module.into(), level));
+        self
+    }
+
+    pub fn console(mut self, console: bool) -> Self {
+        self.console = console;
+        self
+    }
+
+    pub fn file<P: Into<PathBuf>>(mut self, path: P, max_size: u64, max_files: usize) -> Self {
+        self.file = Some(FileOutput {
+            path: path.into(),
+            max_size,
+            max_files,
+        });
+        self
+    }
+
+    pub fn history(mut self, history: usize) -> Self {
+        self.history = history;
+        self
+    }
+
+    // Installs the global logger, records are also kept in the returned history
+    pub fn init(self) -> Result<Arc<Mutex<LogHistory>>, Error> {
+        let history = Arc::new(Mutex::new(LogHistory::new(self.history)));
+        let file = self
+            .file
+            .map(|output| RotatingFile::open(output).map(Mutex::new))
+            .transpose()?;
+
+        let max_level = self
+            .modules
+            .iter()
+            .map(|(_, level)| *level)
+            .fold(self.level, LevelFilter::max);
+        let mut modules = self.modules;
+        modules.sort_by(|(a, _), (b, _)| b.len().cmp(&a.len()));
+
+        log::set_boxed_logger(Box::new(Logger {
+            level: self.level,
+            modules,
+            console: self.console,
+            file,
+            history: history.clone(),
+            start: Instant::now(),
+        }))?;
+        log::set_max_level(max_level);
+
+        Ok(history)
+    }
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        Self::new(LevelFilter::Info)
+    }
+}
+
+impl fmt::Display for LogRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:10.3} {:<5} [{}] {}",
+            self.time, self.level, self.target, self.message
+        )
+    }
+}
+
+impl LogHistory {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            records: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    pub fn push(&mut self, record: LogRecord) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.records.len() == self.capacity {
+            self.records.pop_front();
+        }
+        self.records.push_back(record);
+    }
+
+    pub fn records(&self) -> impl DoubleEndedIterator<Item = &LogRecord> + ExactSizeIterator {
+        self.records.iter()
+    }
+
+    pub fn clear(&mut self) {
+        self.records.clear();
+    }
+}
+
+impl Default for LogHistory {
+    fn default() -> Self {
+        Self::new(DEFAULT_HISTORY_SIZE)
+    }
+}
+
+fn rotated_path(path: &Path, index: usize) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push(format!(".{}", index));
+    PathBuf::from(name)
+}
+
+impl RotatingFile {
+    fn open(output: FileOutput) -> Result<Self, Error> {
+        if let Some(parent) = output.path.parent() {
+            fs::create_dir_all(parent).map_err(|e| Error::file(parent, e))?;
+        }
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&output.path)
+            .map_err(|e| Error::file(&output.path, e))?;
+        let size = file.metadata().map(|m| m.len()).unwrap_or(0);
+
+        Ok(Self { output, file, size })
+    }
+
+    // proper.log -> proper.log.1 -> ... -> proper.log.N, the oldest one is dropped
+    fn rotate(&mut self) -> std::io::Result<()> {
+        let path = &self.output.path;
+        if self.output.max_files == 0 {
+            self.file = File::create(path)?;
+        } else {
+            fs::remove_file(rotated_path(path, self.output.max_files)).ok();
+            for index in (1..self.output.max_files).rev() {
+                fs::rename(rotated_path(path, index), rotated_path(path, index + 1)).ok();
+            }
+            fs::rename(path, rotated_path(path, 1))?;
+            self.file = File::create(path)?;
+        }
+        self.size = 0;
+        Ok(())
+    }
+
+    fn write_line(&mut self, line: &str) -> std::io::Result<()> {
+        let len = line.len() as u64 + 1;
+        if self.output.max_size != 0 && self.size != 0 && self.size + len > self.output.max_size {
+            self.rotate()?;
+        }
+        writeln!(self.file, "{}", line)?;
+        self.size += len;
+        Ok(())
+    }
+}
+
+impl Logger {
+    fn level_for(&self, target: &str) -> LevelFilter {
+        self.modules
+            .iter()
+            .find(|(module, _)| {
+                target
+                    .strip_prefix(module.as_str())
+                    .map_or(false, |rest| rest.is_empty() || rest.starts_with("::"))
+            })
+            .map_or(self.level, |(_, level)| *level)
+    }
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level_for(metadata.target())
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        let record = LogRecord {
+            level: record.level(),
+            target: record.target().to_owned(),
+            message: record.args().to_string(),
+            time: self.start.elapsed().as_secs_f64(),
+        };
+        let line = record.to_string();
+
+        if self.console {
+            eprintln!("{}", line);
+        }
+        if let Some(file) = &self.file {
+            // Nowhere to report this to, the logger itself is failing
+            file.lock().recover().write_line(&line).ok();
+        }
+        self.history.lock().recover().push(record);
+    }
+
+    fn flush(&self) {
+        if let Some(file) = &self.file {
+            file.lock().recover().file.flush().ok();
+        }
+    }
+}
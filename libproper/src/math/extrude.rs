@@ -0,0 +1,195 @@
+use std::{f32::consts::PI, sync::Arc};
+
+use nalgebra::{Point2, Vector2, Vector3, Vector4};
+
+use crate::{
+    error::Error,
+    render::Vertex,
+    resource::{
+        material::MaterialTemplate,
+        mesh::MeshData,
+        model::{Model, ModelRegistry},
+    },
+};
+
+use super::spline::Spline;
+
+// Cross-section swept along a spline, x goes to the right of the path and y up. Surfaces face
+// to the left of the direction the points go in, so strips go from left to right and closed
+// profiles clockwise
+#[derive(Clone, Debug)]
+pub struct Profile {
+    pub points: Vec<Point2<f32>>,
+    pub closed: bool,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct ExtrudeOptions {
+    // Longest distance between two rings of the profile
+    pub spacing: f32,
+    // Where the profile's y points, as far as the path allows
+    pub up: Vector3<f32>,
+    // Distance along the path covered by the texture once, the texture's U goes across the
+    // profile
+    pub texture_length: f32,
+}
+
+impl Profile {
+    pub fn new(points: Vec<Point2<f32>>, closed: bool) -> Self {
+        Self { points, closed }
+    }
+
+    // Flat strip centered on the path, e.g. a road or a river surface
+    pub fn strip(width: f32) -> Self {
+        Self::new(
+            vec![
+                Point2::new(-width * 0.5, 0.0),
+                Point2::new(width * 0.5, 0.0),
+            ],
+            false,
+        )
+    }
+
+    // Strip with raised edges on both sides
+    pub fn road(width: f32, curb_width: f32, curb_height: f32) -> Self {
+        let inner = width * 0.5;
+        let outer = inner + curb_width;
+        Self::new(
+            vec![
+                Point2::new(-outer, 0.0),
+                Point2::new(-outer, curb_height),
+                Point2::new(-inner, curb_height),
+                Point2::new(-inner, 0.0),
+                Point2::new(inner, 0.0),
+                Point2::new(inner, curb_height),
+                Point2::new(outer, curb_height),
+                Point2::new(outer, 0.0),
+            ],
+            false,
+        )
+    }
+
+    // Pipes, cables and the like
+    pub fn circle(radius: f32, sides: usize) -> Self {
+        let sides = sides.max(3);
+        let points = (0..sides)
+            .map(|i| {
+                let angle = -2.0 * PI * i as f32 / sides as f32;
+                Point2::new(angle.cos() * radius, angle.sin() * radius)
+            })
+            .collect();
+        Self::new(points, true)
+    }
+
+    // Closed profiles repeat the first point, so the texture seam gets its own vertices
+    fn ring(&self) -> Vec<Point2<f32>> {
+        let mut ring = self.points.clone();
+        if self.closed && !ring.is_empty() {
+            ring.push(ring[0]);
+        }
+        ring
+    }
+}
+
+impl Default for ExtrudeOptions {
+    fn default() -> Self {
+        Self {
+            spacing: 1.0,
+            up: Vector3::y(),
+            texture_length: 1.0,
+        }
+    }
+}
+
+// Smooth normals in profile space, averaged between the edges meeting at each point
+fn profile_normals(ring: &[Point2<f32>], closed: bool) -> Vec<Vector2<f32>> {
+    let count = ring.len();
+    (0..count)
+        .map(|i| {
+            // The last point of a closed ring is the first one again
+            let previous = match i {
+                0 if closed => ring[count - 2],
+                0 => ring[0],
+                _ => ring[i - 1],
+            };
+            let next = if i + 1 < count {
+                ring[i + 1]
+            } else if closed {
+                ring[1]
+            } else {
+                ring[i]
+            };
+            let edge = next - previous;
+            Vector2::new(-edge.y, edge.x)
+                .try_normalize(f32::EPSILON)
+                .unwrap_or_else(Vector2::y)
+        })
+        .collect()
+}
+
+// Rings of the profile placed along the spline and joined with quads. A closed spline gets
+// a ring at both its start and end, so the texture can wrap around
+pub fn extrude(spline: &Spline, profile: &Profile, options: &ExtrudeOptions) -> MeshData {
+    let mut mesh = MeshData::default();
+    let ring = profile.ring();
+    let length = spline.length();
+    if ring.len() < 2 || length <= 0.0 {
+        return mesh;
+    }
+
+    let normals = profile_normals(&ring, profile.closed);
+    let mut across = vec![0.0];
+    for pair in ring.windows(2) {
+        across.push(across.last().unwrap() + (pair[1] - pair[0]).norm());
+    }
+    let width = across.last().copied().unwrap_or(0.0).max(f32::EPSILON);
+
+    let rings = (length / options.spacing.max(1e-3)).ceil().max(1.0) as usize;
+    for i in 0..=rings {
+        let distance = length * i as f32 / rings as f32;
+        let frame = spline.frame_at(distance, &options.up);
+        // U goes across the profile, V along the path
+        for (j, point) in ring.iter().enumerate() {
+            mesh.vertices.push(Vertex {
+                v_position: frame.position + frame.right * point.x + frame.up * point.y,
+                v_normal: frame.right * normals[j].x + frame.up * normals[j].y,
+                v_tex_coord: Point2::new(
+                    across[j] / width,
+                    distance / options.texture_length.max(1e-3),
+                ),
+                v_tangent: Vector4::zeros(),
+                v_lightmap_coord: Point2::origin(),
+            });
+        }
+    }
+
+    let stride = ring.len() as u32;
+    for i in 0..rings as u32 {
+        for j in 0..stride - 1 {
+            let a = i * stride + j;
+            let b = a + 1;
+            let c = a + stride;
+            let d = c + 1;
+            mesh.indices.extend([a, b, c, b, d, c]);
+        }
+    }
+    mesh.generate_tangents();
+
+    mesh
+}
+
+// Uploads the extruded mesh, e.g. for a MeshObject made with ModelRegistry::create_mesh_object
+pub fn extrude_model(
+    spline: &Spline,
+    profile: &Profile,
+    options: &ExtrudeOptions,
+    models: &ModelRegistry,
+    material_template: Arc<dyn MaterialTemplate>,
+) -> Result<Model, Error> {
+    let _span = tracing::info_span!("extrude_model", length = spline.length()).entered();
+    let mesh = extrude(spline, profile, options);
+    if mesh.indices.is_empty() {
+        return Err(Error::EmptyExtrusion);
+    }
+    Model::from_mesh(models.uploads(), &mesh, material_template)
+}
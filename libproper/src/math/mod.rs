@@ -0,0 +1,3 @@
+pub mod extrude;
+pub mod noise;
+pub mod spline;
@@ -0,0 +1,371 @@
+use std::sync::Arc;
+
+use rayon::prelude::*;
+use vulkano::format::Format;
+
+use crate::{
+    error::Error,
+    resource::texture::{SampledTexture, TextureRegistry},
+};
+
+// Simplex output is scaled up to roughly [-1, 1], Perlin is there already
+const SIMPLEX2_SCALE: f32 = 70.0;
+const SIMPLEX3_SCALE: f32 = 32.0;
+// Skew and unskew factors between the simplex grid and the square one
+const F2: f32 = 0.366_025_42;
+const G2: f32 = 0.211_324_87;
+const F3: f32 = 1.0 / 3.0;
+const G3: f32 = 1.0 / 6.0;
+
+const GRADIENTS_2D: [[f32; 2]; 8] = [
+    [1.0, 1.0],
+    [-1.0, 1.0],
+    [1.0, -1.0],
+    [-1.0, -1.0],
+    [1.0, 0.0],
+    [-1.0, 0.0],
+    [0.0, 1.0],
+    [0.0, -1.0],
+];
+// Cube edge midpoints, as in Perlin's improved noise
+const GRADIENTS_3D: [[f32; 3]; 12] = [
+    [1.0, 1.0, 0.0],
+    [-1.0, 1.0, 0.0],
+    [1.0, -1.0, 0.0],
+    [-1.0, -1.0, 0.0],
+    [1.0, 0.0, 1.0],
+    [-1.0, 0.0, 1.0],
+    [1.0, 0.0, -1.0],
+    [-1.0, 0.0, -1.0],
+    [0.0, 1.0, 1.0],
+    [0.0, -1.0, 1.0],
+    [0.0, 1.0, -1.0],
+    [0.0, -1.0, -1.0],
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NoiseKind {
+    Perlin,
+    // Fewer directional artifacts and cheaper in 3D, can't be tiled
+    Simplex,
+}
+
+// Gradient noise with values in about [-1, 1] and features about a unit apart. Gradients come
+// from hashing the lattice point with the seed, so there's no table to build and equal seeds
+// give equal noise everywhere. Seed it from a Random stream to make it replayable
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Noise {
+    seed: u32,
+}
+
+// Fractal Brownian motion: octaves of noise at rising frequencies and falling amplitudes,
+// normalized back to about [-1, 1]
+#[derive(Clone, Copy, Debug)]
+pub struct Fbm {
+    pub kind: NoiseKind,
+    pub octaves: u32,
+    // Of the first octave
+    pub frequency: f32,
+    // Frequency multiplier between octaves
+    pub lacunarity: f32,
+    // Amplitude multiplier between octaves
+    pub gain: f32,
+}
+
+const fn hash(seed: u32, x: i32, y: i32, z: i32) -> u32 {
+    let mut value = seed
+        ^ (x as u32).wrapping_mul(0x8da6_b343)
+        ^ (y as u32).wrapping_mul(0xd816_3841)
+        ^ (z as u32).wrapping_mul(0xcb1a_b31f);
+    value ^= value >> 16;
+    value = value.wrapping_mul(0x7feb_352d);
+    value ^= value >> 15;
+    value = value.wrapping_mul(0x846c_a68b);
+    value ^ (value >> 16)
+}
+
+// Quintic, so the noise has a continuous second derivative across lattice cells
+fn fade(t: f32) -> f32 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+// Lattice coordinate wrapped to the period, if any
+fn wrap(value: i32, period: Option<u32>) -> i32 {
+    match period {
+        Some(period) if period > 0 => value.rem_euclid(period as i32),
+        _ => value,
+    }
+}
+
+impl Noise {
+    pub const fn new(seed: u32) -> Self {
+        Self { seed }
+    }
+
+    #[inline]
+    pub const fn seed(&self) -> u32 {
+        self.seed
+    }
+
+    // Unrelated noise from the same seed, e.g. for another channel or octave
+    pub const fn offset(&self, index: u32) -> Self {
+        Self::new(hash(self.seed, index as i32, 1, 0))
+    }
+
+    pub fn perlin2(&self, x: f32, y: f32) -> f32 {
+        self.perlin2_wrapped(x, y, [None; 2])
+    }
+
+    // Repeats every period units on both axes
+    pub fn perlin2_tiled(&self, x: f32, y: f32, period: [u32; 2]) -> f32 {
+        self.perlin2_wrapped(x, y, period.map(Some))
+    }
+
+    pub fn perlin3(&self, x: f32, y: f32, z: f32) -> f32 {
+        let (x0, y0, z0) = (x.floor(), y.floor(), z.floor());
+        let (fx, fy, fz) = (x - x0, y - y0, z - z0);
+        let (ix, iy, iz) = (x0 as i32, y0 as i32, z0 as i32);
+        let corner = |dx: i32, dy: i32, dz: i32| {
+            let [gx, gy, gz] =
+                GRADIENTS_3D[hash(self.seed, ix + dx, iy + dy, iz + dz) as usize % 12];
+            gx * (fx - dx as f32) + gy * (fy - dy as f32) + gz * (fz - dz as f32)
+        };
+
+        let (u, v, w) = (fade(fx), fade(fy), fade(fz));
+        lerp(
+            lerp(
+                lerp(corner(0, 0, 0), corner(1, 0, 0), u),
+                lerp(corner(0, 1, 0), corner(1, 1, 0), u),
+                v,
+            ),
+            lerp(
+                lerp(corner(0, 0, 1), corner(1, 0, 1), u),
+                lerp(corner(0, 1, 1), corner(1, 1, 1), u),
+                v,
+            ),
+            w,
+        )
+    }
+
+    pub fn simplex2(&self, x: f32, y: f32) -> f32 {
+        let skew = (x + y) * F2;
+        let (i, j) = ((x + skew).floor(), (y + skew).floor());
+        let unskew = (i + j) * G2;
+        let (x0, y0) = (x - (i - unskew), y - (j - unskew));
+        // Which of the cell's two triangles the point is in
+        let (i1, j1) = if x0 > y0 { (1, 0) } else { (0, 1) };
+        let (i, j) = (i as i32, j as i32);
+
+        let corners = [
+            (x0, y0, i, j),
+            (x0 - i1 as f32 + G2, y0 - j1 as f32 + G2, i + i1, j + j1),
+            (x0 - 1.0 + 2.0 * G2, y0 - 1.0 + 2.0 * G2, i + 1, j + 1),
+        ];
+        let sum: f32 = corners
+            .iter()
+            .map(|&(x, y, ci, cj)| {
+                let t = 0.5 - x * x - y * y;
+                if t <= 0.0 {
+                    return 0.0;
+                }
+                let [gx, gy] = GRADIENTS_2D[hash(self.seed, ci, cj, 0) as usize & 7];
+                t * t * t * t * (gx * x + gy * y)
+            })
+            .sum();
+        sum * SIMPLEX2_SCALE
+    }
+
+    pub fn simplex3(&self, x: f32, y: f32, z: f32) -> f32 {
+        let skew = (x + y + z) * F3;
+        let (i, j, k) = ((x + skew).floor(), (y + skew).floor(), (z + skew).floor());
+        let unskew = (i + j + k) * G3;
+        let (x0, y0, z0) = (x - (i - unskew), y - (j - unskew), z - (k - unskew));
+        // Second and third corners of the tetrahedron the point is in
+        let (first, second) = if x0 >= y0 {
+            if y0 >= z0 {
+                ([1, 0, 0], [1, 1, 0])
+            } else if x0 >= z0 {
+                ([1, 0, 0], [1, 0, 1])
+            } else {
+                ([0, 0, 1], [1, 0, 1])
+            }
+        } else if y0 < z0 {
+            ([0, 0, 1], [0, 1, 1])
+        } else if x0 < z0 {
+            ([0, 1, 0], [0, 1, 1])
+        } else {
+            ([0, 1, 0], [1, 1, 0])
+        };
+        let base = [i as i32, j as i32, k as i32];
+
+        let sum: f32 = [[0, 0, 0], first, second, [1, 1, 1]]
+            .iter()
+            .enumerate()
+            .map(|(n, offset)| {
+                let x = x0 - offset[0] as f32 + n as f32 * G3;
+                let y = y0 - offset[1] as f32 + n as f32 * G3;
+                let z = z0 - offset[2] as f32 + n as f32 * G3;
+                let t = 0.6 - x * x - y * y - z * z;
+                if t <= 0.0 {
+                    return 0.0;
+                }
+                let [gx, gy, gz] = GRADIENTS_3D[hash(
+                    self.seed,
+                    base[0] + offset[0],
+                    base[1] + offset[1],
+                    base[2] + offset[2],
+                ) as usize
+                    % 12];
+                t * t * t * t * (gx * x + gy * y + gz * z)
+            })
+            .sum();
+        sum * SIMPLEX3_SCALE
+    }
+
+    pub fn sample2(&self, kind: NoiseKind, x: f32, y: f32) -> f32 {
+        match kind {
+            NoiseKind::Perlin => self.perlin2(x, y),
+            NoiseKind::Simplex => self.simplex2(x, y),
+        }
+    }
+
+    pub fn sample3(&self, kind: NoiseKind, x: f32, y: f32, z: f32) -> f32 {
+        match kind {
+            NoiseKind::Perlin => self.perlin3(x, y, z),
+            NoiseKind::Simplex => self.simplex3(x, y, z),
+        }
+    }
+
+    fn perlin2_wrapped(&self, x: f32, y: f32, period: [Option<u32>; 2]) -> f32 {
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+        let (ix, iy) = (x0 as i32, y0 as i32);
+        let corner = |dx: i32, dy: i32| {
+            let cx = wrap(ix + dx, period[0]);
+            let cy = wrap(iy + dy, period[1]);
+            let [gx, gy] = GRADIENTS_2D[hash(self.seed, cx, cy, 0) as usize & 7];
+            gx * (fx - dx as f32) + gy * (fy - dy as f32)
+        };
+
+        let (u, v) = (fade(fx), fade(fy));
+        lerp(
+            lerp(corner(0, 0), corner(1, 0), u),
+            lerp(corner(0, 1), corner(1, 1), u),
+            v,
+        )
+    }
+}
+
+impl Default for Fbm {
+    fn default() -> Self {
+        Self {
+            kind: NoiseKind::Perlin,
+            octaves: 5,
+            frequency: 1.0,
+            lacunarity: 2.0,
+            gain: 0.5,
+        }
+    }
+}
+
+impl Fbm {
+    pub fn with_kind(mut self, kind: NoiseKind) -> Self {
+        self.kind = kind;
+        self
+    }
+
+    pub fn with_octaves(mut self, octaves: u32) -> Self {
+        self.octaves = octaves;
+        self
+    }
+
+    pub fn with_frequency(mut self, frequency: f32) -> Self {
+        self.frequency = frequency;
+        self
+    }
+
+    pub fn sample2(&self, noise: &Noise, x: f32, y: f32) -> f32 {
+        self.accumulate(|octave, frequency| {
+            noise
+                .offset(octave)
+                .sample2(self.kind, x * frequency, y * frequency)
+        })
+    }
+
+    pub fn sample3(&self, noise: &Noise, x: f32, y: f32, z: f32) -> f32 {
+        self.accumulate(|octave, frequency| {
+            noise
+                .offset(octave)
+                .sample3(self.kind, x * frequency, y * frequency, z * frequency)
+        })
+    }
+
+    // Repeats every period units on both axes. Only Perlin noise tiles, so the kind is
+    // ignored, and every octave has to fit a whole number of lattice cells into the period:
+    // the frequencies are rounded to make it so
+    pub fn sample2_tiled(&self, noise: &Noise, x: f32, y: f32, period: [f32; 2]) -> f32 {
+        self.accumulate(|octave, frequency| {
+            let cells = period.map(|period| (period * frequency).round().max(1.0));
+            noise.offset(octave).perlin2_tiled(
+                x / period[0] * cells[0],
+                y / period[1] * cells[1],
+                cells.map(|cells| cells as u32),
+            )
+        })
+    }
+
+    fn accumulate<F: Fn(u32, f32) -> f32>(&self, octave: F) -> f32 {
+        let mut sum = 0.0;
+        let mut total = 0.0;
+        let mut amplitude = 1.0;
+        let mut frequency = self.frequency;
+        for index in 0..self.octaves.max(1) {
+            sum += octave(index, frequency) * amplitude;
+            total += amplitude;
+            amplitude *= self.gain;
+            frequency *= self.lacunarity;
+        }
+        sum / total
+    }
+}
+
+// Tileable RGBA8 image of fBm, each channel from its own seed and mapped from [-1, 1] to
+// [0, 255]. The texture repeats every `cells` lattice cells of the first octave
+pub fn bake(noise: &Noise, fbm: &Fbm, size: u32, cells: u32) -> Vec<u8> {
+    let _span = tracing::info_span!("bake_noise", size).entered();
+    let period = cells.max(1) as f32;
+    let fbm = fbm.with_frequency(1.0);
+    let mut data = vec![0; (size * size * 4) as usize];
+    data.par_chunks_mut((size * 4) as usize)
+        .enumerate()
+        .for_each(|(y, row)| {
+            for (x, pixel) in row.chunks_exact_mut(4).enumerate() {
+                let u = (x as f32 + 0.5) / size as f32 * period;
+                let v = (y as f32 + 0.5) / size as f32 * period;
+                for (channel, value) in pixel.iter_mut().enumerate() {
+                    let sample =
+                        fbm.sample2_tiled(&noise.offset(channel as u32 + 1), u, v, [period; 2]);
+                    *value = ((sample * 0.5 + 0.5).clamp(0.0, 1.0) * 255.0).round() as u8;
+                }
+            }
+        });
+    data
+}
+
+// Bakes the noise on the CPU and uploads it under the name, for shaders which sample noise
+// instead of computing it. The default sampler repeats, so the texture can be tiled
+pub fn bake_texture(
+    textures: &mut TextureRegistry,
+    name: &str,
+    noise: &Noise,
+    fbm: &Fbm,
+    size: u32,
+    cells: u32,
+) -> Result<Arc<SampledTexture>, Error> {
+    let data = bake(noise, fbm, size, cells);
+    textures.create_from_data(name, size, size, Format::R8G8B8A8_UNORM, &data)
+}
@@ -0,0 +1,285 @@
+use nalgebra::{Point3, Vector3};
+
+// Arc length samples per segment, distances between them are interpolated linearly
+const ARC_SAMPLES: usize = 16;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SplineKind {
+    // Passes through every point
+    CatmullRom,
+    // Cubic segments sharing their end points: anchor, control, control, anchor, control, ...
+    Bezier,
+}
+
+// Position and orientation on a spline, right and up are perpendicular to forward
+#[derive(Clone, Copy, Debug)]
+pub struct SplineFrame {
+    pub position: Point3<f32>,
+    pub forward: Vector3<f32>,
+    pub right: Vector3<f32>,
+    pub up: Vector3<f32>,
+}
+
+// Piecewise cubic curve. Positions are given by a parameter going from 0 to the segment count,
+// or by the distance along the curve through a precomputed arc length table
+#[derive(Clone, Debug)]
+pub struct Spline {
+    kind: SplineKind,
+    points: Vec<Point3<f32>>,
+    closed: bool,
+    // Distance from the start at each sample, the last one is the length
+    lengths: Vec<f32>,
+}
+
+pub fn catmull_rom(
+    p0: &Vector3<f32>,
+    p1: &Vector3<f32>,
+    p2: &Vector3<f32>,
+    p3: &Vector3<f32>,
+    t: f32,
+) -> Vector3<f32> {
+    let t2 = t * t;
+    let t3 = t2 * t;
+    (p1 * 2.0
+        + (p2 - p0) * t
+        + (p0 * 2.0 - p1 * 5.0 + p2 * 4.0 - p3) * t2
+        + (p1 * 3.0 - p0 - p2 * 3.0 + p3) * t3)
+        * 0.5
+}
+
+pub fn catmull_rom_derivative(
+    p0: &Vector3<f32>,
+    p1: &Vector3<f32>,
+    p2: &Vector3<f32>,
+    p3: &Vector3<f32>,
+    t: f32,
+) -> Vector3<f32> {
+    ((p2 - p0)
+        + (p0 * 2.0 - p1 * 5.0 + p2 * 4.0 - p3) * (2.0 * t)
+        + (p1 * 3.0 - p0 - p2 * 3.0 + p3) * (3.0 * t * t))
+        * 0.5
+}
+
+pub fn bezier(
+    p0: &Vector3<f32>,
+    p1: &Vector3<f32>,
+    p2: &Vector3<f32>,
+    p3: &Vector3<f32>,
+    t: f32,
+) -> Vector3<f32> {
+    let u = 1.0 - t;
+    p0 * (u * u * u) + p1 * (3.0 * u * u * t) + p2 * (3.0 * u * t * t) + p3 * (t * t * t)
+}
+
+pub fn bezier_derivative(
+    p0: &Vector3<f32>,
+    p1: &Vector3<f32>,
+    p2: &Vector3<f32>,
+    p3: &Vector3<f32>,
+    t: f32,
+) -> Vector3<f32> {
+    let u = 1.0 - t;
+    (p1 - p0) * (3.0 * u * u) + (p2 - p1) * (6.0 * u * t) + (p3 - p2) * (3.0 * t * t)
+}
+
+impl Spline {
+    pub fn catmull_rom(points: Vec<Point3<f32>>) -> Self {
+        Self::new(SplineKind::CatmullRom, points, false)
+    }
+
+    // Catmull-Rom spline which goes on from the last point back to the first
+    pub fn closed_catmull_rom(points: Vec<Point3<f32>>) -> Self {
+        Self::new(SplineKind::CatmullRom, points, true)
+    }
+
+    // Points after the last whole segment are ignored
+    pub fn bezier(points: Vec<Point3<f32>>) -> Self {
+        Self::new(SplineKind::Bezier, points, false)
+    }
+
+    pub fn new(kind: SplineKind, points: Vec<Point3<f32>>, closed: bool) -> Self {
+        let mut spline = Self {
+            kind,
+            points,
+            closed,
+            lengths: vec![],
+        };
+        spline.update_lengths();
+        spline
+    }
+
+    #[inline]
+    pub const fn kind(&self) -> SplineKind {
+        self.kind
+    }
+
+    #[inline]
+    pub const fn is_closed(&self) -> bool {
+        self.closed
+    }
+
+    #[inline]
+    pub fn points(&self) -> &[Point3<f32>] {
+        &self.points
+    }
+
+    pub fn set_points(&mut self, points: Vec<Point3<f32>>) {
+        self.points = points;
+        self.update_lengths();
+    }
+
+    pub fn segment_count(&self) -> usize {
+        let count = self.points.len();
+        match self.kind {
+            SplineKind::CatmullRom if self.closed && count > 1 => count,
+            SplineKind::CatmullRom => count.saturating_sub(1),
+            SplineKind::Bezier => count.saturating_sub(1) / 3,
+        }
+    }
+
+    #[inline]
+    pub fn length(&self) -> f32 {
+        self.lengths.last().copied().unwrap_or(0.0)
+    }
+
+    // Position at a parameter between 0 and the segment count
+    pub fn evaluate(&self, t: f32) -> Point3<f32> {
+        match self.locate(t) {
+            Some((segment, t)) => {
+                let [p0, p1, p2, p3] = self.segment_points(segment);
+                Point3::from(match self.kind {
+                    SplineKind::CatmullRom => catmull_rom(&p0, &p1, &p2, &p3, t),
+                    SplineKind::Bezier => bezier(&p0, &p1, &p2, &p3, t),
+                })
+            }
+            None => self.points.first().copied().unwrap_or_else(Point3::origin),
+        }
+    }
+
+    // Not normalized, its length is the speed along the curve per unit of the parameter
+    pub fn derivative(&self, t: f32) -> Vector3<f32> {
+        match self.locate(t) {
+            Some((segment, t)) => {
+                let [p0, p1, p2, p3] = self.segment_points(segment);
+                match self.kind {
+                    SplineKind::CatmullRom => catmull_rom_derivative(&p0, &p1, &p2, &p3, t),
+                    SplineKind::Bezier => bezier_derivative(&p0, &p1, &p2, &p3, t),
+                }
+            }
+            None => Vector3::zeros(),
+        }
+    }
+
+    // Parameter at a distance along the curve. Closed splines wrap around, open ones clamp
+    pub fn parameter_at(&self, distance: f32) -> f32 {
+        let length = self.length();
+        if length <= 0.0 {
+            return 0.0;
+        }
+        let distance = if self.closed {
+            distance.rem_euclid(length)
+        } else {
+            distance.clamp(0.0, length)
+        };
+
+        let next = self
+            .lengths
+            .partition_point(|&sample| sample < distance)
+            .clamp(1, self.lengths.len() - 1);
+        let (start, end) = (self.lengths[next - 1], self.lengths[next]);
+        let fraction = if end > start {
+            (distance - start) / (end - start)
+        } else {
+            0.0
+        };
+        (next - 1) as f32 / ARC_SAMPLES as f32 + fraction / ARC_SAMPLES as f32
+    }
+
+    pub fn point_at(&self, distance: f32) -> Point3<f32> {
+        self.evaluate(self.parameter_at(distance))
+    }
+
+    // Unit direction of travel, zero where the curve doesn't move
+    pub fn direction_at(&self, distance: f32) -> Vector3<f32> {
+        self.derivative(self.parameter_at(distance))
+            .try_normalize(f32::EPSILON)
+            .unwrap_or_else(Vector3::zeros)
+    }
+
+    // Frame with its up as close to the given one as the direction allows. Where the curve
+    // goes straight up or down another axis is used, so the frame may turn around there
+    pub fn frame_at(&self, distance: f32, up: &Vector3<f32>) -> SplineFrame {
+        let forward = self.direction_at(distance);
+        let forward = if forward == Vector3::zeros() {
+            Vector3::z()
+        } else {
+            forward
+        };
+        let right = forward
+            .cross(up)
+            .try_normalize(1e-4)
+            .or_else(|| forward.cross(&Vector3::z()).try_normalize(1e-4))
+            .unwrap_or_else(Vector3::x);
+        SplineFrame {
+            position: self.point_at(distance),
+            forward,
+            right,
+            up: right.cross(&forward),
+        }
+    }
+
+    // Segment and the parameter within it
+    fn locate(&self, t: f32) -> Option<(usize, f32)> {
+        let segments = self.segment_count();
+        if segments == 0 {
+            return None;
+        }
+        let t = if self.closed {
+            t.rem_euclid(segments as f32)
+        } else {
+            t.clamp(0.0, segments as f32)
+        };
+        let segment = (t as usize).min(segments - 1);
+        Some((segment, t - segment as f32))
+    }
+
+    fn segment_points(&self, segment: usize) -> [Vector3<f32>; 4] {
+        match self.kind {
+            SplineKind::CatmullRom => {
+                let count = self.points.len() as isize;
+                let point = |index: isize| {
+                    let index = if self.closed {
+                        index.rem_euclid(count)
+                    } else {
+                        index.clamp(0, count - 1)
+                    };
+                    self.points[index as usize].coords
+                };
+                let i = segment as isize;
+                [point(i - 1), point(i), point(i + 1), point(i + 2)]
+            }
+            SplineKind::Bezier => {
+                let i = segment * 3;
+                [0, 1, 2, 3].map(|offset| self.points[i + offset].coords)
+            }
+        }
+    }
+
+    fn update_lengths(&mut self) {
+        let samples = self.segment_count() * ARC_SAMPLES;
+        self.lengths.clear();
+        if samples == 0 {
+            return;
+        }
+
+        let mut length = 0.0;
+        let mut previous = self.evaluate(0.0);
+        self.lengths.push(0.0);
+        for i in 1..=samples {
+            let point = self.evaluate(i as f32 / ARC_SAMPLES as f32);
+            length += (point - previous).norm();
+            self.lengths.push(length);
+            previous = point;
+        }
+    }
+}
@@ -0,0 +1,309 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::Error;
+
+pub const MOUSE_SENSITIVITY: &str = "input.mouse_sensitivity";
+pub const MOUSE_INVERT_Y: &str = "input.mouse_invert_y";
+pub const MOUSE_SMOOTHING: &str = "input.mouse_smoothing";
+pub const MOUSE_ACCELERATION: &str = "input.mouse_acceleration";
+pub const MASTER_VOLUME: &str = "audio.master_volume";
+pub const WINDOW_MODE: &str = "window.mode";
+pub const PAUSE_ON_FOCUS_LOSS: &str = "window.pause_on_focus_loss";
+// Applied on the next start
+pub const MSAA_SAMPLES: &str = "graphics.msaa_samples";
+pub const SWAPCHAIN_IMAGES: &str = "graphics.swapchain_images";
+// Used if the surface supports it, applied on the next start
+pub const HDR_OUTPUT: &str = "graphics.hdr_output";
+pub const GAMMA: &str = "graphics.gamma";
+pub const CONTRAST: &str = "graphics.contrast";
+pub const BRIGHTNESS: &str = "graphics.brightness";
+pub const PAPER_WHITE: &str = "graphics.paper_white";
+// Width over height, 0 fills the whole window
+pub const ASPECT_RATIO: &str = "graphics.aspect_ratio";
+// Only used if the device supports multi-draw indirect
+pub const INDIRECT_DRAW: &str = "graphics.indirect_draw";
+pub const GPU_CULLING: &str = "graphics.gpu_culling";
+pub const LIGHT_SHAFTS: &str = "graphics.light_shafts";
+pub const LIGHT_SHAFT_INTENSITY: &str = "graphics.light_shaft_intensity";
+pub const LIGHT_SHAFT_LENGTH: &str = "graphics.light_shaft_length";
+pub const COLOR_GRADING: &str = "graphics.color_grading";
+// Scales the intensity scenes grade with, in [0, 1]
+pub const COLOR_GRADING_INTENSITY: &str = "graphics.color_grading_intensity";
+pub const SHADOWS: &str = "graphics.shadows";
+// Shadow maps rendered per frame, a point light takes six
+pub const SHADOW_BUDGET: &str = "graphics.shadow_budget";
+// Pixels on each side of a shadow map, applied on the next start
+pub const SHADOW_RESOLUTION: &str = "graphics.shadow_resolution";
+pub const DEBUG_GRID: &str = "debug.grid";
+pub const DEBUG_AXES: &str = "debug.axes";
+pub const DEBUG_GRID_SPACING: &str = "debug.grid_spacing";
+pub const DEBUG_GRID_FADE: &str = "debug.grid_fade_distance";
+pub const SNAP: &str = "editor.snap";
+pub const SNAP_TRANSLATION: &str = "editor.snap_translation";
+pub const SNAP_ROTATION: &str = "editor.snap_rotation";
+pub const SNAP_SCALE: &str = "editor.snap_scale";
+pub const MINIMAP: &str = "hud.minimap";
+// World units across the minimap
+pub const MINIMAP_ZOOM: &str = "hud.minimap_zoom";
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum PreferenceValue {
+    Bool(bool),
+    Integer(i64),
+    Float(f64),
+    Text(String),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WindowMode {
+    Windowed,
+    Fullscreen,
+}
+
+// Player preferences, stored as a flat key/value table in the platform config directory.
+// Changes are only kept in memory until save_if_dirty() is called
+pub struct Preferences {
+    values: BTreeMap<String, PreferenceValue>,
+    path: PathBuf,
+    dirty: bool,
+}
+
+// Defaults only, never saved. Used by golden runs and bakes, which must neither depend on nor
+// change the player's settings
+impl Default for Preferences {
+    fn default() -> Self {
+        Self {
+            values: BTreeMap::new(),
+            path: PathBuf::new(),
+            dirty: false,
+        }
+    }
+}
+
+impl From<bool> for PreferenceValue {
+    fn from(value: bool) -> Self {
+        Self::Bool(value)
+    }
+}
+
+impl From<i64> for PreferenceValue {
+    fn from(value: i64) -> Self {
+        Self::Integer(value)
+    }
+}
+
+impl From<f64> for PreferenceValue {
+    fn from(value: f64) -> Self {
+        Self::Float(value)
+    }
+}
+
+impl From<f32> for PreferenceValue {
+    fn from(value: f32) -> Self {
+        Self::Float(value as f64)
+    }
+}
+
+impl From<String> for PreferenceValue {
+    fn from(value: String) -> Self {
+        Self::Text(value)
+    }
+}
+
+impl From<&str> for PreferenceValue {
+    fn from(value: &str) -> Self {
+        Self::Text(value.to_owned())
+    }
+}
+
+impl WindowMode {
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Windowed => "windowed",
+            Self::Fullscreen => "fullscreen",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "windowed" => Some(Self::Windowed),
+            "fullscreen" => Some(Self::Fullscreen),
+            _ => None,
+        }
+    }
+}
+
+impl Preferences {
+    pub const FILE_NAME: &'static str = "preferences.toml";
+
+    // Falls back to the working directory if the platform has no config directory
+    pub fn default_path() -> PathBuf {
+        dirs::config_dir()
+            .map(|dir| dir.join("proper"))
+            .unwrap_or_default()
+            .join(Self::FILE_NAME)
+    }
+
+    pub fn load_or_default<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let values = if path.exists() {
+            let text = std::fs::read_to_string(path).map_err(|err| Error::file(path, err))?;
+            toml::from_str(&text).map_err(|err| Error::asset_parse(path, err))?
+        } else {
+            BTreeMap::new()
+        };
+
+        Ok(Self {
+            values,
+            path: path.to_owned(),
+            dirty: false,
+        })
+    }
+
+    #[inline]
+    pub fn get(&self, key: &str) -> Option<&PreferenceValue> {
+        self.values.get(key)
+    }
+
+    pub fn set<V: Into<PreferenceValue>>(&mut self, key: &str, value: V) {
+        let value = value.into();
+        if self.values.get(key) != Some(&value) {
+            self.values.insert(key.to_owned(), value);
+            self.dirty = true;
+        }
+    }
+
+    pub fn remove(&mut self, key: &str) {
+        if self.values.remove(key).is_some() {
+            self.dirty = true;
+        }
+    }
+
+    pub fn bool(&self, key: &str, default: bool) -> bool {
+        match self.values.get(key) {
+            Some(&PreferenceValue::Bool(value)) => value,
+            _ => default,
+        }
+    }
+
+    pub fn integer(&self, key: &str, default: i64) -> i64 {
+        match self.values.get(key) {
+            Some(&PreferenceValue::Integer(value)) => value,
+            _ => default,
+        }
+    }
+
+    // Integers written by hand ("volume = 1") are accepted too
+    pub fn float(&self, key: &str, default: f64) -> f64 {
+        match self.values.get(key) {
+            Some(&PreferenceValue::Float(value)) => value,
+            Some(&PreferenceValue::Integer(value)) => value as f64,
+            _ => default,
+        }
+    }
+
+    pub fn text<'a>(&'a self, key: &str, default: &'a str) -> &'a str {
+        match self.values.get(key) {
+            Some(PreferenceValue::Text(value)) => value,
+            _ => default,
+        }
+    }
+
+    pub fn master_volume(&self) -> f32 {
+        self.float(MASTER_VOLUME, 1.0) as f32
+    }
+
+    pub fn window_mode(&self) -> WindowMode {
+        WindowMode::from_name(self.text(WINDOW_MODE, "")).unwrap_or(WindowMode::Windowed)
+    }
+
+    pub fn pause_on_focus_loss(&self) -> bool {
+        self.bool(PAUSE_ON_FOCUS_LOSS, true)
+    }
+
+    pub fn msaa_samples(&self) -> u32 {
+        self.integer(MSAA_SAMPLES, 4).clamp(1, 64) as u32
+    }
+
+    // 2 for double buffering, 3 for triple buffering (mailbox presentation if available)
+    pub fn swapchain_images(&self) -> u32 {
+        self.integer(SWAPCHAIN_IMAGES, 3).clamp(2, 3) as u32
+    }
+
+    pub fn hdr_output(&self) -> bool {
+        self.bool(HDR_OUTPUT, false)
+    }
+
+    #[inline]
+    pub const fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    pub fn save(&mut self) -> Result<(), Error> {
+        if self.path.as_os_str().is_empty() {
+            self.dirty = false;
+            return Ok(());
+        }
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent).map_err(|err| Error::file(parent, err))?;
+        }
+        let text = toml::to_string_pretty(&self.values)
+            .map_err(|err| Error::data_serialize(&self.path, err))?;
+        std::fs::write(&self.path, text).map_err(|err| Error::file(&self.path, err))?;
+        self.dirty = false;
+        Ok(())
+    }
+
+    pub fn save_if_dirty(&mut self) -> Result<(), Error> {
+        if self.dirty {
+            self.save()
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn save_and_load() {
+        let path = std::env::temp_dir()
+            .join(format!("proper-test-{}", std::process::id()))
+            .join(Preferences::FILE_NAME);
+
+        let mut preferences = Preferences::load_or_default(&path).unwrap();
+        assert_eq!(preferences.get(MASTER_VOLUME), None);
+        preferences.set(MASTER_VOLUME, 0.5);
+        preferences.set(MSAA_SAMPLES, 8i64);
+        preferences.set(SHADOWS, false);
+        preferences.set(WINDOW_MODE, WindowMode::Fullscreen.name());
+        assert!(preferences.is_dirty());
+        preferences.save_if_dirty().unwrap();
+        assert!(!preferences.is_dirty());
+
+        let loaded = Preferences::load_or_default(&path).unwrap();
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+        assert_eq!(loaded.master_volume(), 0.5);
+        assert_eq!(loaded.msaa_samples(), 8);
+        assert!(!loaded.bool(SHADOWS, true));
+        assert_eq!(loaded.window_mode(), WindowMode::Fullscreen);
+        assert!(!loaded.is_dirty());
+    }
+
+    #[test]
+    fn defaults_are_never_saved() {
+        let mut preferences = Preferences::default();
+        preferences.set(MASTER_VOLUME, 0.25);
+        preferences.save_if_dirty().unwrap();
+        assert!(!preferences.is_dirty());
+        assert_eq!(preferences.master_volume(), 0.25);
+    }
+}
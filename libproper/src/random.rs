@@ -0,0 +1,46 @@
+use rand::{rngs::StdRng, SeedableRng};
+
+const FNV_OFFSET: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
+
+// Source of every random number in the engine. Each consumer gets its own stream, derived
+// from the master seed and the stream name, so extra draws in one system don't change what
+// the others see and a run can be replayed from the seed alone
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Random {
+    seed: u64,
+}
+
+// Stable across runs and builds, unlike the std hasher
+fn hash_name(name: &str) -> u64 {
+    name.bytes().fold(FNV_OFFSET, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(FNV_PRIME)
+    })
+}
+
+// SplitMix64 finalizer, spreads seeds which differ in a few bits
+fn mix(mut value: u64) -> u64 {
+    value = (value ^ (value >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    value = (value ^ (value >> 27)).wrapping_mul(0x94d049bb133111eb);
+    value ^ (value >> 31)
+}
+
+impl Random {
+    pub const fn new(seed: u64) -> Self {
+        Self { seed }
+    }
+
+    pub fn from_entropy() -> Self {
+        Self::new(rand::random())
+    }
+
+    #[inline]
+    pub const fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    // Same name and seed always give the same sequence
+    pub fn stream(&self, name: &str) -> StdRng {
+        StdRng::seed_from_u64(mix(self.seed ^ hash_name(name)))
+    }
+}
@@ -0,0 +1,255 @@
+use std::{
+    marker::PhantomData,
+    ops::Range,
+    sync::{Arc, Mutex},
+};
+
+use bytemuck::Pod;
+use vulkano::{
+    buffer::{BufferUsage, CpuAccessibleBuffer, TypedBufferAccess},
+    descriptor_set::{
+        layout::{
+            DescriptorSetLayout, DescriptorSetLayoutBinding, DescriptorSetLayoutCreateInfo,
+            DescriptorType,
+        },
+        DescriptorSet, DescriptorSetWithOffsets, PersistentDescriptorSet, WriteDescriptorSet,
+    },
+    device::Device,
+    shader::ShaderStages,
+    DeviceSize,
+};
+
+use crate::{error::Error, lock::Recover};
+
+use super::memory::{AllocationCategory, GpuAllocation};
+
+// Set the model data is bound to, see uniforms.rs
+pub const MODEL_SET: usize = 2;
+// Slots per buffer, more buffers are added as needed
+const CHUNK_SLOTS: DeviceSize = 4096;
+
+// The chunk's copy read by the frames drawing to one swapchain image
+struct ChunkImage {
+    buffer: Arc<CpuAccessibleBuffer<[u8]>>,
+    set: Arc<PersistentDescriptorSet>,
+    // Bytes written since the copy was last flushed
+    dirty: Range<usize>,
+    _memory: GpuAllocation,
+}
+
+struct ArenaChunk {
+    // Slots are written here and copied to an image's buffer before a frame draws to it, so
+    // frames still in flight keep reading the values they were recorded with
+    data: Vec<u8>,
+    images: Vec<ChunkImage>,
+}
+
+#[derive(Default)]
+struct ArenaState {
+    chunks: Vec<ArenaChunk>,
+    // (chunk, slot) pairs released by dropped slots
+    free: Vec<(usize, u32)>,
+    // Slots of the last chunk handed out so far
+    next_slot: u32,
+}
+
+// Small uniform blocks of one type packed into a few big buffers instead of a buffer each.
+// Every chunk has one buffer and descriptor set per swapchain image, the set covers a single
+// slot which is picked with a dynamic offset when binding
+pub struct UniformArena<T> {
+    device: Arc<Device>,
+    layout: Arc<DescriptorSetLayout>,
+    stride: DeviceSize,
+    state: Arc<Mutex<ArenaState>>,
+    _marker: PhantomData<fn() -> T>,
+}
+
+// Returned to the arena when dropped
+pub struct ArenaSlot<T> {
+    state: Arc<Mutex<ArenaState>>,
+    chunk: usize,
+    slot: u32,
+    stride: DeviceSize,
+    _marker: PhantomData<fn() -> T>,
+}
+
+// Makes the auto-generated layout of a forward pipeline match the arena's one, so the
+// arena's descriptor sets can be bound to it
+pub fn use_dynamic_model_set(set_layouts: &mut [DescriptorSetLayoutCreateInfo]) {
+    let binding = set_layouts
+        .get_mut(MODEL_SET)
+        .and_then(|set| set.bindings.get_mut(&0));
+    if let Some(binding) = binding {
+        if binding.descriptor_type == DescriptorType::UniformBuffer {
+            binding.descriptor_type = DescriptorType::UniformBufferDynamic;
+            binding.stages = ShaderStages::all_graphics();
+        }
+    }
+}
+
+impl<T: Pod> UniformArena<T> {
+    pub fn new(device: Arc<Device>) -> Result<Self, Error> {
+        let alignment = device
+            .physical_device()
+            .properties()
+            .min_uniform_buffer_offset_alignment;
+        let size = std::mem::size_of::<T>() as DeviceSize;
+        let stride = (size + alignment - 1) / alignment * alignment;
+
+        let layout = DescriptorSetLayout::new(
+            device.clone(),
+            DescriptorSetLayoutCreateInfo {
+                bindings: [(
+                    0,
+                    DescriptorSetLayoutBinding {
+                        stages: ShaderStages::all_graphics(),
+                        ..DescriptorSetLayoutBinding::descriptor_type(
+                            DescriptorType::UniformBufferDynamic,
+                        )
+                    },
+                )]
+                .into(),
+                ..Default::default()
+            },
+        )?;
+
+        Ok(Self {
+            device,
+            layout,
+            stride,
+            state: Arc::new(Mutex::new(ArenaState::default())),
+            _marker: PhantomData,
+        })
+    }
+
+    pub fn allocate(&self) -> Result<ArenaSlot<T>, Error> {
+        let mut state = self.state.lock().recover();
+        let (chunk, slot) = match state.free.pop() {
+            Some(free) => free,
+            None => {
+                if state.chunks.is_empty() || state.next_slot as DeviceSize == CHUNK_SLOTS {
+                    let chunk = self.create_chunk();
+                    state.chunks.push(chunk);
+                    state.next_slot = 0;
+                }
+                state.next_slot += 1;
+                (state.chunks.len() - 1, state.next_slot - 1)
+            }
+        };
+
+        Ok(ArenaSlot {
+            state: self.state.clone(),
+            chunk,
+            slot,
+            stride: self.stride,
+            _marker: PhantomData,
+        })
+    }
+
+    // Copies the slots written since the image's last frame to its buffers. Called before a
+    // frame draws to the image, once the image's previous frame has finished
+    pub fn flush(&self, image: usize) -> Result<(), Error> {
+        let mut state = self.state.lock().recover();
+        for chunk in &mut state.chunks {
+            // Copies for an image are created the first time a frame draws to it
+            while chunk.images.len() <= image {
+                let copy = self.create_chunk_image(&chunk.data)?;
+                chunk.images.push(copy);
+            }
+
+            let copy = &mut chunk.images[image];
+            if !copy.dirty.is_empty() {
+                let dirty = std::mem::replace(&mut copy.dirty, 0..0);
+                copy.buffer.write()?[dirty.clone()].copy_from_slice(&chunk.data[dirty]);
+            }
+        }
+        Ok(())
+    }
+
+    fn create_chunk(&self) -> ArenaChunk {
+        let bytes = self.stride * CHUNK_SLOTS;
+        log::debug!(
+            "Allocating a {} byte uniform arena chunk for {}",
+            bytes,
+            std::any::type_name::<T>()
+        );
+        ArenaChunk {
+            data: vec![0; bytes as usize],
+            images: vec![],
+        }
+    }
+
+    fn create_chunk_image(&self, data: &[u8]) -> Result<ChunkImage, Error> {
+        let buffer = CpuAccessibleBuffer::from_iter(
+            self.device.clone(),
+            BufferUsage::uniform_buffer(),
+            false,
+            data.iter().copied(),
+        )?;
+        let slot = buffer
+            .into_buffer_slice()
+            .slice(0..std::mem::size_of::<T>() as DeviceSize)
+            .unwrap();
+        let set = PersistentDescriptorSet::new(
+            self.layout.clone(),
+            [WriteDescriptorSet::buffer(0, slot)],
+        )?;
+
+        Ok(ChunkImage {
+            buffer,
+            set,
+            dirty: 0..0,
+            _memory: GpuAllocation::new(AllocationCategory::Uniform, data.len() as u64),
+        })
+    }
+}
+
+impl<T> Clone for UniformArena<T> {
+    fn clone(&self) -> Self {
+        Self {
+            device: self.device.clone(),
+            layout: self.layout.clone(),
+            stride: self.stride,
+            state: self.state.clone(),
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<T: Pod> ArenaSlot<T> {
+    // Seen by the GPU once the arena is flushed for an image
+    pub fn write(&self, value: &T) {
+        let mut state = self.state.lock().recover();
+        let chunk = &mut state.chunks[self.chunk];
+        let start = (self.slot as DeviceSize * self.stride) as usize;
+        let bytes = bytemuck::bytes_of(value);
+        let end = start + bytes.len();
+        chunk.data[start..end].copy_from_slice(bytes);
+
+        for copy in &mut chunk.images {
+            copy.dirty = if copy.dirty.is_empty() {
+                start..end
+            } else {
+                copy.dirty.start.min(start)..copy.dirty.end.max(end)
+            };
+        }
+    }
+
+    // To be bound at MODEL_SET, the arena has to be flushed for the image first
+    pub fn descriptor_set(&self, image: usize) -> DescriptorSetWithOffsets {
+        self.state.lock().recover().chunks[self.chunk].images[image]
+            .set
+            .clone()
+            .offsets([(self.slot as DeviceSize * self.stride) as u32])
+    }
+}
+
+impl<T> Drop for ArenaSlot<T> {
+    fn drop(&mut self) {
+        self.state
+            .lock()
+            .recover()
+            .free
+            .push((self.chunk, self.slot));
+    }
+}
@@ -0,0 +1,66 @@
+use nalgebra::{Matrix4, Vector3};
+
+use crate::{
+    preferences::{Preferences, ASPECT_RATIO},
+    world::camera::Camera,
+};
+
+pub const ASPECT_PRESETS: [(&str, f32); 5] = [
+    ("Free", 0.0),
+    ("16:9", 16.0 / 9.0),
+    ("16:10", 16.0 / 10.0),
+    ("4:3", 4.0 / 3.0),
+    ("21:9", 64.0 / 27.0),
+];
+
+// Keeps the picture at the target aspect ratio whatever the window size, the rest of the
+// window is covered with letterbox or pillarbox bars by the screen pass. The scene is still
+// rendered to the whole window, with the projection squeezed so the area between the bars
+// shows exactly what a window of the target aspect would
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct AspectLock {
+    // Width over height, None renders to the whole window
+    pub target: Option<f32>,
+}
+
+impl AspectLock {
+    pub fn new(target: f32) -> Self {
+        Self {
+            target: Some(target),
+        }
+    }
+
+    pub fn from_preferences(preferences: &Preferences) -> Self {
+        let target = preferences.float(ASPECT_RATIO, 0.0) as f32;
+        Self {
+            target: (target > 0.0).then(|| target.clamp(0.25, 4.0)),
+        }
+    }
+
+    // Fraction of the window's width and height covered by each side's bar
+    pub fn bars(&self, width: f32, height: f32) -> [f32; 2] {
+        let [x, y] = self.scale(width, height);
+        [(1.0 - x) * 0.5, (1.0 - y) * 0.5]
+    }
+
+    // Size of the area between the bars relative to the window
+    pub fn scale(&self, width: f32, height: f32) -> [f32; 2] {
+        let aspect = width / height;
+        match self.target {
+            Some(target) if aspect > target => [target / aspect, 1.0],
+            Some(target) => [1.0, aspect / target],
+            None => [1.0, 1.0],
+        }
+    }
+
+    pub fn projection(&self, camera: &Camera, width: f32, height: f32) -> Matrix4<f32> {
+        match self.target {
+            Some(target) => {
+                let [x, y] = self.scale(width, height);
+                Matrix4::new_nonuniform_scaling(&Vector3::new(x, y, 1.0))
+                    * camera.projection_matrix(target)
+            }
+            None => camera.projection_matrix(width / height),
+        }
+    }
+}
@@ -0,0 +1,75 @@
+use std::{collections::BTreeMap, sync::Arc};
+
+use vulkano::descriptor_set::{
+    layout::DescriptorSetLayout, PersistentDescriptorSet, WriteDescriptorSet,
+};
+
+use crate::{error::Error, resource::texture::SampledTexture};
+
+// Size of the texture array in scene_bindless.frag, the device has to allow this many samplers
+// per stage for the bindless path to be used
+pub const BINDLESS_TEXTURE_COUNT: u32 = 1024;
+
+// Every texture used by bindless materials, in one array bound once per pipeline switch instead
+// of a descriptor set per material instance. Slot 0 and the slots not assigned yet point to the
+// placeholder, so the whole array is always valid. Textures stay referenced for as long as the
+// table lives
+pub struct BindlessTextures {
+    slots: Vec<Arc<SampledTexture>>,
+    indices: BTreeMap<usize, u32>,
+    set: Option<Arc<PersistentDescriptorSet>>,
+}
+
+impl BindlessTextures {
+    pub fn new(placeholder: Arc<SampledTexture>) -> Self {
+        Self {
+            slots: vec![placeholder],
+            indices: BTreeMap::new(),
+            set: None,
+        }
+    }
+
+    // Index to pass to the shader, the same texture always gets the same slot. Falls back to
+    // the placeholder once the array is full
+    pub fn index_of(&mut self, texture: &Arc<SampledTexture>) -> u32 {
+        let key = Arc::as_ptr(texture) as usize;
+        if let Some(&index) = self.indices.get(&key) {
+            return index;
+        }
+        if self.slots.len() >= BINDLESS_TEXTURE_COUNT as usize {
+            log::warn!(
+                "Bindless texture array is full ({} textures), using the placeholder",
+                BINDLESS_TEXTURE_COUNT
+            );
+            return 0;
+        }
+
+        let index = self.slots.len() as u32;
+        self.slots.push(texture.clone());
+        self.indices.insert(key, index);
+        // Rebuilt with the new texture on next use, command buffers keep the old one alive
+        self.set = None;
+        index
+    }
+
+    pub fn descriptor_set(
+        &mut self,
+        layout: &Arc<DescriptorSetLayout>,
+    ) -> Result<Arc<PersistentDescriptorSet>, Error> {
+        if let Some(set) = self.set.as_ref() {
+            return Ok(set.clone());
+        }
+
+        let placeholder = &self.slots[0];
+        let textures = (0..BINDLESS_TEXTURE_COUNT as usize).map(|index| {
+            let texture = self.slots.get(index).unwrap_or(placeholder);
+            (texture.image().clone(), texture.sampler().clone())
+        });
+        let set = PersistentDescriptorSet::new(
+            layout.clone(),
+            [WriteDescriptorSet::image_view_sampler_array(0, 0, textures)],
+        )?;
+        self.set = Some(set.clone());
+        Ok(set)
+    }
+}
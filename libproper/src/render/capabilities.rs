@@ -0,0 +1,182 @@
+use std::fmt::Write;
+
+use vulkano::{
+    device::{physical::PhysicalDevice, DeviceExtensions, Features},
+    image::SampleCounts,
+    Version,
+};
+
+use super::bindless::BINDLESS_TEXTURE_COUNT;
+
+// What the selected device supports, queried once at startup. Settings are checked against
+// this instead of failing in the middle of pipeline or image creation
+#[derive(Clone, Debug)]
+pub struct Capabilities {
+    pub device_name: String,
+    pub instance_version: Version,
+    pub device_version: Version,
+    // Sample counts supported by both color and depth attachments, ascending
+    pub msaa_samples: Vec<u32>,
+    pub sampler_anisotropy: bool,
+    pub max_anisotropy: f32,
+    pub texture_compression_bc: bool,
+    pub descriptor_indexing: bool,
+    pub sampled_image_array_dynamic_indexing: bool,
+    pub max_per_stage_samplers: u32,
+    // With a non-zero first instance, needed for indirect draws
+    pub multi_draw_indirect: bool,
+    pub max_image_dimension: u32,
+    pub max_push_constants_size: u32,
+    pub enabled_extensions: DeviceExtensions,
+}
+
+fn supports_samples(counts: &SampleCounts, samples: u32) -> bool {
+    match samples {
+        1 => counts.sample1,
+        2 => counts.sample2,
+        4 => counts.sample4,
+        8 => counts.sample8,
+        16 => counts.sample16,
+        32 => counts.sample32,
+        64 => counts.sample64,
+        _ => false,
+    }
+}
+
+impl Capabilities {
+    pub fn query(
+        physical: PhysicalDevice,
+        instance_version: Version,
+        enabled_extensions: DeviceExtensions,
+    ) -> Self {
+        let properties = physical.properties();
+        let features = physical.supported_features();
+        let device_version = properties.api_version.min(instance_version);
+        let msaa_samples = [1, 2, 4, 8, 16, 32, 64]
+            .into_iter()
+            .filter(|&samples| {
+                supports_samples(&properties.framebuffer_color_sample_counts, samples)
+                    && supports_samples(&properties.framebuffer_depth_sample_counts, samples)
+            })
+            .collect();
+
+        Self {
+            device_name: properties.device_name.clone(),
+            instance_version,
+            device_version,
+            msaa_samples,
+            sampler_anisotropy: features.sampler_anisotropy,
+            max_anisotropy: properties.max_sampler_anisotropy,
+            texture_compression_bc: features.texture_compression_bc,
+            // Core in 1.2, the extension isn't requested
+            descriptor_indexing: features.descriptor_indexing && device_version >= Version::V1_2,
+            sampled_image_array_dynamic_indexing: features
+                .shader_sampled_image_array_dynamic_indexing,
+            max_per_stage_samplers: properties
+                .max_per_stage_descriptor_samplers
+                .min(properties.max_per_stage_descriptor_sampled_images),
+            multi_draw_indirect: features.multi_draw_indirect
+                && features.draw_indirect_first_instance,
+            max_image_dimension: properties.max_image_dimension2_d,
+            max_push_constants_size: properties.max_push_constants_size,
+            enabled_extensions,
+        }
+    }
+
+    // Optional features turned on when the device has them
+    pub fn features(&self) -> Features {
+        Features {
+            sampler_anisotropy: self.sampler_anisotropy,
+            texture_compression_bc: self.texture_compression_bc,
+            descriptor_indexing: self.bindless_textures(),
+            shader_sampled_image_array_dynamic_indexing: self.bindless_textures(),
+            multi_draw_indirect: self.multi_draw_indirect,
+            draw_indirect_first_instance: self.multi_draw_indirect,
+            ..Features::none()
+        }
+    }
+
+    // Whether materials can index one big texture array instead of binding their own sets
+    pub fn bindless_textures(&self) -> bool {
+        self.descriptor_indexing
+            && self.sampled_image_array_dynamic_indexing
+            && self.max_per_stage_samplers >= BINDLESS_TEXTURE_COUNT
+    }
+
+    pub fn max_msaa_samples(&self) -> u32 {
+        self.msaa_samples.last().copied().unwrap_or(1)
+    }
+
+    // Largest supported count not above the requested one
+    pub fn clamp_msaa_samples(&self, samples: u32) -> u32 {
+        let supported = self
+            .msaa_samples
+            .iter()
+            .rev()
+            .copied()
+            .find(|&supported| supported <= samples)
+            .unwrap_or(1);
+        if supported != samples {
+            log::warn!(
+                "{} MSAA samples is not supported, using {}",
+                samples,
+                supported
+            );
+        }
+        supported
+    }
+
+    pub fn clamp_anisotropy(&self, anisotropy: f32) -> Option<f32> {
+        self.sampler_anisotropy
+            .then(|| anisotropy.clamp(1.0, self.max_anisotropy))
+    }
+
+    // Multi-line, for the log and crash reports
+    pub fn report(&self) -> String {
+        let yes_no = |value: bool| if value { "yes" } else { "no" };
+        let mut report = String::new();
+        writeln!(report, "Device: {}", self.device_name).ok();
+        writeln!(
+            report,
+            "  Vulkan: instance {}, device {}",
+            self.instance_version, self.device_version
+        )
+        .ok();
+        writeln!(report, "  MSAA samples: {:?}", self.msaa_samples).ok();
+        writeln!(
+            report,
+            "  Anisotropic filtering: {} (max {})",
+            yes_no(self.sampler_anisotropy),
+            self.max_anisotropy
+        )
+        .ok();
+        writeln!(
+            report,
+            "  BC texture compression: {}",
+            yes_no(self.texture_compression_bc)
+        )
+        .ok();
+        writeln!(
+            report,
+            "  Descriptor indexing: {} (bindless textures: {})",
+            yes_no(self.descriptor_indexing),
+            yes_no(self.bindless_textures())
+        )
+        .ok();
+        writeln!(
+            report,
+            "  Multi-draw indirect: {}",
+            yes_no(self.multi_draw_indirect)
+        )
+        .ok();
+        writeln!(report, "  Max 2D image size: {}", self.max_image_dimension).ok();
+        writeln!(
+            report,
+            "  Max push constants: {} bytes",
+            self.max_push_constants_size
+        )
+        .ok();
+        write!(report, "  Extensions: {:?}", self.enabled_extensions).ok();
+        report
+    }
+}
@@ -0,0 +1,61 @@
+use image::RgbaImage;
+use vulkano::{
+    buffer::{BufferUsage, CpuAccessibleBuffer},
+    command_buffer::{AutoCommandBufferBuilder, CommandBufferUsage, CopyImageToBufferInfo},
+    format::Format,
+    image::ImageAccess,
+    sync::GpuFuture,
+};
+
+use crate::error::Error;
+
+use super::frame::Frame;
+
+// Copies the frame's image, which the layers below have finished drawing into. Waits for
+// the copy, so it's only meant for tools and tests
+pub fn capture_frame(
+    in_future: Box<dyn GpuFuture>,
+    frame: &Frame,
+) -> Result<(RgbaImage, Box<dyn GpuFuture>), Error> {
+    let image = frame.destination.image().clone();
+    let [width, height, _] = image.dimensions().width_height_depth();
+    let format = image.format();
+    let bgra = match format {
+        Format::B8G8R8A8_SRGB | Format::B8G8R8A8_UNORM => true,
+        Format::R8G8B8A8_SRGB | Format::R8G8B8A8_UNORM => false,
+        format => return Err(Error::UnsupportedCaptureFormat(format)),
+    };
+
+    let queue = &frame.gfx_queue;
+    let buffer = unsafe {
+        CpuAccessibleBuffer::<[u8]>::uninitialized_array(
+            queue.device().clone(),
+            (width * height * 4) as u64,
+            BufferUsage::transfer_dst(),
+            true,
+        )?
+    };
+    let mut builder = AutoCommandBufferBuilder::primary(
+        queue.device().clone(),
+        queue.family(),
+        CommandBufferUsage::OneTimeSubmit,
+    )?;
+    builder.copy_image_to_buffer(CopyImageToBufferInfo::image_buffer(image, buffer.clone()))?;
+    let future = in_future
+        .then_execute(queue.clone(), builder.build()?)?
+        .then_signal_fence_and_flush()?;
+    future.wait(None)?;
+
+    let mut data = buffer.read()?.to_vec();
+    if bgra {
+        for pixel in data.chunks_exact_mut(4) {
+            pixel.swap(0, 2);
+        }
+    }
+    let actual = data.len();
+    let image = RgbaImage::from_raw(width, height, data).ok_or(Error::TextureDataSize {
+        expected: (width * height * 4) as u64,
+        actual,
+    })?;
+    Ok((image, Box::new(future)))
+}
@@ -0,0 +1,149 @@
+use nalgebra::{Matrix4, Point3, Vector4};
+
+// Screen tiles across and down, then depth slices. The size is passed to the shader in
+// Light_Data, so it can change without touching lighting.glsl
+pub const CLUSTER_GRID: [u32; 3] = [16, 9, 24];
+pub const CLUSTER_COUNT: usize = (CLUSTER_GRID[0] * CLUSTER_GRID[1] * CLUSTER_GRID[2]) as usize;
+// Light references over all clusters, lights past it are left out of the clusters they'd go in
+pub const MAX_CLUSTER_INDICES: usize = CLUSTER_COUNT * 32;
+
+// Light lists of the view frustum split into clusters: screen tiles, sliced exponentially
+// in view depth so the slices near the camera stay thin. Built on the CPU each frame by
+// testing every light's bounding sphere, fragments only go through the lights of their own
+// cluster
+#[derive(Default)]
+pub struct LightClusters {
+    // Offset into the indices and light count of every cluster, x fastest, then y, then depth
+    clusters: Vec<[u32; 2]>,
+    indices: Vec<u32>,
+    // Per-cluster lists, kept between frames for their allocations
+    lists: Vec<Vec<u32>>,
+    near: f32,
+    far: f32,
+}
+
+impl LightClusters {
+    #[inline]
+    pub fn clusters(&self) -> &[[u32; 2]] {
+        &self.clusters
+    }
+
+    #[inline]
+    pub fn indices(&self) -> &[u32] {
+        &self.indices
+    }
+
+    // Framebuffer size, near plane and the log of the depth range, as lighting.glsl
+    // expects them
+    pub fn params(&self, width: f32, height: f32) -> [f32; 4] {
+        [width, height, self.near, (self.far / self.near).ln()]
+    }
+
+    // Lights are given by their position and range, indices refer to their order. The view
+    // and projection must be the ones the scene is drawn with
+    pub fn build<I: IntoIterator<Item = (Point3<f32>, f32)>>(
+        &mut self,
+        lights: I,
+        view: &Matrix4<f32>,
+        projection: &Matrix4<f32>,
+        near: f32,
+        far: f32,
+    ) {
+        let _span = tracing::info_span!("build_light_clusters").entered();
+        self.near = near.max(1e-3);
+        self.far = far.max(self.near * 2.0);
+        self.lists.resize_with(CLUSTER_COUNT, Vec::new);
+        for list in self.lists.iter_mut() {
+            list.clear();
+        }
+
+        for (index, (position, range)) in lights.into_iter().enumerate() {
+            let center = view * position.to_homogeneous();
+            let [x, y, z] = match self.cluster_range(&center, range, projection) {
+                Some(range) => range,
+                None => continue,
+            };
+            for slice in z[0]..=z[1] {
+                for row in y[0]..=y[1] {
+                    for column in x[0]..=x[1] {
+                        self.lists[cluster_index(column, row, slice)].push(index as u32);
+                    }
+                }
+            }
+        }
+
+        self.clusters.clear();
+        self.indices.clear();
+        for list in self.lists.iter() {
+            let count = list.len().min(MAX_CLUSTER_INDICES - self.indices.len());
+            self.clusters
+                .push([self.indices.len() as u32, count as u32]);
+            self.indices.extend_from_slice(&list[..count]);
+        }
+    }
+
+    // Inclusive column, row and slice ranges touched by a sphere in view space, None if it's
+    // outside the frustum. Conservative: the box around the sphere is projected instead of
+    // the sphere itself
+    fn cluster_range(
+        &self,
+        center: &Vector4<f32>,
+        radius: f32,
+        projection: &Matrix4<f32>,
+    ) -> Option<[[u32; 2]; 3]> {
+        // The camera looks down -Z
+        let (nearest, farthest) = (-center.z - radius, -center.z + radius);
+        if farthest < self.near || nearest > self.far {
+            return None;
+        }
+        let z = [self.slice(nearest), self.slice(farthest)];
+
+        // Corners in front of the camera project fine, a sphere reaching behind the near
+        // plane may cover any part of the screen
+        let full = [[0, CLUSTER_GRID[0] - 1], [0, CLUSTER_GRID[1] - 1], z];
+        if nearest <= self.near {
+            return Some(full);
+        }
+
+        let mut min = [f32::MAX; 2];
+        let mut max = [f32::MIN; 2];
+        for corner in 0..8 {
+            let offset = Vector4::new(
+                if corner & 1 == 0 { -radius } else { radius },
+                if corner & 2 == 0 { -radius } else { radius },
+                if corner & 4 == 0 { -radius } else { radius },
+                0.0,
+            );
+            let clip = projection * (center + offset);
+            for axis in 0..2 {
+                let ndc = clip[axis] / clip.w;
+                min[axis] = min[axis].min(ndc);
+                max[axis] = max[axis].max(ndc);
+            }
+        }
+        if (0..2).any(|axis| max[axis] < -1.0 || min[axis] > 1.0) {
+            return None;
+        }
+
+        let tile = |ndc: f32, axis: usize| {
+            let cells = CLUSTER_GRID[axis];
+            (((ndc * 0.5 + 0.5) * cells as f32).max(0.0) as u32).min(cells - 1)
+        };
+        Some([
+            [tile(min[0], 0), tile(max[0], 0)],
+            [tile(min[1], 1), tile(max[1], 1)],
+            z,
+        ])
+    }
+
+    fn slice(&self, depth: f32) -> u32 {
+        let slices = CLUSTER_GRID[2];
+        let t = (depth.max(self.near) / self.near).ln() / (self.far / self.near).ln();
+        ((t * slices as f32).max(0.0) as u32).min(slices - 1)
+    }
+}
+
+#[inline]
+const fn cluster_index(column: u32, row: u32, slice: u32) -> usize {
+    ((slice * CLUSTER_GRID[1] + row) * CLUSTER_GRID[0] + column) as usize
+}
@@ -0,0 +1,36 @@
+use crate::preferences::{Preferences, BRIGHTNESS, CONTRAST, GAMMA, PAPER_WHITE};
+
+// Lighting happens in linear space: textures are sampled from sRGB images, material colors
+// are linear and the scene is rendered into a floating point attachment. The screen pass
+// then applies these and either leaves the sRGB encoding to the swapchain or encodes HDR10
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct OutputSettings {
+    // Applied on top of the sRGB/PQ transfer function, 1 leaves the output unchanged
+    pub gamma: f32,
+    pub contrast: f32,
+    pub brightness: f32,
+    // Luminance in nits of a 1.0 scene value, HDR10 output only
+    pub paper_white: f32,
+}
+
+impl Default for OutputSettings {
+    fn default() -> Self {
+        Self {
+            gamma: 1.0,
+            contrast: 1.0,
+            brightness: 0.0,
+            paper_white: 200.0,
+        }
+    }
+}
+
+impl OutputSettings {
+    pub fn from_preferences(preferences: &Preferences) -> Self {
+        Self {
+            gamma: preferences.float(GAMMA, 1.0).clamp(0.5, 3.0) as f32,
+            contrast: preferences.float(CONTRAST, 1.0).clamp(0.0, 2.0) as f32,
+            brightness: preferences.float(BRIGHTNESS, 0.0).clamp(-1.0, 1.0) as f32,
+            paper_white: preferences.float(PAPER_WHITE, 200.0).clamp(80.0, 1000.0) as f32,
+        }
+    }
+}
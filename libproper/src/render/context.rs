@@ -1,4 +1,4 @@
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
 use vulkano::{
     device::{
@@ -7,10 +7,12 @@ use vulkano::{
     },
     format::Format,
     image::{view::ImageView, ImageUsage, SwapchainImage},
-    instance::{Instance, InstanceCreateInfo},
+    instance::{Instance, InstanceCreateInfo, InstanceExtensions},
     pipeline::graphics::viewport::Viewport,
-    swapchain::{self, Surface, Swapchain, SwapchainCreateInfo},
-    sync::{self, GpuFuture},
+    swapchain::{
+        self, AcquireError, ColorSpace, PresentMode, Surface, Swapchain, SwapchainCreateInfo,
+    },
+    sync::{self, FenceSignalFuture, FlushError, GpuFuture},
 };
 use vulkano_win::VkSurfaceBuild;
 use winit::{
@@ -19,9 +21,17 @@ use winit::{
     window::{Window, WindowBuilder},
 };
 
-use crate::{error::Error, event::Event, layer::LayerManager};
+use crate::{
+    error::Error,
+    event::Event,
+    layer::LayerManager,
+    lock::Recover,
+    render::upload::{UploadId, UploadQueue},
+};
+
+use super::{capabilities::Capabilities, frame::Frame};
 
-use super::frame::Frame;
+type FrameFence = Arc<FenceSignalFuture<Box<dyn GpuFuture>>>;
 
 type SwapchainCreateOutput = (
     Arc<Swapchain<Window>>,
@@ -33,22 +43,37 @@ pub struct VulkanContext {
 
     device: Arc<Device>,
     queue: Arc<Queue>,
+    capabilities: Capabilities,
 
     format: Format,
+    color_space: ColorSpace,
     swapchain: Arc<Swapchain<Window>>,
     swapchain_images: Vec<Arc<ImageView<SwapchainImage<Window>>>>,
     viewport: Viewport,
     need_swapchain_recreation: bool,
+    // Per swapchain image, signaled when the frame which last drew to the image has finished.
+    // Resources kept per image can be written once it is
+    frame_fences: Vec<Option<FrameFence>>,
+    previous_image: Option<usize>,
 }
 
 impl VulkanContext {
+    // image_count and hdr are requests, the surface decides what's actually used
     pub fn new_windowed<T>(
         event_loop: &EventLoop<T>,
         window_builder: WindowBuilder,
+        image_count: u32,
+        hdr: bool,
     ) -> Result<Self, Error> {
         log::debug!("Creating new windowed vulkan context");
 
-        let instance_extensions = vulkano_win::required_extensions();
+        // HDR color spaces are only reported when the extension is enabled
+        let supported_extensions =
+            InstanceExtensions::supported_by_core().unwrap_or_else(|_| InstanceExtensions::none());
+        let instance_extensions = InstanceExtensions {
+            ext_swapchain_colorspace: hdr && supported_extensions.ext_swapchain_colorspace,
+            ..vulkano_win::required_extensions()
+        };
         let device_extensions = DeviceExtensions {
             khr_swapchain: true,
             khr_maintenance1: true,
@@ -62,26 +87,37 @@ impl VulkanContext {
 
         let surface = window_builder.build_vk_surface(event_loop, instance.clone())?;
 
-        let format = Format::B8G8R8A8_SRGB;
-
         let (physical, queue_family) = Self::select_physical_device(&instance, &surface)?;
+        let enabled_extensions = physical
+            .supported_extensions()
+            .intersection(&device_extensions);
+        let capabilities =
+            Capabilities::query(physical, instance.api_version(), enabled_extensions);
+        log::info!("{}", capabilities.report());
 
         let (device, mut queues) = Device::new(
             physical,
             DeviceCreateInfo {
                 queue_create_infos: vec![QueueCreateInfo::family(queue_family)],
-                enabled_extensions: physical
-                    .supported_extensions()
-                    .intersection(&device_extensions),
+                enabled_extensions,
+                // Optional, textures fall back to regular filtering without anisotropy
+                enabled_features: capabilities.features(),
                 ..Default::default()
             },
         )?;
         let queue = queues.next().unwrap();
 
-        let (swapchain, swapchain_images) =
-            Self::create_swapchain(device.clone(), surface.clone(), format)?;
+        let (format, color_space) = Self::select_format(&device, &surface, hdr)?;
+        let (swapchain, swapchain_images) = Self::create_swapchain(
+            device.clone(),
+            surface.clone(),
+            format,
+            color_space,
+            image_count,
+        )?;
 
         let viewport = Self::create_viewport(&surface);
+        let frame_fences = vec![None; swapchain_images.len()];
 
         log::debug!("Vulkan init finished");
 
@@ -89,11 +125,15 @@ impl VulkanContext {
             surface,
             device,
             queue,
+            capabilities,
             swapchain,
             swapchain_images,
             viewport,
             format,
+            color_space,
             need_swapchain_recreation: false,
+            frame_fences,
+            previous_image: None,
         })
     }
 
@@ -109,6 +149,11 @@ impl VulkanContext {
         &self.swapchain_images
     }
 
+    #[inline]
+    pub fn image_count(&self) -> usize {
+        self.swapchain_images.len()
+    }
+
     pub const fn viewport(&self) -> &Viewport {
         &self.viewport
     }
@@ -121,10 +166,43 @@ impl VulkanContext {
         self.surface.window().inner_size()
     }
 
+    // The screen pass has to encode PQ itself, unlike sRGB this isn't done by the hardware
+    #[inline]
+    pub fn is_hdr10(&self) -> bool {
+        self.color_space == ColorSpace::Hdr10St2084
+    }
+
     pub fn output_format(&self) -> Format {
         self.format
     }
 
+    #[inline]
+    pub const fn capabilities(&self) -> &Capabilities {
+        &self.capabilities
+    }
+
+    // Device name, type, driver and API version
+    pub fn device_summary(&self) -> String {
+        let properties = self.device.physical_device().properties();
+        format!(
+            "{} ({:?}), driver {}, Vulkan {}",
+            properties.device_name,
+            properties.device_type,
+            properties.driver_info.as_deref().unwrap_or("unknown"),
+            properties.api_version
+        )
+    }
+
+    // Total size of the device-local memory heaps
+    pub fn device_local_memory(&self) -> u64 {
+        self.device
+            .physical_device()
+            .memory_heaps()
+            .filter(|heap| heap.is_device_local())
+            .map(|heap| heap.size())
+            .sum()
+    }
+
     pub fn invalidate_surface(&mut self) {
         self.need_swapchain_recreation = true;
     }
@@ -133,9 +211,19 @@ impl VulkanContext {
         &mut self,
         flow: &mut ControlFlow,
         layer_manager: &mut LayerManager,
-    ) -> Result<(), Error> {
+        uploads: &Mutex<UploadQueue>,
+    ) -> Result<Vec<UploadId>, Error> {
+        let _span = tracing::info_span!("frame").entered();
+        let mut completed = vec![];
+
         if self.need_swapchain_recreation {
+            // Frames in flight may still be drawing to the old images
+            for image in 0..self.frame_fences.len() {
+                completed.extend(self.wait_for_image(image, uploads)?);
+            }
             let dimensions = self.recreate_swapchain()?;
+            self.frame_fences = vec![None; self.swapchain_images.len()];
+            self.previous_image = None;
 
             let event = Event::SwapchainInvalidated {
                 swapchain_images: &self.swapchain_images,
@@ -145,14 +233,41 @@ impl VulkanContext {
             layer_manager.notify_all(&event, flow)?;
         }
 
-        let (image_index, suboptimal, acquire_future) =
-            swapchain::acquire_next_image(self.swapchain.clone(), None)?;
+        let (image_index, suboptimal, acquire_future) = {
+            let _span = tracing::info_span!("acquire").entered();
+            match swapchain::acquire_next_image(self.swapchain.clone(), None) {
+                Ok(acquired) => acquired,
+                // The window was resized in the meantime, skip the frame
+                Err(AcquireError::OutOfDate) => {
+                    self.need_swapchain_recreation = true;
+                    return Ok(completed);
+                }
+                Err(err) => return Err(err.into()),
+            }
+        };
 
         if suboptimal {
             self.need_swapchain_recreation = true;
         }
 
-        let mut in_future: Box<dyn GpuFuture + 'static> = Box::new(acquire_future);
+        {
+            let _span = tracing::info_span!("wait_for_image").entered();
+            completed.extend(self.wait_for_image(image_index, uploads)?);
+        }
+
+        // Orders the frame after the previous one, which may still be using the attachments
+        // shared by all images
+        let previous = self
+            .previous_image
+            .and_then(|image| self.frame_fences[image].clone());
+        let mut in_future: Box<dyn GpuFuture> = match previous {
+            Some(fence) => fence.join(acquire_future).boxed(),
+            None => sync::now(self.device.clone()).join(acquire_future).boxed(),
+        };
+        let upload_batch = uploads.lock().recover().take_batch(image_index);
+        if let Some(batch) = upload_batch {
+            in_future = Box::new(in_future.join(batch));
+        }
         let frame = Frame {
             image_index,
             gfx_queue: self.queue.clone(),
@@ -160,18 +275,36 @@ impl VulkanContext {
             viewport: self.viewport.clone(),
         };
 
-        for layer in layer_manager.iter_mut() {
-            in_future = layer.on_draw(in_future, &frame)?;
-        }
+        in_future = layer_manager.draw(in_future, &frame)?;
 
-        let future = sync::now(self.device.clone())
-            .join(in_future)
+        let _span = tracing::info_span!("present").entered();
+        let future = in_future
             .then_swapchain_present(self.queue.clone(), self.swapchain.clone(), image_index)
-            .then_signal_fence_and_flush()?;
+            .boxed()
+            .then_signal_fence_and_flush();
+
+        self.previous_image = Some(image_index);
+        match future {
+            Ok(future) => self.frame_fences[image_index] = Some(Arc::new(future)),
+            // The frame was submitted, only presenting it failed
+            Err(FlushError::OutOfDate) => self.need_swapchain_recreation = true,
+            Err(err) => return Err(err.into()),
+        }
 
-        future.wait(None).unwrap();
+        Ok(completed)
+    }
 
-        Ok(())
+    // Returns the uploads which were submitted with the image's previous frame
+    fn wait_for_image(
+        &mut self,
+        image: usize,
+        uploads: &Mutex<UploadQueue>,
+    ) -> Result<Vec<UploadId>, Error> {
+        if let Some(fence) = self.frame_fences[image].take() {
+            fence.wait(None)?;
+        }
+        // The wait released vulkano's locks on what the frame used, staging blocks included
+        Ok(uploads.lock().recover().complete_batch(image))
     }
 
     fn recreate_swapchain(&mut self) -> Result<PhysicalSize<u32>, Error> {
@@ -214,30 +347,79 @@ impl VulkanContext {
             .ok_or(Error::NoPhysicalDevice)
     }
 
+    fn select_format(
+        device: &Arc<Device>,
+        surface: &Arc<Surface<Window>>,
+        hdr: bool,
+    ) -> Result<(Format, ColorSpace), Error> {
+        const HDR10: (Format, ColorSpace) =
+            (Format::A2B10G10R10_UNORM_PACK32, ColorSpace::Hdr10St2084);
+        const SDR: (Format, ColorSpace) = (Format::B8G8R8A8_SRGB, ColorSpace::SrgbNonLinear);
+
+        if hdr {
+            let formats = device
+                .physical_device()
+                .surface_formats(surface, Default::default())?;
+            if formats.contains(&HDR10) {
+                log::info!("Using HDR10 output");
+                return Ok(HDR10);
+            }
+            log::warn!("HDR output was requested, but the surface doesn't support HDR10");
+        }
+
+        Ok(SDR)
+    }
+
     fn create_swapchain(
         device: Arc<Device>,
         surface: Arc<Surface<Window>>,
         format: Format,
+        color_space: ColorSpace,
+        image_count: u32,
     ) -> Result<SwapchainCreateOutput, Error> {
         let caps = device
             .physical_device()
             .surface_capabilities(&surface, Default::default())?;
 
         let image_format = Some(format);
+        let max_image_count = caps.max_image_count.unwrap_or(u32::MAX);
+        let min_image_count = image_count.clamp(caps.min_image_count, max_image_count);
+        if min_image_count != image_count {
+            log::warn!(
+                "Surface doesn't support {} swapchain images, using {}",
+                image_count,
+                min_image_count
+            );
+        }
+
+        // Mailbox needs a third image to be any different from FIFO
+        let mailbox = device
+            .physical_device()
+            .surface_present_modes(&surface)?
+            .any(|mode| mode == PresentMode::Mailbox);
+        let present_mode = if min_image_count >= 3 && mailbox {
+            PresentMode::Mailbox
+        } else {
+            PresentMode::Fifo
+        };
 
         let (swapchain, images) = Swapchain::new(
             device,
             surface.clone(),
             SwapchainCreateInfo {
-                min_image_count: caps.min_image_count,
+                min_image_count,
+                present_mode,
                 image_extent: surface.window().inner_size().into(),
                 image_usage: ImageUsage {
                     color_attachment: true,
                     transfer_dst: true,
+                    // Lets the golden image tests and reflection bakes read the frames back
+                    transfer_src: caps.supported_usage_flags.transfer_src,
                     ..ImageUsage::none()
                 },
                 composite_alpha: caps.supported_composite_alpha.iter().next().unwrap(),
                 image_format,
+                image_color_space: color_space,
                 ..Default::default()
             },
         )?;
@@ -246,6 +428,11 @@ impl VulkanContext {
             .into_iter()
             .map(|image| ImageView::new_default(image).map_err(Error::from))
             .collect::<Result<Vec<_>, _>>()?;
+        log::debug!(
+            "Swapchain has {} images, {:?} present mode",
+            swapchain_images.len(),
+            present_mode
+        );
 
         Ok((swapchain, swapchain_images))
     }
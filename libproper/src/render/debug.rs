@@ -0,0 +1,42 @@
+use crate::preferences::{
+    Preferences, DEBUG_AXES, DEBUG_GRID, DEBUG_GRID_FADE, DEBUG_GRID_SPACING,
+};
+
+// Helpers drawn in the world pass on top of the scene geometry
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DebugViewSettings {
+    // Infinite grid on the Y=0 plane
+    pub grid: bool,
+    // Axis lines through the origin: X red, Y green, Z blue
+    pub axes: bool,
+    // Distance between minor grid lines, every 10th line is a major one
+    pub grid_spacing: f32,
+    // Distance from the camera at which the grid has fully faded out
+    pub grid_fade_distance: f32,
+}
+
+impl Default for DebugViewSettings {
+    fn default() -> Self {
+        Self {
+            grid: false,
+            axes: false,
+            grid_spacing: 1.0,
+            grid_fade_distance: 100.0,
+        }
+    }
+}
+
+impl DebugViewSettings {
+    pub fn from_preferences(preferences: &Preferences) -> Self {
+        Self {
+            grid: preferences.bool(DEBUG_GRID, false),
+            axes: preferences.bool(DEBUG_AXES, false),
+            grid_spacing: preferences
+                .float(DEBUG_GRID_SPACING, 1.0)
+                .clamp(0.01, 100.0) as f32,
+            grid_fade_distance: preferences
+                .float(DEBUG_GRID_FADE, 100.0)
+                .clamp(1.0, 10000.0) as f32,
+        }
+    }
+}
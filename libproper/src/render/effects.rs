@@ -0,0 +1,139 @@
+use nalgebra::{Matrix4, Rotation3, Translation3, Vector3};
+
+use crate::math::noise::Noise;
+
+// Trauma lost per second
+const TRAUMA_DECAY: f32 = 0.8;
+const MAX_SHAKE_ANGLE: f32 = 0.05;
+const MAX_SHAKE_OFFSET: f32 = 0.08;
+// Shake noise frequency, in Hz
+const SHAKE_FREQUENCY: f32 = 12.0;
+const VIGNETTE_DECAY: f32 = 1.5;
+const FLASH_DECAY: f32 = 4.0;
+const DAMAGE_COLOR: [f32; 3] = [0.6, 0.0, 0.0];
+// Letterbox bar height change per second
+const LETTERBOX_SPEED: f32 = 0.25;
+
+// Procedural camera shake and full-screen overlays. Shake follows the trauma model: events
+// add trauma, which decays over time, and the shake amount is its square so small hits are
+// subtle while big ones are violent
+#[derive(Clone, Debug)]
+pub struct CameraEffects {
+    trauma: f32,
+    time: f32,
+    vignette: f32,
+    vignette_color: [f32; 3],
+    flash: f32,
+    flash_color: [f32; 3],
+    letterbox: f32,
+    letterbox_target: f32,
+}
+
+// Colors with the strength of the effect in alpha, passed to the screen pass
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ScreenOverlay {
+    pub vignette: [f32; 4],
+    pub flash: [f32; 4],
+    // Height of the black bars at the top and bottom, as a fraction of the screen
+    pub letterbox: f32,
+}
+
+// Smooth noise in [-1, 1], a different curve for every seed
+fn noise(seed: u32, time: f32) -> f32 {
+    Noise::new(seed)
+        .perlin2(time * SHAKE_FREQUENCY, 0.5)
+        .clamp(-1.0, 1.0)
+}
+
+impl Default for CameraEffects {
+    fn default() -> Self {
+        Self {
+            trauma: 0.0,
+            time: 0.0,
+            vignette: 0.0,
+            vignette_color: DAMAGE_COLOR,
+            flash: 0.0,
+            flash_color: [1.0; 3],
+            letterbox: 0.0,
+            letterbox_target: 0.0,
+        }
+    }
+}
+
+impl CameraEffects {
+    #[inline]
+    pub const fn trauma(&self) -> f32 {
+        self.trauma
+    }
+
+    // Trauma is kept in [0, 1]
+    pub fn add_trauma(&mut self, amount: f32) {
+        self.trauma = (self.trauma + amount).clamp(0.0, 1.0);
+    }
+
+    // Red vignette along with a bit of shake, amount is in [0, 1]
+    pub fn damage(&mut self, amount: f32) {
+        let amount = amount.clamp(0.0, 1.0);
+        self.vignette = (self.vignette + amount).min(1.0);
+        self.vignette_color = DAMAGE_COLOR;
+        self.add_trauma(amount * 0.5);
+    }
+
+    pub fn flash(&mut self, color: [f32; 3], intensity: f32) {
+        self.flash = intensity.clamp(0.0, 1.0);
+        self.flash_color = color;
+    }
+
+    // The bars slide in or out to the height, 0 removes them
+    pub fn set_letterbox(&mut self, height: f32) {
+        self.letterbox_target = height.clamp(0.0, 0.5);
+    }
+
+    pub fn clear(&mut self) {
+        self.trauma = 0.0;
+        self.vignette = 0.0;
+        self.flash = 0.0;
+    }
+
+    pub fn update(&mut self, delta: f32) {
+        self.time += delta;
+        self.trauma = (self.trauma - TRAUMA_DECAY * delta).max(0.0);
+        self.vignette = (self.vignette - VIGNETTE_DECAY * delta).max(0.0);
+        self.flash = (self.flash - FLASH_DECAY * delta).max(0.0);
+
+        let step = LETTERBOX_SPEED * delta;
+        self.letterbox = if self.letterbox < self.letterbox_target {
+            (self.letterbox + step).min(self.letterbox_target)
+        } else {
+            (self.letterbox - step).max(self.letterbox_target)
+        };
+    }
+
+    // Applied on top of the view matrix: view = shake * view
+    pub fn shake_matrix(&self) -> Matrix4<f32> {
+        let shake = self.trauma * self.trauma;
+        if shake <= 0.0 {
+            return Matrix4::identity();
+        }
+
+        let rotation = Rotation3::from_euler_angles(
+            noise(1, self.time) * MAX_SHAKE_ANGLE * shake,
+            noise(2, self.time) * MAX_SHAKE_ANGLE * shake,
+            noise(3, self.time) * MAX_SHAKE_ANGLE * shake,
+        );
+        let offset =
+            Vector3::new(noise(4, self.time), noise(5, self.time), 0.0) * MAX_SHAKE_OFFSET * shake;
+
+        Translation3::from(offset).to_homogeneous() * rotation.to_homogeneous()
+    }
+
+    pub fn overlay(&self) -> ScreenOverlay {
+        let [vr, vg, vb] = self.vignette_color;
+        let [fr, fg, fb] = self.flash_color;
+        ScreenOverlay {
+            vignette: [vr, vg, vb, self.vignette],
+            flash: [fr, fg, fb, self.flash],
+            letterbox: self.letterbox,
+        }
+    }
+}
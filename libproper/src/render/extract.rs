@@ -0,0 +1,119 @@
+use std::sync::Arc;
+
+use nalgebra::Matrix4;
+use vulkano::{buffer::CpuAccessibleBuffer, descriptor_set::DescriptorSetWithOffsets};
+
+use crate::{
+    resource::{
+        material::{MaterialInstance, MaterialTemplate},
+        model::Model,
+    },
+    world::{
+        camera::Camera,
+        entity::Entity,
+        light::{DirectionalLight, LocalLight},
+        scene::Scene,
+    },
+};
+
+use super::{effects::ScreenOverlay, grading::ScreenGrading, Vertex};
+
+// What the forward pass needs of an entity, as of the extraction
+pub struct RenderObject {
+    pub transform: Matrix4<f32>,
+    pub model: Arc<Model>,
+    pub model_set: DescriptorSetWithOffsets,
+    pub material_template: Arc<dyn MaterialTemplate>,
+    pub material: MaterialInstance,
+    // Indexed by submesh, None where the mesh's material is used
+    pub submesh_materials: Vec<Option<(Arc<dyn MaterialTemplate>, MaterialInstance)>>,
+    pub morph_buffer: Option<Arc<CpuAccessibleBuffer<[Vertex]>>>,
+}
+
+pub struct RenderGroup {
+    pub material_template: Arc<dyn MaterialTemplate>,
+    pub objects: Vec<RenderObject>,
+}
+
+// Snapshot of the scene taken once per frame with the scene locked for reading. Recording
+// only reads the snapshot, so logic can write to the scene in the meantime
+#[derive(Default)]
+pub struct RenderScene {
+    pub camera: Camera,
+    pub light: DirectionalLight,
+    pub lights: Vec<LocalLight>,
+    pub shake: Matrix4<f32>,
+    pub overlay: ScreenOverlay,
+    pub grading: ScreenGrading,
+    pub groups: Vec<RenderGroup>,
+}
+
+impl RenderObject {
+    pub fn extract(entity: &Entity, image: usize) -> Self {
+        let mesh = entity.mesh();
+        let submesh_materials = (0..mesh.model().submeshes().len())
+            .map(|index| {
+                mesh.submesh_material_override(index)
+                    .map(|material| (material.template.clone(), material.instance.clone()))
+            })
+            .collect();
+
+        Self {
+            transform: entity.transform(),
+            model: mesh.model().clone(),
+            model_set: mesh.model_set(image),
+            material_template: mesh.material_template().clone(),
+            material: mesh.material_instance().clone(),
+            submesh_materials,
+            morph_buffer: mesh.morph_buffer().cloned(),
+        }
+    }
+
+    pub fn submesh_material(
+        &self,
+        index: usize,
+    ) -> (&Arc<dyn MaterialTemplate>, &MaterialInstance) {
+        match self.submesh_materials.get(index) {
+            Some(Some((template, instance))) => (template, instance),
+            _ => (&self.material_template, &self.material),
+        }
+    }
+
+    #[inline]
+    pub fn has_submesh_materials(&self) -> bool {
+        self.submesh_materials.iter().any(Option::is_some)
+    }
+}
+
+impl RenderScene {
+    // Refills the snapshot for a frame drawing to the image, keeping the allocations of the
+    // previous frame
+    pub fn extract(&mut self, scene: &Scene, image: usize) {
+        let _span = tracing::info_span!("extract_scene").entered();
+        self.camera = scene.camera.clone();
+        self.light = scene.light.clone();
+        self.lights.clone_from(&scene.lights);
+        self.shake = scene.camera_effects.shake_matrix();
+        self.overlay = scene.camera_effects.overlay();
+        self.grading = scene.color_grading.grade();
+
+        self.groups.truncate(scene.data.len());
+        for (index, group) in scene.data.iter().enumerate() {
+            let objects = group
+                .entities
+                .iter()
+                .map(|entity| RenderObject::extract(entity, image));
+            match self.groups.get_mut(index) {
+                Some(extracted) => {
+                    extracted.material_template = group.material_template.clone();
+                    extracted.objects.clear();
+                    extracted.objects.extend(objects);
+                }
+                None => self.groups.push(RenderGroup {
+                    material_template: group.material_template.clone(),
+                    objects: objects.collect(),
+                }),
+            }
+        }
+    }
+}
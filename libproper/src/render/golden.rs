@@ -0,0 +1,458 @@
+use std::{
+    fmt::Write as _,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, RwLock},
+};
+
+use image::{Rgba, RgbaImage};
+use nalgebra::Point3;
+use serde::Deserialize;
+use vulkano::sync::GpuFuture;
+use winit::event_loop::{ControlFlow, EventLoopProxy};
+
+use crate::{
+    error::Error,
+    event::{Event, GameEvent},
+    layer::{priority, Layer},
+    lock::Recover,
+    resource::{material::MaterialRegistry, model::ModelRegistry, texture::TextureRegistry},
+    time::Time,
+    world::{description::SceneDescription, scenes::SceneManager},
+};
+
+use super::{capture::capture_frame, frame::Frame};
+
+// Largest YIQ difference between two colors, black and white
+const MAX_YIQ_DELTA: f32 = 35215.0;
+
+// Known scenes rendered at a fixed resolution and compared to reference images, see
+// res/golden/suite.toml
+#[derive(Deserialize, Clone)]
+pub struct GoldenSuite {
+    #[serde(default = "default_width")]
+    pub width: u32,
+    #[serde(default = "default_height")]
+    pub height: u32,
+    // Per-pixel color difference in [0, 1] above which the pixel is a mismatch
+    #[serde(default = "default_threshold")]
+    pub threshold: f32,
+    // Fraction of mismatched pixels the case still passes with
+    #[serde(default = "default_max_mismatch")]
+    pub max_mismatch: f32,
+    // Frames drawn before the capture, so uploads have finished
+    #[serde(default = "default_warmup_frames")]
+    pub warmup_frames: u32,
+    #[serde(default = "default_reference_dir")]
+    pub reference_dir: PathBuf,
+    // Actual and diff images of the failed cases and the report go here
+    #[serde(default = "default_output_dir")]
+    pub output_dir: PathBuf,
+    // Stores the captures as the references instead of comparing them. Otherwise a case
+    // without a reference fails
+    #[serde(default)]
+    pub update: bool,
+    #[serde(rename = "case")]
+    pub cases: Vec<GoldenCase>,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct GoldenCase {
+    pub name: String,
+    // Scene file
+    pub scene: PathBuf,
+    pub camera_position: [f32; 3],
+    pub camera_target: [f32; 3],
+}
+
+pub struct ImageDiff {
+    pub mismatched: usize,
+    pub total: usize,
+    // Mismatched pixels in red over the dimmed reference
+    pub image: RgbaImage,
+}
+
+#[derive(Debug)]
+pub enum GoldenOutcome {
+    Passed { mismatched: usize },
+    Failed { mismatched: usize, total: usize },
+    // The capture was stored as the reference, see GoldenSuite::update
+    Updated,
+    // No reference to compare to and not updating
+    Missing,
+    Error(String),
+}
+
+// Runs the suite in place of the game and exits with status 1 if a case fails. Scenes
+// should be static: the capture is taken after a number of frames, not at a fixed time
+pub struct GoldenLayer {
+    event_proxy: EventLoopProxy<GameEvent>,
+    suite: GoldenSuite,
+    scenes: Arc<Mutex<SceneManager>>,
+    material_registry: Arc<RwLock<MaterialRegistry>>,
+    model_registry: Arc<RwLock<ModelRegistry>>,
+    texture_registry: Arc<RwLock<TextureRegistry>>,
+    case: usize,
+    // Frames drawn since the case's scene became active, None until it's loaded
+    frames: Option<u32>,
+    results: Vec<(String, GoldenOutcome)>,
+}
+
+fn default_width() -> u32 {
+    640
+}
+
+fn default_height() -> u32 {
+    360
+}
+
+fn default_threshold() -> f32 {
+    0.1
+}
+
+fn default_max_mismatch() -> f32 {
+    0.001
+}
+
+fn default_warmup_frames() -> u32 {
+    8
+}
+
+fn default_reference_dir() -> PathBuf {
+    PathBuf::from("res/golden/reference")
+}
+
+fn default_output_dir() -> PathBuf {
+    PathBuf::from("golden-out")
+}
+
+fn rgb_to_yiq(pixel: &Rgba<u8>) -> [f32; 3] {
+    let [r, g, b, _] = pixel.0.map(f32::from);
+    [
+        r * 0.29889531 + g * 0.58662247 + b * 0.11448223,
+        r * 0.59597799 - g * 0.2741761 - b * 0.32180189,
+        r * 0.21147017 - g * 0.52261711 + b * 0.31114694,
+    ]
+}
+
+// Perceptual color difference from "Measuring perceived color difference using YIQ NTSC
+// transmission color space in mobile applications" (Kotsarenko, Ramos), as used by
+// pixelmatch. Alpha is ignored, the captures are opaque
+pub fn color_delta(a: &Rgba<u8>, b: &Rgba<u8>) -> f32 {
+    let [ya, ia, qa] = rgb_to_yiq(a);
+    let [yb, ib, qb] = rgb_to_yiq(b);
+    let (y, i, q) = (ya - yb, ia - ib, qa - qb);
+    (0.5053 * y * y + 0.299 * i * i + 0.1957 * q * q) / MAX_YIQ_DELTA
+}
+
+// Images of different sizes don't match at all
+pub fn compare(actual: &RgbaImage, reference: &RgbaImage, threshold: f32) -> ImageDiff {
+    let total = (reference.width() * reference.height()) as usize;
+    if actual.dimensions() != reference.dimensions() {
+        return ImageDiff {
+            mismatched: total.max(1),
+            total: total.max(1),
+            image: actual.clone(),
+        };
+    }
+
+    // Deltas are squared distances
+    let threshold = threshold * threshold;
+    let mut mismatched = 0;
+    let mut image = RgbaImage::new(reference.width(), reference.height());
+    for ((a, r), out) in actual
+        .pixels()
+        .zip(reference.pixels())
+        .zip(image.pixels_mut())
+    {
+        if color_delta(a, r) > threshold {
+            mismatched += 1;
+            *out = Rgba([255, 0, 0, 255]);
+        } else {
+            let [y, ..] = rgb_to_yiq(r);
+            let gray = (255.0 - 0.1 * (255.0 - y)) as u8;
+            *out = Rgba([gray, gray, gray, 255]);
+        }
+    }
+
+    ImageDiff {
+        mismatched,
+        total,
+        image,
+    }
+}
+
+fn save_image(image: &RgbaImage, path: &Path) -> Result<(), Error> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).map_err(|err| Error::file(dir, err))?;
+    }
+    image.save(path).map_err(|err| match err {
+        image::ImageError::IoError(err) => Error::file(path, err),
+        err => Error::asset_parse(path, err),
+    })
+}
+
+impl GoldenSuite {
+    pub const DEFAULT_PATH: &'static str = "res/golden/suite.toml";
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path).map_err(|err| Error::file(path, err))?;
+        toml::from_str(&text).map_err(|err| Error::asset_parse(path, err))
+    }
+
+    pub fn reference_path(&self, case: &GoldenCase) -> PathBuf {
+        self.reference_dir.join(format!("{}.png", case.name))
+    }
+}
+
+impl GoldenOutcome {
+    pub fn is_failure(&self) -> bool {
+        matches!(self, Self::Failed { .. } | Self::Missing | Self::Error(_))
+    }
+}
+
+impl GoldenLayer {
+    pub fn new(
+        event_proxy: EventLoopProxy<GameEvent>,
+        suite: GoldenSuite,
+        scenes: Arc<Mutex<SceneManager>>,
+        material_registry: Arc<RwLock<MaterialRegistry>>,
+        model_registry: Arc<RwLock<ModelRegistry>>,
+        texture_registry: Arc<RwLock<TextureRegistry>>,
+    ) -> Self {
+        Self {
+            event_proxy,
+            suite,
+            scenes,
+            material_registry,
+            model_registry,
+            texture_registry,
+            case: 0,
+            frames: None,
+            results: vec![],
+        }
+    }
+
+    fn load_case(&mut self, case: &GoldenCase) -> Result<(), Error> {
+        let mut scene = {
+            let mut materials = self.material_registry.write().recover();
+            let mut models = self.model_registry.write().recover();
+            let mut textures = self.texture_registry.write().recover();
+            SceneDescription::load(&case.scene)?.instantiate_scene(
+                &mut materials,
+                &mut models,
+                &mut textures,
+            )?
+        };
+        let position = Point3::from(case.camera_position);
+        scene.camera.set_position(position);
+        scene
+            .camera
+            .set_direction(&(Point3::from(case.camera_target) - position).normalize());
+
+        let name = format!("golden:{}", case.name);
+        let mut scenes = self.scenes.lock().recover();
+        scenes.insert(&name, scene);
+        scenes.switch_to(&name, false)?;
+        self.event_proxy
+            .send_event(GameEvent::SceneSwitched(name))
+            .ok();
+        Ok(())
+    }
+
+    fn check(&self, case: &GoldenCase, actual: &RgbaImage) -> Result<GoldenOutcome, Error> {
+        let reference_path = self.suite.reference_path(case);
+        if self.suite.update {
+            save_image(actual, &reference_path)?;
+            return Ok(GoldenOutcome::Updated);
+        }
+        if !reference_path.exists() {
+            // Kept for review before it's copied over as the reference
+            save_image(
+                actual,
+                &self
+                    .suite
+                    .output_dir
+                    .join(format!("{}.actual.png", case.name)),
+            )?;
+            return Ok(GoldenOutcome::Missing);
+        }
+
+        let reference = image::open(&reference_path)
+            .map_err(|err| match err {
+                image::ImageError::IoError(err) => Error::file(&reference_path, err),
+                err => Error::asset_parse(&reference_path, err),
+            })?
+            .into_rgba8();
+        let diff = compare(actual, &reference, self.suite.threshold);
+        if diff.mismatched as f32 <= diff.total as f32 * self.suite.max_mismatch {
+            return Ok(GoldenOutcome::Passed {
+                mismatched: diff.mismatched,
+            });
+        }
+
+        let output_dir = &self.suite.output_dir;
+        save_image(
+            actual,
+            &output_dir.join(format!("{}.actual.png", case.name)),
+        )?;
+        save_image(
+            &diff.image,
+            &output_dir.join(format!("{}.diff.png", case.name)),
+        )?;
+        Ok(GoldenOutcome::Failed {
+            mismatched: diff.mismatched,
+            total: diff.total,
+        })
+    }
+
+    fn finish(&self) -> ! {
+        let mut report = String::new();
+        for (name, outcome) in &self.results {
+            let status = match outcome {
+                GoldenOutcome::Passed { mismatched } => {
+                    format!("ok ({} pixels differ)", mismatched)
+                }
+                GoldenOutcome::Failed { mismatched, total } => format!(
+                    "FAILED: {} of {} pixels differ ({:.3}%)",
+                    mismatched,
+                    total,
+                    *mismatched as f64 / *total as f64 * 100.0
+                ),
+                GoldenOutcome::Updated => "reference updated".to_owned(),
+                GoldenOutcome::Missing => {
+                    "FAILED: no reference, run with --golden-update to create it".to_owned()
+                }
+                GoldenOutcome::Error(message) => format!("ERROR: {}", message),
+            };
+            writeln!(report, "{}: {}", name, status).unwrap();
+        }
+        let failed = self
+            .results
+            .iter()
+            .filter(|(_, outcome)| outcome.is_failure())
+            .count();
+        writeln!(report, "{} cases, {} failed", self.results.len(), failed).unwrap();
+
+        log::info!("Golden image results:\n{}", report);
+        let report_path = self.suite.output_dir.join("report.txt");
+        if let Err(err) = std::fs::create_dir_all(&self.suite.output_dir)
+            .and_then(|_| std::fs::write(&report_path, &report))
+        {
+            log::error!("Failed to write {:?}: {}", report_path, err);
+        }
+
+        // The event loop never returns, this is the only way to set the exit status
+        std::process::exit(if failed == 0 { 0 } else { 1 });
+    }
+}
+
+impl Layer for GoldenLayer {
+    fn on_attach(&mut self) {}
+
+    fn on_detach(&mut self) {}
+
+    fn draw_priority(&self) -> i32 {
+        priority::CAPTURE
+    }
+
+    fn on_event(&mut self, _event: &Event, _flow: &mut ControlFlow) -> Result<bool, Error> {
+        Ok(false)
+    }
+
+    fn on_tick(&mut self, _time: &Time) -> Result<(), Error> {
+        if self.frames.is_some() {
+            return Ok(());
+        }
+
+        let case = match self.suite.cases.get(self.case) {
+            Some(case) => case.clone(),
+            None => self.finish(),
+        };
+        match self.load_case(&case) {
+            Ok(()) => self.frames = Some(0),
+            Err(err) => {
+                self.results
+                    .push((case.name, GoldenOutcome::Error(err.full_message())));
+                self.case += 1;
+            }
+        }
+        Ok(())
+    }
+
+    fn on_draw(
+        &mut self,
+        in_future: Box<dyn GpuFuture>,
+        frame: &Frame,
+    ) -> Result<Box<dyn GpuFuture>, Error> {
+        let frames = match self.frames.as_mut() {
+            Some(frames) => frames,
+            None => return Ok(in_future),
+        };
+        *frames += 1;
+        if *frames <= self.suite.warmup_frames {
+            return Ok(in_future);
+        }
+
+        let case = self.suite.cases[self.case].clone();
+        let (actual, future) = capture_frame(in_future, frame)?;
+        let outcome = self
+            .check(&case, &actual)
+            .unwrap_or_else(|err| GoldenOutcome::Error(err.full_message()));
+        log::info!("Golden case {}: {:?}", case.name, outcome);
+        self.results.push((case.name, outcome));
+        self.case += 1;
+        self.frames = None;
+        Ok(future)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filled(width: u32, height: u32, color: [u8; 3]) -> RgbaImage {
+        let [r, g, b] = color;
+        RgbaImage::from_pixel(width, height, Rgba([r, g, b, 255]))
+    }
+
+    #[test]
+    fn color_delta_is_normalized() {
+        let black = Rgba([0, 0, 0, 255]);
+        let white = Rgba([255, 255, 255, 255]);
+        assert_eq!(color_delta(&black, &black), 0.0);
+        let delta = color_delta(&black, &white);
+        assert!(delta > 0.9 && delta <= 1.0, "{}", delta);
+        // Alpha is ignored
+        assert_eq!(color_delta(&black, &Rgba([0, 0, 0, 0])), 0.0);
+    }
+
+    #[test]
+    fn identical_images_match() {
+        let image = filled(4, 4, [10, 120, 200]);
+        let diff = compare(&image, &image, 0.1);
+        assert_eq!(diff.mismatched, 0);
+        assert_eq!(diff.total, 16);
+    }
+
+    #[test]
+    fn mismatches_are_counted_and_marked() {
+        let reference = filled(4, 4, [0, 0, 0]);
+        let mut actual = reference.clone();
+        actual.put_pixel(1, 2, Rgba([255, 255, 255, 255]));
+        // Below the threshold
+        actual.put_pixel(3, 3, Rgba([1, 1, 1, 255]));
+
+        let diff = compare(&actual, &reference, 0.1);
+        assert_eq!(diff.mismatched, 1);
+        assert_eq!(*diff.image.get_pixel(1, 2), Rgba([255, 0, 0, 255]));
+        assert_ne!(*diff.image.get_pixel(3, 3), Rgba([255, 0, 0, 255]));
+    }
+
+    #[test]
+    fn different_sizes_never_match() {
+        let diff = compare(&filled(4, 4, [0, 0, 0]), &filled(4, 2, [0, 0, 0]), 1.0);
+        assert_eq!(diff.mismatched, diff.total);
+        assert_eq!(diff.total, 8);
+    }
+}
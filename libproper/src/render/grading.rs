@@ -0,0 +1,316 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use vulkano::format::Format;
+
+use crate::{
+    error::{Error, ResourceKind},
+    preferences::{Preferences, COLOR_GRADING, COLOR_GRADING_INTENSITY},
+    resource::{
+        sampler::{SamplerDesc, TextureFilter, TextureWrap},
+        texture::{SampledTexture, TextureRegistry},
+    },
+};
+
+pub const LUT_DIRECTORY: &str = "res/luts";
+// Edge length of the identity LUT the screen pass falls back to
+pub const NEUTRAL_LUT_SIZE: u32 = 16;
+// Larger .cube files exist but don't grade any better at 8 bits per channel
+const MAX_LUT_SIZE: u32 = 64;
+// LUTs are registered as textures under this prefix and the LUT's name
+const LUT_TEXTURE_PREFIX: &str = "lut/";
+
+// Color grading of the final image with 3D lookup tables, stored as strips of their blue
+// slices: N slices of N by N side by side, red across each slice and green down. Lookups are
+// done on sRGB-encoded colors, which is what LUTs are authored for
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ColorGradingSettings {
+    pub enabled: bool,
+    // Applied on top of the scene's intensity
+    pub intensity: f32,
+}
+
+// Grading state of a scene. Switching LUTs blends from the one shown to the new one, e.g.
+// when walking between zones
+#[derive(Clone)]
+pub struct ColorGrading {
+    // None is the neutral LUT
+    from: Option<Arc<SampledTexture>>,
+    to: Option<Arc<SampledTexture>>,
+    // 0 shows `from`, 1 `to`
+    blend: f32,
+    // Seconds the current transition takes
+    duration: f32,
+    intensity: f32,
+}
+
+// Screen pass parameters for one frame, the default leaves the image as it is
+#[derive(Clone, Default)]
+pub struct ScreenGrading {
+    pub luts: [Option<Arc<SampledTexture>>; 2],
+    pub blend: f32,
+    pub intensity: f32,
+}
+
+impl Default for ColorGradingSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            intensity: 1.0,
+        }
+    }
+}
+
+impl ColorGradingSettings {
+    pub fn from_preferences(preferences: &Preferences) -> Self {
+        Self {
+            enabled: preferences.bool(COLOR_GRADING, true),
+            intensity: preferences
+                .float(COLOR_GRADING_INTENSITY, 1.0)
+                .clamp(0.0, 1.0) as f32,
+        }
+    }
+
+    pub fn apply(&self, grading: &ScreenGrading) -> ScreenGrading {
+        if !self.enabled {
+            return ScreenGrading::default();
+        }
+        ScreenGrading {
+            intensity: grading.intensity * self.intensity,
+            ..grading.clone()
+        }
+    }
+}
+
+impl Default for ColorGrading {
+    fn default() -> Self {
+        Self {
+            from: None,
+            to: None,
+            blend: 1.0,
+            duration: 0.0,
+            intensity: 1.0,
+        }
+    }
+}
+
+impl ColorGrading {
+    // The LUT shown once the current transition is over
+    #[inline]
+    pub const fn lut(&self) -> Option<&Arc<SampledTexture>> {
+        self.to.as_ref()
+    }
+
+    #[inline]
+    pub const fn intensity(&self) -> f32 {
+        self.intensity
+    }
+
+    // 0 leaves the image ungraded
+    pub fn set_intensity(&mut self, intensity: f32) {
+        self.intensity = intensity.clamp(0.0, 1.0);
+    }
+
+    // Blends to the LUT over the duration, None goes back to neutral. A transition which is
+    // still going starts over from the LUT that shows the most
+    pub fn set_lut(&mut self, lut: Option<Arc<SampledTexture>>, duration: f32) {
+        self.from = if self.blend >= 0.5 {
+            self.to.take()
+        } else {
+            self.from.take()
+        };
+        self.to = lut;
+        self.duration = duration.max(0.0);
+        self.blend = if self.duration > 0.0 { 0.0 } else { 1.0 };
+    }
+
+    pub fn update(&mut self, delta: f32) {
+        if self.blend >= 1.0 {
+            return;
+        }
+        self.blend = if self.duration > 0.0 {
+            (self.blend + delta / self.duration).min(1.0)
+        } else {
+            1.0
+        };
+        if self.blend >= 1.0 {
+            self.from = None;
+        }
+    }
+
+    pub fn grade(&self) -> ScreenGrading {
+        let neutral = self.from.is_none() && self.to.is_none();
+        ScreenGrading {
+            luts: [self.from.clone(), self.to.clone()],
+            blend: self.blend,
+            intensity: if neutral { 0.0 } else { self.intensity },
+        }
+    }
+}
+
+pub fn lut_path(name: &str, extension: &str) -> PathBuf {
+    Path::new(LUT_DIRECTORY).join(format!("{}.{}", name, extension))
+}
+
+// Loads res/luts/<name>.cube, or <name>.png if there's no .cube file. Once loaded, the LUT
+// stays registered with the textures
+pub fn load_lut(textures: &mut TextureRegistry, name: &str) -> Result<Arc<SampledTexture>, Error> {
+    let texture_name = format!("{}{}", LUT_TEXTURE_PREFIX, name);
+    if let Some(texture) = textures.get(&texture_name) {
+        return Ok(texture.clone());
+    }
+
+    let cube_path = lut_path(name, "cube");
+    let (size, data) = if cube_path.exists() {
+        load_cube(&cube_path)
+    } else {
+        load_strip(&lut_path(name, "png"))
+    }
+    .map_err(|err| err.context(ResourceKind::Texture, &texture_name))?;
+
+    log::info!("Loaded {}x{}x{} LUT {:?}", size, size, size, name);
+    textures.create_from_data(
+        &texture_name,
+        size * size,
+        size,
+        Format::R8G8B8A8_UNORM,
+        &data,
+    )?;
+    // Filtering across the edge of a slice would blend in the neighbouring one
+    textures.set_sampler(
+        &texture_name,
+        &SamplerDesc {
+            filter: TextureFilter::Linear,
+            wrap: TextureWrap::Clamp,
+            ..Default::default()
+        },
+    )
+}
+
+// Strip of the identity LUT, grades nothing
+pub fn neutral_lut_data(size: u32) -> Vec<u8> {
+    let scale = 255.0 / (size - 1) as f32;
+    let mut data = Vec::with_capacity((size * size * size * 4) as usize);
+    for green in 0..size {
+        for blue in 0..size {
+            for red in 0..size {
+                data.extend_from_slice(&[
+                    (red as f32 * scale).round() as u8,
+                    (green as f32 * scale).round() as u8,
+                    (blue as f32 * scale).round() as u8,
+                    255,
+                ]);
+            }
+        }
+    }
+    data
+}
+
+fn load_strip(path: &Path) -> Result<(u32, Vec<u8>), Error> {
+    let image = image::open(path)
+        .map_err(|err| match err {
+            image::ImageError::IoError(err) => Error::file(path, err),
+            err => Error::asset_parse(path, err),
+        })?
+        .into_rgba8();
+    let (width, height) = image.dimensions();
+    if height < 2 || height > MAX_LUT_SIZE || width != height * height {
+        return Err(Error::asset_parse(
+            path,
+            format!(
+                "a LUT strip must be N*N by N pixels, N up to {}, not {}x{}",
+                MAX_LUT_SIZE, width, height
+            ),
+        ));
+    }
+    Ok((height, image.into_raw()))
+}
+
+// Adobe/Resolve .cube format: keywords, then one "r g b" line per entry, red changing the
+// fastest. 1D LUTs and input domains other than 0 to 1 aren't supported
+fn load_cube(path: &Path) -> Result<(u32, Vec<u8>), Error> {
+    let text = std::fs::read_to_string(path).map_err(|err| Error::file(path, err))?;
+    let mut size = None;
+    let mut entries = vec![];
+
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut words = line.split_whitespace();
+        let keyword = words.next().unwrap_or_default();
+        let parse_triple = |words: std::str::SplitWhitespace| {
+            let values = words
+                .map(str::parse::<f32>)
+                .collect::<Result<Vec<_>, _>>()
+                .ok()
+                .filter(|values| values.len() == 3)
+                .ok_or_else(|| {
+                    Error::asset_parse(path, format!("line {}: expected 3 numbers", number + 1))
+                })?;
+            Ok::<_, Error>([values[0], values[1], values[2]])
+        };
+
+        match keyword {
+            "TITLE" => (),
+            "LUT_1D_SIZE" => {
+                return Err(Error::asset_parse(path, "1D LUTs are not supported"));
+            }
+            "LUT_3D_SIZE" => {
+                let value = words
+                    .next()
+                    .and_then(|word| word.parse::<u32>().ok())
+                    .filter(|size| (2..=MAX_LUT_SIZE).contains(size))
+                    .ok_or_else(|| {
+                        Error::asset_parse(
+                            path,
+                            format!(
+                                "line {}: LUT size must be 2 to {}",
+                                number + 1,
+                                MAX_LUT_SIZE
+                            ),
+                        )
+                    })?;
+                size = Some(value);
+            }
+            "DOMAIN_MIN" | "DOMAIN_MAX" => {
+                let expected = if keyword == "DOMAIN_MIN" { 0.0 } else { 1.0 };
+                if parse_triple(words)? != [expected; 3] {
+                    return Err(Error::asset_parse(
+                        path,
+                        format!("line {}: only the 0 to 1 domain is supported", number + 1),
+                    ));
+                }
+            }
+            _ => entries.push(parse_triple(line.split_whitespace())?),
+        }
+    }
+
+    let size = size.ok_or_else(|| Error::asset_parse(path, "LUT_3D_SIZE is missing"))?;
+    if entries.len() != (size * size * size) as usize {
+        return Err(Error::asset_parse(
+            path,
+            format!(
+                "expected {} entries, found {}",
+                size * size * size,
+                entries.len()
+            ),
+        ));
+    }
+
+    // Reordered from red, green, blue to the strip's rows of green
+    let mut data = vec![0; entries.len() * 4];
+    for (index, entry) in entries.iter().enumerate() {
+        let index = index as u32;
+        let (red, green, blue) = (index % size, index / size % size, index / (size * size));
+        let offset = ((green * size * size + blue * size + red) * 4) as usize;
+        for channel in 0..3 {
+            data[offset + channel] = (entry[channel].clamp(0.0, 1.0) * 255.0).round() as u8;
+        }
+        data[offset + 3] = 255;
+    }
+    Ok((size, data))
+}
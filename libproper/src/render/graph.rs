@@ -0,0 +1,384 @@
+use std::sync::Arc;
+
+use vulkano::{
+    command_buffer::RenderPassBeginInfo,
+    device::Device,
+    format::{ClearValue, Format},
+    image::{view::ImageView, AttachmentImage, ImageViewAbstract, SampleCount, SwapchainImage},
+    render_pass::{Framebuffer, FramebufferCreateInfo, RenderPass, Subpass},
+};
+use winit::window::Window;
+
+use crate::error::Error;
+
+use super::memory::{AllocationCategory, GpuAllocation};
+
+// Single-sampled scene color sampled by the screen pass, resolved into when MSAA is on
+pub const COLOR_ATTACHMENT: &str = "color";
+pub const MS_COLOR_ATTACHMENT: &str = "ms_color";
+pub const DEPTH_ATTACHMENT: &str = "depth";
+
+// Linear and with headroom above 1.0, the screen pass maps it to the output format
+const SCENE_FORMAT: Format = Format::R16G16B16A16_SFLOAT;
+// Alpha stays 0 where nothing was drawn, the light shafts treat those pixels as open sky
+const SCENE_CLEAR_COLOR: ClearValue = ClearValue::Float([0.0, 0.0, 0.0, 0.0]);
+const CLEAR_COLOR: ClearValue = ClearValue::Float([0.0, 0.0, 0.0, 1.0]);
+
+// Passes in execution order. Each is the only subpass of its render pass, so the screen pass
+// can sample any pixel of the scene color instead of just its own
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Pass {
+    // Scene geometry, multisampled and resolved if MSAA is on
+    Forward,
+    // Copies the scene color into the swapchain image
+    Screen,
+}
+
+// Image owned by the graph, recreated along with the swapchain
+struct Attachment {
+    name: &'static str,
+    format: Format,
+    samples: SampleCount,
+    // Stored and read by the screen pass through a sampler
+    sampled: bool,
+    // Resolve targets are fully overwritten, so they're not cleared
+    clear: Option<ClearValue>,
+}
+
+// Owns the render passes and everything sized after the swapchain. Layers look their subpasses
+// and attachments up here instead of creating their own
+pub struct RenderGraph {
+    device: Arc<Device>,
+    scene_pass: Arc<RenderPass>,
+    output_pass: Arc<RenderPass>,
+    attachments: Vec<Attachment>,
+    views: Vec<Arc<ImageView<AttachmentImage>>>,
+    // The scene attachments are shared between swapchain images
+    scene_framebuffer: Arc<Framebuffer>,
+    output_framebuffers: Vec<Arc<Framebuffer>>,
+    samples: SampleCount,
+    _memory: GpuAllocation,
+}
+
+impl RenderGraph {
+    // Vulkan only guarantees 1 and 4 samples for color and depth attachments
+    pub fn new(
+        device: Arc<Device>,
+        output_format: Format,
+        samples: u32,
+        swapchain_images: &[Arc<ImageView<SwapchainImage<Window>>>],
+    ) -> Result<Self, Error> {
+        let samples = match samples {
+            1 => SampleCount::Sample1,
+            4 => SampleCount::Sample4,
+            _ => {
+                log::warn!("Unsupported MSAA sample count {}, using 4", samples);
+                SampleCount::Sample4
+            }
+        };
+
+        let (scene_pass, attachments) = if samples == SampleCount::Sample1 {
+            Self::create_single_sampled(&device)?
+        } else {
+            Self::create_multisampled(&device)?
+        };
+        let output_pass = Self::create_output(&device, output_format)?;
+
+        let (views, scene_framebuffer, output_framebuffers, memory) = Self::create_framebuffers(
+            &device,
+            &scene_pass,
+            &output_pass,
+            &attachments,
+            swapchain_images,
+        )?;
+
+        Ok(Self {
+            device,
+            scene_pass,
+            output_pass,
+            attachments,
+            views,
+            scene_framebuffer,
+            output_framebuffers,
+            samples,
+            _memory: memory,
+        })
+    }
+
+    // The scene pass, materials build their pipelines for its forward subpass
+    #[inline]
+    pub const fn render_pass(&self) -> &Arc<RenderPass> {
+        &self.scene_pass
+    }
+
+    #[inline]
+    pub const fn samples(&self) -> SampleCount {
+        self.samples
+    }
+
+    pub fn subpass(&self, pass: Pass) -> Subpass {
+        let render_pass = match pass {
+            Pass::Forward => &self.scene_pass,
+            Pass::Screen => &self.output_pass,
+        };
+        Subpass::from(render_pass.clone(), 0).unwrap()
+    }
+
+    pub fn attachment(&self, name: &str) -> Option<&Arc<ImageView<AttachmentImage>>> {
+        self.attachments
+            .iter()
+            .position(|attachment| attachment.name == name)
+            .map(|index| &self.views[index])
+    }
+
+    // The image index only matters for the screen pass, which writes the swapchain image
+    pub fn begin_info(&self, pass: Pass, image_index: usize) -> RenderPassBeginInfo {
+        match pass {
+            Pass::Forward => {
+                let mut info = RenderPassBeginInfo::framebuffer(self.scene_framebuffer.clone());
+                info.clear_values = self
+                    .attachments
+                    .iter()
+                    .map(|attachment| attachment.clear)
+                    .collect();
+                info
+            }
+            Pass::Screen => {
+                let mut info =
+                    RenderPassBeginInfo::framebuffer(self.output_framebuffers[image_index].clone());
+                info.clear_values = vec![Some(CLEAR_COLOR)];
+                info
+            }
+        }
+    }
+
+    // Attachment views change, layers holding them have to fetch them again
+    pub fn recreate(
+        &mut self,
+        swapchain_images: &[Arc<ImageView<SwapchainImage<Window>>>],
+    ) -> Result<(), Error> {
+        let (views, scene_framebuffer, output_framebuffers, memory) = Self::create_framebuffers(
+            &self.device,
+            &self.scene_pass,
+            &self.output_pass,
+            &self.attachments,
+            swapchain_images,
+        )?;
+
+        self.views = views;
+        self.scene_framebuffer = scene_framebuffer;
+        self.output_framebuffers = output_framebuffers;
+        self._memory = memory;
+
+        Ok(())
+    }
+
+    // Attachments are listed in the same order as in the render pass
+    fn create_multisampled(
+        device: &Arc<Device>,
+    ) -> Result<(Arc<RenderPass>, Vec<Attachment>), Error> {
+        let render_pass = vulkano::ordered_passes_renderpass!(
+            device.clone(),
+            attachments: {
+                ms_color: {
+                    load: Clear,
+                    store: DontCare,
+                    format: SCENE_FORMAT,
+                    samples: 4,
+                },
+                depth: {
+                    load: Clear,
+                    store: DontCare,
+                    format: Format::D16_UNORM,
+                    samples: 4,
+                },
+                color: {
+                    load: DontCare,
+                    store: Store,
+                    format: SCENE_FORMAT,
+                    samples: 1,
+                }
+            },
+            passes: [
+                {
+                    color: [ms_color],
+                    depth_stencil: {depth},
+                    input: [],
+                    resolve: [color]
+                }
+            ]
+        )?;
+
+        let attachments = vec![
+            Attachment {
+                name: MS_COLOR_ATTACHMENT,
+                format: SCENE_FORMAT,
+                samples: SampleCount::Sample4,
+                sampled: false,
+                clear: Some(SCENE_CLEAR_COLOR),
+            },
+            Attachment {
+                name: DEPTH_ATTACHMENT,
+                format: Format::D16_UNORM,
+                samples: SampleCount::Sample4,
+                sampled: false,
+                clear: Some(ClearValue::Depth(1.0)),
+            },
+            Attachment {
+                name: COLOR_ATTACHMENT,
+                format: SCENE_FORMAT,
+                samples: SampleCount::Sample1,
+                sampled: true,
+                clear: None,
+            },
+        ];
+
+        Ok((render_pass, attachments))
+    }
+
+    fn create_single_sampled(
+        device: &Arc<Device>,
+    ) -> Result<(Arc<RenderPass>, Vec<Attachment>), Error> {
+        let render_pass = vulkano::ordered_passes_renderpass!(
+            device.clone(),
+            attachments: {
+                color: {
+                    load: Clear,
+                    store: Store,
+                    format: SCENE_FORMAT,
+                    samples: 1,
+                },
+                depth: {
+                    load: Clear,
+                    store: DontCare,
+                    format: Format::D16_UNORM,
+                    samples: 1,
+                }
+            },
+            passes: [
+                {
+                    color: [color],
+                    depth_stencil: {depth},
+                    input: []
+                }
+            ]
+        )?;
+
+        let attachments = vec![
+            Attachment {
+                name: COLOR_ATTACHMENT,
+                format: SCENE_FORMAT,
+                samples: SampleCount::Sample1,
+                sampled: true,
+                clear: Some(SCENE_CLEAR_COLOR),
+            },
+            Attachment {
+                name: DEPTH_ATTACHMENT,
+                format: Format::D16_UNORM,
+                samples: SampleCount::Sample1,
+                sampled: false,
+                clear: Some(ClearValue::Depth(1.0)),
+            },
+        ];
+
+        Ok((render_pass, attachments))
+    }
+
+    // Draws into the swapchain image
+    fn create_output(
+        device: &Arc<Device>,
+        output_format: Format,
+    ) -> Result<Arc<RenderPass>, Error> {
+        Ok(vulkano::single_pass_renderpass!(
+            device.clone(),
+            attachments: {
+                final_color: {
+                    load: Clear,
+                    store: Store,
+                    format: output_format,
+                    samples: 1,
+                }
+            },
+            pass: {
+                color: [final_color],
+                depth_stencil: {}
+            }
+        )?)
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn create_framebuffers(
+        device: &Arc<Device>,
+        scene_pass: &Arc<RenderPass>,
+        output_pass: &Arc<RenderPass>,
+        attachments: &[Attachment],
+        swapchain_images: &[Arc<ImageView<SwapchainImage<Window>>>],
+    ) -> Result<
+        (
+            Vec<Arc<ImageView<AttachmentImage>>>,
+            Arc<Framebuffer>,
+            Vec<Arc<Framebuffer>>,
+            GpuAllocation,
+        ),
+        Error,
+    > {
+        let [width, height] = swapchain_images[0].dimensions().width_height();
+
+        let mut bytes = 0;
+        let mut views = vec![];
+        for attachment in attachments {
+            let image = if attachment.sampled {
+                AttachmentImage::sampled_multisampled(
+                    device.clone(),
+                    [width, height],
+                    attachment.samples,
+                    attachment.format,
+                )?
+            } else {
+                AttachmentImage::transient_multisampled(
+                    device.clone(),
+                    [width, height],
+                    attachment.samples,
+                    attachment.format,
+                )?
+            };
+
+            bytes += width as u64
+                * height as u64
+                * attachment.samples as u64
+                * attachment.format.block_size().unwrap_or(4);
+            views.push(ImageView::new_default(image)?);
+        }
+
+        let scene_framebuffer = Framebuffer::new(
+            scene_pass.clone(),
+            FramebufferCreateInfo {
+                attachments: views
+                    .iter()
+                    .map(|view| view.clone() as Arc<dyn ImageViewAbstract>)
+                    .collect(),
+                ..Default::default()
+            },
+        )?;
+
+        let output_framebuffers = swapchain_images
+            .iter()
+            .map(|image| {
+                Framebuffer::new(
+                    output_pass.clone(),
+                    FramebufferCreateInfo {
+                        attachments: vec![image.clone() as Arc<dyn ImageViewAbstract>],
+                        ..Default::default()
+                    },
+                )
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok((
+            views,
+            scene_framebuffer,
+            output_framebuffers,
+            GpuAllocation::new(AllocationCategory::Attachment, bytes),
+        ))
+    }
+}
@@ -0,0 +1,144 @@
+use std::{
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+    time::{Duration, Instant},
+};
+
+// Fraction of the budget after which a warning is logged
+pub const BUDGET_WARNING_THRESHOLD: f64 = 0.9;
+
+const CATEGORY_COUNT: usize = 5;
+#[allow(clippy::declare_interior_mutable_const)]
+const ZERO: AtomicU64 = AtomicU64::new(0);
+
+static LIVE_BYTES: [AtomicU64; CATEGORY_COUNT] = [ZERO; CATEGORY_COUNT];
+static LIVE_COUNT: [AtomicU64; CATEGORY_COUNT] = [ZERO; CATEGORY_COUNT];
+static PEAK_BYTES: [AtomicU64; CATEGORY_COUNT] = [ZERO; CATEGORY_COUNT];
+// Size of device-local heaps, 0 if not known yet
+static BUDGET: AtomicU64 = ZERO;
+static OVER_THRESHOLD: AtomicBool = AtomicBool::new(false);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AllocationCategory {
+    Mesh,
+    Texture,
+    Uniform,
+    Attachment,
+    // Host-visible upload memory
+    Staging,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CategoryUsage {
+    pub bytes: u64,
+    pub count: u64,
+    pub peak_bytes: u64,
+}
+
+// Tracks a buffer/image for as long as it's alive
+pub(crate) struct GpuAllocation {
+    category: AllocationCategory,
+    bytes: u64,
+    created: Instant,
+}
+
+impl AllocationCategory {
+    pub const ALL: [Self; CATEGORY_COUNT] = [
+        Self::Mesh,
+        Self::Texture,
+        Self::Uniform,
+        Self::Attachment,
+        Self::Staging,
+    ];
+
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Mesh => "Mesh",
+            Self::Texture => "Texture",
+            Self::Uniform => "Uniform",
+            Self::Attachment => "Attachment",
+            Self::Staging => "Staging",
+        }
+    }
+}
+
+impl GpuAllocation {
+    pub(crate) fn new(category: AllocationCategory, bytes: u64) -> Self {
+        let index = category as usize;
+        let live = LIVE_BYTES[index].fetch_add(bytes, Ordering::Relaxed) + bytes;
+        LIVE_COUNT[index].fetch_add(1, Ordering::Relaxed);
+        PEAK_BYTES[index].fetch_max(live, Ordering::Relaxed);
+        check_budget();
+
+        Self {
+            category,
+            bytes,
+            created: Instant::now(),
+        }
+    }
+
+    #[inline]
+    pub(crate) fn age(&self) -> Duration {
+        self.created.elapsed()
+    }
+}
+
+impl Drop for GpuAllocation {
+    fn drop(&mut self) {
+        let index = self.category as usize;
+        LIVE_BYTES[index].fetch_sub(self.bytes, Ordering::Relaxed);
+        LIVE_COUNT[index].fetch_sub(1, Ordering::Relaxed);
+        log::trace!(
+            "Freed {} bytes of {} memory after {:.2}s",
+            self.bytes,
+            self.category.name(),
+            self.age().as_secs_f64()
+        );
+        check_budget();
+    }
+}
+
+pub fn usage(category: AllocationCategory) -> CategoryUsage {
+    let index = category as usize;
+    CategoryUsage {
+        bytes: LIVE_BYTES[index].load(Ordering::Relaxed),
+        count: LIVE_COUNT[index].load(Ordering::Relaxed),
+        peak_bytes: PEAK_BYTES[index].load(Ordering::Relaxed),
+    }
+}
+
+pub fn total_bytes() -> u64 {
+    LIVE_BYTES
+        .iter()
+        .map(|bytes| bytes.load(Ordering::Relaxed))
+        .sum()
+}
+
+#[inline]
+pub fn budget() -> u64 {
+    BUDGET.load(Ordering::Relaxed)
+}
+
+pub fn set_budget(bytes: u64) {
+    BUDGET.store(bytes, Ordering::Relaxed);
+    check_budget();
+}
+
+// Warns once when crossing the threshold, re-armed when the usage drops below it
+fn check_budget() {
+    let budget = budget();
+    if budget == 0 {
+        return;
+    }
+
+    let total = total_bytes();
+    let over = total as f64 >= budget as f64 * BUDGET_WARNING_THRESHOLD;
+    if over && !OVER_THRESHOLD.swap(true, Ordering::Relaxed) {
+        log::warn!(
+            "GPU memory usage is nearing the budget: {:.1} of {:.1} MiB",
+            total as f64 / (1024.0 * 1024.0),
+            budget as f64 / (1024.0 * 1024.0)
+        );
+    } else if !over {
+        OVER_THRESHOLD.store(false, Ordering::Relaxed);
+    }
+}
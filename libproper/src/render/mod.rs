@@ -1,17 +1,42 @@
 use bytemuck::{Pod, Zeroable};
-use nalgebra::{Point3, Vector3, Point2};
+use nalgebra::{Point3, Vector3, Vector4, Point2};
 
+pub mod arena;
+pub mod aspect;
+pub mod bindless;
+pub mod capabilities;
+pub mod capture;
+pub mod cluster;
+pub mod color;
 pub mod context;
+pub mod debug;
+pub mod effects;
+pub mod extract;
 pub mod frame;
+#[cfg(feature = "golden")]
+pub mod golden;
+pub mod grading;
+pub mod graph;
+pub mod memory;
+pub mod probe;
 pub mod shader;
+pub mod shadow;
+pub mod shafts;
+pub mod stats;
 pub mod system;
+pub mod uniforms;
+pub mod upload;
 
 #[repr(C)]
 #[derive(Default, Clone, Copy, Zeroable, Pod)]
 pub struct Vertex {
     pub v_position: Point3<f32>,
     pub v_normal: Vector3<f32>,
-    pub v_tex_coord: Point2<f32>
+    pub v_tex_coord: Point2<f32>,
+    // xyz along increasing U, w is the sign of the bitangent
+    pub v_tangent: Vector4<f32>,
+    // Second UV set, unique per triangle so baked lighting doesn't repeat
+    pub v_lightmap_coord: Point2<f32>
 }
 
 #[repr(C)]
@@ -20,5 +45,5 @@ pub struct SimpleVertex {
     pub v_position: Point3<f32>
 }
 
-vulkano::impl_vertex!(Vertex, v_position, v_normal, v_tex_coord);
+vulkano::impl_vertex!(Vertex, v_position, v_normal, v_tex_coord, v_tangent, v_lightmap_coord);
 vulkano::impl_vertex!(SimpleVertex, v_position);
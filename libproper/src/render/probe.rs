@@ -0,0 +1,330 @@
+use std::{
+    f32::consts::PI,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, RwLock},
+};
+
+use image::{imageops, RgbaImage};
+use nalgebra::{Matrix4, Point3, Vector3};
+use serde::Deserialize;
+use vulkano::sync::GpuFuture;
+use winit::event_loop::{ControlFlow, EventLoopProxy};
+
+use crate::{
+    error::Error,
+    event::{Event, GameEvent},
+    layer::{priority, Layer},
+    lock::Recover,
+    resource::{material::MaterialRegistry, model::ModelRegistry, texture::TextureRegistry},
+    time::Time,
+    world::{description::SceneDescription, scenes::SceneManager},
+    Application,
+};
+
+use super::{capture::capture_frame, frame::Frame};
+
+const KTX2_IDENTIFIER: [u8; 12] = [
+    0xab, 0x4b, 0x54, 0x58, 0x20, 0x32, 0x30, 0xbb, 0x0d, 0x0a, 0x1a, 0x0a,
+];
+// VK_FORMAT_R8G8B8A8_SRGB
+const KTX2_FORMAT: u32 = 43;
+// Basic data format descriptor block with four 8-bit samples
+const KTX2_DFD_BLOCK_SIZE: u16 = 24 + 16 * 4;
+// Header, index and the level index of a single level
+const KTX2_DFD_OFFSET: u32 = 48 + 32 + 24;
+
+// View direction and the up vector of each face in the KTX2/Vulkan order: +X, -X, +Y, -Y,
+// +Z, -Z. Up is towards the top row of the face
+const CUBE_FACES: [([f32; 3], [f32; 3]); 6] = [
+    ([1.0, 0.0, 0.0], [0.0, 1.0, 0.0]),
+    ([-1.0, 0.0, 0.0], [0.0, 1.0, 0.0]),
+    ([0.0, 1.0, 0.0], [0.0, 0.0, -1.0]),
+    ([0.0, -1.0, 0.0], [0.0, 0.0, 1.0]),
+    ([0.0, 0.0, 1.0], [0.0, 1.0, 0.0]),
+    ([0.0, 0.0, -1.0], [0.0, 1.0, 0.0]),
+];
+
+// Reflection captures of a scene, rendered as cube maps at the probe positions and saved as
+// KTX2 files for image-based lighting. See res/probes/bake.toml
+#[derive(Deserialize, Clone)]
+pub struct ReflectionBake {
+    // Scene file
+    pub scene: PathBuf,
+    // Size of a cube face, the hidden window is made this size
+    #[serde(default = "default_resolution")]
+    pub resolution: u32,
+    // Frames drawn before each face is captured, so uploads have finished
+    #[serde(default = "default_warmup_frames")]
+    pub warmup_frames: u32,
+    // <name>.ktx2 per probe
+    #[serde(default = "default_output_dir")]
+    pub output_dir: PathBuf,
+    #[serde(rename = "probe")]
+    pub probes: Vec<ReflectionProbe>,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct ReflectionProbe {
+    pub name: String,
+    pub position: [f32; 3],
+}
+
+// Runs the bake in place of the game and exits once every probe is written, with status 1 if
+// any failed. The frames are captured after post-processing, so the cube maps are 8-bit sRGB
+pub struct ReflectionBakeLayer {
+    event_proxy: EventLoopProxy<GameEvent>,
+    bake: ReflectionBake,
+    scenes: Arc<Mutex<SceneManager>>,
+    material_registry: Arc<RwLock<MaterialRegistry>>,
+    model_registry: Arc<RwLock<ModelRegistry>>,
+    texture_registry: Arc<RwLock<TextureRegistry>>,
+    loaded: bool,
+    probe: usize,
+    faces: Vec<RgbaImage>,
+    // Frames drawn since the camera was set up for the next face, None until it is
+    frames: Option<u32>,
+    failed: usize,
+}
+
+fn default_resolution() -> u32 {
+    256
+}
+
+fn default_warmup_frames() -> u32 {
+    4
+}
+
+fn default_output_dir() -> PathBuf {
+    PathBuf::from("res/probes")
+}
+
+impl ReflectionBake {
+    pub const DEFAULT_PATH: &'static str = "res/probes/bake.toml";
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path).map_err(|err| Error::file(path, err))?;
+        toml::from_str(&text).map_err(|err| Error::asset_parse(path, err))
+    }
+
+    pub fn output_path(&self, probe: &ReflectionProbe) -> PathBuf {
+        self.output_dir.join(format!("{}.ktx2", probe.name))
+    }
+}
+
+// Library entry point for content pipelines: bakes in a hidden window, then exits the process
+pub fn run_reflection_bake(bake: ReflectionBake) -> Result<(), Error> {
+    Application::builder().reflection_bake(bake).build()?.run();
+    Ok(())
+}
+
+// Single-level cube map, faces in CUBE_FACES order with rows going top to bottom
+pub fn write_ktx2_cube<P: AsRef<Path>>(
+    path: P,
+    size: u32,
+    faces: &[RgbaImage],
+) -> Result<(), Error> {
+    let path = path.as_ref();
+    let face_size = (size * size * 4) as usize;
+    assert!(faces.len() == 6 && faces.iter().all(|face| face.len() == face_size));
+
+    let dfd_size = 4 + KTX2_DFD_BLOCK_SIZE as u32;
+    let data_offset = KTX2_DFD_OFFSET + dfd_size;
+    let data_size = (face_size * 6) as u64;
+
+    let mut out = Vec::with_capacity(data_offset as usize + data_size as usize);
+    out.extend_from_slice(&KTX2_IDENTIFIER);
+    // vkFormat, typeSize, width, height, depth, layers, faces, levels, supercompression
+    for value in [KTX2_FORMAT, 1, size, size, 0, 0, 6, 1, 0] {
+        out.extend_from_slice(&value.to_le_bytes());
+    }
+    // Data format descriptor, no key/value or supercompression data
+    for value in [KTX2_DFD_OFFSET, dfd_size, 0, 0] {
+        out.extend_from_slice(&value.to_le_bytes());
+    }
+    out.extend_from_slice(&0u64.to_le_bytes());
+    out.extend_from_slice(&0u64.to_le_bytes());
+    for value in [data_offset as u64, data_size, data_size] {
+        out.extend_from_slice(&value.to_le_bytes());
+    }
+
+    out.extend_from_slice(&dfd_size.to_le_bytes());
+    // Khronos vendor, basic descriptor type, version 2
+    out.extend_from_slice(&0u32.to_le_bytes());
+    out.extend_from_slice(&2u16.to_le_bytes());
+    out.extend_from_slice(&KTX2_DFD_BLOCK_SIZE.to_le_bytes());
+    // RGBSDA color model, BT.709 primaries, sRGB transfer, straight alpha
+    out.extend_from_slice(&[1, 1, 2, 0]);
+    // 1x1x1 texel blocks, 4 bytes in the only plane
+    out.extend_from_slice(&[0, 0, 0, 0, 4, 0, 0, 0, 0, 0, 0, 0]);
+    // R, G, B, then alpha, which stays linear
+    for (index, channel) in [0u8, 1, 2, 0x1f].into_iter().enumerate() {
+        out.extend_from_slice(&(index as u16 * 8).to_le_bytes());
+        out.extend_from_slice(&[7, channel]);
+        out.extend_from_slice(&[0, 0, 0, 0]);
+        out.extend_from_slice(&0u32.to_le_bytes());
+        out.extend_from_slice(&255u32.to_le_bytes());
+    }
+
+    for face in faces {
+        out.extend_from_slice(face.as_raw());
+    }
+
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).map_err(|err| Error::file(dir, err))?;
+    }
+    std::fs::write(path, out).map_err(|err| Error::file(path, err))
+}
+
+impl ReflectionBakeLayer {
+    pub fn new(
+        event_proxy: EventLoopProxy<GameEvent>,
+        bake: ReflectionBake,
+        scenes: Arc<Mutex<SceneManager>>,
+        material_registry: Arc<RwLock<MaterialRegistry>>,
+        model_registry: Arc<RwLock<ModelRegistry>>,
+        texture_registry: Arc<RwLock<TextureRegistry>>,
+    ) -> Self {
+        Self {
+            event_proxy,
+            bake,
+            scenes,
+            material_registry,
+            model_registry,
+            texture_registry,
+            loaded: false,
+            probe: 0,
+            faces: vec![],
+            frames: None,
+            failed: 0,
+        }
+    }
+
+    fn load_scene(&mut self) -> Result<(), Error> {
+        let scene = {
+            let mut materials = self.material_registry.write().recover();
+            let mut models = self.model_registry.write().recover();
+            let mut textures = self.texture_registry.write().recover();
+            SceneDescription::load(&self.bake.scene)?.instantiate_scene(
+                &mut materials,
+                &mut models,
+                &mut textures,
+            )?
+        };
+
+        let name = "reflection_bake";
+        let mut scenes = self.scenes.lock().recover();
+        scenes.insert(name, scene);
+        scenes.switch_to(name, false)?;
+        self.event_proxy
+            .send_event(GameEvent::SceneSwitched(name.to_owned()))
+            .ok();
+        Ok(())
+    }
+
+    // 90 degree square views, one per face
+    fn set_camera(&self, probe: &ReflectionProbe, face: usize) {
+        let (forward, up) = CUBE_FACES[face];
+        let position = Point3::from(probe.position);
+        let view = Matrix4::look_at_rh(
+            &position,
+            &(position + Vector3::from(forward)),
+            &Vector3::from(up),
+        );
+
+        let scenes = self.scenes.lock().recover();
+        let mut scene = scenes.active().write().recover();
+        scene.camera.set_position(position);
+        scene.camera.set_fov(PI * 0.5);
+        scene.camera.set_view_override(Some(view));
+    }
+
+    fn finish(&self) -> ! {
+        log::info!(
+            "Baked {} reflection probes, {} failed",
+            self.bake.probes.len() - self.failed,
+            self.failed
+        );
+        // The event loop never returns, this is the only way to set the exit status
+        std::process::exit(if self.failed == 0 { 0 } else { 1 });
+    }
+}
+
+impl Layer for ReflectionBakeLayer {
+    fn on_attach(&mut self) {}
+
+    fn on_detach(&mut self) {}
+
+    fn draw_priority(&self) -> i32 {
+        priority::CAPTURE
+    }
+
+    fn on_event(&mut self, _event: &Event, _flow: &mut ControlFlow) -> Result<bool, Error> {
+        Ok(false)
+    }
+
+    fn on_tick(&mut self, _time: &Time) -> Result<(), Error> {
+        if self.frames.is_some() {
+            return Ok(());
+        }
+        if !self.loaded {
+            if let Err(err) = self.load_scene() {
+                log::error!("Reflection bake failed: {}", err.full_message());
+                std::process::exit(1);
+            }
+            self.loaded = true;
+        }
+
+        let probe = match self.bake.probes.get(self.probe) {
+            Some(probe) => probe,
+            None => self.finish(),
+        };
+        self.set_camera(probe, self.faces.len());
+        self.frames = Some(0);
+        Ok(())
+    }
+
+    fn on_draw(
+        &mut self,
+        in_future: Box<dyn GpuFuture>,
+        frame: &Frame,
+    ) -> Result<Box<dyn GpuFuture>, Error> {
+        let frames = match self.frames.as_mut() {
+            Some(frames) => frames,
+            None => return Ok(in_future),
+        };
+        *frames += 1;
+        if *frames <= self.bake.warmup_frames {
+            return Ok(in_future);
+        }
+
+        // Cube maps are seen from the inside, mirrored compared to a camera looking out
+        let (mut face, future) = capture_frame(in_future, frame)?;
+        imageops::flip_horizontal_in_place(&mut face);
+        self.faces.push(face);
+        self.frames = None;
+
+        if self.faces.len() == CUBE_FACES.len() {
+            let probe = &self.bake.probes[self.probe];
+            let faces = std::mem::take(&mut self.faces);
+            let size = self.bake.resolution;
+            let result = if faces.iter().all(|face| face.dimensions() == (size, size)) {
+                write_ktx2_cube(self.bake.output_path(probe), size, &faces)
+            } else {
+                Err(Error::asset_parse(
+                    &self.bake.scene,
+                    "the window isn't the size of a cube face",
+                ))
+            };
+            match result {
+                Ok(()) => log::info!("Baked reflection probe {}", probe.name),
+                Err(err) => {
+                    log::error!("Reflection probe {}: {}", probe.name, err.full_message());
+                    self.failed += 1;
+                }
+            }
+            self.probe += 1;
+        }
+        Ok(future)
+    }
+}
@@ -0,0 +1,9 @@
+#version 450
+
+layout(location = 0) in vec3 m_color;
+
+layout(location = 0) out vec4 f_color;
+
+void main() {
+    f_color = vec4(m_color, 1.0);
+}
@@ -0,0 +1,20 @@
+#version 450
+
+layout(push_constant) uniform Axes_Data {
+    mat4 view_projection;
+    float axis_length;
+} u_axes;
+
+layout(location = 0) out vec3 m_color;
+
+// X, Y and Z lines from the origin, no vertex buffer needed
+const vec3 DIRECTIONS[3] = vec3[](vec3(1.0, 0.0, 0.0), vec3(0.0, 1.0, 0.0), vec3(0.0, 0.0, 1.0));
+const vec3 COLORS[3] = vec3[](vec3(1.0, 0.15, 0.15), vec3(0.15, 1.0, 0.15), vec3(0.15, 0.3, 1.0));
+
+void main() {
+    int axis = gl_VertexIndex / 2;
+    vec3 position = DIRECTIONS[axis] * u_axes.axis_length * float(gl_VertexIndex % 2);
+
+    m_color = COLORS[axis];
+    gl_Position = u_axes.view_projection * vec4(position, 1.0);
+}
@@ -0,0 +1,76 @@
+#version 450
+
+layout(location = 0) in vec3 m_near;
+layout(location = 1) in vec3 m_far;
+
+layout(push_constant) uniform Grid_Data {
+    mat4 inv_view_projection;
+    vec4 camera_position;
+    float near;
+    float far;
+    float spacing;
+    float fade_distance;
+    int axes;
+} u_grid;
+
+layout(location = 0) out vec4 f_color;
+
+const vec3 LINE_COLOR = vec3(0.5);
+const vec3 X_AXIS_COLOR = vec3(1.0, 0.15, 0.15);
+const vec3 Z_AXIS_COLOR = vec3(0.15, 0.3, 1.0);
+
+// 1 on a line, 0 between them, lines are about a pixel wide at any distance
+float grid_line(vec2 coord, float spacing) {
+    vec2 scaled = coord / spacing;
+    vec2 grid = abs(fract(scaled - 0.5) - 0.5) / fwidth(scaled);
+    return 1.0 - min(min(grid.x, grid.y), 1.0);
+}
+
+void main() {
+    // Where the view ray of this pixel hits the Y=0 plane
+    float denominator = m_far.y - m_near.y;
+    if (abs(denominator) < 1e-6) {
+        discard;
+    }
+    float t = -m_near.y / denominator;
+    if (t <= 0.0 || t > 1.0) {
+        discard;
+    }
+    vec3 position = m_near + t * (m_far - m_near);
+
+    // Both points are on the same ray from the camera, so the view depth is linear in t
+    float n = u_grid.near;
+    float f = u_grid.far;
+    float depth = n + t * (f - n);
+    // Same projection as the scene, which gets clipped below 0 as well
+    float ndc_depth = (f + n) / (f - n) - 2.0 * f * n / ((f - n) * depth);
+    if (ndc_depth < 0.0) {
+        discard;
+    }
+    gl_FragDepth = ndc_depth;
+
+    float minor = grid_line(position.xz, u_grid.spacing);
+    float major = grid_line(position.xz, u_grid.spacing * 10.0);
+    vec3 color = LINE_COLOR;
+    float alpha = max(minor * 0.3, major * 0.6);
+
+    if (u_grid.axes != 0) {
+        vec2 width = fwidth(position.xz);
+        if (abs(position.z) < width.y) {
+            color = X_AXIS_COLOR;
+            alpha = 1.0;
+        }
+        if (abs(position.x) < width.x) {
+            color = Z_AXIS_COLOR;
+            alpha = 1.0;
+        }
+    }
+
+    float distance = length(position.xz - u_grid.camera_position.xz);
+    alpha *= 1.0 - smoothstep(u_grid.fade_distance * 0.5, u_grid.fade_distance, distance);
+    if (alpha <= 0.0) {
+        discard;
+    }
+
+    f_color = vec4(color, alpha);
+}
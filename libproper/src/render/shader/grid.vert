@@ -0,0 +1,30 @@
+#version 450
+
+layout(push_constant) uniform Grid_Data {
+    mat4 inv_view_projection;
+    vec4 camera_position;
+    float near;
+    float far;
+    float spacing;
+    float fade_distance;
+    int axes;
+} u_grid;
+
+layout(location = 0) out vec3 m_near;
+layout(location = 1) out vec3 m_far;
+
+// Single triangle covering the screen, no vertex buffer needed
+const vec2 POSITIONS[3] = vec2[](vec2(-1.0, -1.0), vec2(3.0, -1.0), vec2(-1.0, 3.0));
+
+vec3 unproject(vec2 xy, float z) {
+    vec4 point = u_grid.inv_view_projection * vec4(xy, z, 1.0);
+    return point.xyz / point.w;
+}
+
+void main() {
+    vec2 xy = POSITIONS[gl_VertexIndex];
+
+    m_near = unproject(xy, -1.0);
+    m_far = unproject(xy, 1.0);
+    gl_Position = vec4(xy, 0.0, 1.0);
+}
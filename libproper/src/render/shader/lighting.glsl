@@ -0,0 +1,137 @@
+// Light_Data and the shadow atlas, included by the scene fragment shaders. Custom material
+// shaders have to include it as well, set 0 must have the same layout in every pipeline
+
+// Must match MAX_SHADOW_MAPS and SHADOW_ATLAS_TILES in shadow.rs
+#define MAX_SHADOW_MAPS 16
+#define SHADOW_ATLAS_TILES 4
+// Depth offset against shadow acne
+#define SHADOW_BIAS 0.0005
+
+// Local lights shaded per fragment at most, cheaper permutations lower it. Custom shaders get
+// it as a define from the variant, for the others it's a specialization constant
+#ifndef NUM_LIGHTS
+// Must match MAX_LOCAL_LIGHTS in shadow.rs
+layout(constant_id = 5) const int NUM_LIGHTS = 1024;
+#endif
+
+struct Local_Light {
+    // w is the range
+    vec4 position;
+    // Premultiplied by the intensity, w is the first shadow map
+    vec4 color;
+    // Axis of spot lights, zero for point lights
+    vec4 direction;
+    // Cosines of the outer and the inner angle, then the number of shadow maps
+    vec4 cone;
+};
+
+layout(set = 0, binding = 1) uniform Light_Data {
+    vec4 direction;
+    vec4 color;
+    vec4 ambient;
+    // Columns, rows and depth slices of the light clusters
+    uvec4 cluster_grid;
+    // Framebuffer size, near plane, log(far / near)
+    vec4 cluster_params;
+    // Third row of the view matrix, minus the view depth of a world position
+    vec4 view_z;
+    mat4 shadow_matrices[MAX_SHADOW_MAPS];
+} u_light;
+
+layout(set = 0, binding = 2) uniform sampler2DShadow u_shadow_atlas;
+
+layout(set = 0, binding = 3) readonly buffer Light_Buffer {
+    Local_Light lights[];
+} u_lights;
+
+// Offset into the index list and light count of every cluster
+layout(set = 0, binding = 4) readonly buffer Cluster_Buffer {
+    uvec2 clusters[];
+} u_clusters;
+
+layout(set = 0, binding = 5) readonly buffer Cluster_Indices {
+    uint indices[];
+} u_cluster_indices;
+
+float shadow(int map, vec3 position) {
+    vec4 clip = u_light.shadow_matrices[map] * vec4(position, 1.0);
+    vec3 ndc = clip.xyz / clip.w;
+
+    // Clamped to the tile, so filtering never reads the one next to it
+    float tile_size = 1.0 / SHADOW_ATLAS_TILES;
+    vec2 texel = 1.0 / vec2(textureSize(u_shadow_atlas, 0));
+    vec2 tile = vec2(map % SHADOW_ATLAS_TILES, map / SHADOW_ATLAS_TILES) * tile_size;
+    vec2 uv = clamp(tile + (ndc.xy * 0.5 + 0.5) * tile_size, tile + texel, tile + tile_size - texel);
+    return texture(u_shadow_atlas, vec3(uv, ndc.z - SHADOW_BIAS));
+}
+
+// Same order as the cube faces in shadow.rs: +X, -X, +Y, -Y, +Z, -Z
+int cube_face(vec3 direction) {
+    vec3 axis = abs(direction);
+    if (axis.x >= axis.y && axis.x >= axis.z) {
+        return direction.x >= 0.0 ? 0 : 1;
+    }
+    if (axis.y >= axis.z) {
+        return direction.y >= 0.0 ? 2 : 3;
+    }
+    return direction.z >= 0.0 ? 4 : 5;
+}
+
+vec3 local_light(Local_Light light, vec3 normal, vec3 position) {
+    vec3 to_light = light.position.xyz - position;
+    float distance = length(to_light);
+    float range = light.position.w;
+    if (distance >= range) {
+        return vec3(0.0);
+    }
+    vec3 l = to_light / max(distance, 1e-4);
+
+    // Inverse square, windowed to reach zero at the range
+    float window = clamp(1.0 - pow(distance / range, 4.0), 0.0, 1.0);
+    float attenuation = window * window / (distance * distance + 1.0);
+    // Always 1 for point lights, their cone covers everything
+    float cone = smoothstep(light.cone.x, light.cone.y, dot(-l, light.direction.xyz));
+    float lit = attenuation * cone * clamp(dot(normal, l), 0.0, 1.0);
+
+    int maps = int(light.cone.z);
+    if (lit > 0.0 && maps > 0) {
+        int map = int(light.color.w) + (maps == 6 ? cube_face(-to_light) : 0);
+        lit *= shadow(map, position);
+    }
+    return light.color.rgb * lit;
+}
+
+// Same layout as LightClusters: x fastest, then y, then the exponential depth slices
+uint cluster_index(vec3 position) {
+    uvec3 grid = u_light.cluster_grid.xyz;
+    float near = u_light.cluster_params.z;
+    vec2 tile = gl_FragCoord.xy / u_light.cluster_params.xy * vec2(grid.xy);
+    float depth = max(-dot(u_light.view_z, vec4(position, 1.0)), near);
+    float slice = log(depth / near) / u_light.cluster_params.w * float(grid.z);
+    uvec3 cell = min(uvec3(max(vec3(tile, slice), 0.0)), grid - 1u);
+    return (cell.z * grid.y + cell.y) * grid.x + cell.x;
+}
+
+// Tangent space normal map texel to a normal, the tangent's w is the sign of the bitangent
+vec3 apply_normal_map(vec3 normal, vec4 tangent, vec3 texel) {
+    vec3 n = normalize(normal);
+    vec3 t = normalize(tangent.xyz - n * dot(n, tangent.xyz));
+    vec3 b = cross(n, t) * tangent.w;
+    vec3 m = texel * 2.0 - 1.0;
+    return normalize(t * m.x + b * m.y + n * m.z);
+}
+
+// The ambient term is u_light.ambient, or the baked indirect light of lightmapped meshes
+vec3 shade(vec3 albedo, vec3 normal, vec3 position, vec3 ambient) {
+    normal = normalize(normal);
+    float cos_theta = clamp(dot(normal, -u_light.direction.xyz), 0, 1);
+    vec3 light = u_light.color.rgb * cos_theta + ambient;
+
+    uvec2 cluster = u_clusters.clusters[cluster_index(position)];
+    uint count = min(cluster.y, uint(NUM_LIGHTS));
+    for (uint i = 0u; i < count; ++i) {
+        uint index = u_cluster_indices.indices[cluster.x + i];
+        light += local_light(u_lights.lights[index], normal, position);
+    }
+    return albedo * light;
+}
@@ -2,6 +2,12 @@
 #![allow(clippy::needless_question_mark)]
 #![allow(unused)]
 
+use std::{collections::BTreeMap, sync::Arc};
+
+use vulkano::{device::Device, shader::ShaderModule};
+
+use crate::error::Error;
+
 pub mod simple_vs {
     vulkano_shaders::shader! {
         ty: "vertex",
@@ -24,6 +30,32 @@ pub mod simple_fs {
     }
 }
 
+pub mod bindless_fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        path: "src/render/shader/scene_bindless.frag",
+        types_meta: {
+            use bytemuck::{Pod, Zeroable};
+            #[derive(Clone, Copy, Pod, Zeroable)]
+        }
+    }
+}
+
+// No derived traits for these two, their storage blocks have unsized arrays
+pub mod indirect_vs {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        path: "src/render/shader/scene_indirect.vert"
+    }
+}
+
+pub mod cull_cs {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        path: "src/render/shader/cull.comp"
+    }
+}
+
 pub mod screen_vs {
     vulkano_shaders::shader! {
         ty: "vertex",
@@ -45,3 +77,188 @@ pub mod screen_fs {
         }
     }
 }
+
+pub mod grid_vs {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        path: "src/render/shader/grid.vert",
+        types_meta: {
+            use bytemuck::{Pod, Zeroable};
+            #[derive(Clone, Copy, Pod, Zeroable)]
+        }
+    }
+}
+
+pub mod grid_fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        path: "src/render/shader/grid.frag",
+        types_meta: {
+            use bytemuck::{Pod, Zeroable};
+            #[derive(Clone, Copy, Pod, Zeroable)]
+        }
+    }
+}
+
+pub mod axes_vs {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        path: "src/render/shader/axes.vert",
+        types_meta: {
+            use bytemuck::{Pod, Zeroable};
+            #[derive(Clone, Copy, Pod, Zeroable)]
+        }
+    }
+}
+
+pub mod axes_fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        path: "src/render/shader/axes.frag",
+        types_meta: {
+            use bytemuck::{Pod, Zeroable};
+            #[derive(Clone, Copy, Pod, Zeroable)]
+        }
+    }
+}
+
+pub mod shadow_vs {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        path: "src/render/shader/shadow.vert",
+        types_meta: {
+            use bytemuck::{Pod, Zeroable};
+            #[derive(Clone, Copy, Pod, Zeroable)]
+        }
+    }
+}
+
+pub mod shadow_fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        path: "src/render/shader/shadow.frag"
+    }
+}
+
+// Engine headers runtime-compiled shaders can include, custom materials need lighting.glsl
+// for set 0
+const INCLUDES: &[(&str, &str)] = &[("lighting.glsl", include_str!("lighting.glsl"))];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ShaderStage {
+    Vertex,
+    Fragment,
+}
+
+// Set of preprocessor defines/specialization values selecting a shader permutation
+#[derive(Clone, Default, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ShaderVariant {
+    defines: BTreeMap<String, String>,
+}
+
+#[derive(Clone)]
+pub enum ShaderSource {
+    SpirV(Vec<u32>),
+    Glsl(String),
+}
+
+impl ShaderSource {
+    pub fn load(
+        &self,
+        device: Arc<Device>,
+        stage: ShaderStage,
+        name: &str,
+        variant: &ShaderVariant,
+    ) -> Result<Arc<ShaderModule>, Error> {
+        match self {
+            Self::SpirV(words) => {
+                if *variant != ShaderVariant::default() {
+                    log::warn!("{}: defines are ignored for precompiled SPIR-V", name);
+                }
+                unsafe { ShaderModule::from_words(device, words) }.map_err(Error::from)
+            }
+            Self::Glsl(source) => {
+                let words = compile_glsl(source, stage, name, variant)?;
+                unsafe { ShaderModule::from_words(device, &words) }.map_err(Error::from)
+            }
+        }
+    }
+}
+
+impl ShaderVariant {
+    // Keys understood by the built-in materials, as specialization constants. Custom material
+    // shaders get every key of the variant as a preprocessor define
+    pub const HAS_DIFFUSE_MAP: &'static str = "HAS_DIFFUSE_MAP";
+    pub const HAS_NORMAL_MAP: &'static str = "HAS_NORMAL_MAP";
+    pub const UNLIT: &'static str = "UNLIT";
+    pub const ALPHA_TEST: &'static str = "ALPHA_TEST";
+    pub const LIGHTMAP: &'static str = "LIGHTMAP";
+    // Local lights shaded per fragment at most
+    pub const NUM_LIGHTS: &'static str = "NUM_LIGHTS";
+    // Skinned vertex shader. Vertex has no joints or weights, so the built-in materials refuse
+    // it and custom materials only get the define
+    pub const SKINNED: &'static str = "SKINNED";
+
+    pub fn with_define(self, name: &str) -> Self {
+        self.with_value(name, "1")
+    }
+
+    pub fn with_value<V: ToString>(mut self, name: &str, value: V) -> Self {
+        self.defines.insert(name.to_owned(), value.to_string());
+        self
+    }
+
+    pub fn is_defined(&self, name: &str) -> bool {
+        self.defines.contains_key(name)
+    }
+
+    pub fn value_or(&self, name: &str, default: i32) -> i32 {
+        match self.defines.get(name) {
+            Some(value) if value.is_empty() => 1,
+            Some(value) => value.parse().unwrap_or_else(|_| {
+                log::warn!("Non-integer value for shader define {}: {:?}", name, value);
+                default
+            }),
+            None => default,
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &String)> {
+        self.defines.iter()
+    }
+}
+
+fn compile_glsl(
+    source: &str,
+    stage: ShaderStage,
+    name: &str,
+    variant: &ShaderVariant,
+) -> Result<Vec<u32>, Error> {
+    let compiler = shaderc::Compiler::new().ok_or(Error::ShaderCompilerUnavailable)?;
+    let mut options = shaderc::CompileOptions::new().ok_or(Error::ShaderCompilerUnavailable)?;
+    for (define, value) in variant.iter() {
+        options.add_macro_definition(define, Some(value));
+    }
+    options.set_include_callback(|requested, _, _, _| {
+        INCLUDES
+            .iter()
+            .find(|(name, _)| *name == requested)
+            .map(|(name, content)| shaderc::ResolvedInclude {
+                resolved_name: (*name).to_owned(),
+                content: (*content).to_owned(),
+            })
+            .ok_or_else(|| format!("Unknown include {}", requested))
+    });
+
+    let kind = match stage {
+        ShaderStage::Vertex => shaderc::ShaderKind::Vertex,
+        ShaderStage::Fragment => shaderc::ShaderKind::Fragment,
+    };
+
+    let artifact = compiler.compile_into_spirv(source, kind, name, "main", Some(&options))?;
+    if artifact.get_num_warnings() != 0 {
+        log::warn!("{}: {}", name, artifact.get_warning_messages());
+    }
+
+    Ok(artifact.as_binary().to_vec())
+}
@@ -2,22 +2,52 @@
 
 layout(location = 0) in vec3 m_normal;
 layout(location = 1) in vec2 m_tex_coord;
+// World space
+layout(location = 2) in vec3 m_position;
+layout(location = 3) in vec2 m_lightmap_coord;
+layout(location = 4) in vec4 m_tangent;
+
+#include "lighting.glsl"
 
 layout(set = 1, binding = 0) uniform Material_Data {
     vec4 diffuse_color;
 } mat;
 layout(set = 1, binding = 1) uniform sampler2D u_diffuse_map;
+// Baked indirect light, in the second UV set
+layout(set = 1, binding = 2) uniform sampler2D u_lightmap;
+layout(set = 1, binding = 3) uniform sampler2D u_normal_map;
 
 layout(location = 0) out vec4 f_color;
 
-const vec3 c_light_direction = normalize(vec3(-1.0, -1.0, -1.0));
+layout(constant_id = 0) const int HAS_DIFFUSE_MAP = 1;
+layout(constant_id = 1) const int UNLIT = 0;
+// Cutout sprites and tiles, texels under half opacity are dropped
+layout(constant_id = 2) const int ALPHA_TEST = 0;
+layout(constant_id = 3) const int LIGHTMAP = 0;
+layout(constant_id = 4) const int HAS_NORMAL_MAP = 0;
 
 void main() {
-    vec3 color_in = mat.diffuse_color.xyz * texture(u_diffuse_map, m_tex_coord).rgb;
-
-    float cos_theta = clamp(dot(m_normal, -c_light_direction), 0, 1);
-
-    vec3 color_out = color_in * cos_theta + color_in * 0.1;
+    vec3 color_in = mat.diffuse_color.xyz;
+    if (HAS_DIFFUSE_MAP != 0) {
+        vec4 texel = texture(u_diffuse_map, m_tex_coord);
+        if (ALPHA_TEST != 0 && texel.a < 0.5) {
+            discard;
+        }
+        color_in *= texel.rgb;
+    }
+
+    vec3 color_out = color_in;
+    if (UNLIT == 0) {
+        vec3 ambient = u_light.ambient.rgb;
+        if (LIGHTMAP != 0) {
+            ambient = texture(u_lightmap, m_lightmap_coord).rgb;
+        }
+        vec3 normal = m_normal;
+        if (HAS_NORMAL_MAP != 0) {
+            normal = apply_normal_map(normal, m_tangent, texture(u_normal_map, m_tex_coord).rgb);
+        }
+        color_out = shade(color_in, normal, m_position, ambient);
+    }
 
     f_color = vec4(color_out, mat.diffuse_color.a);
 }
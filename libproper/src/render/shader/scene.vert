@@ -3,10 +3,18 @@
 layout(location = 0) in vec3 v_position;
 layout(location = 1) in vec3 v_normal;
 layout(location = 2) in vec2 v_tex_coord;
+layout(location = 3) in vec4 v_tangent;
+layout(location = 4) in vec2 v_lightmap_coord;
 
 layout(set = 0, binding = 0) uniform Scene_Data {
     mat4 projection;
     mat4 view;
+    mat4 inv_projection;
+    mat4 inv_view;
+    vec4 camera_position;
+    float near;
+    float far;
+    float time;
 } u_scene;
 
 layout(set = 2, binding = 0) uniform Model_Data {
@@ -15,10 +23,17 @@ layout(set = 2, binding = 0) uniform Model_Data {
 
 layout(location = 0) out vec3 m_normal;
 layout(location = 1) out vec2 m_tex_coord;
+layout(location = 2) out vec3 m_position;
+layout(location = 3) out vec2 m_lightmap_coord;
+layout(location = 4) out vec4 m_tangent;
 
 void main() {
-    gl_Position = u_scene.projection * u_scene.view * u_model.transform * vec4(v_position, 1.0);
+    vec4 position = u_model.transform * vec4(v_position, 1.0);
+    gl_Position = u_scene.projection * u_scene.view * position;
 
     m_tex_coord = v_tex_coord;
     m_normal = v_normal;
+    m_position = position.xyz;
+    m_lightmap_coord = v_lightmap_coord;
+    m_tangent = v_tangent;
 }
@@ -0,0 +1,58 @@
+#version 450
+
+// Must match BINDLESS_TEXTURE_COUNT
+#define BINDLESS_TEXTURE_COUNT 1024
+
+layout(location = 0) in vec3 m_normal;
+layout(location = 1) in vec2 m_tex_coord;
+// World space
+layout(location = 2) in vec3 m_position;
+layout(location = 3) in vec2 m_lightmap_coord;
+layout(location = 4) in vec4 m_tangent;
+
+#include "lighting.glsl"
+
+layout(set = 1, binding = 0) uniform sampler2D u_textures[BINDLESS_TEXTURE_COUNT];
+
+// Per-instance, x of texture_indices is the diffuse map, y the lightmap and z the normal map
+layout(push_constant) uniform Material_Push {
+    vec4 diffuse_color;
+    uvec4 texture_indices;
+} mat;
+
+layout(location = 0) out vec4 f_color;
+
+layout(constant_id = 0) const int HAS_DIFFUSE_MAP = 1;
+layout(constant_id = 1) const int UNLIT = 0;
+// Cutout sprites and tiles, texels under half opacity are dropped
+layout(constant_id = 2) const int ALPHA_TEST = 0;
+layout(constant_id = 3) const int LIGHTMAP = 0;
+layout(constant_id = 4) const int HAS_NORMAL_MAP = 0;
+
+void main() {
+    vec3 color_in = mat.diffuse_color.xyz;
+    if (HAS_DIFFUSE_MAP != 0) {
+        // Push constants are uniform across the draw, so no nonuniformEXT is needed
+        vec4 texel = texture(u_textures[mat.texture_indices.x], m_tex_coord);
+        if (ALPHA_TEST != 0 && texel.a < 0.5) {
+            discard;
+        }
+        color_in *= texel.rgb;
+    }
+
+    vec3 color_out = color_in;
+    if (UNLIT == 0) {
+        vec3 ambient = u_light.ambient.rgb;
+        if (LIGHTMAP != 0) {
+            ambient = texture(u_textures[mat.texture_indices.y], m_lightmap_coord).rgb;
+        }
+        vec3 normal = m_normal;
+        if (HAS_NORMAL_MAP != 0) {
+            vec3 texel = texture(u_textures[mat.texture_indices.z], m_tex_coord).rgb;
+            normal = apply_normal_map(normal, m_tangent, texel);
+        }
+        color_out = shade(color_in, normal, m_position, ambient);
+    }
+
+    f_color = vec4(color_out, mat.diffuse_color.a);
+}
@@ -0,0 +1,48 @@
+#version 450
+
+layout(location = 0) in vec3 v_position;
+layout(location = 1) in vec3 v_normal;
+layout(location = 2) in vec2 v_tex_coord;
+layout(location = 3) in vec4 v_tangent;
+layout(location = 4) in vec2 v_lightmap_coord;
+
+layout(set = 0, binding = 0) uniform Scene_Data {
+    mat4 projection;
+    mat4 view;
+    mat4 inv_projection;
+    mat4 inv_view;
+    vec4 camera_position;
+    float near;
+    float far;
+    float time;
+} u_scene;
+
+// Same layout as InstanceData, the bounds are only used for culling
+struct Instance_Data {
+    mat4 transform;
+    vec4 bounds_min;
+    vec4 bounds_max;
+};
+
+// Indexed by the first_instance of the indirect command
+layout(set = 2, binding = 0) readonly buffer Instance_Buffer {
+    Instance_Data data[];
+} u_instances;
+
+layout(location = 0) out vec3 m_normal;
+layout(location = 1) out vec2 m_tex_coord;
+layout(location = 2) out vec3 m_position;
+layout(location = 3) out vec2 m_lightmap_coord;
+layout(location = 4) out vec4 m_tangent;
+
+void main() {
+    mat4 transform = u_instances.data[gl_InstanceIndex].transform;
+    vec4 position = transform * vec4(v_position, 1.0);
+    gl_Position = u_scene.projection * u_scene.view * position;
+
+    m_tex_coord = v_tex_coord;
+    m_normal = v_normal;
+    m_position = position.xyz;
+    m_lightmap_coord = v_lightmap_coord;
+    m_tangent = v_tangent;
+}
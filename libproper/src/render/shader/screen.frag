@@ -1,14 +1,131 @@
 #version 450
 
+layout(location = 0) in vec2 m_ndc;
+
 layout(location = 0) out vec4 f_color;
 
-layout(input_attachment_index = 0, set = 0, binding = 0) uniform subpassInputMS u_color;
+// Already resolved when MSAA is on, so this doesn't depend on the sample count. Alpha is
+// scene coverage, 0 where only the sky shows
+layout(set = 0, binding = 0) uniform sampler2D u_color;
+// Color grading LUTs blended between, see render::grading for the strip layout
+layout(set = 1, binding = 0) uniform sampler2D u_lut_from;
+layout(set = 1, binding = 1) uniform sampler2D u_lut_to;
+
+// See render::color::OutputSettings, the overlays are render::effects::ScreenOverlay, the
+// shafts render::shafts::SunShafts and the grading render::grading::ScreenGrading. Vectors go
+// first so the block has no padding
+layout(push_constant) uniform Output_Data {
+    vec4 vignette;
+    vec4 flash;
+    // xy is the sun position in texture coordinates, z the shaft strength (0 is off) and w
+    // the fraction of the way to the sun the blur reaches
+    vec4 sun;
+    // Light color, alpha is the falloff between blur samples
+    vec4 shaft_color;
+    // x is the blend towards the second LUT, y the intensity (0 is off)
+    vec4 grading;
+    float gamma;
+    float contrast;
+    float brightness;
+    float paper_white;
+    int hdr10;
+    // Bar sizes relative to the screen height/width
+    float letterbox;
+    float pillarbox;
+} u_output;
+
+// Linear BT.709 to linear BT.2020 primaries (column-major)
+const mat3 REC709_TO_REC2020 = mat3(
+    0.6274, 0.0691, 0.0164,
+    0.3293, 0.9195, 0.0880,
+    0.0433, 0.0114, 0.8956
+);
+
+// Contrast pivots around linear middle grey
+const float MIDDLE_GREY = 0.18;
+// Distance from the center, relative to the corners, where the vignette starts
+const float VIGNETTE_START = 0.4;
+const int SHAFT_SAMPLES = 48;
+
+// SMPTE ST 2084 inverse EOTF
+vec3 pq_encode(vec3 nits) {
+    const float m1 = 0.1593017578125;
+    const float m2 = 78.84375;
+    const float c1 = 0.8359375;
+    const float c2 = 18.8515625;
+    const float c3 = 18.6875;
+
+    vec3 y = pow(clamp(nits / 10000.0, 0.0, 1.0), vec3(m1));
+    return pow((c1 + c2 * y) / (1.0 + c3 * y), vec3(m2));
+}
+
+vec3 srgb_encode(vec3 color) {
+    return mix(color * 12.92, 1.055 * pow(color, vec3(1.0 / 2.4)) - 0.055, step(0.0031308, color));
+}
+
+vec3 srgb_decode(vec3 color) {
+    return mix(color / 12.92, pow((color + 0.055) / 1.055, vec3(2.4)), step(0.04045, color));
+}
+
+// Blue picks the two nearest slices of the strip, the filtering does red and green
+vec3 lut_lookup(sampler2D lut, vec3 color) {
+    float size = float(textureSize(lut, 0).y);
+    vec3 texel = color * (size - 1.0);
+    float slice = floor(texel.b);
+    float next = min(slice + 1.0, size - 1.0);
+    vec2 uv = (texel.rg + 0.5) / vec2(size * size, size);
+    vec3 low = texture(lut, uv + vec2(slice / size, 0.0)).rgb;
+    vec3 high = texture(lut, uv + vec2(next / size, 0.0)).rgb;
+    return mix(low, high, texel.b - slice);
+}
+
+// LUTs only cover 0 to 1, brighter HDR colors are clipped in proportion to the intensity
+vec3 color_grade(vec3 color) {
+    vec3 encoded = srgb_encode(clamp(color, 0.0, 1.0));
+    vec3 graded = mix(lut_lookup(u_lut_from, encoded), lut_lookup(u_lut_to, encoded), u_output.grading.x);
+    return mix(color, srgb_decode(graded), u_output.grading.y);
+}
+
+// Radial blur of the open sky towards the sun (GPU Gems 3, chapter 13), scene geometry
+// blocks it
+vec3 light_shafts(vec2 uv) {
+    vec2 delta = (uv - u_output.sun.xy) * u_output.sun.w / float(SHAFT_SAMPLES);
+    vec2 position = uv;
+    float falloff = 1.0;
+    float sum = 0.0;
+    for (int i = 0; i < SHAFT_SAMPLES; i++) {
+        position -= delta;
+        sum += (1.0 - texture(u_color, position).a) * falloff;
+        falloff *= u_output.shaft_color.a;
+    }
+    return u_output.shaft_color.rgb * u_output.sun.z * sum / float(SHAFT_SAMPLES);
+}
 
 void main() {
-    vec4 color_out = vec4(0.0);
-    for (int i = 0; i < 4; ++i) {
-        color_out += subpassLoad(u_color, i);
+    if (abs(m_ndc.y) > 1.0 - 2.0 * u_output.letterbox || abs(m_ndc.x) > 1.0 - 2.0 * u_output.pillarbox) {
+        f_color = vec4(0.0, 0.0, 0.0, 1.0);
+        return;
+    }
+
+    vec3 color = texelFetch(u_color, ivec2(gl_FragCoord.xy), 0).rgb;
+    if (u_output.sun.z > 0.0) {
+        color += light_shafts(m_ndc * 0.5 + 0.5);
     }
-    color_out /= vec4(4.0);
-    f_color = color_out;
+
+    float edge = smoothstep(VIGNETTE_START, 1.0, length(m_ndc) * 0.7071);
+    color = mix(color, u_output.vignette.rgb, edge * u_output.vignette.a);
+    color = mix(color, u_output.flash.rgb, u_output.flash.a);
+
+    color = max((color - MIDDLE_GREY) * u_output.contrast + MIDDLE_GREY + u_output.brightness, 0.0);
+    color = pow(color, vec3(1.0 / u_output.gamma));
+    if (u_output.grading.y > 0.0) {
+        color = color_grade(color);
+    }
+
+    // SDR swapchains are sRGB, the hardware encodes the linear value on write
+    if (u_output.hdr10 != 0) {
+        color = pq_encode(REC709_TO_REC2020 * color * u_output.paper_white);
+    }
+
+    f_color = vec4(color, 1.0);
 }
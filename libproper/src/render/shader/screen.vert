@@ -2,6 +2,9 @@
 
 layout(location = 0) in vec3 v_position;
 
+layout(location = 0) out vec2 m_ndc;
+
 void main() {
     gl_Position = vec4(v_position, 1.0);
+    m_ndc = v_position.xy;
 }
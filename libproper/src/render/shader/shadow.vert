@@ -0,0 +1,15 @@
+#version 450
+
+layout(location = 0) in vec3 v_position;
+
+layout(set = 2, binding = 0) uniform Model_Data {
+    mat4 transform;
+} u_model;
+
+layout(push_constant) uniform Shadow_Data {
+    mat4 view_projection;
+} u_shadow;
+
+void main() {
+    gl_Position = u_shadow.view_projection * u_model.transform * vec4(v_position, 1.0);
+}
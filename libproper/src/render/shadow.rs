@@ -0,0 +1,165 @@
+use std::f32::consts::PI;
+
+use nalgebra::{Matrix4, Point3, Vector3};
+
+use crate::{
+    preferences::{Preferences, SHADOWS, SHADOW_BUDGET, SHADOW_RESOLUTION},
+    world::light::{LightKind, LocalLight},
+};
+
+// Size of the light buffer, lights are culled per cluster so most fragments see few of them
+pub const MAX_LOCAL_LIGHTS: usize = 1024;
+// Must match lighting.glsl
+pub const MAX_SHADOW_MAPS: usize = 16;
+// Shadow maps are tiles of a single atlas, this many on each side
+pub const SHADOW_ATLAS_TILES: usize = 4;
+
+const SHADOW_NEAR: f32 = 0.05;
+// Wider cones would need a near plane too close to the light
+const MAX_SPOT_FOV: f32 = PI * 0.95;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ShadowSettings {
+    pub enabled: bool,
+    // Shadow maps rendered per frame, a point light takes six
+    pub budget: usize,
+    // Size of a single map, applied on the next start
+    pub resolution: u32,
+}
+
+// One tile of the shadow atlas
+#[derive(Clone, Copy, Debug)]
+pub struct ShadowMap {
+    pub view_projection: Matrix4<f32>,
+    // Only geometry within range of the light is drawn into the map
+    pub light_position: Point3<f32>,
+    pub range: f32,
+}
+
+// Local lights shaded this frame and the shadow maps rendered for them
+#[derive(Clone, Debug, Default)]
+pub struct LightSelection {
+    // Index of the light in the scene and its first shadow map, most important first
+    pub lights: Vec<(usize, Option<usize>)>,
+    // In atlas order
+    pub shadow_maps: Vec<ShadowMap>,
+}
+
+impl ShadowSettings {
+    pub fn from_preferences(preferences: &Preferences) -> Self {
+        Self {
+            enabled: preferences.bool(SHADOWS, true),
+            budget: preferences
+                .integer(SHADOW_BUDGET, MAX_SHADOW_MAPS as i64)
+                .clamp(0, MAX_SHADOW_MAPS as i64) as usize,
+            resolution: preferences.integer(SHADOW_RESOLUTION, 512).clamp(64, 2048) as u32,
+        }
+    }
+}
+
+impl LightSelection {
+    // The light budget: lights are ranked by how much they matter to the view and get shadow
+    // maps in that order while the budget lasts. A light that doesn't fit anymore leaves the
+    // rest to cheaper ones, so a spot light can still get a map after a point light didn't.
+    // Past MAX_LOCAL_LIGHTS the least important lights are dropped altogether
+    pub fn select(
+        lights: &[LocalLight],
+        camera_position: &Point3<f32>,
+        settings: &ShadowSettings,
+    ) -> Self {
+        let mut ranked: Vec<(usize, f32)> = lights
+            .iter()
+            .enumerate()
+            .filter(|(_, light)| light.intensity > 0.0 && light.range > 0.0)
+            .map(|(index, light)| (index, priority(light, camera_position)))
+            .collect();
+        ranked.sort_by(|a, b| b.1.total_cmp(&a.1));
+        ranked.truncate(MAX_LOCAL_LIGHTS);
+
+        let budget = if settings.enabled {
+            settings.budget.min(MAX_SHADOW_MAPS)
+        } else {
+            0
+        };
+        let mut selection = Self::default();
+        for (index, _) in ranked {
+            let light = &lights[index];
+            let first = selection.shadow_maps.len();
+            let fits = first + light.shadow_map_count() <= budget;
+            if light.cast_shadows && fits {
+                selection
+                    .shadow_maps
+                    .extend(
+                        shadow_matrices(light)
+                            .into_iter()
+                            .map(|view_projection| ShadowMap {
+                                view_projection,
+                                light_position: light.position,
+                                range: light.range,
+                            }),
+                    );
+                selection.lights.push((index, Some(first)));
+            } else {
+                selection.lights.push((index, None));
+            }
+        }
+        selection
+    }
+}
+
+// Lights the camera is inside of come first, then the brighter and larger ones nearby
+fn priority(light: &LocalLight, camera_position: &Point3<f32>) -> f32 {
+    let gap = ((light.position - camera_position).norm() - light.range).max(0.0);
+    light.intensity * light.range / (1.0 + gap * gap)
+}
+
+// nalgebra's projections are OpenGL-style, Vulkan clips depth to 0..1 instead of -1..1
+fn perspective(fov: f32, far: f32) -> Matrix4<f32> {
+    let depth_correction = Matrix4::new(
+        1.0, 0.0, 0.0, 0.0, //
+        0.0, 1.0, 0.0, 0.0, //
+        0.0, 0.0, 0.5, 0.5, //
+        0.0, 0.0, 0.0, 1.0,
+    );
+    depth_correction * Matrix4::new_perspective(1.0, fov, SHADOW_NEAR, far.max(SHADOW_NEAR * 2.0))
+}
+
+fn look_at(eye: &Point3<f32>, direction: &Vector3<f32>) -> Matrix4<f32> {
+    let up = if direction.cross(&Vector3::y()).norm_squared() > 1e-6 {
+        Vector3::y()
+    } else {
+        Vector3::z()
+    };
+    Matrix4::look_at_rh(eye, &(eye + direction), &up)
+}
+
+// Six cube faces ordered +X, -X, +Y, -Y, +Z, -Z for point lights, one map for spot lights
+pub fn shadow_matrices(light: &LocalLight) -> Vec<Matrix4<f32>> {
+    match light.kind {
+        LightKind::Point => {
+            let projection = perspective(PI * 0.5, light.range);
+            [
+                Vector3::x(),
+                -Vector3::x(),
+                Vector3::y(),
+                -Vector3::y(),
+                Vector3::z(),
+                -Vector3::z(),
+            ]
+            .iter()
+            .map(|direction| projection * look_at(&light.position, direction))
+            .collect()
+        }
+        LightKind::Spot {
+            direction,
+            outer_angle,
+            ..
+        } => {
+            let direction = direction
+                .try_normalize(f32::EPSILON)
+                .unwrap_or_else(|| -Vector3::y());
+            let fov = (outer_angle * 2.0).clamp(0.01, MAX_SPOT_FOV);
+            vec![perspective(fov, light.range) * look_at(&light.position, &direction)]
+        }
+    }
+}
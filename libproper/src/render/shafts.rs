@@ -0,0 +1,85 @@
+use nalgebra::{Matrix4, Vector4};
+
+use crate::{
+    preferences::{Preferences, LIGHT_SHAFTS, LIGHT_SHAFT_INTENSITY, LIGHT_SHAFT_LENGTH},
+    world::light::DirectionalLight,
+};
+
+// How far past the screen edge the sun can be, in NDC, before the shafts have faded out
+const OFFSCREEN_FADE: f32 = 0.5;
+
+// Light scattered towards the camera around the sun, drawn by the screen pass as a radial
+// blur of the open sky (pixels nothing was drawn to) towards the sun's screen position. Scene
+// geometry in between blocks it, which gives the shafts
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LightShaftSettings {
+    pub enabled: bool,
+    pub intensity: f32,
+    // Fraction of the way to the sun the blur reaches
+    pub length: f32,
+    // Falloff of every blur sample relative to the previous one
+    pub decay: f32,
+}
+
+// Screen pass parameters for one frame, the default draws no shafts
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SunShafts {
+    // Texture coordinates of the sun, may be off screen
+    pub position: [f32; 2],
+    pub strength: f32,
+    pub length: f32,
+    pub color: [f32; 3],
+    pub decay: f32,
+}
+
+impl Default for LightShaftSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            intensity: 0.5,
+            length: 0.8,
+            decay: 0.97,
+        }
+    }
+}
+
+impl LightShaftSettings {
+    pub fn from_preferences(preferences: &Preferences) -> Self {
+        Self {
+            enabled: preferences.bool(LIGHT_SHAFTS, false),
+            intensity: preferences
+                .float(LIGHT_SHAFT_INTENSITY, 0.5)
+                .clamp(0.0, 4.0) as f32,
+            length: preferences.float(LIGHT_SHAFT_LENGTH, 0.8).clamp(0.05, 1.0) as f32,
+            ..Default::default()
+        }
+    }
+
+    // None if disabled or the sun is behind the camera. The light is a direction, so it's
+    // projected as a point at infinity, which also keeps the shafts still as the camera moves
+    pub fn sun(
+        &self,
+        light: &DirectionalLight,
+        view_projection: &Matrix4<f32>,
+    ) -> Option<SunShafts> {
+        if !self.enabled || self.intensity <= 0.0 {
+            return None;
+        }
+        let towards = -light.direction.try_normalize(f32::EPSILON)?;
+        let clip = view_projection * Vector4::new(towards.x, towards.y, towards.z, 0.0);
+        if clip.w <= f32::EPSILON {
+            return None;
+        }
+
+        let ndc = clip.xy() / clip.w;
+        let outside = ndc.x.abs().max(ndc.y.abs()) - 1.0;
+        let visibility = 1.0 - (outside / OFFSCREEN_FADE).clamp(0.0, 1.0);
+        (visibility > 0.0).then(|| SunShafts {
+            position: [ndc.x * 0.5 + 0.5, ndc.y * 0.5 + 0.5],
+            strength: self.intensity * visibility,
+            length: self.length,
+            color: light.color.into(),
+            decay: self.decay,
+        })
+    }
+}
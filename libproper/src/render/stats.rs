@@ -0,0 +1,61 @@
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+use super::memory;
+
+// Updated by everything which uploads to the GPU, collected once per frame
+static UPLOADS: AtomicUsize = AtomicUsize::new(0);
+static UPLOAD_BYTES: AtomicU64 = AtomicU64::new(0);
+
+// Draw counts are set by the world layer during the frame, the rest once the frame is
+// submitted. Times are in seconds
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Stats {
+    pub frame_number: u64,
+    pub frame_time: f64,
+    pub tick_time: f64,
+    pub draw_time: f64,
+    pub entities_rendered: usize,
+    pub entities_culled: usize,
+    pub draw_calls: usize,
+    // Material descriptor set and vertex buffer binds
+    pub state_binds: usize,
+    pub triangles: usize,
+    pub buffer_uploads: usize,
+    pub upload_bytes: u64,
+    // Sum of the buffers and images tracked by render::memory
+    pub vram_estimate: u64,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DrawCounts {
+    pub entities: usize,
+    pub culled: usize,
+    pub draw_calls: usize,
+    pub state_binds: usize,
+    pub triangles: usize,
+}
+
+pub(crate) fn record_upload(bytes: u64) {
+    UPLOADS.fetch_add(1, Ordering::Relaxed);
+    UPLOAD_BYTES.fetch_add(bytes, Ordering::Relaxed);
+}
+
+impl Stats {
+    pub fn set_draw_counts(&mut self, counts: DrawCounts) {
+        self.entities_rendered = counts.entities;
+        self.entities_culled = counts.culled;
+        self.draw_calls = counts.draw_calls;
+        self.state_binds = counts.state_binds;
+        self.triangles = counts.triangles;
+    }
+
+    pub(crate) fn end_frame(&mut self, frame_time: f64, tick_time: f64, draw_time: f64) {
+        self.frame_number += 1;
+        self.frame_time = frame_time;
+        self.tick_time = tick_time;
+        self.draw_time = draw_time;
+        self.buffer_uploads = UPLOADS.swap(0, Ordering::Relaxed);
+        self.upload_bytes = UPLOAD_BYTES.swap(0, Ordering::Relaxed);
+        self.vram_estimate = memory::total_bytes();
+    }
+}
@@ -1,5 +1,6 @@
+use nalgebra::Matrix4;
 use rayon::prelude::*;
-use std::{ops::Deref, sync::Arc};
+use std::sync::{atomic::Ordering, Arc};
 
 use vulkano::{
     buffer::TypedBufferAccess,
@@ -10,42 +11,75 @@ use vulkano::{
     },
     descriptor_set::PersistentDescriptorSet,
     device::Queue,
-    pipeline::{Pipeline, PipelineBindPoint, PipelineLayout},
+    pipeline::{Pipeline, PipelineBindPoint},
     render_pass::Subpass,
 };
 
 use crate::{
     error::Error,
+    lock::Recover,
+    render::{
+        extract::{RenderObject, RenderScene},
+        stats::DrawCounts,
+    },
     resource::material::MaterialTemplate,
-    world::{entity::Entity, scene::Scene},
 };
 
+use super::indirect::{self, IndirectDrawSettings, IndirectDraws, IndirectFrame};
+
 pub struct ForwardSystem {
     gfx_queue: Arc<Queue>,
-    common_pipeline_layout: Arc<PipelineLayout>,
     subpass: Subpass,
+    // Only if the device supports multi-draw indirect with a first instance
+    indirect: Option<IndirectDraws>,
 }
 
 impl ForwardSystem {
-    pub fn new(
-        gfx_queue: Arc<Queue>,
-        subpass: Subpass,
-        common_pipeline_layout: Arc<PipelineLayout>,
-    ) -> Result<Self, Error> {
+    pub fn new(gfx_queue: Arc<Queue>, subpass: Subpass) -> Result<Self, Error> {
+        let features = gfx_queue.device().enabled_features();
+        let indirect = if features.multi_draw_indirect && features.draw_indirect_first_instance {
+            Some(IndirectDraws::new(gfx_queue.clone())?)
+        } else {
+            None
+        };
+
         Ok(Self {
             gfx_queue,
-            common_pipeline_layout,
             subpass,
+            indirect,
         })
     }
 
+    // Builds the indirect draws of the frame, called before the render pass is started
+    pub fn prepare(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        scene: &RenderScene,
+        settings: &IndirectDrawSettings,
+        view_projection: &Matrix4<f32>,
+    ) -> Result<Option<IndirectFrame>, Error> {
+        match self.indirect.as_ref() {
+            Some(indirect) if settings.enabled => {
+                indirect.prepare(builder, scene, settings, view_projection)
+            }
+            _ => Ok(None),
+        }
+    }
+
+    // Entities are expected to be sorted by draw_order(), so material and vertex buffer binds
+    // are only recorded when they change. Submeshes with a material of another template switch
+    // the pipeline for their draw. The scene set is bound through each pipeline's own layout,
+    // layouts with push constants aren't compatible with the common one. Returns the number
+    // of binds as well
     fn record_command_buffer_part(
         &self,
         material_template: &Arc<dyn MaterialTemplate>,
         scene_set: &Arc<PersistentDescriptorSet>,
-        entities: &[Entity],
-    ) -> SecondaryAutoCommandBuffer {
-        let pipeline = material_template.pipeline().read().unwrap();
+        objects: &[&RenderObject],
+    ) -> Result<(SecondaryAutoCommandBuffer, usize), Error> {
+        let _span = tracing::info_span!("record_part", entities = objects.len()).entered();
+        let mut pipeline = material_template.pipeline().read().recover().clone();
+        let mut bound_template = material_template.id().load(Ordering::Acquire);
 
         let mut secondary_builder = AutoCommandBufferBuilder::secondary(
             self.gfx_queue.device().clone(),
@@ -60,84 +94,200 @@ impl ForwardSystem {
                 )),
                 ..Default::default()
             },
-        )
-        .unwrap();
+        )?;
 
         secondary_builder
             .bind_pipeline_graphics(pipeline.clone())
             .bind_descriptor_sets(
                 PipelineBindPoint::Graphics,
-                self.common_pipeline_layout.clone(),
+                pipeline.layout().clone(),
                 0,
                 scene_set.clone(),
             );
+        material_template.bind_shared(&mut secondary_builder, &pipeline)?;
 
-        for object in entities {
-            let mesh = object.mesh();
-            let model = mesh.model();
+        let mut binds = 0;
+        let mut bound_material = None;
+        let mut bound_vertices = None;
+        for object in objects {
+            let model = &object.model;
             let model_data = model.data();
 
-            mesh.material_instance()
-                .bind_data(&mut secondary_builder, &pipeline);
-
-            secondary_builder
-                .bind_vertex_buffers(0, model_data.clone())
-                .bind_descriptor_sets(
-                    PipelineBindPoint::Graphics,
-                    pipeline.layout().clone(),
-                    2,
-                    mesh.model_set().clone(),
-                )
-                .draw(model_data.len().try_into().unwrap(), 1, 0, 0)
-                .unwrap();
+            // Morphed entities have vertex buffers of their own
+            let vertices_key = object
+                .morph_buffer
+                .as_ref()
+                .map_or(Arc::as_ptr(model) as usize, |buffer| {
+                    Arc::as_ptr(buffer) as *const () as usize
+                });
+            if bound_vertices != Some(vertices_key) {
+                match object.morph_buffer.as_ref() {
+                    Some(buffer) => secondary_builder.bind_vertex_buffers(0, buffer.clone()),
+                    None => secondary_builder.bind_vertex_buffers(0, model_data.clone()),
+                };
+                secondary_builder.bind_index_buffer(model.indices().clone());
+                bound_vertices = Some(vertices_key);
+                binds += 1;
+            }
+
+            let mut model_set_bound = false;
+            for (index, submesh) in model.submeshes().iter().enumerate() {
+                let (template, material) = object.submesh_material(index);
+                let template_id = template.id().load(Ordering::Acquire);
+                if template_id != bound_template {
+                    pipeline = template.pipeline().read().recover().clone();
+                    secondary_builder
+                        .bind_pipeline_graphics(pipeline.clone())
+                        .bind_descriptor_sets(
+                            PipelineBindPoint::Graphics,
+                            pipeline.layout().clone(),
+                            0,
+                            scene_set.clone(),
+                        );
+                    template.bind_shared(&mut secondary_builder, &pipeline)?;
+                    bound_template = template_id;
+                    bound_material = None;
+                    model_set_bound = false;
+                    binds += 1;
+                }
+                if bound_material != Some(material.key()) {
+                    material.bind_data(&mut secondary_builder, &pipeline);
+                    bound_material = Some(material.key());
+                    binds += 1;
+                }
+                if !model_set_bound {
+                    secondary_builder.bind_descriptor_sets(
+                        PipelineBindPoint::Graphics,
+                        pipeline.layout().clone(),
+                        2,
+                        object.model_set.clone(),
+                    );
+                    model_set_bound = true;
+                }
+
+                secondary_builder.draw_indexed(
+                    submesh.index_count,
+                    1,
+                    submesh.first_index,
+                    0,
+                    0,
+                )?;
+            }
         }
 
-        secondary_builder.build().unwrap()
+        Ok((secondary_builder.build()?, binds))
+    }
+
+    fn record_indirect(
+        &self,
+        scene_set: &Arc<PersistentDescriptorSet>,
+        frame: &IndirectFrame,
+    ) -> Result<(SecondaryAutoCommandBuffer, usize), Error> {
+        let mut secondary_builder = AutoCommandBufferBuilder::secondary(
+            self.gfx_queue.device().clone(),
+            self.gfx_queue.family(),
+            CommandBufferUsage::OneTimeSubmit,
+            CommandBufferInheritanceInfo {
+                render_pass: Some(CommandBufferInheritanceRenderPassType::BeginRenderPass(
+                    CommandBufferInheritanceRenderPassInfo {
+                        subpass: self.subpass.clone(),
+                        framebuffer: None,
+                    },
+                )),
+                ..Default::default()
+            },
+        )?;
+        let binds = frame.record(&mut secondary_builder, scene_set)?;
+        Ok((secondary_builder.build()?, binds))
     }
 
-    fn record_secondary_buffers<T: Deref<Target = Scene>>(
+    // Entities covered by the indirect frame are left out
+    fn record_secondary_buffers(
         &self,
         scene_set: &Arc<PersistentDescriptorSet>,
-        scene: T,
-    ) -> Vec<SecondaryAutoCommandBuffer> {
+        scene: &RenderScene,
+        indirect: Option<&IndirectFrame>,
+    ) -> Result<(Vec<SecondaryAutoCommandBuffer>, usize), Error> {
+        let _span = tracing::info_span!("record_commands").entered();
         let mut cbs = vec![];
+        let mut binds = 0;
+
+        if let Some(frame) = indirect {
+            let (cb, indirect_binds) = self.record_indirect(scene_set, frame)?;
+            cbs.push(cb);
+            binds += indirect_binds;
+        }
+
+        for group in scene.groups.iter() {
+            let mut objects: Vec<&RenderObject> = group
+                .objects
+                .iter()
+                .filter(|object| {
+                    indirect.is_none() || !indirect::is_indirect(&group.material_template, object)
+                })
+                .collect();
+            let num_objects = objects.len();
+            if num_objects == 0 {
+                continue;
+            }
+            objects.sort_unstable_by_key(|object| draw_order(object));
 
-        for group in scene.data.iter() {
-            let num_objects = group.entities.len();
-            // let material_template = materials.get(group.material_template_id());
             if num_objects > 12 {
-                let chunks = group.entities.chunks(num_objects / 12);
+                let chunks = objects.chunks(num_objects / 12);
 
-                let data: Vec<SecondaryAutoCommandBuffer> = chunks
+                let data: Vec<(SecondaryAutoCommandBuffer, usize)> = chunks
                     .par_bridge()
                     .map(|chunk| {
                         self.record_command_buffer_part(&group.material_template, scene_set, chunk)
                     })
-                    .collect();
+                    .collect::<Result<_, _>>()?;
 
-                cbs.extend(data);
+                for (cb, chunk_binds) in data {
+                    cbs.push(cb);
+                    binds += chunk_binds;
+                }
             } else {
-                cbs.push(self.record_command_buffer_part(
-                    &group.material_template,
-                    scene_set,
-                    &group.entities,
-                ));
+                let (cb, group_binds) =
+                    self.record_command_buffer_part(&group.material_template, scene_set, &objects)?;
+                cbs.push(cb);
+                binds += group_binds;
             }
         }
 
-        cbs
+        Ok((cbs, binds))
     }
 
-    pub fn do_frame<T: Deref<Target = Scene>>(
+    pub fn do_frame(
         &self,
         builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
         scene_set: &Arc<PersistentDescriptorSet>,
-        scene: T,
-    ) -> Result<(), Error> {
-        let cbs = self.record_secondary_buffers(scene_set, scene);
+        scene: &RenderScene,
+        indirect: Option<&IndirectFrame>,
+    ) -> Result<DrawCounts, Error> {
+        // One draw call per submesh, or per batch for the indirect ones
+        let mut counts = DrawCounts {
+            draw_calls: indirect.map_or(0, IndirectFrame::draw_calls),
+            ..Default::default()
+        };
+        for group in scene.groups.iter() {
+            for object in group.objects.iter() {
+                counts.entities += 1;
+                counts.triangles += object.model.triangle_count();
+                if indirect.is_none() || !indirect::is_indirect(&group.material_template, object) {
+                    counts.draw_calls += object.model.submeshes().len();
+                }
+            }
+        }
+        let (cbs, binds) = self.record_secondary_buffers(scene_set, scene, indirect)?;
+        counts.state_binds = binds;
 
-        builder.execute_commands_from_vec(cbs).unwrap();
+        builder.execute_commands_from_vec(cbs)?;
 
-        Ok(())
+        Ok(counts)
     }
 }
+
+// Groups the entities sharing a material instance, then a model, within a material template
+pub(super) fn draw_order(object: &RenderObject) -> (usize, usize) {
+    (object.material.key(), Arc::as_ptr(&object.model) as usize)
+}
@@ -0,0 +1,221 @@
+use std::sync::Arc;
+
+use nalgebra::Matrix4;
+use vulkano::{
+    command_buffer::{
+        AutoCommandBufferBuilder, CommandBufferInheritanceInfo,
+        CommandBufferInheritanceRenderPassInfo, CommandBufferInheritanceRenderPassType,
+        CommandBufferUsage, SecondaryAutoCommandBuffer,
+    },
+    device::Queue,
+    pipeline::{
+        graphics::{
+            color_blend::ColorBlendState,
+            depth_stencil::DepthStencilState,
+            input_assembly::{InputAssemblyState, PrimitiveTopology},
+            multisample::MultisampleState,
+            vertex_input::BuffersDefinition,
+            viewport::{Viewport, ViewportState},
+        },
+        GraphicsPipeline, Pipeline, StateMode,
+    },
+    render_pass::Subpass,
+    shader::ShaderModule,
+};
+
+use crate::{
+    error::Error,
+    render::{debug::DebugViewSettings, shader},
+    world::camera::Camera,
+};
+
+const AXIS_LENGTH: f32 = 1.0;
+
+// Grid and origin axes, drawn in the forward pass after the scene geometry. Both are
+// generated in the shaders, so there are no buffers or descriptor sets
+pub struct GridSystem {
+    gfx_queue: Arc<Queue>,
+    subpass: Subpass,
+
+    grid_vs: Arc<ShaderModule>,
+    grid_fs: Arc<ShaderModule>,
+    axes_vs: Arc<ShaderModule>,
+    axes_fs: Arc<ShaderModule>,
+    grid_pipeline: Arc<GraphicsPipeline>,
+    axes_pipeline: Arc<GraphicsPipeline>,
+}
+
+impl GridSystem {
+    pub fn new(
+        gfx_queue: Arc<Queue>,
+        subpass: Subpass,
+        viewport: &Viewport,
+    ) -> Result<Self, Error> {
+        let device = gfx_queue.device().clone();
+        let grid_vs = shader::grid_vs::load(device.clone())?;
+        let grid_fs = shader::grid_fs::load(device.clone())?;
+        let axes_vs = shader::axes_vs::load(device.clone())?;
+        let axes_fs = shader::axes_fs::load(device)?;
+
+        let (grid_pipeline, axes_pipeline) = Self::create_pipelines(
+            &gfx_queue,
+            &subpass,
+            viewport,
+            [&grid_vs, &grid_fs, &axes_vs, &axes_fs],
+        )?;
+
+        Ok(Self {
+            gfx_queue,
+            subpass,
+            grid_vs,
+            grid_fs,
+            axes_vs,
+            axes_fs,
+            grid_pipeline,
+            axes_pipeline,
+        })
+    }
+
+    // None if there's nothing to draw
+    pub fn do_frame(
+        &self,
+        camera: &Camera,
+        projection: &Matrix4<f32>,
+        settings: &DebugViewSettings,
+    ) -> Result<Option<SecondaryAutoCommandBuffer>, Error> {
+        if !settings.grid && !settings.axes {
+            return Ok(None);
+        }
+
+        let mut builder = AutoCommandBufferBuilder::secondary(
+            self.gfx_queue.device().clone(),
+            self.gfx_queue.family(),
+            CommandBufferUsage::OneTimeSubmit,
+            CommandBufferInheritanceInfo {
+                render_pass: Some(CommandBufferInheritanceRenderPassType::BeginRenderPass(
+                    CommandBufferInheritanceRenderPassInfo {
+                        subpass: self.subpass.clone(),
+                        framebuffer: None,
+                    },
+                )),
+                ..Default::default()
+            },
+        )?;
+
+        let view_projection = projection * camera.view_matrix();
+        let position = camera.position();
+
+        if settings.grid {
+            let data = shader::grid_vs::ty::Grid_Data {
+                inv_view_projection: view_projection
+                    .try_inverse()
+                    .unwrap_or_else(Matrix4::identity)
+                    .into(),
+                camera_position: [position.x, position.y, position.z, 1.0],
+                near: camera.near(),
+                far: camera.far(),
+                spacing: settings.grid_spacing,
+                fade_distance: settings.grid_fade_distance,
+                axes: i32::from(settings.axes),
+            };
+
+            builder
+                .bind_pipeline_graphics(self.grid_pipeline.clone())
+                .push_constants(self.grid_pipeline.layout().clone(), 0, data)
+                .draw(3, 1, 0, 0)?;
+        }
+
+        if settings.axes {
+            let data = shader::axes_vs::ty::Axes_Data {
+                view_projection: view_projection.into(),
+                axis_length: AXIS_LENGTH,
+            };
+
+            builder
+                .bind_pipeline_graphics(self.axes_pipeline.clone())
+                .push_constants(self.axes_pipeline.layout().clone(), 0, data)
+                .draw(6, 1, 0, 0)?;
+        }
+
+        Ok(Some(builder.build()?))
+    }
+
+    pub fn swapchain_invalidated(&mut self, viewport: &Viewport) -> Result<(), Error> {
+        (self.grid_pipeline, self.axes_pipeline) = Self::create_pipelines(
+            &self.gfx_queue,
+            &self.subpass,
+            viewport,
+            [&self.grid_vs, &self.grid_fs, &self.axes_vs, &self.axes_fs],
+        )?;
+
+        Ok(())
+    }
+
+    fn create_pipelines(
+        gfx_queue: &Arc<Queue>,
+        subpass: &Subpass,
+        viewport: &Viewport,
+        [grid_vs, grid_fs, axes_vs, axes_fs]: [&Arc<ShaderModule>; 4],
+    ) -> Result<(Arc<GraphicsPipeline>, Arc<GraphicsPipeline>), Error> {
+        // Blended over the scene, so nothing drawn later is hidden behind the grid
+        let mut depth_stencil = DepthStencilState::simple_depth_test();
+        if let Some(depth) = depth_stencil.depth.as_mut() {
+            depth.write_enable = StateMode::Fixed(false);
+        }
+
+        let grid = GraphicsPipeline::start()
+            .vertex_input_state(BuffersDefinition::new())
+            .input_assembly_state(InputAssemblyState::new())
+            .vertex_shader(
+                grid_vs
+                    .entry_point("main")
+                    .ok_or(Error::MissingShaderEntryPoint)?,
+                (),
+            )
+            .fragment_shader(
+                grid_fs
+                    .entry_point("main")
+                    .ok_or(Error::MissingShaderEntryPoint)?,
+                (),
+            )
+            .depth_stencil_state(depth_stencil)
+            .color_blend_state(ColorBlendState::new(1).blend_alpha())
+            .multisample_state(MultisampleState {
+                rasterization_samples: subpass.num_samples().unwrap(),
+                ..Default::default()
+            })
+            .viewport_state(ViewportState::viewport_fixed_scissor_irrelevant([
+                viewport.clone()
+            ]))
+            .render_pass(subpass.clone())
+            .build(gfx_queue.device().clone())?;
+
+        let axes = GraphicsPipeline::start()
+            .vertex_input_state(BuffersDefinition::new())
+            .input_assembly_state(InputAssemblyState::new().topology(PrimitiveTopology::LineList))
+            .vertex_shader(
+                axes_vs
+                    .entry_point("main")
+                    .ok_or(Error::MissingShaderEntryPoint)?,
+                (),
+            )
+            .fragment_shader(
+                axes_fs
+                    .entry_point("main")
+                    .ok_or(Error::MissingShaderEntryPoint)?,
+                (),
+            )
+            .depth_stencil_state(DepthStencilState::simple_depth_test())
+            .multisample_state(MultisampleState {
+                rasterization_samples: subpass.num_samples().unwrap(),
+                ..Default::default()
+            })
+            .viewport_state(ViewportState::viewport_fixed_scissor_irrelevant([
+                viewport.clone()
+            ]))
+            .render_pass(subpass.clone())
+            .build(gfx_queue.device().clone())?;
+
+        Ok((grid, axes))
+    }
+}
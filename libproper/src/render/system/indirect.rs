@@ -0,0 +1,323 @@
+use std::{
+    ops::Range,
+    sync::{atomic::Ordering, Arc},
+};
+
+use bytemuck::{Pod, Zeroable};
+use nalgebra::Matrix4;
+use vulkano::{
+    buffer::{BufferUsage, CpuAccessibleBuffer, TypedBufferAccess},
+    command_buffer::{
+        AutoCommandBufferBuilder, DrawIndexedIndirectCommand, PrimaryAutoCommandBuffer,
+        SecondaryAutoCommandBuffer,
+    },
+    descriptor_set::{PersistentDescriptorSet, WriteDescriptorSet},
+    device::Queue,
+    pipeline::{ComputePipeline, Pipeline, PipelineBindPoint},
+};
+
+use crate::{
+    error::Error,
+    preferences::{Preferences, GPU_CULLING, INDIRECT_DRAW},
+    render::{
+        extract::{RenderObject, RenderScene},
+        shader, stats,
+    },
+    resource::{
+        material::{MaterialInstance, MaterialTemplate},
+        model::Model,
+    },
+};
+
+use super::forward::draw_order;
+
+// Must match the local size in cull.comp
+const CULL_GROUP_SIZE: u32 = 64;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct IndirectDrawSettings {
+    pub enabled: bool,
+    // Draws of entities outside the view frustum are zeroed by a compute pass
+    pub gpu_culling: bool,
+}
+
+// Per-entity data read by scene_indirect.vert and cull.comp, in model space
+#[repr(C)]
+#[derive(Clone, Copy, Zeroable, Pod)]
+pub struct InstanceData {
+    pub transform: [[f32; 4]; 4],
+    pub bounds_min: [f32; 4],
+    pub bounds_max: [f32; 4],
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Zeroable, Pod)]
+struct CullData {
+    view_projection: [[f32; 4]; 4],
+    draw_count: u32,
+}
+
+// Consecutive commands sharing the material instance and the model, drawn with one call
+struct IndirectBatch {
+    template: Arc<dyn MaterialTemplate>,
+    material: MaterialInstance,
+    model: Arc<Model>,
+    commands: Range<u64>,
+}
+
+// Built each frame before the render pass starts, one command per submesh
+pub struct IndirectFrame {
+    instances: Arc<CpuAccessibleBuffer<[InstanceData]>>,
+    commands: Arc<CpuAccessibleBuffer<[DrawIndexedIndirectCommand]>>,
+    batches: Vec<IndirectBatch>,
+}
+
+// GPU-driven path of the forward pass: per-entity parameters live in buffers and whole batches
+// are submitted with vkCmdDrawIndexedIndirect, so recording doesn't grow with the entity count
+pub struct IndirectDraws {
+    gfx_queue: Arc<Queue>,
+    cull_pipeline: Arc<ComputePipeline>,
+}
+
+impl IndirectDrawSettings {
+    pub fn from_preferences(preferences: &Preferences) -> Self {
+        Self {
+            enabled: preferences.bool(INDIRECT_DRAW, true),
+            gpu_culling: preferences.bool(GPU_CULLING, true),
+        }
+    }
+}
+
+// Entities with vertex buffers of their own or per-submesh materials are drawn one at a time,
+// as are the ones with templates lacking an indirect pipeline
+pub fn is_indirect(template: &Arc<dyn MaterialTemplate>, object: &RenderObject) -> bool {
+    template.indirect_pipeline().is_some()
+        && object.morph_buffer.is_none()
+        && !object.has_submesh_materials()
+}
+
+impl IndirectDraws {
+    pub fn new(gfx_queue: Arc<Queue>) -> Result<Self, Error> {
+        let cs = shader::cull_cs::load(gfx_queue.device().clone())?;
+        let cull_pipeline = ComputePipeline::new(
+            gfx_queue.device().clone(),
+            cs.entry_point("main")
+                .ok_or(Error::MissingShaderEntryPoint)?,
+            &(),
+            None,
+            |_| {},
+        )?;
+
+        Ok(Self {
+            gfx_queue,
+            cull_pipeline,
+        })
+    }
+
+    // Has to be recorded outside of the render pass, the culling dispatch writes the commands
+    pub fn prepare(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        scene: &RenderScene,
+        settings: &IndirectDrawSettings,
+        view_projection: &Matrix4<f32>,
+    ) -> Result<Option<IndirectFrame>, Error> {
+        let _span = tracing::info_span!("prepare_indirect").entered();
+        let mut instances = vec![];
+        let mut commands = vec![];
+        let mut batches: Vec<IndirectBatch> = vec![];
+
+        for group in scene.groups.iter() {
+            let mut objects: Vec<&RenderObject> = group
+                .objects
+                .iter()
+                .filter(|object| is_indirect(&group.material_template, object))
+                .collect();
+            objects.sort_unstable_by_key(|object| draw_order(object));
+
+            for object in objects {
+                let model = &object.model;
+                let bounds = model.bounds();
+                let first_instance = instances.len() as u32;
+                instances.push(InstanceData {
+                    transform: object.transform.into(),
+                    bounds_min: [bounds.min.x, bounds.min.y, bounds.min.z, 1.0],
+                    bounds_max: [bounds.max.x, bounds.max.y, bounds.max.z, 1.0],
+                });
+
+                let start = commands.len() as u64;
+                let continues = batches.last().map_or(false, |batch| {
+                    batch.material.key() == object.material.key()
+                        && Arc::ptr_eq(&batch.model, model)
+                });
+                if !continues {
+                    batches.push(IndirectBatch {
+                        template: group.material_template.clone(),
+                        material: object.material.clone(),
+                        model: model.clone(),
+                        commands: start..start,
+                    });
+                }
+
+                commands.extend(model.submeshes().iter().map(|submesh| {
+                    DrawIndexedIndirectCommand {
+                        index_count: submesh.index_count,
+                        instance_count: 1,
+                        first_index: submesh.first_index,
+                        vertex_offset: 0,
+                        first_instance,
+                    }
+                }));
+                batches.last_mut().unwrap().commands.end = commands.len() as u64;
+            }
+        }
+
+        if commands.is_empty() {
+            return Ok(None);
+        }
+
+        let draw_count = commands.len() as u32;
+        stats::record_upload(
+            (instances.len() * std::mem::size_of::<InstanceData>()
+                + commands.len() * std::mem::size_of::<DrawIndexedIndirectCommand>())
+                as u64,
+        );
+        let device = self.gfx_queue.device().clone();
+        let instances = CpuAccessibleBuffer::from_iter(
+            device.clone(),
+            BufferUsage {
+                storage_buffer: true,
+                ..BufferUsage::none()
+            },
+            false,
+            instances,
+        )?;
+        let commands = CpuAccessibleBuffer::from_iter(
+            device,
+            BufferUsage {
+                storage_buffer: true,
+                indirect_buffer: true,
+                ..BufferUsage::none()
+            },
+            false,
+            commands,
+        )?;
+
+        if settings.gpu_culling {
+            let layout = self.cull_pipeline.layout().set_layouts().get(0).unwrap();
+            let set = PersistentDescriptorSet::new(
+                layout.clone(),
+                [
+                    WriteDescriptorSet::buffer(0, instances.clone()),
+                    WriteDescriptorSet::buffer(1, commands.clone()),
+                ],
+            )?;
+            builder
+                .bind_pipeline_compute(self.cull_pipeline.clone())
+                .bind_descriptor_sets(
+                    PipelineBindPoint::Compute,
+                    self.cull_pipeline.layout().clone(),
+                    0,
+                    set,
+                )
+                .push_constants(
+                    self.cull_pipeline.layout().clone(),
+                    0,
+                    CullData {
+                        view_projection: (*view_projection).into(),
+                        draw_count,
+                    },
+                )
+                .dispatch([(draw_count + CULL_GROUP_SIZE - 1) / CULL_GROUP_SIZE, 1, 1])?;
+        }
+
+        Ok(Some(IndirectFrame {
+            instances,
+            commands,
+            batches,
+        }))
+    }
+}
+
+impl IndirectFrame {
+    // Number of indirect draw calls recorded
+    #[inline]
+    pub fn draw_calls(&self) -> usize {
+        self.batches.len()
+    }
+
+    // Returns the number of binds as well
+    pub fn record(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<SecondaryAutoCommandBuffer>,
+        scene_set: &Arc<PersistentDescriptorSet>,
+    ) -> Result<usize, Error> {
+        let _span = tracing::info_span!("record_indirect", batches = self.batches.len()).entered();
+        let mut binds = 0;
+        let mut instance_set = None;
+        let mut bound_pipeline = None;
+        let mut bound_template = None;
+        let mut bound_material = None;
+        let mut bound_model = None;
+
+        for batch in self.batches.iter() {
+            let template_id = batch.template.id().load(Ordering::Acquire);
+            if bound_template != Some(template_id) {
+                let template_pipeline = batch.template.indirect_pipeline().unwrap();
+                // Every indirect pipeline has the same instance set layout
+                if instance_set.is_none() {
+                    let layout = template_pipeline.layout().set_layouts().get(2).unwrap();
+                    instance_set = Some(PersistentDescriptorSet::new(
+                        layout.clone(),
+                        [WriteDescriptorSet::buffer(0, self.instances.clone())],
+                    )?);
+                }
+
+                builder
+                    .bind_pipeline_graphics(template_pipeline.clone())
+                    .bind_descriptor_sets(
+                        PipelineBindPoint::Graphics,
+                        template_pipeline.layout().clone(),
+                        0,
+                        scene_set.clone(),
+                    );
+                batch.template.bind_shared(builder, &template_pipeline)?;
+                builder.bind_descriptor_sets(
+                    PipelineBindPoint::Graphics,
+                    template_pipeline.layout().clone(),
+                    2,
+                    instance_set.clone().unwrap(),
+                );
+                bound_pipeline = Some(template_pipeline);
+                bound_template = Some(template_id);
+                bound_material = None;
+                binds += 1;
+            }
+            let pipeline = bound_pipeline.as_ref().unwrap();
+
+            if bound_material != Some(batch.material.key()) {
+                batch.material.bind_data(builder, pipeline);
+                bound_material = Some(batch.material.key());
+                binds += 1;
+            }
+
+            let model_key = Arc::as_ptr(&batch.model) as usize;
+            if bound_model != Some(model_key) {
+                builder
+                    .bind_vertex_buffers(0, batch.model.data().clone())
+                    .bind_index_buffer(batch.model.indices().clone());
+                bound_model = Some(model_key);
+                binds += 1;
+            }
+
+            let commands = self
+                .commands
+                .into_buffer_slice()
+                .slice(batch.commands.clone())
+                .unwrap();
+            builder.draw_indexed_indirect(commands)?;
+        }
+
+        Ok(binds)
+    }
+}
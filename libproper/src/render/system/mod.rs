@@ -1,2 +1,5 @@
 pub mod forward;
+pub mod grid;
+pub mod indirect;
 pub mod screen;
+pub mod shadow;
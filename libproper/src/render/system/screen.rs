@@ -6,7 +6,11 @@ use vulkano::{
     command_buffer::{AutoCommandBufferBuilder, PrimaryAutoCommandBuffer},
     descriptor_set::{PersistentDescriptorSet, WriteDescriptorSet},
     device::{Device, Queue},
-    image::{view::ImageView, AttachmentImage},
+    format::Format,
+    image::{
+        view::ImageView, AttachmentImage, ImageDimensions, ImageViewAbstract, ImmutableImage,
+        MipmapsCount,
+    },
     pipeline::{
         graphics::{
             input_assembly::InputAssemblyState,
@@ -16,13 +20,22 @@ use vulkano::{
         GraphicsPipeline, Pipeline, PipelineBindPoint,
     },
     render_pass::Subpass,
+    sampler::{Filter, Sampler, SamplerAddressMode, SamplerCreateInfo},
     shader::ShaderModule,
     sync::GpuFuture,
 };
 
 use crate::{
     error::Error,
-    render::{shader, SimpleVertex},
+    render::{
+        color::OutputSettings,
+        effects::ScreenOverlay,
+        grading::{self, ScreenGrading, NEUTRAL_LUT_SIZE},
+        shader,
+        shafts::SunShafts,
+        SimpleVertex,
+    },
+    resource::texture::SampledTexture,
 };
 
 pub struct ScreenSystem {
@@ -30,7 +43,14 @@ pub struct ScreenSystem {
     subpass: Subpass,
 
     vertex_buffer: Arc<ImmutableBuffer<[SimpleVertex]>>,
+    // Blur samples past the edges repeat the edge pixels, also samples the neutral LUT
+    sampler: Arc<Sampler>,
     screen_set: Arc<PersistentDescriptorSet>,
+    // Bound in place of a missing LUT
+    neutral_lut: Arc<ImageView<ImmutableImage>>,
+    // Rebuilt when the LUTs change, None after the pipeline was recreated
+    lut_set: Option<Arc<PersistentDescriptorSet>>,
+    luts: [Option<Arc<SampledTexture>>; 2],
     vs: Arc<ShaderModule>,
     fs: Arc<ShaderModule>,
     pipeline: Arc<GraphicsPipeline>,
@@ -68,13 +88,24 @@ impl ScreenSystem {
             gfx_queue.clone(),
         )?;
 
-        init.then_signal_fence_and_flush()
-            .unwrap()
-            .wait(None)
-            .unwrap();
+        init.then_signal_fence_and_flush()?.wait(None)?;
+
+        let (neutral_lut, init) = ImmutableImage::from_iter(
+            grading::neutral_lut_data(NEUTRAL_LUT_SIZE),
+            ImageDimensions::Dim2d {
+                width: NEUTRAL_LUT_SIZE * NEUTRAL_LUT_SIZE,
+                height: NEUTRAL_LUT_SIZE,
+                array_layers: 1,
+            },
+            MipmapsCount::One,
+            Format::R8G8B8A8_UNORM,
+            gfx_queue.clone(),
+        )?;
+        init.then_signal_fence_and_flush()?.wait(None)?;
+        let neutral_lut = ImageView::new_default(neutral_lut)?;
 
-        let vs = shader::screen_vs::load(gfx_queue.device().clone()).unwrap();
-        let fs = shader::screen_fs::load(gfx_queue.device().clone()).unwrap();
+        let vs = shader::screen_vs::load(gfx_queue.device().clone())?;
+        let fs = shader::screen_fs::load(gfx_queue.device().clone())?;
 
         let pipeline = Self::create_screen_pipeline(
             gfx_queue.device().clone(),
@@ -82,20 +113,38 @@ impl ScreenSystem {
             subpass.clone(),
             vs.clone(),
             fs.clone(),
-        );
+        )?;
+
+        let sampler = Sampler::new(
+            gfx_queue.device().clone(),
+            SamplerCreateInfo {
+                mag_filter: Filter::Linear,
+                min_filter: Filter::Linear,
+                address_mode: [SamplerAddressMode::ClampToEdge; 3],
+                ..Default::default()
+            },
+        )?;
 
         let screen_layout = pipeline.layout().set_layouts().get(0).unwrap();
 
         let screen_set = PersistentDescriptorSet::new(
             screen_layout.clone(),
-            vec![WriteDescriptorSet::image_view(0, color_view)],
+            vec![WriteDescriptorSet::image_view_sampler(
+                0,
+                color_view,
+                sampler.clone(),
+            )],
         )?;
 
         Ok(Self {
             gfx_queue,
             subpass,
             vertex_buffer,
+            sampler,
             screen_set,
+            neutral_lut,
+            lut_set: None,
+            luts: [None, None],
             vs,
             fs,
             pipeline,
@@ -103,9 +152,43 @@ impl ScreenSystem {
     }
 
     pub fn do_frame(
-        &self,
+        &mut self,
         builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        settings: &OutputSettings,
+        overlay: &ScreenOverlay,
+        shafts: &SunShafts,
+        grading: &ScreenGrading,
+        // See AspectLock::bars, cinematic letterboxing is applied within them
+        bars: [f32; 2],
+        hdr10: bool,
     ) -> Result<(), Error> {
+        let [pillarbox, letterbox] = bars;
+        let output = shader::screen_fs::ty::Output_Data {
+            vignette: overlay.vignette,
+            flash: overlay.flash,
+            sun: [
+                shafts.position[0],
+                shafts.position[1],
+                shafts.strength,
+                shafts.length,
+            ],
+            shaft_color: [
+                shafts.color[0],
+                shafts.color[1],
+                shafts.color[2],
+                shafts.decay,
+            ],
+            grading: [grading.blend, grading.intensity, 0.0, 0.0],
+            gamma: settings.gamma,
+            contrast: settings.contrast,
+            brightness: settings.brightness,
+            paper_white: settings.paper_white,
+            hdr10: i32::from(hdr10),
+            letterbox: letterbox + overlay.letterbox * (1.0 - 2.0 * letterbox),
+            pillarbox,
+        };
+        let lut_set = self.lut_set(&grading.luts)?;
+
         builder
             .bind_pipeline_graphics(self.pipeline.clone())
             .bind_vertex_buffers(0, self.vertex_buffer.clone())
@@ -113,8 +196,9 @@ impl ScreenSystem {
                 PipelineBindPoint::Graphics,
                 self.pipeline.layout().clone(),
                 0,
-                self.screen_set.clone(),
+                (self.screen_set.clone(), lut_set),
             )
+            .push_constants(self.pipeline.layout().clone(), 0, output)
             .draw(6, 1, 0, 0)?;
 
         Ok(())
@@ -131,33 +215,79 @@ impl ScreenSystem {
             self.subpass.clone(),
             self.vs.clone(),
             self.fs.clone(),
-        );
+        )?;
+        self.lut_set = None;
 
         let screen_layout = self.pipeline.layout().set_layouts().get(0).unwrap();
 
         self.screen_set = PersistentDescriptorSet::new(
             screen_layout.clone(),
-            vec![WriteDescriptorSet::image_view(0, color_view)],
+            vec![WriteDescriptorSet::image_view_sampler(
+                0,
+                color_view,
+                self.sampler.clone(),
+            )],
         )?;
 
         Ok(())
     }
 
+    fn lut_set(
+        &mut self,
+        luts: &[Option<Arc<SampledTexture>>; 2],
+    ) -> Result<Arc<PersistentDescriptorSet>, Error> {
+        let same = |a: &Option<Arc<SampledTexture>>, b: &Option<Arc<SampledTexture>>| match (a, b) {
+            (Some(a), Some(b)) => Arc::ptr_eq(a, b),
+            (None, None) => true,
+            _ => false,
+        };
+        if let Some(set) = self.lut_set.as_ref() {
+            if same(&self.luts[0], &luts[0]) && same(&self.luts[1], &luts[1]) {
+                return Ok(set.clone());
+            }
+        }
+
+        let writes = luts.iter().enumerate().map(|(binding, lut)| match lut {
+            Some(lut) => WriteDescriptorSet::image_view_sampler(
+                binding as u32,
+                lut.image().clone(),
+                lut.sampler().clone(),
+            ),
+            None => WriteDescriptorSet::image_view_sampler(
+                binding as u32,
+                self.neutral_lut.clone() as Arc<dyn ImageViewAbstract>,
+                self.sampler.clone(),
+            ),
+        });
+        let lut_layout = self.pipeline.layout().set_layouts().get(1).unwrap();
+        let set = PersistentDescriptorSet::new(lut_layout.clone(), writes)?;
+
+        self.lut_set = Some(set.clone());
+        self.luts = luts.clone();
+        Ok(set)
+    }
+
     fn create_screen_pipeline(
         device: Arc<Device>,
         viewport: Viewport,
         subpass: Subpass,
         screen_vs: Arc<ShaderModule>,
         screen_fs: Arc<ShaderModule>,
-    ) -> Arc<GraphicsPipeline> {
-        GraphicsPipeline::start()
+    ) -> Result<Arc<GraphicsPipeline>, Error> {
+        let vs_entry = screen_vs
+            .entry_point("main")
+            .ok_or(Error::MissingShaderEntryPoint)?;
+        let fs_entry = screen_fs
+            .entry_point("main")
+            .ok_or(Error::MissingShaderEntryPoint)?;
+
+        Ok(GraphicsPipeline::start()
             .vertex_input_state(BuffersDefinition::new().vertex::<SimpleVertex>())
             .input_assembly_state(InputAssemblyState::new())
             .render_pass(subpass)
-            .vertex_shader(screen_vs.entry_point("main").unwrap(), ())
-            .fragment_shader(screen_fs.entry_point("main").unwrap(), ())
+            .vertex_shader(vs_entry, ())
+            .fragment_shader(fs_entry, ())
             .viewport_state(ViewportState::viewport_fixed_scissor_irrelevant([viewport]))
-            .build(device)
-            .unwrap()
+            .build(device)?)
     }
 }
@@ -0,0 +1,222 @@
+use std::sync::Arc;
+
+use nalgebra::Point3;
+use vulkano::{
+    command_buffer::{
+        AutoCommandBufferBuilder, PrimaryAutoCommandBuffer, RenderPassBeginInfo, SubpassContents,
+    },
+    device::Queue,
+    format::{ClearValue, Format},
+    image::{view::ImageView, AttachmentImage, ImageViewAbstract},
+    pipeline::{
+        graphics::{
+            depth_stencil::{CompareOp, DepthStencilState},
+            input_assembly::InputAssemblyState,
+            vertex_input::BuffersDefinition,
+            viewport::{Viewport, ViewportState},
+        },
+        GraphicsPipeline, Pipeline, PipelineBindPoint,
+    },
+    render_pass::{Framebuffer, FramebufferCreateInfo, RenderPass, Subpass},
+    sampler::{Filter, Sampler, SamplerAddressMode, SamplerCreateInfo},
+};
+
+use crate::{
+    error::Error,
+    render::{
+        arena,
+        extract::{RenderObject, RenderScene},
+        memory::{AllocationCategory, GpuAllocation},
+        shader,
+        shadow::{ShadowMap, SHADOW_ATLAS_TILES},
+        Vertex,
+    },
+};
+
+const SHADOW_FORMAT: Format = Format::D16_UNORM;
+
+// Renders the shadow maps picked by the light budget into tiles of one depth atlas, sampled
+// by lighting.glsl. The atlas is cleared every frame even with no maps, so it's always
+// initialized when the forward pass samples it
+pub struct ShadowSystem {
+    pipeline: Arc<GraphicsPipeline>,
+    framebuffer: Arc<Framebuffer>,
+    atlas: Arc<ImageView<AttachmentImage>>,
+    sampler: Arc<Sampler>,
+    // Of a single map
+    resolution: u32,
+    _memory: GpuAllocation,
+}
+
+impl ShadowSystem {
+    pub fn new(gfx_queue: Arc<Queue>, resolution: u32) -> Result<Self, Error> {
+        let device = gfx_queue.device().clone();
+        let render_pass = Self::create_render_pass(&gfx_queue)?;
+
+        let size = resolution * SHADOW_ATLAS_TILES as u32;
+        let atlas = ImageView::new_default(AttachmentImage::sampled(
+            device.clone(),
+            [size, size],
+            SHADOW_FORMAT,
+        )?)?;
+        let memory = GpuAllocation::new(
+            AllocationCategory::Attachment,
+            size as u64 * size as u64 * SHADOW_FORMAT.block_size().unwrap_or(2),
+        );
+        let framebuffer = Framebuffer::new(
+            render_pass.clone(),
+            FramebufferCreateInfo {
+                attachments: vec![atlas.clone() as Arc<dyn ImageViewAbstract>],
+                ..Default::default()
+            },
+        )?;
+
+        // Filtered comparisons give 2x2 PCF for free
+        let sampler = Sampler::new(
+            device.clone(),
+            SamplerCreateInfo {
+                mag_filter: Filter::Linear,
+                min_filter: Filter::Linear,
+                address_mode: [SamplerAddressMode::ClampToEdge; 3],
+                compare: Some(CompareOp::LessOrEqual),
+                ..Default::default()
+            },
+        )?;
+
+        let vs = shader::shadow_vs::load(device.clone())?;
+        let fs = shader::shadow_fs::load(device.clone())?;
+        let pipeline = GraphicsPipeline::start()
+            .vertex_input_state(BuffersDefinition::new().vertex::<Vertex>())
+            .input_assembly_state(InputAssemblyState::new())
+            .vertex_shader(
+                vs.entry_point("main")
+                    .ok_or(Error::MissingShaderEntryPoint)?,
+                (),
+            )
+            .fragment_shader(
+                fs.entry_point("main")
+                    .ok_or(Error::MissingShaderEntryPoint)?,
+                (),
+            )
+            .depth_stencil_state(DepthStencilState::simple_depth_test())
+            // Each map is drawn into its own tile
+            .viewport_state(ViewportState::viewport_dynamic_scissor_irrelevant())
+            .render_pass(Subpass::from(render_pass, 0).ok_or(Error::MissingSubpass)?)
+            // Model sets are the same as in the forward pass
+            .with_auto_layout(device, arena::use_dynamic_model_set)?;
+
+        Ok(Self {
+            pipeline,
+            framebuffer,
+            atlas,
+            sampler,
+            resolution,
+            _memory: memory,
+        })
+    }
+
+    #[inline]
+    pub const fn atlas(&self) -> &Arc<ImageView<AttachmentImage>> {
+        &self.atlas
+    }
+
+    #[inline]
+    pub const fn sampler(&self) -> &Arc<Sampler> {
+        &self.sampler
+    }
+
+    // Recorded before the forward pass, returns the number of draw calls
+    pub fn do_frame(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        scene: &RenderScene,
+        shadow_maps: &[ShadowMap],
+    ) -> Result<usize, Error> {
+        let _span = tracing::info_span!("shadow_maps", maps = shadow_maps.len()).entered();
+        let mut info = RenderPassBeginInfo::framebuffer(self.framebuffer.clone());
+        info.clear_values = vec![Some(ClearValue::Depth(1.0))];
+        builder.begin_render_pass(info, SubpassContents::Inline)?;
+
+        let mut draw_calls = 0;
+        if !shadow_maps.is_empty() {
+            builder.bind_pipeline_graphics(self.pipeline.clone());
+        }
+        let tile = self.resolution as f32;
+        for (index, map) in shadow_maps.iter().enumerate() {
+            let column = (index % SHADOW_ATLAS_TILES) as f32;
+            let row = (index / SHADOW_ATLAS_TILES) as f32;
+            builder
+                .set_viewport(
+                    0,
+                    [Viewport {
+                        origin: [column * tile, row * tile],
+                        dimensions: [tile, tile],
+                        depth_range: 0.0..1.0,
+                    }],
+                )
+                .push_constants(
+                    self.pipeline.layout().clone(),
+                    0,
+                    shader::shadow_vs::ty::Shadow_Data {
+                        view_projection: map.view_projection.into(),
+                    },
+                );
+
+            let objects = scene
+                .groups
+                .iter()
+                .flat_map(|group| group.objects.iter())
+                .filter(|object| in_range(object, &map.light_position, map.range));
+            for object in objects {
+                match object.morph_buffer.as_ref() {
+                    Some(buffer) => builder.bind_vertex_buffers(0, buffer.clone()),
+                    None => builder.bind_vertex_buffers(0, object.model.data().clone()),
+                };
+                builder
+                    .bind_index_buffer(object.model.indices().clone())
+                    .bind_descriptor_sets(
+                        PipelineBindPoint::Graphics,
+                        self.pipeline.layout().clone(),
+                        2,
+                        object.model_set.clone(),
+                    );
+                for submesh in object.model.submeshes() {
+                    builder.draw_indexed(submesh.index_count, 1, submesh.first_index, 0, 0)?;
+                    draw_calls += 1;
+                }
+            }
+        }
+
+        builder.end_render_pass()?;
+        Ok(draw_calls)
+    }
+
+    fn create_render_pass(gfx_queue: &Arc<Queue>) -> Result<Arc<RenderPass>, Error> {
+        Ok(vulkano::single_pass_renderpass!(
+            gfx_queue.device().clone(),
+            attachments: {
+                depth: {
+                    load: Clear,
+                    store: Store,
+                    format: SHADOW_FORMAT,
+                    samples: 1,
+                }
+            },
+            pass: {
+                color: [],
+                depth_stencil: {depth}
+            }
+        )?)
+    }
+}
+
+// Bounding sphere of the object against the light's range
+fn in_range(object: &RenderObject, light_position: &Point3<f32>, range: f32) -> bool {
+    let bounds = object.model.bounds();
+    let center = object.transform.transform_point(&bounds.center());
+    let scale = (0..3)
+        .map(|axis| object.transform.column(axis).xyz().norm())
+        .fold(0.0, f32::max);
+    let radius = bounds.half_extents().norm() * scale;
+    (center - light_position).norm() <= range + radius
+}
@@ -0,0 +1,180 @@
+use bytemuck::{Pod, Zeroable};
+use nalgebra::{Matrix4, Vector3};
+
+use crate::world::{
+    camera::Camera,
+    entity::Entity,
+    light::{DirectionalLight, LightKind, LocalLight},
+};
+
+use super::{
+    cluster::CLUSTER_GRID,
+    shadow::{LightSelection, MAX_SHADOW_MAPS},
+};
+
+// CPU-side mirrors of the std140 blocks declared in scene.vert/lighting.glsl:
+//  set 0, binding 0: Scene_Data
+//  set 0, binding 1: Light_Data
+//  set 0, binding 2: the shadow atlas, not a buffer
+//  set 0, binding 3: Light_Buffer, std430 array of LocalLightUniform
+//  set 0, binding 4/5: the light clusters, see LightClusters
+//  set 2, binding 0: Model_Data, bound with a dynamic offset into the model arena
+// Custom material shaders must declare the same blocks for sets 0 and 2
+
+#[repr(C)]
+#[derive(Clone, Copy, Zeroable, Pod)]
+pub struct CameraUniform {
+    pub projection: [[f32; 4]; 4],
+    pub view: [[f32; 4]; 4],
+    pub inv_projection: [[f32; 4]; 4],
+    pub inv_view: [[f32; 4]; 4],
+    pub camera_position: [f32; 4],
+    pub near: f32,
+    pub far: f32,
+    pub time: f32,
+    _pad: f32,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Zeroable, Pod)]
+pub struct LocalLightUniform {
+    pub position: [f32; 4],
+    pub color: [f32; 4],
+    pub direction: [f32; 4],
+    pub cone: [f32; 4],
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Zeroable, Pod)]
+pub struct LightUniform {
+    pub direction: [f32; 4],
+    pub color: [f32; 4],
+    pub ambient: [f32; 4],
+    pub cluster_grid: [u32; 4],
+    pub cluster_params: [f32; 4],
+    pub view_z: [f32; 4],
+    pub shadow_matrices: [[[f32; 4]; 4]; MAX_SHADOW_MAPS],
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Zeroable, Pod)]
+pub struct ModelUniform {
+    pub transform: [[f32; 4]; 4],
+}
+
+impl CameraUniform {
+    pub fn new(camera: &Camera, projection: Matrix4<f32>, time: f32) -> Self {
+        let view = camera.view_matrix();
+        let position = camera.position();
+
+        Self {
+            projection: projection.into(),
+            view: view.into(),
+            inv_projection: projection
+                .try_inverse()
+                .unwrap_or_else(Matrix4::identity)
+                .into(),
+            inv_view: view.try_inverse().unwrap_or_else(Matrix4::identity).into(),
+            camera_position: [position.x, position.y, position.z, 1.0],
+            near: camera.near(),
+            far: camera.far(),
+            time,
+            _pad: 0.0,
+        }
+    }
+
+    // Shake only moves what's rendered, picking and the camera itself are unaffected
+    pub fn apply_shake(&mut self, shake: &Matrix4<f32>) {
+        let view = shake * Matrix4::from(self.view);
+        self.view = view.into();
+        self.inv_view = view.try_inverse().unwrap_or_else(Matrix4::identity).into();
+    }
+}
+
+impl LightUniform {
+    pub fn set_shadow_maps(&mut self, selection: &LightSelection) {
+        for (slot, map) in selection
+            .shadow_maps
+            .iter()
+            .take(MAX_SHADOW_MAPS)
+            .enumerate()
+        {
+            self.shadow_matrices[slot] = map.view_projection.into();
+        }
+    }
+
+    // The view is the one the clusters were built with, params come from LightClusters
+    pub fn set_clusters(&mut self, view: &Matrix4<f32>, params: [f32; 4]) {
+        let [columns, rows, slices] = CLUSTER_GRID;
+        self.cluster_grid = [columns, rows, slices, 0];
+        self.cluster_params = params;
+        self.view_z = [view[(2, 0)], view[(2, 1)], view[(2, 2)], view[(2, 3)]];
+    }
+}
+
+impl LocalLightUniform {
+    pub fn new(light: &LocalLight, first_map: Option<usize>) -> Self {
+        let color = light.color * light.intensity;
+        // A cone covering everything for point lights, so the shader doesn't tell them apart
+        let (direction, cos_outer, cos_inner) = match light.kind {
+            LightKind::Point => (Vector3::zeros(), -2.0, -1.0),
+            LightKind::Spot {
+                direction,
+                inner_angle,
+                outer_angle,
+            } => {
+                let cos_outer = outer_angle.cos();
+                (
+                    direction
+                        .try_normalize(f32::EPSILON)
+                        .unwrap_or_else(Vector3::zeros),
+                    cos_outer,
+                    inner_angle.cos().max(cos_outer + 1e-4),
+                )
+            }
+        };
+        let (first_map, maps) = match first_map {
+            Some(first) => (first as f32, light.shadow_map_count() as f32),
+            None => (-1.0, 0.0),
+        };
+
+        Self {
+            position: [
+                light.position.x,
+                light.position.y,
+                light.position.z,
+                light.range,
+            ],
+            color: [color.x, color.y, color.z, first_map],
+            direction: [direction.x, direction.y, direction.z, 0.0],
+            cone: [cos_outer, cos_inner, maps, 0.0],
+        }
+    }
+}
+
+// Without shadow maps or clusters, see set_shadow_maps() and set_clusters()
+impl From<&DirectionalLight> for LightUniform {
+    fn from(light: &DirectionalLight) -> Self {
+        let direction = light.direction.normalize();
+        Self {
+            direction: [direction.x, direction.y, direction.z, 0.0],
+            color: [light.color.x, light.color.y, light.color.z, 1.0],
+            ambient: [light.ambient, light.ambient, light.ambient, 1.0],
+            ..Zeroable::zeroed()
+        }
+    }
+}
+
+impl From<&Matrix4<f32>> for ModelUniform {
+    fn from(transform: &Matrix4<f32>) -> Self {
+        Self {
+            transform: *transform.as_ref(),
+        }
+    }
+}
+
+impl From<&Entity> for ModelUniform {
+    fn from(entity: &Entity) -> Self {
+        Self::from(&entity.transform())
+    }
+}
@@ -0,0 +1,256 @@
+use std::sync::Arc;
+
+use bytemuck::Pod;
+use vulkano::{
+    buffer::{BufferSlice, BufferUsage, CpuAccessibleBuffer, ImmutableBuffer, TypedBufferAccess},
+    command_buffer::{
+        AutoCommandBufferBuilder, CommandBufferUsage, CopyBufferInfo, PrimaryCommandBuffer,
+    },
+    device::Queue,
+    format::Format,
+    image::{ImageDimensions, ImmutableImage, MipmapsCount},
+    sync::GpuFuture,
+};
+
+use crate::error::Error;
+
+use super::memory::{AllocationCategory, GpuAllocation};
+
+pub type UploadFuture = Box<dyn GpuFuture + Send + Sync>;
+pub type StagingSlice = Arc<BufferSlice<[u8], CpuAccessibleBuffer<[u8]>>>;
+
+// Staging memory is allocated in blocks of this size, larger uploads get a buffer of their own
+pub const STAGING_BLOCK_SIZE: u64 = 16 << 20;
+// Covers texel block sizes and the usual optimal copy offset alignment
+const STAGING_ALIGNMENT: u64 = 16;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct UploadId(u64);
+
+struct StagingBlock {
+    buffer: Arc<CpuAccessibleBuffer<[u8]>>,
+    used: u64,
+    _memory: GpuAllocation,
+}
+
+// Host-visible blocks which stay mapped and are reused. Data is sub-allocated linearly from the
+// open block, blocks used by a batch are reclaimed when the frame which submitted it finishes
+#[derive(Default)]
+struct StagingRing {
+    open: Option<StagingBlock>,
+    free: Vec<StagingBlock>,
+    // Written since the last batch was taken
+    submitted: Vec<StagingBlock>,
+}
+
+// Uploads and staging blocks submitted with the frame drawing to a swapchain image
+struct InFlightBatch {
+    image: usize,
+    ids: Vec<UploadId>,
+    blocks: Vec<StagingBlock>,
+}
+
+// Resource initialization is collected here instead of waiting for a fence per resource,
+// then joined into the next frame so the draws using the resources are ordered after it.
+// Uploads go to the graphics queue: vulkano's immutable resources are exclusively owned
+// by the queue family they were initialized on
+pub struct UploadQueue {
+    queue: Arc<Queue>,
+    pending: Vec<UploadFuture>,
+    pending_ids: Vec<UploadId>,
+    in_flight: Vec<InFlightBatch>,
+    staging: StagingRing,
+    last_id: u64,
+}
+
+impl StagingBlock {
+    fn new(queue: &Arc<Queue>, size: u64) -> Result<Self, Error> {
+        // vulkano keeps host-visible memory mapped for the lifetime of the allocation
+        let buffer = unsafe {
+            CpuAccessibleBuffer::uninitialized_array(
+                queue.device().clone(),
+                size,
+                BufferUsage::transfer_src(),
+                false,
+            )?
+        };
+        Ok(Self {
+            buffer,
+            used: 0,
+            _memory: GpuAllocation::new(AllocationCategory::Staging, size),
+        })
+    }
+
+    fn remaining(&self) -> u64 {
+        self.buffer.len() - self.used
+    }
+
+    fn write(&mut self, data: &[u8]) -> Result<StagingSlice, Error> {
+        let start = self.used;
+        let end = start + data.len() as u64;
+        self.buffer.write()?[start as usize..end as usize].copy_from_slice(data);
+        self.used = (end + STAGING_ALIGNMENT - 1) / STAGING_ALIGNMENT * STAGING_ALIGNMENT;
+        Ok(self.buffer.into_buffer_slice().slice(start..end).unwrap())
+    }
+}
+
+impl StagingRing {
+    fn stage(&mut self, queue: &Arc<Queue>, data: &[u8]) -> Result<StagingSlice, Error> {
+        let size = data.len() as u64;
+        if size > STAGING_BLOCK_SIZE {
+            // Only kept until its batch completes
+            let mut block = StagingBlock::new(queue, size)?;
+            let slice = block.write(data)?;
+            self.submitted.push(block);
+            return Ok(slice);
+        }
+
+        if self
+            .open
+            .as_ref()
+            .map_or(true, |block| block.remaining() < size)
+        {
+            let block = match self.free.pop() {
+                Some(block) => block,
+                None => {
+                    log::debug!("Allocating a {} byte staging block", STAGING_BLOCK_SIZE);
+                    StagingBlock::new(queue, STAGING_BLOCK_SIZE)?
+                }
+            };
+            if let Some(full) = self.open.replace(block) {
+                self.submitted.push(full);
+            }
+        }
+        self.open.as_mut().unwrap().write(data)
+    }
+
+    fn seal(&mut self) -> Vec<StagingBlock> {
+        if let Some(block) = self.open.take() {
+            self.submitted.push(block);
+        }
+        std::mem::take(&mut self.submitted)
+    }
+
+    fn reclaim(&mut self, blocks: Vec<StagingBlock>) {
+        for mut block in blocks {
+            // One-off blocks are dropped
+            if block.buffer.len() == STAGING_BLOCK_SIZE {
+                block.used = 0;
+                self.free.push(block);
+            }
+        }
+    }
+}
+
+impl UploadQueue {
+    pub fn new(queue: Arc<Queue>) -> Self {
+        Self {
+            queue,
+            pending: vec![],
+            pending_ids: vec![],
+            in_flight: vec![],
+            staging: StagingRing::default(),
+            last_id: 0,
+        }
+    }
+
+    #[inline]
+    pub const fn queue(&self) -> &Arc<Queue> {
+        &self.queue
+    }
+
+    // Completion is reported with GameEvent::UploadsCompleted
+    pub fn push<F: GpuFuture + Send + Sync + 'static>(&mut self, init: F) -> UploadId {
+        self.last_id += 1;
+        let id = UploadId(self.last_id);
+        self.pending.push(Box::new(init));
+        self.pending_ids.push(id);
+        id
+    }
+
+    // Copies the data to staging memory. The copy reading it has to be pushed before the queue
+    // is unlocked, otherwise the block may be reclaimed before the copy is submitted
+    pub fn stage(&mut self, data: &[u8]) -> Result<StagingSlice, Error> {
+        self.staging.stage(&self.queue, data)
+    }
+
+    // Device-local buffer, usable right away
+    pub fn upload_buffer<T: Pod + Send + Sync>(
+        &mut self,
+        data: &[T],
+        usage: BufferUsage,
+    ) -> Result<Arc<ImmutableBuffer<[T]>>, Error> {
+        let staged = self.stage(bytemuck::cast_slice(data))?;
+        let (buffer, init) = unsafe {
+            ImmutableBuffer::uninitialized_array(
+                self.queue.device().clone(),
+                data.len() as u64,
+                BufferUsage {
+                    transfer_dst: true,
+                    ..usage
+                },
+            )?
+        };
+
+        let mut builder = AutoCommandBufferBuilder::primary(
+            self.queue.device().clone(),
+            self.queue.family(),
+            CommandBufferUsage::OneTimeSubmit,
+        )?;
+        builder.copy_buffer(CopyBufferInfo::buffers(staged, init))?;
+        let future = builder.build()?.execute(self.queue.clone())?;
+        self.push(future);
+
+        Ok(buffer)
+    }
+
+    pub fn upload_image(
+        &mut self,
+        data: &[u8],
+        dimensions: ImageDimensions,
+        mipmaps: MipmapsCount,
+        format: Format,
+    ) -> Result<Arc<ImmutableImage>, Error> {
+        let staged = self.stage(data)?;
+        let (image, init) =
+            ImmutableImage::from_buffer(staged, dimensions, mipmaps, format, self.queue.clone())?;
+        self.push(init);
+        Ok(image)
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    // All pending uploads as a single future, to be joined into the frame drawing to the image
+    pub(crate) fn take_batch(&mut self, image: usize) -> Option<UploadFuture> {
+        let blocks = self.staging.seal();
+        let ids = std::mem::take(&mut self.pending_ids);
+        if !blocks.is_empty() || !ids.is_empty() {
+            self.in_flight.push(InFlightBatch { image, ids, blocks });
+        }
+
+        let mut pending = self.pending.drain(..);
+        let first = pending.next()?;
+        Some(pending.fold(first, |batch, init| -> UploadFuture {
+            Box::new(batch.join(init))
+        }))
+    }
+
+    // Called once the image's previous frame has finished on the GPU and its fence was waited,
+    // which releases vulkano's locks on the staging blocks
+    pub(crate) fn complete_batch(&mut self, image: usize) -> Vec<UploadId> {
+        let (done, in_flight): (Vec<_>, Vec<_>) = std::mem::take(&mut self.in_flight)
+            .into_iter()
+            .partition(|batch| batch.image == image);
+        self.in_flight = in_flight;
+
+        let mut completed = vec![];
+        for batch in done {
+            self.staging.reclaim(batch.blocks);
+            completed.extend(batch.ids);
+        }
+        completed
+    }
+}
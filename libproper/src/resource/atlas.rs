@@ -0,0 +1,185 @@
+use std::{collections::BTreeMap, path::Path, sync::Arc};
+
+use image::RgbaImage;
+use nalgebra::Point2;
+use vulkano::format::Format;
+
+use crate::error::Error;
+
+use super::texture::{SampledTexture, TextureRegistry};
+
+// Edge pixels are repeated into the padding, so filtering doesn't bleed between images
+const DEFAULT_PADDING: u32 = 2;
+const MIN_SIZE: u32 = 64;
+
+// Part of the atlas holding one image
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AtlasRect {
+    // Texture coordinates of the top left and bottom right corners
+    pub uv_min: Point2<f32>,
+    pub uv_max: Point2<f32>,
+    // Size of the source image in pixels
+    pub width: u32,
+    pub height: u32,
+}
+
+// Many small images in one texture, so they can share a descriptor set
+pub struct TextureAtlas {
+    texture: Arc<SampledTexture>,
+    rects: BTreeMap<String, AtlasRect>,
+}
+
+pub struct AtlasBuilder {
+    max_size: u32,
+    padding: u32,
+    images: Vec<(String, RgbaImage)>,
+}
+
+impl TextureAtlas {
+    #[inline]
+    pub const fn texture(&self) -> &Arc<SampledTexture> {
+        &self.texture
+    }
+
+    #[inline]
+    pub fn get(&self, name: &str) -> Option<&AtlasRect> {
+        self.rects.get(name)
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.rects.keys().map(String::as_str)
+    }
+}
+
+impl AtlasBuilder {
+    // The atlas is square, with the side being a power of two up to max_size
+    pub fn new(max_size: u32) -> Self {
+        Self {
+            max_size,
+            padding: DEFAULT_PADDING,
+            images: vec![],
+        }
+    }
+
+    pub fn with_padding(mut self, padding: u32) -> Self {
+        self.padding = padding;
+        self
+    }
+
+    // Adding an image under the same name replaces it
+    pub fn add(&mut self, name: &str, image: RgbaImage) {
+        if image.width() == 0 || image.height() == 0 {
+            log::warn!("Skipping empty atlas image {:?}", name);
+            return;
+        }
+        self.images.retain(|(existing, _)| existing != name);
+        self.images.push((name.to_owned(), image));
+    }
+
+    pub fn add_from_path<P: AsRef<Path>>(&mut self, name: &str, path: P) -> Result<(), Error> {
+        let path = path.as_ref();
+        let image = image::open(path).map_err(|err| match err {
+            image::ImageError::IoError(err) => Error::file(path, err),
+            err => Error::asset_parse(path, err),
+        })?;
+        self.add(name, image.into_rgba8());
+        Ok(())
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.images.is_empty()
+    }
+
+    // Uploads the packed images as a single texture, registered under the name
+    pub fn build(self, name: &str, textures: &mut TextureRegistry) -> Result<TextureAtlas, Error> {
+        let _span = tracing::info_span!("build_atlas", images = self.images.len()).entered();
+        let area: u64 = self
+            .images
+            .iter()
+            .map(|(_, image)| {
+                (image.width() + self.padding * 2) as u64
+                    * (image.height() + self.padding * 2) as u64
+            })
+            .sum();
+
+        let mut size = MIN_SIZE
+            .max((area as f64).sqrt().ceil() as u32)
+            .next_power_of_two();
+        let positions = loop {
+            if size > self.max_size {
+                return Err(Error::AtlasOverflow(self.max_size));
+            }
+            if let Some(positions) = self.pack(size) {
+                break positions;
+            }
+            size *= 2;
+        };
+        log::debug!(
+            "Packed {} images into a {}x{} atlas {:?}",
+            self.images.len(),
+            size,
+            size,
+            name
+        );
+
+        let mut atlas = RgbaImage::new(size, size);
+        let mut rects = BTreeMap::new();
+        let padding = self.padding as i64;
+        for ((image_name, image), [x, y]) in self.images.into_iter().zip(positions) {
+            let (width, height) = image.dimensions();
+            for dy in -padding..height as i64 + padding {
+                for dx in -padding..width as i64 + padding {
+                    let source = image.get_pixel(
+                        dx.clamp(0, width as i64 - 1) as u32,
+                        dy.clamp(0, height as i64 - 1) as u32,
+                    );
+                    atlas.put_pixel((x as i64 + dx) as u32, (y as i64 + dy) as u32, *source);
+                }
+            }
+
+            let size = size as f32;
+            rects.insert(
+                image_name,
+                AtlasRect {
+                    uv_min: Point2::new(x as f32 / size, y as f32 / size),
+                    uv_max: Point2::new((x + width) as f32 / size, (y + height) as f32 / size),
+                    width,
+                    height,
+                },
+            );
+        }
+
+        let texture = textures.create_from_data(name, size, size, Format::R8G8B8A8_SRGB, &atlas)?;
+        Ok(TextureAtlas { texture, rects })
+    }
+
+    // Shelf packing, tallest images first. Returns the top left corners, without padding, in
+    // the order the images were added
+    fn pack(&self, size: u32) -> Option<Vec<[u32; 2]>> {
+        let mut order: Vec<usize> = (0..self.images.len()).collect();
+        order.sort_by_key(|&i| std::cmp::Reverse(self.images[i].1.height()));
+
+        let mut positions = vec![[0; 2]; self.images.len()];
+        let (mut x, mut y, mut shelf_height) = (0, 0, 0);
+        for i in order {
+            let image = &self.images[i].1;
+            let width = image.width() + self.padding * 2;
+            let height = image.height() + self.padding * 2;
+            if x + width > size {
+                x = 0;
+                y += shelf_height;
+                shelf_height = 0;
+            }
+            if x + width > size || y + height > size {
+                return None;
+            }
+
+            positions[i] = [x + self.padding, y + self.padding];
+            x += width;
+            shelf_height = shelf_height.max(height);
+        }
+
+        Some(positions)
+    }
+}
@@ -0,0 +1,258 @@
+use std::{
+    path::Path,
+    sync::{
+        mpsc::{self, Receiver, TryRecvError},
+        Arc, RwLock,
+    },
+};
+
+use serde::Deserialize;
+
+use crate::{error::Error, lock::Recover};
+
+use super::{material::MaterialRegistry, model::ModelRegistry, texture::TextureRegistry};
+
+// List of assets which have to be resident before the game starts
+#[derive(Deserialize, Default)]
+#[serde(default)]
+pub struct AssetManifest {
+    pub materials: Vec<String>,
+    pub textures: Vec<String>,
+    pub models: Vec<ModelAsset>,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct ModelAsset {
+    pub name: String,
+    #[serde(default = "default_material")]
+    pub material: String,
+}
+
+#[derive(Clone)]
+enum AssetRequest {
+    Material(String),
+    Texture(String),
+    Model(ModelAsset),
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct LoadProgress {
+    pub loaded: usize,
+    pub total: usize,
+    pub current: String,
+}
+
+enum LoadMessage {
+    Progress(LoadProgress),
+    Finished,
+    Failed(String),
+}
+
+pub struct AssetLoader {
+    material_registry: Arc<RwLock<MaterialRegistry>>,
+    model_registry: Arc<RwLock<ModelRegistry>>,
+    texture_registry: Arc<RwLock<TextureRegistry>>,
+    requests: Vec<AssetRequest>,
+}
+
+pub struct LoadingHandle {
+    receiver: Receiver<LoadMessage>,
+    progress: LoadProgress,
+    finished: bool,
+    error: Option<String>,
+}
+
+fn default_material() -> String {
+    "simple".to_owned()
+}
+
+impl AssetManifest {
+    pub fn load_or_default<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let path = path.as_ref();
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let text = std::fs::read_to_string(path).map_err(|err| Error::file(path, err))?;
+        toml::from_str(&text).map_err(|err| Error::asset_parse(path, err))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.materials.is_empty() && self.textures.is_empty() && self.models.is_empty()
+    }
+}
+
+impl AssetRequest {
+    fn name(&self) -> &str {
+        match self {
+            Self::Material(name) | Self::Texture(name) => name,
+            Self::Model(model) => &model.name,
+        }
+    }
+}
+
+impl AssetLoader {
+    pub fn new(
+        material_registry: Arc<RwLock<MaterialRegistry>>,
+        model_registry: Arc<RwLock<ModelRegistry>>,
+        texture_registry: Arc<RwLock<TextureRegistry>>,
+    ) -> Self {
+        Self {
+            material_registry,
+            model_registry,
+            texture_registry,
+            requests: vec![],
+        }
+    }
+
+    pub fn with_material(mut self, name: &str) -> Self {
+        self.requests.push(AssetRequest::Material(name.to_owned()));
+        self
+    }
+
+    pub fn with_texture(mut self, name: &str) -> Self {
+        self.requests.push(AssetRequest::Texture(name.to_owned()));
+        self
+    }
+
+    pub fn with_model(mut self, name: &str, material: &str) -> Self {
+        self.requests.push(AssetRequest::Model(ModelAsset {
+            name: name.to_owned(),
+            material: material.to_owned(),
+        }));
+        self
+    }
+
+    pub fn with_manifest(mut self, manifest: &AssetManifest) -> Self {
+        let materials = manifest
+            .materials
+            .iter()
+            .cloned()
+            .map(AssetRequest::Material);
+        let textures = manifest.textures.iter().cloned().map(AssetRequest::Texture);
+        let models = manifest.models.iter().cloned().map(AssetRequest::Model);
+        self.requests
+            .extend(materials.chain(textures).chain(models));
+        self
+    }
+
+    // Loads everything on a background thread, progress is reported through the handle
+    pub fn start(self) -> LoadingHandle {
+        let (sender, receiver) = mpsc::channel();
+        let total = self.requests.len();
+
+        rayon::spawn(move || {
+            for (loaded, request) in self.requests.iter().enumerate() {
+                sender
+                    .send(LoadMessage::Progress(LoadProgress {
+                        loaded,
+                        total,
+                        current: request.name().to_owned(),
+                    }))
+                    .ok();
+
+                if let Err(err) = self.load(request) {
+                    let message = format!("{:?}: {}", request.name(), err);
+                    sender.send(LoadMessage::Failed(message)).ok();
+                    return;
+                }
+            }
+            sender.send(LoadMessage::Finished).ok();
+        });
+
+        LoadingHandle {
+            receiver,
+            progress: LoadProgress {
+                total,
+                ..Default::default()
+            },
+            finished: false,
+            error: None,
+        }
+    }
+
+    fn load(&self, request: &AssetRequest) -> Result<(), Error> {
+        match request {
+            AssetRequest::Material(name) => {
+                self.material_registry.write().recover().get_or_load(name)?;
+            }
+            AssetRequest::Texture(name) => {
+                self.texture_registry.write().recover().get_or_load(name)?;
+            }
+            AssetRequest::Model(model) => {
+                let material = self
+                    .material_registry
+                    .write()
+                    .recover()
+                    .get_or_load(&model.material)?;
+                self.model_registry
+                    .write()
+                    .recover()
+                    .get_or_load(&model.name, material)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl LoadingHandle {
+    // Drains pending progress messages, returns true once loading is over
+    pub fn poll(&mut self) -> bool {
+        loop {
+            match self.receiver.try_recv() {
+                Ok(message) => self.handle(message),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    self.finished = true;
+                    break;
+                }
+            }
+        }
+        self.finished
+    }
+
+    // Blocks until loading is over
+    pub fn wait(mut self) -> Result<(), Error> {
+        while !self.finished {
+            match self.receiver.recv() {
+                Ok(message) => self.handle(message),
+                Err(_) => self.finished = true,
+            }
+        }
+        match self.error {
+            Some(err) => Err(Error::AssetLoad(err)),
+            None => Ok(()),
+        }
+    }
+
+    #[inline]
+    pub const fn progress(&self) -> &LoadProgress {
+        &self.progress
+    }
+
+    #[inline]
+    pub fn error(&self) -> Option<&str> {
+        self.error.as_deref()
+    }
+
+    pub fn fraction(&self) -> f32 {
+        if self.progress.total == 0 {
+            1.0
+        } else {
+            self.progress.loaded as f32 / self.progress.total as f32
+        }
+    }
+
+    fn handle(&mut self, message: LoadMessage) {
+        match message {
+            LoadMessage::Progress(progress) => self.progress = progress,
+            LoadMessage::Finished => {
+                self.progress.loaded = self.progress.total;
+                self.finished = true;
+            }
+            LoadMessage::Failed(err) => {
+                self.error = Some(err);
+                self.finished = true;
+            }
+        }
+    }
+}
@@ -2,7 +2,7 @@ use std::{
     collections::BTreeMap,
     sync::{
         atomic::{AtomicU64, Ordering},
-        Arc, RwLock,
+        Arc, Mutex, RwLock,
     },
 };
 
@@ -22,16 +22,24 @@ use vulkano::{
         GraphicsPipeline, Pipeline, PipelineBindPoint,
     },
     render_pass::{RenderPass, Subpass},
-    shader::ShaderModule,
-    sync::GpuFuture,
+    shader::{ShaderModule, SpecializationConstants},
+    sync::{self, GpuFuture},
 };
 
 use crate::{
-    error::Error,
-    render::{shader, Vertex},
+    error::{Error, ResourceKind},
+    lock::Recover,
+    render::{
+        arena,
+        bindless::BindlessTextures,
+        shader::{self, ShaderSource, ShaderStage, ShaderVariant},
+        shadow::MAX_LOCAL_LIGHTS,
+        upload::UploadFuture,
+        Vertex,
+    },
 };
 
-use super::texture::SampledTexture;
+use super::{preset::PresetLibrary, texture::SampledTexture};
 
 pub trait MaterialTemplate: Send + Sync {
     fn recreate_pipeline(
@@ -42,71 +50,268 @@ pub trait MaterialTemplate: Send + Sync {
     ) -> Result<(), Error>;
 
     fn pipeline(&self) -> &RwLock<Arc<GraphicsPipeline>>;
+    // Variant of pipeline() reading the transforms from the instance buffer of indirect draws.
+    // Entities of templates without one are drawn one at a time
+    fn indirect_pipeline(&self) -> Option<Arc<GraphicsPipeline>> {
+        None
+    }
     fn create_instance(
         &self,
         gfx_queue: Arc<Queue>,
         create_info: MaterialInstanceCreateInfo,
-    ) -> Result<(MaterialInstance, Box<dyn GpuFuture>), Error>;
+    ) -> Result<(MaterialInstance, UploadFuture), Error>;
 
     fn id(&self) -> &AtomicU64;
+    // Parameters accepted by create_instance(), used for editing
+    fn layout(&self) -> &MaterialLayout;
+
+    // Binds what every instance of the template uses, right after the pipeline
+    fn bind_shared(
+        &self,
+        _builder: &mut AutoCommandBufferBuilder<SecondaryAutoCommandBuffer>,
+        _pipeline: &Arc<GraphicsPipeline>,
+    ) -> Result<(), Error> {
+        Ok(())
+    }
 }
 
 #[derive(Clone, Default)]
 pub struct MaterialInstanceCreateInfo {
     textures: BTreeMap<String, Arc<SampledTexture>>,
     colors: BTreeMap<String, [f32; 4]>,
+    scalars: BTreeMap<String, f32>,
 }
 
+#[derive(Clone)]
 pub struct MaterialInstance {
-    set_index: u32,
-    material_set: Arc<PersistentDescriptorSet>,
+    data: MaterialData,
+}
+
+#[derive(Clone)]
+enum MaterialData {
+    DescriptorSet {
+        set_index: u32,
+        set: Arc<PersistentDescriptorSet>,
+    },
+    // Bindless materials only push their parameters, texture indices included
+    PushConstants(Arc<shader::bindless_fs::ty::Material_Push>),
+    // Custom shaders without parameters don't have to declare a material set
+    Empty,
 }
 
+pub trait MaterialTemplateFactory: Send + Sync {
+    fn create(
+        &self,
+        gfx_queue: &Arc<Queue>,
+        render_pass: &Arc<RenderPass>,
+        viewport: &Viewport,
+        variant: &ShaderVariant,
+    ) -> Result<Arc<dyn MaterialTemplate>, Error>;
+}
+
+impl<F> MaterialTemplateFactory for F
+where
+    F: Fn(
+            &Arc<Queue>,
+            &Arc<RenderPass>,
+            &Viewport,
+            &ShaderVariant,
+        ) -> Result<Arc<dyn MaterialTemplate>, Error>
+        + Send
+        + Sync,
+{
+    fn create(
+        &self,
+        gfx_queue: &Arc<Queue>,
+        render_pass: &Arc<RenderPass>,
+        viewport: &Viewport,
+        variant: &ShaderVariant,
+    ) -> Result<Arc<dyn MaterialTemplate>, Error> {
+        self(gfx_queue, render_pass, viewport, variant)
+    }
+}
+
+// Describes the material set (set = 1): colors are packed as vec4s followed by
+// float scalars into a uniform at binding 0, textures follow in declaration order
+#[derive(Clone, Default)]
+pub struct MaterialLayout {
+    colors: Vec<(String, [f32; 4])>,
+    scalars: Vec<(String, f32)>,
+    textures: Vec<String>,
+}
+
+// Used in place of the materials which fail to load
+pub const FALLBACK_MATERIAL: &str = "simple";
+
 pub struct MaterialRegistry {
     gfx_queue: Arc<Queue>,
     render_pass: Arc<RenderPass>,
     viewport: Viewport,
     last_id: u64,
-    data: BTreeMap<String, Arc<dyn MaterialTemplate>>,
+    factories: BTreeMap<String, Box<dyn MaterialTemplateFactory>>,
+    // Bound by custom materials for the textures an instance doesn't set
+    placeholder: Arc<SampledTexture>,
+    // Pipelines are built lazily, once per used (template, variant) combination
+    data: BTreeMap<(String, ShaderVariant), Arc<dyn MaterialTemplate>>,
+    presets: PresetLibrary,
 }
 
 unsafe impl Send for MaterialRegistry {}
+unsafe impl Sync for MaterialRegistry {}
 
 impl MaterialRegistry {
-    pub fn new(gfx_queue: Arc<Queue>, render_pass: Arc<RenderPass>, viewport: Viewport) -> Self {
-        Self {
+    // With bindless textures, the simple material indexes them instead of binding a set per
+    // instance. The placeholder is TextureRegistry's
+    pub fn new(
+        gfx_queue: Arc<Queue>,
+        render_pass: Arc<RenderPass>,
+        viewport: Viewport,
+        bindless: Option<Arc<Mutex<BindlessTextures>>>,
+        placeholder: Arc<SampledTexture>,
+    ) -> Self {
+        let mut registry = Self {
             gfx_queue,
             render_pass,
             viewport,
             last_id: 0,
+            factories: BTreeMap::new(),
+            placeholder,
             data: BTreeMap::new(),
+            presets: PresetLibrary::default(),
+        };
+
+        registry.register_factory(
+            "simple",
+            move |gfx_queue: &Arc<Queue>,
+                  render_pass: &Arc<RenderPass>,
+                  viewport: &Viewport,
+                  variant: &ShaderVariant| {
+                match bindless.as_ref() {
+                    Some(textures) => Ok(Arc::new(BindlessMaterial::new(
+                        gfx_queue,
+                        render_pass,
+                        viewport,
+                        variant,
+                        textures.clone(),
+                    )?) as Arc<dyn MaterialTemplate>),
+                    None => Ok(Arc::new(SimpleMaterial::new(
+                        gfx_queue,
+                        render_pass,
+                        viewport,
+                        variant,
+                    )?) as Arc<dyn MaterialTemplate>),
+                }
+            },
+        );
+
+        registry
+    }
+
+    pub fn register_factory<F: MaterialTemplateFactory + 'static>(
+        &mut self,
+        name: &str,
+        factory: F,
+    ) {
+        if self
+            .factories
+            .insert(name.to_owned(), Box::new(factory))
+            .is_some()
+        {
+            log::warn!("Replacing material factory {:?}", name);
         }
     }
 
+    pub fn register_custom(
+        &mut self,
+        name: &str,
+        vs: ShaderSource,
+        fs: ShaderSource,
+        layout: MaterialLayout,
+    ) {
+        let shader_name = name.to_owned();
+        let placeholder = self.placeholder.clone();
+        self.register_factory(
+            name,
+            move |gfx_queue: &Arc<Queue>,
+                  render_pass: &Arc<RenderPass>,
+                  viewport: &Viewport,
+                  variant: &ShaderVariant| {
+                Ok(Arc::new(CustomMaterial::new(
+                    gfx_queue,
+                    render_pass,
+                    viewport,
+                    &shader_name,
+                    (&vs, &fs),
+                    variant,
+                    layout.clone(),
+                    placeholder.clone(),
+                )?) as Arc<dyn MaterialTemplate>)
+            },
+        );
+    }
+
     pub fn get_or_load(&mut self, name: &str) -> Result<Arc<dyn MaterialTemplate>, Error> {
-        if let Some(template) = self.get(name) {
+        self.get_or_load_variant(name, &ShaderVariant::default())
+    }
+
+    pub fn get_or_load_variant(
+        &mut self,
+        name: &str,
+        variant: &ShaderVariant,
+    ) -> Result<Arc<dyn MaterialTemplate>, Error> {
+        if let Some(template) = self.get_variant(name, variant) {
             Ok(template.clone())
         } else {
-            self.last_id += 1;
-            let id = self.last_id;
-            log::info!("Loading material {:?} (#{})", name, id);
-
-            let mat = match name {
-                "simple" => Arc::new(
-                    SimpleMaterial::new(&self.gfx_queue, &self.render_pass, &self.viewport)
-                        .unwrap(),
-                ),
-                _ => panic!(),
+            let mat = match self.create_template(name, variant) {
+                Err(err) if name != FALLBACK_MATERIAL => {
+                    log::warn!(
+                        "Using the fallback material for {:?}: {}",
+                        name,
+                        err.full_message()
+                    );
+                    self.get_or_load(FALLBACK_MATERIAL)?
+                }
+                result => result?,
             };
-
-            mat.id().store(id, Ordering::Release);
-
-            self.data.insert(name.to_owned(), mat.clone());
+            self.data
+                .insert((name.to_owned(), variant.clone()), mat.clone());
 
             Ok(mat)
         }
     }
 
+    fn create_template(
+        &mut self,
+        name: &str,
+        variant: &ShaderVariant,
+    ) -> Result<Arc<dyn MaterialTemplate>, Error> {
+        let factory = self
+            .factories
+            .get(name)
+            .ok_or_else(|| Error::UnknownMaterial(name.to_owned()))?;
+
+        self.last_id += 1;
+        let id = self.last_id;
+        log::info!("Loading material {:?} {:?} (#{})", name, variant, id);
+
+        let mat = factory
+            .create(&self.gfx_queue, &self.render_pass, &self.viewport, variant)
+            .map_err(|err| err.context(ResourceKind::Material, name))?;
+
+        mat.id().store(id, Ordering::Release);
+        Ok(mat)
+    }
+
+    #[inline]
+    pub fn presets(&self) -> &PresetLibrary {
+        &self.presets
+    }
+
+    #[inline]
+    pub fn presets_mut(&mut self) -> &mut PresetLibrary {
+        &mut self.presets
+    }
+
     pub fn recreate_pipelines(&mut self, viewport: &Viewport) -> Result<(), Error> {
         self.viewport = viewport.clone();
         for mat in self.data.values_mut() {
@@ -116,22 +321,49 @@ impl MaterialRegistry {
     }
 
     pub fn get(&self, name: &str) -> Option<&Arc<dyn MaterialTemplate>> {
-        self.data.get(name)
+        self.get_variant(name, &ShaderVariant::default())
+    }
+
+    pub fn get_variant(
+        &self,
+        name: &str,
+        variant: &ShaderVariant,
+    ) -> Option<&Arc<dyn MaterialTemplate>> {
+        self.data.get(&(name.to_owned(), variant.clone()))
     }
 }
 
 impl MaterialInstance {
+    // Instances sharing a descriptor set or push constant data draw the same, e.g. the ones
+    // deduplicated by Scene::spawn_batch()
+    #[inline]
+    pub fn key(&self) -> usize {
+        match &self.data {
+            MaterialData::DescriptorSet { set, .. } => Arc::as_ptr(set) as usize,
+            MaterialData::PushConstants(data) => Arc::as_ptr(data) as usize,
+            MaterialData::Empty => 0,
+        }
+    }
+
     pub fn bind_data(
         &self,
         builder: &mut AutoCommandBufferBuilder<SecondaryAutoCommandBuffer>,
         pipeline: &Arc<GraphicsPipeline>,
     ) {
-        builder.bind_descriptor_sets(
-            PipelineBindPoint::Graphics,
-            pipeline.layout().clone(),
-            self.set_index,
-            self.material_set.clone(),
-        );
+        match &self.data {
+            MaterialData::DescriptorSet { set_index, set } => {
+                builder.bind_descriptor_sets(
+                    PipelineBindPoint::Graphics,
+                    pipeline.layout().clone(),
+                    *set_index,
+                    set.clone(),
+                );
+            }
+            MaterialData::PushConstants(data) => {
+                builder.push_constants(pipeline.layout().clone(), 0, **data);
+            }
+            MaterialData::Empty => (),
+        }
     }
 }
 
@@ -145,14 +377,156 @@ impl MaterialInstanceCreateInfo {
         self.textures.insert(name.to_owned(), texture);
         self
     }
+
+    pub fn with_scalar(mut self, name: &str, value: f32) -> Self {
+        self.scalars.insert(name.to_owned(), value);
+        self
+    }
+
+    pub fn color(&self, name: &str) -> Option<[f32; 4]> {
+        self.colors.get(name).copied()
+    }
+
+    pub fn scalar(&self, name: &str) -> Option<f32> {
+        self.scalars.get(name).copied()
+    }
+
+    pub fn texture(&self, name: &str) -> Option<&Arc<SampledTexture>> {
+        self.textures.get(name)
+    }
+
+    pub fn set_color(&mut self, name: &str, color: [f32; 4]) {
+        self.colors.insert(name.to_owned(), color);
+    }
+
+    pub fn set_scalar(&mut self, name: &str, value: f32) {
+        self.scalars.insert(name.to_owned(), value);
+    }
+
+    pub fn set_texture(&mut self, name: &str, texture: Option<Arc<SampledTexture>>) {
+        match texture {
+            Some(texture) => self.textures.insert(name.to_owned(), texture),
+            None => self.textures.remove(name),
+        };
+    }
+
+    // Parameters set in the other create info replace the ones in this one
+    pub fn merge(&mut self, other: &Self) {
+        self.textures
+            .extend(other.textures.iter().map(|(k, v)| (k.clone(), v.clone())));
+        self.colors
+            .extend(other.colors.iter().map(|(k, v)| (k.clone(), *v)));
+        self.scalars
+            .extend(other.scalars.iter().map(|(k, v)| (k.clone(), *v)));
+    }
+}
+
+// Textures are compared by identity
+impl PartialEq for MaterialInstanceCreateInfo {
+    fn eq(&self, other: &Self) -> bool {
+        self.colors == other.colors
+            && self.scalars == other.scalars
+            && self.textures.len() == other.textures.len()
+            && self
+                .textures
+                .iter()
+                .zip(other.textures.iter())
+                .all(|((a_name, a), (b_name, b))| a_name == b_name && Arc::ptr_eq(a, b))
+    }
+}
+
+impl MaterialLayout {
+    pub fn with_color(mut self, name: &str, default: [f32; 4]) -> Self {
+        self.colors.push((name.to_owned(), default));
+        self
+    }
+
+    pub fn with_scalar(mut self, name: &str, default: f32) -> Self {
+        self.scalars.push((name.to_owned(), default));
+        self
+    }
+
+    pub fn with_texture(mut self, name: &str) -> Self {
+        self.textures.push(name.to_owned());
+        self
+    }
+
+    #[inline]
+    pub fn colors(&self) -> &[(String, [f32; 4])] {
+        &self.colors
+    }
+
+    #[inline]
+    pub fn scalars(&self) -> &[(String, f32)] {
+        &self.scalars
+    }
+
+    #[inline]
+    pub fn textures(&self) -> &[String] {
+        &self.textures
+    }
+}
+
+fn create_forward_pipeline<Fss: SpecializationConstants>(
+    gfx_queue: &Arc<Queue>,
+    render_pass: &Arc<RenderPass>,
+    viewport: Viewport,
+    vs: &Arc<ShaderModule>,
+    fs: &Arc<ShaderModule>,
+    fs_constants: Fss,
+) -> Result<Arc<GraphicsPipeline>, Error> {
+    let subpass = Subpass::from(render_pass.clone(), 0).ok_or(Error::MissingSubpass)?;
+
+    GraphicsPipeline::start()
+        .input_assembly_state(InputAssemblyState::new())
+        .vertex_input_state(BuffersDefinition::new().vertex::<Vertex>())
+        .vertex_shader(
+            vs.entry_point("main")
+                .ok_or(Error::MissingShaderEntryPoint)?,
+            (),
+        )
+        .fragment_shader(
+            fs.entry_point("main")
+                .ok_or(Error::MissingShaderEntryPoint)?,
+            fs_constants,
+        )
+        .depth_stencil_state(DepthStencilState::simple_depth_test())
+        .multisample_state(MultisampleState {
+            rasterization_samples: subpass.num_samples().unwrap(),
+            ..Default::default()
+        })
+        .viewport_state(ViewportState::viewport_fixed_scissor_irrelevant([viewport]))
+        .render_pass(subpass)
+        // Model data comes from the model arena
+        .with_auto_layout(gfx_queue.device().clone(), arena::use_dynamic_model_set)
+        .map_err(Error::from)
+}
+
+// Parameters of the simple and bindless materials. Only the LIGHTMAP and HAS_NORMAL_MAP
+// variants take a lightmap and a normal map, so other meshes aren't reported for missing them
+fn forward_layout(lightmap: bool, normal_map: bool) -> MaterialLayout {
+    let mut layout = MaterialLayout::default()
+        .with_color("diffuse_color", [1.0; 4])
+        .with_texture("diffuse_map");
+    if lightmap {
+        layout = layout.with_texture("lightmap");
+    }
+    if normal_map {
+        layout = layout.with_texture("normal_map");
+    }
+    layout
 }
 
 // Specific materials
 
 pub struct SimpleMaterial {
     pipeline: RwLock<Arc<GraphicsPipeline>>,
+    indirect_pipeline: RwLock<Arc<GraphicsPipeline>>,
     vs: Arc<ShaderModule>,
+    indirect_vs: Arc<ShaderModule>,
     fs: Arc<ShaderModule>,
+    fs_constants: shader::simple_fs::SpecializationConstants,
+    layout: MaterialLayout,
     id: AtomicU64,
 }
 
@@ -161,57 +535,50 @@ impl SimpleMaterial {
         gfx_queue: &Arc<Queue>,
         render_pass: &Arc<RenderPass>,
         viewport: &Viewport,
+        variant: &ShaderVariant,
     ) -> Result<Self, Error> {
         let vs = shader::simple_vs::load(gfx_queue.device().clone())?;
+        let indirect_vs = shader::indirect_vs::load(gfx_queue.device().clone())?;
         let fs = shader::simple_fs::load(gfx_queue.device().clone())?;
-        let pipeline = RwLock::new(Self::create_pipeline(
+        if variant.value_or(ShaderVariant::SKINNED, 0) != 0 {
+            return Err(Error::UnsupportedShaderVariant(ShaderVariant::SKINNED));
+        }
+        let fs_constants = shader::simple_fs::SpecializationConstants {
+            HAS_DIFFUSE_MAP: variant.value_or(ShaderVariant::HAS_DIFFUSE_MAP, 1),
+            UNLIT: variant.value_or(ShaderVariant::UNLIT, 0),
+            ALPHA_TEST: variant.value_or(ShaderVariant::ALPHA_TEST, 0),
+            LIGHTMAP: variant.value_or(ShaderVariant::LIGHTMAP, 0),
+            HAS_NORMAL_MAP: variant.value_or(ShaderVariant::HAS_NORMAL_MAP, 0),
+            NUM_LIGHTS: variant.value_or(ShaderVariant::NUM_LIGHTS, MAX_LOCAL_LIGHTS as i32),
+        };
+        let pipeline = RwLock::new(create_forward_pipeline(
             gfx_queue,
             render_pass,
             viewport.clone(),
             &vs,
             &fs,
+            fs_constants,
+        )?);
+        let indirect_pipeline = RwLock::new(create_forward_pipeline(
+            gfx_queue,
+            render_pass,
+            viewport.clone(),
+            &indirect_vs,
+            &fs,
+            fs_constants,
         )?);
 
         Ok(Self {
             pipeline,
+            indirect_pipeline,
             vs,
+            indirect_vs,
             fs,
+            fs_constants,
+            layout: forward_layout(fs_constants.LIGHTMAP != 0, fs_constants.HAS_NORMAL_MAP != 0),
             id: AtomicU64::new(0),
         })
     }
-
-    fn create_pipeline(
-        gfx_queue: &Arc<Queue>,
-        render_pass: &Arc<RenderPass>,
-        viewport: Viewport,
-        vs: &Arc<ShaderModule>,
-        fs: &Arc<ShaderModule>,
-    ) -> Result<Arc<GraphicsPipeline>, Error> {
-        let subpass = Subpass::from(render_pass.clone(), 0).ok_or(Error::MissingSubpass)?;
-
-        GraphicsPipeline::start()
-            .input_assembly_state(InputAssemblyState::new())
-            .vertex_input_state(BuffersDefinition::new().vertex::<Vertex>())
-            .vertex_shader(
-                vs.entry_point("main")
-                    .ok_or(Error::MissingShaderEntryPoint)?,
-                (),
-            )
-            .fragment_shader(
-                fs.entry_point("main")
-                    .ok_or(Error::MissingShaderEntryPoint)?,
-                (),
-            )
-            .depth_stencil_state(DepthStencilState::simple_depth_test())
-            .multisample_state(MultisampleState {
-                rasterization_samples: subpass.num_samples().unwrap(),
-                ..Default::default()
-            })
-            .viewport_state(ViewportState::viewport_fixed_scissor_irrelevant([viewport]))
-            .render_pass(subpass)
-            .build(gfx_queue.device().clone())
-            .map_err(Error::from)
-    }
 }
 
 impl MaterialTemplate for SimpleMaterial {
@@ -219,15 +586,34 @@ impl MaterialTemplate for SimpleMaterial {
         &self.id
     }
 
+    fn layout(&self) -> &MaterialLayout {
+        &self.layout
+    }
+
     fn recreate_pipeline(
         &self,
         gfx_queue: &Arc<Queue>,
         render_pass: &Arc<RenderPass>,
         viewport: &Viewport,
     ) -> Result<(), Error> {
-        let mut lock = self.pipeline.write().unwrap();
-        *lock =
-            Self::create_pipeline(gfx_queue, render_pass, viewport.clone(), &self.vs, &self.fs)?;
+        let mut lock = self.pipeline.write().recover();
+        *lock = create_forward_pipeline(
+            gfx_queue,
+            render_pass,
+            viewport.clone(),
+            &self.vs,
+            &self.fs,
+            self.fs_constants,
+        )?;
+        let mut lock = self.indirect_pipeline.write().recover();
+        *lock = create_forward_pipeline(
+            gfx_queue,
+            render_pass,
+            viewport.clone(),
+            &self.indirect_vs,
+            &self.fs,
+            self.fs_constants,
+        )?;
         Ok(())
     }
 
@@ -235,7 +621,7 @@ impl MaterialTemplate for SimpleMaterial {
         &self,
         gfx_queue: Arc<Queue>,
         create_info: MaterialInstanceCreateInfo,
-    ) -> Result<(MaterialInstance, Box<dyn GpuFuture>), Error> {
+    ) -> Result<(MaterialInstance, UploadFuture), Error> {
         let (buffer, init) = ImmutableBuffer::from_data(
             shader::simple_fs::ty::Material_Data {
                 diffuse_color: *create_info.colors.get("diffuse_color").unwrap_or(&[1.0; 4]),
@@ -254,18 +640,41 @@ impl MaterialTemplate for SimpleMaterial {
         } else {
             diffuse_map = WriteDescriptorSet::none(1);
         }
+        let lightmap = match create_info.textures.get("lightmap") {
+            Some(map) => WriteDescriptorSet::image_view_sampler(
+                2,
+                map.image().clone(),
+                map.sampler().clone(),
+            ),
+            None => WriteDescriptorSet::none(2),
+        };
+        let normal_map = match create_info.textures.get("normal_map") {
+            Some(map) => WriteDescriptorSet::image_view_sampler(
+                3,
+                map.image().clone(),
+                map.sampler().clone(),
+            ),
+            None => WriteDescriptorSet::none(3),
+        };
 
-        let pipeline_lock = self.pipeline.read().unwrap();
+        let pipeline_lock = self.pipeline.read().recover();
         let layout = pipeline_lock.layout().set_layouts().get(1).unwrap();
         let material_set = PersistentDescriptorSet::new(
             layout.clone(),
-            vec![WriteDescriptorSet::buffer(0, buffer), diffuse_map],
+            vec![
+                WriteDescriptorSet::buffer(0, buffer),
+                diffuse_map,
+                lightmap,
+                normal_map,
+            ],
         )?;
 
         Ok((
             MaterialInstance {
-                set_index: 1,
-                material_set,
+                data: MaterialData::DescriptorSet {
+                    set_index: 1,
+                    set: material_set,
+                },
             },
             Box::new(init),
         ))
@@ -274,4 +683,304 @@ impl MaterialTemplate for SimpleMaterial {
     fn pipeline(&self) -> &RwLock<Arc<GraphicsPipeline>> {
         &self.pipeline
     }
+
+    fn indirect_pipeline(&self) -> Option<Arc<GraphicsPipeline>> {
+        Some(self.indirect_pipeline.read().recover().clone())
+    }
+}
+
+pub struct CustomMaterial {
+    pipeline: RwLock<Arc<GraphicsPipeline>>,
+    vs: Arc<ShaderModule>,
+    fs: Arc<ShaderModule>,
+    layout: MaterialLayout,
+    // Bound for the layout's textures an instance doesn't set
+    placeholder: Arc<SampledTexture>,
+    id: AtomicU64,
+}
+
+impl CustomMaterial {
+    pub fn new(
+        gfx_queue: &Arc<Queue>,
+        render_pass: &Arc<RenderPass>,
+        viewport: &Viewport,
+        name: &str,
+        (vs, fs): (&ShaderSource, &ShaderSource),
+        variant: &ShaderVariant,
+        layout: MaterialLayout,
+        placeholder: Arc<SampledTexture>,
+    ) -> Result<Self, Error> {
+        let vs = vs.load(
+            gfx_queue.device().clone(),
+            ShaderStage::Vertex,
+            &format!("{}.vert", name),
+            variant,
+        )?;
+        let fs = fs.load(
+            gfx_queue.device().clone(),
+            ShaderStage::Fragment,
+            &format!("{}.frag", name),
+            variant,
+        )?;
+        let pipeline = RwLock::new(create_forward_pipeline(
+            gfx_queue,
+            render_pass,
+            viewport.clone(),
+            &vs,
+            &fs,
+            (),
+        )?);
+
+        Ok(Self {
+            pipeline,
+            vs,
+            fs,
+            layout,
+            placeholder,
+            id: AtomicU64::new(0),
+        })
+    }
+}
+
+impl MaterialTemplate for CustomMaterial {
+    fn id(&self) -> &AtomicU64 {
+        &self.id
+    }
+
+    fn layout(&self) -> &MaterialLayout {
+        &self.layout
+    }
+
+    fn recreate_pipeline(
+        &self,
+        gfx_queue: &Arc<Queue>,
+        render_pass: &Arc<RenderPass>,
+        viewport: &Viewport,
+    ) -> Result<(), Error> {
+        let mut lock = self.pipeline.write().recover();
+        *lock = create_forward_pipeline(
+            gfx_queue,
+            render_pass,
+            viewport.clone(),
+            &self.vs,
+            &self.fs,
+            (),
+        )?;
+        Ok(())
+    }
+
+    fn create_instance(
+        &self,
+        gfx_queue: Arc<Queue>,
+        create_info: MaterialInstanceCreateInfo,
+    ) -> Result<(MaterialInstance, UploadFuture), Error> {
+        let mut writes = vec![];
+        let mut binding = 0;
+        let mut init: UploadFuture = Box::new(sync::now(gfx_queue.device().clone()));
+
+        if !self.layout.colors.is_empty() || !self.layout.scalars.is_empty() {
+            let colors = self
+                .layout
+                .colors
+                .iter()
+                .flat_map(|(name, default)| *create_info.colors.get(name).unwrap_or(default));
+            let scalars = self
+                .layout
+                .scalars
+                .iter()
+                .map(|(name, default)| *create_info.scalars.get(name).unwrap_or(default));
+            let data = colors.chain(scalars).collect::<Vec<_>>();
+            let (buffer, buffer_init) =
+                ImmutableBuffer::from_iter(data, BufferUsage::uniform_buffer(), gfx_queue)?;
+
+            writes.push(WriteDescriptorSet::buffer(binding, buffer));
+            init = Box::new(init.join(buffer_init));
+            binding += 1;
+        }
+
+        // Samplers have to be written, user shaders aren't built for partially bound sets
+        for name in self.layout.textures.iter() {
+            let map = create_info.textures.get(name).unwrap_or(&self.placeholder);
+            writes.push(WriteDescriptorSet::image_view_sampler(
+                binding,
+                map.image().clone(),
+                map.sampler().clone(),
+            ));
+            binding += 1;
+        }
+
+        let pipeline_lock = self.pipeline.read().recover();
+        let data = match pipeline_lock.layout().set_layouts().get(1) {
+            Some(layout) => MaterialData::DescriptorSet {
+                set_index: 1,
+                set: PersistentDescriptorSet::new(layout.clone(), writes)?,
+            },
+            None if writes.is_empty() => MaterialData::Empty,
+            None => return Err(Error::MissingMaterialSet),
+        };
+
+        Ok((MaterialInstance { data }, init))
+    }
+
+    fn pipeline(&self) -> &RwLock<Arc<GraphicsPipeline>> {
+        &self.pipeline
+    }
+}
+
+pub struct BindlessMaterial {
+    pipeline: RwLock<Arc<GraphicsPipeline>>,
+    indirect_pipeline: RwLock<Arc<GraphicsPipeline>>,
+    vs: Arc<ShaderModule>,
+    indirect_vs: Arc<ShaderModule>,
+    fs: Arc<ShaderModule>,
+    fs_constants: shader::bindless_fs::SpecializationConstants,
+    layout: MaterialLayout,
+    textures: Arc<Mutex<BindlessTextures>>,
+    id: AtomicU64,
+}
+
+impl BindlessMaterial {
+    pub fn new(
+        gfx_queue: &Arc<Queue>,
+        render_pass: &Arc<RenderPass>,
+        viewport: &Viewport,
+        variant: &ShaderVariant,
+        textures: Arc<Mutex<BindlessTextures>>,
+    ) -> Result<Self, Error> {
+        let vs = shader::simple_vs::load(gfx_queue.device().clone())?;
+        let indirect_vs = shader::indirect_vs::load(gfx_queue.device().clone())?;
+        let fs = shader::bindless_fs::load(gfx_queue.device().clone())?;
+        if variant.value_or(ShaderVariant::SKINNED, 0) != 0 {
+            return Err(Error::UnsupportedShaderVariant(ShaderVariant::SKINNED));
+        }
+        let fs_constants = shader::bindless_fs::SpecializationConstants {
+            HAS_DIFFUSE_MAP: variant.value_or(ShaderVariant::HAS_DIFFUSE_MAP, 1),
+            UNLIT: variant.value_or(ShaderVariant::UNLIT, 0),
+            ALPHA_TEST: variant.value_or(ShaderVariant::ALPHA_TEST, 0),
+            LIGHTMAP: variant.value_or(ShaderVariant::LIGHTMAP, 0),
+            HAS_NORMAL_MAP: variant.value_or(ShaderVariant::HAS_NORMAL_MAP, 0),
+            NUM_LIGHTS: variant.value_or(ShaderVariant::NUM_LIGHTS, MAX_LOCAL_LIGHTS as i32),
+        };
+        let pipeline = RwLock::new(create_forward_pipeline(
+            gfx_queue,
+            render_pass,
+            viewport.clone(),
+            &vs,
+            &fs,
+            fs_constants,
+        )?);
+        let indirect_pipeline = RwLock::new(create_forward_pipeline(
+            gfx_queue,
+            render_pass,
+            viewport.clone(),
+            &indirect_vs,
+            &fs,
+            fs_constants,
+        )?);
+
+        Ok(Self {
+            pipeline,
+            indirect_pipeline,
+            vs,
+            indirect_vs,
+            fs,
+            fs_constants,
+            // Same parameters as the simple material, so presets and the editor work with both
+            layout: forward_layout(fs_constants.LIGHTMAP != 0, fs_constants.HAS_NORMAL_MAP != 0),
+            textures,
+            id: AtomicU64::new(0),
+        })
+    }
+}
+
+impl MaterialTemplate for BindlessMaterial {
+    fn id(&self) -> &AtomicU64 {
+        &self.id
+    }
+
+    fn layout(&self) -> &MaterialLayout {
+        &self.layout
+    }
+
+    fn recreate_pipeline(
+        &self,
+        gfx_queue: &Arc<Queue>,
+        render_pass: &Arc<RenderPass>,
+        viewport: &Viewport,
+    ) -> Result<(), Error> {
+        let mut lock = self.pipeline.write().recover();
+        *lock = create_forward_pipeline(
+            gfx_queue,
+            render_pass,
+            viewport.clone(),
+            &self.vs,
+            &self.fs,
+            self.fs_constants,
+        )?;
+        let mut lock = self.indirect_pipeline.write().recover();
+        *lock = create_forward_pipeline(
+            gfx_queue,
+            render_pass,
+            viewport.clone(),
+            &self.indirect_vs,
+            &self.fs,
+            self.fs_constants,
+        )?;
+        Ok(())
+    }
+
+    // Nothing is uploaded, the texture array is updated when it's bound next
+    fn create_instance(
+        &self,
+        gfx_queue: Arc<Queue>,
+        create_info: MaterialInstanceCreateInfo,
+    ) -> Result<(MaterialInstance, UploadFuture), Error> {
+        let diffuse_map = create_info
+            .textures
+            .get("diffuse_map")
+            .map_or(0, |map| self.textures.lock().recover().index_of(map));
+        let lightmap = create_info
+            .textures
+            .get("lightmap")
+            .map_or(0, |map| self.textures.lock().recover().index_of(map));
+        let normal_map = create_info
+            .textures
+            .get("normal_map")
+            .map_or(0, |map| self.textures.lock().recover().index_of(map));
+        let data = shader::bindless_fs::ty::Material_Push {
+            diffuse_color: *create_info.colors.get("diffuse_color").unwrap_or(&[1.0; 4]),
+            texture_indices: [diffuse_map, lightmap, normal_map, 0],
+        };
+
+        Ok((
+            MaterialInstance {
+                data: MaterialData::PushConstants(Arc::new(data)),
+            },
+            Box::new(sync::now(gfx_queue.device().clone())),
+        ))
+    }
+
+    fn bind_shared(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<SecondaryAutoCommandBuffer>,
+        pipeline: &Arc<GraphicsPipeline>,
+    ) -> Result<(), Error> {
+        let layout = pipeline.layout().set_layouts().get(1).unwrap();
+        let set = self.textures.lock().recover().descriptor_set(layout)?;
+        builder.bind_descriptor_sets(
+            PipelineBindPoint::Graphics,
+            pipeline.layout().clone(),
+            1,
+            set,
+        );
+        Ok(())
+    }
+
+    fn pipeline(&self) -> &RwLock<Arc<GraphicsPipeline>> {
+        &self.pipeline
+    }
+
+    fn indirect_pipeline(&self) -> Option<Arc<GraphicsPipeline>> {
+        Some(self.indirect_pipeline.read().recover().clone())
+    }
 }
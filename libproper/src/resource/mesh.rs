@@ -0,0 +1,606 @@
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap},
+};
+
+use nalgebra::{Matrix3, Point2, Point3, Vector3, Vector4};
+
+use crate::render::Vertex;
+
+// Attributes closer than this are considered equal when welding
+const WELD_EPSILON: f32 = 1e-5;
+// Modelled post-transform cache, in vertices
+const CACHE_SIZE: usize = 32;
+// Part of a lightmap cell left empty on each side, so filtering and dilation don't bleed
+// into the neighbouring triangles
+const LIGHTMAP_PADDING: f32 = 0.1;
+
+// Plane error quadric, the upper triangle of a symmetric 4x4 matrix
+#[derive(Clone, Copy, Default)]
+struct Quadric([f64; 10]);
+
+// Indexed triangle list, loaded models are processed in this form before being uploaded
+#[derive(Clone, Debug, Default)]
+pub struct MeshData {
+    pub vertices: Vec<Vertex>,
+    pub indices: Vec<u32>,
+}
+
+fn quantize(value: f32) -> i32 {
+    (value / WELD_EPSILON).round() as i32
+}
+
+pub(crate) fn position_key(position: &Point3<f32>) -> [i32; 3] {
+    [
+        quantize(position.x),
+        quantize(position.y),
+        quantize(position.z),
+    ]
+}
+
+// Any vector perpendicular to the normal, for triangles without usable texture coordinates
+fn perpendicular(normal: &Vector3<f32>) -> Vector3<f32> {
+    let other = if normal.x.abs() < 0.9 {
+        Vector3::x()
+    } else {
+        Vector3::y()
+    };
+    normal.cross(&other).normalize()
+}
+
+// Forsyth's "Linear-speed vertex cache optimisation": vertices recently used and ones with
+// few triangles left are preferred
+fn vertex_score(cache_position: Option<usize>, remaining: usize) -> f32 {
+    if remaining == 0 {
+        return -1.0;
+    }
+    let cache = match cache_position {
+        Some(position) if position < 3 => 0.75,
+        Some(position) => (1.0 - (position - 3) as f32 / (CACHE_SIZE - 3) as f32).powf(1.5),
+        None => 0.0,
+    };
+    cache + 2.0 * (remaining as f32).powf(-0.5)
+}
+
+fn find(remap: &mut [usize], mut vertex: usize) -> usize {
+    while remap[vertex] != vertex {
+        remap[vertex] = remap[remap[vertex]];
+        vertex = remap[vertex];
+    }
+    vertex
+}
+
+impl Quadric {
+    fn from_triangle(a: &Point3<f32>, b: &Point3<f32>, c: &Point3<f32>) -> Self {
+        let normal = (b - a).cross(&(c - a)).cast::<f64>();
+        let area = normal.norm();
+        if area <= f64::EPSILON {
+            return Self::default();
+        }
+        let [x, y, z] = [normal.x / area, normal.y / area, normal.z / area];
+        let w = -(x * a.x as f64 + y * a.y as f64 + z * a.z as f64);
+        // Weighted by area so small triangles don't dominate
+        Self(
+            [
+                x * x,
+                x * y,
+                x * z,
+                x * w,
+                y * y,
+                y * z,
+                y * w,
+                z * z,
+                z * w,
+                w * w,
+            ]
+            .map(|v| v * area),
+        )
+    }
+
+    fn add(&mut self, other: &Self) {
+        for (a, b) in self.0.iter_mut().zip(other.0) {
+            *a += b;
+        }
+    }
+
+    fn error(&self, point: &Point3<f32>) -> f32 {
+        let [x, y, z] = [point.x as f64, point.y as f64, point.z as f64];
+        let q = &self.0;
+        let error = q[0] * x * x
+            + 2.0 * q[1] * x * y
+            + 2.0 * q[2] * x * z
+            + 2.0 * q[3] * x
+            + q[4] * y * y
+            + 2.0 * q[5] * y * z
+            + 2.0 * q[6] * y
+            + q[7] * z * z
+            + 2.0 * q[8] * z
+            + q[9];
+        error.max(0.0) as f32
+    }
+}
+
+impl MeshData {
+    // Every three vertices are a triangle
+    pub fn from_triangles(vertices: Vec<Vertex>) -> Self {
+        let indices = (0..vertices.len() as u32).collect();
+        Self { vertices, indices }
+    }
+
+    // Cube with sides of 1 centered at the origin, each face mapped to the whole texture
+    pub fn unit_cube() -> Self {
+        let mut mesh = Self::default();
+        for axis in 0..3 {
+            for sign in [1.0f32, -1.0] {
+                let mut normal = Vector3::zeros();
+                normal[axis] = sign;
+                let u = Vector3::ith((axis + 1) % 3, 1.0);
+                let v = normal.cross(&u);
+
+                let base = mesh.vertices.len() as u32;
+                for (su, sv) in [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)] {
+                    let position = (normal + u * su + v * sv) * 0.5;
+                    mesh.vertices.push(Vertex {
+                        v_position: position.into(),
+                        v_normal: normal,
+                        v_tex_coord: Point2::new((su + 1.0) * 0.5, (1.0 - sv) * 0.5),
+                        // Texture V grows opposite to v
+                        v_tangent: Vector4::new(u.x, u.y, u.z, -1.0),
+                    });
+                }
+                mesh.indices.extend([0, 1, 2, 0, 2, 3].map(|i| base + i));
+            }
+        }
+        mesh
+    }
+
+    #[inline]
+    pub fn triangle_count(&self) -> usize {
+        self.indices.len() / 3
+    }
+
+    // The other mesh's triangles go after the ones of this mesh
+    pub fn append(&mut self, other: &Self) {
+        let offset = self.vertices.len() as u32;
+        self.vertices.extend_from_slice(&other.vertices);
+        self.indices
+            .extend(other.indices.iter().map(|&index| index + offset));
+    }
+
+    pub fn triangles(&self) -> impl Iterator<Item = [usize; 3]> + '_ {
+        self.indices
+            .chunks_exact(3)
+            .map(|t| [t[0] as usize, t[1] as usize, t[2] as usize])
+    }
+
+    // Normals and tangents are transformed as directions, mirroring flips the winding
+    pub fn transform(&mut self, transform: &Matrix3<f32>) {
+        let normal_transform = transform
+            .try_inverse()
+            .map_or_else(Matrix3::identity, |inverse| inverse.transpose());
+        let mirror = transform.determinant() < 0.0;
+
+        for vertex in &mut self.vertices {
+            vertex.v_position = Point3::from(transform * vertex.v_position.coords);
+            vertex.v_normal = (normal_transform * vertex.v_normal)
+                .try_normalize(f32::EPSILON)
+                .unwrap_or(vertex.v_normal);
+            let tangent = (transform * vertex.v_tangent.xyz())
+                .try_normalize(f32::EPSILON)
+                .unwrap_or_else(Vector3::zeros);
+            let sign = if mirror {
+                -vertex.v_tangent.w
+            } else {
+                vertex.v_tangent.w
+            };
+            vertex.v_tangent = Vector4::new(tangent.x, tangent.y, tangent.z, sign);
+        }
+        if mirror {
+            for triangle in self.indices.chunks_exact_mut(3) {
+                triangle.swap(1, 2);
+            }
+        }
+    }
+
+    // For textures stored with the first row at the bottom. The bitangents point the other
+    // way afterwards
+    pub fn flip_v(&mut self) {
+        for vertex in &mut self.vertices {
+            vertex.v_tex_coord.y = 1.0 - vertex.v_tex_coord.y;
+            vertex.v_tangent.w = -vertex.v_tangent.w;
+        }
+    }
+
+    // OBJ files without normals leave them zeroed
+    pub fn has_normals(&self) -> bool {
+        self.vertices
+            .iter()
+            .all(|vertex| vertex.v_normal.norm_squared() > f32::EPSILON)
+    }
+
+    // Merges the vertices with equal attributes, tangents are ignored and should be generated
+    // afterwards
+    pub fn weld(&mut self) {
+        let mut remap = HashMap::new();
+        let mut vertices = vec![];
+        let indices = self
+            .indices
+            .iter()
+            .map(|&i| {
+                let vertex = self.vertices[i as usize];
+                let key = [
+                    vertex.v_position.x,
+                    vertex.v_position.y,
+                    vertex.v_position.z,
+                    vertex.v_normal.x,
+                    vertex.v_normal.y,
+                    vertex.v_normal.z,
+                    vertex.v_tex_coord.x,
+                    vertex.v_tex_coord.y,
+                ]
+                .map(quantize);
+
+                *remap.entry(key).or_insert_with(|| {
+                    vertices.push(vertex);
+                    vertices.len() as u32 - 1
+                })
+            })
+            .collect();
+
+        self.vertices = vertices;
+        self.indices = indices;
+    }
+
+    // Area-weighted face normals are shared between all the vertices at the same position,
+    // so the mesh is smooth across texture seams
+    pub fn generate_normals(&mut self) {
+        let mut normals: HashMap<[i32; 3], Vector3<f32>> = HashMap::new();
+        for [a, b, c] in self.triangles() {
+            let [pa, pb, pc] = [a, b, c].map(|i| self.vertices[i].v_position);
+            let face = (pb - pa).cross(&(pc - pa));
+            for position in [pa, pb, pc] {
+                *normals
+                    .entry(position_key(&position))
+                    .or_insert_with(Vector3::zeros) += face;
+            }
+        }
+
+        for vertex in &mut self.vertices {
+            let normal = normals
+                .get(&position_key(&vertex.v_position))
+                .copied()
+                .unwrap_or_else(Vector3::zeros);
+            vertex.v_normal = normal
+                .try_normalize(f32::EPSILON)
+                .unwrap_or_else(Vector3::y);
+        }
+    }
+
+    // Per-triangle tangents from the texture coordinate gradients, accumulated per vertex and
+    // orthogonalized against the normal. Same convention as MikkTSpace: the bitangent is
+    // cross(normal, tangent.xyz) * tangent.w
+    pub fn generate_tangents(&mut self) {
+        let mut tangents = vec![Vector3::zeros(); self.vertices.len()];
+        let mut bitangents = vec![Vector3::zeros(); self.vertices.len()];
+
+        for triangle in self.triangles() {
+            let [a, b, c] = triangle.map(|i| &self.vertices[i]);
+            let e1 = b.v_position - a.v_position;
+            let e2 = c.v_position - a.v_position;
+            let d1 = b.v_tex_coord - a.v_tex_coord;
+            let d2 = c.v_tex_coord - a.v_tex_coord;
+
+            let determinant = d1.x * d2.y - d2.x * d1.y;
+            if determinant.abs() <= f32::EPSILON {
+                continue;
+            }
+            let r = 1.0 / determinant;
+            let tangent = (e1 * d2.y - e2 * d1.y) * r;
+            let bitangent = (e2 * d1.x - e1 * d2.x) * r;
+
+            for i in triangle {
+                tangents[i] += tangent;
+                bitangents[i] += bitangent;
+            }
+        }
+
+        for ((vertex, tangent), bitangent) in self.vertices.iter_mut().zip(tangents).zip(bitangents)
+        {
+            let normal = vertex.v_normal;
+            let tangent = (tangent - normal * normal.dot(&tangent))
+                .try_normalize(f32::EPSILON)
+                .unwrap_or_else(|| perpendicular(&normal));
+            let sign = if normal.cross(&tangent).dot(&bitangent) < 0.0 {
+                -1.0
+            } else {
+                1.0
+            };
+            vertex.v_tangent = Vector4::new(tangent.x, tangent.y, tangent.z, sign);
+        }
+    }
+
+    // Second UV set for baked lighting: every triangle gets its own square cell in a grid
+    // covering the whole texture, flattened onto its plane and scaled to fit. Shared vertices
+    // are split, the indices keep their order so ranges of them stay valid. Simple rather
+    // than tight, small triangles get as many texels as large ones
+    pub fn generate_lightmap_coords(&mut self) {
+        let triangles: Vec<[usize; 3]> = self.triangles().collect();
+        let cells = (triangles.len() as f32).sqrt().ceil().max(1.0) as usize;
+        let cell_size = 1.0 / cells as f32;
+
+        let mut vertices = Vec::with_capacity(triangles.len() * 3);
+        for (index, triangle) in triangles.iter().enumerate() {
+            let [a, b, c] = triangle.map(|i| self.vertices[i]);
+            let e1 = b.v_position - a.v_position;
+            let e2 = c.v_position - a.v_position;
+            let u = e1.try_normalize(f32::EPSILON).unwrap_or_else(Vector3::x);
+            let normal = u.cross(&e2);
+            let v = normal
+                .cross(&u)
+                .try_normalize(f32::EPSILON)
+                .unwrap_or_else(|| perpendicular(&u));
+
+            let flat = [Vector3::zeros(), e1, e2].map(|e| Point2::new(e.dot(&u), e.dot(&v)));
+            let min = flat
+                .iter()
+                .fold(Point2::new(f32::MAX, f32::MAX), |m, p| m.inf(p));
+            let max = flat
+                .iter()
+                .fold(Point2::new(f32::MIN, f32::MIN), |m, p| m.sup(p));
+            let extent = (max - min).max().max(f32::EPSILON);
+            let scale = cell_size * (1.0 - 2.0 * LIGHTMAP_PADDING) / extent;
+            let origin = Point2::new((index % cells) as f32, (index / cells) as f32)
+                .map(|cell| (cell + LIGHTMAP_PADDING) * cell_size);
+
+            for (mut vertex, point) in [a, b, c].into_iter().zip(flat) {
+                vertex.v_lightmap_coord = origin + (point - min) * scale;
+                vertices.push(vertex);
+            }
+        }
+
+        self.indices = (0..vertices.len() as u32).collect();
+        self.vertices = vertices;
+    }
+
+    // Triangles are emitted greedily by the score of their vertices in a modelled LRU cache
+    pub fn optimize_vertex_cache(&mut self) {
+        let triangles: Vec<[usize; 3]> = self.triangles().collect();
+        let mut adjacency = vec![vec![]; self.vertices.len()];
+        for (t, triangle) in triangles.iter().enumerate() {
+            for &v in triangle {
+                adjacency[v].push(t);
+            }
+        }
+
+        let mut scores: Vec<f32> = adjacency
+            .iter()
+            .map(|triangles| vertex_score(None, triangles.len()))
+            .collect();
+        let triangle_score = |scores: &[f32], triangle: &[usize; 3]| {
+            triangle.iter().map(|&v| scores[v]).sum::<f32>()
+        };
+        let mut triangle_scores: Vec<f32> = triangles
+            .iter()
+            .map(|triangle| triangle_score(&scores, triangle))
+            .collect();
+        let mut emitted = vec![false; triangles.len()];
+        let mut next_unemitted = 0;
+        let mut cache: Vec<usize> = Vec::with_capacity(CACHE_SIZE + 3);
+        let mut indices = Vec::with_capacity(self.indices.len());
+
+        loop {
+            // Only the triangles of cached vertices are considered, the rest are in order
+            let mut best: Option<usize> = None;
+            for &v in &cache {
+                for &t in &adjacency[v] {
+                    if best.map_or(true, |best| triangle_scores[t] > triangle_scores[best]) {
+                        best = Some(t);
+                    }
+                }
+            }
+            let best = match best {
+                Some(best) => best,
+                None => {
+                    while next_unemitted < triangles.len() && emitted[next_unemitted] {
+                        next_unemitted += 1;
+                    }
+                    if next_unemitted == triangles.len() {
+                        break;
+                    }
+                    next_unemitted
+                }
+            };
+
+            let triangle = triangles[best];
+            emitted[best] = true;
+            indices.extend(triangle.map(|v| v as u32));
+            for v in triangle {
+                adjacency[v].retain(|&t| t != best);
+                cache.retain(|&c| c != v);
+            }
+            for (position, v) in triangle.into_iter().enumerate() {
+                cache.insert(position, v);
+            }
+            let evicted = cache.split_off(cache.len().min(CACHE_SIZE));
+
+            for (position, &v) in cache.iter().enumerate() {
+                scores[v] = vertex_score(Some(position), adjacency[v].len());
+            }
+            for &v in &evicted {
+                scores[v] = vertex_score(None, adjacency[v].len());
+            }
+            for &v in cache.iter().chain(&evicted) {
+                for &t in &adjacency[v] {
+                    triangle_scores[t] = triangle_score(&scores, &triangles[t]);
+                }
+            }
+        }
+
+        self.indices = indices;
+    }
+
+    // Orders the vertices by first use and drops unused ones, should be done after reordering
+    // the triangles
+    pub fn optimize_vertex_fetch(&mut self) {
+        let mut remap = vec![u32::MAX; self.vertices.len()];
+        let mut vertices = Vec::with_capacity(self.vertices.len());
+        for index in &mut self.indices {
+            let new = &mut remap[*index as usize];
+            if *new == u32::MAX {
+                *new = vertices.len() as u32;
+                vertices.push(self.vertices[*index as usize]);
+            }
+            *index = *new;
+        }
+        self.vertices = vertices;
+    }
+
+    // Quadric error edge collapse onto one of the edge's vertices, so no attributes have to be
+    // interpolated. Vertices on borders and texture seams stay where they are
+    pub fn simplify(&mut self, target_triangles: usize) {
+        let mut triangles: Vec<[usize; 3]> = self.triangles().collect();
+        let mut live = triangles.len();
+        if live <= target_triangles {
+            return;
+        }
+
+        let mut locked = vec![false; self.vertices.len()];
+        let mut shared_positions: HashMap<[i32; 3], Vec<usize>> = HashMap::new();
+        for (i, vertex) in self.vertices.iter().enumerate() {
+            shared_positions
+                .entry(position_key(&vertex.v_position))
+                .or_default()
+                .push(i);
+        }
+        for vertices in shared_positions
+            .values()
+            .filter(|vertices| vertices.len() > 1)
+        {
+            for &v in vertices {
+                locked[v] = true;
+            }
+        }
+        let mut edge_uses: HashMap<(usize, usize), usize> = HashMap::new();
+        for [a, b, c] in &triangles {
+            for (u, v) in [(*a, *b), (*b, *c), (*c, *a)] {
+                *edge_uses.entry((u.min(v), u.max(v))).or_default() += 1;
+            }
+        }
+        for (&(u, v), &uses) in &edge_uses {
+            if uses == 1 {
+                locked[u] = true;
+                locked[v] = true;
+            }
+        }
+
+        let positions: Vec<Point3<f32>> = self.vertices.iter().map(|v| v.v_position).collect();
+        let mut quadrics = vec![Quadric::default(); self.vertices.len()];
+        let mut vertex_triangles = vec![vec![]; self.vertices.len()];
+        for (t, &[a, b, c]) in triangles.iter().enumerate() {
+            let quadric = Quadric::from_triangle(&positions[a], &positions[b], &positions[c]);
+            for v in [a, b, c] {
+                quadrics[v].add(&quadric);
+                vertex_triangles[v].push(t);
+            }
+        }
+
+        // Costs are non-negative, so their bit patterns sort the same as the values
+        let mut heap: BinaryHeap<Reverse<(u32, usize, usize)>> = BinaryHeap::new();
+        let push_collapse =
+            |heap: &mut BinaryHeap<_>, quadrics: &[Quadric], from: usize, to: usize| {
+                let mut quadric = quadrics[from];
+                quadric.add(&quadrics[to]);
+                let cost = quadric.error(&positions[to]);
+                heap.push(Reverse((cost.to_bits(), from, to)));
+            };
+        for &(u, v) in edge_uses.keys() {
+            if !locked[u] {
+                push_collapse(&mut heap, &quadrics, u, v);
+            }
+            if !locked[v] {
+                push_collapse(&mut heap, &quadrics, v, u);
+            }
+        }
+
+        let mut remap: Vec<usize> = (0..self.vertices.len()).collect();
+        let mut removed = vec![false; triangles.len()];
+        while live > target_triangles {
+            let Reverse((cost, from, to)) = match heap.pop() {
+                Some(collapse) => collapse,
+                None => break,
+            };
+            let to = find(&mut remap, to);
+            if find(&mut remap, from) != from || to == from {
+                continue;
+            }
+            // Quadrics grow as vertices are merged, stale entries are queued again
+            let mut quadric = quadrics[from];
+            quadric.add(&quadrics[to]);
+            let current = quadric.error(&positions[to]).to_bits();
+            if current != cost {
+                heap.push(Reverse((current, from, to)));
+                continue;
+            }
+
+            // Triangles which would flip over make the collapse invalid
+            let flips = vertex_triangles[from].iter().any(|&t| {
+                if removed[t] {
+                    return false;
+                }
+                // Corners always point at vertices which weren't collapsed
+                let corners = triangles[t];
+                if corners.contains(&to) {
+                    return false;
+                }
+                let [a, b, c] = corners.map(|v| positions[v]);
+                let [na, nb, nc] = corners.map(|v| {
+                    if v == from {
+                        positions[to]
+                    } else {
+                        positions[v]
+                    }
+                });
+                (b - a).cross(&(c - a)).dot(&(nb - na).cross(&(nc - na))) <= 0.0
+            });
+            if flips {
+                continue;
+            }
+
+            remap[from] = to;
+            quadrics[to] = quadric;
+            let moved = std::mem::take(&mut vertex_triangles[from]);
+            let mut neighbours = vec![];
+            for &t in &moved {
+                if removed[t] {
+                    continue;
+                }
+                triangles[t] = triangles[t].map(|v| find(&mut remap, v));
+                let [a, b, c] = triangles[t];
+                if a == b || b == c || c == a {
+                    removed[t] = true;
+                    live -= 1;
+                } else {
+                    neighbours.extend([a, b, c].into_iter().filter(|&v| v != to));
+                }
+            }
+            vertex_triangles[to].extend(moved);
+
+            for v in neighbours {
+                if !locked[v] {
+                    push_collapse(&mut heap, &quadrics, v, to);
+                }
+                if !locked[to] {
+                    push_collapse(&mut heap, &quadrics, to, v);
+                }
+            }
+        }
+
+        self.indices = triangles
+            .iter()
+            .zip(removed)
+            .filter(|(_, removed)| !removed)
+            .flat_map(|(triangle, _)| triangle.map(|v| find(&mut remap, v) as u32))
+            .collect();
+        self.optimize_vertex_fetch();
+    }
+}
@@ -0,0 +1,129 @@
+use std::{
+    collections::BTreeMap,
+    ffi::OsString,
+    path::{Path, PathBuf},
+};
+
+use nalgebra::Matrix3;
+use serde::{de::DeserializeOwned, Deserialize};
+use vulkano::format::Format;
+
+use crate::error::Error;
+
+use super::{mesh::MeshData, sampler::SamplerDesc};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ColorSpace {
+    // Color data, converted to linear when sampled
+    Srgb,
+    // Normal maps, masks and other non-color data
+    Linear,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MipPolicy {
+    None,
+    Generate,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UpAxis {
+    Y,
+    Z,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct TextureMeta {
+    pub color_space: ColorSpace,
+    pub mipmaps: MipPolicy,
+    pub sampler: SamplerDesc,
+}
+
+// Import settings, also passed directly to override the ones next to the model file
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct ModelMeta {
+    // Uniform, applied to the positions
+    pub scale: f32,
+    // Z-up models are turned so their up axis becomes +Y
+    pub up_axis: UpAxis,
+    // v = 1 - v, for exporters which put the texture origin at the bottom left
+    pub flip_v: bool,
+    // Blend shape names and their files, relative to the model. The files have to be the
+    // model with only positions and normals changed
+    pub morph_targets: BTreeMap<String, PathBuf>,
+    // Generate the second UV set for lightmaps, see MeshData::generate_lightmap_coords().
+    // Ignored for models with morph targets
+    pub lightmap_coords: bool,
+}
+
+// Reads the optional TOML file next to an asset, named after the whole file:
+// res/textures/grass.png.meta for res/textures/grass.png. Missing files and fields keep
+// their defaults
+pub fn load<T: DeserializeOwned + Default, P: AsRef<Path>>(asset_path: P) -> Result<T, Error> {
+    let mut path = OsString::from(asset_path.as_ref());
+    path.push(".meta");
+    let path = Path::new(&path);
+    if !path.exists() {
+        return Ok(T::default());
+    }
+
+    let text = std::fs::read_to_string(path).map_err(|err| Error::file(path, err))?;
+    toml::from_str(&text).map_err(|err| Error::asset_parse(path, err))
+}
+
+impl Default for TextureMeta {
+    fn default() -> Self {
+        Self {
+            color_space: ColorSpace::Srgb,
+            mipmaps: MipPolicy::None,
+            sampler: SamplerDesc::default(),
+        }
+    }
+}
+
+impl TextureMeta {
+    // Images are loaded as 8-bit RGBA
+    pub const fn format(&self) -> Format {
+        match self.color_space {
+            ColorSpace::Srgb => Format::R8G8B8A8_SRGB,
+            ColorSpace::Linear => Format::R8G8B8A8_UNORM,
+        }
+    }
+}
+
+impl Default for ModelMeta {
+    fn default() -> Self {
+        Self {
+            scale: 1.0,
+            up_axis: UpAxis::Y,
+            flip_v: false,
+            morph_targets: BTreeMap::new(),
+            lightmap_coords: false,
+        }
+    }
+}
+
+impl ModelMeta {
+    pub fn transform(&self) -> Matrix3<f32> {
+        let rotation = match self.up_axis {
+            UpAxis::Y => Matrix3::identity(),
+            // (x, y, z) -> (x, z, -y)
+            UpAxis::Z => Matrix3::new(1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, -1.0, 0.0),
+        };
+        rotation * self.scale
+    }
+
+    pub fn apply(&self, mesh: &mut MeshData) {
+        if self.scale != 1.0 || self.up_axis != UpAxis::Y {
+            mesh.transform(&self.transform());
+        }
+        if self.flip_v {
+            mesh.flip_v();
+        }
+    }
+}
@@ -1,3 +1,10 @@
+pub mod atlas;
+pub mod loader;
 pub mod material;
+pub mod mesh;
+pub mod meta;
 pub mod model;
+pub mod morph;
+pub mod preset;
+pub mod sampler;
 pub mod texture;
@@ -1,36 +1,116 @@
 use std::{
-    collections::BTreeMap,
+    collections::{BTreeMap, HashMap},
     fs::File,
     io::BufReader,
     path::{Path, PathBuf},
-    sync::Arc,
+    sync::{Arc, Mutex},
 };
 
-use nalgebra::Point2;
-use obj::{Obj, TexturedVertex};
-use vulkano::{
-    buffer::{BufferUsage, ImmutableBuffer},
-    device::Queue,
-    sync::GpuFuture,
+use nalgebra::{Point2, Point3, Vector3, Vector4};
+use obj::raw::{object::Polygon, RawObj};
+use vulkano::buffer::{BufferUsage, ImmutableBuffer, TypedBufferAccess};
+
+use crate::{
+    error::{Error, ResourceKind},
+    lock::Recover,
+    render::{
+        arena::UniformArena,
+        memory::{AllocationCategory, GpuAllocation},
+        stats,
+        uniforms::ModelUniform,
+        upload::UploadQueue,
+        Vertex,
+    },
+    world::{scene::MeshObject, spatial::Aabb},
 };
 
-use crate::{error::Error, render::Vertex, world::scene::MeshObject};
+#[cfg(feature = "physics")]
+use crate::world::collision_mesh::CollisionMesh;
+
+use super::{
+    material::{MaterialInstanceCreateInfo, MaterialTemplate},
+    mesh::MeshData,
+    meta::{self, ModelMeta},
+    morph::{MorphTarget, MorphTargets},
+};
 
-use super::material::{MaterialInstanceCreateInfo, MaterialTemplate};
+// Texture name which makes the loader generate tangents
+const NORMAL_MAP: &str = "normal_map";
 
 pub struct Model {
     data: Arc<ImmutableBuffer<[Vertex]>>,
+    indices: Arc<ImmutableBuffer<[u32]>>,
+    // CPU-side copy of the triangle list, used for picking/navigation/etc.
+    positions: Vec<Point3<f32>>,
+    bounds: Aabb,
+    submeshes: Vec<Submesh>,
+    morph_targets: Option<MorphTargets>,
+    // Per triangle corner like the positions, empty without a lightmap UV set
+    lightmap_coords: Vec<Point2<f32>>,
     material_template: Arc<dyn MaterialTemplate>,
+    _memory: GpuAllocation,
+}
+
+// Range of the index buffer drawn with its own material. Models loaded from files have one
+// per material used in the file, others have a single one covering everything
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Submesh {
+    // Material name from the model file, empty for the triangles without one
+    pub name: String,
+    pub first_index: u32,
+    pub index_count: u32,
+}
+
+type ModelData = (
+    Arc<ImmutableBuffer<[Vertex]>>,
+    Arc<ImmutableBuffer<[u32]>>,
+    Vec<Point3<f32>>,
+);
+
+// Processing applied to models loaded from files
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ModelLoadOptions {
+    // Only done for the files which have no normals
+    pub generate_normals: bool,
+    // Always done if the material has a normal map
+    pub generate_tangents: bool,
+    // Merge duplicate vertices
+    pub weld: bool,
+    // Reorder the triangles for the post-transform vertex cache and the vertices for fetching
+    pub optimize: bool,
+    // Collapse edges until the model has at most this many triangles. Borders and texture seams
+    // are kept, so the target may not be reached
+    pub simplify_to: Option<usize>,
 }
 
 pub struct ModelRegistry {
-    gfx_queue: Arc<Queue>,
+    uploads: Arc<Mutex<UploadQueue>>,
+    // Transforms of all mesh objects
+    model_arena: UniformArena<ModelUniform>,
+    // By model hash, backed by the on-disk cache
+    #[cfg(feature = "physics")]
+    collision_meshes: HashMap<u64, Arc<CollisionMesh>>,
+    #[cfg(feature = "physics")]
+    collision_cache_dir: PathBuf,
     data: BTreeMap<String, Arc<Model>>,
+    load_options: ModelLoadOptions,
+}
+
+impl Default for ModelLoadOptions {
+    fn default() -> Self {
+        Self {
+            generate_normals: true,
+            generate_tangents: false,
+            weld: false,
+            optimize: true,
+            simplify_to: None,
+        }
+    }
 }
 
 impl Model {
     pub fn new<I>(
-        gfx_queue: Arc<Queue>,
+        uploads: &Mutex<UploadQueue>,
         vertices: I,
         material_template: Arc<dyn MaterialTemplate>,
     ) -> Result<Self, Error>
@@ -38,27 +118,135 @@ impl Model {
         I: IntoIterator<Item = Vertex>,
         I::IntoIter: ExactSizeIterator,
     {
-        let (buffer, init) =
-            ImmutableBuffer::from_iter(vertices, BufferUsage::vertex_buffer(), gfx_queue)?;
-
-        init.then_signal_fence_and_flush()?.wait(None).unwrap();
+        let mesh = MeshData::from_triangles(vertices.into_iter().collect());
+        Self::from_mesh(uploads, &mesh, material_template)
+    }
 
-        Ok(Self {
-            data: buffer,
+    // Single submesh model from generated geometry
+    pub fn from_mesh(
+        uploads: &Mutex<UploadQueue>,
+        mesh: &MeshData,
+        material_template: Arc<dyn MaterialTemplate>,
+    ) -> Result<Self, Error> {
+        let model_data = Self::upload(uploads, mesh)?;
+        Ok(Self::from_parts(
+            model_data,
+            vec![Submesh::whole(mesh)],
             material_template,
-        })
+        ))
     }
 
+    // Unit cube, used in place of missing or broken model files
+    pub fn placeholder(
+        uploads: &Mutex<UploadQueue>,
+        material_template: Arc<dyn MaterialTemplate>,
+    ) -> Result<Self, Error> {
+        Self::from_mesh(uploads, &MeshData::unit_cube(), material_template)
+    }
+
+    // Import settings are read from the .meta file next to the model unless given
     pub fn load_to_device<P: AsRef<Path>>(
-        gfx_queue: Arc<Queue>,
+        uploads: &Mutex<UploadQueue>,
         path: P,
         material_template: Arc<dyn MaterialTemplate>,
+        options: &ModelLoadOptions,
+        import: Option<&ModelMeta>,
     ) -> Result<Self, Error> {
-        let data = Self::load_obj(gfx_queue, path)?;
-        Ok(Self {
-            data,
-            material_template,
-        })
+        let path = path.as_ref();
+        let import = match import {
+            Some(import) => import.clone(),
+            None => meta::load(path)?,
+        };
+        let tangents = options.generate_tangents
+            || material_template
+                .layout()
+                .textures()
+                .iter()
+                .any(|name| name == NORMAL_MAP);
+
+        // Morph target files are the same model with the vertices moved, so vertices can't be
+        // merged or reordered
+        let mut shape_files = vec![];
+        for (name, file) in &import.morph_targets {
+            let shape_path = path.parent().unwrap_or_else(|| Path::new("")).join(file);
+            let parts = Self::load_obj(&shape_path)?;
+            shape_files.push((name, shape_path, parts.into_iter()));
+        }
+        let optimize = options.optimize;
+        let options = if shape_files.is_empty() {
+            *options
+        } else {
+            ModelLoadOptions {
+                weld: false,
+                optimize: false,
+                simplify_to: None,
+                ..*options
+            }
+        };
+
+        // Submeshes are processed separately so reordering keeps their triangles together
+        let mut mesh = MeshData::default();
+        let mut shapes = vec![vec![]; shape_files.len()];
+        let mut submeshes = vec![];
+        for (name, mut part) in Self::load_obj(path)? {
+            import.apply(&mut part);
+            Self::process(&mut part, &options, tangents);
+            if optimize && !shape_files.is_empty() {
+                part.optimize_vertex_cache();
+            }
+
+            for ((_, shape_path, shape_parts), shape) in shape_files.iter_mut().zip(&mut shapes) {
+                let mut shape_part = match shape_parts.next() {
+                    Some((_, shape_part)) if shape_part.vertices.len() == part.vertices.len() => {
+                        shape_part
+                    }
+                    _ => {
+                        return Err(Error::asset_parse(
+                            shape_path.as_path(),
+                            "morph target doesn't match the vertices of the model",
+                        ))
+                    }
+                };
+                import.apply(&mut shape_part);
+                if options.generate_normals && !shape_part.has_normals() {
+                    shape_part.generate_normals();
+                }
+                shape.extend_from_slice(&shape_part.vertices);
+            }
+
+            let first_index = mesh.indices.len() as u32;
+            mesh.append(&part);
+            submeshes.push(Submesh {
+                name,
+                first_index,
+                index_count: part.indices.len() as u32,
+            });
+        }
+
+        // Done last, splitting the vertices undoes welding and the vertex cache order
+        let lightmapped = import.lightmap_coords && shape_files.is_empty();
+        if lightmapped {
+            mesh.generate_lightmap_coords();
+        }
+
+        let model_data = Self::upload(uploads, &mesh)?;
+        let mut model = Self::from_parts(model_data, submeshes, material_template);
+        if lightmapped {
+            model.lightmap_coords = mesh
+                .indices
+                .iter()
+                .map(|&i| mesh.vertices[i as usize].v_lightmap_coord)
+                .collect();
+        }
+        if !shape_files.is_empty() {
+            let targets = shape_files
+                .iter()
+                .zip(&shapes)
+                .map(|((name, _, _), shape)| MorphTarget::from_shapes(name, &mesh.vertices, shape))
+                .collect();
+            model.morph_targets = Some(MorphTargets::new(mesh.vertices, targets));
+        }
+        Ok(model)
     }
 
     #[inline]
@@ -66,42 +254,266 @@ impl Model {
         &self.data
     }
 
+    #[inline]
+    pub const fn indices(&self) -> &Arc<ImmutableBuffer<[u32]>> {
+        &self.indices
+    }
+
     #[inline]
     pub const fn material_template(&self) -> &Arc<dyn MaterialTemplate> {
         &self.material_template
     }
 
-    fn load_obj<P: AsRef<Path>>(
-        gfx_queue: Arc<Queue>,
-        path: P,
-    ) -> Result<Arc<ImmutableBuffer<[Vertex]>>, Error> {
-        let input = BufReader::new(File::open(path).unwrap());
-        let obj: Obj<TexturedVertex> = obj::load_obj(input).unwrap();
-
-        let vertices = obj.indices.iter().map(|&i| {
-            let v = obj.vertices[i as usize];
-            Vertex {
-                v_position: v.position.into(),
-                v_normal: v.normal.into(),
-                v_tex_coord: Point2::new(v.texture[0], v.texture[1]),
+    #[inline]
+    pub fn positions(&self) -> &[Point3<f32>] {
+        &self.positions
+    }
+
+    #[inline]
+    pub const fn bounds(&self) -> &Aabb {
+        &self.bounds
+    }
+
+    #[inline]
+    pub fn morph_targets(&self) -> Option<&MorphTargets> {
+        self.morph_targets.as_ref()
+    }
+
+    // Empty unless the model was imported with lightmap_coords
+    #[inline]
+    pub fn lightmap_coords(&self) -> &[Point2<f32>] {
+        &self.lightmap_coords
+    }
+
+    #[inline]
+    pub fn submeshes(&self) -> &[Submesh] {
+        &self.submeshes
+    }
+
+    pub fn submesh_index(&self, name: &str) -> Option<usize> {
+        self.submeshes
+            .iter()
+            .position(|submesh| submesh.name == name)
+    }
+
+    pub fn triangles(&self) -> impl Iterator<Item = [Point3<f32>; 3]> + '_ {
+        self.positions.chunks_exact(3).map(|t| [t[0], t[1], t[2]])
+    }
+
+    pub fn triangle_count(&self) -> usize {
+        self.positions.len() / 3
+    }
+
+    fn from_parts(
+        (data, indices, positions): ModelData,
+        submeshes: Vec<Submesh>,
+        material_template: Arc<dyn MaterialTemplate>,
+    ) -> Self {
+        let bounds = Aabb::from_points(&positions)
+            .unwrap_or_else(|| Aabb::new(Point3::origin(), Point3::origin()));
+
+        let memory = GpuAllocation::new(
+            AllocationCategory::Mesh,
+            data.len() * std::mem::size_of::<Vertex>() as u64
+                + indices.len() * std::mem::size_of::<u32>() as u64,
+        );
+
+        Self {
+            data,
+            indices,
+            positions,
+            bounds,
+            submeshes,
+            morph_targets: None,
+            lightmap_coords: vec![],
+            material_template,
+            _memory: memory,
+        }
+    }
+
+    // The buffers can be used right away, the copies are submitted with the next frame
+    fn upload(uploads: &Mutex<UploadQueue>, mesh: &MeshData) -> Result<ModelData, Error> {
+        let _span = tracing::info_span!("upload_model", vertices = mesh.vertices.len()).entered();
+        let positions = mesh
+            .indices
+            .iter()
+            .map(|&i| mesh.vertices[i as usize].v_position)
+            .collect();
+        stats::record_upload((mesh.vertices.len() * std::mem::size_of::<Vertex>()) as u64);
+        stats::record_upload((mesh.indices.len() * std::mem::size_of::<u32>()) as u64);
+        let mut uploads = uploads.lock().recover();
+        let buffer = uploads.upload_buffer(&mesh.vertices, BufferUsage::vertex_buffer())?;
+        let indices = uploads.upload_buffer(&mesh.indices, BufferUsage::index_buffer())?;
+
+        Ok((buffer, indices, positions))
+    }
+
+    fn process(mesh: &mut MeshData, options: &ModelLoadOptions, tangents: bool) {
+        let _span = tracing::info_span!("process_model", vertices = mesh.vertices.len()).entered();
+        if options.weld {
+            mesh.weld();
+        }
+        if options.generate_normals && !mesh.has_normals() {
+            mesh.generate_normals();
+        }
+        if tangents {
+            mesh.generate_tangents();
+        }
+        if let Some(target) = options.simplify_to {
+            let before = mesh.triangle_count();
+            mesh.simplify(target);
+            log::debug!(
+                "Simplified {} -> {} triangles",
+                before,
+                mesh.triangle_count()
+            );
+        }
+        if options.optimize {
+            mesh.optimize_vertex_cache();
+            mesh.optimize_vertex_fetch();
+        }
+    }
+
+    // One mesh per material (usemtl) in the file, in the order they first appear
+    fn load_obj<P: AsRef<Path>>(path: P) -> Result<Vec<(String, MeshData)>, Error> {
+        let path = path.as_ref();
+        let input = BufReader::new(File::open(path).map_err(|err| Error::file(path, err))?);
+        let obj = obj::raw::parse_obj(input).map_err(|err| Error::asset_parse(path, err))?;
+
+        let mut assigned = vec![false; obj.polygons.len()];
+        let mut parts: Vec<(String, Vec<usize>)> = vec![];
+        for (name, group) in &obj.meshes {
+            let polygons: Vec<usize> = group
+                .polygons
+                .iter()
+                .flat_map(|range| range.start..range.end)
+                .filter(|&polygon| polygon < assigned.len() && !assigned[polygon])
+                .collect();
+            for &polygon in &polygons {
+                assigned[polygon] = true;
+            }
+            if !polygons.is_empty() {
+                parts.push((name.clone(), polygons));
             }
-        });
+        }
+        let rest: Vec<usize> = (0..assigned.len())
+            .filter(|&polygon| !assigned[polygon])
+            .collect();
+        if !rest.is_empty() {
+            parts.push((String::new(), rest));
+        }
+        parts.sort_by_key(|(_, polygons)| polygons[0]);
 
-        let (buffer, init) =
-            ImmutableBuffer::from_iter(vertices, BufferUsage::vertex_buffer(), gfx_queue)?;
+        Ok(parts
+            .into_iter()
+            .map(|(name, polygons)| (name, obj_mesh(&obj, &polygons)))
+            .collect())
+    }
+}
 
-        init.then_signal_fence_and_flush()?.wait(None).unwrap();
+impl Submesh {
+    fn whole(mesh: &MeshData) -> Self {
+        Self {
+            name: String::new(),
+            first_index: 0,
+            index_count: mesh.indices.len() as u32,
+        }
+    }
+}
+
+// Corners sharing position, texture coordinate and normal indices become one vertex, polygons
+// are split into triangle fans
+fn obj_mesh(obj: &RawObj, polygons: &[usize]) -> MeshData {
+    let mut mesh = MeshData::default();
+    let mut lookup: HashMap<(usize, Option<usize>, Option<usize>), u32> = HashMap::new();
 
-        Ok(buffer)
+    for &polygon in polygons {
+        let corners: Vec<(usize, Option<usize>, Option<usize>)> = match &obj.polygons[polygon] {
+            Polygon::P(corners) => corners.iter().map(|&p| (p, None, None)).collect(),
+            Polygon::PT(corners) => corners.iter().map(|&(p, t)| (p, Some(t), None)).collect(),
+            Polygon::PN(corners) => corners.iter().map(|&(p, n)| (p, None, Some(n))).collect(),
+            Polygon::PTN(corners) => corners
+                .iter()
+                .map(|&(p, t, n)| (p, Some(t), Some(n)))
+                .collect(),
+        };
+
+        let indices: Vec<u32> = corners
+            .into_iter()
+            .map(|corner @ (p, t, n)| {
+                *lookup.entry(corner).or_insert_with(|| {
+                    let (x, y, z, _) = obj.positions.get(p).copied().unwrap_or_default();
+                    let (u, v, _) = t
+                        .and_then(|t| obj.tex_coords.get(t).copied())
+                        .unwrap_or_default();
+                    let (nx, ny, nz) = n
+                        .and_then(|n| obj.normals.get(n).copied())
+                        .unwrap_or_default();
+                    mesh.vertices.push(Vertex {
+                        v_position: Point3::new(x, y, z),
+                        v_normal: Vector3::new(nx, ny, nz),
+                        v_tex_coord: Point2::new(u, v),
+                        v_tangent: Vector4::zeros(),
+                        v_lightmap_coord: Point2::origin(),
+                    });
+                    mesh.vertices.len() as u32 - 1
+                })
+            })
+            .collect();
+
+        for i in 1..indices.len().saturating_sub(1) {
+            mesh.indices
+                .extend_from_slice(&[indices[0], indices[i], indices[i + 1]]);
+        }
     }
+
+    mesh
 }
 
 impl ModelRegistry {
-    pub fn new(gfx_queue: Arc<Queue>) -> Self {
-        Self {
-            gfx_queue,
+    pub fn new(uploads: Arc<Mutex<UploadQueue>>) -> Result<Self, Error> {
+        let device = uploads.lock().recover().queue().device().clone();
+        Ok(Self {
+            uploads,
+            model_arena: UniformArena::new(device)?,
+            #[cfg(feature = "physics")]
+            collision_meshes: HashMap::new(),
+            #[cfg(feature = "physics")]
+            collision_cache_dir: CollisionMesh::default_cache_dir(),
             data: BTreeMap::new(),
-        }
+            load_options: ModelLoadOptions::default(),
+        })
+    }
+
+    #[inline]
+    pub const fn uploads(&self) -> &Arc<Mutex<UploadQueue>> {
+        &self.uploads
+    }
+
+    #[inline]
+    pub const fn model_arena(&self) -> &UniformArena<ModelUniform> {
+        &self.model_arena
+    }
+
+    #[inline]
+    pub const fn load_options(&self) -> &ModelLoadOptions {
+        &self.load_options
+    }
+
+    // Shared by the entities using the model, built once per model and launch at most
+    #[cfg(feature = "physics")]
+    pub fn collision_mesh(&mut self, model: &Model) -> Arc<CollisionMesh> {
+        let hash = crate::world::collision_mesh::model_hash(model);
+        let cache_dir = &self.collision_cache_dir;
+        self.collision_meshes
+            .entry(hash)
+            .or_insert_with(|| Arc::new(CollisionMesh::load_or_build(model, cache_dir)))
+            .clone()
+    }
+
+    // Applies to the models loaded afterwards, already loaded ones are kept as they are
+    pub fn set_load_options(&mut self, options: ModelLoadOptions) {
+        self.load_options = options;
     }
 
     pub fn create_mesh_object(
@@ -112,7 +524,8 @@ impl ModelRegistry {
     ) -> Result<MeshObject, Error> {
         let model = self.get_or_load(name, material_template.clone())?;
         let mesh = MeshObject::new(
-            self.gfx_queue.clone(),
+            self.uploads.clone(),
+            &self.model_arena,
             model,
             material_template,
             material_create_info,
@@ -130,20 +543,72 @@ impl ModelRegistry {
             // TODO check material ID
             Ok(model.clone())
         } else {
-            log::info!("Loading model {:?}", name);
-
             let filename = name.to_owned() + ".obj";
             let mut path = PathBuf::from("res/models/");
             path.push(filename);
 
-            let data = Arc::new(Model::load_to_device(
-                self.gfx_queue.clone(),
+            match self.load_from_path(name, path, material_template.clone()) {
+                Err(err) if err.is_missing_asset() => {
+                    log::warn!(
+                        "Using a placeholder for model {:?}: {}",
+                        name,
+                        err.full_message()
+                    );
+                    let model = Arc::new(Model::placeholder(&self.uploads, material_template)?);
+                    self.data.insert(name.to_owned(), model.clone());
+                    Ok(model)
+                }
+                result => result,
+            }
+        }
+    }
+
+    // Loads (or reloads) a model from outside of res/models, registering it under the name
+    pub fn load_from_path<P: AsRef<Path>>(
+        &mut self,
+        name: &str,
+        path: P,
+        material_template: Arc<dyn MaterialTemplate>,
+    ) -> Result<Arc<Model>, Error> {
+        self.load_with_import(name, path, material_template, None)
+    }
+
+    // Same as load_from_path(), with import settings replacing the ones in the .meta file
+    pub fn load_from_path_with_import<P: AsRef<Path>>(
+        &mut self,
+        name: &str,
+        path: P,
+        material_template: Arc<dyn MaterialTemplate>,
+        import: &ModelMeta,
+    ) -> Result<Arc<Model>, Error> {
+        self.load_with_import(name, path, material_template, Some(import))
+    }
+
+    fn load_with_import<P: AsRef<Path>>(
+        &mut self,
+        name: &str,
+        path: P,
+        material_template: Arc<dyn MaterialTemplate>,
+        import: Option<&ModelMeta>,
+    ) -> Result<Arc<Model>, Error> {
+        log::info!("Loading model {:?} from {:?}", name, path.as_ref());
+
+        let data = Arc::new(
+            Model::load_to_device(
+                &self.uploads,
                 path,
                 material_template,
-            )?);
+                &self.load_options,
+                import,
+            )
+            .map_err(|err| err.context(ResourceKind::Model, name))?,
+        );
 
-            self.data.insert(name.to_owned(), data.clone());
-            Ok(data)
-        }
+        self.data.insert(name.to_owned(), data.clone());
+        Ok(data)
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.data.keys().map(String::as_str)
     }
 }
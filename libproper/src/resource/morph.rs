@@ -0,0 +1,91 @@
+use nalgebra::Vector3;
+
+use crate::render::Vertex;
+
+// Offsets from the base shape, one per vertex of the model
+#[derive(Clone, Debug)]
+pub struct MorphTarget {
+    pub name: String,
+    pub position_deltas: Vec<Vector3<f32>>,
+    pub normal_deltas: Vec<Vector3<f32>>,
+}
+
+// Blend shapes of a model. Blending is done on the CPU into a vertex buffer of the entity,
+// so only the entities whose weights changed cost anything
+#[derive(Clone, Debug)]
+pub struct MorphTargets {
+    base: Vec<Vertex>,
+    targets: Vec<MorphTarget>,
+}
+
+impl MorphTarget {
+    // Both meshes have to list the same vertices in the same order
+    pub fn from_shapes(name: &str, base: &[Vertex], shape: &[Vertex]) -> Self {
+        let (position_deltas, normal_deltas) = base
+            .iter()
+            .zip(shape)
+            .map(|(base, shape)| {
+                (
+                    shape.v_position - base.v_position,
+                    shape.v_normal - base.v_normal,
+                )
+            })
+            .unzip();
+
+        Self {
+            name: name.to_owned(),
+            position_deltas,
+            normal_deltas,
+        }
+    }
+}
+
+impl MorphTargets {
+    pub fn new(base: Vec<Vertex>, targets: Vec<MorphTarget>) -> Self {
+        Self { base, targets }
+    }
+
+    #[inline]
+    pub fn base(&self) -> &[Vertex] {
+        &self.base
+    }
+
+    #[inline]
+    pub fn targets(&self) -> &[MorphTarget] {
+        &self.targets
+    }
+
+    pub fn index(&self, name: &str) -> Option<usize> {
+        self.targets.iter().position(|target| target.name == name)
+    }
+
+    // Weights are indexed like the targets, missing ones are 0
+    pub fn blend(&self, weights: &[f32], output: &mut [Vertex]) {
+        output.copy_from_slice(&self.base);
+
+        let mut any = false;
+        for (target, &weight) in self.targets.iter().zip(weights) {
+            if weight == 0.0 {
+                continue;
+            }
+            any = true;
+            for ((vertex, position), normal) in output
+                .iter_mut()
+                .zip(&target.position_deltas)
+                .zip(&target.normal_deltas)
+            {
+                vertex.v_position += position * weight;
+                vertex.v_normal += normal * weight;
+            }
+        }
+
+        if any {
+            for vertex in output {
+                vertex.v_normal = vertex
+                    .v_normal
+                    .try_normalize(f32::EPSILON)
+                    .unwrap_or(vertex.v_normal);
+            }
+        }
+    }
+}
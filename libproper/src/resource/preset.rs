@@ -0,0 +1,131 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use serde::Deserialize;
+
+use crate::error::{Error, ResourceKind};
+
+use super::{material::MaterialInstanceCreateInfo, texture::TextureRegistry};
+
+const PRESET_DIRECTORY: &str = "res/materials";
+
+// Material instance parameters kept in res/materials/<name>.toml, so looks can be tuned
+// without touching code:
+//
+//     [colors]
+//     diffuse_color = [1.0, 0.5, 0.0, 1.0]
+//     [textures]
+//     diffuse_map = "texture0"
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct MaterialPreset {
+    pub colors: BTreeMap<String, [f32; 4]>,
+    pub scalars: BTreeMap<String, f32>,
+    // Texture names, loaded through the texture registry
+    pub textures: BTreeMap<String, String>,
+}
+
+struct PresetFile {
+    preset: MaterialPreset,
+    path: PathBuf,
+    modified: Option<SystemTime>,
+}
+
+// Loaded presets along with the modification times of their files
+#[derive(Default)]
+pub struct PresetLibrary {
+    data: BTreeMap<String, PresetFile>,
+}
+
+impl MaterialPreset {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path).map_err(|err| Error::file(path, err))?;
+        toml::from_str(&text).map_err(|err| Error::asset_parse(path, err))
+    }
+
+    pub fn create_info(
+        &self,
+        textures: &mut TextureRegistry,
+    ) -> Result<MaterialInstanceCreateInfo, Error> {
+        let mut create_info = MaterialInstanceCreateInfo::default();
+        for (name, &color) in &self.colors {
+            create_info.set_color(name, color);
+        }
+        for (name, &value) in &self.scalars {
+            create_info.set_scalar(name, value);
+        }
+        for (name, texture) in &self.textures {
+            create_info = create_info.with_texture(name, textures.get_or_load(texture)?);
+        }
+        Ok(create_info)
+    }
+}
+
+impl PresetLibrary {
+    pub fn get_or_load(&mut self, name: &str) -> Result<&MaterialPreset, Error> {
+        if !self.data.contains_key(name) {
+            let mut path = PathBuf::from(PRESET_DIRECTORY);
+            path.push(format!("{}.toml", name));
+
+            log::info!("Loading material preset {:?}", name);
+            let modified = modified_time(&path);
+            let preset = MaterialPreset::load(&path)
+                .map_err(|err| err.context(ResourceKind::Material, name))?;
+            self.data.insert(
+                name.to_owned(),
+                PresetFile {
+                    preset,
+                    path,
+                    modified,
+                },
+            );
+        }
+
+        Ok(&self.data[name].preset)
+    }
+
+    #[inline]
+    pub fn get(&self, name: &str) -> Option<&MaterialPreset> {
+        self.data.get(name).map(|file| &file.preset)
+    }
+
+    // Re-reads the presets whose files changed since they were loaded and returns their
+    // names. Files which fail to parse keep the old values until they're fixed
+    pub fn reload_changed(&mut self) -> Vec<String> {
+        let mut changed = vec![];
+        for (name, file) in &mut self.data {
+            let modified = modified_time(&file.path);
+            if modified == file.modified {
+                continue;
+            }
+            file.modified = modified;
+
+            match MaterialPreset::load(&file.path) {
+                Ok(preset) if preset == file.preset => (),
+                Ok(preset) => {
+                    log::info!("Reloaded material preset {:?}", name);
+                    file.preset = preset;
+                    changed.push(name.clone());
+                }
+                Err(err) => {
+                    log::warn!(
+                        "Failed to reload material preset {:?}: {}",
+                        name,
+                        err.full_message()
+                    );
+                }
+            }
+        }
+        changed
+    }
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
@@ -0,0 +1,134 @@
+use std::{collections::BTreeMap, sync::Arc};
+
+use serde::Deserialize;
+use vulkano::{
+    device::Device,
+    sampler::{
+        Filter, Sampler, SamplerAddressMode, SamplerCreateInfo, SamplerMipmapMode, LOD_CLAMP_NONE,
+    },
+};
+
+use crate::error::Error;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TextureFilter {
+    Nearest,
+    Linear,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TextureWrap {
+    Repeat,
+    Mirror,
+    Clamp,
+}
+
+// How a texture is sampled, the [sampler] table of the texture's metadata
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct SamplerDesc {
+    pub filter: TextureFilter,
+    pub wrap: TextureWrap,
+    // Maximum anisotropy, ignored if the device doesn't support it
+    pub anisotropy: Option<f32>,
+    pub mip_bias: f32,
+}
+
+// Float fields are compared by their bit patterns
+type SamplerKey = (TextureFilter, TextureWrap, Option<u32>, u32);
+
+// Samplers are shared between all the textures with equal descriptions
+pub struct SamplerCache {
+    device: Arc<Device>,
+    samplers: BTreeMap<SamplerKey, Arc<Sampler>>,
+}
+
+impl Default for SamplerDesc {
+    fn default() -> Self {
+        Self {
+            filter: TextureFilter::Linear,
+            wrap: TextureWrap::Repeat,
+            anisotropy: None,
+            mip_bias: 0.0,
+        }
+    }
+}
+
+impl SamplerDesc {
+    fn key(&self) -> SamplerKey {
+        (
+            self.filter,
+            self.wrap,
+            self.anisotropy.map(f32::to_bits),
+            self.mip_bias.to_bits(),
+        )
+    }
+}
+
+impl From<TextureFilter> for Filter {
+    fn from(filter: TextureFilter) -> Self {
+        match filter {
+            TextureFilter::Nearest => Self::Nearest,
+            TextureFilter::Linear => Self::Linear,
+        }
+    }
+}
+
+impl From<TextureWrap> for SamplerAddressMode {
+    fn from(wrap: TextureWrap) -> Self {
+        match wrap {
+            TextureWrap::Repeat => Self::Repeat,
+            TextureWrap::Mirror => Self::MirroredRepeat,
+            TextureWrap::Clamp => Self::ClampToEdge,
+        }
+    }
+}
+
+impl SamplerCache {
+    pub fn new(device: Arc<Device>) -> Self {
+        Self {
+            device,
+            samplers: BTreeMap::new(),
+        }
+    }
+
+    pub fn get(&mut self, desc: &SamplerDesc) -> Result<Arc<Sampler>, Error> {
+        if let Some(sampler) = self.samplers.get(&desc.key()) {
+            return Ok(sampler.clone());
+        }
+
+        let anisotropy = desc.anisotropy.filter(|_| {
+            let supported = self.device.enabled_features().sampler_anisotropy;
+            if !supported {
+                log::warn!("Anisotropic filtering is not supported, ignoring it");
+            }
+            supported
+        });
+        let max_anisotropy = self
+            .device
+            .physical_device()
+            .properties()
+            .max_sampler_anisotropy;
+        let sampler = Sampler::new(
+            self.device.clone(),
+            SamplerCreateInfo {
+                min_filter: desc.filter.into(),
+                mag_filter: desc.filter.into(),
+                mipmap_mode: match desc.filter {
+                    TextureFilter::Nearest => SamplerMipmapMode::Nearest,
+                    TextureFilter::Linear => SamplerMipmapMode::Linear,
+                },
+                address_mode: [desc.wrap.into(); 3],
+                mip_lod_bias: desc.mip_bias,
+                lod: 0.0..=LOD_CLAMP_NONE,
+                anisotropy: anisotropy.map(|value| value.clamp(1.0, max_anisotropy)),
+                ..Default::default()
+            },
+        )?;
+
+        self.samplers.insert(desc.key(), sampler.clone());
+        Ok(sampler)
+    }
+}
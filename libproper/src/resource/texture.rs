@@ -1,103 +1,369 @@
 use std::{
     collections::BTreeMap,
     path::{Path, PathBuf},
-    sync::Arc,
+    sync::{Arc, Mutex},
 };
 
 use vulkano::{
-    device::Queue,
+    command_buffer::{
+        AutoCommandBufferBuilder, CommandBufferUsage, CopyBufferToImageInfo, PrimaryCommandBuffer,
+    },
     format::Format,
-    image::{view::ImageView, ImageDimensions, ImmutableImage, MipmapsCount},
-    sampler::{Filter, Sampler, SamplerAddressMode, SamplerCreateInfo},
-    sync::GpuFuture,
+    image::{
+        view::{ImageView, ImageViewAbstract},
+        ImageAccess, ImageCreateFlags, ImageDimensions, ImageUsage, ImmutableImage, MipmapsCount,
+        StorageImage,
+    },
+    sampler::Sampler,
 };
 
-use crate::error::Error;
+use crate::{
+    error::{Error, ResourceKind},
+    lock::Recover,
+    render::{
+        memory::{AllocationCategory, GpuAllocation},
+        stats,
+        upload::{UploadId, UploadQueue},
+    },
+};
+
+use super::{
+    meta::{self, MipPolicy, TextureMeta},
+    sampler::{SamplerCache, SamplerDesc, TextureFilter},
+};
 
 #[derive(Clone)]
 pub struct SampledTexture {
     sampler: Arc<Sampler>,
-    image: Arc<ImageView<ImmutableImage>>,
+    sampler_desc: SamplerDesc,
+    image: Arc<dyn ImageViewAbstract>,
+    _memory: Arc<GpuAllocation>,
+}
+
+// Keeps the image a registered texture samples from, so its contents can be replaced
+pub struct DynamicTexture {
+    uploads: Arc<Mutex<UploadQueue>>,
+    texture: Arc<SampledTexture>,
+    image: Arc<StorageImage>,
 }
 
 pub struct TextureRegistry {
-    gfx_queue: Arc<Queue>,
-    sampler: Arc<Sampler>,
+    uploads: Arc<Mutex<UploadQueue>>,
+    samplers: SamplerCache,
     data: BTreeMap<String, Arc<SampledTexture>>,
+    placeholder: Arc<SampledTexture>,
+    // Names of the images in TEXTURE_DIRECTORY as of the last refresh_available()
+    directory_listing: Vec<String>,
 }
 
+const TEXTURE_DIRECTORY: &str = "res/textures";
+const PLACEHOLDER_NAME: &str = "placeholder";
+const PLACEHOLDER_SIZE: u32 = 16;
+const PLACEHOLDER_CELL: u32 = 4;
+
 impl TextureRegistry {
-    pub fn new(gfx_queue: Arc<Queue>) -> Result<Self, Error> {
-        let sampler = Sampler::new(
-            gfx_queue.device().clone(),
-            SamplerCreateInfo {
-                min_filter: Filter::Linear,
-                mag_filter: Filter::Linear,
-                address_mode: [SamplerAddressMode::Repeat; 3],
-                ..Default::default()
-            },
-        )
-        .unwrap();
+    pub fn new(uploads: Arc<Mutex<UploadQueue>>) -> Result<Self, Error> {
+        let device = uploads.lock().recover().queue().device().clone();
+        let mut samplers = SamplerCache::new(device);
+        let placeholder_sampler = SamplerDesc {
+            filter: TextureFilter::Nearest,
+            ..Default::default()
+        };
+        let placeholder = Arc::new(SampledTexture {
+            sampler: samplers.get(&placeholder_sampler)?,
+            sampler_desc: placeholder_sampler,
+            image: Self::create_placeholder(&uploads)?,
+            _memory: Arc::new(GpuAllocation::new(
+                AllocationCategory::Texture,
+                (PLACEHOLDER_SIZE * PLACEHOLDER_SIZE * 4) as u64,
+            )),
+        });
+
+        let mut data = BTreeMap::new();
+        data.insert(PLACEHOLDER_NAME.to_owned(), placeholder.clone());
 
         Ok(Self {
-            gfx_queue,
-            sampler,
-            data: BTreeMap::new(),
+            uploads,
+            samplers,
+            data,
+            placeholder,
+            directory_listing: list_directory(),
         })
     }
 
+    // Magenta checkerboard, used in place of missing or broken images
+    #[inline]
+    pub const fn placeholder(&self) -> &Arc<SampledTexture> {
+        &self.placeholder
+    }
+
     pub fn get_or_load(&mut self, name: &str) -> Result<Arc<SampledTexture>, Error> {
         if let Some(texture) = self.data.get(name) {
             Ok(texture.clone())
         } else {
-            log::info!("Loading texture {:?}", name);
             let filename = name.to_owned() + ".png";
-            let mut path = PathBuf::from("res/textures");
+            let mut path = PathBuf::from(TEXTURE_DIRECTORY);
             path.push(filename);
 
-            let image = self.load_image(path);
-            let texture = Arc::new(SampledTexture {
-                sampler: self.sampler.clone(),
-                image,
-            });
-
-            self.data.insert(name.to_owned(), texture.clone());
+            match self.load_from_path(name, path) {
+                Err(err) if err.is_missing_asset() => {
+                    log::warn!(
+                        "Using a placeholder for texture {:?}: {}",
+                        name,
+                        err.full_message()
+                    );
+                    self.data.insert(name.to_owned(), self.placeholder.clone());
+                    Ok(self.placeholder.clone())
+                }
+                result => result,
+            }
+        }
+    }
 
+    // Overrides the sampler from the texture's metadata
+    pub fn get_or_load_with_sampler(
+        &mut self,
+        name: &str,
+        sampler: &SamplerDesc,
+    ) -> Result<Arc<SampledTexture>, Error> {
+        let texture = self.get_or_load(name)?;
+        if texture.sampler_desc == *sampler {
             Ok(texture)
+        } else {
+            self.set_sampler(name, sampler)
         }
     }
 
-    fn load_image<P: AsRef<Path>>(&self, path: P) -> Arc<ImageView<ImmutableImage>> {
-        let image = image::open(path).unwrap();
-        let width = image.width();
-        let height = image.height();
-        let data = image.into_rgba8();
+    // Textures sample with the same image afterwards, the materials already created keep the
+    // old sampler
+    pub fn set_sampler(
+        &mut self,
+        name: &str,
+        sampler: &SamplerDesc,
+    ) -> Result<Arc<SampledTexture>, Error> {
+        let texture = self
+            .data
+            .get(name)
+            .ok_or_else(|| Error::UnknownTexture(name.to_owned()))?;
+        let texture = Arc::new(SampledTexture {
+            sampler: self.samplers.get(sampler)?,
+            sampler_desc: *sampler,
+            ..SampledTexture::clone(texture)
+        });
+
+        self.data.insert(name.to_owned(), texture.clone());
+        Ok(texture)
+    }
 
-        let (texture, init) = ImmutableImage::from_iter(
-            data.into_raw(),
+    // Loads (or reloads) an image from outside of res/textures, registering it under the name
+    pub fn load_from_path<P: AsRef<Path>>(
+        &mut self,
+        name: &str,
+        path: P,
+    ) -> Result<Arc<SampledTexture>, Error> {
+        log::info!("Loading texture {:?} from {:?}", name, path.as_ref());
+
+        let (image, bytes, sampler) = self
+            .load_image(path)
+            .map_err(|err| err.context(ResourceKind::Texture, name))?;
+
+        self.register(name, image, bytes, &sampler)
+    }
+
+    // Immutable texture from pixels in memory, rows go top to bottom
+    pub fn create_from_data(
+        &mut self,
+        name: &str,
+        width: u32,
+        height: u32,
+        format: Format,
+        data: &[u8],
+    ) -> Result<Arc<SampledTexture>, Error> {
+        let image = self
+            .upload(width, height, format, MipmapsCount::One, data.to_vec())
+            .map_err(|err| err.context(ResourceKind::Texture, name))?;
+
+        self.register(name, image, data.len() as u64, &SamplerDesc::default())
+    }
+
+    // Texture which can be updated after creation, e.g. for video frames or procedural data
+    pub fn create_dynamic(
+        &mut self,
+        name: &str,
+        width: u32,
+        height: u32,
+        format: Format,
+        data: &[u8],
+    ) -> Result<DynamicTexture, Error> {
+        let queue = self.uploads.lock().recover().queue().clone();
+        let image = StorageImage::with_usage(
+            queue.device().clone(),
             ImageDimensions::Dim2d {
                 width,
                 height,
                 array_layers: 1,
             },
-            MipmapsCount::One,
-            Format::R8G8B8A8_UNORM,
-            self.gfx_queue.clone(),
+            format,
+            ImageUsage {
+                sampled: true,
+                transfer_dst: true,
+                ..ImageUsage::none()
+            },
+            ImageCreateFlags::none(),
+            [queue.family()],
         )
-        .unwrap();
+        .map_err(|err| Error::from(err).context(ResourceKind::Texture, name))?;
+
+        let texture = DynamicTexture {
+            uploads: self.uploads.clone(),
+            texture: self.register(
+                name,
+                ImageView::new_default(image.clone())?,
+                data.len() as u64,
+                &SamplerDesc::default(),
+            )?,
+            image,
+        };
+        texture
+            .update(data)
+            .map_err(|err| err.context(ResourceKind::Texture, name))?;
+
+        Ok(texture)
+    }
+
+    #[inline]
+    pub fn get(&self, name: &str) -> Option<&Arc<SampledTexture>> {
+        self.data.get(name)
+    }
+
+    // Names of loaded textures and the ones which can be loaded from res/textures. The
+    // directory isn't read here, see refresh_available()
+    pub fn available(&self) -> Vec<String> {
+        let mut names = self
+            .data
+            .keys()
+            .chain(self.directory_listing.iter())
+            .cloned()
+            .collect::<Vec<_>>();
+        names.sort();
+        names.dedup();
+        names
+    }
+
+    // Picks up images added to or removed from res/textures since the registry was created
+    pub fn refresh_available(&mut self) {
+        self.directory_listing = list_directory();
+    }
+
+    pub fn name_of(&self, texture: &Arc<SampledTexture>) -> Option<&str> {
+        self.data
+            .iter()
+            .find(|(_, t)| Arc::ptr_eq(t, texture))
+            .map(|(name, _)| name.as_str())
+    }
+
+    fn create_placeholder(
+        uploads: &Mutex<UploadQueue>,
+    ) -> Result<Arc<ImageView<ImmutableImage>>, Error> {
+        let data = (0..PLACEHOLDER_SIZE * PLACEHOLDER_SIZE)
+            .flat_map(|i| {
+                let (x, y) = (i % PLACEHOLDER_SIZE, i / PLACEHOLDER_SIZE);
+                if (x / PLACEHOLDER_CELL + y / PLACEHOLDER_CELL) % 2 == 0 {
+                    [255, 0, 255, 255]
+                } else {
+                    [0, 0, 0, 255]
+                }
+            })
+            .collect::<Vec<u8>>();
+
+        let image = uploads.lock().recover().upload_image(
+            &data,
+            ImageDimensions::Dim2d {
+                width: PLACEHOLDER_SIZE,
+                height: PLACEHOLDER_SIZE,
+                array_layers: 1,
+            },
+            MipmapsCount::One,
+            Format::R8G8B8A8_SRGB,
+        )?;
+
+        Ok(ImageView::new_default(image)?)
+    }
+
+    // Returns the image, its size in bytes and how it's sampled
+    fn load_image<P: AsRef<Path>>(
+        &self,
+        path: P,
+    ) -> Result<(Arc<ImageView<ImmutableImage>>, u64, SamplerDesc), Error> {
+        let path = path.as_ref();
+        let meta: TextureMeta = meta::load(path)?;
+        let image = image::open(path).map_err(|err| match err {
+            image::ImageError::IoError(err) => Error::file(path, err),
+            err => Error::asset_parse(path, err),
+        })?;
+        let data = image.into_rgba8();
+        let (width, height) = data.dimensions();
+        let mipmaps = match meta.mipmaps {
+            MipPolicy::None => MipmapsCount::One,
+            MipPolicy::Generate => MipmapsCount::Log2,
+        };
+        // The whole chain takes a third more than the base level
+        let bytes = match mipmaps {
+            MipmapsCount::One => data.len() as u64,
+            _ => data.len() as u64 * 4 / 3,
+        };
+        let image = self.upload(width, height, meta.format(), mipmaps, data.into_raw())?;
+        Ok((image, bytes, meta.sampler))
+    }
+
+    fn upload(
+        &self,
+        width: u32,
+        height: u32,
+        format: Format,
+        mipmaps: MipmapsCount,
+        data: Vec<u8>,
+    ) -> Result<Arc<ImageView<ImmutableImage>>, Error> {
+        let _span = tracing::info_span!("upload_texture", width, height).entered();
+        check_data_size(width, height, format, data.len())?;
+        stats::record_upload(data.len() as u64);
+
+        let texture = self.uploads.lock().recover().upload_image(
+            &data,
+            ImageDimensions::Dim2d {
+                width,
+                height,
+                array_layers: 1,
+            },
+            mipmaps,
+            format,
+        )?;
+
+        Ok(ImageView::new_default(texture)?)
+    }
 
-        init.then_signal_fence_and_flush()
-            .unwrap()
-            .wait(None)
-            .unwrap();
+    fn register(
+        &mut self,
+        name: &str,
+        image: Arc<dyn ImageViewAbstract>,
+        bytes: u64,
+        sampler: &SamplerDesc,
+    ) -> Result<Arc<SampledTexture>, Error> {
+        let texture = Arc::new(SampledTexture {
+            sampler: self.samplers.get(sampler)?,
+            sampler_desc: *sampler,
+            image,
+            _memory: Arc::new(GpuAllocation::new(AllocationCategory::Texture, bytes)),
+        });
 
-        ImageView::new_default(texture).unwrap()
+        self.data.insert(name.to_owned(), texture.clone());
+        Ok(texture)
     }
 }
 
 impl SampledTexture {
     #[inline]
-    pub const fn image(&self) -> &Arc<ImageView<ImmutableImage>> {
+    pub const fn image(&self) -> &Arc<dyn ImageViewAbstract> {
         &self.image
     }
 
@@ -105,4 +371,66 @@ impl SampledTexture {
     pub const fn sampler(&self) -> &Arc<Sampler> {
         &self.sampler
     }
+
+    #[inline]
+    pub const fn sampler_desc(&self) -> &SamplerDesc {
+        &self.sampler_desc
+    }
+
+    pub fn dimensions(&self) -> [u32; 2] {
+        self.image.image().dimensions().width_height()
+    }
+}
+
+impl DynamicTexture {
+    #[inline]
+    pub const fn texture(&self) -> &Arc<SampledTexture> {
+        &self.texture
+    }
+
+    // The data has to have the size and format the texture was created with. The copy is
+    // submitted with the next frame, same as other uploads
+    pub fn update(&self, data: &[u8]) -> Result<UploadId, Error> {
+        let [width, height] = self.image.dimensions().width_height();
+        let _span = tracing::info_span!("update_texture", width, height).entered();
+        check_data_size(width, height, self.image.format(), data.len())?;
+        stats::record_upload(data.len() as u64);
+
+        let mut uploads = self.uploads.lock().recover();
+        let queue = uploads.queue().clone();
+        let staging = uploads.stage(data)?;
+
+        let mut builder = AutoCommandBufferBuilder::primary(
+            queue.device().clone(),
+            queue.family(),
+            CommandBufferUsage::OneTimeSubmit,
+        )?;
+        builder.copy_buffer_to_image(CopyBufferToImageInfo::buffer_image(
+            staging,
+            self.image.clone(),
+        ))?;
+        let init = builder.build()?.execute(queue)?;
+
+        Ok(uploads.push(init))
+    }
+}
+
+fn check_data_size(width: u32, height: u32, format: Format, actual: usize) -> Result<(), Error> {
+    let expected = width as u64 * height as u64 * format.block_size().unwrap_or(0);
+    if expected != actual as u64 {
+        return Err(Error::TextureDataSize { expected, actual });
+    }
+    Ok(())
+}
+
+fn list_directory() -> Vec<String> {
+    let dir = match std::fs::read_dir(TEXTURE_DIRECTORY) {
+        Ok(dir) => dir,
+        Err(_) => return vec![],
+    };
+    dir.flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().map_or(false, |ext| ext == "png"))
+        .filter_map(|path| path.file_stem().and_then(|s| s.to_str()).map(str::to_owned))
+        .collect()
 }
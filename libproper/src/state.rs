@@ -0,0 +1,64 @@
+use crate::event::GameEvent;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GameState {
+    Loading,
+    MainMenu,
+    Playing,
+    Paused,
+}
+
+// Managed by the Application above the layer stack, driven by GameEvents
+pub struct GameStateStack {
+    stack: Vec<GameState>,
+}
+
+impl GameState {
+    // Simulation layers don't tick in these states
+    pub const fn is_frozen(self) -> bool {
+        !matches!(self, Self::Playing)
+    }
+
+    pub const fn has_menu(self) -> bool {
+        matches!(self, Self::MainMenu | Self::Paused)
+    }
+}
+
+impl Default for GameStateStack {
+    fn default() -> Self {
+        Self::new(GameState::Playing)
+    }
+}
+
+impl GameStateStack {
+    pub fn new(initial: GameState) -> Self {
+        Self {
+            stack: vec![initial],
+        }
+    }
+
+    #[inline]
+    pub fn current(&self) -> GameState {
+        *self.stack.last().unwrap()
+    }
+
+    // Returns the new state if the event caused a transition
+    pub fn handle(&mut self, event: &GameEvent) -> Option<GameState> {
+        let previous = self.current();
+
+        match event {
+            GameEvent::PushGameState(state) if *state != previous => self.stack.push(*state),
+            GameEvent::PopGameState if self.stack.len() > 1 => {
+                self.stack.pop();
+            }
+            GameEvent::SetGameState(state) => {
+                self.stack.clear();
+                self.stack.push(*state);
+            }
+            _ => return None,
+        }
+
+        let current = self.current();
+        (current != previous).then_some(current)
+    }
+}
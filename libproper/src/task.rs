@@ -0,0 +1,295 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll, Wake, Waker},
+};
+
+use crate::{error::Error, event::GameEvent, lock::Recover, resource::loader::LoadingHandle};
+
+pub type TaskFuture = Pin<Box<dyn Future<Output = Result<(), Error>> + Send>>;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct TaskHandle(u64);
+
+trait EventWaiter: Send {
+    fn offer(&mut self, event: &GameEvent);
+    fn is_done(&self) -> bool;
+}
+
+struct EventFilter<T, F> {
+    filter: F,
+    result: Option<T>,
+}
+
+#[derive(Default)]
+struct TaskQueue {
+    // Simulation time, as of the last executor update
+    time: f64,
+    last_handle: u64,
+    spawned: Vec<(TaskHandle, TaskFuture)>,
+    aborted: BTreeSet<TaskHandle>,
+    running: BTreeSet<TaskHandle>,
+    waiters: Vec<Arc<Mutex<dyn EventWaiter>>>,
+}
+
+// Spawns and controls sequential gameplay logic written as async blocks, e.g.
+//
+//     let t = tasks.clone();
+//     tasks.spawn(async move {
+//         t.delay(2.0).await;
+//         t.wait_for(|event| matches!(event, GameEvent::Signal { .. }).then(|| ())).await;
+//         Ok(())
+//     });
+//
+// Tasks are polled once per logic tick on the main thread, awaiting anything else than what
+// this provides just stalls the task
+#[derive(Clone, Default)]
+pub struct Tasks {
+    queue: Arc<Mutex<TaskQueue>>,
+}
+
+// Owned by the logic layer, runs the tasks
+pub struct TaskExecutor {
+    tasks: Tasks,
+    futures: BTreeMap<TaskHandle, TaskFuture>,
+    waker: Waker,
+}
+
+// Nothing has to be woken up, every task is polled each tick anyway
+struct TickWaker;
+
+pub struct Delay {
+    tasks: Tasks,
+    duration: f64,
+    deadline: Option<f64>,
+}
+
+pub struct NextTick {
+    yielded: bool,
+}
+
+pub struct WaitEvent<T, F> {
+    tasks: Tasks,
+    waiter: Arc<Mutex<EventFilter<T, F>>>,
+    registered: bool,
+}
+
+pub struct AssetsLoaded {
+    handle: LoadingHandle,
+}
+
+impl<T, F> EventWaiter for EventFilter<T, F>
+where
+    T: Send,
+    F: FnMut(&GameEvent) -> Option<T> + Send,
+{
+    fn offer(&mut self, event: &GameEvent) {
+        if self.result.is_none() {
+            self.result = (self.filter)(event);
+        }
+    }
+
+    fn is_done(&self) -> bool {
+        self.result.is_some()
+    }
+}
+
+impl Wake for TickWaker {
+    fn wake(self: Arc<Self>) {}
+}
+
+impl Tasks {
+    pub fn spawn<F>(&self, future: F) -> TaskHandle
+    where
+        F: Future<Output = Result<(), Error>> + Send + 'static,
+    {
+        let mut queue = self.queue.lock().recover();
+        queue.last_handle += 1;
+        let handle = TaskHandle(queue.last_handle);
+        queue.spawned.push((handle, Box::pin(future)));
+        queue.running.insert(handle);
+        handle
+    }
+
+    // The task is dropped before its next poll
+    pub fn abort(&self, handle: TaskHandle) -> bool {
+        let mut queue = self.queue.lock().recover();
+        if queue.running.remove(&handle) {
+            queue.aborted.insert(handle);
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn is_running(&self, handle: TaskHandle) -> bool {
+        self.queue.lock().recover().running.contains(&handle)
+    }
+
+    // In simulation time, so it stops while paused and stretches in slow motion
+    pub fn delay(&self, seconds: f64) -> Delay {
+        Delay {
+            tasks: self.clone(),
+            duration: seconds,
+            deadline: None,
+        }
+    }
+
+    pub fn next_tick(&self) -> NextTick {
+        NextTick { yielded: false }
+    }
+
+    // Resolves with the value of the first event the filter accepts. Only events reaching the
+    // logic layer are seen, the filter runs with the task queue locked and must not use Tasks
+    pub fn wait_for<T, F>(&self, filter: F) -> WaitEvent<T, F>
+    where
+        T: Send + 'static,
+        F: FnMut(&GameEvent) -> Option<T> + Send + 'static,
+    {
+        WaitEvent {
+            tasks: self.clone(),
+            waiter: Arc::new(Mutex::new(EventFilter {
+                filter,
+                result: None,
+            })),
+            registered: false,
+        }
+    }
+
+    pub fn assets_loaded(&self, handle: LoadingHandle) -> AssetsLoaded {
+        AssetsLoaded { handle }
+    }
+
+    fn time(&self) -> f64 {
+        self.queue.lock().recover().time
+    }
+}
+
+impl TaskExecutor {
+    pub fn new(tasks: Tasks) -> Self {
+        Self {
+            tasks,
+            futures: BTreeMap::new(),
+            waker: Waker::from(Arc::new(TickWaker)),
+        }
+    }
+
+    #[inline]
+    pub const fn tasks(&self) -> &Tasks {
+        &self.tasks
+    }
+
+    pub fn dispatch(&self, event: &GameEvent) {
+        let mut queue = self.tasks.queue.lock().recover();
+        // Waiters of finished or dropped futures go away
+        queue.waiters.retain(|waiter| {
+            let mut waiter = waiter.lock().recover();
+            waiter.offer(event);
+            !waiter.is_done()
+        });
+    }
+
+    // Polls every task once, tasks which fail are dropped and the first error is returned
+    pub fn update(&mut self, time: f64) -> Result<(), Error> {
+        {
+            let mut queue = self.tasks.queue.lock().recover();
+            queue.time = time;
+            self.futures.extend(queue.spawned.drain(..));
+            for handle in std::mem::take(&mut queue.aborted) {
+                self.futures.remove(&handle);
+            }
+            queue.waiters.retain(|waiter| Arc::strong_count(waiter) > 1);
+        }
+
+        let mut cx = Context::from_waker(&self.waker);
+        let mut finished = vec![];
+        let mut error = None;
+        for (handle, future) in self.futures.iter_mut() {
+            match future.as_mut().poll(&mut cx) {
+                Poll::Ready(Ok(())) => finished.push(*handle),
+                Poll::Ready(Err(err)) => {
+                    finished.push(*handle);
+                    error.get_or_insert(err);
+                }
+                Poll::Pending => (),
+            }
+        }
+
+        let mut queue = self.tasks.queue.lock().recover();
+        for handle in finished {
+            self.futures.remove(&handle);
+            queue.running.remove(&handle);
+        }
+
+        match error {
+            Some(err) => Err(err),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Future for Delay {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<()> {
+        let now = self.tasks.time();
+        let duration = self.duration;
+        let deadline = *self.deadline.get_or_insert(now + duration);
+        if now >= deadline {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+impl Future for NextTick {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<()> {
+        if self.yielded {
+            Poll::Ready(())
+        } else {
+            self.yielded = true;
+            Poll::Pending
+        }
+    }
+}
+
+impl<T, F> Future for WaitEvent<T, F>
+where
+    T: Send + 'static,
+    F: FnMut(&GameEvent) -> Option<T> + Send + 'static,
+{
+    type Output = T;
+
+    fn poll(mut self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<T> {
+        if !self.registered {
+            self.registered = true;
+            let waiter: Arc<Mutex<dyn EventWaiter>> = self.waiter.clone();
+            self.tasks.queue.lock().recover().waiters.push(waiter);
+        }
+
+        let result = self.waiter.lock().recover().result.take();
+        match result {
+            Some(value) => Poll::Ready(value),
+            None => Poll::Pending,
+        }
+    }
+}
+
+impl Future for AssetsLoaded {
+    type Output = Result<(), Error>;
+
+    fn poll(mut self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Self::Output> {
+        if !self.handle.poll() {
+            return Poll::Pending;
+        }
+        match self.handle.error() {
+            Some(err) => Poll::Ready(Err(Error::AssetLoad(err.to_owned()))),
+            None => Poll::Ready(Ok(())),
+        }
+    }
+}
@@ -0,0 +1,161 @@
+// Step of the fixed timestep updates (motion, physics), in seconds
+pub const FIXED_TIMESTEP: f64 = 1.0 / 60.0;
+// Fixed steps run in one frame at most, time past that is dropped instead of piling up after
+// a hitch, which would make the following frames even slower
+pub const MAX_FIXED_STEPS: u32 = 5;
+
+// Engine clock, advanced once per main loop iteration and handed to the layers. Simulation
+// time is scaled and stops while paused, real time always follows the wall clock and is
+// what the GUI should use
+#[derive(Clone, Debug)]
+pub struct Time {
+    total: f64,
+    delta: f64,
+    real_total: f64,
+    real_delta: f64,
+    fixed_delta: f64,
+    fixed_accumulator: f64,
+    fixed_steps: u32,
+    frame: u64,
+    time_scale: f64,
+    paused: bool,
+}
+
+impl Default for Time {
+    fn default() -> Self {
+        Self {
+            total: 0.0,
+            delta: 0.0,
+            real_total: 0.0,
+            real_delta: 0.0,
+            fixed_delta: FIXED_TIMESTEP,
+            fixed_accumulator: 0.0,
+            fixed_steps: 0,
+            frame: 0,
+            time_scale: 1.0,
+            paused: false,
+        }
+    }
+}
+
+impl Time {
+    // Simulation time since start, in seconds
+    #[inline]
+    pub const fn total(&self) -> f64 {
+        self.total
+    }
+
+    // Scaled, zero while paused
+    #[inline]
+    pub const fn delta(&self) -> f64 {
+        self.delta
+    }
+
+    #[inline]
+    pub const fn real_total(&self) -> f64 {
+        self.real_total
+    }
+
+    #[inline]
+    pub const fn real_delta(&self) -> f64 {
+        self.real_delta
+    }
+
+    #[inline]
+    pub const fn fixed_delta(&self) -> f64 {
+        self.fixed_delta
+    }
+
+    // Fixed timestep updates due this frame, slow motion makes them less frequent
+    #[inline]
+    pub const fn fixed_steps(&self) -> u32 {
+        self.fixed_steps
+    }
+
+    #[inline]
+    pub const fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    #[inline]
+    pub const fn time_scale(&self) -> f64 {
+        self.time_scale
+    }
+
+    #[inline]
+    pub const fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    // 1 is normal speed, 0.25 is slow motion
+    pub fn set_time_scale(&mut self, scale: f64) {
+        self.time_scale = scale.max(0.0);
+    }
+
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+    }
+
+    pub fn advance(&mut self, real_delta: f64) {
+        self.frame += 1;
+        self.real_delta = real_delta;
+        self.real_total += real_delta;
+
+        self.delta = if self.paused {
+            0.0
+        } else {
+            real_delta * self.time_scale
+        };
+        self.total += self.delta;
+
+        self.fixed_accumulator =
+            (self.fixed_accumulator + self.delta).min(self.fixed_delta * MAX_FIXED_STEPS as f64);
+        self.fixed_steps = 0;
+        while self.fixed_accumulator >= self.fixed_delta {
+            self.fixed_accumulator -= self.fixed_delta;
+            self.fixed_steps += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accumulates_fixed_steps() {
+        let mut time = Time::default();
+        time.advance(FIXED_TIMESTEP * 2.5);
+        assert_eq!(time.fixed_steps(), 2);
+        // The leftover half step carries over
+        time.advance(FIXED_TIMESTEP * 0.75);
+        assert_eq!(time.fixed_steps(), 1);
+        assert_eq!(time.frame(), 2);
+    }
+
+    #[test]
+    fn long_frames_are_clamped() {
+        let mut time = Time::default();
+        time.advance(1.0);
+        assert_eq!(time.fixed_steps(), MAX_FIXED_STEPS);
+        // The dropped time doesn't show up in the following frames
+        time.advance(0.0);
+        assert_eq!(time.fixed_steps(), 0);
+        assert_eq!(time.total(), 1.0);
+    }
+
+    #[test]
+    fn pause_and_scale() {
+        let mut time = Time::default();
+        time.set_paused(true);
+        time.advance(1.0);
+        assert_eq!(time.delta(), 0.0);
+        assert_eq!(time.fixed_steps(), 0);
+        assert_eq!(time.real_total(), 1.0);
+
+        time.set_paused(false);
+        time.set_time_scale(0.5);
+        time.advance(FIXED_TIMESTEP * 2.5);
+        assert_eq!(time.fixed_steps(), 1);
+    }
+}
@@ -0,0 +1,139 @@
+use std::{collections::BTreeMap, sync::Mutex};
+
+use crate::lock::Recover;
+
+type Callback = Box<dyn FnMut() + Send>;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct TimerHandle(u64);
+
+struct Timer {
+    remaining: f64,
+    // Repeating timers are rescheduled by this much after firing
+    interval: Option<f64>,
+    // Taken out while the callback runs outside of the lock
+    callback: Option<Callback>,
+}
+
+// Delayed and repeating callbacks for game logic, advanced by the fixed timestep loop so they
+// stop while the game is paused. Timers due in the same tick fire in the order they were
+// started
+#[derive(Default)]
+pub struct TimerManager {
+    timers: BTreeMap<TimerHandle, Timer>,
+    last_handle: u64,
+    time: f64,
+}
+
+impl TimerManager {
+    // Game time, in seconds
+    #[inline]
+    pub const fn time(&self) -> f64 {
+        self.time
+    }
+
+    // Callbacks run with the manager unlocked, so they may start or cancel timers themselves
+    pub fn after<F: FnMut() + Send + 'static>(&mut self, delay: f64, f: F) -> TimerHandle {
+        self.insert(Timer {
+            remaining: delay.max(0.0),
+            interval: None,
+            callback: Some(Box::new(f)),
+        })
+    }
+
+    // First call is one interval from now, a zero interval fires every tick
+    pub fn every<F: FnMut() + Send + 'static>(&mut self, interval: f64, f: F) -> TimerHandle {
+        let interval = interval.max(0.0);
+        self.insert(Timer {
+            remaining: interval,
+            interval: Some(interval),
+            callback: Some(Box::new(f)),
+        })
+    }
+
+    pub fn next_tick<F: FnMut() + Send + 'static>(&mut self, f: F) -> TimerHandle {
+        self.after(0.0, f)
+    }
+
+    pub fn cancel(&mut self, handle: TimerHandle) -> bool {
+        self.timers.remove(&handle).is_some()
+    }
+
+    pub fn cancel_all(&mut self) {
+        self.timers.clear();
+    }
+
+    pub fn is_active(&self, handle: TimerHandle) -> bool {
+        self.timers.contains_key(&handle)
+    }
+
+    // Seconds until the timer fires next
+    pub fn remaining(&self, handle: TimerHandle) -> Option<f64> {
+        self.timers
+            .get(&handle)
+            .map(|timer| timer.remaining.max(0.0))
+    }
+
+    // Takes the manager's lock only to collect the due callbacks and to put the repeating ones
+    // back, a repeating timer cancelled by a callback is not restored
+    pub fn update(timers: &Mutex<Self>, dt: f64) {
+        let due = timers.lock().recover().advance(dt);
+
+        for (handle, count, mut callback) in due {
+            for _ in 0..count {
+                callback();
+            }
+            timers.lock().recover().restore(handle, callback);
+        }
+    }
+
+    fn advance(&mut self, dt: f64) -> Vec<(TimerHandle, u32, Callback)> {
+        self.time += dt;
+        let mut due = vec![];
+        let mut finished = vec![];
+
+        for (handle, timer) in self.timers.iter_mut() {
+            if timer.callback.is_none() {
+                continue;
+            }
+            timer.remaining -= dt;
+            // Short intervals can fire more than once per tick
+            let mut count = 0;
+            while timer.remaining <= 0.0 {
+                count += 1;
+                match timer.interval {
+                    Some(interval) if interval > 0.0 => timer.remaining += interval,
+                    Some(_) => {
+                        timer.remaining = 0.0;
+                        break;
+                    }
+                    None => {
+                        finished.push(*handle);
+                        break;
+                    }
+                }
+            }
+            if count > 0 {
+                due.push((*handle, count, timer.callback.take().unwrap()));
+            }
+        }
+
+        for handle in finished {
+            self.timers.remove(&handle);
+        }
+        due
+    }
+
+    fn restore(&mut self, handle: TimerHandle, callback: Callback) {
+        if let Some(timer) = self.timers.get_mut(&handle) {
+            timer.callback = Some(callback);
+        }
+    }
+
+    fn insert(&mut self, timer: Timer) -> TimerHandle {
+        self.last_handle += 1;
+        let handle = TimerHandle(self.last_handle);
+        self.timers.insert(handle, timer);
+        handle
+    }
+}
@@ -0,0 +1,87 @@
+use std::path::PathBuf;
+
+use crate::error::Error;
+
+// Exporters for the engine's tracing spans, each needs its cargo feature enabled
+#[derive(Default)]
+pub struct TraceConfig {
+    chrome: Option<PathBuf>,
+    tracy: bool,
+}
+
+// Keeps the exporters running, the chrome trace is written out when it's dropped
+#[derive(Default)]
+pub struct TraceGuard {
+    #[cfg(feature = "chrome-trace")]
+    _chrome: Option<tracing_chrome::FlushGuard>,
+}
+
+impl TraceConfig {
+    // Trace file for chrome://tracing or Perfetto, needs the "chrome-trace" feature
+    pub fn chrome<P: Into<PathBuf>>(mut self, path: P) -> Self {
+        self.chrome = Some(path.into());
+        self
+    }
+
+    // Streams spans to a running Tracy profiler, needs the "tracy" feature
+    pub fn tracy(mut self) -> Self {
+        self.tracy = true;
+        self
+    }
+
+    #[cfg(any(feature = "chrome-trace", feature = "tracy"))]
+    pub fn init(self) -> Result<TraceGuard, Error> {
+        use tracing_subscriber::prelude::*;
+
+        #[cfg(feature = "chrome-trace")]
+        let (chrome, chrome_guard) = match self.chrome {
+            Some(path) => {
+                let (layer, guard) = tracing_chrome::ChromeLayerBuilder::new()
+                    .file(path)
+                    .include_args(true)
+                    .build();
+                (Some(layer), Some(guard))
+            }
+            None => (None, None),
+        };
+        #[cfg(not(feature = "chrome-trace"))]
+        let chrome = unavailable::<tracing_subscriber::layer::Identity>(
+            self.chrome.is_some(),
+            "chrome-trace",
+        );
+
+        #[cfg(feature = "tracy")]
+        let tracy = self.tracy.then(tracing_tracy::TracyLayer::new);
+        #[cfg(not(feature = "tracy"))]
+        let tracy = unavailable::<tracing_subscriber::layer::Identity>(self.tracy, "tracy");
+
+        tracing_subscriber::registry()
+            .with(chrome)
+            .with(tracy)
+            .try_init()
+            .map_err(|err| Error::TraceInit(err.to_string()))?;
+
+        Ok(TraceGuard {
+            #[cfg(feature = "chrome-trace")]
+            _chrome: chrome_guard,
+        })
+    }
+
+    #[cfg(not(any(feature = "chrome-trace", feature = "tracy")))]
+    pub fn init(self) -> Result<TraceGuard, Error> {
+        unavailable::<()>(self.chrome.is_some(), "chrome-trace");
+        unavailable::<()>(self.tracy, "tracy");
+        Ok(TraceGuard::default())
+    }
+}
+
+#[allow(dead_code)]
+fn unavailable<T>(requested: bool, feature: &str) -> Option<T> {
+    if requested {
+        log::warn!(
+            "Trace exporter requires the {:?} feature, ignoring",
+            feature
+        );
+    }
+    None
+}
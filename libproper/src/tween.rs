@@ -0,0 +1,241 @@
+use std::{collections::HashMap, f32::consts::PI};
+
+use nalgebra::{Point3, Vector3};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Ease {
+    Linear,
+    QuadIn,
+    QuadOut,
+    QuadInOut,
+    CubicIn,
+    CubicOut,
+    CubicInOut,
+    SineInOut,
+    BackOut,
+    BounceOut,
+}
+
+pub trait Tweenable: Copy + Send + 'static {
+    fn lerp(from: &Self, to: &Self, t: f32) -> Self;
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct TweenHandle(u64);
+
+pub struct Tween<T: Tweenable> {
+    from: T,
+    to: T,
+    duration: f32,
+    delay: f32,
+    elapsed: f32,
+    ease: Ease,
+    setter: Box<dyn FnMut(T) + Send>,
+    on_complete: Option<Box<dyn FnOnce() + Send>>,
+    next: Option<Box<dyn TweenTrack>>,
+}
+
+// Type-erased tween, so tweens of different value types can share one manager
+pub trait TweenTrack: Send {
+    // Returns true when finished
+    fn advance(&mut self, dt: f32) -> bool;
+    fn take_next(&mut self) -> Option<Box<dyn TweenTrack>>;
+    fn append(&mut self, next: Box<dyn TweenTrack>);
+}
+
+#[derive(Default)]
+pub struct TweenManager {
+    tweens: HashMap<TweenHandle, Box<dyn TweenTrack>>,
+    last_handle: u64,
+}
+
+impl Ease {
+    pub fn apply(self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            Self::Linear => t,
+            Self::QuadIn => t * t,
+            Self::QuadOut => 1.0 - (1.0 - t) * (1.0 - t),
+            Self::QuadInOut => {
+                if t < 0.5 {
+                    2.0 * t * t
+                } else {
+                    1.0 - (-2.0 * t + 2.0).powi(2) / 2.0
+                }
+            }
+            Self::CubicIn => t * t * t,
+            Self::CubicOut => 1.0 - (1.0 - t).powi(3),
+            Self::CubicInOut => {
+                if t < 0.5 {
+                    4.0 * t * t * t
+                } else {
+                    1.0 - (-2.0 * t + 2.0).powi(3) / 2.0
+                }
+            }
+            Self::SineInOut => -((PI * t).cos() - 1.0) / 2.0,
+            Self::BackOut => {
+                const C1: f32 = 1.70158;
+                const C3: f32 = C1 + 1.0;
+                1.0 + C3 * (t - 1.0).powi(3) + C1 * (t - 1.0).powi(2)
+            }
+            Self::BounceOut => {
+                const N1: f32 = 7.5625;
+                const D1: f32 = 2.75;
+                if t < 1.0 / D1 {
+                    N1 * t * t
+                } else if t < 2.0 / D1 {
+                    let t = t - 1.5 / D1;
+                    N1 * t * t + 0.75
+                } else if t < 2.5 / D1 {
+                    let t = t - 2.25 / D1;
+                    N1 * t * t + 0.9375
+                } else {
+                    let t = t - 2.625 / D1;
+                    N1 * t * t + 0.984375
+                }
+            }
+        }
+    }
+}
+
+impl Tweenable for f32 {
+    fn lerp(from: &Self, to: &Self, t: f32) -> Self {
+        from + (to - from) * t
+    }
+}
+
+impl Tweenable for Vector3<f32> {
+    fn lerp(from: &Self, to: &Self, t: f32) -> Self {
+        from.lerp(to, t)
+    }
+}
+
+impl Tweenable for Point3<f32> {
+    fn lerp(from: &Self, to: &Self, t: f32) -> Self {
+        from + (to - from) * t
+    }
+}
+
+// RGBA color
+impl Tweenable for [f32; 4] {
+    fn lerp(from: &Self, to: &Self, t: f32) -> Self {
+        [0, 1, 2, 3].map(|i| from[i] + (to[i] - from[i]) * t)
+    }
+}
+
+impl<T: Tweenable> Tween<T> {
+    pub fn new<F: FnMut(T) + Send + 'static>(from: T, to: T, duration: f32, setter: F) -> Self {
+        Self {
+            from,
+            to,
+            duration,
+            delay: 0.0,
+            elapsed: 0.0,
+            ease: Ease::Linear,
+            setter: Box::new(setter),
+            on_complete: None,
+            next: None,
+        }
+    }
+
+    pub fn with_ease(mut self, ease: Ease) -> Self {
+        self.ease = ease;
+        self
+    }
+
+    pub fn with_delay(mut self, delay: f32) -> Self {
+        self.delay = delay;
+        self
+    }
+
+    // Called from within TweenManager::update, must not lock the manager itself
+    pub fn on_complete<F: FnOnce() + Send + 'static>(mut self, f: F) -> Self {
+        self.on_complete = Some(Box::new(f));
+        self
+    }
+
+    // Starts `next` once this tween (and anything already chained to it) completes
+    pub fn then<U: Tweenable>(mut self, next: Tween<U>) -> Self {
+        self.append(Box::new(next));
+        self
+    }
+}
+
+impl<T: Tweenable> TweenTrack for Tween<T> {
+    fn advance(&mut self, dt: f32) -> bool {
+        if self.delay > 0.0 {
+            self.delay -= dt;
+            if self.delay > 0.0 {
+                return false;
+            }
+        }
+
+        self.elapsed += dt;
+        let t = if self.duration > 0.0 {
+            self.elapsed / self.duration
+        } else {
+            1.0
+        };
+        (self.setter)(T::lerp(&self.from, &self.to, self.ease.apply(t)));
+
+        if t >= 1.0 {
+            if let Some(on_complete) = self.on_complete.take() {
+                on_complete();
+            }
+            true
+        } else {
+            false
+        }
+    }
+
+    fn take_next(&mut self) -> Option<Box<dyn TweenTrack>> {
+        self.next.take()
+    }
+
+    fn append(&mut self, next: Box<dyn TweenTrack>) {
+        match self.next.as_mut() {
+            Some(chain) => chain.append(next),
+            None => self.next = Some(next),
+        }
+    }
+}
+
+impl TweenManager {
+    pub fn start<T: Tweenable>(&mut self, tween: Tween<T>) -> TweenHandle {
+        self.last_handle += 1;
+        let handle = TweenHandle(self.last_handle);
+        self.tweens.insert(handle, Box::new(tween));
+        handle
+    }
+
+    // Cancels the tween and everything chained after it
+    pub fn cancel(&mut self, handle: TweenHandle) -> bool {
+        self.tweens.remove(&handle).is_some()
+    }
+
+    pub fn cancel_all(&mut self) {
+        self.tweens.clear();
+    }
+
+    pub fn is_active(&self, handle: TweenHandle) -> bool {
+        self.tweens.contains_key(&handle)
+    }
+
+    pub fn update(&mut self, dt: f32) {
+        let mut finished = vec![];
+
+        for (handle, track) in self.tweens.iter_mut() {
+            if track.advance(dt) {
+                match track.take_next() {
+                    // The chain keeps its handle
+                    Some(next) => *track = next,
+                    None => finished.push(*handle),
+                }
+            }
+        }
+
+        for handle in finished {
+            self.tweens.remove(&handle);
+        }
+    }
+}
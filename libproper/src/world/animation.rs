@@ -0,0 +1,210 @@
+use std::sync::Arc;
+
+use nalgebra::{Point3, UnitQuaternion};
+
+use crate::{error::Error, event::GameEvent};
+
+use super::schedule::{System, SystemAccess, SystemContext};
+
+#[derive(Clone, Debug)]
+pub struct Keyframe<T> {
+    pub time: f32,
+    pub value: T,
+}
+
+// Named event emitted when playback crosses `time`
+#[derive(Clone, Debug)]
+pub struct AnimationMarker {
+    pub time: f32,
+    pub name: String,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct AnimationClip {
+    pub duration: f32,
+    pub looping: bool,
+    pub position: Vec<Keyframe<Point3<f32>>>,
+    pub rotation: Vec<Keyframe<UnitQuaternion<f32>>>,
+    pub markers: Vec<AnimationMarker>,
+}
+
+pub struct AnimationPlayer {
+    clip: Arc<AnimationClip>,
+    time: f32,
+    pub speed: f32,
+    pub playing: bool,
+}
+
+#[derive(Default)]
+pub struct AnimationSystem;
+
+impl AnimationClip {
+    pub fn new(duration: f32, looping: bool) -> Self {
+        Self {
+            duration,
+            looping,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_position_key(mut self, time: f32, value: Point3<f32>) -> Self {
+        self.position.push(Keyframe { time, value });
+        self.position.sort_by(|a, b| a.time.total_cmp(&b.time));
+        self
+    }
+
+    pub fn with_rotation_key(mut self, time: f32, value: UnitQuaternion<f32>) -> Self {
+        self.rotation.push(Keyframe { time, value });
+        self.rotation.sort_by(|a, b| a.time.total_cmp(&b.time));
+        self
+    }
+
+    pub fn with_marker(mut self, time: f32, name: &str) -> Self {
+        self.markers.push(AnimationMarker {
+            time,
+            name: name.to_owned(),
+        });
+        self
+    }
+
+    pub fn sample_position(&self, time: f32) -> Option<Point3<f32>> {
+        sample(&self.position, time, |a, b, t| a + (b - a) * t)
+    }
+
+    pub fn sample_rotation(&self, time: f32) -> Option<UnitQuaternion<f32>> {
+        sample(&self.rotation, time, |a, b, t| a.slerp(b, t))
+    }
+
+    // Markers in the (from, to] interval
+    fn markers_between(&self, from: f32, to: f32) -> impl Iterator<Item = &AnimationMarker> {
+        self.markers
+            .iter()
+            .filter(move |m| m.time > from && m.time <= to)
+    }
+}
+
+impl AnimationPlayer {
+    pub fn new(clip: Arc<AnimationClip>) -> Self {
+        Self {
+            clip,
+            time: 0.0,
+            speed: 1.0,
+            playing: true,
+        }
+    }
+
+    #[inline]
+    pub const fn clip(&self) -> &Arc<AnimationClip> {
+        &self.clip
+    }
+
+    #[inline]
+    pub const fn time(&self) -> f32 {
+        self.time
+    }
+
+    pub fn play(&mut self, clip: Arc<AnimationClip>) {
+        self.clip = clip;
+        self.time = 0.0;
+        self.playing = true;
+    }
+
+    pub fn seek(&mut self, time: f32) {
+        self.time = time.clamp(0.0, self.clip.duration);
+    }
+
+    // Advances playback, returning names of the markers passed
+    fn advance(&mut self, dt: f32) -> Vec<String> {
+        if !self.playing || self.clip.duration <= 0.0 {
+            return vec![];
+        }
+
+        let from = self.time;
+        let to = from + dt * self.speed;
+        let duration = self.clip.duration;
+        let mut passed = vec![];
+
+        if to < duration {
+            passed.extend(self.clip.markers_between(from, to).map(|m| m.name.clone()));
+            self.time = to;
+        } else if self.clip.looping {
+            // Markers at t=0 fire when wrapping around
+            passed.extend(
+                self.clip
+                    .markers_between(from, duration)
+                    .map(|m| m.name.clone()),
+            );
+            let wrapped = to % duration;
+            passed.extend(
+                self.clip
+                    .markers
+                    .iter()
+                    .filter(|m| m.time <= wrapped)
+                    .map(|m| m.name.clone()),
+            );
+            self.time = wrapped;
+        } else {
+            passed.extend(
+                self.clip
+                    .markers_between(from, duration)
+                    .map(|m| m.name.clone()),
+            );
+            self.time = duration;
+            self.playing = false;
+        }
+
+        passed
+    }
+}
+
+impl System for AnimationSystem {
+    fn access(&self) -> SystemAccess {
+        SystemAccess::default()
+            .write::<AnimationPlayer>()
+            .write_transforms()
+    }
+
+    fn run(&mut self, ctx: &mut SystemContext) -> Result<(), Error> {
+        for entity in ctx.entities.iter_mut() {
+            let id = entity.id();
+            let player = match entity.components_mut().get_mut::<AnimationPlayer>() {
+                Some(player) if player.playing => player,
+                _ => continue,
+            };
+
+            ctx.events.extend(
+                player
+                    .advance(ctx.delta)
+                    .into_iter()
+                    .map(|name| GameEvent::AnimationEvent { entity: id, name }),
+            );
+
+            let clip = player.clip.clone();
+            let time = player.time;
+            let position = clip.sample_position(time).unwrap_or(*entity.position());
+            let rotation = clip.sample_rotation(time).unwrap_or(*entity.rotation());
+            entity.set_transform(position, rotation);
+        }
+
+        Ok(())
+    }
+}
+
+fn sample<T: Clone, F: Fn(&T, &T, f32) -> T>(
+    keys: &[Keyframe<T>],
+    time: f32,
+    lerp: F,
+) -> Option<T> {
+    let next = keys.iter().position(|k| k.time > time);
+
+    match next {
+        None => keys.last().map(|k| k.value.clone()),
+        Some(0) => keys.first().map(|k| k.value.clone()),
+        Some(index) => {
+            let a = &keys[index - 1];
+            let b = &keys[index];
+            let t = (time - a.time) / (b.time - a.time);
+            Some(lerp(&a.value, &b.value, t))
+        }
+    }
+}
@@ -1,15 +1,69 @@
 use std::f32::consts::PI;
 
-use nalgebra::{Point3, Vector3, clamp};
+use nalgebra::{Matrix4, Point3, Vector3, clamp};
 
-#[derive(Default)]
+// Distance of the 2D camera from the z = 0 plane, sprites are visible within it on both sides
+pub const CAMERA_2D_DEPTH: f32 = 100.0;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Projection {
+    Perspective,
+    // World units between the bottom and top of the screen, the width follows the aspect
+    Orthographic { height: f32 },
+}
+
+#[derive(Clone)]
 pub struct Camera {
     position: Point3<f32>,
     pitch: f32,
-    yaw: f32
+    yaw: f32,
+    fov: f32,
+    near: f32,
+    far: f32,
+    projection: Projection,
+    // Replaces the view built from pitch and yaw, for views they can't express such as cube
+    // map faces looking straight up or down
+    view_override: Option<Matrix4<f32>>,
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Self {
+            position: Point3::origin(),
+            pitch: 0.0,
+            yaw: 0.0,
+            fov: 45.0,
+            near: 0.01,
+            far: 100.0,
+            projection: Projection::Perspective,
+            view_override: None,
+        }
+    }
 }
 
 impl Camera {
+    // Orthographic camera looking down -Z at the XY plane, with Y up
+    pub fn orthographic_2d(height: f32) -> Self {
+        Self {
+            position: Point3::new(0.0, 0.0, CAMERA_2D_DEPTH),
+            pitch: 0.0,
+            yaw: -PI / 2.0,
+            near: 0.01,
+            far: CAMERA_2D_DEPTH * 2.0,
+            projection: Projection::Orthographic { height },
+            ..Default::default()
+        }
+    }
+
+    #[inline]
+    pub const fn projection(&self) -> Projection {
+        self.projection
+    }
+
+    pub fn set_projection(&mut self, projection: Projection) {
+        self.projection = projection;
+    }
+
     #[inline]
     pub const fn position(&self) -> &Point3<f32> {
         &self.position
@@ -25,6 +79,30 @@ impl Camera {
         self.yaw
     }
 
+    // Vertical, in radians
+    #[inline]
+    pub const fn fov(&self) -> f32 {
+        self.fov
+    }
+
+    pub fn set_fov(&mut self, fov: f32) {
+        self.fov = fov;
+    }
+
+    pub fn set_view_override(&mut self, view: Option<Matrix4<f32>>) {
+        self.view_override = view;
+    }
+
+    #[inline]
+    pub const fn near(&self) -> f32 {
+        self.near
+    }
+
+    #[inline]
+    pub const fn far(&self) -> f32 {
+        self.far
+    }
+
     pub fn forward(&self) -> Vector3<f32> {
         let xzlen = self.pitch.cos();
         Vector3::new(self.yaw.cos() * xzlen, self.pitch.sin(), self.yaw.sin() * xzlen)
@@ -35,6 +113,37 @@ impl Camera {
         Vector3::new(-self.yaw.sin() * xzlen, self.pitch.sin(), self.yaw.cos() * xzlen)
     }
 
+    pub fn view_matrix(&self) -> Matrix4<f32> {
+        if let Some(view) = self.view_override {
+            return view;
+        }
+        Matrix4::look_at_rh(
+            &self.position,
+            &(self.position + self.forward()),
+            &Vector3::new(0.0, 1.0, 0.0),
+        )
+    }
+
+    pub fn projection_matrix(&self, aspect: f32) -> Matrix4<f32> {
+        match self.projection {
+            Projection::Perspective => Matrix4::new_perspective(aspect, self.fov, self.near, self.far),
+            Projection::Orthographic { height } => {
+                let (half_width, half_height) = (height * aspect * 0.5, height * 0.5);
+                Matrix4::new_orthographic(-half_width, half_width, -half_height, half_height, self.near, self.far)
+            }
+        }
+    }
+
+    pub fn set_position(&mut self, position: Point3<f32>) {
+        self.position = position;
+    }
+
+    // Points the camera along the direction, which must be normalized
+    pub fn set_direction(&mut self, direction: &Vector3<f32>) {
+        self.pitch = clamp(direction.y.asin(), -89.9f32.to_radians(), 89.9f32.to_radians());
+        self.yaw = direction.z.atan2(direction.x);
+    }
+
     pub fn translate(&mut self, delta: Vector3<f32>) {
         self.position += delta;
     }
@@ -0,0 +1,391 @@
+use std::collections::BTreeSet;
+
+use nalgebra::{Point3, UnitQuaternion, Vector3};
+
+use crate::event::GameEvent;
+
+use super::{
+    collision_mesh::MeshCollider,
+    entity::EntityId,
+    scene::Scene,
+    spatial::{Aabb, SpatialGrid},
+};
+
+#[derive(Clone, Copy, Debug)]
+pub enum ColliderShape {
+    Sphere { radius: f32 },
+    // Axis-aligned in world space, entity rotation is ignored
+    Aabb { half_extents: Vector3<f32> },
+    // Oriented along the entity's local Y axis
+    Capsule { half_height: f32, radius: f32 },
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Collider {
+    pub shape: ColliderShape,
+    pub offset: Vector3<f32>,
+    pub is_trigger: bool,
+}
+
+// For trigger contacts `a` is always the trigger. Ordered by the entity pair, so the
+// enter and exit events of a frame come out in the same order every run
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Contact {
+    pub a: EntityId,
+    pub b: EntityId,
+    pub is_trigger: bool,
+}
+
+pub struct CollisionSystem {
+    grid: SpatialGrid<usize>,
+    contacts: BTreeSet<Contact>,
+}
+
+// Collider resolved to world space
+#[derive(Clone, Copy)]
+enum WorldShape {
+    Box(Aabb),
+    // Sphere-swept segment: spheres are zero-length capsules
+    Swept {
+        a: Point3<f32>,
+        b: Point3<f32>,
+        radius: f32,
+    },
+}
+
+struct WorldCollider {
+    entity: EntityId,
+    is_trigger: bool,
+    shape: WorldShape,
+}
+
+impl Collider {
+    pub fn sphere(radius: f32) -> Self {
+        Self::new(ColliderShape::Sphere { radius })
+    }
+
+    pub fn aabb(half_extents: Vector3<f32>) -> Self {
+        Self::new(ColliderShape::Aabb { half_extents })
+    }
+
+    pub fn capsule(half_height: f32, radius: f32) -> Self {
+        Self::new(ColliderShape::Capsule {
+            half_height,
+            radius,
+        })
+    }
+
+    pub fn with_offset(mut self, offset: Vector3<f32>) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    pub fn trigger(mut self) -> Self {
+        self.is_trigger = true;
+        self
+    }
+
+    pub fn world_bounds(&self, position: &Point3<f32>, rotation: &UnitQuaternion<f32>) -> Aabb {
+        Self::shape_bounds(&self.world_shape(position, rotation))
+    }
+
+    fn new(shape: ColliderShape) -> Self {
+        Self {
+            shape,
+            offset: Vector3::zeros(),
+            is_trigger: false,
+        }
+    }
+
+    fn world_shape(&self, position: &Point3<f32>, rotation: &UnitQuaternion<f32>) -> WorldShape {
+        let center = position + rotation * self.offset;
+        match self.shape {
+            ColliderShape::Sphere { radius } => WorldShape::Swept {
+                a: center,
+                b: center,
+                radius,
+            },
+            ColliderShape::Aabb { half_extents } => {
+                WorldShape::Box(Aabb::from_center(center, half_extents))
+            }
+            ColliderShape::Capsule {
+                half_height,
+                radius,
+            } => {
+                let axis = rotation * Vector3::new(0.0, half_height, 0.0);
+                WorldShape::Swept {
+                    a: center - axis,
+                    b: center + axis,
+                    radius,
+                }
+            }
+        }
+    }
+
+    fn shape_bounds(shape: &WorldShape) -> Aabb {
+        match *shape {
+            WorldShape::Box(aabb) => aabb,
+            WorldShape::Swept { a, b, radius } => {
+                let r = Vector3::repeat(radius);
+                Aabb::new(a.inf(&b) - r, a.sup(&b) + r)
+            }
+        }
+    }
+}
+
+impl Contact {
+    fn new(a: &WorldCollider, b: &WorldCollider) -> Self {
+        let (a, b) = if b.is_trigger || (!a.is_trigger && b.entity < a.entity) {
+            (b, a)
+        } else {
+            (a, b)
+        };
+
+        Self {
+            a: a.entity,
+            b: b.entity,
+            is_trigger: a.is_trigger,
+        }
+    }
+}
+
+impl Default for CollisionSystem {
+    fn default() -> Self {
+        Self::new(4.0)
+    }
+}
+
+impl CollisionSystem {
+    pub fn new(cell_size: f32) -> Self {
+        Self {
+            grid: SpatialGrid::new(cell_size),
+            contacts: BTreeSet::new(),
+        }
+    }
+
+    pub fn update(&mut self, scene: &Scene) -> Vec<GameEvent> {
+        let colliders = scene
+            .entities()
+            .filter_map(|entity| {
+                let collider = entity.components().get::<Collider>()?;
+                Some(WorldCollider {
+                    entity: entity.id(),
+                    is_trigger: collider.is_trigger,
+                    shape: collider.world_shape(entity.position(), entity.rotation()),
+                })
+            })
+            .collect::<Vec<_>>();
+
+        self.grid.clear();
+        for (index, collider) in colliders.iter().enumerate() {
+            self.grid
+                .insert(index, Collider::shape_bounds(&collider.shape));
+        }
+
+        let mut contacts = BTreeSet::new();
+        for (a, b) in self.grid.overlapping_pairs() {
+            let (a, b) = (&colliders[a], &colliders[b]);
+            if (a.is_trigger && b.is_trigger) || !shapes_overlap(&a.shape, &b.shape) {
+                continue;
+            }
+            contacts.insert(Contact::new(a, b));
+        }
+        Self::collide_meshes(scene, &colliders, &mut contacts);
+
+        let mut events = vec![];
+        for contact in contacts.difference(&self.contacts) {
+            events.push(Self::contact_event(contact, true));
+        }
+        for contact in self.contacts.difference(&contacts) {
+            events.push(Self::contact_event(contact, false));
+        }
+
+        self.contacts = contacts;
+        events
+    }
+
+    pub fn contacts(&self) -> impl Iterator<Item = &Contact> {
+        self.contacts.iter()
+    }
+
+    // Forgets the contacts without reporting their exit, for when the scene is replaced
+    pub fn clear(&mut self) {
+        self.contacts.clear();
+    }
+
+    // Mesh colliders are checked against the colliders in their world bounds, in mesh space.
+    // The colliders are few, a large mesh covers far more grid cells, so they are tested
+    // directly instead of querying the grid
+    fn collide_meshes(
+        scene: &Scene,
+        colliders: &[WorldCollider],
+        contacts: &mut BTreeSet<Contact>,
+    ) {
+        let meshes = scene.entities().filter_map(|entity| {
+            let mesh_collider = entity.components().get::<MeshCollider>()?;
+            Some((entity, mesh_collider))
+        });
+        for (entity, mesh_collider) in meshes {
+            let transform = entity.transform();
+            let inverse = match transform.try_inverse() {
+                Some(inverse) => inverse,
+                None => continue,
+            };
+            // Radii are scaled into mesh space, which needs a uniform scale. Otherwise the
+            // smallest axis is used, which reports contacts early instead of missing them
+            let scale = entity.scale();
+            debug_assert!(
+                scale.max() - scale.min() <= scale.max().abs() * 1e-3,
+                "Mesh collider of {:?} has a non-uniform scale {:?}",
+                entity.id(),
+                scale
+            );
+            let scale = scale.min();
+            let bounds = mesh_collider.mesh.world_bounds(&transform);
+            let mesh = WorldCollider {
+                entity: entity.id(),
+                is_trigger: false,
+                shape: WorldShape::Box(bounds),
+            };
+
+            for collider in colliders {
+                if collider.entity == mesh.entity
+                    || collider.is_trigger
+                    || !bounds.intersects(&Collider::shape_bounds(&collider.shape))
+                {
+                    continue;
+                }
+                // Boxes are only used for pickups and the like, they don't hit the environment
+                let (a, b, radius) = match collider.shape {
+                    WorldShape::Swept { a, b, radius } => (a, b, radius),
+                    WorldShape::Box(_) => continue,
+                };
+                let a = inverse.transform_point(&a);
+                let b = inverse.transform_point(&b);
+                if mesh_collider
+                    .mesh
+                    .segment_distance_squared(&a, &b, radius / scale)
+                    .is_some()
+                {
+                    contacts.insert(Contact::new(&mesh, collider));
+                }
+            }
+        }
+    }
+
+    fn contact_event(contact: &Contact, enter: bool) -> GameEvent {
+        let (a, b) = (contact.a, contact.b);
+        match (contact.is_trigger, enter) {
+            (true, true) => GameEvent::TriggerEnter {
+                trigger: a,
+                entity: b,
+            },
+            (true, false) => GameEvent::TriggerExit {
+                trigger: a,
+                entity: b,
+            },
+            (false, true) => GameEvent::CollisionEnter(a, b),
+            (false, false) => GameEvent::CollisionExit(a, b),
+        }
+    }
+}
+
+fn shapes_overlap(a: &WorldShape, b: &WorldShape) -> bool {
+    match (*a, *b) {
+        (WorldShape::Box(a), WorldShape::Box(b)) => a.intersects(&b),
+        (WorldShape::Box(aabb), WorldShape::Swept { a, b, radius })
+        | (WorldShape::Swept { a, b, radius }, WorldShape::Box(aabb)) => {
+            segment_aabb_distance_squared(&a, &b, &aabb) <= radius * radius
+        }
+        (
+            WorldShape::Swept {
+                a: a0,
+                b: a1,
+                radius: ra,
+            },
+            WorldShape::Swept {
+                a: b0,
+                b: b1,
+                radius: rb,
+            },
+        ) => segment_segment_distance_squared(&a0, &a1, &b0, &b1) <= (ra + rb) * (ra + rb),
+    }
+}
+
+pub fn closest_point_on_segment(
+    point: &Point3<f32>,
+    a: &Point3<f32>,
+    b: &Point3<f32>,
+) -> Point3<f32> {
+    let ab = b - a;
+    let len_sq = ab.norm_squared();
+    if len_sq <= f32::EPSILON {
+        return *a;
+    }
+    let t = ((point - a).dot(&ab) / len_sq).clamp(0.0, 1.0);
+    a + ab * t
+}
+
+fn segment_segment_distance_squared(
+    p0: &Point3<f32>,
+    p1: &Point3<f32>,
+    q0: &Point3<f32>,
+    q1: &Point3<f32>,
+) -> f32 {
+    let d1 = p1 - p0;
+    let d2 = q1 - q0;
+    let r = p0 - q0;
+    let a = d1.norm_squared();
+    let e = d2.norm_squared();
+    let f = d2.dot(&r);
+
+    let (s, t) = if a <= f32::EPSILON && e <= f32::EPSILON {
+        (0.0, 0.0)
+    } else if a <= f32::EPSILON {
+        (0.0, (f / e).clamp(0.0, 1.0))
+    } else {
+        let c = d1.dot(&r);
+        if e <= f32::EPSILON {
+            ((-c / a).clamp(0.0, 1.0), 0.0)
+        } else {
+            let b = d1.dot(&d2);
+            let denom = a * e - b * b;
+            let s = if denom > f32::EPSILON {
+                ((b * f - c * e) / denom).clamp(0.0, 1.0)
+            } else {
+                0.0
+            };
+            let t = (b * s + f) / e;
+            if t < 0.0 {
+                ((-c / a).clamp(0.0, 1.0), 0.0)
+            } else if t > 1.0 {
+                (((b - c) / a).clamp(0.0, 1.0), 1.0)
+            } else {
+                (s, t)
+            }
+        }
+    };
+
+    ((p0 + d1 * s) - (q0 + d2 * t)).norm_squared()
+}
+
+fn segment_aabb_distance_squared(a: &Point3<f32>, b: &Point3<f32>, aabb: &Aabb) -> f32 {
+    // Distance to a convex set is convex along the segment, so ternary search converges
+    let distance = |t: f32| {
+        let point = a + (b - a) * t;
+        (aabb.closest_point(&point) - point).norm_squared()
+    };
+
+    let (mut lo, mut hi) = (0.0f32, 1.0f32);
+    for _ in 0..24 {
+        let m0 = lo + (hi - lo) / 3.0;
+        let m1 = hi - (hi - lo) / 3.0;
+        if distance(m0) < distance(m1) {
+            hi = m1;
+        } else {
+            lo = m0;
+        }
+    }
+
+    distance((lo + hi) * 0.5)
+}
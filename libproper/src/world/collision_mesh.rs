@@ -0,0 +1,328 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use nalgebra::{Matrix4, Point3};
+
+use crate::{
+    error::Error,
+    resource::{mesh::position_key, model::Model},
+};
+
+use super::{
+    collision::closest_point_on_segment,
+    spatial::{Aabb, SpatialGrid},
+};
+
+// Bumped when the format or the way meshes are built changes, stale files are rebuilt
+const CACHE_MAGIC: &[u8; 4] = b"PCM1";
+const HEADER_SIZE: usize = 4 + 8 + 4 + 4;
+const GRID_CELL_SIZE: f32 = 2.0;
+
+// Static triangle soup for environment collision, in model space: welded positions without
+// degenerate triangles, bucketed in a grid for queries. Attached to entities as MeshCollider
+pub struct CollisionMesh {
+    hash: u64,
+    vertices: Vec<Point3<f32>>,
+    triangles: Vec<[u32; 3]>,
+    bounds: Aabb,
+    grid: SpatialGrid<usize>,
+}
+
+// Solid environment geometry, collides with sphere and capsule colliders. The entity's scale
+// has to be uniform
+#[derive(Clone)]
+pub struct MeshCollider {
+    pub mesh: Arc<CollisionMesh>,
+}
+
+// FNV-1a of the model's triangle positions, stable across runs and builds
+pub fn model_hash(model: &Model) -> u64 {
+    bytemuck::cast_slice::<_, u8>(model.positions())
+        .iter()
+        .fold(0xcbf29ce484222325, |hash, &byte| {
+            (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+        })
+}
+
+// Ericson's closest point on a triangle
+pub fn closest_point_on_triangle(point: &Point3<f32>, triangle: &[Point3<f32>; 3]) -> Point3<f32> {
+    let [a, b, c] = triangle;
+    let ab = b - a;
+    let ac = c - a;
+    let ap = point - a;
+    let d1 = ab.dot(&ap);
+    let d2 = ac.dot(&ap);
+    if d1 <= 0.0 && d2 <= 0.0 {
+        return *a;
+    }
+
+    let bp = point - b;
+    let d3 = ab.dot(&bp);
+    let d4 = ac.dot(&bp);
+    if d3 >= 0.0 && d4 <= d3 {
+        return *b;
+    }
+
+    let vc = d1 * d4 - d3 * d2;
+    if vc <= 0.0 && d1 >= 0.0 && d3 <= 0.0 {
+        return a + ab * (d1 / (d1 - d3));
+    }
+
+    let cp = point - c;
+    let d5 = ab.dot(&cp);
+    let d6 = ac.dot(&cp);
+    if d6 >= 0.0 && d5 <= d6 {
+        return *c;
+    }
+
+    let vb = d5 * d2 - d1 * d6;
+    if vb <= 0.0 && d2 >= 0.0 && d6 <= 0.0 {
+        return a + ac * (d2 / (d2 - d6));
+    }
+
+    let va = d3 * d6 - d5 * d4;
+    if va <= 0.0 && d4 - d3 >= 0.0 && d5 - d6 >= 0.0 {
+        return closest_point_on_segment(point, b, c);
+    }
+
+    let denom = 1.0 / (va + vb + vc);
+    a + ab * (vb * denom) + ac * (vc * denom)
+}
+
+impl CollisionMesh {
+    pub fn from_model(model: &Model) -> Self {
+        Self::from_triangles(model_hash(model), model.triangles())
+    }
+
+    // Mesh from generated geometry, the hash identifies it in the cache and should change
+    // with the triangles
+    pub fn from_triangles<I: IntoIterator<Item = [Point3<f32>; 3]>>(hash: u64, source: I) -> Self {
+        let mut remap = HashMap::new();
+        let mut vertices = vec![];
+        let mut triangles = vec![];
+        for triangle in source {
+            let indices = triangle.map(|position| {
+                *remap.entry(position_key(&position)).or_insert_with(|| {
+                    vertices.push(position);
+                    vertices.len() as u32 - 1
+                })
+            });
+            let [a, b, c] = indices;
+            if a == b || b == c || a == c {
+                continue;
+            }
+            triangles.push(indices);
+        }
+
+        Self::from_parts(hash, vertices, triangles)
+    }
+
+    // Reads <cache_dir>/<model hash>.bin if it's there, otherwise builds the mesh and writes
+    // it. Cache errors are only logged
+    pub fn load_or_build(model: &Model, cache_dir: &Path) -> Self {
+        let hash = model_hash(model);
+        let path = cache_dir.join(format!("{:016x}.bin", hash));
+        match Self::load(&path, hash) {
+            Ok(Some(mesh)) => return mesh,
+            Ok(None) => (),
+            Err(err) => log::warn!("Rebuilding collision mesh: {}", err.full_message()),
+        }
+
+        let mesh = Self::from_model(model);
+        log::debug!(
+            "Built collision mesh {:016x}: {} triangles",
+            hash,
+            mesh.triangles.len()
+        );
+        if let Err(err) = mesh.save(&path) {
+            log::warn!("Failed to cache collision mesh: {}", err.full_message());
+        }
+        mesh
+    }
+
+    // Falls back to the working directory if the platform has no cache directory
+    pub fn default_cache_dir() -> PathBuf {
+        dirs::cache_dir()
+            .map(|dir| dir.join("proper"))
+            .unwrap_or_default()
+            .join("collision")
+    }
+
+    #[inline]
+    pub const fn hash(&self) -> u64 {
+        self.hash
+    }
+
+    #[inline]
+    pub const fn bounds(&self) -> &Aabb {
+        &self.bounds
+    }
+
+    pub fn triangle_count(&self) -> usize {
+        self.triangles.len()
+    }
+
+    pub fn triangles_in(&self, bounds: &Aabb) -> impl Iterator<Item = [Point3<f32>; 3]> + '_ {
+        self.grid
+            .query(bounds)
+            .into_iter()
+            .map(|index| self.triangles[index].map(|i| self.vertices[i as usize]))
+    }
+
+    // Squared distance from a segment to the closest triangle, if any is within max_distance
+    pub fn segment_distance_squared(
+        &self,
+        a: &Point3<f32>,
+        b: &Point3<f32>,
+        max_distance: f32,
+    ) -> Option<f32> {
+        let bounds = Aabb::new(a.inf(b), a.sup(b));
+        let bounds = Aabb::from_center(
+            bounds.center(),
+            bounds.half_extents().add_scalar(max_distance),
+        );
+
+        self.triangles_in(&bounds)
+            .map(|triangle| segment_triangle_distance_squared(a, b, &triangle))
+            .filter(|&distance| distance <= max_distance * max_distance)
+            .min_by(f32::total_cmp)
+    }
+
+    // World-space box around the mesh placed with the transform
+    pub fn world_bounds(&self, transform: &Matrix4<f32>) -> Aabb {
+        let corners = self
+            .bounds
+            .corners()
+            .map(|corner| transform.transform_point(&corner));
+        Aabb::from_points(&corners).unwrap()
+    }
+
+    fn from_parts(hash: u64, vertices: Vec<Point3<f32>>, triangles: Vec<[u32; 3]>) -> Self {
+        let bounds = Aabb::from_points(&vertices)
+            .unwrap_or_else(|| Aabb::new(Point3::origin(), Point3::origin()));
+        let mut grid = SpatialGrid::new(GRID_CELL_SIZE);
+        for (index, triangle) in triangles.iter().enumerate() {
+            let corners = triangle.map(|i| vertices[i as usize]);
+            grid.insert(index, Aabb::from_points(&corners).unwrap());
+        }
+
+        Self {
+            hash,
+            vertices,
+            triangles,
+            bounds,
+            grid,
+        }
+    }
+
+    // None if there's no cache file or it was written for another model
+    fn load(path: &Path, hash: u64) -> Result<Option<Self>, Error> {
+        let bytes = match fs::read(path) {
+            Ok(bytes) => bytes,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(Error::file(path, err)),
+        };
+        if bytes.len() < HEADER_SIZE || &bytes[..4] != CACHE_MAGIC {
+            return Ok(None);
+        }
+
+        let read_u32 =
+            |offset: usize| u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap());
+        let stored_hash = u64::from_le_bytes(bytes[4..12].try_into().unwrap());
+        if stored_hash != hash {
+            return Ok(None);
+        }
+        let vertex_count = read_u32(12) as usize;
+        let triangle_count = read_u32(16) as usize;
+
+        let vertex_bytes = vertex_count * std::mem::size_of::<Point3<f32>>();
+        let triangle_bytes = triangle_count * std::mem::size_of::<[u32; 3]>();
+        if bytes.len() != HEADER_SIZE + vertex_bytes + triangle_bytes {
+            return Err(Error::asset_parse(path, "truncated collision mesh"));
+        }
+        // The data isn't aligned within the file, so it's read value by value instead of cast
+        let body = &bytes[HEADER_SIZE..];
+        let words: Vec<[u8; 4]> = body
+            .chunks_exact(4)
+            .map(|word| word.try_into().unwrap())
+            .collect();
+        let (vertex_words, triangle_words) = words.split_at(vertex_count * 3);
+        let vertices: Vec<Point3<f32>> = vertex_words
+            .chunks_exact(3)
+            .map(|v| {
+                Point3::new(
+                    f32::from_le_bytes(v[0]),
+                    f32::from_le_bytes(v[1]),
+                    f32::from_le_bytes(v[2]),
+                )
+            })
+            .collect();
+        let triangles: Vec<[u32; 3]> = triangle_words
+            .chunks_exact(3)
+            .map(|t| {
+                [
+                    u32::from_le_bytes(t[0]),
+                    u32::from_le_bytes(t[1]),
+                    u32::from_le_bytes(t[2]),
+                ]
+            })
+            .collect();
+        if triangles
+            .iter()
+            .flatten()
+            .any(|&i| i as usize >= vertices.len())
+        {
+            return Err(Error::asset_parse(path, "vertex index out of range"));
+        }
+
+        Ok(Some(Self::from_parts(hash, vertices, triangles)))
+    }
+
+    fn save(&self, path: &Path) -> Result<(), Error> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|err| Error::file(dir, err))?;
+        }
+
+        let mut bytes = Vec::with_capacity(
+            HEADER_SIZE
+                + self.vertices.len() * std::mem::size_of::<Point3<f32>>()
+                + self.triangles.len() * std::mem::size_of::<[u32; 3]>(),
+        );
+        bytes.extend_from_slice(CACHE_MAGIC);
+        bytes.extend_from_slice(&self.hash.to_le_bytes());
+        bytes.extend_from_slice(&(self.vertices.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&(self.triangles.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(bytemuck::cast_slice(&self.vertices));
+        bytes.extend_from_slice(bytemuck::cast_slice(&self.triangles));
+        fs::write(path, bytes).map_err(|err| Error::file(path, err))
+    }
+}
+
+fn segment_triangle_distance_squared(
+    a: &Point3<f32>,
+    b: &Point3<f32>,
+    triangle: &[Point3<f32>; 3],
+) -> f32 {
+    // Same as for boxes: the distance to a convex set is convex along the segment
+    let distance = |t: f32| {
+        let point = a + (b - a) * t;
+        (closest_point_on_triangle(&point, triangle) - point).norm_squared()
+    };
+
+    let (mut lo, mut hi) = (0.0f32, 1.0f32);
+    for _ in 0..24 {
+        let m0 = lo + (hi - lo) / 3.0;
+        let m1 = hi - (hi - lo) / 3.0;
+        if distance(m0) < distance(m1) {
+            hi = m1;
+        } else {
+            lo = m0;
+        }
+    }
+
+    distance((lo + hi) * 0.5)
+}
@@ -0,0 +1,210 @@
+use nalgebra::Vector3;
+
+use crate::{error::Error, event::GameEvent};
+
+use super::{
+    entity::EntityId,
+    ray::Ray,
+    scene::{RaycastHit, Scene},
+};
+
+// Distance moved past a hit on the owner before casting again
+const OWNER_SKIP: f32 = 1e-3;
+
+// Moved by ProjectileSystem, not by Kinematics: every step is swept against the scene so
+// fast projectiles don't pass through thin geometry
+#[derive(Clone, Debug)]
+pub struct Projectile {
+    pub velocity: Vector3<f32>,
+    pub gravity: Vector3<f32>,
+    // Seconds left, despawned without an impact when it runs out
+    pub lifetime: f32,
+    pub damage: f32,
+    // Never hit, reported as the damage source
+    pub owner: Option<EntityId>,
+    // Sent along with the impact, e.g. the name of a particle effect or a sound
+    pub impact: Option<String>,
+}
+
+// Instant ray weapon
+#[derive(Clone, Debug)]
+pub struct HitscanShot {
+    pub range: f32,
+    pub damage: f32,
+    pub owner: Option<EntityId>,
+    pub impact: Option<String>,
+}
+
+#[derive(Default)]
+pub struct ProjectileSystem;
+
+impl Projectile {
+    pub fn new(velocity: Vector3<f32>, damage: f32) -> Self {
+        Self {
+            velocity,
+            gravity: Vector3::zeros(),
+            lifetime: 10.0,
+            damage,
+            owner: None,
+            impact: None,
+        }
+    }
+
+    pub fn with_gravity(mut self, gravity: Vector3<f32>) -> Self {
+        self.gravity = gravity;
+        self
+    }
+
+    pub fn with_lifetime(mut self, lifetime: f32) -> Self {
+        self.lifetime = lifetime;
+        self
+    }
+
+    pub fn with_owner(mut self, owner: EntityId) -> Self {
+        self.owner = Some(owner);
+        self
+    }
+
+    pub fn with_impact(mut self, impact: &str) -> Self {
+        self.impact = Some(impact.to_owned());
+        self
+    }
+}
+
+impl HitscanShot {
+    pub fn new(range: f32, damage: f32) -> Self {
+        Self {
+            range,
+            damage,
+            owner: None,
+            impact: None,
+        }
+    }
+
+    pub fn with_owner(mut self, owner: EntityId) -> Self {
+        self.owner = Some(owner);
+        self
+    }
+
+    pub fn with_impact(mut self, impact: &str) -> Self {
+        self.impact = Some(impact.to_owned());
+        self
+    }
+
+    // Damage and impact events of the first thing hit within range, if any
+    pub fn fire(&self, scene: &Scene, ray: &Ray) -> Vec<GameEvent> {
+        match cast(scene, ray, self.range, self.owner, None) {
+            Some(hit) => hit_events(&hit, self.damage, self.owner, &self.impact),
+            None => vec![],
+        }
+    }
+}
+
+impl ProjectileSystem {
+    pub fn update(&mut self, scene: &mut Scene, dt: f32) -> Result<Vec<GameEvent>, Error> {
+        let mut events = vec![];
+        let mut finished = vec![];
+        let mut moves = vec![];
+
+        for entity in scene.entities() {
+            let projectile = match entity.components().get::<Projectile>() {
+                Some(projectile) => projectile,
+                None => continue,
+            };
+            if projectile.lifetime <= dt {
+                finished.push(entity.id());
+                continue;
+            }
+
+            let velocity = projectile.velocity + projectile.gravity * dt;
+            let step = velocity * dt;
+            if step == Vector3::zeros() {
+                moves.push((entity.id(), *entity.position(), velocity));
+                continue;
+            }
+            let ray = Ray::new(*entity.position(), step);
+            let hit = cast(
+                scene,
+                &ray,
+                step.norm(),
+                projectile.owner,
+                Some(entity.id()),
+            );
+            match hit {
+                Some(hit) => {
+                    events.extend(hit_events(
+                        &hit,
+                        projectile.damage,
+                        projectile.owner,
+                        &projectile.impact,
+                    ));
+                    finished.push(entity.id());
+                }
+                None => moves.push((entity.id(), entity.position() + step, velocity)),
+            }
+        }
+
+        for (id, position, velocity) in moves {
+            let entity = match scene.get_mut(id) {
+                Some(entity) => entity,
+                None => continue,
+            };
+            if let Some(projectile) = entity.components_mut().get_mut::<Projectile>() {
+                projectile.velocity = velocity;
+                projectile.lifetime -= dt;
+            }
+            entity.set_position(position)?;
+        }
+        scene.despawn_batch(&finished);
+
+        Ok(events)
+    }
+}
+
+// Closest hit within the distance. The owner is skipped by casting again past it, so a shot
+// fired from inside the owner's bounds still hits what's behind
+fn cast(
+    scene: &Scene,
+    ray: &Ray,
+    distance: f32,
+    owner: Option<EntityId>,
+    exclude: Option<EntityId>,
+) -> Option<RaycastHit> {
+    let mut ray = *ray;
+    let mut travelled = 0.0;
+    loop {
+        let mut hit = scene.raycast(&ray, exclude)?;
+        if travelled + hit.distance > distance {
+            return None;
+        }
+        if Some(hit.entity) != owner {
+            hit.distance += travelled;
+            return Some(hit);
+        }
+
+        let skip = hit.distance + OWNER_SKIP;
+        travelled += skip;
+        ray = Ray::new(ray.at(skip), ray.direction.into_inner());
+    }
+}
+
+fn hit_events(
+    hit: &RaycastHit,
+    damage: f32,
+    source: Option<EntityId>,
+    impact: &Option<String>,
+) -> Vec<GameEvent> {
+    vec![
+        GameEvent::Damage {
+            target: hit.entity,
+            amount: damage,
+            source,
+        },
+        GameEvent::Impact {
+            entity: hit.entity,
+            point: hit.point,
+            normal: hit.normal,
+            effect: impact.clone(),
+        },
+    ]
+}
@@ -0,0 +1,84 @@
+use std::{
+    any::{Any, TypeId},
+    collections::HashMap,
+};
+
+use crate::resource::material::MaterialInstanceCreateInfo;
+
+pub trait Component: Any + Send + Sync {}
+
+impl<T: Any + Send + Sync> Component for T {}
+
+// Marks entities whose geometry never moves (navmesh baking, environment collision)
+#[derive(Clone, Copy, Default)]
+pub struct StaticGeometry;
+
+// Moved to the new scene on scene switches along with its GPU resources, e.g. the player
+#[derive(Clone, Copy, Default)]
+pub struct Persistent;
+
+// Material parameters come from a preset file, reapplied when the file changes. Overrides
+// are set on top of the preset
+#[derive(Clone)]
+pub struct MaterialPresetRef {
+    pub preset: String,
+    pub overrides: MaterialInstanceCreateInfo,
+}
+
+#[derive(Default)]
+pub struct Components {
+    data: HashMap<TypeId, Box<dyn Any + Send + Sync>>,
+}
+
+impl Components {
+    pub fn insert<T: Component>(&mut self, component: T) -> Option<T> {
+        self.data
+            .insert(TypeId::of::<T>(), Box::new(component))
+            .and_then(|old| old.downcast::<T>().ok())
+            .map(|old| *old)
+    }
+
+    pub fn remove<T: Component>(&mut self) -> Option<T> {
+        self.data
+            .remove(&TypeId::of::<T>())
+            .and_then(|old| old.downcast::<T>().ok())
+            .map(|old| *old)
+    }
+
+    pub fn get<T: Component>(&self) -> Option<&T> {
+        self.data
+            .get(&TypeId::of::<T>())
+            .and_then(|c| c.downcast_ref::<T>())
+    }
+
+    pub fn get_mut<T: Component>(&mut self) -> Option<&mut T> {
+        self.data
+            .get_mut(&TypeId::of::<T>())
+            .and_then(|c| c.downcast_mut::<T>())
+    }
+
+    // Moves the listed component types out, used to hand them to a system for a stage
+    pub(crate) fn take(&mut self, types: &[TypeId]) -> Components {
+        let data = types
+            .iter()
+            .filter_map(|ty| self.data.remove_entry(ty))
+            .collect();
+        Components { data }
+    }
+
+    pub(crate) fn append(&mut self, other: Components) {
+        self.data.extend(other.data);
+    }
+
+    pub fn contains<T: Component>(&self) -> bool {
+        self.data.contains_key(&TypeId::of::<T>())
+    }
+
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+}
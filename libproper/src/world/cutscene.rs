@@ -0,0 +1,190 @@
+use std::path::{Path, PathBuf};
+
+use nalgebra::{Point3, Vector3};
+use serde::Deserialize;
+
+use crate::{error::Error, math::spline::catmull_rom};
+
+const CUTSCENE_DIRECTORY: &str = "res/cutscenes";
+
+// In-engine cutscene, loaded from res/cutscenes/<name>.toml:
+//
+//     letterbox = 0.12
+//     [[shot]]
+//     start = 0.0
+//     end = 4.0
+//     path = [[0.0, 2.0, 5.0], [3.0, 2.0, 5.0], [5.0, 3.0, 0.0]]
+//     look_at = [0.0, 0.0, 0.0]
+//     [[event]]
+//     time = 2.0
+//     signal = "door_open"
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct Cutscene {
+    // Height of each bar, as a fraction of the screen
+    #[serde(default)]
+    pub letterbox: f32,
+    #[serde(default, rename = "shot")]
+    pub shots: Vec<CameraShot>,
+    #[serde(default, rename = "event")]
+    pub events: Vec<CutsceneEvent>,
+}
+
+// Camera move along a Catmull-Rom spline through the path points, between the start and end
+// times. Without a look-at target the camera faces along the path
+#[derive(Clone, Debug, Deserialize)]
+pub struct CameraShot {
+    pub start: f32,
+    pub end: f32,
+    pub path: Vec<[f32; 3]>,
+    pub look_at: Option<[f32; 3]>,
+    // Slow in and out instead of moving at a constant rate
+    #[serde(default)]
+    pub ease: bool,
+}
+
+// Sent as GameEvent::Signal when playback reaches the time
+#[derive(Clone, Debug, Deserialize)]
+pub struct CutsceneEvent {
+    pub time: f32,
+    pub signal: String,
+}
+
+pub struct CutscenePlayer {
+    name: String,
+    cutscene: Cutscene,
+    duration: f32,
+    time: f32,
+    // Events are sorted by time, the ones before this have been sent
+    next_event: usize,
+}
+
+impl Cutscene {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path).map_err(|err| Error::file(path, err))?;
+        toml::from_str(&text).map_err(|err| Error::asset_parse(path, err))
+    }
+
+    pub fn load_by_name(name: &str) -> Result<Self, Error> {
+        let mut path = PathBuf::from(CUTSCENE_DIRECTORY);
+        path.push(format!("{}.toml", name));
+        Self::load(path)
+    }
+
+    // Until the last shot ends or the last event fires
+    pub fn duration(&self) -> f32 {
+        let shots = self.shots.iter().map(|shot| shot.end);
+        let events = self.events.iter().map(|event| event.time);
+        shots.chain(events).fold(0.0, f32::max)
+    }
+
+    // Camera position and forward direction, None between shots
+    pub fn sample(&self, time: f32) -> Option<(Point3<f32>, Vector3<f32>)> {
+        let shot = self
+            .shots
+            .iter()
+            .rev()
+            .find(|shot| shot.start <= time && time <= shot.end)?;
+        shot.sample(time)
+    }
+}
+
+impl CameraShot {
+    fn point(&self, index: isize) -> Vector3<f32> {
+        let index = index.clamp(0, self.path.len() as isize - 1) as usize;
+        Vector3::from(self.path[index])
+    }
+
+    // Position at t in [0, 1] along the whole path
+    fn position(&self, t: f32) -> Vector3<f32> {
+        let segments = self.path.len() - 1;
+        if segments == 0 {
+            return self.point(0);
+        }
+        let scaled = t.clamp(0.0, 1.0) * segments as f32;
+        let segment = (scaled as usize).min(segments - 1);
+        let i = segment as isize;
+        catmull_rom(
+            &self.point(i - 1),
+            &self.point(i),
+            &self.point(i + 1),
+            &self.point(i + 2),
+            scaled - segment as f32,
+        )
+    }
+
+    fn sample(&self, time: f32) -> Option<(Point3<f32>, Vector3<f32>)> {
+        if self.path.is_empty() {
+            return None;
+        }
+        let length = self.end - self.start;
+        let mut t = if length > 0.0 {
+            (time - self.start) / length
+        } else {
+            1.0
+        };
+        if self.ease {
+            t = t * t * (3.0 - 2.0 * t);
+        }
+
+        let position = self.position(t);
+        let direction = match self.look_at {
+            Some(target) => Vector3::from(target) - position,
+            None => self.position((t + 0.01).min(1.0)) - self.position((t - 0.01).max(0.0)),
+        };
+        let direction = direction.try_normalize(f32::EPSILON)?;
+        Some((Point3::from(position), direction))
+    }
+}
+
+impl CutscenePlayer {
+    pub fn new(name: &str, mut cutscene: Cutscene) -> Self {
+        cutscene.events.sort_by(|a, b| a.time.total_cmp(&b.time));
+        let duration = cutscene.duration();
+        Self {
+            name: name.to_owned(),
+            cutscene,
+            duration,
+            time: 0.0,
+            next_event: 0,
+        }
+    }
+
+    #[inline]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    #[inline]
+    pub const fn cutscene(&self) -> &Cutscene {
+        &self.cutscene
+    }
+
+    #[inline]
+    pub const fn time(&self) -> f32 {
+        self.time
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.time >= self.duration
+    }
+
+    // Returns the signals of the events passed
+    pub fn advance(&mut self, delta: f32) -> Vec<String> {
+        self.time = (self.time + delta).min(self.duration);
+
+        let mut signals = vec![];
+        while let Some(event) = self.cutscene.events.get(self.next_event) {
+            if event.time > self.time {
+                break;
+            }
+            signals.push(event.signal.clone());
+            self.next_event += 1;
+        }
+        signals
+    }
+
+    pub fn camera(&self) -> Option<(Point3<f32>, Vector3<f32>)> {
+        self.cutscene.sample(self.time)
+    }
+}
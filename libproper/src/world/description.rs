@@ -0,0 +1,336 @@
+use std::{collections::BTreeMap, path::Path, sync::Arc};
+
+use nalgebra::{Point3, UnitQuaternion, Vector3};
+use serde::Deserialize;
+
+use crate::{
+    error::Error,
+    render::grading,
+    resource::{
+        material::{MaterialInstanceCreateInfo, MaterialRegistry},
+        model::ModelRegistry,
+        texture::TextureRegistry,
+    },
+};
+
+#[cfg(feature = "physics")]
+use super::collision_mesh::MeshCollider;
+use super::{
+    component::{MaterialPresetRef, StaticGeometry},
+    entity::Entity,
+    inventory::{ItemRegistry, Pickup},
+    lightmap::{self, LightmapRef},
+    scene::Scene,
+    spatial::Aabb,
+    spawner::Spawner,
+    trigger::TriggerVolume,
+};
+
+// On-disk scene file format (TOML)
+#[derive(Deserialize, Default)]
+pub struct SceneDescription {
+    #[serde(default, rename = "entity")]
+    pub entities: Vec<EntityDescription>,
+    #[serde(default, rename = "trigger")]
+    pub triggers: Vec<TriggerDescription>,
+    #[serde(default, rename = "pickup")]
+    pub pickups: Vec<PickupDescription>,
+    #[serde(default, rename = "spawner")]
+    pub spawners: Vec<SpawnerDescription>,
+    // LUT from res/luts the scene starts out graded with
+    pub color_grading: Option<String>,
+}
+
+#[derive(Clone, Deserialize)]
+pub struct EntityDescription {
+    pub model: String,
+    #[serde(default = "default_material")]
+    pub material: String,
+    #[serde(default)]
+    pub position: [f32; 3],
+    // Euler angles (roll, pitch, yaw), degrees
+    #[serde(default)]
+    pub rotation: [f32; 3],
+    // Name of a material preset in res/materials, color and texture are set on top of it
+    pub preset: Option<String>,
+    pub color: Option<[f32; 4]>,
+    pub texture: Option<String>,
+    #[serde(default)]
+    pub static_geometry: bool,
+    // Solid for sphere and capsule colliders, using the model's triangles
+    #[serde(default)]
+    pub collision: bool,
+    // Baked lighting from res/lightmaps/<name>.png, applied if it's been baked
+    pub lightmap: Option<String>,
+    // Materials of the model's submeshes, by the material names in the model file
+    #[serde(default)]
+    pub submeshes: BTreeMap<String, SubmeshDescription>,
+}
+
+#[derive(Clone, Deserialize)]
+pub struct SubmeshDescription {
+    #[serde(default = "default_material")]
+    pub material: String,
+    pub color: Option<[f32; 4]>,
+    pub texture: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct TriggerDescription {
+    // Signal sent when an entity enters
+    pub name: String,
+    // Signal sent when it leaves
+    pub exit: Option<String>,
+    pub position: [f32; 3],
+    pub half_extents: [f32; 3],
+    #[serde(default)]
+    pub once: bool,
+}
+
+// Entity showing the item's model
+#[derive(Deserialize)]
+pub struct PickupDescription {
+    // Name of a file in res/items
+    pub item: String,
+    #[serde(default = "default_one")]
+    pub count: u32,
+    #[serde(default)]
+    pub position: [f32; 3],
+    #[serde(default = "default_pickup_radius")]
+    pub radius: f32,
+    // Only picked up with the interact key held
+    #[serde(default)]
+    pub interact: bool,
+}
+
+// Spawns copies of the entity in waves, see Spawner
+#[derive(Deserialize)]
+pub struct SpawnerDescription {
+    // Reported in GameEvent::WaveSpawned
+    #[serde(default)]
+    pub name: String,
+    // Its position is relative to the spawn point
+    pub entity: EntityDescription,
+    #[serde(default)]
+    pub position: [f32; 3],
+    // Spawn points are picked in this box around the position
+    #[serde(default)]
+    pub half_extents: [f32; 3],
+    // Entities per wave
+    #[serde(default = "default_one")]
+    pub count: u32,
+    #[serde(default = "default_one")]
+    pub waves: u32,
+    // Seconds between spawns within a wave
+    #[serde(default)]
+    pub interval: f32,
+    // Seconds between the last spawn of a wave and the first one of the next
+    #[serde(default)]
+    pub wave_delay: f32,
+    // Seconds before the first spawn, counted once started
+    #[serde(default)]
+    pub delay: f32,
+    // Waits for a GameEvent::Signal with the name, e.g. from a trigger volume
+    pub signal: Option<String>,
+}
+
+fn default_one() -> u32 {
+    1
+}
+
+fn default_pickup_radius() -> f32 {
+    1.5
+}
+
+fn default_material() -> String {
+    "simple".to_owned()
+}
+
+impl SceneDescription {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path).map_err(|err| Error::file(path, err))?;
+        toml::from_str(&text).map_err(|err| Error::asset_parse(path, err))
+    }
+
+    pub fn instantiate(
+        &self,
+        materials: &mut MaterialRegistry,
+        models: &mut ModelRegistry,
+        textures: &mut TextureRegistry,
+    ) -> Result<Vec<Entity>, Error> {
+        let mut items = ItemRegistry::default();
+        let entities = self
+            .entities
+            .iter()
+            .map(|entity| entity.instantiate(materials, models, textures));
+        let pickups = self
+            .pickups
+            .iter()
+            .map(|pickup| pickup.instantiate(&mut items, materials, models, textures));
+        entities.chain(pickups).collect()
+    }
+
+    // As a scene of its own, to be switched to or merged into another one
+    pub fn instantiate_scene(
+        &self,
+        materials: &mut MaterialRegistry,
+        models: &mut ModelRegistry,
+        textures: &mut TextureRegistry,
+    ) -> Result<Scene, Error> {
+        let mut scene = Scene::default();
+        for entity in self.instantiate(materials, models, textures)? {
+            scene.add(entity);
+        }
+        scene
+            .triggers
+            .extend(self.triggers.iter().map(TriggerDescription::to_volume));
+        scene.spawners.extend(
+            self.spawners
+                .iter()
+                .map(|spawner| Spawner::new(Arc::new(spawner.clone()))),
+        );
+        if let Some(name) = self.color_grading.as_ref() {
+            let lut = grading::load_lut(textures, name)?;
+            scene.color_grading.set_lut(Some(lut), 0.0);
+        }
+        Ok(scene)
+    }
+}
+
+impl TriggerDescription {
+    pub fn to_volume(&self) -> TriggerVolume {
+        TriggerVolume {
+            bounds: Aabb::from_center(
+                Point3::from(self.position),
+                Vector3::from(self.half_extents),
+            ),
+            name: self.name.clone(),
+            exit_name: self.exit.clone(),
+            once: self.once,
+        }
+    }
+}
+
+impl PickupDescription {
+    pub fn instantiate(
+        &self,
+        items: &mut ItemRegistry,
+        materials: &mut MaterialRegistry,
+        models: &mut ModelRegistry,
+        textures: &mut TextureRegistry,
+    ) -> Result<Entity, Error> {
+        let stack = items.stack(&self.item, self.count)?;
+        let description = EntityDescription {
+            model: stack.definition.model.clone(),
+            material: default_material(),
+            position: self.position,
+            rotation: [0.0; 3],
+            preset: None,
+            color: None,
+            texture: None,
+            static_geometry: false,
+            collision: false,
+            lightmap: None,
+            submeshes: BTreeMap::new(),
+        };
+        let mut pickup = Pickup::new(stack, self.radius);
+        pickup.requires_interaction = self.interact;
+
+        Ok(description
+            .instantiate(materials, models, textures)?
+            .with_component(pickup))
+    }
+}
+
+impl EntityDescription {
+    pub fn instantiate(
+        &self,
+        materials: &mut MaterialRegistry,
+        models: &mut ModelRegistry,
+        textures: &mut TextureRegistry,
+    ) -> Result<Entity, Error> {
+        let material = materials.get_or_load(&self.material)?;
+
+        let mut material_create_info = MaterialInstanceCreateInfo::default();
+        if let Some(color) = self.color {
+            material_create_info = material_create_info.with_color("diffuse_color", color);
+        }
+        if let Some(texture) = &self.texture {
+            material_create_info =
+                material_create_info.with_texture("diffuse_map", textures.get_or_load(texture)?);
+        }
+
+        let preset = match &self.preset {
+            Some(name) => {
+                let mut create_info = materials
+                    .presets_mut()
+                    .get_or_load(name)?
+                    .create_info(textures)?;
+                create_info.merge(&material_create_info);
+                Some(MaterialPresetRef {
+                    preset: name.clone(),
+                    overrides: std::mem::replace(&mut material_create_info, create_info),
+                })
+            }
+            None => None,
+        };
+
+        let mut mesh = models.create_mesh_object(&self.model, material, material_create_info)?;
+        for (name, submesh) in &self.submeshes {
+            let index = match mesh.model().submesh_index(name) {
+                Some(index) => index,
+                None => {
+                    log::warn!("Model {:?} has no submesh {:?}", self.model, name);
+                    continue;
+                }
+            };
+
+            let mut create_info = MaterialInstanceCreateInfo::default();
+            if let Some(color) = submesh.color {
+                create_info = create_info.with_color("diffuse_color", color);
+            }
+            if let Some(texture) = &submesh.texture {
+                create_info =
+                    create_info.with_texture("diffuse_map", textures.get_or_load(texture)?);
+            }
+            mesh.set_submesh_material(
+                index,
+                materials.get_or_load(&submesh.material)?,
+                create_info,
+            )?;
+        }
+        let mut entity = Entity::new_with_mesh(Point3::from(self.position), mesh)?;
+
+        if self.rotation != [0.0; 3] {
+            let [roll, pitch, yaw] = self.rotation.map(f32::to_radians);
+            entity.set_rotation(UnitQuaternion::from_euler_angles(roll, pitch, yaw))?;
+        }
+        if self.static_geometry {
+            entity.components_mut().insert(StaticGeometry);
+        }
+        #[cfg(feature = "physics")]
+        if self.collision {
+            let mesh = models.collision_mesh(entity.mesh().model());
+            entity.components_mut().insert(MeshCollider { mesh });
+        }
+        #[cfg(not(feature = "physics"))]
+        if self.collision {
+            log::warn!(
+                "{:?} has collision, which needs the physics feature",
+                self.model
+            );
+        }
+        if let Some(preset) = preset {
+            entity.components_mut().insert(preset);
+        }
+        if let Some(name) = &self.lightmap {
+            entity
+                .components_mut()
+                .insert(LightmapRef { name: name.clone() });
+            lightmap::apply_lightmap(&mut entity, textures, materials)?;
+        }
+
+        Ok(entity)
+    }
+}
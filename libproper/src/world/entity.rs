@@ -1,12 +1,23 @@
-use nalgebra::{Matrix4, Point3, Vector3};
+use nalgebra::{Isometry3, Matrix4, Point3, Translation3, UnitQuaternion, Vector3};
 
 use crate::error::Error;
 
-use super::scene::MeshObject;
+use super::{
+    component::{Component, Components},
+    scene::MeshObject,
+    spatial::Aabb,
+};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct EntityId(pub u64);
 
 pub struct Entity {
+    id: EntityId,
     position: Point3<f32>,
+    rotation: UnitQuaternion<f32>,
+    scale: Vector3<f32>,
     mesh: MeshObject,
+    components: Components,
 }
 
 unsafe impl Send for Entity {}
@@ -14,11 +25,25 @@ unsafe impl Sync for Entity {}
 
 impl Entity {
     pub fn new_with_mesh(position: Point3<f32>, mut mesh: MeshObject) -> Result<Self, Error> {
-        let transform = Self::create_transform(Vector3::new(position.x, position.y, position.z));
+        let rotation = UnitQuaternion::identity();
+        let scale = Vector3::repeat(1.0);
+        let transform = Self::create_transform(&position, &rotation, &scale);
 
         mesh.update_transform(&transform)?;
 
-        Ok(Self { position, mesh })
+        Ok(Self {
+            id: EntityId::default(),
+            position,
+            rotation,
+            scale,
+            mesh,
+            components: Components::default(),
+        })
+    }
+
+    #[inline]
+    pub const fn id(&self) -> EntityId {
+        self.id
     }
 
     #[inline]
@@ -26,12 +51,97 @@ impl Entity {
         &self.position
     }
 
+    #[inline]
+    pub const fn rotation(&self) -> &UnitQuaternion<f32> {
+        &self.rotation
+    }
+
+    // Only affects rendering, colliders keep their own dimensions
+    #[inline]
+    pub const fn scale(&self) -> &Vector3<f32> {
+        &self.scale
+    }
+
     #[inline]
     pub const fn mesh(&self) -> &MeshObject {
         &self.mesh
     }
 
-    fn create_transform(translation: Vector3<f32>) -> Matrix4<f32> {
-        Matrix4::new_translation(&translation)
+    #[inline]
+    pub fn mesh_mut(&mut self) -> &mut MeshObject {
+        &mut self.mesh
+    }
+
+    #[inline]
+    pub const fn components(&self) -> &Components {
+        &self.components
+    }
+
+    #[inline]
+    pub fn components_mut(&mut self) -> &mut Components {
+        &mut self.components
+    }
+
+    pub fn with_component<T: Component>(mut self, component: T) -> Self {
+        self.components.insert(component);
+        self
+    }
+
+    pub fn set_mesh(&mut self, mut mesh: MeshObject) -> Result<(), Error> {
+        mesh.update_transform(&self.transform())?;
+        self.mesh = mesh;
+        Ok(())
+    }
+
+    pub(crate) fn set_id(&mut self, id: EntityId) {
+        self.id = id;
+    }
+
+    pub fn set_position(&mut self, position: Point3<f32>) -> Result<(), Error> {
+        self.set_transform(position, self.rotation)
+    }
+
+    pub fn set_rotation(&mut self, rotation: UnitQuaternion<f32>) -> Result<(), Error> {
+        self.set_transform(self.position, rotation)
+    }
+
+    pub fn set_scale(&mut self, scale: Vector3<f32>) -> Result<(), Error> {
+        self.scale = scale;
+        self.mesh.update_transform(&self.transform())
+    }
+
+    pub fn set_transform(
+        &mut self,
+        position: Point3<f32>,
+        rotation: UnitQuaternion<f32>,
+    ) -> Result<(), Error> {
+        self.position = position;
+        self.rotation = rotation;
+        self.mesh.update_transform(&self.transform())
+    }
+
+    // World-space box around the transformed model bounds
+    pub fn bounds(&self) -> Aabb {
+        let transform = self.transform();
+        let corners = self
+            .mesh
+            .model()
+            .bounds()
+            .corners()
+            .map(|corner| transform.transform_point(&corner));
+        Aabb::from_points(&corners).unwrap()
+    }
+
+    pub fn transform(&self) -> Matrix4<f32> {
+        Self::create_transform(&self.position, &self.rotation, &self.scale)
+    }
+
+    fn create_transform(
+        position: &Point3<f32>,
+        rotation: &UnitQuaternion<f32>,
+        scale: &Vector3<f32>,
+    ) -> Matrix4<f32> {
+        Isometry3::from_parts(Translation3::from(position.coords), *rotation).to_homogeneous()
+            * Matrix4::new_nonuniform_scaling(scale)
     }
 }
@@ -0,0 +1,113 @@
+use crate::event::GameEvent;
+
+use super::{entity::EntityId, ray::Ray, scene::Scene};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InteractionMode {
+    // The reticle has to be on the entity's geometry
+    Raycast,
+    // Anywhere in front of the camera within the radius, e.g. for small or see-through objects
+    Radius,
+}
+
+// Can be used with the interact key while the camera is within the radius
+#[derive(Clone, Debug)]
+pub struct Interactable {
+    // Shown while the entity is focused, e.g. "Open"
+    pub prompt: String,
+    pub radius: f32,
+    pub mode: InteractionMode,
+    pub enabled: bool,
+}
+
+// Tracks the interactable under the reticle. Sends GameEvent::InteractionPrompt when it
+// changes and GameEvent::Interact when the interact key goes down
+#[derive(Default)]
+pub struct InteractionSystem {
+    focused: Option<EntityId>,
+    was_pressed: bool,
+}
+
+impl Interactable {
+    pub fn new(prompt: &str, radius: f32) -> Self {
+        Self {
+            prompt: prompt.to_owned(),
+            radius,
+            mode: InteractionMode::Raycast,
+            enabled: true,
+        }
+    }
+
+    pub fn with_mode(mut self, mode: InteractionMode) -> Self {
+        self.mode = mode;
+        self
+    }
+}
+
+impl InteractionSystem {
+    #[inline]
+    pub const fn focused(&self) -> Option<EntityId> {
+        self.focused
+    }
+
+    pub fn update(&mut self, scene: &Scene, pressed: bool) -> Vec<GameEvent> {
+        let mut events = vec![];
+
+        let focused = Self::find_focus(scene);
+        if focused.map(|(id, _)| id) != self.focused {
+            self.focused = focused.map(|(id, _)| id);
+            events.push(GameEvent::InteractionPrompt(
+                focused.map(|(_, prompt)| prompt.to_owned()),
+            ));
+        }
+
+        if pressed && !self.was_pressed {
+            if let Some(entity) = self.focused {
+                events.push(GameEvent::Interact(entity));
+            }
+        }
+        self.was_pressed = pressed;
+
+        events
+    }
+
+    // Forgets the focus, for when the scene is replaced. Returns the event hiding the prompt
+    // if it was shown
+    pub fn clear(&mut self) -> Option<GameEvent> {
+        self.focused
+            .take()
+            .map(|_| GameEvent::InteractionPrompt(None))
+    }
+
+    fn find_focus(scene: &Scene) -> Option<(EntityId, &str)> {
+        let origin = *scene.camera.position();
+        let ray = Ray::new(origin, scene.camera.forward());
+
+        if let Some(hit) = scene.raycast(&ray, None) {
+            let interactable = scene
+                .get(hit.entity)
+                .and_then(|entity| entity.components().get::<Interactable>())
+                .filter(|interactable| interactable.enabled && hit.distance <= interactable.radius);
+            if let Some(interactable) = interactable {
+                return Some((hit.entity, &interactable.prompt));
+            }
+        }
+
+        // Otherwise the closest radius-based one in front of the camera, these are meant for
+        // things the reticle easily misses
+        scene
+            .entities()
+            .filter_map(|entity| {
+                let interactable = entity.components().get::<Interactable>()?;
+                let offset = entity.position() - origin;
+                let distance = offset.norm();
+                let usable = interactable.enabled
+                    && interactable.mode == InteractionMode::Radius
+                    && distance <= interactable.radius
+                    && offset.dot(&ray.direction) > 0.0;
+                usable.then(|| (distance, entity.id(), interactable.prompt.as_str()))
+            })
+            .min_by(|a, b| a.0.total_cmp(&b.0))
+            .map(|(_, id, prompt)| (id, prompt))
+    }
+}
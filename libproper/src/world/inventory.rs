@@ -0,0 +1,258 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use serde::Deserialize;
+
+use crate::{error::Error, event::GameEvent};
+
+use super::{entity::EntityId, scene::Scene};
+
+const ITEM_DIRECTORY: &str = "res/items";
+
+// Item type, res/items/<id>.toml
+#[derive(Deserialize, Debug)]
+pub struct ItemDefinition {
+    // Display name
+    pub name: String,
+    // Texture name
+    pub icon: Option<String>,
+    // Used for the pickup entity
+    pub model: String,
+    #[serde(default = "default_stack_size")]
+    pub stack_size: u32,
+}
+
+#[derive(Clone, Debug)]
+pub struct ItemStack {
+    pub id: String,
+    pub definition: Arc<ItemDefinition>,
+    pub count: u32,
+}
+
+// Definitions are loaded once and shared by the stacks
+#[derive(Default)]
+pub struct ItemRegistry {
+    items: BTreeMap<String, Arc<ItemDefinition>>,
+}
+
+// Fixed number of slots, each holding up to a stack of one item
+#[derive(Clone, Debug)]
+pub struct Inventory {
+    slots: Vec<Option<ItemStack>>,
+}
+
+// Taken into the inventory of an entity which comes close enough, the pickup entity is
+// despawned once all of it is taken
+#[derive(Clone, Debug)]
+pub struct Pickup {
+    pub stack: ItemStack,
+    pub radius: f32,
+    // Only picked up while the interact action is held
+    pub requires_interaction: bool,
+}
+
+#[derive(Default)]
+pub struct PickupSystem;
+
+fn default_stack_size() -> u32 {
+    1
+}
+
+impl ItemDefinition {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path).map_err(|err| Error::file(path, err))?;
+        let definition: Self =
+            toml::from_str(&text).map_err(|err| Error::asset_parse(path, err))?;
+        if definition.stack_size == 0 {
+            return Err(Error::asset_parse(path, "stack_size must be at least 1"));
+        }
+        Ok(definition)
+    }
+
+    pub fn load_by_name(id: &str) -> Result<Self, Error> {
+        let mut path = PathBuf::from(ITEM_DIRECTORY);
+        path.push(format!("{}.toml", id));
+        Self::load(path)
+    }
+}
+
+impl ItemRegistry {
+    pub fn get_or_load(&mut self, id: &str) -> Result<Arc<ItemDefinition>, Error> {
+        if let Some(definition) = self.items.get(id) {
+            return Ok(definition.clone());
+        }
+
+        let definition = Arc::new(ItemDefinition::load_by_name(id)?);
+        self.items.insert(id.to_owned(), definition.clone());
+        Ok(definition)
+    }
+
+    pub fn stack(&mut self, id: &str, count: u32) -> Result<ItemStack, Error> {
+        Ok(ItemStack {
+            id: id.to_owned(),
+            definition: self.get_or_load(id)?,
+            count,
+        })
+    }
+}
+
+impl Inventory {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            slots: vec![None; capacity],
+        }
+    }
+
+    #[inline]
+    pub fn slots(&self) -> &[Option<ItemStack>] {
+        &self.slots
+    }
+
+    pub fn count(&self, id: &str) -> u32 {
+        self.stacks()
+            .filter(|stack| stack.id == id)
+            .map(|stack| stack.count)
+            .sum()
+    }
+
+    pub fn stacks(&self) -> impl Iterator<Item = &ItemStack> {
+        self.slots.iter().flatten()
+    }
+
+    // Tops up the existing stacks of the item first, then fills empty slots. Returns the
+    // number of items which didn't fit
+    pub fn add(&mut self, stack: &ItemStack) -> u32 {
+        let stack_size = stack.definition.stack_size;
+        let mut remaining = stack.count;
+
+        for existing in self.slots.iter_mut().flatten() {
+            if remaining == 0 {
+                break;
+            }
+            if existing.id == stack.id && existing.count < stack_size {
+                let moved = remaining.min(stack_size - existing.count);
+                existing.count += moved;
+                remaining -= moved;
+            }
+        }
+        for slot in self.slots.iter_mut().filter(|slot| slot.is_none()) {
+            if remaining == 0 {
+                break;
+            }
+            let moved = remaining.min(stack_size);
+            *slot = Some(ItemStack {
+                count: moved,
+                ..stack.clone()
+            });
+            remaining -= moved;
+        }
+
+        remaining
+    }
+
+    // Returns the number of items actually removed
+    pub fn remove(&mut self, id: &str, count: u32) -> u32 {
+        let mut removed = 0;
+        for slot in self.slots.iter_mut().rev() {
+            if removed == count {
+                break;
+            }
+            if let Some(stack) = slot.as_mut().filter(|stack| stack.id == id) {
+                let taken = stack.count.min(count - removed);
+                stack.count -= taken;
+                removed += taken;
+                if stack.count == 0 {
+                    *slot = None;
+                }
+            }
+        }
+        removed
+    }
+}
+
+impl Pickup {
+    pub fn new(stack: ItemStack, radius: f32) -> Self {
+        Self {
+            stack,
+            radius,
+            requires_interaction: false,
+        }
+    }
+
+    pub fn on_interaction(mut self) -> Self {
+        self.requires_interaction = true;
+        self
+    }
+}
+
+impl PickupSystem {
+    // Moves pickups in range into inventories, nearest pickups first for each holder
+    pub fn update(&mut self, scene: &mut Scene, interacting: bool) -> Vec<GameEvent> {
+        let holders: Vec<_> = scene
+            .entities()
+            .filter(|entity| entity.components().contains::<Inventory>())
+            .map(|entity| (entity.id(), *entity.position()))
+            .collect();
+        if holders.is_empty() {
+            return vec![];
+        }
+
+        let mut events = vec![];
+        let mut emptied = vec![];
+        for (holder, position) in holders {
+            let mut in_range: Vec<(f32, EntityId)> = scene
+                .entities()
+                .filter_map(|entity| {
+                    let pickup = entity.components().get::<Pickup>()?;
+                    let distance = (entity.position() - position).norm();
+                    let usable = distance <= pickup.radius
+                        && (interacting || !pickup.requires_interaction)
+                        && !emptied.contains(&entity.id());
+                    usable.then(|| (distance, entity.id()))
+                })
+                .collect();
+            in_range.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+            for (_, id) in in_range {
+                let mut stack = match scene
+                    .get(id)
+                    .and_then(|entity| entity.components().get::<Pickup>())
+                {
+                    Some(pickup) => pickup.stack.clone(),
+                    None => continue,
+                };
+                let inventory = scene
+                    .get_mut(holder)
+                    .and_then(|entity| entity.components_mut().get_mut::<Inventory>())
+                    .unwrap();
+                let left = inventory.add(&stack);
+                let taken = stack.count - left;
+                if taken == 0 {
+                    continue;
+                }
+
+                events.push(GameEvent::ItemPickedUp {
+                    entity: holder,
+                    item: stack.id.clone(),
+                    count: taken,
+                });
+                stack.count = left;
+                if left == 0 {
+                    emptied.push(id);
+                } else if let Some(pickup) = scene
+                    .get_mut(id)
+                    .and_then(|entity| entity.components_mut().get_mut::<Pickup>())
+                {
+                    pickup.stack = stack;
+                }
+            }
+        }
+
+        scene.despawn_batch(&emptied);
+        events
+    }
+}
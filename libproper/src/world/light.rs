@@ -0,0 +1,106 @@
+use nalgebra::{Point3, Vector3};
+
+#[derive(Clone)]
+pub struct DirectionalLight {
+    pub direction: Vector3<f32>,
+    pub color: Vector3<f32>,
+    pub ambient: f32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LightKind {
+    // Shines in every direction, shadowed with a cube map
+    Point,
+    // Cone around the direction. Angles are measured from its axis, in radians, the light
+    // fades out between the inner and the outer one
+    Spot {
+        direction: Vector3<f32>,
+        inner_angle: f32,
+        outer_angle: f32,
+    },
+}
+
+// Light with a position, falls off to nothing at its range
+#[derive(Clone, Debug)]
+pub struct LocalLight {
+    pub position: Point3<f32>,
+    pub kind: LightKind,
+    pub color: Vector3<f32>,
+    pub intensity: f32,
+    pub range: f32,
+    // Whether it asks for shadow maps, the light budget decides if it gets them
+    pub cast_shadows: bool,
+}
+
+impl Default for DirectionalLight {
+    fn default() -> Self {
+        Self {
+            direction: Vector3::new(-1.0, -1.0, -1.0),
+            color: Vector3::new(1.0, 1.0, 1.0),
+            ambient: 0.1,
+        }
+    }
+}
+
+impl LocalLight {
+    pub fn point(position: Point3<f32>, color: Vector3<f32>, intensity: f32, range: f32) -> Self {
+        Self {
+            position,
+            kind: LightKind::Point,
+            color,
+            intensity,
+            range,
+            cast_shadows: false,
+        }
+    }
+
+    // The inner angle is set to 80% of the outer one, see with_cone()
+    pub fn spot(
+        position: Point3<f32>,
+        direction: Vector3<f32>,
+        outer_angle: f32,
+        color: Vector3<f32>,
+        intensity: f32,
+        range: f32,
+    ) -> Self {
+        Self {
+            position,
+            kind: LightKind::Spot {
+                direction,
+                inner_angle: outer_angle * 0.8,
+                outer_angle,
+            },
+            color,
+            intensity,
+            range,
+            cast_shadows: false,
+        }
+    }
+
+    pub fn with_shadows(mut self, cast_shadows: bool) -> Self {
+        self.cast_shadows = cast_shadows;
+        self
+    }
+
+    // No effect on point lights
+    pub fn with_cone(mut self, inner: f32, outer: f32) -> Self {
+        if let LightKind::Spot {
+            inner_angle,
+            outer_angle,
+            ..
+        } = &mut self.kind
+        {
+            *inner_angle = inner.min(outer);
+            *outer_angle = outer;
+        }
+        self
+    }
+
+    // Cube faces for point lights, a single perspective map for spot lights
+    pub const fn shadow_map_count(&self) -> usize {
+        match self.kind {
+            LightKind::Point => 6,
+            LightKind::Spot { .. } => 1,
+        }
+    }
+}
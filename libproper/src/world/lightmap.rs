@@ -0,0 +1,595 @@
+use std::{
+    f32::consts::PI,
+    path::{Path, PathBuf},
+};
+
+use image::RgbaImage;
+use nalgebra::{Point2, Point3, Vector2, Vector3};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use rayon::prelude::*;
+
+use crate::{
+    error::Error,
+    render::shader::ShaderVariant,
+    resource::{material::MaterialRegistry, texture::TextureRegistry},
+};
+
+use super::{
+    component::StaticGeometry,
+    entity::{Entity, EntityId},
+    light::{LightKind, LocalLight},
+    ray::Ray,
+    scene::Scene,
+    spatial::Aabb,
+};
+
+pub const LIGHTMAP_DIRECTORY: &str = "res/lightmaps";
+// Lightmapped entities are switched to this material
+const LIGHTMAP_MATERIAL: &str = "simple";
+// Rays leave surfaces this far above them, against self-intersection
+const RAY_OFFSET: f32 = 1e-3;
+// Texels whose centers are this far outside of a triangle, in texels, still belong to it
+const TEXEL_MARGIN: f32 = 0.75;
+const BVH_LEAF_SIZE: usize = 4;
+
+// Entity with baked lighting, the lightmap is <LIGHTMAP_DIRECTORY>/<name>.png. The model has
+// to be imported with lightmap_coords
+#[derive(Clone)]
+pub struct LightmapRef {
+    pub name: String,
+}
+
+#[derive(Clone, Debug)]
+pub struct LightmapBakeSettings {
+    // Width and height of every lightmap
+    pub resolution: u32,
+    // Paths traced per texel
+    pub samples: u32,
+    // Extra bounces after the first hit
+    pub bounces: u32,
+    // Light coming from rays which escape the scene, the scene's ambient light if None
+    pub sky_color: Option<Vector3<f32>>,
+}
+
+// Indirect light reaching the surface, linear. The shader uses it in place of the ambient term
+pub struct Lightmap {
+    size: u32,
+    texels: Vec<Vector3<f32>>,
+}
+
+// Triangle of the bake scene, in world space
+struct BakeTriangle {
+    points: [Point3<f32>; 3],
+    normal: Vector3<f32>,
+    // Material color, textures are not read back for the bake
+    albedo: Vector3<f32>,
+}
+
+struct BvhNode {
+    bounds: Aabb,
+    // Leaves hold triangles first..first + count. Inner nodes have a count of 0, their left
+    // child follows them and the right one is at first
+    first: usize,
+    count: usize,
+}
+
+// Bounding volume hierarchy over the bake triangles, split at the median of the longest axis
+struct Bvh {
+    nodes: Vec<BvhNode>,
+    triangles: Vec<BakeTriangle>,
+}
+
+// Scene as seen by the path tracer
+struct BakeScene {
+    bvh: Bvh,
+    sun_direction: Vector3<f32>,
+    sun_color: Vector3<f32>,
+    sky_color: Vector3<f32>,
+    lights: Vec<LocalLight>,
+    bounces: u32,
+}
+
+impl Default for LightmapBakeSettings {
+    fn default() -> Self {
+        Self {
+            resolution: 128,
+            samples: 64,
+            bounces: 1,
+            sky_color: None,
+        }
+    }
+}
+
+impl Lightmap {
+    #[inline]
+    pub const fn size(&self) -> u32 {
+        self.size
+    }
+
+    // Rows go top to bottom, as the texture expects
+    pub fn texel(&self, x: u32, y: u32) -> Vector3<f32> {
+        self.texels[(y * self.size + x) as usize]
+    }
+
+    // 8-bit sRGB-encoded, the texture is loaded as sRGB so the shader sees linear values.
+    // Light over 1 is clamped
+    pub fn to_image(&self) -> RgbaImage {
+        let encode = |v: f32| {
+            let v = v.clamp(0.0, 1.0);
+            let v = if v <= 0.003_130_8 {
+                v * 12.92
+            } else {
+                1.055 * v.powf(1.0 / 2.4) - 0.055
+            };
+            (v * 255.0).round() as u8
+        };
+        RgbaImage::from_fn(self.size, self.size, |x, y| {
+            let texel = self.texel(x, y);
+            image::Rgba([encode(texel.x), encode(texel.y), encode(texel.z), 255])
+        })
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        let path = path.as_ref();
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).map_err(|err| Error::file(dir, err))?;
+        }
+        self.to_image().save(path).map_err(|err| match err {
+            image::ImageError::IoError(err) => Error::file(path, err),
+            err => Error::asset_parse(path, err),
+        })
+    }
+
+    // Texels no triangle covers take the average of their covered neighbours, so filtering at
+    // the chart edges doesn't pull in black
+    fn dilate(size: u32, texels: &mut [Option<Vector3<f32>>]) {
+        let previous = texels.to_vec();
+        for y in 0..size as i32 {
+            for x in 0..size as i32 {
+                let index = (y * size as i32 + x) as usize;
+                if previous[index].is_some() {
+                    continue;
+                }
+                let mut sum = Vector3::zeros();
+                let mut count = 0;
+                for (dx, dy) in (-1..=1).flat_map(|dy| (-1..=1).map(move |dx| (dx, dy))) {
+                    let (nx, ny) = (x + dx, y + dy);
+                    if nx < 0 || ny < 0 || nx >= size as i32 || ny >= size as i32 {
+                        continue;
+                    }
+                    if let Some(texel) = previous[(ny * size as i32 + nx) as usize] {
+                        sum += texel;
+                        count += 1;
+                    }
+                }
+                if count > 0 {
+                    texels[index] = Some(sum / count as f32);
+                }
+            }
+        }
+    }
+}
+
+impl Bvh {
+    fn new(mut triangles: Vec<BakeTriangle>) -> Self {
+        let mut nodes = vec![];
+        if !triangles.is_empty() {
+            let count = triangles.len();
+            Self::build(&mut nodes, &mut triangles, 0, count);
+        }
+        Self { nodes, triangles }
+    }
+
+    // Appends the node for triangles first..first + count and its subtree, returns its index
+    fn build(
+        nodes: &mut Vec<BvhNode>,
+        triangles: &mut [BakeTriangle],
+        first: usize,
+        count: usize,
+    ) -> usize {
+        let range = &mut triangles[first..first + count];
+        let bounds = Aabb::from_points(range.iter().flat_map(|t| t.points.iter()))
+            .unwrap_or_else(|| Aabb::new(Point3::origin(), Point3::origin()));
+        let index = nodes.len();
+        nodes.push(BvhNode {
+            bounds,
+            first,
+            count,
+        });
+        if count <= BVH_LEAF_SIZE {
+            return index;
+        }
+
+        let axis = bounds.half_extents().imax();
+        let centroid = |t: &BakeTriangle| t.points[0][axis] + t.points[1][axis] + t.points[2][axis];
+        range.sort_unstable_by(|a, b| centroid(a).total_cmp(&centroid(b)));
+
+        // The left child is built right after its parent, at index + 1
+        let half = count / 2;
+        Self::build(nodes, triangles, first, half);
+        let right = Self::build(nodes, triangles, first + half, count - half);
+        nodes[index].first = right;
+        nodes[index].count = 0;
+        index
+    }
+
+    // Closest hit closer than max_distance, as the distance and the triangle
+    fn intersect(&self, ray: &Ray, max_distance: f32) -> Option<(f32, usize)> {
+        if self.nodes.is_empty() {
+            return None;
+        }
+        let mut closest: Option<(f32, usize)> = None;
+        let mut stack = vec![0];
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+            let limit = closest.map_or(max_distance, |(distance, _)| distance);
+            match ray.intersect_aabb(&node.bounds) {
+                Some(distance) if distance <= limit => (),
+                _ => continue,
+            }
+            if node.count == 0 {
+                stack.push(node.first);
+                stack.push(index + 1);
+                continue;
+            }
+            for triangle in node.first..node.first + node.count {
+                if let Some(distance) = ray.intersect_triangle(&self.triangles[triangle].points) {
+                    let limit = closest.map_or(max_distance, |(distance, _)| distance);
+                    if distance < limit {
+                        closest = Some((distance, triangle));
+                    }
+                }
+            }
+        }
+        closest
+    }
+}
+
+impl BakeScene {
+    // Occluders are the static geometry and the lightmapped entities
+    fn new(scene: &Scene, settings: &LightmapBakeSettings) -> Self {
+        let mut triangles = vec![];
+        for entity in scene.entities().filter(|entity| {
+            let components = entity.components();
+            components.contains::<StaticGeometry>() || components.contains::<LightmapRef>()
+        }) {
+            let transform = entity.transform();
+            let color = entity
+                .mesh()
+                .material_create_info()
+                .color("diffuse_color")
+                .unwrap_or([1.0; 4]);
+            let albedo = Vector3::new(color[0], color[1], color[2]).map(|v| v.clamp(0.0, 1.0));
+            for triangle in entity.mesh().model().triangles() {
+                let points = triangle.map(|point| transform.transform_point(&point));
+                let normal = match (points[1] - points[0])
+                    .cross(&(points[2] - points[0]))
+                    .try_normalize(f32::EPSILON)
+                {
+                    Some(normal) => normal,
+                    None => continue,
+                };
+                triangles.push(BakeTriangle {
+                    points,
+                    normal,
+                    albedo,
+                });
+            }
+        }
+
+        let light = &scene.light;
+        Self {
+            bvh: Bvh::new(triangles),
+            sun_direction: light
+                .direction
+                .try_normalize(f32::EPSILON)
+                .unwrap_or_else(|| -Vector3::y()),
+            sun_color: light.color,
+            sky_color: settings
+                .sky_color
+                .unwrap_or_else(|| Vector3::repeat(light.ambient)),
+            lights: scene.lights.clone(),
+            bounces: settings.bounces,
+        }
+    }
+
+    fn occluded(&self, origin: &Point3<f32>, direction: Vector3<f32>, distance: f32) -> bool {
+        self.bvh
+            .intersect(&Ray::new(*origin, direction), distance)
+            .is_some()
+    }
+
+    // Same falloff and cones as lighting.glsl, with shadow rays in place of shadow maps
+    fn direct(&self, point: &Point3<f32>, normal: &Vector3<f32>) -> Vector3<f32> {
+        let origin = point + normal * RAY_OFFSET;
+        let mut light = Vector3::zeros();
+
+        let cos_theta = normal.dot(&-self.sun_direction);
+        if cos_theta > 0.0 && !self.occluded(&origin, -self.sun_direction, f32::MAX) {
+            light += self.sun_color * cos_theta;
+        }
+
+        for local in self.lights.iter() {
+            let to_light = local.position - point;
+            let distance = to_light.norm();
+            if distance >= local.range || local.intensity <= 0.0 {
+                continue;
+            }
+            let l = to_light / distance.max(1e-4);
+            let cos_theta = normal.dot(&l);
+            if cos_theta <= 0.0 {
+                continue;
+            }
+            let window = (1.0 - (distance / local.range).powi(4)).clamp(0.0, 1.0);
+            let attenuation = window * window / (distance * distance + 1.0);
+            let cone = match local.kind {
+                LightKind::Point => 1.0,
+                LightKind::Spot {
+                    direction,
+                    inner_angle,
+                    outer_angle,
+                } => {
+                    let direction = direction
+                        .try_normalize(f32::EPSILON)
+                        .unwrap_or_else(|| -Vector3::y());
+                    smoothstep(outer_angle.cos(), inner_angle.cos(), (-l).dot(&direction))
+                }
+            };
+            if cone > 0.0 && !self.occluded(&origin, l, distance - RAY_OFFSET) {
+                light += local.color * local.intensity * attenuation * cone * cos_theta;
+            }
+        }
+        light
+    }
+
+    // Light arriving along the ray: the sky if it escapes, otherwise what the hit surface
+    // reflects of its direct light and, bounces permitting, of its own indirect light
+    fn radiance(&self, ray: &Ray, depth: u32, rng: &mut StdRng) -> Vector3<f32> {
+        let (distance, index) = match self.bvh.intersect(ray, f32::MAX) {
+            Some(hit) => hit,
+            None => return self.sky_color,
+        };
+        let triangle = &self.bvh.triangles[index];
+        let point = ray.at(distance);
+        let normal = if triangle.normal.dot(&ray.direction) > 0.0 {
+            -triangle.normal
+        } else {
+            triangle.normal
+        };
+
+        let mut light = self.direct(&point, &normal);
+        if depth < self.bounces {
+            let bounce = Ray::new(point + normal * RAY_OFFSET, cosine_sample(&normal, rng));
+            light += self.radiance(&bounce, depth + 1, rng);
+        }
+        triangle.albedo.component_mul(&light)
+    }
+
+    // Cosine-weighted, so the average is the irradiance over pi the shader expects
+    fn irradiance(
+        &self,
+        point: &Point3<f32>,
+        normal: &Vector3<f32>,
+        samples: u32,
+        rng: &mut StdRng,
+    ) -> Vector3<f32> {
+        let origin = point + normal * RAY_OFFSET;
+        let mut sum = Vector3::zeros();
+        for _ in 0..samples {
+            sum += self.radiance(&Ray::new(origin, cosine_sample(normal, rng)), 0, rng);
+        }
+        sum / samples.max(1) as f32
+    }
+}
+
+// Traces the indirect light of every entity with a LightmapRef and lightmap coordinates.
+// Offline: slow for anything but low resolutions and sample counts, run from a tool or the
+// console and load the results with apply_lightmaps()
+pub fn bake_lightmaps(scene: &Scene, settings: &LightmapBakeSettings) -> Vec<(EntityId, Lightmap)> {
+    let _span = tracing::info_span!("bake_lightmaps").entered();
+    let bake = BakeScene::new(scene, settings);
+    log::info!("Baking lightmaps: {} triangles", bake.bvh.triangles.len());
+
+    scene
+        .entities()
+        .filter(|entity| {
+            entity.components().contains::<LightmapRef>()
+                && !entity.mesh().model().lightmap_coords().is_empty()
+        })
+        .map(|entity| (entity.id(), bake_entity(&bake, entity, settings)))
+        .collect()
+}
+
+fn bake_entity(bake: &BakeScene, entity: &Entity, settings: &LightmapBakeSettings) -> Lightmap {
+    let size = settings.resolution.max(1);
+    let transform = entity.transform();
+    // Mirroring flips the winding, and with it the face normals
+    let winding = transform.fixed_slice::<3, 3>(0, 0).determinant().signum();
+
+    // Surface point and normal of every covered texel, from the triangle it's most inside of
+    let mut surface: Vec<Option<(f32, Point3<f32>, Vector3<f32>)>> =
+        vec![None; (size * size) as usize];
+    let model = entity.mesh().model();
+    for (points, coords) in model
+        .triangles()
+        .zip(model.lightmap_coords().chunks_exact(3))
+    {
+        let points = points.map(|point| transform.transform_point(&point));
+        let normal = match ((points[1] - points[0]).cross(&(points[2] - points[0])) * winding)
+            .try_normalize(f32::EPSILON)
+        {
+            Some(normal) => normal,
+            None => continue,
+        };
+        let texels = [coords[0], coords[1], coords[2]].map(|coord| coord * size as f32);
+        let min = texels
+            .iter()
+            .fold(Point2::new(f32::MAX, f32::MAX), |m, p| m.inf(p));
+        let max = texels
+            .iter()
+            .fold(Point2::new(f32::MIN, f32::MIN), |m, p| m.sup(p));
+        let (x0, y0) = ((min.x - 1.0).max(0.0) as u32, (min.y - 1.0).max(0.0) as u32);
+        let (x1, y1) = (
+            ((max.x + 1.0) as u32).min(size - 1),
+            ((max.y + 1.0) as u32).min(size - 1),
+        );
+
+        for y in y0..=y1 {
+            for x in x0..=x1 {
+                let center = Point2::new(x as f32 + 0.5, y as f32 + 0.5);
+                let (inside, weights) = match coverage(&texels, &center) {
+                    Some(coverage) => coverage,
+                    None => continue,
+                };
+                let texel = &mut surface[(y * size + x) as usize];
+                if texel.map_or(false, |(best, _, _)| best >= inside) {
+                    continue;
+                }
+                let position = Point3::from(
+                    points[0].coords * weights[0]
+                        + points[1].coords * weights[1]
+                        + points[2].coords * weights[2],
+                );
+                *texel = Some((inside, position, normal));
+            }
+        }
+    }
+
+    let seed = entity.id().0;
+    let baked: Vec<Option<Vector3<f32>>> = surface
+        .par_iter()
+        .enumerate()
+        .map(|(index, texel)| {
+            texel.map(|(_, position, normal)| {
+                let mut rng =
+                    StdRng::seed_from_u64(seed.wrapping_mul(0x9e37_79b9_7f4a_7c15) ^ index as u64);
+                bake.irradiance(&position, &normal, settings.samples, &mut rng)
+            })
+        })
+        .collect();
+
+    let mut texels = baked;
+    Lightmap::dilate(size, &mut texels);
+    Lightmap::dilate(size, &mut texels);
+    Lightmap {
+        size,
+        texels: texels
+            .into_iter()
+            .map(|texel| texel.unwrap_or(bake.sky_color))
+            .collect(),
+    }
+}
+
+// How far the point is inside the triangle, in texels, and its clamped barycentric weights.
+// None if it's further outside than TEXEL_MARGIN
+fn coverage(triangle: &[Point2<f32>; 3], point: &Point2<f32>) -> Option<(f32, [f32; 3])> {
+    let area = cross(&(triangle[1] - triangle[0]), &(triangle[2] - triangle[0]));
+    if area.abs() <= f32::EPSILON {
+        return None;
+    }
+    let mut inside = f32::MAX;
+    let mut weights = [0.0; 3];
+    for i in 0..3 {
+        let (a, b) = (triangle[(i + 1) % 3], triangle[(i + 2) % 3]);
+        let edge = b - a;
+        // Signed so the inside is positive for either winding
+        let signed = cross(&edge, &(point - a)) * area.signum();
+        inside = inside.min(signed / edge.norm().max(f32::EPSILON));
+        weights[i] = (signed / area.abs()).max(0.0);
+    }
+    if inside < -TEXEL_MARGIN {
+        return None;
+    }
+    let sum: f32 = weights.iter().sum();
+    Some((inside, weights.map(|weight| weight / sum.max(f32::EPSILON))))
+}
+
+// Loads the entity's lightmap and switches its submeshes to the lightmapped variant of the
+// simple material, keeping their parameters. False if it has no LightmapRef or the lightmap
+// hasn't been baked yet
+pub fn apply_lightmap(
+    entity: &mut Entity,
+    textures: &mut TextureRegistry,
+    materials: &mut MaterialRegistry,
+) -> Result<bool, Error> {
+    let name = match entity.components().get::<LightmapRef>() {
+        Some(lightmap) => lightmap.name.clone(),
+        None => return Ok(false),
+    };
+    let path = lightmap_path(&name);
+    if !path.exists() {
+        log::warn!("No lightmap baked for {:?}", name);
+        return Ok(false);
+    }
+    let texture = textures.load_from_path(&format!("lightmap/{}", name), &path)?;
+
+    let mesh = entity.mesh_mut();
+    for index in 0..mesh.model().submeshes().len() {
+        let create_info = match mesh.submesh_material_override(index) {
+            Some(material) => material.create_info.clone(),
+            None => mesh.material_create_info().clone(),
+        };
+        let variant = ShaderVariant::default()
+            .with_value(ShaderVariant::LIGHTMAP, 1)
+            .with_value(
+                ShaderVariant::HAS_DIFFUSE_MAP,
+                create_info.texture("diffuse_map").is_some() as i32,
+            )
+            .with_value(
+                ShaderVariant::HAS_NORMAL_MAP,
+                create_info.texture("normal_map").is_some() as i32,
+            );
+        let template = materials.get_or_load_variant(LIGHTMAP_MATERIAL, &variant)?;
+        mesh.set_submesh_material(
+            index,
+            template,
+            create_info.with_texture("lightmap", texture.clone()),
+        )?;
+    }
+    Ok(true)
+}
+
+// apply_lightmap() for the whole scene, returns how many were applied
+pub fn apply_lightmaps(
+    scene: &mut Scene,
+    textures: &mut TextureRegistry,
+    materials: &mut MaterialRegistry,
+) -> Result<usize, Error> {
+    let mut applied = 0;
+    for entity in scene.entities_mut() {
+        if apply_lightmap(entity, textures, materials)? {
+            applied += 1;
+        }
+    }
+    Ok(applied)
+}
+
+#[inline]
+pub fn lightmap_path(name: &str) -> PathBuf {
+    Path::new(LIGHTMAP_DIRECTORY).join(format!("{}.png", name))
+}
+
+#[inline]
+fn cross(a: &Vector2<f32>, b: &Vector2<f32>) -> f32 {
+    a.x * b.y - a.y * b.x
+}
+
+#[inline]
+fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+fn cosine_sample(normal: &Vector3<f32>, rng: &mut StdRng) -> Vector3<f32> {
+    let (r1, r2): (f32, f32) = (rng.gen(), rng.gen());
+    let phi = 2.0 * PI * r1;
+    let r = r2.sqrt();
+    let other = if normal.x.abs() < 0.9 {
+        Vector3::x()
+    } else {
+        Vector3::y()
+    };
+    let tangent = normal.cross(&other).normalize();
+    let bitangent = normal.cross(&tangent);
+    tangent * (r * phi.cos()) + bitangent * (r * phi.sin()) + normal * (1.0 - r2).max(0.0).sqrt()
+}
@@ -1,3 +1,32 @@
+pub mod animation;
 pub mod camera;
+#[cfg(feature = "physics")]
+pub mod collision;
+#[cfg(feature = "physics")]
+pub mod collision_mesh;
+pub mod combat;
+pub mod component;
+pub mod cutscene;
+pub mod description;
 pub mod entity;
+pub mod interaction;
+pub mod inventory;
+pub mod light;
+pub mod lightmap;
+pub mod morph;
+pub mod motion;
+pub mod nav;
+pub mod pixel;
+pub mod ray;
 pub mod scene;
+pub mod scenes;
+pub mod schedule;
+pub mod spawner;
+pub mod spatial;
+pub mod sprite;
+pub mod stats;
+pub mod streaming;
+pub mod tilemap;
+pub mod trigger;
+pub mod validate;
+pub mod voxel;
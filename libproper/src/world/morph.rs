@@ -0,0 +1,108 @@
+use crate::error::Error;
+
+use super::schedule::{System, SystemAccess, SystemContext};
+
+#[derive(Clone, Copy, Debug)]
+struct MorphTween {
+    target: usize,
+    from: f32,
+    to: f32,
+    duration: f32,
+    time: f32,
+}
+
+// Blend shape weights of an entity, indexed like the morph targets of its model. The mesh is
+// only re-blended when they change
+#[derive(Clone, Debug, Default)]
+pub struct MorphWeights {
+    weights: Vec<f32>,
+    tweens: Vec<MorphTween>,
+    dirty: bool,
+}
+
+#[derive(Default)]
+pub struct MorphSystem;
+
+impl MorphWeights {
+    #[inline]
+    pub fn weights(&self) -> &[f32] {
+        &self.weights
+    }
+
+    pub fn get(&self, target: usize) -> f32 {
+        self.weights.get(target).copied().unwrap_or(0.0)
+    }
+
+    // Stops the tween of the target, if there's one
+    pub fn set(&mut self, target: usize, weight: f32) {
+        self.tweens.retain(|tween| tween.target != target);
+        self.set_weight(target, weight);
+    }
+
+    // Linear transition from the current weight
+    pub fn tween(&mut self, target: usize, weight: f32, duration: f32) {
+        if duration <= 0.0 {
+            self.set(target, weight);
+            return;
+        }
+        let from = self.get(target);
+        self.tweens.retain(|tween| tween.target != target);
+        self.tweens.push(MorphTween {
+            target,
+            from,
+            to: weight,
+            duration,
+            time: 0.0,
+        });
+    }
+
+    pub fn is_tweening(&self) -> bool {
+        !self.tweens.is_empty()
+    }
+
+    fn set_weight(&mut self, target: usize, weight: f32) {
+        if self.weights.len() <= target {
+            self.weights.resize(target + 1, 0.0);
+        }
+        if self.weights[target] != weight {
+            self.weights[target] = weight;
+            self.dirty = true;
+        }
+    }
+
+    fn advance(&mut self, dt: f32) {
+        let mut tweens = std::mem::take(&mut self.tweens);
+        for tween in &mut tweens {
+            tween.time = (tween.time + dt).min(tween.duration);
+            let t = tween.time / tween.duration;
+            self.set_weight(tween.target, tween.from + (tween.to - tween.from) * t);
+        }
+        tweens.retain(|tween| tween.time < tween.duration);
+        self.tweens = tweens;
+    }
+}
+
+impl System for MorphSystem {
+    fn access(&self) -> SystemAccess {
+        SystemAccess::default().write::<MorphWeights>()
+    }
+
+    fn run(&mut self, ctx: &mut SystemContext) -> Result<(), Error> {
+        for entity in ctx.entities.iter_mut() {
+            let weights = match entity.components_mut().get_mut::<MorphWeights>() {
+                Some(weights) => weights,
+                None => continue,
+            };
+            weights.advance(ctx.delta);
+            if !weights.dirty {
+                continue;
+            }
+            weights.dirty = false;
+
+            let weights = weights.weights.clone();
+            entity.mesh_mut().apply_morph(&weights)?;
+        }
+
+        Ok(())
+    }
+}
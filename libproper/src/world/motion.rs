@@ -0,0 +1,93 @@
+use nalgebra::{UnitQuaternion, Vector3};
+
+use crate::error::Error;
+
+use super::scene::Scene;
+
+#[derive(Clone, Copy)]
+pub struct Kinematics {
+    pub linear_velocity: Vector3<f32>,
+    // Rotation axis scaled by angular speed in radians per second
+    pub angular_velocity: Vector3<f32>,
+    pub gravity: Option<Vector3<f32>>,
+    pub linear_damping: f32,
+    pub angular_damping: f32,
+}
+
+#[derive(Default)]
+pub struct MotionSystem;
+
+impl Default for Kinematics {
+    fn default() -> Self {
+        Self {
+            linear_velocity: Vector3::zeros(),
+            angular_velocity: Vector3::zeros(),
+            gravity: None,
+            linear_damping: 0.0,
+            angular_damping: 0.0,
+        }
+    }
+}
+
+impl Kinematics {
+    pub fn with_velocity(mut self, velocity: Vector3<f32>) -> Self {
+        self.linear_velocity = velocity;
+        self
+    }
+
+    pub fn with_angular_velocity(mut self, velocity: Vector3<f32>) -> Self {
+        self.angular_velocity = velocity;
+        self
+    }
+
+    pub fn with_gravity(mut self, gravity: Vector3<f32>) -> Self {
+        self.gravity = Some(gravity);
+        self
+    }
+
+    pub fn with_damping(mut self, linear: f32, angular: f32) -> Self {
+        self.linear_damping = linear;
+        self.angular_damping = angular;
+        self
+    }
+
+    pub fn is_resting(&self) -> bool {
+        self.gravity.is_none()
+            && self.linear_velocity == Vector3::zeros()
+            && self.angular_velocity == Vector3::zeros()
+    }
+
+    fn integrate(&mut self, dt: f32) -> (Vector3<f32>, UnitQuaternion<f32>) {
+        if let Some(gravity) = self.gravity {
+            self.linear_velocity += gravity * dt;
+        }
+
+        self.linear_velocity *= (1.0 - self.linear_damping * dt).max(0.0);
+        self.angular_velocity *= (1.0 - self.angular_damping * dt).max(0.0);
+
+        (
+            self.linear_velocity * dt,
+            UnitQuaternion::from_scaled_axis(self.angular_velocity * dt),
+        )
+    }
+}
+
+impl MotionSystem {
+    pub fn update(&self, scene: &mut Scene, dt: f32) -> Result<(), Error> {
+        for entity in scene.entities_mut() {
+            let mut kinematics = match entity.components().get::<Kinematics>() {
+                Some(kinematics) if !kinematics.is_resting() => *kinematics,
+                _ => continue,
+            };
+
+            let (translation, rotation) = kinematics.integrate(dt);
+            entity.components_mut().insert(kinematics);
+
+            let position = entity.position() + translation;
+            let rotation = rotation * entity.rotation();
+            entity.set_transform(position, rotation)?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,393 @@
+use std::{
+    cmp::Ordering,
+    collections::{BinaryHeap, HashMap},
+    fs::File,
+    io::BufReader,
+    path::Path,
+};
+
+use nalgebra::{Point3, Vector3};
+use obj::{Obj, Position};
+
+use crate::error::Error;
+
+use super::{component::StaticGeometry, entity::Entity, motion::Kinematics, scene::Scene};
+
+pub struct NavBakeSettings {
+    pub max_slope: f32,
+    pub weld_distance: f32,
+}
+
+pub struct NavMesh {
+    vertices: Vec<Point3<f32>>,
+    triangles: Vec<[u32; 3]>,
+    // (neighbour triangle, shared edge) per triangle
+    neighbours: Vec<Vec<(usize, [u32; 2])>>,
+}
+
+#[derive(Clone)]
+pub struct NavAgent {
+    pub speed: f32,
+    pub arrive_radius: f32,
+    path: Vec<Point3<f32>>,
+}
+
+#[derive(Default)]
+pub struct NavigationSystem;
+
+#[derive(PartialEq)]
+struct OpenNode {
+    cost: f32,
+    triangle: usize,
+}
+
+impl Default for NavBakeSettings {
+    fn default() -> Self {
+        Self {
+            max_slope: 45f32.to_radians(),
+            weld_distance: 0.01,
+        }
+    }
+}
+
+impl NavMesh {
+    pub fn new(vertices: Vec<Point3<f32>>, triangles: Vec<[u32; 3]>) -> Self {
+        let mut edges: HashMap<[u32; 2], Vec<usize>> = HashMap::new();
+        for (index, triangle) in triangles.iter().enumerate() {
+            for i in 0..3 {
+                let (a, b) = (triangle[i], triangle[(i + 1) % 3]);
+                edges.entry([a.min(b), a.max(b)]).or_default().push(index);
+            }
+        }
+
+        let mut neighbours = vec![vec![]; triangles.len()];
+        for (edge, shared) in edges {
+            for &a in shared.iter() {
+                for &b in shared.iter().filter(|&&b| b != a) {
+                    neighbours[a].push((b, edge));
+                }
+            }
+        }
+
+        Self {
+            vertices,
+            triangles,
+            neighbours,
+        }
+    }
+
+    // Bakes walkable triangles of entities marked with StaticGeometry
+    pub fn bake(scene: &Scene, settings: &NavBakeSettings) -> Self {
+        let min_up = settings.max_slope.cos();
+        let mut vertices = vec![];
+        let mut triangles = vec![];
+        let mut welded: HashMap<(i64, i64, i64), u32> = HashMap::new();
+
+        let mut weld = |point: Point3<f32>| {
+            let key = (point.coords / settings.weld_distance).map(|v| v.round() as i64);
+            *welded.entry((key.x, key.y, key.z)).or_insert_with(|| {
+                vertices.push(point);
+                (vertices.len() - 1) as u32
+            })
+        };
+
+        for entity in scene
+            .entities()
+            .filter(|e| e.components().contains::<StaticGeometry>())
+        {
+            let transform = entity.transform();
+            for [a, b, c] in entity.mesh().model().triangles() {
+                let (a, b, c) = (
+                    transform.transform_point(&a),
+                    transform.transform_point(&b),
+                    transform.transform_point(&c),
+                );
+                let normal = (b - a).cross(&(c - a));
+                if normal.norm_squared() <= f32::EPSILON
+                    || normal.normalize().dot(&Vector3::y()) < min_up
+                {
+                    continue;
+                }
+                let triangle = [weld(a), weld(b), weld(c)];
+                if triangle[0] != triangle[1]
+                    && triangle[1] != triangle[2]
+                    && triangle[0] != triangle[2]
+                {
+                    triangles.push(triangle);
+                }
+            }
+        }
+
+        log::info!("Baked navmesh: {} triangles", triangles.len());
+
+        Self::new(vertices, triangles)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let input = BufReader::new(File::open(path).map_err(|err| Error::file(path, err))?);
+        let obj: Obj<Position, u32> =
+            obj::load_obj(input).map_err(|err| Error::asset_parse(path, err))?;
+
+        let vertices = obj
+            .vertices
+            .iter()
+            .map(|v| Point3::from(v.position))
+            .collect();
+        let triangles = obj
+            .indices
+            .chunks_exact(3)
+            .map(|t| [t[0], t[1], t[2]])
+            .collect();
+
+        Ok(Self::new(vertices, triangles))
+    }
+
+    pub fn triangle_count(&self) -> usize {
+        self.triangles.len()
+    }
+
+    pub fn centroid(&self, triangle: usize) -> Point3<f32> {
+        let [a, b, c] = self.corners(triangle);
+        Point3::from((a.coords + b.coords + c.coords) / 3.0)
+    }
+
+    // Triangle under (or closest to) the point, searched in the XZ plane
+    pub fn find_triangle(&self, point: &Point3<f32>) -> Option<usize> {
+        let containing = (0..self.triangles.len())
+            .filter(|&t| {
+                let [a, b, c] = self.corners(t);
+                point_in_triangle_xz(point, &a, &b, &c)
+            })
+            .min_by(|&a, &b| {
+                let da = (self.centroid(a).y - point.y).abs();
+                let db = (self.centroid(b).y - point.y).abs();
+                da.total_cmp(&db)
+            });
+
+        containing.or_else(|| {
+            (0..self.triangles.len()).min_by(|&a, &b| {
+                let da = (self.centroid(a) - point).norm_squared();
+                let db = (self.centroid(b) - point).norm_squared();
+                da.total_cmp(&db)
+            })
+        })
+    }
+
+    pub fn find_path(&self, from: &Point3<f32>, to: &Point3<f32>) -> Option<Vec<Point3<f32>>> {
+        let start = self.find_triangle(from)?;
+        let goal = self.find_triangle(to)?;
+
+        let mut came_from: HashMap<usize, (usize, [u32; 2])> = HashMap::new();
+        let mut cost = HashMap::from([(start, 0.0f32)]);
+        let mut open = BinaryHeap::from([OpenNode {
+            cost: 0.0,
+            triangle: start,
+        }]);
+
+        while let Some(OpenNode { triangle, .. }) = open.pop() {
+            if triangle == goal {
+                break;
+            }
+
+            let current_cost = cost[&triangle];
+            for &(next, edge) in self.neighbours[triangle].iter() {
+                let next_cost =
+                    current_cost + (self.centroid(next) - self.centroid(triangle)).norm();
+                if cost.get(&next).map_or(true, |&c| next_cost < c) {
+                    cost.insert(next, next_cost);
+                    came_from.insert(next, (triangle, edge));
+                    open.push(OpenNode {
+                        cost: next_cost + (self.centroid(next) - to).norm(),
+                        triangle: next,
+                    });
+                }
+            }
+        }
+
+        if start != goal && !came_from.contains_key(&goal) {
+            return None;
+        }
+
+        // Walk back through the portals (shared edges), using their midpoints as waypoints
+        let mut path = vec![*to];
+        let mut current = goal;
+        while let Some(&(previous, [a, b])) = came_from.get(&current) {
+            let (a, b) = (self.vertices[a as usize], self.vertices[b as usize]);
+            path.push(nalgebra::center(&a, &b));
+            current = previous;
+        }
+        path.push(*from);
+        path.reverse();
+
+        Some(path)
+    }
+
+    fn corners(&self, triangle: usize) -> [Point3<f32>; 3] {
+        self.triangles[triangle].map(|i| self.vertices[i as usize])
+    }
+}
+
+impl NavAgent {
+    pub fn new(speed: f32) -> Self {
+        Self {
+            speed,
+            arrive_radius: 0.1,
+            path: vec![],
+        }
+    }
+
+    pub fn set_destination(
+        &mut self,
+        navmesh: &NavMesh,
+        from: &Point3<f32>,
+        to: &Point3<f32>,
+    ) -> bool {
+        match navmesh.find_path(from, to) {
+            Some(path) => {
+                self.path = path;
+                true
+            }
+            None => {
+                self.path.clear();
+                false
+            }
+        }
+    }
+
+    pub fn stop(&mut self) {
+        self.path.clear();
+    }
+
+    pub fn is_moving(&self) -> bool {
+        !self.path.is_empty()
+    }
+
+    pub fn path(&self) -> &[Point3<f32>] {
+        &self.path
+    }
+
+    // None once the path is done. The last step before a waypoint is shortened to land on it,
+    // a full one could overshoot the arrive radius
+    fn steer(&mut self, position: &Point3<f32>, dt: f32) -> Option<Vector3<f32>> {
+        while let Some(next) = self.path.first() {
+            let offset = next - position;
+            let distance = offset.norm();
+            if distance > self.arrive_radius {
+                return Some(offset / distance * self.speed.min(distance / dt));
+            }
+            self.path.remove(0);
+        }
+        None
+    }
+}
+
+impl NavigationSystem {
+    // Drives the Kinematics of every entity with a NavAgent along its path. Agents without one
+    // are left alone, their velocity is only cleared once when they arrive
+    pub fn update<'a, I: Iterator<Item = &'a mut Entity>>(&self, entities: I, dt: f32) {
+        for entity in entities {
+            let position = *entity.position();
+            let components = entity.components_mut();
+            let velocity = match components.get_mut::<NavAgent>() {
+                Some(agent) if agent.is_moving() => {
+                    agent.steer(&position, dt).unwrap_or_else(Vector3::zeros)
+                }
+                _ => continue,
+            };
+
+            let kinematics = components.get::<Kinematics>().copied().unwrap_or_default();
+            components.insert(Kinematics {
+                linear_velocity: velocity,
+                ..kinematics
+            });
+        }
+    }
+}
+
+impl Eq for OpenNode {}
+
+impl Ord for OpenNode {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // Reversed for a min-heap
+        other.cost.total_cmp(&self.cost)
+    }
+}
+
+impl PartialOrd for OpenNode {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+fn point_in_triangle_xz(
+    p: &Point3<f32>,
+    a: &Point3<f32>,
+    b: &Point3<f32>,
+    c: &Point3<f32>,
+) -> bool {
+    let sign = |p: &Point3<f32>, a: &Point3<f32>, b: &Point3<f32>| {
+        (p.x - b.x) * (a.z - b.z) - (a.x - b.x) * (p.z - b.z)
+    };
+    let d0 = sign(p, a, b);
+    let d1 = sign(p, b, c);
+    let d2 = sign(p, c, a);
+    let has_neg = d0 < 0.0 || d1 < 0.0 || d2 < 0.0;
+    let has_pos = d0 > 0.0 || d1 > 0.0 || d2 > 0.0;
+    !(has_neg && has_pos)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Two quads along X, plus a triangle which isn't connected to them
+    fn strip() -> NavMesh {
+        let vertices = vec![
+            Point3::new(0.0, 0.0, 0.0),
+            Point3::new(1.0, 0.0, 0.0),
+            Point3::new(2.0, 0.0, 0.0),
+            Point3::new(0.0, 0.0, 1.0),
+            Point3::new(1.0, 0.0, 1.0),
+            Point3::new(2.0, 0.0, 1.0),
+            Point3::new(10.0, 0.0, 0.0),
+            Point3::new(11.0, 0.0, 0.0),
+            Point3::new(10.0, 0.0, 1.0),
+        ];
+        let triangles = vec![[0, 1, 4], [0, 4, 3], [1, 2, 5], [1, 5, 4], [6, 7, 8]];
+        NavMesh::new(vertices, triangles)
+    }
+
+    #[test]
+    fn path_crosses_portals() {
+        let mesh = strip();
+        let from = Point3::new(0.2, 0.0, 0.8);
+        let to = Point3::new(1.8, 0.0, 0.2);
+        let path = mesh.find_path(&from, &to).unwrap();
+
+        assert_eq!(path.first(), Some(&from));
+        assert_eq!(path.last(), Some(&to));
+        // [0, 4, 3] -> [0, 1, 4] -> [1, 5, 4] -> [1, 2, 5]
+        assert_eq!(path.len(), 5);
+        assert_eq!(path[1], Point3::new(0.5, 0.0, 0.5));
+        assert_eq!(path[2], Point3::new(1.0, 0.0, 0.5));
+        assert_eq!(path[3], Point3::new(1.5, 0.0, 0.5));
+    }
+
+    #[test]
+    fn path_within_a_triangle() {
+        let mesh = strip();
+        let from = Point3::new(0.1, 0.0, 0.5);
+        let to = Point3::new(0.2, 0.0, 0.9);
+        assert_eq!(mesh.find_path(&from, &to), Some(vec![from, to]));
+    }
+
+    #[test]
+    fn no_path_between_islands() {
+        let mesh = strip();
+        let from = Point3::new(0.2, 0.0, 0.8);
+        let to = Point3::new(10.2, 0.0, 0.2);
+        assert_eq!(mesh.find_triangle(&to), Some(4));
+        assert_eq!(mesh.find_path(&from, &to), None);
+    }
+}
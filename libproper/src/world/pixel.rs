@@ -0,0 +1,61 @@
+use nalgebra::Point3;
+use winit::dpi::PhysicalSize;
+
+use super::camera::{Camera, Projection};
+
+// Keeps pixel art crisp with a 2D camera: every texel covers a whole number of screen pixels
+// and the camera only moves by whole pixels
+#[derive(Clone, Copy, Debug)]
+pub struct PixelPerfect {
+    // Texels per world unit of the sprites
+    pub pixels_per_unit: f32,
+    // Height in texels the game is designed for, the screen shows at least this much
+    pub reference_height: u32,
+}
+
+impl PixelPerfect {
+    pub fn new(pixels_per_unit: f32, reference_height: u32) -> Self {
+        Self {
+            pixels_per_unit,
+            reference_height,
+        }
+    }
+
+    // Screen pixels per texel, the largest one showing the whole reference height
+    pub fn scale(&self, window: PhysicalSize<u32>) -> u32 {
+        (window.height / self.reference_height.max(1)).max(1)
+    }
+
+    // World units across the screen height at the scale
+    pub fn view_height(&self, window: PhysicalSize<u32>) -> f32 {
+        window.height as f32 / (self.pixels_per_unit * self.scale(window) as f32)
+    }
+
+    // Size of one screen pixel in world units
+    pub fn pixel_size(&self, window: PhysicalSize<u32>) -> f32 {
+        1.0 / (self.pixels_per_unit * self.scale(window) as f32)
+    }
+
+    // Rounds to the pixel grid. With an odd window size the screen center falls in the middle
+    // of a pixel, so the grid is shifted by half a pixel on that axis
+    pub fn snap(&self, position: Point3<f32>, window: PhysicalSize<u32>) -> Point3<f32> {
+        let pixel = self.pixel_size(window);
+        let snap = |value: f32, size: u32| {
+            let offset = if size % 2 == 1 { pixel * 0.5 } else { 0.0 };
+            ((value - offset) / pixel).round() * pixel + offset
+        };
+        Point3::new(
+            snap(position.x, window.width),
+            snap(position.y, window.height),
+            position.z,
+        )
+    }
+
+    // Sets the orthographic height and snaps the camera, call after moving it each frame
+    pub fn apply(&self, camera: &mut Camera, window: PhysicalSize<u32>) {
+        camera.set_projection(Projection::Orthographic {
+            height: self.view_height(window),
+        });
+        camera.set_position(self.snap(*camera.position(), window));
+    }
+}
@@ -0,0 +1,110 @@
+use nalgebra::{Matrix4, Point2, Point3, Unit, Vector3};
+
+use super::spatial::Aabb;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Ray {
+    pub origin: Point3<f32>,
+    pub direction: Unit<Vector3<f32>>,
+}
+
+impl Ray {
+    pub fn new(origin: Point3<f32>, direction: Vector3<f32>) -> Self {
+        Self {
+            origin,
+            direction: Unit::new_normalize(direction),
+        }
+    }
+
+    // Through a point on the screen, given in normalized device coordinates: (-1, -1) is the
+    // top left corner
+    pub fn from_ndc(view_projection: &Matrix4<f32>, ndc: Point2<f32>) -> Option<Self> {
+        let inverse = view_projection.try_inverse()?;
+        let near = inverse.transform_point(&Point3::new(ndc.x, ndc.y, -1.0));
+        let far = inverse.transform_point(&Point3::new(ndc.x, ndc.y, 1.0));
+        Some(Self::new(near, far - near))
+    }
+
+    #[inline]
+    pub fn at(&self, t: f32) -> Point3<f32> {
+        self.origin + self.direction.into_inner() * t
+    }
+
+    // Distance along the ray, None if the plane is parallel to or behind it
+    pub fn intersect_plane(&self, point: &Point3<f32>, normal: &Vector3<f32>) -> Option<f32> {
+        let denominator = self.direction.dot(normal);
+        if denominator.abs() <= f32::EPSILON {
+            return None;
+        }
+        let t = (point - self.origin).dot(normal) / denominator;
+        (t >= 0.0).then(|| t)
+    }
+
+    // Parameter of the point on the line closest to the ray, None if they're parallel
+    pub fn closest_on_line(&self, origin: &Point3<f32>, direction: &Vector3<f32>) -> Option<f32> {
+        let w = self.origin - origin;
+        let b = self.direction.dot(direction);
+        let c = direction.norm_squared();
+        let d = self.direction.dot(&w);
+        let e = direction.dot(&w);
+        let denominator = c - b * b;
+        if denominator.abs() <= f32::EPSILON {
+            return None;
+        }
+        Some((e - b * d) / denominator)
+    }
+
+    // Distance to where the ray enters the box, 0 if it starts inside
+    pub fn intersect_aabb(&self, aabb: &Aabb) -> Option<f32> {
+        let mut near = 0.0f32;
+        let mut far = f32::INFINITY;
+        for i in 0..3 {
+            let inverse = 1.0 / self.direction[i];
+            let a = (aabb.min[i] - self.origin[i]) * inverse;
+            let b = (aabb.max[i] - self.origin[i]) * inverse;
+            near = near.max(a.min(b));
+            far = far.min(a.max(b));
+        }
+        (near <= far).then(|| near)
+    }
+
+    // Both sides of the triangle are hit
+    pub fn intersect_triangle(&self, [a, b, c]: &[Point3<f32>; 3]) -> Option<f32> {
+        let ab = b - a;
+        let ac = c - a;
+        let p = self.direction.cross(&ac);
+        let determinant = ab.dot(&p);
+        if determinant.abs() <= f32::EPSILON {
+            return None;
+        }
+
+        let inverse = 1.0 / determinant;
+        let offset = self.origin - a;
+        let u = offset.dot(&p) * inverse;
+        if !(0.0..=1.0).contains(&u) {
+            return None;
+        }
+        let q = offset.cross(&ab);
+        let v = self.direction.dot(&q) * inverse;
+        if v < 0.0 || u + v > 1.0 {
+            return None;
+        }
+
+        let t = ac.dot(&q) * inverse;
+        (t >= 0.0).then(|| t)
+    }
+
+    pub fn distance_to_segment(&self, a: &Point3<f32>, b: &Point3<f32>) -> f32 {
+        let ab = b - a;
+        let s = self.closest_on_line(a, &ab).unwrap_or(0.0).clamp(0.0, 1.0);
+        let point = a + ab * s;
+        let t = (point - self.origin).dot(&self.direction).max(0.0);
+        (point - self.at(t)).norm()
+    }
+}
+
+// Normalized device coordinates of a point, None if it's behind the camera
+pub fn project(view_projection: &Matrix4<f32>, point: &Point3<f32>) -> Option<Point2<f32>> {
+    let clip = view_projection * point.to_homogeneous();
+    (clip.w > f32::EPSILON).then(|| Point2::new(clip.x / clip.w, clip.y / clip.w))
+}
@@ -1,32 +1,50 @@
-use std::sync::{Arc, atomic::Ordering};
+use std::{collections::{HashMap, HashSet}, sync::{Arc, Mutex, atomic::Ordering}};
 
 use bytemuck::Zeroable;
-use nalgebra::Matrix4;
+use nalgebra::{Isometry3, Matrix4, Point3, UnitQuaternion, Vector3};
 use vulkano::{
-    buffer::{BufferUsage, CpuAccessibleBuffer},
-    descriptor_set::{PersistentDescriptorSet, WriteDescriptorSet},
-    device::Queue,
-    pipeline::Pipeline,
-    sync::GpuFuture,
+    buffer::{cpu_access::WriteLockError, BufferUsage, CpuAccessibleBuffer},
+    descriptor_set::DescriptorSetWithOffsets,
 };
 
 use crate::{
+    ai::behavior::AiController,
     error::Error,
-    render::shader,
+    lock::Recover,
+    render::{arena::{ArenaSlot, UniformArena}, effects::CameraEffects, grading::ColorGrading, memory::{AllocationCategory, GpuAllocation}, stats, uniforms::ModelUniform, upload::{UploadFuture, UploadId, UploadQueue}, Vertex},
     resource::{
         material::{MaterialInstance, MaterialInstanceCreateInfo, MaterialTemplate},
         model::Model,
     },
 };
 
-use super::{entity::Entity, camera::Camera};
+use super::{
+    component::{Component, Components, Persistent},
+    entity::{Entity, EntityId},
+    camera::Camera,
+    light::{DirectionalLight, LocalLight},
+    nav::{NavBakeSettings, NavMesh},
+    ray::Ray,
+    spawner::Spawner,
+    trigger::TriggerVolume,
+    voxel::VoxelWorld,
+};
 
 #[derive(Default)]
 pub struct Scene {
     // Renderable entities, sorted by material template
     pub camera: Camera,
+    pub camera_effects: CameraEffects,
+    pub color_grading: ColorGrading,
+    pub light: DirectionalLight,
+    pub lights: Vec<LocalLight>,
+    pub navmesh: Option<NavMesh>,
+    pub voxels: VoxelWorld,
+    pub triggers: Vec<TriggerVolume>,
+    pub spawners: Vec<Spawner>,
     pub data: Vec<MaterialEntityGroup>,
     pub loading_list: Vec<Entity>,
+    last_entity_id: u64,
 }
 
 pub struct MaterialEntityGroup {
@@ -35,10 +53,42 @@ pub struct MaterialEntityGroup {
 }
 
 pub struct MeshObject {
+    uploads: Arc<Mutex<UploadQueue>>,
     model: Arc<Model>,
-    model_buffer: Arc<CpuAccessibleBuffer<shader::simple_vs::ty::Model_Data>>,
-    model_set: Arc<PersistentDescriptorSet>,
+    model_slot: ArenaSlot<ModelUniform>,
+    material_template: Arc<dyn MaterialTemplate>,
+    material_create_info: MaterialInstanceCreateInfo,
     material_instance: MaterialInstance,
+    // Indexed by submesh, the ones without a material of their own use the one above
+    submesh_materials: Vec<Option<SubmeshMaterial>>,
+    // Blended vertices, drawn instead of the model's ones if it has morph targets
+    morph_buffer: Option<Arc<CpuAccessibleBuffer<[Vertex]>>>,
+    _morph_memory: Option<GpuAllocation>,
+}
+
+pub struct SubmeshMaterial {
+    pub template: Arc<dyn MaterialTemplate>,
+    pub create_info: MaterialInstanceCreateInfo,
+    pub instance: MaterialInstance,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RaycastHit {
+    pub entity: EntityId,
+    pub distance: f32,
+    pub point: Point3<f32>,
+    // Faces the ray origin
+    pub normal: Vector3<f32>,
+}
+
+// Everything needed to create an entity, used by Scene::spawn_batch()
+pub struct EntitySpec {
+    pub model: Arc<Model>,
+    pub material_template: Arc<dyn MaterialTemplate>,
+    pub material: MaterialInstanceCreateInfo,
+    pub position: Point3<f32>,
+    pub rotation: UnitQuaternion<f32>,
+    pub components: Components,
 }
 
 impl Scene {
@@ -51,14 +101,117 @@ impl Scene {
         self.data.iter_mut()
     }
 
-    pub fn add(&mut self, entity: Entity) {
+    pub fn entities(&self) -> impl Iterator<Item = &Entity> {
+        self.data.iter().flat_map(|group| group.entities.iter())
+    }
+
+    pub fn entities_mut(&mut self) -> impl Iterator<Item = &mut Entity> {
+        self.data.iter_mut().flat_map(|group| group.entities.iter_mut())
+    }
+
+    pub fn bake_navmesh(&mut self, settings: &NavBakeSettings) {
+        self.navmesh = Some(NavMesh::bake(self, settings));
+    }
+
+    pub fn get(&self, id: EntityId) -> Option<&Entity> {
+        self.entities().find(|entity| entity.id() == id)
+    }
+
+    pub fn get_mut(&mut self, id: EntityId) -> Option<&mut Entity> {
+        self.entities_mut().find(|entity| entity.id() == id)
+    }
+
+    // Closest entity triangle hit by the ray, entity bounds are tested first
+    pub fn raycast(&self, ray: &Ray, exclude: Option<EntityId>) -> Option<RaycastHit> {
+        let mut closest: Option<RaycastHit> = None;
+
+        for entity in self.entities() {
+            if Some(entity.id()) == exclude {
+                continue;
+            }
+            let bounds_distance = match ray.intersect_aabb(&entity.bounds()) {
+                Some(distance) => distance,
+                None => continue,
+            };
+            if closest.map_or(false, |hit| hit.distance < bounds_distance) {
+                continue;
+            }
+
+            let transform = entity.transform();
+            for triangle in entity.mesh().model().triangles() {
+                let triangle = triangle.map(|point| transform.transform_point(&point));
+                let distance = match ray.intersect_triangle(&triangle) {
+                    Some(distance) => distance,
+                    None => continue,
+                };
+                if closest.map_or(false, |hit| hit.distance <= distance) {
+                    continue;
+                }
+
+                let normal = (triangle[1] - triangle[0])
+                    .cross(&(triangle[2] - triangle[0]))
+                    .normalize();
+                closest = Some(RaycastHit {
+                    entity: entity.id(),
+                    distance,
+                    point: ray.at(distance),
+                    normal: if normal.dot(&ray.direction) > 0.0 { -normal } else { normal },
+                });
+            }
+        }
+
+        closest
+    }
+
+    pub fn remove(&mut self, id: EntityId) -> Option<Entity> {
+        for group in self.data.iter_mut() {
+            if let Some(index) = group.entities.iter().position(|e| e.id() == id) {
+                return Some(group.entities.remove(index));
+            }
+        }
+        None
+    }
+
+    pub fn add(&mut self, mut entity: Entity) -> EntityId {
+        self.last_entity_id += 1;
+        let id = EntityId(self.last_entity_id);
+        entity.set_id(id);
+        self.insert(entity);
+        id
+    }
+
+    // Keeps the ID of an entity from another scene, unless this one already has it
+    pub fn adopt(&mut self, entity: Entity) -> EntityId {
+        let id = entity.id();
+        if id == EntityId::default() || self.get(id).is_some() {
+            return self.add(entity);
+        }
+        self.last_entity_id = self.last_entity_id.max(id.0);
+        self.insert(entity);
+        id
+    }
+
+    // Removes the entities marked Persistent, to be adopted by the next scene
+    pub fn take_persistent(&mut self) -> Vec<Entity> {
+        let mut taken = vec![];
+        for group in self.data.iter_mut() {
+            let (mut persistent, kept): (Vec<_>, Vec<_>) = std::mem::take(&mut group.entities)
+                .into_iter()
+                .partition(|entity| entity.components().contains::<Persistent>());
+            group.entities = kept;
+            taken.append(&mut persistent);
+        }
+        taken
+    }
+
+    fn insert(&mut self, entity: Entity) {
         let material_template = entity.mesh().model().material_template();
-        let id = material_template.id().load(Ordering::Acquire);
+        let template_id = material_template.id().load(Ordering::Acquire);
 
         if let Some(group) = self
             .data
             .iter_mut()
-            .find(|p| id == p.material_template.id().load(Ordering::Acquire))
+            .find(|p| template_id == p.material_template.id().load(Ordering::Acquire))
         {
             group.entities.push(entity);
         } else {
@@ -68,6 +221,142 @@ impl Scene {
             });
         }
     }
+
+    // Moves the entities of another scene into this one under new IDs, e.g. to compose a level
+    // of several scene files. The offset is applied on top of their transforms. Entity
+    // references in AI blackboards are remapped, the returned map is for anything else
+    // holding IDs from the other scene
+    pub fn merge(&mut self, other: Scene, offset: Option<&Isometry3<f32>>) -> Result<HashMap<EntityId, EntityId>, Error> {
+        let mut ids = HashMap::new();
+        for group in other.data {
+            for mut entity in group.entities {
+                if let Some(offset) = offset {
+                    entity.set_transform(offset * entity.position(), offset.rotation * entity.rotation())?;
+                }
+                let old_id = entity.id();
+                ids.insert(old_id, self.add(entity));
+            }
+        }
+
+        for volume in other.triggers {
+            self.triggers.push(match offset {
+                Some(offset) => volume.transformed(offset),
+                None => volume,
+            });
+        }
+        for spawner in other.spawners {
+            self.spawners.push(match offset {
+                Some(offset) => spawner.transformed(offset),
+                None => spawner,
+            });
+        }
+
+        let merged: HashSet<EntityId> = ids.values().copied().collect();
+        for entity in self.entities_mut().filter(|entity| merged.contains(&entity.id())) {
+            if let Some(controller) = entity.components_mut().get_mut::<AiController>() {
+                controller.blackboard.remap_entities(&ids);
+            }
+        }
+
+        Ok(ids)
+    }
+
+    // Entities with equal material parameters share a material instance, and all of the
+    // initialization goes out as one upload with the next frame instead of one per entity.
+    // The returned upload is reported in GameEvent::UploadsCompleted
+    pub fn spawn_batch(
+        &mut self,
+        uploads: &Arc<Mutex<UploadQueue>>,
+        model_arena: &UniformArena<ModelUniform>,
+        specs: Vec<EntitySpec>,
+    ) -> Result<(Vec<EntityId>, Option<UploadId>), Error> {
+        let gfx_queue = uploads.lock().recover().queue().clone();
+        let mut instances: Vec<(u64, MaterialInstanceCreateInfo, MaterialInstance)> = vec![];
+        let mut inits: Vec<UploadFuture> = vec![];
+        let mut entities = Vec::with_capacity(specs.len());
+
+        for spec in specs {
+            let template_id = spec.material_template.id().load(Ordering::Acquire);
+            let existing = instances
+                .iter()
+                .find(|(id, info, _)| *id == template_id && *info == spec.material);
+            let material_instance = match existing {
+                Some((_, _, instance)) => instance.clone(),
+                None => {
+                    let (instance, init) = spec
+                        .material_template
+                        .create_instance(gfx_queue.clone(), spec.material.clone())?;
+                    inits.push(init);
+                    instances.push((template_id, spec.material.clone(), instance.clone()));
+                    instance
+                }
+            };
+
+            let mesh = MeshObject::with_instance(
+                uploads.clone(),
+                model_arena,
+                spec.model,
+                spec.material_template,
+                spec.material,
+                material_instance,
+            )?;
+            let mut entity = Entity::new_with_mesh(spec.position, mesh)?;
+            if spec.rotation != UnitQuaternion::identity() {
+                entity.set_rotation(spec.rotation)?;
+            }
+            entity.components_mut().append(spec.components);
+            entities.push(entity);
+        }
+
+        let upload = inits
+            .into_iter()
+            .reduce(|batch, init| -> UploadFuture { Box::new(batch.join(init)) })
+            .map(|init| uploads.lock().recover().push(init));
+        let ids = entities.into_iter().map(|entity| self.add(entity)).collect();
+
+        Ok((ids, upload))
+    }
+
+    // Removes all of the entities in one pass over the scene
+    pub fn despawn_batch(&mut self, ids: &[EntityId]) -> Vec<Entity> {
+        let mut removed = vec![];
+        for group in self.data.iter_mut() {
+            let (mut gone, kept): (Vec<_>, Vec<_>) = std::mem::take(&mut group.entities)
+                .into_iter()
+                .partition(|entity| ids.contains(&entity.id()));
+            group.entities = kept;
+            removed.append(&mut gone);
+        }
+        removed
+    }
+}
+
+impl EntitySpec {
+    pub fn new(
+        model: Arc<Model>,
+        material_template: Arc<dyn MaterialTemplate>,
+        material: MaterialInstanceCreateInfo,
+        position: Point3<f32>,
+    ) -> Self {
+        Self {
+            model,
+            material_template,
+            material,
+            position,
+            rotation: UnitQuaternion::identity(),
+            components: Components::default(),
+        }
+    }
+
+    pub fn with_rotation(mut self, rotation: UnitQuaternion<f32>) -> Self {
+        self.rotation = rotation;
+        self
+    }
+
+    pub fn with_component<T: Component>(mut self, component: T) -> Self {
+        self.components.insert(component);
+        self
+    }
 }
 
 impl MaterialEntityGroup {
@@ -79,35 +368,69 @@ impl MaterialEntityGroup {
 
 impl MeshObject {
     pub fn new(
-        gfx_queue: Arc<Queue>,
+        uploads: Arc<Mutex<UploadQueue>>,
+        model_arena: &UniformArena<ModelUniform>,
         model: Arc<Model>,
         material_template: Arc<dyn MaterialTemplate>,
         material_instance_create_info: MaterialInstanceCreateInfo,
     ) -> Result<Self, Error> {
-        let model_buffer = CpuAccessibleBuffer::from_data(
-            gfx_queue.device().clone(),
-            BufferUsage::uniform_buffer(),
-            false,
-            Zeroable::zeroed(),
-        )?;
+        let gfx_queue = uploads.lock().recover().queue().clone();
+        let (material_instance, init) = material_template
+            .create_instance(gfx_queue, material_instance_create_info.clone())?;
 
-        let pipeline_lock = material_template.pipeline().read().unwrap();
-        let model_layout = pipeline_lock.layout().set_layouts().get(2).unwrap();
-        let (material_instance, init) =
-            material_template.create_instance(gfx_queue, material_instance_create_info)?;
+        uploads.lock().recover().push(init);
 
-        init.then_signal_fence_and_flush()?.wait(None).unwrap();
+        Self::with_instance(
+            uploads,
+            model_arena,
+            model,
+            material_template,
+            material_instance_create_info,
+            material_instance,
+        )
+    }
+
+    // The material instance has to be created from the template and create info given
+    pub(crate) fn with_instance(
+        uploads: Arc<Mutex<UploadQueue>>,
+        model_arena: &UniformArena<ModelUniform>,
+        model: Arc<Model>,
+        material_template: Arc<dyn MaterialTemplate>,
+        material_instance_create_info: MaterialInstanceCreateInfo,
+        material_instance: MaterialInstance,
+    ) -> Result<Self, Error> {
+        let model_slot = model_arena.allocate()?;
+        model_slot.write(&ModelUniform::zeroed());
 
-        let model_set = PersistentDescriptorSet::new(
-            model_layout.clone(),
-            vec![WriteDescriptorSet::buffer(0, model_buffer.clone())],
-        )?;
+        let (morph_buffer, morph_memory) = match model.morph_targets() {
+            Some(morph_targets) => {
+                let device = uploads.lock().recover().queue().device().clone();
+                let vertices = morph_targets.base();
+                let buffer = CpuAccessibleBuffer::from_iter(
+                    device,
+                    BufferUsage::vertex_buffer(),
+                    false,
+                    vertices.iter().copied(),
+                )?;
+                let memory = GpuAllocation::new(
+                    AllocationCategory::Mesh,
+                    (vertices.len() * std::mem::size_of::<Vertex>()) as u64,
+                );
+                (Some(buffer), Some(memory))
+            }
+            None => (None, None),
+        };
 
         Ok(Self {
+            uploads,
             model,
-            model_buffer,
-            model_set,
+            model_slot,
+            material_template,
+            material_create_info: material_instance_create_info,
             material_instance,
+            submesh_materials: vec![],
+            morph_buffer,
+            _morph_memory: morph_memory,
         })
     }
 
@@ -116,25 +439,117 @@ impl MeshObject {
         &self.model
     }
 
+    // Set 2 with the dynamic offset of this object's transform, in the image's copy
     #[inline]
-    pub const fn model_buffer(
-        &self,
-    ) -> &Arc<CpuAccessibleBuffer<shader::simple_vs::ty::Model_Data>> {
-        &self.model_buffer
+    pub fn model_set(&self, image: usize) -> DescriptorSetWithOffsets {
+        self.model_slot.descriptor_set(image)
+    }
+
+    pub const fn material_instance(&self) -> &MaterialInstance {
+        &self.material_instance
     }
 
     #[inline]
-    pub const fn model_set(&self) -> &Arc<PersistentDescriptorSet> {
-        &self.model_set
+    pub const fn material_template(&self) -> &Arc<dyn MaterialTemplate> {
+        &self.material_template
     }
 
-    pub const fn material_instance(&self) -> &MaterialInstance {
-        &self.material_instance
+    #[inline]
+    pub const fn material_create_info(&self) -> &MaterialInstanceCreateInfo {
+        &self.material_create_info
+    }
+
+    #[inline]
+    pub const fn morph_buffer(&self) -> Option<&Arc<CpuAccessibleBuffer<[Vertex]>>> {
+        self.morph_buffer.as_ref()
+    }
+
+    // Weights are indexed like the model's morph targets, does nothing for models without any.
+    // If frames in flight are still drawing the current buffer, the blend goes to a new one
+    pub fn apply_morph(&mut self, weights: &[f32]) -> Result<(), Error> {
+        let (morph_targets, buffer) = match (self.model.morph_targets(), &self.morph_buffer) {
+            (Some(morph_targets), Some(buffer)) => (morph_targets, buffer),
+            _ => return Ok(()),
+        };
+        match buffer.write() {
+            Ok(mut lock) => morph_targets.blend(weights, &mut lock),
+            Err(WriteLockError::GpuLocked) => {
+                let mut vertices = morph_targets.base().to_vec();
+                morph_targets.blend(weights, &mut vertices);
+                let device = self.uploads.lock().recover().queue().device().clone();
+                self.morph_buffer = Some(CpuAccessibleBuffer::from_iter(
+                    device,
+                    BufferUsage::vertex_buffer(),
+                    false,
+                    vertices,
+                )?);
+            }
+            Err(err) => return Err(err.into()),
+        }
+        stats::record_upload(
+            (morph_targets.base().len() * std::mem::size_of::<Vertex>()) as u64,
+        );
+        Ok(())
+    }
+
+    pub fn submesh_material(&self, index: usize) -> (&Arc<dyn MaterialTemplate>, &MaterialInstance) {
+        match self.submesh_materials.get(index) {
+            Some(Some(material)) => (&material.template, &material.instance),
+            _ => (&self.material_template, &self.material_instance),
+        }
+    }
+
+    // None if the submesh uses the mesh's material
+    pub fn submesh_material_override(&self, index: usize) -> Option<&SubmeshMaterial> {
+        self.submesh_materials.get(index).and_then(Option::as_ref)
+    }
+
+    // The template has to take the same vertex input as the mesh's one
+    pub fn set_submesh_material(
+        &mut self,
+        index: usize,
+        template: Arc<dyn MaterialTemplate>,
+        create_info: MaterialInstanceCreateInfo,
+    ) -> Result<(), Error> {
+        let gfx_queue = self.uploads.lock().recover().queue().clone();
+        let (instance, init) = template.create_instance(gfx_queue, create_info.clone())?;
+
+        self.uploads.lock().recover().push(init);
+
+        if self.submesh_materials.len() <= index {
+            self.submesh_materials.resize_with(index + 1, || None);
+        }
+        self.submesh_materials[index] = Some(SubmeshMaterial {
+            template,
+            create_info,
+            instance,
+        });
+        Ok(())
+    }
+
+    pub fn clear_submesh_material(&mut self, index: usize) {
+        if let Some(material) = self.submesh_materials.get_mut(index) {
+            *material = None;
+        }
+    }
+
+    // Rebuilds the material instance with new parameters, the template is kept
+    pub fn update_material(&mut self, create_info: MaterialInstanceCreateInfo) -> Result<(), Error> {
+        let gfx_queue = self.uploads.lock().recover().queue().clone();
+        let (material_instance, init) = self
+            .material_template
+            .create_instance(gfx_queue, create_info.clone())?;
+
+        self.uploads.lock().recover().push(init);
+
+        self.material_instance = material_instance;
+        self.material_create_info = create_info;
+        Ok(())
     }
 
     pub fn update_transform(&mut self, transform: &Matrix4<f32>) -> Result<(), Error> {
-        let mut lock = self.model_buffer.write()?;
-        lock.transform = *transform.as_ref();
+        self.model_slot.write(&ModelUniform::from(transform));
+        stats::record_upload(std::mem::size_of::<ModelUniform>() as u64);
         Ok(())
     }
 }
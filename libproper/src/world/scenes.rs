@@ -0,0 +1,153 @@
+use std::{
+    collections::BTreeMap,
+    sync::{
+        mpsc::{self, Receiver, TryRecvError},
+        Arc, RwLock,
+    },
+};
+
+use crate::{error::Error, lock::Recover};
+
+use super::scene::Scene;
+
+// Name of the scene the application starts with
+pub const DEFAULT_SCENE: &str = "default";
+
+// Keeps the loaded scenes besides the active one, e.g. the main menu background while playing.
+// The active scene is the one behind the lock shared by the layers, switching swaps its
+// contents, so nothing has to be handed a new lock
+pub struct SceneManager {
+    active: Arc<RwLock<Scene>>,
+    active_name: String,
+    loaded: BTreeMap<String, Scene>,
+    // Built by background jobs, moved to the loaded ones by poll()
+    preloading: BTreeMap<String, Receiver<Result<Scene, Error>>>,
+}
+
+impl SceneManager {
+    pub fn new(active: Arc<RwLock<Scene>>) -> Self {
+        Self {
+            active,
+            active_name: DEFAULT_SCENE.to_owned(),
+            loaded: BTreeMap::new(),
+            preloading: BTreeMap::new(),
+        }
+    }
+
+    #[inline]
+    pub const fn active(&self) -> &Arc<RwLock<Scene>> {
+        &self.active
+    }
+
+    #[inline]
+    pub fn active_name(&self) -> &str {
+        &self.active_name
+    }
+
+    // Loaded but inactive scenes
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.loaded.keys().map(String::as_str)
+    }
+
+    pub fn is_loaded(&self, name: &str) -> bool {
+        name == self.active_name || self.loaded.contains_key(name)
+    }
+
+    pub fn is_preloading(&self, name: &str) -> bool {
+        self.preloading.contains_key(name)
+    }
+
+    // Replaces an inactive scene of the same name
+    pub fn insert(&mut self, name: &str, scene: Scene) {
+        self.preloading.remove(name);
+        self.loaded.insert(name.to_owned(), scene);
+    }
+
+    // Builds the scene on a worker thread, it can be switched to once poll() reports it
+    pub fn preload<F>(&mut self, name: &str, build: F)
+    where
+        F: FnOnce() -> Result<Scene, Error> + Send + 'static,
+    {
+        let (sender, receiver) = mpsc::channel();
+        self.preloading.insert(name.to_owned(), receiver);
+        rayon::spawn(move || {
+            sender.send(build()).ok();
+        });
+    }
+
+    // Names of the scenes finished preloading since the last call, failed ones are dropped
+    pub fn poll(&mut self) -> Vec<Result<String, Error>> {
+        let mut finished = vec![];
+        self.preloading
+            .retain(|name, receiver| match receiver.try_recv() {
+                Ok(result) => {
+                    finished.push((name.clone(), result));
+                    false
+                }
+                Err(TryRecvError::Empty) => true,
+                Err(TryRecvError::Disconnected) => {
+                    finished.push((name.clone(), Err(Error::ScenePreload(name.clone()))));
+                    false
+                }
+            });
+
+        finished
+            .into_iter()
+            .map(|(name, result)| {
+                let scene = result?;
+                self.loaded.insert(name.clone(), scene);
+                Ok(name)
+            })
+            .collect()
+    }
+
+    // Waits for the scene if it's still preloading. Persistent entities move to the new scene,
+    // keeping their IDs unless these are taken there. The rest of the previous scene is kept
+    // as an inactive scene if asked to, otherwise it's dropped along with its GPU resources
+    pub fn switch_to(&mut self, name: &str, keep_previous: bool) -> Result<(), Error> {
+        if name == self.active_name {
+            return Ok(());
+        }
+
+        if let Some(receiver) = self.preloading.remove(name) {
+            log::info!("Waiting for scene {:?} to finish preloading", name);
+            let scene = receiver
+                .recv()
+                .map_err(|_| Error::ScenePreload(name.to_owned()))??;
+            self.loaded.insert(name.to_owned(), scene);
+        }
+        let scene = self
+            .loaded
+            .remove(name)
+            .ok_or_else(|| Error::UnknownScene(name.to_owned()))?;
+
+        let previous = {
+            let mut active = self.active.write().recover();
+            let mut previous = std::mem::replace(&mut *active, scene);
+            for entity in previous.take_persistent() {
+                let id = entity.id();
+                let new_id = active.adopt(entity);
+                if new_id != id {
+                    log::warn!(
+                        "Persistent entity {:?} is now {:?}, the ID is taken in scene {:?}",
+                        id,
+                        new_id,
+                        name
+                    );
+                }
+            }
+            previous
+        };
+        let previous_name = std::mem::replace(&mut self.active_name, name.to_owned());
+        log::info!("Switched from scene {:?} to {:?}", previous_name, name);
+        if keep_previous {
+            self.loaded.insert(previous_name, previous);
+        }
+        Ok(())
+    }
+
+    // The active scene can't be unloaded, switch away from it first
+    pub fn unload(&mut self, name: &str) -> bool {
+        self.preloading.remove(name).is_some() | self.loaded.remove(name).is_some()
+    }
+}
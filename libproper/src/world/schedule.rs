@@ -0,0 +1,273 @@
+use std::any::{type_name, TypeId};
+
+use nalgebra::{Point3, UnitQuaternion};
+use rand::rngs::StdRng;
+use rayon::prelude::*;
+
+use crate::{error::Error, event::GameEvent, random::Random};
+
+use super::{
+    component::{Component, Components},
+    entity::{Entity, EntityId},
+    scene::{MeshObject, Scene},
+};
+
+// Component types a system reads and writes. Transforms are read as they were at the start
+// of the stage, writes to them are applied once the whole stage has finished
+#[derive(Clone, Debug, Default)]
+pub struct SystemAccess {
+    reads: Vec<TypeId>,
+    writes: Vec<TypeId>,
+    writes_transforms: bool,
+}
+
+pub trait System: Send {
+    fn name(&self) -> &str {
+        type_name::<Self>()
+    }
+
+    fn access(&self) -> SystemAccess;
+
+    fn run(&mut self, ctx: &mut SystemContext) -> Result<(), Error>;
+}
+
+// Components written by a system, moved out of an entity for the duration of a stage
+struct Row {
+    index: usize,
+    components: Components,
+    transform: Option<(Point3<f32>, UnitQuaternion<f32>)>,
+}
+
+pub struct EntityView<'a> {
+    entity: &'a Entity,
+    row: &'a mut Row,
+}
+
+// Entities are the ones holding at least one of the components the system writes
+pub struct SystemContext<'a> {
+    pub scene: &'a Scene,
+    pub entities: Vec<EntityView<'a>>,
+    pub events: Vec<GameEvent>,
+    pub delta: f32,
+    // Owned by the system, see Random
+    pub rng: &'a mut StdRng,
+}
+
+// Systems without conflicting access share a stage and run in parallel, stages run one
+// after another
+pub struct SystemScheduler {
+    random: Random,
+    systems: Vec<Box<dyn System>>,
+    accesses: Vec<SystemAccess>,
+    rngs: Vec<StdRng>,
+    stages: Vec<Vec<usize>>,
+}
+
+impl SystemAccess {
+    pub fn read<T: Component>(mut self) -> Self {
+        self.reads.push(TypeId::of::<T>());
+        self
+    }
+
+    pub fn write<T: Component>(mut self) -> Self {
+        self.writes.push(TypeId::of::<T>());
+        self
+    }
+
+    pub fn write_transforms(mut self) -> Self {
+        self.writes_transforms = true;
+        self
+    }
+
+    pub fn conflicts_with(&self, other: &Self) -> bool {
+        let overlaps = |a: &[TypeId], b: &[TypeId]| a.iter().any(|ty| b.contains(ty));
+
+        (self.writes_transforms && other.writes_transforms)
+            || overlaps(&self.writes, &other.writes)
+            || overlaps(&self.writes, &other.reads)
+            || overlaps(&self.reads, &other.writes)
+    }
+}
+
+impl<'a> EntityView<'a> {
+    #[inline]
+    pub const fn id(&self) -> EntityId {
+        self.entity.id()
+    }
+
+    pub fn position(&self) -> &Point3<f32> {
+        match &self.row.transform {
+            Some((position, _)) => position,
+            None => self.entity.position(),
+        }
+    }
+
+    pub fn rotation(&self) -> &UnitQuaternion<f32> {
+        match &self.row.transform {
+            Some((_, rotation)) => rotation,
+            None => self.entity.rotation(),
+        }
+    }
+
+    // Looks in the components handed to the system first, then in the rest of the entity
+    pub fn get<T: Component>(&self) -> Option<&T> {
+        self.row
+            .components
+            .get::<T>()
+            .or_else(|| self.entity.components().get::<T>())
+    }
+
+    #[inline]
+    pub const fn mesh(&self) -> &MeshObject {
+        self.entity.mesh()
+    }
+
+    // Only the components the system declared as written
+    #[inline]
+    pub fn components_mut(&mut self) -> &mut Components {
+        &mut self.row.components
+    }
+
+    pub fn set_transform(&mut self, position: Point3<f32>, rotation: UnitQuaternion<f32>) {
+        self.row.transform = Some((position, rotation));
+    }
+}
+
+impl SystemScheduler {
+    pub fn new(random: Random) -> Self {
+        Self {
+            random,
+            systems: vec![],
+            accesses: vec![],
+            rngs: vec![],
+            stages: vec![],
+        }
+    }
+
+    pub fn add<S: System + 'static>(&mut self, system: S) {
+        let access = system.access();
+        // Right after the last stage with a conflicting system, so those keep their order
+        let stage = self
+            .stages
+            .iter()
+            .rposition(|stage| {
+                stage
+                    .iter()
+                    .any(|&i| self.accesses[i].conflicts_with(&access))
+            })
+            .map_or(0, |i| i + 1);
+        let index = self.systems.len();
+
+        log::debug!("System {} runs in stage {}", system.name(), stage);
+        match self.stages.get_mut(stage) {
+            Some(systems) => systems.push(index),
+            None => self.stages.push(vec![index]),
+        }
+        // Index keeps two systems of the same type apart
+        let rng = self
+            .random
+            .stream(&format!("system/{}/{}", system.name(), index));
+        self.rngs.push(rng);
+        self.systems.push(Box::new(system));
+        self.accesses.push(access);
+    }
+
+    #[inline]
+    pub fn stage_count(&self) -> usize {
+        self.stages.len()
+    }
+
+    // All stages are finished by the time this returns, so rendering sees a consistent scene.
+    // Events are returned in the order the systems were added in
+    pub fn run(&mut self, scene: &mut Scene, delta: f32) -> Result<Vec<GameEvent>, Error> {
+        let mut events = vec![];
+
+        for stage in &self.stages {
+            let mut rows: Vec<Vec<Row>> = {
+                let mut entities: Vec<&mut Entity> = scene.entities_mut().collect();
+                stage
+                    .iter()
+                    .map(|&i| take_rows(&mut entities, &self.accesses[i].writes))
+                    .collect()
+            };
+
+            let results: Vec<Result<Vec<GameEvent>, Error>> = {
+                let shared: &Scene = scene;
+                let entities: Vec<&Entity> = shared.entities().collect();
+                let mut systems: Vec<(&mut Box<dyn System>, &mut StdRng)> = self
+                    .systems
+                    .iter_mut()
+                    .zip(self.rngs.iter_mut())
+                    .enumerate()
+                    .filter(|(i, _)| stage.contains(i))
+                    .map(|(_, system)| system)
+                    .collect();
+
+                systems
+                    .par_iter_mut()
+                    .zip(rows.par_iter_mut())
+                    .map(|((system, rng), rows)| {
+                        let _span = tracing::info_span!("system", name = system.name()).entered();
+                        let mut ctx = SystemContext {
+                            scene: shared,
+                            entities: rows
+                                .iter_mut()
+                                .map(|row| EntityView {
+                                    entity: entities[row.index],
+                                    row,
+                                })
+                                .collect(),
+                            events: vec![],
+                            delta,
+                            rng,
+                        };
+                        system.run(&mut ctx).map(|_| ctx.events)
+                    })
+                    .collect()
+            };
+
+            // Everything is put back before reporting errors so no components get lost
+            let mut error = None;
+            let mut entities: Vec<&mut Entity> = scene.entities_mut().collect();
+            for (rows, result) in rows.into_iter().zip(results) {
+                for row in rows {
+                    let entity = &mut entities[row.index];
+                    entity.components_mut().append(row.components);
+                    if let Some((position, rotation)) = row.transform {
+                        if let Err(err) = entity.set_transform(position, rotation) {
+                            error.get_or_insert(err);
+                        }
+                    }
+                }
+
+                match result {
+                    Ok(mut system_events) => events.append(&mut system_events),
+                    Err(err) => {
+                        error.get_or_insert(err);
+                    }
+                }
+            }
+
+            if let Some(err) = error {
+                return Err(err);
+            }
+        }
+
+        Ok(events)
+    }
+}
+
+fn take_rows(entities: &mut [&mut Entity], types: &[TypeId]) -> Vec<Row> {
+    entities
+        .iter_mut()
+        .enumerate()
+        .filter_map(|(index, entity)| {
+            let components = entity.components_mut().take(types);
+            (!components.is_empty()).then(|| Row {
+                index,
+                components,
+                transform: None,
+            })
+        })
+        .collect()
+}
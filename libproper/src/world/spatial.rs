@@ -0,0 +1,193 @@
+use std::collections::{HashMap, HashSet};
+
+use nalgebra::{Point3, Vector3};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Aabb {
+    pub min: Point3<f32>,
+    pub max: Point3<f32>,
+}
+
+// Uniform hash grid used as a broadphase for proximity queries
+pub struct SpatialGrid<T> {
+    cell_size: f32,
+    cells: HashMap<(i32, i32, i32), Vec<usize>>,
+    items: Vec<(T, Aabb)>,
+}
+
+impl Aabb {
+    pub fn new(min: Point3<f32>, max: Point3<f32>) -> Self {
+        Self { min, max }
+    }
+
+    pub fn from_center(center: Point3<f32>, half_extents: Vector3<f32>) -> Self {
+        Self {
+            min: center - half_extents,
+            max: center + half_extents,
+        }
+    }
+
+    pub fn from_points<'a, I: IntoIterator<Item = &'a Point3<f32>>>(points: I) -> Option<Self> {
+        let mut iter = points.into_iter();
+        let first = *iter.next()?;
+        Some(iter.fold(Self::new(first, first), |aabb, point| {
+            aabb.union(&Self::new(*point, *point))
+        }))
+    }
+
+    pub fn corners(&self) -> [Point3<f32>; 8] {
+        [0, 1, 2, 3, 4, 5, 6, 7].map(|i| {
+            Point3::new(
+                if i & 1 == 0 { self.min.x } else { self.max.x },
+                if i & 2 == 0 { self.min.y } else { self.max.y },
+                if i & 4 == 0 { self.min.z } else { self.max.z },
+            )
+        })
+    }
+
+    pub fn center(&self) -> Point3<f32> {
+        nalgebra::center(&self.min, &self.max)
+    }
+
+    pub fn half_extents(&self) -> Vector3<f32> {
+        (self.max - self.min) * 0.5
+    }
+
+    pub fn union(&self, other: &Aabb) -> Self {
+        Self {
+            min: self.min.inf(&other.min),
+            max: self.max.sup(&other.max),
+        }
+    }
+
+    pub fn intersects(&self, other: &Aabb) -> bool {
+        self.min.x <= other.max.x
+            && self.max.x >= other.min.x
+            && self.min.y <= other.max.y
+            && self.max.y >= other.min.y
+            && self.min.z <= other.max.z
+            && self.max.z >= other.min.z
+    }
+
+    pub fn contains(&self, point: &Point3<f32>) -> bool {
+        point.x >= self.min.x
+            && point.x <= self.max.x
+            && point.y >= self.min.y
+            && point.y <= self.max.y
+            && point.z >= self.min.z
+            && point.z <= self.max.z
+    }
+
+    pub fn closest_point(&self, point: &Point3<f32>) -> Point3<f32> {
+        point.sup(&self.min).inf(&self.max)
+    }
+}
+
+impl<T: Copy> SpatialGrid<T> {
+    pub fn new(cell_size: f32) -> Self {
+        Self {
+            cell_size,
+            cells: HashMap::new(),
+            items: vec![],
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.cells.clear();
+        self.items.clear();
+    }
+
+    pub fn insert(&mut self, value: T, bounds: Aabb) {
+        let index = self.items.len();
+        self.items.push((value, bounds));
+
+        for cell in self.cells_of(&bounds) {
+            self.cells.entry(cell).or_default().push(index);
+        }
+    }
+
+    pub fn query(&self, bounds: &Aabb) -> Vec<T> {
+        let mut seen = HashSet::new();
+        let mut result = vec![];
+
+        for cell in self.cells_of(bounds) {
+            for &index in self.cells.get(&cell).into_iter().flatten() {
+                let (value, item_bounds) = &self.items[index];
+                if seen.insert(index) && item_bounds.intersects(bounds) {
+                    result.push(*value);
+                }
+            }
+        }
+
+        result
+    }
+
+    // Pairs of items whose bounds overlap, each reported once
+    pub fn overlapping_pairs(&self) -> Vec<(T, T)> {
+        let mut seen = HashSet::new();
+        let mut result = vec![];
+
+        for indices in self.cells.values() {
+            for (i, &a) in indices.iter().enumerate() {
+                for &b in &indices[i + 1..] {
+                    let key = (a.min(b), a.max(b));
+                    if seen.insert(key) && self.items[a].1.intersects(&self.items[b].1) {
+                        result.push((self.items[key.0].0, self.items[key.1].0));
+                    }
+                }
+            }
+        }
+
+        result
+    }
+
+    fn cells_of(&self, bounds: &Aabb) -> impl Iterator<Item = (i32, i32, i32)> {
+        let min = (bounds.min.coords / self.cell_size).map(|v| v.floor() as i32);
+        let max = (bounds.max.coords / self.cell_size).map(|v| v.floor() as i32);
+
+        (min.x..=max.x).flat_map(move |x| {
+            (min.y..=max.y).flat_map(move |y| (min.z..=max.z).map(move |z| (x, y, z)))
+        })
+    }
+}
+
+// The grid is only used as a broadphase by world::collision
+#[cfg(all(test, feature = "physics"))]
+mod tests {
+    use super::*;
+
+    fn cube(x: f32, size: f32) -> Aabb {
+        Aabb::new(Point3::new(x, 0.0, 0.0), Point3::new(x + size, size, size))
+    }
+
+    fn sorted(mut pairs: Vec<(u32, u32)>) -> Vec<(u32, u32)> {
+        for pair in pairs.iter_mut() {
+            *pair = (pair.0.min(pair.1), pair.0.max(pair.1));
+        }
+        pairs.sort_unstable();
+        pairs
+    }
+
+    #[test]
+    fn overlapping_pairs_are_reported_once() {
+        let mut grid = SpatialGrid::new(1.0);
+        // Spans several cells shared with 1 and 2
+        grid.insert(0, cube(0.0, 3.0));
+        grid.insert(1, cube(2.5, 1.0));
+        grid.insert(2, cube(0.5, 0.25));
+        grid.insert(3, cube(10.0, 1.0));
+
+        assert_eq!(sorted(grid.overlapping_pairs()), vec![(0, 1), (0, 2)]);
+    }
+
+    #[test]
+    fn same_cell_without_overlap() {
+        let mut grid = SpatialGrid::new(4.0);
+        grid.insert(0, cube(0.0, 1.0));
+        grid.insert(1, cube(2.0, 1.0));
+        assert!(grid.overlapping_pairs().is_empty());
+
+        grid.clear();
+        assert!(grid.overlapping_pairs().is_empty());
+    }
+}
@@ -0,0 +1,179 @@
+use std::sync::Arc;
+
+use nalgebra::{Isometry3, Point3, Vector3};
+use rand::{rngs::StdRng, Rng};
+
+use crate::{
+    error::Error,
+    event::GameEvent,
+    random::Random,
+    resource::{material::MaterialRegistry, model::ModelRegistry, texture::TextureRegistry},
+};
+
+use super::{description::SpawnerDescription, entity::Entity, scene::Scene};
+
+// Scene-defined source of entities, spawned in waves at random points of a box. Runs by
+// itself or once a signal arrives
+#[derive(Clone)]
+pub struct Spawner {
+    pub description: Arc<SpawnerDescription>,
+    pub position: Point3<f32>,
+    armed: bool,
+    // Until the next spawn
+    timer: f32,
+    wave: u32,
+    spawned_in_wave: u32,
+}
+
+// Spawn points come from a stream of their own, so a seeded run spawns the same things in the
+// same places every time
+pub struct SpawnerSystem {
+    rng: StdRng,
+}
+
+// An entity to instantiate, decided with the scene locked and created once the resource
+// registries are locked as well
+pub struct SpawnRequest {
+    pub description: Arc<SpawnerDescription>,
+    pub position: Point3<f32>,
+}
+
+impl Spawner {
+    pub fn new(description: Arc<SpawnerDescription>) -> Self {
+        Self {
+            position: Point3::from(description.position),
+            armed: description.signal.is_none(),
+            timer: description.delay,
+            wave: 0,
+            spawned_in_wave: 0,
+            description,
+        }
+    }
+
+    pub fn transformed(&self, offset: &Isometry3<f32>) -> Self {
+        Self {
+            position: offset * self.position,
+            ..self.clone()
+        }
+    }
+
+    #[inline]
+    pub const fn is_armed(&self) -> bool {
+        self.armed
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.wave >= self.description.waves
+    }
+
+    #[inline]
+    pub const fn wave(&self) -> u32 {
+        self.wave
+    }
+
+    // Starts the spawner if it waits for the signal
+    pub fn signal(&mut self, name: &str) -> bool {
+        if !self.armed && self.description.signal.as_deref() == Some(name) {
+            self.armed = true;
+            true
+        } else {
+            false
+        }
+    }
+
+    // Number of entities due, finishes waves along the way
+    fn advance(&mut self, dt: f32, finished_waves: &mut Vec<u32>) -> u32 {
+        if !self.armed || self.is_finished() || self.description.count == 0 {
+            return 0;
+        }
+
+        let mut due = 0;
+        self.timer -= dt;
+        while self.timer <= 0.0 && !self.is_finished() {
+            due += 1;
+            self.spawned_in_wave += 1;
+            if self.spawned_in_wave >= self.description.count {
+                finished_waves.push(self.wave);
+                self.wave += 1;
+                self.spawned_in_wave = 0;
+                self.timer += self.description.wave_delay;
+            } else {
+                self.timer += self.description.interval;
+            }
+        }
+        due
+    }
+}
+
+impl SpawnerSystem {
+    pub fn new(random: Random) -> Self {
+        Self {
+            rng: random.stream("spawner"),
+        }
+    }
+
+    // Advances the timers, returns what has to be spawned and the wave events
+    pub fn update(&mut self, scene: &mut Scene, dt: f32) -> (Vec<SpawnRequest>, Vec<GameEvent>) {
+        let mut requests = vec![];
+        let mut events = vec![];
+
+        for spawner in scene.spawners.iter_mut() {
+            let mut finished_waves = vec![];
+            let due = spawner.advance(dt, &mut finished_waves);
+            let half_extents = Vector3::from(spawner.description.half_extents);
+            for _ in 0..due {
+                let offset = half_extents.map(|extent| {
+                    if extent > 0.0 {
+                        self.rng.gen_range(-extent..extent)
+                    } else {
+                        0.0
+                    }
+                });
+                requests.push(SpawnRequest {
+                    description: spawner.description.clone(),
+                    position: spawner.position + offset,
+                });
+            }
+            events.extend(
+                finished_waves
+                    .into_iter()
+                    .map(|wave| GameEvent::WaveSpawned {
+                        spawner: spawner.description.name.clone(),
+                        wave,
+                    }),
+            );
+        }
+
+        (requests, events)
+    }
+
+    pub fn signal(scene: &mut Scene, name: &str) {
+        for spawner in scene.spawners.iter_mut() {
+            if spawner.signal(name) {
+                log::debug!(
+                    "Spawner {:?} started by {:?}",
+                    spawner.description.name,
+                    name
+                );
+            }
+        }
+    }
+}
+
+impl SpawnRequest {
+    // The template entity's position is relative to the spawn point
+    pub fn instantiate(
+        &self,
+        materials: &mut MaterialRegistry,
+        models: &mut ModelRegistry,
+        textures: &mut TextureRegistry,
+    ) -> Result<Entity, Error> {
+        let mut entity = self
+            .description
+            .entity
+            .instantiate(materials, models, textures)?;
+        let position = self.position + entity.position().coords;
+        entity.set_position(position)?;
+        Ok(entity)
+    }
+}
@@ -0,0 +1,74 @@
+use crate::error::Error;
+
+use super::{
+    camera::CAMERA_2D_DEPTH,
+    schedule::{System, SystemAccess, SystemContext},
+};
+
+// Depth between two sorting layers, entities of a layer stay within it
+const LAYER_SPACING: f32 = 1.0;
+// Depth per world unit of Y when sorting by Y, keeps 500 units either way within a layer
+const Y_SORT_SCALE: f32 = 1e-3;
+
+// Draw order of an entity seen by a 2D camera (see Camera::orthographic_2d). The order comes
+// from the depth buffer: SpriteSortSystem moves the entity along Z so higher layers are in
+// front, and within a layer entities lower on the screen are in front if sorted by Y
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SpriteOrder {
+    pub layer: i32,
+    pub sort_by_y: bool,
+}
+
+#[derive(Default)]
+pub struct SpriteSortSystem;
+
+impl SpriteOrder {
+    pub fn new(layer: i32) -> Self {
+        Self {
+            layer,
+            sort_by_y: false,
+        }
+    }
+
+    pub fn sorted_by_y(mut self) -> Self {
+        self.sort_by_y = true;
+        self
+    }
+
+    pub fn depth(&self, y: f32) -> f32 {
+        let offset = if self.sort_by_y {
+            -y * Y_SORT_SCALE
+        } else {
+            0.0
+        };
+        let depth = self.layer as f32 * LAYER_SPACING
+            + offset.clamp(-LAYER_SPACING * 0.5, LAYER_SPACING * 0.5);
+        // Keeps the entity between the camera's near and far planes
+        depth.clamp(-CAMERA_2D_DEPTH + 1.0, CAMERA_2D_DEPTH - 1.0)
+    }
+}
+
+impl System for SpriteSortSystem {
+    fn access(&self) -> SystemAccess {
+        SystemAccess::default()
+            .write::<SpriteOrder>()
+            .write_transforms()
+    }
+
+    fn run(&mut self, ctx: &mut SystemContext) -> Result<(), Error> {
+        for entity in ctx.entities.iter_mut() {
+            let order = match entity.get::<SpriteOrder>() {
+                Some(order) => *order,
+                None => continue,
+            };
+            let mut position = *entity.position();
+            let depth = order.depth(position.y);
+            if position.z != depth {
+                position.z = depth;
+                let rotation = *entity.rotation();
+                entity.set_transform(position, rotation);
+            }
+        }
+        Ok(())
+    }
+}
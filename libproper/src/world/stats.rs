@@ -0,0 +1,221 @@
+use std::collections::BTreeMap;
+
+use crate::error::Error;
+
+use super::schedule::{System, SystemAccess, SystemContext};
+
+// Stat GameEvent::Damage is taken from, the entity dies when it reaches its minimum
+pub const HEALTH: &str = "health";
+// Subtracted from incoming damage
+pub const ARMOR: &str = "armor";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ModifierKind {
+    Add(f32),
+    // Applied after all of the additions
+    Multiply(f32),
+}
+
+#[derive(Clone, Debug)]
+pub struct StatModifier {
+    // Modifiers are removed by source, e.g. the name of a buff or an equipped item
+    pub source: String,
+    pub kind: ModifierKind,
+    // Seconds, permanent if None
+    pub remaining: Option<f32>,
+}
+
+// Value is (base + additions) * multipliers, clamped to [min, max]
+#[derive(Clone, Debug)]
+pub struct Stat {
+    base: f32,
+    min: f32,
+    max: f32,
+    modifiers: Vec<StatModifier>,
+}
+
+// Named stats of an entity, e.g. health, speed or damage
+#[derive(Clone, Debug, Default)]
+pub struct Stats {
+    stats: BTreeMap<String, Stat>,
+}
+
+// Expires timed modifiers
+#[derive(Default)]
+pub struct StatsSystem;
+
+impl StatModifier {
+    pub fn new(source: &str, kind: ModifierKind) -> Self {
+        Self {
+            source: source.to_owned(),
+            kind,
+            remaining: None,
+        }
+    }
+
+    pub fn timed(source: &str, kind: ModifierKind, duration: f32) -> Self {
+        Self {
+            remaining: Some(duration),
+            ..Self::new(source, kind)
+        }
+    }
+}
+
+impl Stat {
+    pub fn new(base: f32, min: f32, max: f32) -> Self {
+        Self {
+            base: base.clamp(min, max),
+            min,
+            max,
+            modifiers: vec![],
+        }
+    }
+
+    // No bounds
+    pub fn unclamped(base: f32) -> Self {
+        Self::new(base, f32::NEG_INFINITY, f32::INFINITY)
+    }
+
+    pub fn value(&self) -> f32 {
+        let (add, multiply) =
+            self.modifiers
+                .iter()
+                .fold((0.0, 1.0), |(add, multiply), modifier| {
+                    match modifier.kind {
+                        ModifierKind::Add(amount) => (add + amount, multiply),
+                        ModifierKind::Multiply(factor) => (add, multiply * factor),
+                    }
+                });
+        ((self.base + add) * multiply).clamp(self.min, self.max)
+    }
+
+    #[inline]
+    pub const fn base(&self) -> f32 {
+        self.base
+    }
+
+    #[inline]
+    pub const fn min(&self) -> f32 {
+        self.min
+    }
+
+    #[inline]
+    pub const fn max(&self) -> f32 {
+        self.max
+    }
+
+    pub fn set_base(&mut self, base: f32) {
+        self.base = base.clamp(self.min, self.max);
+    }
+
+    pub fn is_at_min(&self) -> bool {
+        self.value() <= self.min
+    }
+
+    pub fn modifiers(&self) -> &[StatModifier] {
+        &self.modifiers
+    }
+}
+
+impl Stats {
+    pub fn with(mut self, name: &str, stat: Stat) -> Self {
+        self.insert(name, stat);
+        self
+    }
+
+    // Shorthand for a health stat in [0, max], starting full
+    pub fn with_health(self, max: f32) -> Self {
+        self.with(HEALTH, Stat::new(max, 0.0, max))
+    }
+
+    pub fn insert(&mut self, name: &str, stat: Stat) -> Option<Stat> {
+        self.stats.insert(name.to_owned(), stat)
+    }
+
+    pub fn stat(&self, name: &str) -> Option<&Stat> {
+        self.stats.get(name)
+    }
+
+    pub fn stat_mut(&mut self, name: &str) -> Option<&mut Stat> {
+        self.stats.get_mut(name)
+    }
+
+    // Value with the modifiers applied
+    pub fn get(&self, name: &str) -> Option<f32> {
+        self.stat(name).map(Stat::value)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Stat)> {
+        self.stats.iter().map(|(name, stat)| (name.as_str(), stat))
+    }
+
+    // Changes the base value, returns false if there's no such stat
+    pub fn add(&mut self, name: &str, delta: f32) -> bool {
+        match self.stat_mut(name) {
+            Some(stat) => {
+                stat.set_base(stat.base + delta);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn add_modifier(&mut self, name: &str, modifier: StatModifier) -> bool {
+        match self.stat_mut(name) {
+            Some(stat) => {
+                stat.modifiers.push(modifier);
+                true
+            }
+            None => false,
+        }
+    }
+
+    // From all of the stats
+    pub fn remove_modifiers(&mut self, source: &str) {
+        for stat in self.stats.values_mut() {
+            stat.modifiers.retain(|modifier| modifier.source != source);
+        }
+    }
+
+    // Takes the damage reduced by armor from health. Returns the damage dealt, None if there's
+    // no health stat or it was already at its minimum
+    pub fn apply_damage(&mut self, amount: f32) -> Option<f32> {
+        let armor = self.get(ARMOR).unwrap_or(0.0);
+        let health = self.stat_mut(HEALTH).filter(|health| !health.is_at_min())?;
+        let before = health.value();
+        health.set_base(health.base - (amount - armor).max(0.0));
+        Some(before - health.value())
+    }
+
+    pub fn is_dead(&self) -> bool {
+        self.stat(HEALTH).map_or(false, Stat::is_at_min)
+    }
+
+    fn advance(&mut self, dt: f32) {
+        for stat in self.stats.values_mut() {
+            stat.modifiers
+                .retain_mut(|modifier| match modifier.remaining.as_mut() {
+                    Some(remaining) => {
+                        *remaining -= dt;
+                        *remaining > 0.0
+                    }
+                    None => true,
+                });
+        }
+    }
+}
+
+impl System for StatsSystem {
+    fn access(&self) -> SystemAccess {
+        SystemAccess::default().write::<Stats>()
+    }
+
+    fn run(&mut self, ctx: &mut SystemContext) -> Result<(), Error> {
+        for entity in ctx.entities.iter_mut() {
+            if let Some(stats) = entity.components_mut().get_mut::<Stats>() {
+                stats.advance(ctx.delta);
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,204 @@
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    sync::{
+        mpsc::{self, Receiver, TryRecvError},
+        Arc, RwLock,
+    },
+};
+
+use nalgebra::Point3;
+
+use crate::{
+    event::GameEvent,
+    lock::Recover,
+    resource::{material::MaterialRegistry, model::ModelRegistry, texture::TextureRegistry},
+};
+
+use super::{
+    description::SceneDescription,
+    entity::{Entity, EntityId},
+    scene::Scene,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct CellCoord {
+    pub x: i32,
+    pub z: i32,
+}
+
+#[derive(Clone, Debug)]
+pub struct StreamingSettings {
+    // Cell files are looked up as <directory>/cell_<x>_<z>.toml
+    pub directory: PathBuf,
+    pub cell_size: f32,
+    // In cells, unload_radius should be larger than load_radius to avoid thrashing
+    pub load_radius: i32,
+    pub unload_radius: i32,
+}
+
+// Only Loaded cells have sent GameEvent::CellLoaded, so only they send CellUnloaded
+enum CellState {
+    Loading(Receiver<Option<Vec<Entity>>>),
+    Loaded(Vec<EntityId>),
+    // No cell file, or its load failed. Kept so it isn't retried every tick
+    Empty,
+}
+
+pub struct StreamingSystem {
+    settings: StreamingSettings,
+    material_registry: Arc<RwLock<MaterialRegistry>>,
+    model_registry: Arc<RwLock<ModelRegistry>>,
+    texture_registry: Arc<RwLock<TextureRegistry>>,
+    cells: HashMap<CellCoord, CellState>,
+}
+
+impl CellCoord {
+    pub fn containing(point: &Point3<f32>, cell_size: f32) -> Self {
+        Self {
+            x: (point.x / cell_size).floor() as i32,
+            z: (point.z / cell_size).floor() as i32,
+        }
+    }
+
+    pub fn distance(&self, other: &CellCoord) -> i32 {
+        (self.x - other.x).abs().max((self.z - other.z).abs())
+    }
+}
+
+impl Default for StreamingSettings {
+    fn default() -> Self {
+        Self {
+            directory: PathBuf::from("res/cells"),
+            cell_size: 32.0,
+            load_radius: 1,
+            unload_radius: 2,
+        }
+    }
+}
+
+impl StreamingSystem {
+    pub fn new(
+        settings: StreamingSettings,
+        material_registry: Arc<RwLock<MaterialRegistry>>,
+        model_registry: Arc<RwLock<ModelRegistry>>,
+        texture_registry: Arc<RwLock<TextureRegistry>>,
+    ) -> Self {
+        Self {
+            settings,
+            material_registry,
+            model_registry,
+            texture_registry,
+            cells: HashMap::new(),
+        }
+    }
+
+    #[inline]
+    pub const fn settings(&self) -> &StreamingSettings {
+        &self.settings
+    }
+
+    // Forgets the cells of a scene which was switched away from, the ones still loading are
+    // discarded when their jobs finish
+    pub fn clear(&mut self) {
+        self.cells.clear();
+    }
+
+    // Empty cells count as loaded, there's nothing more to wait for
+    pub fn is_loaded(&self, coord: &CellCoord) -> bool {
+        matches!(
+            self.cells.get(coord),
+            Some(CellState::Loaded(_) | CellState::Empty)
+        )
+    }
+
+    pub fn update(&mut self, scene: &mut Scene, focus: &Point3<f32>) -> Vec<GameEvent> {
+        let center = CellCoord::containing(focus, self.settings.cell_size);
+        let load_radius = self.settings.load_radius;
+        let mut events = vec![];
+
+        for x in -load_radius..=load_radius {
+            for z in -load_radius..=load_radius {
+                let coord = CellCoord {
+                    x: center.x + x,
+                    z: center.z + z,
+                };
+                if !self.cells.contains_key(&coord) {
+                    self.request(coord);
+                }
+            }
+        }
+
+        for (coord, state) in self.cells.iter_mut() {
+            let receiver = match state {
+                CellState::Loading(receiver) => receiver,
+                CellState::Loaded(_) | CellState::Empty => continue,
+            };
+
+            match receiver.try_recv() {
+                Ok(Some(entities)) => {
+                    let ids = entities.into_iter().map(|e| scene.add(e)).collect();
+                    *state = CellState::Loaded(ids);
+                    events.push(GameEvent::CellLoaded(*coord));
+                }
+                Ok(None) | Err(TryRecvError::Disconnected) => *state = CellState::Empty,
+                Err(TryRecvError::Empty) => (),
+            }
+        }
+
+        let unload = self
+            .cells
+            .keys()
+            .filter(|coord| coord.distance(&center) > self.settings.unload_radius)
+            .copied()
+            .collect::<Vec<_>>();
+
+        for coord in unload {
+            // Dropping the receiver of an in-flight load discards its result
+            if let Some(CellState::Loaded(ids)) = self.cells.remove(&coord) {
+                scene.despawn_batch(&ids);
+                events.push(GameEvent::CellUnloaded(coord));
+            }
+        }
+
+        events
+    }
+
+    fn request(&mut self, coord: CellCoord) {
+        let path = self
+            .settings
+            .directory
+            .join(format!("cell_{}_{}.toml", coord.x, coord.z));
+
+        if !path.exists() {
+            self.cells.insert(coord, CellState::Empty);
+            return;
+        }
+
+        let (sender, receiver) = mpsc::channel();
+        let material_registry = self.material_registry.clone();
+        let model_registry = self.model_registry.clone();
+        let texture_registry = self.texture_registry.clone();
+
+        rayon::spawn(move || {
+            let result = SceneDescription::load(&path).and_then(|description| {
+                description.instantiate(
+                    &mut material_registry.write().recover(),
+                    &mut model_registry.write().recover(),
+                    &mut texture_registry.write().recover(),
+                )
+            });
+
+            let entities = match result {
+                Ok(entities) => Some(entities),
+                Err(err) => {
+                    log::error!("Failed to load cell {:?}: {}", path, err);
+                    None
+                }
+            };
+            sender.send(entities).ok();
+        });
+
+        self.cells.insert(coord, CellState::Loading(receiver));
+    }
+}
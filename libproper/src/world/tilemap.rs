@@ -0,0 +1,974 @@
+use std::{
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Arc,
+};
+
+use nalgebra::{Point2, Point3, Vector3, Vector4};
+use serde::Deserialize;
+
+use crate::{
+    error::Error,
+    render::{shader::ShaderVariant, Vertex},
+    resource::{
+        atlas::{AtlasBuilder, AtlasRect, TextureAtlas},
+        material::{MaterialInstanceCreateInfo, MaterialRegistry},
+        model::{Model, ModelRegistry},
+        sampler::{SamplerDesc, TextureFilter, TextureWrap},
+        texture::TextureRegistry,
+    },
+};
+
+#[cfg(feature = "physics")]
+use super::{
+    camera::CAMERA_2D_DEPTH,
+    collision_mesh::{CollisionMesh, MeshCollider},
+};
+use super::{component::StaticGeometry, entity::Entity, scene::MeshObject, sprite::SpriteOrder};
+
+pub const TILEMAP_DIRECTORY: &str = "res/tilemaps";
+// Tiles along each side of a chunk, every chunk of a layer is one mesh
+pub const CHUNK_TILES: u32 = 32;
+const MAX_ATLAS_SIZE: u32 = 4096;
+// Keeps texture coordinates off the edges of a tile, so its neighbours in the tileset don't
+// show up along the seams
+const UV_INSET: f32 = 0.01;
+
+// Tiled stores flips in the top bits of a global tile id
+const FLIP_HORIZONTAL: u32 = 0x8000_0000;
+const FLIP_VERTICAL: u32 = 0x4000_0000;
+const FLIP_DIAGONAL: u32 = 0x2000_0000;
+// Also drops the hexagonal rotation bit
+const GID_MASK: u32 = 0x0fff_ffff;
+
+// Faces of a collision box, corners are indexed by their x, y and z bits
+#[cfg(feature = "physics")]
+const BOX_FACES: [[usize; 4]; 6] = [
+    [0, 2, 6, 4],
+    [1, 5, 7, 3],
+    [0, 4, 5, 1],
+    [2, 3, 7, 6],
+    [0, 1, 3, 2],
+    [4, 6, 7, 5],
+];
+
+// Global tile id as stored by Tiled, 0 is an empty cell
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Tile(pub u32);
+
+// Image cut into equally sized tiles, global ids from first_gid on map to them row by row
+#[derive(Clone, Debug)]
+pub struct Tileset {
+    pub name: String,
+    pub first_gid: u32,
+    pub image: PathBuf,
+    pub tile_size: [u32; 2],
+    pub columns: u32,
+    pub tile_count: u32,
+    pub margin: u32,
+    pub spacing: u32,
+}
+
+pub struct TileLayer {
+    pub name: String,
+    pub visible: bool,
+    // Solid tiles of the layer become collision boxes, set by naming the layer "collision" or
+    // with a bool "collision" property
+    pub collision: bool,
+    // SpriteOrder layer of the chunks, the layer's index unless it has a "sort_layer" property
+    pub sort_layer: i32,
+    width: u32,
+    height: u32,
+    tiles: Vec<Tile>,
+}
+
+// Orthogonal tile map made in Tiled (https://www.mapeditor.org), loaded from res/tilemaps.
+// Maps are saved as JSON (.tmj, .json) or TMX with CSV layer data, tilesets may be embedded
+// or external (.tsj, .tsx). The map lies in the XY plane facing +Z with its top left corner at
+// the origin, so it fits a 2D camera (see Camera::orthographic_2d)
+pub struct Tilemap {
+    name: String,
+    width: u32,
+    height: u32,
+    tile_size: [u32; 2],
+    pixels_per_unit: f32,
+    layers: Vec<TileLayer>,
+    tilesets: Vec<Tileset>,
+}
+
+#[derive(Default)]
+struct LayerProperties {
+    collision: Option<bool>,
+    sort_layer: Option<i32>,
+}
+
+#[derive(Deserialize)]
+struct JsonMap {
+    width: u32,
+    height: u32,
+    tilewidth: u32,
+    tileheight: u32,
+    #[serde(default)]
+    infinite: bool,
+    #[serde(default)]
+    orientation: String,
+    layers: Vec<JsonLayer>,
+    tilesets: Vec<JsonTileset>,
+}
+
+#[derive(Deserialize)]
+struct JsonLayer {
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    width: u32,
+    #[serde(default)]
+    height: u32,
+    #[serde(default = "default_visible")]
+    visible: bool,
+    data: Option<JsonData>,
+    // Children of group layers
+    #[serde(default)]
+    layers: Vec<JsonLayer>,
+    #[serde(default)]
+    properties: Vec<JsonProperty>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum JsonData {
+    Csv(Vec<u32>),
+    Encoded(String),
+}
+
+#[derive(Deserialize)]
+struct JsonProperty {
+    name: String,
+    value: serde_json::Value,
+}
+
+#[derive(Deserialize)]
+struct JsonTileset {
+    #[serde(default)]
+    firstgid: u32,
+    source: Option<String>,
+    #[serde(default)]
+    name: String,
+    image: Option<String>,
+    #[serde(default)]
+    tilewidth: u32,
+    #[serde(default)]
+    tileheight: u32,
+    #[serde(default)]
+    columns: u32,
+    #[serde(default)]
+    tilecount: u32,
+    #[serde(default)]
+    margin: u32,
+    #[serde(default)]
+    spacing: u32,
+}
+
+fn default_visible() -> bool {
+    true
+}
+
+impl Tile {
+    pub const EMPTY: Self = Self(0);
+
+    #[inline]
+    pub const fn gid(&self) -> u32 {
+        self.0 & GID_MASK
+    }
+
+    #[inline]
+    pub const fn is_empty(&self) -> bool {
+        self.gid() == 0
+    }
+
+    #[inline]
+    pub const fn flipped_horizontally(&self) -> bool {
+        self.0 & FLIP_HORIZONTAL != 0
+    }
+
+    #[inline]
+    pub const fn flipped_vertically(&self) -> bool {
+        self.0 & FLIP_VERTICAL != 0
+    }
+
+    #[inline]
+    pub const fn flipped_diagonally(&self) -> bool {
+        self.0 & FLIP_DIAGONAL != 0
+    }
+}
+
+impl Tileset {
+    #[inline]
+    pub const fn contains(&self, gid: u32) -> bool {
+        gid >= self.first_gid && gid - self.first_gid < self.tile_count
+    }
+
+    // Top left corner of the tile in the image, in pixels
+    pub fn tile_origin(&self, gid: u32) -> [u32; 2] {
+        let index = gid - self.first_gid;
+        let columns = self.columns.max(1);
+        [
+            self.margin + (index % columns) * (self.tile_size[0] + self.spacing),
+            self.margin + (index / columns) * (self.tile_size[1] + self.spacing),
+        ]
+    }
+
+    // Texture coordinates of the tile's top left and bottom right corners in the atlas
+    fn uv_rect(&self, gid: u32, rect: &AtlasRect) -> [Point2<f32>; 2] {
+        let [x, y] = self.tile_origin(gid);
+        let size = rect.uv_max - rect.uv_min;
+        let uv = |x: f32, y: f32| {
+            Point2::new(
+                rect.uv_min.x + x / rect.width as f32 * size.x,
+                rect.uv_min.y + y / rect.height as f32 * size.y,
+            )
+        };
+        [
+            uv(x as f32 + UV_INSET, y as f32 + UV_INSET),
+            uv(
+                (x + self.tile_size[0]) as f32 - UV_INSET,
+                (y + self.tile_size[1]) as f32 - UV_INSET,
+            ),
+        ]
+    }
+
+    fn atlas_name(&self) -> String {
+        self.image.to_string_lossy().into_owned()
+    }
+}
+
+impl TileLayer {
+    #[inline]
+    pub const fn width(&self) -> u32 {
+        self.width
+    }
+
+    #[inline]
+    pub const fn height(&self) -> u32 {
+        self.height
+    }
+
+    // Cells outside of the layer are empty
+    pub fn get(&self, x: u32, y: u32) -> Tile {
+        if x < self.width && y < self.height {
+            self.tiles[(x + y * self.width) as usize]
+        } else {
+            Tile::EMPTY
+        }
+    }
+
+    pub fn set(&mut self, x: u32, y: u32, tile: Tile) {
+        if x < self.width && y < self.height {
+            self.tiles[(x + y * self.width) as usize] = tile;
+        }
+    }
+}
+
+impl Tilemap {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path).map_err(|err| Error::file(path, err))?;
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("tmj" | "json") => Self::from_json(path, &text),
+            Some("tmx") => Self::from_tmx(path, &text),
+            _ => Err(Error::asset_parse(
+                path,
+                "Unknown tilemap format, expected .tmj, .json or .tmx",
+            )),
+        }
+    }
+
+    // Looks for res/tilemaps/<name>.tmj, .json and .tmx in that order
+    pub fn load_by_name(name: &str) -> Result<Self, Error> {
+        let base = PathBuf::from(TILEMAP_DIRECTORY).join(name);
+        let path = ["tmj", "json", "tmx"]
+            .iter()
+            .map(|ext| base.with_extension(ext))
+            .find(|path| path.exists())
+            .unwrap_or_else(|| base.with_extension("tmj"));
+        Self::load(path)
+    }
+
+    // A tile is one unit tall by default
+    pub fn with_pixels_per_unit(mut self, pixels_per_unit: f32) -> Self {
+        self.pixels_per_unit = pixels_per_unit;
+        self
+    }
+
+    #[inline]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    #[inline]
+    pub const fn width(&self) -> u32 {
+        self.width
+    }
+
+    #[inline]
+    pub const fn height(&self) -> u32 {
+        self.height
+    }
+
+    #[inline]
+    pub const fn pixels_per_unit(&self) -> f32 {
+        self.pixels_per_unit
+    }
+
+    #[inline]
+    pub fn layers(&self) -> &[TileLayer] {
+        &self.layers
+    }
+
+    #[inline]
+    pub fn layers_mut(&mut self) -> &mut [TileLayer] {
+        &mut self.layers
+    }
+
+    #[inline]
+    pub fn tilesets(&self) -> &[Tileset] {
+        &self.tilesets
+    }
+
+    pub fn layer(&self, name: &str) -> Option<&TileLayer> {
+        self.layers.iter().find(|layer| layer.name == name)
+    }
+
+    pub fn tileset_for(&self, tile: Tile) -> Option<&Tileset> {
+        let gid = tile.gid();
+        self.tilesets.iter().find(|tileset| tileset.contains(gid))
+    }
+
+    // Size of a map cell in world units
+    pub fn cell_size(&self) -> [f32; 2] {
+        self.tile_size
+            .map(|size| size as f32 / self.pixels_per_unit)
+    }
+
+    // Cell under a point given relative to the map's origin
+    pub fn cell_at(&self, point: &Point2<f32>) -> Option<[u32; 2]> {
+        let [width, height] = self.cell_size();
+        let x = (point.x / width).floor();
+        let y = (-point.y / height).floor();
+        (x >= 0.0 && y >= 0.0 && (x as u32) < self.width && (y as u32) < self.height)
+            .then(|| [x as u32, y as u32])
+    }
+
+    // Bottom left and top right corners of a cell relative to the map's origin
+    pub fn cell_bounds(&self, x: u32, y: u32) -> [Point2<f32>; 2] {
+        let [width, height] = self.cell_size();
+        [
+            Point2::new(x as f32 * width, -((y + 1) as f32) * height),
+            Point2::new((x + 1) as f32 * width, -(y as f32) * height),
+        ]
+    }
+
+    // Packs the tileset images into one texture, so every chunk is drawn with one material
+    pub fn build_atlas(&self, textures: &mut TextureRegistry) -> Result<TextureAtlas, Error> {
+        let mut builder = AtlasBuilder::new(MAX_ATLAS_SIZE);
+        for tileset in &self.tilesets {
+            builder.add_from_path(&tileset.atlas_name(), &tileset.image)?;
+        }
+        let name = format!("tilemap:{}", self.name);
+        let atlas = builder.build(&name, textures)?;
+        textures.set_sampler(
+            &name,
+            &SamplerDesc {
+                filter: TextureFilter::Nearest,
+                wrap: TextureWrap::Clamp,
+                ..Default::default()
+            },
+        )?;
+        Ok(atlas)
+    }
+
+    // Quads of the chunk's tiles relative to the map's origin. Tiles larger than a cell stick
+    // out to the top and right, like in Tiled
+    pub fn mesh_chunk(&self, layer: usize, chunk: [u32; 2], atlas: &TextureAtlas) -> Vec<Vertex> {
+        let layer = &self.layers[layer];
+        let mut vertices = vec![];
+        let normal = Vector3::z();
+        let tangent = Vector4::new(1.0, 0.0, 0.0, 1.0);
+
+        for y in chunk[1] * CHUNK_TILES..((chunk[1] + 1) * CHUNK_TILES).min(layer.height) {
+            for x in chunk[0] * CHUNK_TILES..((chunk[0] + 1) * CHUNK_TILES).min(layer.width) {
+                let tile = layer.get(x, y);
+                if tile.is_empty() {
+                    continue;
+                }
+                let tileset = match self.tileset_for(tile) {
+                    Some(tileset) => tileset,
+                    None => continue,
+                };
+                let rect = match atlas.get(&tileset.atlas_name()) {
+                    Some(rect) => rect,
+                    None => continue,
+                };
+
+                let [min, _] = self.cell_bounds(x, y);
+                let size = tileset
+                    .tile_size
+                    .map(|size| size as f32 / self.pixels_per_unit);
+                let [uv_min, uv_max] = tileset.uv_rect(tile.gid(), rect);
+                // Corners are (right, down) within the tile. Tiled flips diagonally first,
+                // so the lookup undoes the flips in reverse
+                let corner = |right: f32, down: f32| {
+                    let (mut u, mut v) = (right, down);
+                    if tile.flipped_vertically() {
+                        v = 1.0 - v;
+                    }
+                    if tile.flipped_horizontally() {
+                        u = 1.0 - u;
+                    }
+                    if tile.flipped_diagonally() {
+                        std::mem::swap(&mut u, &mut v);
+                    }
+                    Vertex {
+                        v_position: Point3::new(
+                            min.x + right * size[0],
+                            min.y + (1.0 - down) * size[1],
+                            0.0,
+                        ),
+                        v_normal: normal,
+                        v_tex_coord: Point2::new(
+                            uv_min.x + u * (uv_max.x - uv_min.x),
+                            uv_min.y + v * (uv_max.y - uv_min.y),
+                        ),
+                        v_tangent: tangent,
+                        v_lightmap_coord: Point2::origin(),
+                    }
+                };
+
+                let quad = [
+                    corner(0.0, 1.0),
+                    corner(1.0, 1.0),
+                    corner(1.0, 0.0),
+                    corner(0.0, 0.0),
+                ];
+                // Counter-clockwise seen from +Z
+                vertices.extend([0, 1, 2, 0, 2, 3].iter().map(|&i| quad[i]));
+            }
+        }
+
+        vertices
+    }
+
+    pub fn is_solid(&self, x: u32, y: u32) -> bool {
+        self.layers
+            .iter()
+            .any(|layer| layer.collision && !layer.get(x, y).is_empty())
+    }
+
+    // Solid cells of all the collision layers merged into rectangles, as [x, y, width, height]
+    // in cells
+    pub fn collision_rects(&self) -> Vec<[u32; 4]> {
+        let (width, height) = (self.width, self.height);
+        let mut free: Vec<bool> = (0..width * height)
+            .map(|i| self.is_solid(i % width, i / width))
+            .collect();
+        let index = |x: u32, y: u32| (x + y * width) as usize;
+        let mut rects = vec![];
+
+        for y in 0..height {
+            let mut x = 0;
+            while x < width {
+                if !free[index(x, y)] {
+                    x += 1;
+                    continue;
+                }
+
+                let mut w = 1;
+                while x + w < width && free[index(x + w, y)] {
+                    w += 1;
+                }
+                let mut h = 1;
+                while y + h < height && (x..x + w).all(|cx| free[index(cx, y + h)]) {
+                    h += 1;
+                }
+
+                for cy in y..y + h {
+                    for cx in x..x + w {
+                        free[index(cx, cy)] = false;
+                    }
+                }
+                rects.push([x, y, w, h]);
+                x += w;
+            }
+        }
+
+        rects
+    }
+
+    // Boxes around the collision rectangles, reaching through all the sorting layers so
+    // sprites collide whatever their depth
+    #[cfg(feature = "physics")]
+    pub fn collision_mesh(&self) -> Option<CollisionMesh> {
+        let rects = self.collision_rects();
+        if rects.is_empty() {
+            return None;
+        }
+
+        // FNV-1a, like model_hash
+        let hash = bytemuck::cast_slice::<_, u8>(&rects[..])
+            .iter()
+            .chain(&self.pixels_per_unit.to_le_bytes())
+            .chain(bytemuck::cast_slice::<_, u8>(&self.tile_size[..]))
+            .fold(0xcbf29ce484222325, |hash, &byte| {
+                (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+            });
+
+        let triangles = rects.into_iter().flat_map(|[x, y, w, h]| {
+            let [min, _] = self.cell_bounds(x, y + h - 1);
+            let [_, max] = self.cell_bounds(x + w - 1, y);
+            let corner = move |i: usize| {
+                Point3::new(
+                    if i & 1 == 0 { min.x } else { max.x },
+                    if i & 2 == 0 { min.y } else { max.y },
+                    if i & 4 == 0 {
+                        -CAMERA_2D_DEPTH
+                    } else {
+                        CAMERA_2D_DEPTH
+                    },
+                )
+            };
+            BOX_FACES.into_iter().flat_map(move |[a, b, c, d]| {
+                [
+                    [corner(a), corner(b), corner(c)],
+                    [corner(a), corner(c), corner(d)],
+                ]
+            })
+        });
+
+        Some(CollisionMesh::from_triangles(hash, triangles))
+    }
+
+    // Static entities for the visible layers, one per non-empty chunk, all placed at the
+    // origin. The collision mesh goes on the first of them
+    pub fn instantiate(
+        &self,
+        origin: Point3<f32>,
+        materials: &mut MaterialRegistry,
+        models: &ModelRegistry,
+        textures: &mut TextureRegistry,
+    ) -> Result<Vec<Entity>, Error> {
+        let _span = tracing::info_span!("instantiate_tilemap", name = %self.name).entered();
+        let atlas = self.build_atlas(textures)?;
+        let material = materials.get_or_load_variant(
+            "simple",
+            &ShaderVariant::default()
+                .with_value(ShaderVariant::UNLIT, 1)
+                .with_value(ShaderVariant::ALPHA_TEST, 1),
+        )?;
+        let create_info = MaterialInstanceCreateInfo::default()
+            .with_color("diffuse_color", [1.0; 4])
+            .with_texture("diffuse_map", atlas.texture().clone());
+        let uploads = models.uploads();
+
+        let chunks = [
+            (self.width + CHUNK_TILES - 1) / CHUNK_TILES,
+            (self.height + CHUNK_TILES - 1) / CHUNK_TILES,
+        ];
+        let mut entities = vec![];
+        for (index, layer) in self.layers.iter().enumerate() {
+            if !layer.visible {
+                continue;
+            }
+            for cy in 0..chunks[1] {
+                for cx in 0..chunks[0] {
+                    let vertices = self.mesh_chunk(index, [cx, cy], &atlas);
+                    if vertices.is_empty() {
+                        continue;
+                    }
+
+                    let model = Arc::new(Model::new(uploads, vertices, material.clone())?);
+                    let mesh = MeshObject::new(
+                        uploads.clone(),
+                        models.model_arena(),
+                        model,
+                        material.clone(),
+                        create_info.clone(),
+                    )?;
+                    let entity = Entity::new_with_mesh(origin, mesh)?
+                        .with_component(StaticGeometry)
+                        .with_component(SpriteOrder::new(layer.sort_layer));
+                    entities.push(entity);
+                }
+            }
+        }
+
+        #[cfg(feature = "physics")]
+        if let Some(mesh) = self.collision_mesh() {
+            match entities.first_mut() {
+                Some(entity) => {
+                    entity.components_mut().insert(MeshCollider {
+                        mesh: Arc::new(mesh),
+                    });
+                }
+                None => log::warn!(
+                    "Tilemap {:?} has collision but no visible tiles to attach it to",
+                    self.name
+                ),
+            }
+        }
+
+        log::debug!("Tilemap {:?}: {} chunk entities", self.name, entities.len());
+        Ok(entities)
+    }
+
+    fn new(path: &Path, width: u32, height: u32, tile_size: [u32; 2]) -> Result<Self, Error> {
+        if tile_size[0] == 0 || tile_size[1] == 0 {
+            return Err(Error::asset_parse(path, "Tile size can't be zero"));
+        }
+        Ok(Self {
+            name: path
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            width,
+            height,
+            tile_size,
+            pixels_per_unit: tile_size[1] as f32,
+            layers: vec![],
+            tilesets: vec![],
+        })
+    }
+
+    fn add_layer(
+        &mut self,
+        path: &Path,
+        name: String,
+        visible: bool,
+        gids: Vec<u32>,
+        properties: LayerProperties,
+    ) -> Result<(), Error> {
+        if gids.len() != (self.width * self.height) as usize {
+            return Err(Error::asset_parse(
+                path,
+                format!(
+                    "Layer {:?} has {} tiles, expected {}x{}",
+                    name,
+                    gids.len(),
+                    self.width,
+                    self.height
+                ),
+            ));
+        }
+        let collision = properties
+            .collision
+            .unwrap_or_else(|| name.eq_ignore_ascii_case("collision"));
+        self.layers.push(TileLayer {
+            visible,
+            collision,
+            sort_layer: properties.sort_layer.unwrap_or(self.layers.len() as i32),
+            width: self.width,
+            height: self.height,
+            tiles: gids.into_iter().map(Tile).collect(),
+            name,
+        });
+        Ok(())
+    }
+
+    fn add_tileset(&mut self, tileset: Tileset) {
+        self.tilesets.push(tileset);
+        self.tilesets.sort_by_key(|tileset| tileset.first_gid);
+    }
+
+    fn from_json(path: &Path, text: &str) -> Result<Self, Error> {
+        let map: JsonMap =
+            serde_json::from_str(text).map_err(|err| Error::asset_parse(path, err))?;
+        if map.infinite {
+            return Err(Error::asset_parse(
+                path,
+                "Infinite maps aren't supported, turn off \"Infinite\" in the map properties",
+            ));
+        }
+        if !map.orientation.is_empty() && map.orientation != "orthogonal" {
+            return Err(Error::asset_parse(
+                path,
+                format!(
+                    "Only orthogonal maps are supported, not {}",
+                    map.orientation
+                ),
+            ));
+        }
+
+        let mut tilemap = Self::new(path, map.width, map.height, [map.tilewidth, map.tileheight])?;
+        let directory = path.parent().unwrap_or_else(|| Path::new(""));
+        for tileset in map.tilesets {
+            let tileset = match &tileset.source {
+                Some(source) => load_external_tileset(&directory.join(source), tileset.firstgid)?,
+                None => json_tileset(path, directory, tileset)?,
+            };
+            tilemap.add_tileset(tileset);
+        }
+        tilemap.add_json_layers(path, map.layers)?;
+
+        Ok(tilemap)
+    }
+
+    fn add_json_layers(&mut self, path: &Path, layers: Vec<JsonLayer>) -> Result<(), Error> {
+        for layer in layers {
+            match layer.kind.as_str() {
+                "tilelayer" => (),
+                "group" => {
+                    self.add_json_layers(path, layer.layers)?;
+                    continue;
+                }
+                kind => {
+                    log::debug!("Skipping {} layer {:?} in {:?}", kind, layer.name, path);
+                    continue;
+                }
+            }
+
+            let gids = match layer.data {
+                Some(JsonData::Csv(gids)) => gids,
+                Some(JsonData::Encoded(_)) => {
+                    return Err(Error::asset_parse(
+                        path,
+                        format!(
+                            "Layer {:?} is encoded, save the map with CSV layer format",
+                            layer.name
+                        ),
+                    ))
+                }
+                None => vec![0; (layer.width * layer.height) as usize],
+            };
+            let mut properties = LayerProperties::default();
+            for property in &layer.properties {
+                match property.name.as_str() {
+                    "collision" => properties.collision = property.value.as_bool(),
+                    "sort_layer" => {
+                        properties.sort_layer = property.value.as_i64().map(|value| value as i32)
+                    }
+                    _ => (),
+                }
+            }
+            self.add_layer(path, layer.name, layer.visible, gids, properties)?;
+        }
+        Ok(())
+    }
+
+    fn from_tmx(path: &Path, text: &str) -> Result<Self, Error> {
+        let document =
+            roxmltree::Document::parse(text).map_err(|err| Error::asset_parse(path, err))?;
+        let root = document.root_element();
+        if !root.has_tag_name("map") {
+            return Err(Error::asset_parse(path, "Expected a <map> element"));
+        }
+        if attribute_or(path, &root, "infinite", 0)? != 0 {
+            return Err(Error::asset_parse(
+                path,
+                "Infinite maps aren't supported, turn off \"Infinite\" in the map properties",
+            ));
+        }
+        let orientation = root.attribute("orientation").unwrap_or("orthogonal");
+        if orientation != "orthogonal" {
+            return Err(Error::asset_parse(
+                path,
+                format!("Only orthogonal maps are supported, not {}", orientation),
+            ));
+        }
+
+        let mut tilemap = Self::new(
+            path,
+            attribute(path, &root, "width")?,
+            attribute(path, &root, "height")?,
+            [
+                attribute(path, &root, "tilewidth")?,
+                attribute(path, &root, "tileheight")?,
+            ],
+        )?;
+        let directory = path.parent().unwrap_or_else(|| Path::new(""));
+        for node in root.children().filter(|node| node.has_tag_name("tileset")) {
+            let first_gid = attribute(path, &node, "firstgid")?;
+            let tileset = match node.attribute("source") {
+                Some(source) => load_external_tileset(&directory.join(source), first_gid)?,
+                None => tmx_tileset(path, directory, &node, first_gid)?,
+            };
+            tilemap.add_tileset(tileset);
+        }
+        tilemap.add_tmx_layers(path, &root)?;
+
+        Ok(tilemap)
+    }
+
+    fn add_tmx_layers(&mut self, path: &Path, parent: &roxmltree::Node) -> Result<(), Error> {
+        for node in parent.children().filter(roxmltree::Node::is_element) {
+            match node.tag_name().name() {
+                "layer" => (),
+                "group" => {
+                    self.add_tmx_layers(path, &node)?;
+                    continue;
+                }
+                _ => continue,
+            }
+
+            let name = node.attribute("name").unwrap_or_default().to_owned();
+            let data = node.children().find(|child| child.has_tag_name("data"));
+            let gids = match data {
+                Some(data) => match data.attribute("encoding") {
+                    Some("csv") => data
+                        .text()
+                        .unwrap_or_default()
+                        .split(',')
+                        .map(str::trim)
+                        .filter(|value| !value.is_empty())
+                        .map(|value| {
+                            value.parse().map_err(|_| {
+                                Error::asset_parse(
+                                    path,
+                                    format!("Invalid tile {:?} in layer {:?}", value, name),
+                                )
+                            })
+                        })
+                        .collect::<Result<Vec<u32>, _>>()?,
+                    None => data
+                        .children()
+                        .filter(|child| child.has_tag_name("tile"))
+                        .map(|tile| attribute_or(path, &tile, "gid", 0))
+                        .collect::<Result<Vec<u32>, _>>()?,
+                    Some(_) => {
+                        return Err(Error::asset_parse(
+                            path,
+                            format!(
+                                "Layer {:?} is encoded, save the map with CSV layer format",
+                                name
+                            ),
+                        ))
+                    }
+                },
+                None => vec![0; (self.width * self.height) as usize],
+            };
+
+            let mut properties = LayerProperties::default();
+            let nodes = node
+                .children()
+                .filter(|child| child.has_tag_name("properties"))
+                .flat_map(|properties| properties.children())
+                .filter(|child| child.has_tag_name("property"));
+            for property in nodes {
+                let value = property.attribute("value").unwrap_or_default();
+                match property.attribute("name") {
+                    Some("collision") => properties.collision = value.parse().ok(),
+                    Some("sort_layer") => properties.sort_layer = value.parse().ok(),
+                    _ => (),
+                }
+            }
+            let visible = attribute_or(path, &node, "visible", 1)? != 0;
+            self.add_layer(path, name, visible, gids, properties)?;
+        }
+        Ok(())
+    }
+}
+
+fn attribute<T: FromStr>(path: &Path, node: &roxmltree::Node, name: &str) -> Result<T, Error> {
+    let value = node.attribute(name).ok_or_else(|| {
+        Error::asset_parse(
+            path,
+            format!("<{}> has no {:?} attribute", node.tag_name().name(), name),
+        )
+    })?;
+    value
+        .parse()
+        .map_err(|_| Error::asset_parse(path, format!("Invalid {:?} value {:?}", name, value)))
+}
+
+fn attribute_or<T: FromStr>(
+    path: &Path,
+    node: &roxmltree::Node,
+    name: &str,
+    default: T,
+) -> Result<T, Error> {
+    match node.attribute(name) {
+        Some(_) => attribute(path, node, name),
+        None => Ok(default),
+    }
+}
+
+fn json_tileset(path: &Path, directory: &Path, tileset: JsonTileset) -> Result<Tileset, Error> {
+    let image = tileset.image.ok_or_else(|| {
+        Error::asset_parse(
+            path,
+            format!(
+                "Tileset {:?} has no image, image collections aren't supported",
+                tileset.name
+            ),
+        )
+    })?;
+    Ok(Tileset {
+        name: tileset.name,
+        first_gid: tileset.firstgid,
+        image: directory.join(image),
+        tile_size: [tileset.tilewidth, tileset.tileheight],
+        columns: tileset.columns,
+        tile_count: tileset.tilecount,
+        margin: tileset.margin,
+        spacing: tileset.spacing,
+    })
+}
+
+fn tmx_tileset(
+    path: &Path,
+    directory: &Path,
+    node: &roxmltree::Node,
+    first_gid: u32,
+) -> Result<Tileset, Error> {
+    let name = node.attribute("name").unwrap_or_default().to_owned();
+    let image = node
+        .children()
+        .find(|child| child.has_tag_name("image"))
+        .ok_or_else(|| {
+            Error::asset_parse(
+                path,
+                format!(
+                    "Tileset {:?} has no image, image collections aren't supported",
+                    name
+                ),
+            )
+        })?;
+    Ok(Tileset {
+        first_gid,
+        image: directory.join(attribute::<String>(path, &image, "source")?),
+        tile_size: [
+            attribute(path, node, "tilewidth")?,
+            attribute(path, node, "tileheight")?,
+        ],
+        columns: attribute(path, node, "columns")?,
+        tile_count: attribute(path, node, "tilecount")?,
+        margin: attribute_or(path, node, "margin", 0)?,
+        spacing: attribute_or(path, node, "spacing", 0)?,
+        name,
+    })
+}
+
+// Tileset in its own file, image paths are relative to that file
+fn load_external_tileset(path: &Path, first_gid: u32) -> Result<Tileset, Error> {
+    let text = std::fs::read_to_string(path).map_err(|err| Error::file(path, err))?;
+    let directory = path.parent().unwrap_or_else(|| Path::new(""));
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("tsx") => {
+            let document =
+                roxmltree::Document::parse(&text).map_err(|err| Error::asset_parse(path, err))?;
+            tmx_tileset(path, directory, &document.root_element(), first_gid)
+        }
+        _ => {
+            let tileset: JsonTileset =
+                serde_json::from_str(&text).map_err(|err| Error::asset_parse(path, err))?;
+            json_tileset(
+                path,
+                directory,
+                JsonTileset {
+                    firstgid: first_gid,
+                    ..tileset
+                },
+            )
+        }
+    }
+}
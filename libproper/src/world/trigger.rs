@@ -0,0 +1,99 @@
+#[cfg(feature = "physics")]
+use std::collections::HashSet;
+
+use nalgebra::Isometry3;
+
+#[cfg(feature = "physics")]
+use crate::event::GameEvent;
+
+use super::spatial::Aabb;
+#[cfg(feature = "physics")]
+use super::{collision::Collider, entity::EntityId, scene::Scene};
+
+// Scene-defined volume, sends GameEvent::Signal with the entity crossing it. Only entities
+// with a solid collider set it off
+#[derive(Clone, Debug)]
+pub struct TriggerVolume {
+    pub bounds: Aabb,
+    // Signal sent on enter
+    pub name: String,
+    pub exit_name: Option<String>,
+    // Disarmed after the first enter, e.g. for cutscenes and checkpoints. The exit signal
+    // isn't sent for these
+    pub once: bool,
+}
+
+#[cfg(feature = "physics")]
+#[derive(Default)]
+pub struct TriggerSystem {
+    // (volume index, entity) pairs currently inside
+    inside: HashSet<(usize, EntityId)>,
+    disarmed: HashSet<usize>,
+}
+
+impl TriggerVolume {
+    // Axis-aligned bounds of the moved volume
+    pub fn transformed(&self, offset: &Isometry3<f32>) -> Self {
+        let corners = self.bounds.corners().map(|corner| offset * corner);
+        Self {
+            bounds: Aabb::from_points(&corners).unwrap(),
+            ..self.clone()
+        }
+    }
+}
+
+#[cfg(feature = "physics")]
+impl TriggerSystem {
+    pub fn update(&mut self, scene: &Scene) -> Vec<GameEvent> {
+        let mut inside = HashSet::new();
+        for entity in scene.entities() {
+            let collider = match entity.components().get::<Collider>() {
+                Some(collider) if !collider.is_trigger => collider,
+                _ => continue,
+            };
+            let bounds = collider.world_bounds(entity.position(), entity.rotation());
+            for (index, volume) in scene.triggers.iter().enumerate() {
+                if volume.bounds.intersects(&bounds) {
+                    inside.insert((index, entity.id()));
+                }
+            }
+        }
+
+        let mut events = vec![];
+        for &(index, entity) in inside.difference(&self.inside) {
+            let volume = &scene.triggers[index];
+            if !self.disarmed.contains(&index) {
+                events.push(GameEvent::Signal {
+                    name: volume.name.clone(),
+                    entity: Some(entity),
+                });
+            }
+            if volume.once {
+                self.disarmed.insert(index);
+            }
+        }
+        for &(index, entity) in self.inside.difference(&inside) {
+            // Volumes are only removed along with the scene, see clear()
+            if let Some(name) = scene
+                .triggers
+                .get(index)
+                .filter(|volume| !volume.once)
+                .and_then(|volume| volume.exit_name.clone())
+            {
+                events.push(GameEvent::Signal {
+                    name,
+                    entity: Some(entity),
+                });
+            }
+        }
+
+        self.inside = inside;
+        events
+    }
+
+    // Forgets who is inside and re-arms the volumes, for when the scene is replaced
+    pub fn clear(&mut self) {
+        self.inside.clear();
+        self.disarmed.clear();
+    }
+}
@@ -0,0 +1,125 @@
+use std::{collections::BTreeSet, fmt, sync::atomic::Ordering};
+
+use super::{entity::EntityId, scene::Scene};
+
+// Scales below this make the model collapse into a plane or a point
+const MIN_SCALE: f32 = 1e-4;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum SceneIssue {
+    // The model has no triangles, e.g. a failed or empty .obj
+    EmptyModel(EntityId),
+    // A texture declared by the material layout isn't set on the instance
+    MissingTexture { entity: EntityId, name: String },
+    // NaN or infinite position, rotation or scale
+    InvalidTransform(EntityId),
+    DegenerateScale(EntityId),
+    DuplicateId(EntityId),
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MaterialStats {
+    pub template_id: u64,
+    pub entities: usize,
+    pub triangles: usize,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SceneStats {
+    pub entities: usize,
+    // Entities waiting for their assets
+    pub loading: usize,
+    pub triangles: usize,
+    pub materials: Vec<MaterialStats>,
+}
+
+impl SceneIssue {
+    pub const fn entity(&self) -> EntityId {
+        match self {
+            Self::EmptyModel(entity)
+            | Self::MissingTexture { entity, .. }
+            | Self::InvalidTransform(entity)
+            | Self::DegenerateScale(entity)
+            | Self::DuplicateId(entity) => *entity,
+        }
+    }
+}
+
+impl fmt::Display for SceneIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::EmptyModel(entity) => write!(f, "Entity #{} has an empty model", entity.0),
+            Self::MissingTexture { entity, name } => {
+                write!(f, "Entity #{} has no {:?} texture", entity.0, name)
+            }
+            Self::InvalidTransform(entity) => {
+                write!(f, "Entity #{} has a non-finite transform", entity.0)
+            }
+            Self::DegenerateScale(entity) => {
+                write!(f, "Entity #{} has a zero scale", entity.0)
+            }
+            Self::DuplicateId(entity) => write!(f, "Entity ID #{} is used twice", entity.0),
+        }
+    }
+}
+
+impl Scene {
+    // Entities still in the loading list are checked too, they'll be added as they are
+    pub fn validate(&self) -> Vec<SceneIssue> {
+        let mut issues = vec![];
+        let mut ids = BTreeSet::new();
+
+        for entity in self.entities().chain(self.loading_list.iter()) {
+            let id = entity.id();
+            if !ids.insert(id) {
+                issues.push(SceneIssue::DuplicateId(id));
+            }
+
+            let mesh = entity.mesh();
+            if mesh.model().triangle_count() == 0 {
+                issues.push(SceneIssue::EmptyModel(id));
+            }
+            for name in mesh.material_template().layout().textures() {
+                if mesh.material_create_info().texture(name).is_none() {
+                    issues.push(SceneIssue::MissingTexture {
+                        entity: id,
+                        name: name.clone(),
+                    });
+                }
+            }
+
+            let scale = entity.scale();
+            let finite = entity.position().iter().all(|v| v.is_finite())
+                && entity.rotation().coords.iter().all(|v| v.is_finite())
+                && scale.iter().all(|v| v.is_finite());
+            if !finite {
+                issues.push(SceneIssue::InvalidTransform(id));
+            } else if scale.iter().any(|v| v.abs() < MIN_SCALE) {
+                issues.push(SceneIssue::DegenerateScale(id));
+            }
+        }
+
+        issues
+    }
+
+    pub fn stats(&self) -> SceneStats {
+        let materials: Vec<_> = self
+            .iter()
+            .map(|group| MaterialStats {
+                template_id: group.material_template.id().load(Ordering::Acquire),
+                entities: group.entities.len(),
+                triangles: group
+                    .iter()
+                    .map(|entity| entity.mesh().model().triangle_count())
+                    .sum(),
+            })
+            .collect();
+
+        SceneStats {
+            entities: materials.iter().map(|material| material.entities).sum(),
+            loading: self.loading_list.len(),
+            triangles: materials.iter().map(|material| material.triangles).sum(),
+            materials,
+        }
+    }
+}
@@ -0,0 +1,416 @@
+use std::{collections::HashMap, sync::Arc};
+
+use nalgebra::{Point2, Point3, Vector3, Vector4};
+
+use crate::{
+    error::Error,
+    render::{shader::ShaderVariant, Vertex},
+    resource::{
+        material::{MaterialInstanceCreateInfo, MaterialRegistry},
+        model::{Model, ModelRegistry},
+    },
+};
+
+use super::{
+    component::StaticGeometry,
+    entity::{Entity, EntityId},
+    scene::{MeshObject, Scene},
+};
+
+pub const CHUNK_SIZE: i32 = 16;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Voxel(pub u8);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ChunkCoord {
+    pub x: i32,
+    pub y: i32,
+    pub z: i32,
+}
+
+pub struct VoxelChunk {
+    voxels: Vec<Voxel>,
+    entity: Option<EntityId>,
+    dirty: bool,
+}
+
+pub struct VoxelWorld {
+    voxel_size: f32,
+    chunks: HashMap<ChunkCoord, VoxelChunk>,
+}
+
+// Turns dirty chunks into renderable (static) entities
+pub struct VoxelSystem {
+    material: String,
+    variant: ShaderVariant,
+    material_create_info: MaterialInstanceCreateInfo,
+}
+
+impl Voxel {
+    pub const AIR: Self = Self(0);
+
+    #[inline]
+    pub const fn is_solid(&self) -> bool {
+        self.0 != 0
+    }
+}
+
+impl ChunkCoord {
+    // Splits a voxel position into the chunk containing it and an index within that chunk
+    fn split(position: &Point3<i32>) -> (Self, usize) {
+        let coord = Self {
+            x: position.x.div_euclid(CHUNK_SIZE),
+            y: position.y.div_euclid(CHUNK_SIZE),
+            z: position.z.div_euclid(CHUNK_SIZE),
+        };
+        let local = position.map(|c| c.rem_euclid(CHUNK_SIZE));
+        (coord, VoxelChunk::index(&local))
+    }
+
+    fn origin(&self) -> Point3<i32> {
+        Point3::new(self.x, self.y, self.z) * CHUNK_SIZE
+    }
+
+    fn offset(&self, dx: i32, dy: i32, dz: i32) -> Self {
+        Self {
+            x: self.x + dx,
+            y: self.y + dy,
+            z: self.z + dz,
+        }
+    }
+}
+
+impl VoxelChunk {
+    fn new() -> Self {
+        Self {
+            voxels: vec![Voxel::AIR; (CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE) as usize],
+            entity: None,
+            dirty: true,
+        }
+    }
+
+    fn index(local: &Point3<i32>) -> usize {
+        (local.x + (local.y + local.z * CHUNK_SIZE) * CHUNK_SIZE) as usize
+    }
+
+    #[inline]
+    pub const fn entity(&self) -> Option<EntityId> {
+        self.entity
+    }
+
+    pub fn is_empty(&self) -> bool {
+        !self.voxels.iter().any(Voxel::is_solid)
+    }
+}
+
+impl Default for VoxelWorld {
+    fn default() -> Self {
+        Self::new(1.0)
+    }
+}
+
+impl VoxelWorld {
+    pub fn new(voxel_size: f32) -> Self {
+        Self {
+            voxel_size,
+            chunks: HashMap::new(),
+        }
+    }
+
+    #[inline]
+    pub const fn voxel_size(&self) -> f32 {
+        self.voxel_size
+    }
+
+    pub fn chunk(&self, coord: &ChunkCoord) -> Option<&VoxelChunk> {
+        self.chunks.get(coord)
+    }
+
+    pub fn voxel_position(&self, point: &Point3<f32>) -> Point3<i32> {
+        point.map(|c| (c / self.voxel_size).floor() as i32)
+    }
+
+    pub fn get(&self, position: &Point3<i32>) -> Voxel {
+        let (coord, index) = ChunkCoord::split(position);
+        self.chunks
+            .get(&coord)
+            .map_or(Voxel::AIR, |chunk| chunk.voxels[index])
+    }
+
+    pub fn set(&mut self, position: &Point3<i32>, voxel: Voxel) {
+        let (coord, index) = ChunkCoord::split(position);
+        if !voxel.is_solid() && !self.chunks.contains_key(&coord) {
+            return;
+        }
+        let chunk = self.chunks.entry(coord).or_insert_with(VoxelChunk::new);
+
+        if chunk.voxels[index] == voxel {
+            return;
+        }
+        chunk.voxels[index] = voxel;
+        chunk.dirty = true;
+
+        // Faces on chunk borders depend on the neighbour's contents too
+        let local = position.map(|c| c.rem_euclid(CHUNK_SIZE));
+        for axis in 0..3 {
+            let mut step = [0; 3];
+            if local[axis] == 0 {
+                step[axis] = -1;
+            } else if local[axis] == CHUNK_SIZE - 1 {
+                step[axis] = 1;
+            } else {
+                continue;
+            }
+            if let Some(neighbour) = self
+                .chunks
+                .get_mut(&coord.offset(step[0], step[1], step[2]))
+            {
+                neighbour.dirty = true;
+            }
+        }
+    }
+
+    pub fn clear(&mut self, position: &Point3<i32>) {
+        self.set(position, Voxel::AIR);
+    }
+
+    // Fills columns [min.x, min.x + size.x) x [min.y, min.y + size.y) (XZ plane) from `base`
+    // up to the height returned for each column
+    pub fn fill_heightfield<F: Fn(i32, i32) -> i32>(
+        &mut self,
+        min: Point2<i32>,
+        size: Point2<i32>,
+        base: i32,
+        height: F,
+        voxel: Voxel,
+    ) {
+        for x in min.x..min.x + size.x {
+            for z in min.y..min.y + size.y {
+                for y in base..height(x, z) {
+                    self.set(&Point3::new(x, y, z), voxel);
+                }
+            }
+        }
+    }
+
+    pub fn take_dirty(&mut self) -> Vec<ChunkCoord> {
+        self.chunks
+            .iter_mut()
+            .filter(|(_, chunk)| chunk.dirty)
+            .map(|(coord, chunk)| {
+                chunk.dirty = false;
+                *coord
+            })
+            .collect()
+    }
+
+    // Greedy meshing: coplanar faces of the same voxel type are merged into larger quads
+    pub fn mesh_chunk(&self, coord: &ChunkCoord) -> Vec<Vertex> {
+        let origin = coord.origin();
+        let n = CHUNK_SIZE;
+        let voxel = |x: [i32; 3]| self.get(&(origin + Vector3::from(x)));
+        let mut vertices = vec![];
+        let mut mask = vec![None; (n * n) as usize];
+
+        for d in 0..3 {
+            let u = (d + 1) % 3;
+            let v = (d + 2) % 3;
+            let mut x = [0; 3];
+            let mut q = [0; 3];
+            q[d] = 1;
+
+            x[d] = -1;
+            while x[d] < n {
+                // Faces between slice x[d] and x[d] + 1, only emitted for voxels of this chunk
+                let mut i = 0;
+                for xv in 0..n {
+                    for xu in 0..n {
+                        x[u] = xu;
+                        x[v] = xv;
+                        let a = voxel(x);
+                        let b = voxel([x[0] + q[0], x[1] + q[1], x[2] + q[2]]);
+
+                        mask[i] = match (a.is_solid(), b.is_solid()) {
+                            (true, false) if x[d] >= 0 => Some((a, true)),
+                            (false, true) if x[d] < n - 1 => Some((b, false)),
+                            _ => None,
+                        };
+                        i += 1;
+                    }
+                }
+                x[d] += 1;
+
+                let mut i = 0;
+                for j in 0..n {
+                    let mut k = 0;
+                    while k < n {
+                        let face = match mask[i] {
+                            Some(face) => face,
+                            None => {
+                                k += 1;
+                                i += 1;
+                                continue;
+                            }
+                        };
+
+                        let mut w = 1;
+                        while k + w < n && mask[i + w as usize] == Some(face) {
+                            w += 1;
+                        }
+                        let mut h = 1;
+                        'grow: while j + h < n {
+                            for l in 0..w {
+                                if mask[i + (l + h * n) as usize] != Some(face) {
+                                    break 'grow;
+                                }
+                            }
+                            h += 1;
+                        }
+
+                        let mut base = x;
+                        base[u] = k;
+                        base[v] = j;
+                        let mut du = [0; 3];
+                        du[u] = w;
+                        let mut dv = [0; 3];
+                        dv[v] = h;
+                        self.emit_quad(&mut vertices, d, face.1, base, du, dv);
+
+                        for l in 0..h {
+                            for m in 0..w {
+                                mask[i + (m + l * n) as usize] = None;
+                            }
+                        }
+                        k += w;
+                        i += w as usize;
+                    }
+                }
+            }
+        }
+
+        vertices
+    }
+
+    fn emit_quad(
+        &self,
+        vertices: &mut Vec<Vertex>,
+        axis: usize,
+        front: bool,
+        base: [i32; 3],
+        du: [i32; 3],
+        dv: [i32; 3],
+    ) {
+        let corner = |su: i32, sv: i32| {
+            let position = Point3::new(
+                base[0] + su * du[0] + sv * dv[0],
+                base[1] + su * du[1] + sv * dv[1],
+                base[2] + su * du[2] + sv * dv[2],
+            );
+            position.map(|c| c as f32 * self.voxel_size)
+        };
+        let mut normal = Vector3::zeros();
+        normal[axis] = if front { 1.0 } else { -1.0 };
+        let (w, h) = (
+            (du[0] + du[1] + du[2]) as f32,
+            (dv[0] + dv[1] + dv[2]) as f32,
+        );
+        let mut tangent = Vector4::zeros();
+        tangent[du.iter().position(|&d| d != 0).unwrap_or(0)] = 1.0;
+        let bitangent = Vector3::new(dv[0], dv[1], dv[2]).map(|c| c.signum() as f32);
+        tangent.w = normal.cross(&tangent.xyz()).dot(&bitangent).signum();
+
+        let quad = [
+            (corner(0, 0), Point2::new(0.0, 0.0)),
+            (corner(1, 0), Point2::new(w, 0.0)),
+            (corner(1, 1), Point2::new(w, h)),
+            (corner(0, 1), Point2::new(0.0, h)),
+        ];
+        // u x v points along +axis, so the winding flips for back faces
+        let order = if front {
+            [0, 1, 2, 0, 2, 3]
+        } else {
+            [0, 2, 1, 0, 3, 2]
+        };
+
+        vertices.extend(order.iter().map(|&i| Vertex {
+            v_position: quad[i].0,
+            v_normal: normal,
+            v_tex_coord: quad[i].1,
+            v_tangent: tangent,
+            v_lightmap_coord: Point2::origin(),
+        }));
+    }
+}
+
+impl Default for VoxelSystem {
+    fn default() -> Self {
+        Self::new(
+            "simple",
+            ShaderVariant::default().with_value(ShaderVariant::HAS_DIFFUSE_MAP, 0),
+            MaterialInstanceCreateInfo::default().with_color("diffuse_color", [0.5, 0.5, 0.5, 1.0]),
+        )
+    }
+}
+
+impl VoxelSystem {
+    pub fn new(
+        material: &str,
+        variant: ShaderVariant,
+        material_create_info: MaterialInstanceCreateInfo,
+    ) -> Self {
+        Self {
+            material: material.to_owned(),
+            variant,
+            material_create_info,
+        }
+    }
+
+    pub fn update(
+        &self,
+        scene: &mut Scene,
+        materials: &mut MaterialRegistry,
+        models: &ModelRegistry,
+    ) -> Result<(), Error> {
+        let dirty = scene.voxels.take_dirty();
+        if dirty.is_empty() {
+            return Ok(());
+        }
+
+        let material = materials.get_or_load_variant(&self.material, &self.variant)?;
+        let uploads = models.uploads();
+
+        for coord in dirty {
+            let vertices = scene.voxels.mesh_chunk(&coord);
+            let old_entity = scene.voxels.chunks[&coord].entity;
+
+            if vertices.is_empty() {
+                if let Some(id) = old_entity {
+                    scene.remove(id);
+                }
+                scene.voxels.chunks.get_mut(&coord).unwrap().entity = None;
+                continue;
+            }
+
+            let model = Arc::new(Model::new(uploads, vertices, material.clone())?);
+            let mesh = MeshObject::new(
+                uploads.clone(),
+                models.model_arena(),
+                model,
+                material.clone(),
+                self.material_create_info.clone(),
+            )?;
+
+            if let Some(entity) = old_entity.and_then(|id| scene.get_mut(id)) {
+                entity.set_mesh(mesh)?;
+            } else {
+                let position = coord.origin().map(|c| c as f32 * scene.voxels.voxel_size);
+                let entity = Entity::new_with_mesh(position, mesh)?.with_component(StaticGeometry);
+                let id = scene.add(entity);
+                scene.voxels.chunks.get_mut(&coord).unwrap().entity = Some(id);
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -1,18 +1,39 @@
-use libproper::Application;
-use log::LevelFilter;
-use simplelog::{
-    ColorChoice, CombinedLogger, ConfigBuilder, SharedLogger, TermLogger, TerminalMode,
+#[cfg(feature = "golden")]
+use libproper::render::golden::GoldenSuite;
+use libproper::{
+    crash::CrashReportConfig, logging::LogConfig, render::probe::ReflectionBake, Application,
 };
+use log::LevelFilter;
 
 fn main() {
-    let loggers: Vec<Box<dyn SharedLogger>> = vec![TermLogger::new(
-        LevelFilter::Debug,
-        ConfigBuilder::new().build(),
-        TerminalMode::Mixed,
-        ColorChoice::Auto,
-    )];
-    let _logger = CombinedLogger::init(loggers).ok();
-
-    let application = Application::new().unwrap();
+    let builder = Application::builder()
+        .logging(
+            LogConfig::new(LevelFilter::Debug)
+                .module("vulkano", LevelFilter::Warn)
+                .module("naga", LevelFilter::Warn)
+                .file("log/proper.log", 4 * 1024 * 1024, 3),
+        )
+        .crash_reports(CrashReportConfig::new("crash"));
+    // --golden renders the golden image suite instead of running the game, --golden-update
+    // replaces its reference images
+    #[cfg(feature = "golden")]
+    let builder = {
+        let args: Vec<_> = std::env::args().collect();
+        let update = args.iter().any(|arg| arg == "--golden-update");
+        if update || args.iter().any(|arg| arg == "--golden") {
+            let mut suite = GoldenSuite::load(GoldenSuite::DEFAULT_PATH).unwrap();
+            suite.update |= update;
+            builder.golden(suite)
+        } else {
+            builder
+        }
+    };
+    // --bake-reflections renders the reflection probes of res/probes/bake.toml and exits
+    let builder = if std::env::args().any(|arg| arg == "--bake-reflections") {
+        builder.reflection_bake(ReflectionBake::load(ReflectionBake::DEFAULT_PATH).unwrap())
+    } else {
+        builder
+    };
+    let application = builder.build().unwrap();
     application.run();
 }